@@ -0,0 +1,39 @@
+//! The internal suite under criterion, for statistics and history beyond
+//! the medians `bebion bench` reports.
+//!
+//! Run with `cargo bench -p bebion-bench --bench internal`, optionally
+//! followed by a workload name to run just that one.
+
+use bebion_bench::Phase;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::time::{Duration, Instant};
+
+fn internal(c: &mut Criterion) {
+    let workloads = bebion_bench::suite("internal").expect("internal suite");
+    for workload in workloads {
+        let mut prepared = workload.prepare().expect("prepare workload");
+        let group_name = match workload.phase {
+            Phase::Parse => "parse",
+            Phase::Compile => "compile",
+            Phase::Execute => "execute",
+        };
+        let mut group = c.benchmark_group(group_name);
+        // Time each run alone, collecting the heap between them
+        group.bench_function(workload.name, |b| {
+            b.iter_custom(|iterations| {
+                let mut total = Duration::ZERO;
+                for _ in 0..iterations {
+                    let start = Instant::now();
+                    prepared.run().expect("run workload");
+                    total += start.elapsed();
+                    prepared.reset();
+                }
+                total
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, internal);
+criterion_main!(benches);
@@ -0,0 +1,307 @@
+//! Internal benchmark suite
+//!
+//! Small workloads that each stress one part of the engine: arithmetic,
+//! property access and calls in the VM, string building, allocation churn
+//! for the GC, and parse and compile throughput. `bebion bench --suite
+//! internal` times them and compares the timings against a baseline file
+//! from an earlier run; `cargo bench -p bebion-bench` runs the same
+//! workloads under criterion for detailed statistics.
+//!
+//! Only the phase a workload is named for is timed: an execution workload
+//! is parsed and compiled once up front, and the heap is collected between
+//! samples.
+//!
+//! `startup` times how long the engine takes to start instead, for
+//! `bebion bench --startup`.
+
+pub mod startup;
+
+use bebion_compiler::bytecode::Bytecode;
+use bebion_compiler::Compiler;
+use bebion_core::{BebionEngine, BebionError};
+use bebion_parser::{Parser, Program};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Suites `suite` knows
+pub const SUITES: &[&str] = &["internal"];
+
+/// How long `run_suite` samples each workload unless told otherwise
+pub const DEFAULT_MEASUREMENT_TIME: Duration = Duration::from_secs(1);
+
+/// Fewest samples a measurement takes, however long they are
+pub(crate) const MIN_SAMPLES: usize = 5;
+
+/// How much slower than its baseline a workload may get, as a fraction,
+/// before it counts as a regression
+pub const DEFAULT_REGRESSION_THRESHOLD: f64 = 0.10;
+
+/// The part of the engine a workload times
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Parse,
+    Compile,
+    Execute,
+}
+
+pub struct Workload {
+    pub name: &'static str,
+    pub phase: Phase,
+    /// The script, or the snippet repeated `repeat` times to make it
+    snippet: &'static str,
+    repeat: usize,
+}
+
+const INTERNAL: &[Workload] = &[
+    Workload {
+        name: "arithmetic_loop",
+        phase: Phase::Execute,
+        snippet: "var s = 0; for (var i = 0; i < 100000; i = i + 1) { s = (s + i * 3) % 1000003 } s",
+        repeat: 1,
+    },
+    Workload {
+        name: "property_access",
+        phase: Phase::Execute,
+        snippet: "var o = {x: 1, y: 2}; var s = 0; for (var i = 0; i < 100000; i = i + 1) { s = s + o.x + o.y; o.x = i } s",
+        repeat: 1,
+    },
+    Workload {
+        name: "calls",
+        phase: Phase::Execute,
+        snippet: "function add(a, b) { return a + b } var s = 0; for (var i = 0; i < 100000; i = i + 1) { s = add(s, i) } s",
+        repeat: 1,
+    },
+    Workload {
+        name: "string_building",
+        phase: Phase::Execute,
+        snippet: "var str = \"\"; for (var i = 0; i < 10000; i = i + 1) { str = str + \"ab\" } i",
+        repeat: 1,
+    },
+    Workload {
+        name: "gc_churn",
+        phase: Phase::Execute,
+        snippet: "var keep; for (var i = 0; i < 20000; i = i + 1) { keep = {a: i, b: [i, i + 1], c: \"x\" + i} } i",
+        repeat: 1,
+    },
+    Workload {
+        name: "parse",
+        phase: Phase::Parse,
+        snippet: PROGRAM_SNIPPET,
+        repeat: 500,
+    },
+    Workload {
+        name: "compile",
+        phase: Phase::Compile,
+        snippet: PROGRAM_SNIPPET,
+        repeat: 500,
+    },
+];
+
+/// A typical function of application code, repeated to make a script large
+/// enough to time parsing and compiling
+const PROGRAM_SNIPPET: &str = "
+function step(a, b) {
+    var t = {x: a, y: [b, \"label\", true, null]};
+    if (a > b) {
+        return t.x * 2 + t.y[0];
+    } else {
+        while (b > 0) { b = b - 1; }
+    }
+    return a + b;
+}
+step(3, 2);
+";
+
+/// The workloads of the suite called `name`
+pub fn suite(name: &str) -> Option<&'static [Workload]> {
+    match name {
+        "internal" => Some(INTERNAL),
+        _ => None,
+    }
+}
+
+impl Workload {
+    pub fn source(&self) -> String {
+        self.snippet.repeat(self.repeat)
+    }
+
+    /// Set the workload up to run repeatedly, doing the work it does not
+    /// time once here
+    pub fn prepare(&self) -> Result<Prepared, BebionError> {
+        let source = self.source();
+        let parse = |source: &str| Parser::new().parse(source).map_err(|e| BebionError::ParseError(e.to_string()));
+        Ok(match self.phase {
+            Phase::Parse => Prepared::Parse { source },
+            Phase::Compile => Prepared::Compile { program: parse(&source)? },
+            Phase::Execute => {
+                let program = parse(&source)?;
+                let bytecode = Compiler::new()
+                    .compile(&program)
+                    .map_err(|e| BebionError::CompileError(e.to_string()))?;
+                Prepared::Execute { engine: Box::new(BebionEngine::new()?), bytecode }
+            }
+        })
+    }
+}
+
+/// A workload ready to run
+pub enum Prepared {
+    Parse { source: String },
+    Compile { program: Program },
+    Execute { engine: Box<BebionEngine>, bytecode: Bytecode },
+}
+
+impl Prepared {
+    /// Run the timed part of the workload once
+    pub fn run(&mut self) -> Result<(), BebionError> {
+        match self {
+            Prepared::Parse { source } => {
+                Parser::new().parse(source).map_err(|e| BebionError::ParseError(e.to_string()))?;
+            }
+            Prepared::Compile { program } => {
+                Compiler::new().compile(program).map_err(|e| BebionError::CompileError(e.to_string()))?;
+            }
+            Prepared::Execute { engine, bytecode } => {
+                engine.execute_bytecode(bytecode)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Put things back between samples, outside the timing
+    pub fn reset(&mut self) {
+        if let Prepared::Execute { engine, .. } = self {
+            engine.gc_collect();
+        }
+    }
+}
+
+/// How long a workload took
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub name: &'static str,
+    /// Median over the samples
+    pub time: Duration,
+    pub samples: usize,
+}
+
+/// Time `workload`: one warm-up run, then samples until `measurement_time`
+/// has passed
+pub fn measure(workload: &Workload, measurement_time: Duration) -> Result<Measurement, BebionError> {
+    let mut prepared = workload.prepare()?;
+    prepared.run()?;
+    prepared.reset();
+
+    let mut times = Vec::new();
+    let start = Instant::now();
+    while times.len() < MIN_SAMPLES || start.elapsed() < measurement_time {
+        let sample_start = Instant::now();
+        prepared.run()?;
+        times.push(sample_start.elapsed());
+        prepared.reset();
+    }
+    times.sort();
+    debug!("Measured {} over {} samples", workload.name, times.len());
+    Ok(Measurement {
+        name: workload.name,
+        time: times[times.len() / 2],
+        samples: times.len(),
+    })
+}
+
+/// Measure the workloads whose name contains `filter`, handing each
+/// measurement to `on_result` as it completes
+pub fn run_suite(
+    workloads: &[Workload],
+    filter: Option<&str>,
+    measurement_time: Duration,
+    mut on_result: impl FnMut(&Measurement),
+) -> Result<Report, BebionError> {
+    let measurements = workloads
+        .iter()
+        .filter(|workload| filter.is_none_or(|filter| workload.name.contains(filter)))
+        .map(|workload| {
+            let measurement = measure(workload, measurement_time)?;
+            on_result(&measurement);
+            Ok(measurement)
+        })
+        .collect::<Result<_, BebionError>>()?;
+    Ok(Report { measurements })
+}
+
+/// The measurements of a run
+pub struct Report {
+    pub measurements: Vec<Measurement>,
+}
+
+/// A measurement next to the baseline's timing of the same workload
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub name: &'static str,
+    pub time: Duration,
+    /// `None` for a workload the baseline has no timing for
+    pub baseline: Option<Duration>,
+}
+
+impl Comparison {
+    /// The change from the baseline as a fraction: `0.25` is 25% slower,
+    /// `-0.5` twice as fast
+    pub fn change(&self) -> Option<f64> {
+        let baseline = self.baseline?.as_secs_f64();
+        (baseline > 0.0).then(|| self.time.as_secs_f64() / baseline - 1.0)
+    }
+
+    /// Whether the workload got slower by more than `threshold`
+    pub fn is_regression(&self, threshold: f64) -> bool {
+        self.change().is_some_and(|change| change > threshold)
+    }
+}
+
+impl Report {
+    pub fn compare(&self, baseline: &Baseline) -> Vec<Comparison> {
+        self.measurements
+            .iter()
+            .map(|measurement| Comparison {
+                name: measurement.name,
+                time: measurement.time,
+                baseline: baseline.timings.get(measurement.name).map(|&nanos| Duration::from_nanos(nanos)),
+            })
+            .collect()
+    }
+
+    /// Record this run's timings into `baseline`; workloads that did not
+    /// run keep their earlier timing
+    pub fn update_baseline(&self, baseline: &mut Baseline) {
+        for measurement in &self.measurements {
+            baseline.timings.insert(measurement.name.to_string(), measurement.time.as_nanos() as u64);
+        }
+    }
+}
+
+/// Workload timings of an earlier run, stored as a JSON object of
+/// nanoseconds by workload name, sorted so it diffs well
+#[derive(Debug, Default)]
+pub struct Baseline {
+    pub timings: BTreeMap<String, u64>,
+}
+
+impl Baseline {
+    /// Load `path`, or start empty if it does not exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let timings =
+            serde_json::from_str(&contents).map_err(|e| format!("Invalid baseline {}: {}", path.display(), e))?;
+        Ok(Self { timings })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(&self.timings).map_err(|e| e.to_string())?;
+        fs::write(path, contents + "\n").map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+}
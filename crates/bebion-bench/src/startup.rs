@@ -0,0 +1,159 @@
+//! Startup timings
+//!
+//! How long it takes to get from nothing to a script's first result, broken
+//! down by the subsystems that start along the way. Each phase is timed
+//! cold, on its first run in the process, and warm, as the median of the
+//! runs after that. Whatever a phase needs but does not time, such as the
+//! engine a first execution runs on, is set up outside the timing.
+//!
+//! The engine has no startup snapshots yet, so there is no restore phase
+//! to time.
+
+use bebion_compiler::Compiler;
+use bebion_core::{BebionEngine, BebionError};
+use bebion_gc::GarbageCollector;
+use bebion_parser::Parser;
+use bebion_runtime::Runtime;
+use bebion_std::StandardLibrary;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::MIN_SAMPLES;
+
+/// One part of startup
+pub struct StartupPhase {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The phase this one is part of, for the subsystems `engine` starts
+    pub within: Option<&'static str>,
+    /// Run the phase once, returning how long its timed part took
+    run: fn() -> Result<Duration, BebionError>,
+}
+
+const PHASES: &[StartupPhase] = &[
+    StartupPhase {
+        name: "heap",
+        description: "garbage collector",
+        within: Some("engine"),
+        run: || Ok(time(GarbageCollector::new).0),
+    },
+    StartupPhase {
+        name: "runtime",
+        description: "VM and built-in globals",
+        within: Some("engine"),
+        run: || {
+            let gc = Arc::new(Mutex::new(GarbageCollector::new()));
+            Ok(time(|| Runtime::new(gc)).0)
+        },
+    },
+    StartupPhase {
+        name: "frontend",
+        description: "parser and compiler",
+        within: Some("engine"),
+        run: || Ok(time(|| (Parser::new(), Compiler::new())).0),
+    },
+    StartupPhase {
+        name: "engine",
+        description: "BebionEngine::new, with module loading, timers and error hooks",
+        within: None,
+        run: || {
+            let (elapsed, engine) = time(BebionEngine::new);
+            engine?;
+            Ok(elapsed)
+        },
+    },
+    StartupPhase {
+        name: "stdlib",
+        description: "standard library modules, built and initialized",
+        within: None,
+        run: || {
+            let mut runtime = Runtime::new(Arc::new(Mutex::new(GarbageCollector::new())));
+            let (elapsed, initialized) = time(|| StandardLibrary::new().initialize_all(&mut runtime));
+            initialized.map_err(|e| BebionError::RuntimeError(e.to_string()))?;
+            Ok(elapsed)
+        },
+    },
+    StartupPhase {
+        name: "first_execution",
+        description: "parsing, compiling and running a script on a new engine",
+        within: None,
+        run: || {
+            let mut engine = BebionEngine::new()?;
+            let (elapsed, result) = time(|| engine.execute_script("0"));
+            result?;
+            Ok(elapsed)
+        },
+    },
+];
+
+/// How long `f` took, and what it returned
+fn time<T>(f: impl FnOnce() -> T) -> (Duration, T) {
+    let start = Instant::now();
+    let value = f();
+    (start.elapsed(), value)
+}
+
+/// How long a startup phase took
+#[derive(Debug, Clone)]
+pub struct StartupMeasurement {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub within: Option<&'static str>,
+    /// The first run in this process
+    pub cold: Duration,
+    /// Median over the runs after the first
+    pub warm: Duration,
+    pub samples: usize,
+}
+
+/// Time each phase of startup, sampling each for `measurement_time` after
+/// its cold run, and hand each measurement to `on_result` as it completes
+pub fn measure_startup(
+    measurement_time: Duration,
+    mut on_result: impl FnMut(&StartupMeasurement),
+) -> Result<StartupReport, BebionError> {
+    let measurements = PHASES
+        .iter()
+        .map(|phase| {
+            let cold = (phase.run)()?;
+            let mut times = Vec::new();
+            let start = Instant::now();
+            while times.len() < MIN_SAMPLES || start.elapsed() < measurement_time {
+                times.push((phase.run)()?);
+            }
+            times.sort();
+            debug!("Measured startup phase {} over {} samples", phase.name, times.len());
+
+            let measurement = StartupMeasurement {
+                name: phase.name,
+                description: phase.description,
+                within: phase.within,
+                cold,
+                warm: times[times.len() / 2],
+                samples: times.len(),
+            };
+            on_result(&measurement);
+            Ok(measurement)
+        })
+        .collect::<Result<_, BebionError>>()?;
+    Ok(StartupReport { measurements })
+}
+
+/// The phases of a startup run
+pub struct StartupReport {
+    pub measurements: Vec<StartupMeasurement>,
+}
+
+impl StartupReport {
+    /// Cold and warm time to a first result: the phases that are not part
+    /// of another
+    pub fn total(&self) -> (Duration, Duration) {
+        self.measurements
+            .iter()
+            .filter(|measurement| measurement.within.is_none())
+            .fold((Duration::ZERO, Duration::ZERO), |(cold, warm), measurement| {
+                (cold + measurement.cold, warm + measurement.warm)
+            })
+    }
+}
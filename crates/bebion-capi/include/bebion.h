@@ -0,0 +1,85 @@
+/*
+ * Bebion C API
+ *
+ * Embeds the bebion JavaScript engine in C, C++, Python (ctypes/cffi), Go
+ * (cgo) and anything else that can call C. Link against libbebion, built
+ * with `cargo build -p bebion-capi --release`.
+ *
+ * Values cross the boundary as UTF-8 JSON text. Strings returned by the
+ * library are owned by the caller and must be released with
+ * bebion_string_free. Functions returning bebion_status report failures
+ * through bebion_last_error.
+ *
+ * An engine must only be used from one thread at a time.
+ */
+
+#ifndef BEBION_H
+#define BEBION_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct bebion_engine bebion_engine;
+
+typedef enum bebion_status {
+    BEBION_OK = 0,
+    BEBION_ERROR_PARSE = 1,
+    BEBION_ERROR_COMPILE = 2,
+    BEBION_ERROR_RUNTIME = 3,
+    BEBION_ERROR_MODULE = 4,
+    BEBION_ERROR_INVALID_ARGUMENT = 5,
+    BEBION_ERROR_JSON = 6,
+    BEBION_ERROR_PANIC = 7,
+} bebion_status;
+
+/*
+ * A host function callable from scripts. Receives the arguments as a JSON
+ * array and returns its result as JSON text, or NULL for undefined. The
+ * engine copies the result, so it only needs to stay valid until the
+ * callback returns to the engine again.
+ */
+typedef char *(*bebion_callback)(void *user_data, const char *args_json);
+
+/* Create an engine, or return NULL and set the last error */
+bebion_engine *bebion_engine_new(void);
+
+/* Shut an engine down and free it. Accepts NULL. */
+void bebion_engine_free(bebion_engine *engine);
+
+/*
+ * Run a script. On success, when result_json is not NULL, it receives the
+ * script's completion value as JSON, or NULL when the value has no JSON
+ * form (undefined, functions).
+ */
+bebion_status bebion_eval(bebion_engine *engine, const char *source, char **result_json);
+
+/* A global's value as JSON, or NULL when it is undefined or has no JSON form */
+char *bebion_get_global(bebion_engine *engine, const char *name);
+
+/* Define a global from JSON text */
+bebion_status bebion_set_global(bebion_engine *engine, const char *name, const char *json);
+
+/*
+ * Register a host function under a global name. Scripts call it with JSON
+ * arguments; arguments with no JSON form arrive as null. user_data is passed
+ * back to every call and must outlive the engine. The callback runs on the
+ * thread that is running the script.
+ */
+bebion_status bebion_register_function(bebion_engine *engine, const char *name,
+                                       bebion_callback callback, void *user_data);
+
+/*
+ * Message of the last failed call on this thread, or NULL. Valid until the
+ * next call into the library on this thread; do not free it.
+ */
+const char *bebion_last_error(void);
+
+/* Free a string returned by the library. Accepts NULL. */
+void bebion_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BEBION_H */
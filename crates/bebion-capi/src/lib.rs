@@ -0,0 +1,274 @@
+//! Bebion C API
+//!
+//! A stable C ABI over `BebionEngine` for embedding bebion from languages
+//! other than Rust. `include/bebion.h` declares everything exported here.
+//! Values cross the boundary as JSON text, errors are reported through a
+//! status code plus a thread-local message, and panics are caught before
+//! they can unwind into the caller.
+
+use bebion_core::{BebionEngine, BebionError};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BebionStatus {
+    Ok = 0,
+    ErrorParse = 1,
+    ErrorCompile = 2,
+    ErrorRuntime = 3,
+    ErrorModule = 4,
+    ErrorInvalidArgument = 5,
+    ErrorJson = 6,
+    ErrorPanic = 7,
+}
+
+pub type BebionCallback = extern "C" fn(user_data: *mut c_void, args_json: *const c_char) -> *const c_char;
+
+/// A host function registered through `bebion_register_function`
+#[derive(Clone, Copy)]
+pub struct HostFunction {
+    callback: BebionCallback,
+    user_data: *mut c_void,
+}
+
+// SAFETY: `bebion.h` requires `user_data` to outlive the engine, and the
+// engine calls host functions only on the thread running the script
+unsafe impl Send for HostFunction {}
+unsafe impl Sync for HostFunction {}
+
+impl HostFunction {
+    /// Call the function with `args`, returning its result as JSON
+    pub fn call(&self, args: &[serde_json::Value]) -> Result<serde_json::Value, String> {
+        let args = CString::new(serde_json::Value::from(args.to_vec()).to_string())
+            .map_err(|e| e.to_string())?;
+        let result = (self.callback)(self.user_data, args.as_ptr());
+        if result.is_null() {
+            return Ok(serde_json::Value::Null);
+        }
+
+        // SAFETY: the callback returns a NUL-terminated string that stays
+        // valid until it is called again
+        let result = unsafe { CStr::from_ptr(result) };
+        let result = result.to_str().map_err(|e| e.to_string())?;
+        serde_json::from_str(result).map_err(|e| format!("Invalid JSON from host function: {}", e))
+    }
+}
+
+/// The engine behind a `bebion_engine *`
+pub struct Engine {
+    engine: BebionEngine,
+    functions: HashMap<String, HostFunction>,
+}
+
+impl Engine {
+    pub fn host_function(&self, name: &str) -> Option<&HostFunction> {
+        self.functions.get(name)
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = CString::new(message.into()).unwrap_or_else(|_| CString::from(c"invalid error message"));
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+fn engine_status(error: &BebionError) -> BebionStatus {
+    match error {
+        BebionError::ParseError(_) => BebionStatus::ErrorParse,
+        BebionError::CompileError(_) => BebionStatus::ErrorCompile,
+        BebionError::RuntimeError(_) => BebionStatus::ErrorRuntime,
+        BebionError::ModuleError(_) => BebionStatus::ErrorModule,
+    }
+}
+
+/// Run `f`, turning a panic into `BebionStatus::ErrorPanic`
+fn guard(f: impl FnOnce() -> Result<(), (BebionStatus, String)>) -> BebionStatus {
+    clear_last_error();
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => BebionStatus::Ok,
+        Ok(Err((status, message))) => {
+            set_last_error(message);
+            status
+        }
+        Err(_) => {
+            set_last_error("bebion panicked");
+            BebionStatus::ErrorPanic
+        }
+    }
+}
+
+/// Borrow a C string argument
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, (BebionStatus, String)> {
+    if ptr.is_null() {
+        return Err((BebionStatus::ErrorInvalidArgument, format!("{} is NULL", what)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| (BebionStatus::ErrorInvalidArgument, format!("{} is not valid UTF-8", what)))
+}
+
+/// # Safety
+///
+/// `engine` must be null or a pointer from `bebion_engine_new` that has not
+/// been freed.
+unsafe fn engine_arg<'a>(engine: *mut Engine) -> Result<&'a mut Engine, (BebionStatus, String)> {
+    engine
+        .as_mut()
+        .ok_or_else(|| (BebionStatus::ErrorInvalidArgument, "engine is NULL".to_string()))
+}
+
+fn into_c_string(json: serde_json::Value) -> *mut c_char {
+    // serde_json escapes NUL in strings, so the text never contains one
+    CString::new(json.to_string()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+#[no_mangle]
+pub extern "C" fn bebion_engine_new() -> *mut Engine {
+    let mut engine = ptr::null_mut();
+    guard(|| {
+        let inner = BebionEngine::new().map_err(|e| (engine_status(&e), e.to_string()))?;
+        engine = Box::into_raw(Box::new(Engine {
+            engine: inner,
+            functions: HashMap::new(),
+        }));
+        Ok(())
+    });
+    engine
+}
+
+/// # Safety
+///
+/// `engine` must be null or a pointer from `bebion_engine_new` that has not
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bebion_engine_free(engine: *mut Engine) {
+    if engine.is_null() {
+        return;
+    }
+    guard(|| {
+        let mut engine = Box::from_raw(engine);
+        engine.engine.shutdown();
+        Ok(())
+    });
+}
+
+/// # Safety
+///
+/// `engine` must be a live engine, `source` a NUL-terminated string and
+/// `result_json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bebion_eval(
+    engine: *mut Engine,
+    source: *const c_char,
+    result_json: *mut *mut c_char,
+) -> BebionStatus {
+    if !result_json.is_null() {
+        *result_json = ptr::null_mut();
+    }
+    guard(|| {
+        let engine = engine_arg(engine)?;
+        let source = str_arg(source, "source")?;
+
+        let result = engine
+            .engine
+            .execute_script(source)
+            .map_err(|e| (engine_status(&e), e.to_string()))?;
+        if !result_json.is_null() {
+            if let Some(json) = engine.engine.json_of(result) {
+                *result_json = into_c_string(json);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `engine` must be a live engine and `name` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn bebion_get_global(engine: *mut Engine, name: *const c_char) -> *mut c_char {
+    let mut json = ptr::null_mut();
+    guard(|| {
+        let engine = engine_arg(engine)?;
+        let name = str_arg(name, "name")?;
+        if let Some(value) = engine.engine.get_global_json(name) {
+            json = into_c_string(value);
+        }
+        Ok(())
+    });
+    json
+}
+
+/// # Safety
+///
+/// `engine` must be a live engine and `name` and `json` NUL-terminated
+/// strings.
+#[no_mangle]
+pub unsafe extern "C" fn bebion_set_global(
+    engine: *mut Engine,
+    name: *const c_char,
+    json: *const c_char,
+) -> BebionStatus {
+    guard(|| {
+        let engine = engine_arg(engine)?;
+        let name = str_arg(name, "name")?;
+        let json = str_arg(json, "json")?;
+        let value: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| (BebionStatus::ErrorJson, format!("Invalid JSON for {}: {}", name, e)))?;
+        engine.engine.set_global_json(name, &value);
+        Ok(())
+    })
+}
+
+/// # Safety
+///
+/// `engine` must be a live engine, `name` a NUL-terminated string, and
+/// `user_data` must stay valid for as long as the engine lives.
+#[no_mangle]
+pub unsafe extern "C" fn bebion_register_function(
+    engine: *mut Engine,
+    name: *const c_char,
+    callback: Option<BebionCallback>,
+    user_data: *mut c_void,
+) -> BebionStatus {
+    guard(|| {
+        let engine = engine_arg(engine)?;
+        let name = str_arg(name, "name")?;
+        let callback = callback.ok_or_else(|| (BebionStatus::ErrorInvalidArgument, "callback is NULL".to_string()))?;
+        let function = HostFunction { callback, user_data };
+        engine.functions.insert(name.to_string(), function);
+        engine.engine.set_global_json_function(name, move |args| function.call(args));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bebion_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// # Safety
+///
+/// `string` must be null or a string returned by this library that has not
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn bebion_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
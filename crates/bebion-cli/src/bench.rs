@@ -0,0 +1,121 @@
+//! `bebion bench`: micro-benchmark runs for engine development
+
+use bebion_bench::startup::{self, StartupMeasurement};
+use bebion_bench::{Baseline, Measurement, DEFAULT_MEASUREMENT_TIME};
+use colored::*;
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+/// Run the workloads of `suite`, printing each timing as it is measured.
+/// With a baseline, also prints how each compares and exits with status 1
+/// if any got slower by more than `threshold`, a fraction.
+pub fn run_bench(
+    suite: &str,
+    filter: Option<&str>,
+    baseline_path: Option<&Path>,
+    update_baseline: bool,
+    threshold: f64,
+    measurement_time: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let workloads = bebion_bench::suite(suite).ok_or_else(|| {
+        format!("Unknown benchmark suite '{}' (available: {})", suite, bebion_bench::SUITES.join(", "))
+    })?;
+    info!("Running benchmark suite {}", suite);
+
+    let report = bebion_bench::run_suite(
+        workloads,
+        filter,
+        measurement_time.unwrap_or(DEFAULT_MEASUREMENT_TIME),
+        |measurement: &Measurement| {
+            println!("{:<20} {:>12?} ({} samples)", measurement.name, measurement.time, measurement.samples);
+        },
+    )?;
+
+    let Some(baseline_path) = baseline_path else {
+        return Ok(());
+    };
+    let mut baseline = Baseline::load(baseline_path)?;
+
+    println!("\n{}", "Compared to baseline:".bright_blue().bold());
+    let mut regressions = 0;
+    for comparison in report.compare(&baseline) {
+        let (Some(base), Some(change)) = (comparison.baseline, comparison.change()) else {
+            println!("  {:<20} {}", comparison.name, "new".yellow());
+            continue;
+        };
+        let change_text = format!("{:+.1}%", change * 100.0);
+        let change_text = if comparison.is_regression(threshold) {
+            regressions += 1;
+            change_text.red().bold()
+        } else if change < -threshold {
+            change_text.green()
+        } else {
+            change_text.normal()
+        };
+        println!("  {:<20} {:>12?} -> {:>12?} {}", comparison.name, base, comparison.time, change_text);
+    }
+    if regressions > 0 {
+        println!(
+            "{} {} workloads regressed by more than {:.0}%",
+            "✗".red().bold(),
+            regressions,
+            threshold * 100.0
+        );
+    }
+
+    if update_baseline {
+        report.update_baseline(&mut baseline);
+        baseline.save(baseline_path)?;
+        println!("Updated baseline {}", baseline_path.display());
+    } else if regressions > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Time each phase of engine startup, printing each as it is measured and
+/// then the share of the warm total each took. With a budget in
+/// milliseconds, exits with status 1 if warm startup takes longer.
+pub fn run_startup_bench(measurement_time: Option<Duration>, budget: Option<f64>) -> Result<(), Box<dyn std::error::Error>> {
+    info!("Timing engine startup");
+
+    println!("{:<22} {:>12} {:>12}", "Phase".bold(), "cold".bold(), "warm".bold());
+    let report = startup::measure_startup(
+        measurement_time.unwrap_or(DEFAULT_MEASUREMENT_TIME),
+        |measurement: &StartupMeasurement| {
+            let name = match measurement.within {
+                Some(_) => format!("  {}", measurement.name),
+                None => measurement.name.to_string(),
+            };
+            println!(
+                "{:<22} {:>12?} {:>12?}  {}",
+                name,
+                measurement.cold,
+                measurement.warm,
+                measurement.description.dimmed()
+            );
+        },
+    )?;
+    println!("{:<22} {:>12} {:>12}  {}", "snapshot_restore", "-", "-", "no startup snapshots yet".dimmed());
+
+    let (cold, warm) = report.total();
+    println!("{:<22} {:>12?} {:>12?}", "total".bold(), cold, warm);
+
+    println!("\n{}", "Share of warm startup:".bright_blue().bold());
+    for measurement in report.measurements.iter().filter(|measurement| measurement.within.is_none()) {
+        let share = measurement.warm.as_secs_f64() / warm.as_secs_f64().max(f64::MIN_POSITIVE);
+        println!("  {:<20} {:>5.1}%", measurement.name, share * 100.0);
+    }
+
+    let Some(budget) = budget else {
+        return Ok(());
+    };
+    let total = warm.as_secs_f64() * 1000.0;
+    if total > budget {
+        println!("{} Warm startup took {:.2}ms, over the {}ms budget", "✗".red().bold(), total, budget);
+        std::process::exit(1);
+    }
+    println!("{} Warm startup took {:.2}ms, within the {}ms budget", "✓".green().bold(), total, budget);
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Bytecode debugger behind the REPL's `.debug` command
+//!
+//! Compiles the input, then steps through it an instruction at a time,
+//! showing where it is and, on request, the operand stack, locals and the
+//! constant pool. A call into a built-in is a single step.
+
+use crate::repl::format_value;
+use bebion_core::{BebionEngine, FrameSnapshot};
+use colored::*;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+/// Step through `source` until it finishes or the user leaves
+pub fn debug_session(rl: &mut DefaultEditor, engine: &mut BebionEngine, source: &str) -> rustyline::Result<()> {
+    if let Err(err) = engine.debug_script(source) {
+        println!("{}: {}", "Error".red().bold(), err);
+        return Ok(());
+    }
+    println!("Debugging; {} for commands", ".help".yellow());
+    show_location(engine, source);
+
+    let prompt = format!("{}> ", "debug".bright_magenta());
+    loop {
+        let line = match rl.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                engine.debug_abort();
+                println!("Debugging stopped");
+                return Ok(());
+            }
+            Err(err) => {
+                engine.debug_abort();
+                return Err(err);
+            }
+        };
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            // An empty line steps, so holding Enter walks through the code
+            None | Some(".step") | Some("s") => {
+                let count = match words.next().map(str::parse::<usize>) {
+                    None => 1,
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => {
+                        println!("{}: .step takes a number of instructions", "Error".red().bold());
+                        continue;
+                    }
+                };
+                if step(engine, count) {
+                    return Ok(());
+                }
+                show_location(engine, source);
+            }
+            Some(".continue") | Some("c") => {
+                step(engine, usize::MAX);
+                return Ok(());
+            }
+            Some(".regs") | Some("r") => show_registers(engine),
+            Some(".consts") => show_constants(engine),
+            Some(".where") | Some("w") => show_location(engine, source),
+            Some(".abort") | Some("q") => {
+                engine.debug_abort();
+                println!("Debugging stopped");
+                return Ok(());
+            }
+            Some(".help") => show_help(),
+            Some(other) => println!("{}: Unknown debugger command: {}", "Error".red().bold(), other),
+        }
+    }
+}
+
+/// Run up to `count` instructions. Returns whether the script finished.
+fn step(engine: &mut BebionEngine, count: usize) -> bool {
+    for _ in 0..count {
+        match engine.debug_step() {
+            Ok(None) => {}
+            Ok(Some(value)) => {
+                println!("{}", format!("=> {}", format_value(value)).bright_cyan());
+                return true;
+            }
+            Err(err) => {
+                println!("{}: {}", "Error".red().bold(), err);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn show_location(engine: &BebionEngine, source: &str) {
+    let Some(frame) = engine.debug_frame() else {
+        return;
+    };
+    let instruction = match &frame.instruction {
+        Some(instruction) => format!("{:?}", instruction),
+        None => "<end>".to_string(),
+    };
+    println!(
+        "{} {} pc {}: {}",
+        format!("#{}", frame.depth).bright_black(),
+        frame_name(&frame).yellow(),
+        frame.pc,
+        instruction.bold()
+    );
+
+    let source_line = frame
+        .line
+        .and_then(|line| Some((line, source.lines().nth(line.checked_sub(1)?)?)));
+    if let Some((line, text)) = source_line {
+        println!("{} {}", format!("{:>4} |", line).bright_black(), text);
+        if let Some(column) = frame.column {
+            println!("{} {}^", "     |".bright_black(), " ".repeat(column.saturating_sub(1)));
+        }
+    }
+}
+
+fn show_registers(engine: &BebionEngine) {
+    let Some(frame) = engine.debug_frame() else {
+        return;
+    };
+    println!("{}", "Stack (top last):".bright_blue().bold());
+    if frame.stack.is_empty() {
+        println!("  (empty)");
+    }
+    for (slot, value) in frame.stack.iter().enumerate() {
+        println!("  [{}] {}", slot, format_value(value.clone()));
+    }
+    println!("{}", "Locals:".bright_blue().bold());
+    if frame.locals.is_empty() {
+        println!("  (none)");
+    }
+    for (index, value) in frame.locals.iter().enumerate() {
+        println!("  local {} = {}", index, format_value(value.clone()));
+    }
+}
+
+fn show_constants(engine: &BebionEngine) {
+    let Some(frame) = engine.debug_frame() else {
+        return;
+    };
+    println!("{} of {}", "Constants".bright_blue().bold(), frame_name(&frame));
+    if frame.constants.is_empty() {
+        println!("  (none)");
+    }
+    for (index, constant) in frame.constants.iter().enumerate() {
+        println!("  {:>3}: {:?}", index, constant);
+    }
+}
+
+fn frame_name(frame: &FrameSnapshot) -> &str {
+    frame.function.as_deref().unwrap_or("<script>")
+}
+
+fn show_help() {
+    println!("{}", "Debugger Commands:".bright_blue().bold());
+    println!("  {} - Run the next n instructions (default 1, also Enter)", ".step [n]".yellow());
+    println!("  {}   - Show the operand stack and locals", ".regs".yellow());
+    println!("  {} - Show the constant pool", ".consts".yellow());
+    println!("  {}  - Show the current instruction and source line", ".where".yellow());
+    println!("  {} - Run to the end", ".continue".yellow());
+    println!("  {}  - Stop without finishing", ".abort".yellow());
+}
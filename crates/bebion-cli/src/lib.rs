@@ -1,13 +1,34 @@
 //! Bebion CLI interface
 
+pub mod bench;
+pub mod debugger;
+pub mod heap_snapshot;
 pub mod repl;
 pub mod runner;
+pub mod standalone;
+pub mod test262;
 
-use bebion_core::BebionEngine;
+use bebion_compiler::OptLevel;
+use bebion_core::{BebionEngine, EngineEvent, GcConfig, UnhandledRejections};
+use bebion_parser::ExperimentalFeatures;
+use bebion_std::tty::{self, Stream};
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{error, info};
 
+/// Optional components and whether this build includes them, for `bebion info`
+const FEATURES: &[(&str, bool)] = &[
+    ("jit", cfg!(feature = "jit")),
+    ("http", cfg!(feature = "http")),
+    ("net", cfg!(feature = "net")),
+    ("crypto", cfg!(feature = "crypto")),
+    ("archive", cfg!(feature = "archive")),
+    ("ffi", cfg!(feature = "ffi")),
+    ("wasi", cfg!(feature = "wasi")),
+];
+
 #[derive(Parser)]
 #[command(name = "bebion")]
 #[command(about = "Bebion JavaScript Runtime")]
@@ -24,9 +45,73 @@ pub struct Cli {
     #[arg(short, long)]
     pub verbose: bool,
     
-    /// Enable debug mode
+    /// Enable debug mode: compile with `DebugInfo` line markers
     #[arg(short, long)]
     pub debug: bool,
+
+    /// Mirror console output to FILE as inspector protocol messages
+    #[arg(long, value_name = "FILE")]
+    pub inspect: Option<PathBuf>,
+
+    /// Seed Math.random for reproducible runs
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+
+    /// Freeze Date.now at an ISO 8601 timestamp, seed Math.random from it
+    /// and run timers in virtual time, for reproducible output
+    #[arg(long, value_name = "TIMESTAMP", value_parser = parse_frozen_time)]
+    pub frozen_time: Option<f64>,
+
+    /// What to do about promises rejected with no handler: warn, strict
+    /// (fail the run) or none
+    #[arg(long, value_name = "MODE", default_value = "warn", value_parser = parse_unhandled_rejections)]
+    pub unhandled_rejections: UnhandledRejections,
+
+    /// Fail if timers and async work still keep the script running after
+    /// this many seconds
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    pub max_run_time: Option<Duration>,
+
+    /// Optimization level: 0 disables optimization, 2 also inlines tiny functions
+    #[arg(short = 'O', value_name = "LEVEL", default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub opt_level: u8,
+
+    /// Run everything in the interpreter instead of compiling hot functions
+    #[arg(long)]
+    pub no_jit: bool,
+
+    /// Enable an experimental language feature (e.g. decorators)
+    #[arg(long = "experimental", value_name = "FEATURE")]
+    pub experimental: Vec<String>,
+
+    /// Fail with an out-of-memory error once the heap outgrows this size
+    /// (e.g. 256M)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_heap: Option<usize>,
+
+    /// Heap growth that starts a minor collection (e.g. 4M)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub gc_young_size: Option<usize>,
+
+    /// Live heap size that makes collections full ones (e.g. 64M)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub gc_old_size: Option<usize>,
+
+    /// Also collect after this many allocations, however small
+    #[arg(long, value_name = "COUNT")]
+    pub gc_interval: Option<usize>,
+
+    /// Print a line to stderr for every garbage collection
+    #[arg(long)]
+    pub gc_trace: bool,
+
+    /// Print inline cache hit rates to stderr after the run
+    #[arg(long)]
+    pub ic_stats: bool,
+
+    /// Start the REPL without running ~/.bebionrc.js
+    #[arg(long, global = true)]
+    pub no_rc: bool,
 }
 
 #[derive(Subcommand)]
@@ -36,6 +121,11 @@ pub enum Commands {
         /// JavaScript file to run
         file: PathBuf,
         
+        /// Keep running, hot-replacing modules that accept updates and
+        /// restarting the file from scratch on other changes
+        #[arg(short, long)]
+        watch: bool,
+        
         /// Arguments to pass to the script
         #[arg(last = true)]
         args: Vec<String>,
@@ -54,6 +144,12 @@ pub enum Commands {
     /// Show engine information
     Info,
     
+    /// Report every syntax error in a file without running it
+    Check {
+        /// JavaScript file to check
+        file: PathBuf,
+    },
+    
     /// Compile JavaScript to bytecode
     Compile {
         /// Input JavaScript file
@@ -66,6 +162,89 @@ pub enum Commands {
         /// Pretty print the bytecode
         #[arg(short, long)]
         pretty: bool,
+        
+        /// Emit `DebugInfo` line markers for the VM to track
+        #[arg(long)]
+        debug: bool,
+        
+        /// Also write a JSON source map to `<output>.map`
+        #[arg(long, conflicts_with = "standalone")]
+        source_map: bool,
+        
+        /// Build a single executable that runs the script, instead of a
+        /// bytecode file
+        #[arg(long)]
+        standalone: bool,
+        
+        /// File to embed in the standalone executable (repeatable)
+        #[arg(long = "asset", value_name = "FILE", requires = "standalone")]
+        assets: Vec<PathBuf>,
+    },
+    
+    /// Run a JavaScript file and write its heap as a Chrome DevTools
+    /// `.heapsnapshot`, to find what keeps memory alive
+    HeapSnapshot {
+        /// JavaScript file to run
+        file: PathBuf,
+        
+        /// Snapshot file (defaults to the script's name with a
+        /// `.heapsnapshot` extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    
+    /// Run the test262 conformance suite (for engine development)
+    Test262 {
+        /// test262 checkout, or a directory or test inside one
+        #[arg(default_value = "test262")]
+        path: PathBuf,
+        
+        /// Only run tests whose path contains this
+        #[arg(long)]
+        filter: Option<String>,
+        
+        /// Passing tests of an earlier run, to report regressions against
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<PathBuf>,
+        
+        /// Record this run's results in the baseline
+        #[arg(long, requires = "baseline")]
+        update_baseline: bool,
+    },
+    
+    /// Time the engine's micro-benchmarks or its startup (for engine development)
+    Bench {
+        /// Benchmark suite to run
+        #[arg(long, default_value = "internal")]
+        suite: String,
+        
+        /// Only run workloads whose name contains this
+        #[arg(long)]
+        filter: Option<String>,
+        
+        /// Timings of an earlier run, to compare against
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<PathBuf>,
+        
+        /// Record this run's timings in the baseline
+        #[arg(long, requires = "baseline")]
+        update_baseline: bool,
+        
+        /// Percent slower than the baseline that counts as a regression
+        #[arg(long, value_name = "PERCENT", default_value_t = 10.0)]
+        threshold: f64,
+        
+        /// Seconds to spend sampling each workload
+        #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+        time: Option<Duration>,
+        
+        /// Time engine startup phase by phase instead of a suite
+        #[arg(long, conflicts_with_all = ["filter", "baseline", "update_baseline"])]
+        startup: bool,
+        
+        /// Milliseconds warm startup may take before `--startup` fails
+        #[arg(long, value_name = "MS", requires = "startup")]
+        budget: Option<f64>,
     },
     
     /// Package management
@@ -105,11 +284,93 @@ impl Cli {
         Self::parse()
     }
 
+    /// The collector settings the GC flags ask for
+    pub fn gc_config(&self) -> GcConfig {
+        let mut config = GcConfig::default();
+        if let Some(young_size) = self.gc_young_size {
+            config.young_threshold = young_size;
+            config.min_young_threshold = config.min_young_threshold.min(young_size);
+            config.max_young_threshold = config.max_young_threshold.max(young_size);
+        }
+        if let Some(old_size) = self.gc_old_size {
+            config.old_threshold = old_size;
+        }
+        config.allocation_interval = self.gc_interval;
+        config.max_heap_size = self.max_heap;
+        config
+    }
+
+    /// Set up `engine` as the runtime flags say
+    fn configure_engine(&self, engine: &mut BebionEngine) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(epoch_ms) = self.frozen_time {
+            engine.freeze_time(epoch_ms);
+        }
+
+        if let Some(seed) = self.seed {
+            engine.set_random_seed(seed);
+        }
+
+        engine.set_unhandled_rejections(self.unhandled_rejections);
+        if self.unhandled_rejections == UnhandledRejections::Warn {
+            engine.add_observer(|event: &EngineEvent| {
+                if let EngineEvent::UnhandledRejection { reason } = event {
+                    eprintln!("Warning: Unhandled promise rejection: {}", reason);
+                }
+            });
+        }
+
+        engine.set_max_run_time(self.max_run_time);
+
+        if self.gc_trace {
+            engine.set_gc_listener(|event| eprintln!("[gc] {}", event));
+        }
+
+        if let Some(opt_level) = OptLevel::from_level(self.opt_level) {
+            engine.set_opt_level(opt_level);
+        }
+        engine.set_debug_info(self.debug);
+
+        #[cfg(feature = "jit")]
+        if !self.no_jit {
+            engine.enable_jit();
+        }
+
+        #[cfg(feature = "ffi")]
+        engine.enable_ffi();
+
+        for feature in ExperimentalFeatures::from_names(&self.experimental)?.iter() {
+            if !feature.is_implemented() {
+                return Err(format!("Experimental feature '{}' is not implemented yet", feature).into());
+            }
+            engine.enable_feature(feature);
+        }
+
+        Ok(())
+    }
+
     pub fn run(&self, engine: &mut BebionEngine) -> Result<(), Box<dyn std::error::Error>> {
+        configure_colors();
+
+        if let Some(path) = &self.inspect {
+            info!("Mirroring console output to {}", path.display());
+            let inspector = bebion_std::console::FileInspector::create(path)?;
+            bebion_std::console::attach_inspector(Arc::new(inspector));
+        }
+
+        self.configure_engine(engine)?;
+
         match &self.command {
-            Some(Commands::Run { file, args }) => {
+            Some(Commands::Run { file, watch, args }) => {
                 info!("Running file: {:?}", file);
-                runner::run_file(engine, file, args)?;
+                if *watch {
+                    runner::watch_file(engine, file, args, || {
+                        let mut engine = BebionEngine::builder().gc_config(self.gc_config()).build()?;
+                        self.configure_engine(&mut engine)?;
+                        Ok(engine)
+                    })?;
+                } else {
+                    runner::run_file(engine, file, args)?;
+                }
             }
             
             Some(Commands::Repl { load }) => {
@@ -118,7 +379,7 @@ impl Cli {
                     info!("Loading file: {:?}", load_file);
                     runner::run_file(engine, load_file, &[])?;
                 }
-                repl::start_repl(engine)?;
+                repl::start_repl_with(engine, &self.repl_options())?;
             }
             
             Some(Commands::Version) => {
@@ -129,9 +390,45 @@ impl Cli {
                 self.show_info(engine);
             }
             
-            Some(Commands::Compile { input, output, pretty }) => {
+            Some(Commands::Check { file }) => {
+                info!("Checking file: {:?}", file);
+                runner::check_file(file)?;
+            }
+            
+            Some(Commands::Compile { input, output, pretty, debug, source_map, standalone, assets }) => {
                 info!("Compiling file: {:?}", input);
-                runner::compile_file(engine, input, output.as_ref(), *pretty)?;
+                if *debug {
+                    engine.set_debug_info(true);
+                }
+                if *standalone {
+                    standalone::compile_standalone(engine, input, output.as_ref(), assets)?;
+                } else {
+                    runner::compile_file(engine, input, output.as_ref(), *pretty, *source_map)?;
+                }
+            }
+            
+            Some(Commands::HeapSnapshot { file, output }) => {
+                info!("Writing heap snapshot of: {:?}", file);
+                heap_snapshot::write_heap_snapshot(engine, file, output.as_ref())?;
+            }
+            
+            Some(Commands::Test262 { path, filter, baseline, update_baseline }) => {
+                test262::run_test262(path, filter.as_deref(), baseline.as_deref(), *update_baseline)?;
+            }
+            
+            Some(Commands::Bench { startup: true, time, budget, .. }) => {
+                bench::run_startup_bench(*time, *budget)?;
+            }
+            
+            Some(Commands::Bench { suite, filter, baseline, update_baseline, threshold, time, .. }) => {
+                bench::run_bench(
+                    suite,
+                    filter.as_deref(),
+                    baseline.as_deref(),
+                    *update_baseline,
+                    *threshold / 100.0,
+                    *time,
+                )?;
             }
             
             Some(Commands::Package { action }) => {
@@ -144,17 +441,28 @@ impl Cli {
                     runner::run_file(engine, file, &[])?;
                 } else {
                     info!("Starting REPL");
-                    repl::start_repl(engine)?;
+                    repl::start_repl_with(engine, &self.repl_options())?;
                 }
             }
         }
         
+        if self.ic_stats {
+            print_ic_stats(engine);
+        }
         Ok(())
     }
 
+    /// How the REPL flags ask it to start
+    fn repl_options(&self) -> repl::ReplOptions {
+        repl::ReplOptions {
+            rc_file: if self.no_rc { None } else { repl::default_rc_file() },
+            ..repl::ReplOptions::default()
+        }
+    }
+
     fn show_version(&self) {
         println!("Bebion JavaScript Runtime v{}", env!("CARGO_PKG_VERSION"));
-        println!("Built with Rust {}", env!("RUSTC_VERSION"));
+        println!("Built with Rust {}", option_env!("RUSTC_VERSION").unwrap_or("(unknown version)"));
     }
 
     fn show_info(&self, engine: &BebionEngine) {
@@ -164,6 +472,11 @@ impl Cli {
         println!("Architecture: {}", std::env::consts::ARCH);
         println!("Platform: {}", std::env::consts::OS);
         
+        println!("\nFeatures:");
+        for (feature, enabled) in FEATURES {
+            println!("  {}: {}", feature, if *enabled { "yes" } else { "no" });
+        }
+        
         // Show GC stats
         let stats = engine.gc_stats();
         println!("\nGarbage Collector:");
@@ -204,3 +517,71 @@ impl Default for Cli {
         Self::new()
     }
 }
+
+/// Make `colored` output decide on colors the way the `tty` module does, so
+/// NO_COLOR, FORCE_COLOR and piped output treat every command alike
+pub fn configure_colors() {
+    colored::control::set_override(tty::colors_enabled(Stream::Stdout));
+}
+
+/// `--ic-stats`: hit rates and site counts of each kind of inline cache
+fn print_ic_stats(engine: &BebionEngine) {
+    let stats = engine.ic_stats();
+    eprintln!("Inline caches:");
+    for (kind, counters) in [("loads", stats.loads), ("stores", stats.stores), ("calls", stats.calls)] {
+        eprintln!(
+            "  {:<7} {:>5.1}% hits ({} of {}), sites: {} monomorphic, {} polymorphic, {} megamorphic",
+            kind,
+            counters.hit_rate() * 100.0,
+            counters.hits,
+            counters.lookups(),
+            counters.monomorphic,
+            counters.polymorphic,
+            counters.megamorphic,
+        );
+    }
+}
+
+/// `--frozen-time`: milliseconds since the epoch for an ISO 8601 timestamp
+fn parse_frozen_time(timestamp: &str) -> Result<f64, String> {
+    bebion_core::parse_iso_timestamp(timestamp)
+        .ok_or_else(|| format!("'{}' is not an ISO 8601 timestamp (e.g. 2024-01-31T12:00:00Z)", timestamp))
+}
+
+/// `--max-run-time` and `bench --time`: a number of seconds, which may be
+/// fractional
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("'{}' is not a number of seconds", seconds))
+}
+
+/// `--max-heap` and the GC sizes: a byte count with an optional K, M or G
+/// suffix, in powers of 1024
+fn parse_size(size: &str) -> Result<usize, String> {
+    let trimmed = size.trim().trim_end_matches(['b', 'B']);
+    let (digits, unit) = match trimmed.char_indices().last() {
+        Some((at, suffix)) if suffix.is_ascii_alphabetic() => (&trimmed[..at], suffix.to_ascii_uppercase()),
+        _ => (trimmed, ' '),
+    };
+    let multiplier: usize = match unit {
+        ' ' => 1,
+        'K' => 1024,
+        'M' => 1024 * 1024,
+        'G' => 1024 * 1024 * 1024,
+        _ => return Err(format!("'{}' is not a size (e.g. 512K, 256M or 2G)", size)),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|count| count.checked_mul(multiplier))
+        .ok_or_else(|| format!("'{}' is not a size (e.g. 512K, 256M or 2G)", size))
+}
+
+/// `--unhandled-rejections`
+fn parse_unhandled_rejections(mode: &str) -> Result<UnhandledRejections, String> {
+    UnhandledRejections::from_name(mode)
+        .ok_or_else(|| format!("'{}' is not one of warn, strict or none", mode))
+}
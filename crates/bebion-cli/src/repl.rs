@@ -1,17 +1,71 @@
 //! Interactive REPL (Read-Eval-Print Loop)
 
-use bebion_core::{BebionEngine, BebionError};
+use crate::debugger;
+use bebion_core::{BebionEngine, BebionError, PromiseInspection, Value};
+use bebion_std::glob::{self, WalkOptions};
+use bebion_std::tty;
 use colored::*;
 use rustyline::error::ReadlineError;
 use rustyline::{DefaultEditor, Result as RustylineResult};
-use std::io::{self, Write};
+use serde_json::json;
+use std::io;
+use std::path::{Path, PathBuf};
 use tracing::{debug, error};
 
+/// Collections `.stats` lists one by one, the latest
+const RECENT_COLLECTIONS: usize = 5;
+
+/// Name of the startup file the REPL runs from the home directory
+pub const RC_FILE_NAME: &str = ".bebionrc.js";
+
+/// How a REPL starts and prompts
+#[derive(Debug, Clone)]
+pub struct ReplOptions {
+    /// Prompt shown for each new input, instead of the numbered
+    /// `bebion:N> `. A startup file can change it with `repl.prompt`.
+    pub prompt: Option<String>,
+    /// Startup file run before the first prompt, if it exists
+    pub rc_file: Option<PathBuf>,
+    /// Print the name, version and help hint first
+    pub banner: bool,
+}
+
+impl Default for ReplOptions {
+    fn default() -> Self {
+        Self {
+            prompt: None,
+            rc_file: default_rc_file(),
+            banner: true,
+        }
+    }
+}
+
+/// `~/.bebionrc.js`, or `None` when there is no home directory
+pub fn default_rc_file() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(RC_FILE_NAME))
+}
+
 pub fn start_repl(engine: &mut BebionEngine) -> Result<(), Box<dyn std::error::Error>> {
-    println!("{}", "Bebion JavaScript Runtime".bright_blue().bold());
-    println!("Version: {}", env!("CARGO_PKG_VERSION"));
-    println!("Type {} for help, {} to exit", ".help".yellow(), ".exit".yellow());
-    println!();
+    start_repl_with(engine, &ReplOptions::default())
+}
+
+/// Start a REPL on `engine` as `options` say. The startup file runs as a
+/// module, so functions it defines and modules it imports stay available
+/// to the inputs that follow, and it sees a global `repl` whose `prompt`
+/// it can set.
+pub fn start_repl_with(engine: &mut BebionEngine, options: &ReplOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if options.banner {
+        println!("{}", "Bebion JavaScript Runtime".bright_blue().bold());
+        println!("Version: {}", env!("CARGO_PKG_VERSION"));
+        println!("Type {} for help, {} to exit", ".help".yellow(), ".exit".yellow());
+        println!();
+    }
+
+    engine.set_global_json("repl", &json!({ "prompt": options.prompt }));
+    if let Some(rc_file) = &options.rc_file {
+        run_rc_file(engine, rc_file);
+    }
 
     let mut rl = DefaultEditor::new()?;
     let mut line_number = 1;
@@ -21,6 +75,8 @@ pub fn start_repl(engine: &mut BebionEngine) -> Result<(), Box<dyn std::error::E
     loop {
         let prompt = if in_multiline {
             format!("{}> ", "...".bright_black())
+        } else if let Some(prompt) = custom_prompt(engine) {
+            prompt
         } else {
             format!("{}> ", format!("bebion:{}", line_number).bright_green())
         };
@@ -29,6 +85,14 @@ pub fn start_repl(engine: &mut BebionEngine) -> Result<(), Box<dyn std::error::E
             Ok(line) => {
                 let trimmed = line.trim();
                 
+                if !in_multiline {
+                    if let Some(source) = trimmed.strip_prefix(".debug ") {
+                        rl.add_history_entry(&line)?;
+                        debugger::debug_session(&mut rl, engine, source)?;
+                        continue;
+                    }
+                }
+
                 // Handle REPL commands
                 if !in_multiline && trimmed.starts_with('.') {
                     match handle_repl_command(trimmed, engine) {
@@ -51,20 +115,18 @@ pub fn start_repl(engine: &mut BebionEngine) -> Result<(), Box<dyn std::error::E
                     continue;
                 }
 
-                if in_multiline {
-                    multiline_buffer.push_str(&line);
-                    multiline_buffer.push('\n');
+                // Keep reading while the parser runs out of input partway
+                // through, and run the input once it is complete or has
+                // another error to report
+                multiline_buffer.push_str(&line);
+                multiline_buffer.push('\n');
+                if engine.is_incomplete(&multiline_buffer) {
+                    in_multiline = true;
                 } else {
-                    // Check if this line needs continuation
-                    if needs_continuation(&line) {
-                        multiline_buffer.push_str(&line);
-                        multiline_buffer.push('\n');
-                        in_multiline = true;
-                    } else {
-                        // Single line execution
-                        execute_code(engine, &line, line_number);
-                        line_number += 1;
-                    }
+                    execute_code(engine, &multiline_buffer, line_number);
+                    multiline_buffer.clear();
+                    in_multiline = false;
+                    line_number += 1;
                 }
 
                 rl.add_history_entry(&line)?;
@@ -93,6 +155,31 @@ pub fn start_repl(engine: &mut BebionEngine) -> Result<(), Box<dyn std::error::E
     Ok(())
 }
 
+/// Run the startup file `path`, reporting what goes wrong with it without
+/// keeping the REPL from starting. A missing file is not an error.
+fn run_rc_file(engine: &mut BebionEngine, path: &Path) {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+        Err(err) => {
+            println!("{}: Failed to read {}: {}", "Error".red().bold(), path.display(), err);
+            return;
+        }
+    };
+    let url = format!("file://{}", path.canonicalize().unwrap_or_else(|_| path.to_path_buf()).display());
+    if let Err(err) = engine.execute_as_module(&url, &content) {
+        println!("{} {}: {}", "Error in".red().bold(), path.display(), err);
+    }
+}
+
+/// The prompt the script has put in `repl.prompt`, if it is a string
+fn custom_prompt(engine: &BebionEngine) -> Option<String> {
+    match engine.get_global_json("repl")?.get("prompt")? {
+        serde_json::Value::String(prompt) => Some(prompt.clone()),
+        _ => None,
+    }
+}
+
 fn execute_code(engine: &mut BebionEngine, code: &str, line_number: usize) {
     if code.trim().is_empty() {
         return;
@@ -100,9 +187,29 @@ fn execute_code(engine: &mut BebionEngine, code: &str, line_number: usize) {
 
     debug!("Executing code at line {}: {}", line_number, code);
 
-    match engine.execute_script(code) {
+    run_as_module(engine, &repl_module_url(), code, line_number);
+}
+
+/// URL of the synthetic module REPL input runs as, so its imports resolve
+/// against the working directory
+fn repl_module_url() -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    format!("file://{}", cwd.join("[repl]").display())
+}
+
+fn run_as_module(engine: &mut BebionEngine, url: &str, code: &str, line_number: usize) {
+    match engine.execute_as_module(url, code) {
         Ok(result) => {
-            println!("{}", format!("=> [object]").bright_cyan());
+            // Promises show their state, as in other runtimes
+            let value = match engine.promise_state(result) {
+                Some(PromiseInspection::Pending) => "Promise { <pending> }".to_string(),
+                Some(PromiseInspection::Fulfilled(value)) => format!("Promise {{ {} }}", format_value(value)),
+                Some(PromiseInspection::Rejected(reason)) => {
+                    format!("Promise {{ <rejected> {} }}", format_value(reason))
+                }
+                None => format_value(engine.value_of(result)),
+            };
+            println!("{}", format!("=> {}", value).bright_cyan());
         }
         Err(err) => {
             print_error(&err, line_number);
@@ -110,6 +217,14 @@ fn execute_code(engine: &mut BebionEngine, code: &str, line_number: usize) {
     }
 }
 
+/// A result as the REPL echoes it, with strings quoted
+pub(crate) fn format_value(value: Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        other => other.to_string(),
+    }
+}
+
 fn print_error(error: &BebionError, line_number: usize) {
     match error {
         BebionError::ParseError(msg) => {
@@ -143,8 +258,7 @@ fn handle_repl_command(command: &str, engine: &mut BebionEngine) -> ReplCommand
         }
         
         ".clear" => {
-            print!("\x1B[2J\x1B[1;1H");
-            io::stdout().flush().unwrap_or(());
+            tty::control(tty::CLEAR_SCREEN);
             ReplCommand::Continue
         }
         
@@ -165,14 +279,22 @@ fn handle_repl_command(command: &str, engine: &mut BebionEngine) -> ReplCommand
         }
         
         cmd if cmd.starts_with(".load ") => {
-            let filename = &cmd[6..].trim();
-            match std::fs::read_to_string(filename) {
-                Ok(content) => {
-                    execute_code(engine, &content, 0);
-                    ReplCommand::Continue
+            let pattern = cmd[6..].trim();
+            let files = match load_paths(pattern) {
+                Ok(files) => files,
+                Err(err) => return ReplCommand::Error(err),
+            };
+            for file in files {
+                let loaded = std::fs::read_to_string(&file).and_then(|content| Ok((file.canonicalize()?, content)));
+                match loaded {
+                    Ok((path, content)) => {
+                        println!("{} {}", "Loading".bright_black(), file.display());
+                        run_as_module(engine, &format!("file://{}", path.display()), &content, 0);
+                    }
+                    Err(err) => return ReplCommand::Error(format!("Failed to load {}: {}", file.display(), err)),
                 }
-                Err(err) => ReplCommand::Error(format!("Failed to load {}: {}", filename, err)),
             }
+            ReplCommand::Continue
         }
         
         cmd if cmd.starts_with(".save ") => {
@@ -184,6 +306,35 @@ fn handle_repl_command(command: &str, engine: &mut BebionEngine) -> ReplCommand
     }
 }
 
+/// The files `.load` runs for `pattern`: a file, the `.js` files of a
+/// directory, or the files matching a glob as `fs.glob` matches them.
+/// Sorted so files load in a predictable order.
+fn load_paths(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(pattern);
+    let mut files = if path.is_dir() {
+        std::fs::read_dir(path)
+            .map_err(|err| format!("Failed to read {}: {}", pattern, err))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.is_file() && file.extension().is_some_and(|extension| extension == "js"))
+            .collect()
+    } else if glob::is_glob(pattern) {
+        glob::glob(pattern, Path::new("."), &WalkOptions::default())
+            .map_err(|err| format!("Failed to match {}: {}", pattern, err))?
+            .into_iter()
+            .map(PathBuf::from)
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    
+    if files.is_empty() {
+        return Err(format!("No files match {}", pattern));
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
 fn show_help() {
     println!("{}", "REPL Commands:".bright_blue().bold());
     println!("  {}  - Show this help", ".help".yellow());
@@ -192,7 +343,8 @@ fn show_help() {
     println!("  {}    - Force garbage collection", ".gc".yellow());
     println!("  {}  - Show runtime statistics", ".stats".yellow());
     println!("  {} - Show version information", ".version".yellow());
-    println!("  {} - Load and execute a file", ".load <file>".yellow());
+    println!("  {} - Load and execute a file, directory or glob", ".load <path>".yellow());
+    println!("  {} - Step through code's bytecode", ".debug <code>".yellow());
     println!("  {} - Save session to file", ".save <file>".yellow());
     println!();
     println!("{}", "JavaScript Features:".bright_blue().bold());
@@ -205,7 +357,7 @@ fn show_help() {
     println!();
 }
 
-fn show_stats(engine: &BebionEngine) {
+fn show_stats(engine: &mut BebionEngine) {
     println!("{}", "Runtime Statistics:".bright_blue().bold());
     
     let stats = engine.gc_stats();
@@ -225,71 +377,16 @@ fn show_stats(engine: &BebionEngine) {
         0.0
     };
     println!("  Collection efficiency: {:.1}%", efficiency);
-}
-
-fn needs_continuation(line: &str) -> bool {
-    let trimmed = line.trim();
-    
-    // Check for obvious continuation patterns
-    if trimmed.ends_with('{') || 
-       trimmed.ends_with('(') || 
-       trimmed.ends_with('[') ||
-       trimmed.ends_with(',') ||
-       trimmed.ends_with('\\') {
-        return true;
-    }
     
-    // Check for incomplete statements
-    if trimmed.starts_with("function ") ||
-       trimmed.starts_with("class ") ||
-       trimmed.starts_with("if ") ||
-       trimmed.starts_with("for ") ||
-       trimmed.starts_with("while ") ||
-       trimmed.starts_with("try ") ||
-       trimmed.starts_with("switch ") {
-        return true;
-    }
-    
-    // Check for unmatched brackets
-    let mut paren_count = 0;
-    let mut brace_count = 0;
-    let mut bracket_count = 0;
-    let mut in_string = false;
-    let mut string_char = '\0';
-    let mut escaped = false;
-    
-    for ch in line.chars() {
-        if escaped {
-            escaped = false;
-            continue;
-        }
-        
-        if ch == '\\' {
-            escaped = true;
-            continue;
-        }
-        
-        if in_string {
-            if ch == string_char {
-                in_string = false;
-            }
-            continue;
-        }
-        
-        match ch {
-            '"' | '\'' | '`' => {
-                in_string = true;
-                string_char = ch;
-            }
-            '(' => paren_count += 1,
-            ')' => paren_count -= 1,
-            '{' => brace_count += 1,
-            '}' => brace_count -= 1,
-            '[' => bracket_count += 1,
-            ']' => bracket_count -= 1,
-            _ => {}
+    let events = engine.take_gc_events();
+    if !events.is_empty() {
+        let longest = events.iter().map(|event| event.pause).max().unwrap_or_default();
+        let freed: usize = events.iter().map(|event| event.bytes_freed).sum();
+        println!("Collections since last .stats: {}", events.len());
+        println!("  Longest pause: {:.3}ms", longest.as_secs_f64() * 1000.0);
+        println!("  Memory freed: {} bytes", freed);
+        for event in events.iter().rev().take(RECENT_COLLECTIONS).rev() {
+            println!("  {}", event);
         }
     }
-    
-    paren_count > 0 || brace_count > 0 || bracket_count > 0 || in_string
 }
@@ -1,12 +1,14 @@
 //! File execution and compilation
 
-use bebion_core::{BebionEngine, BebionError};
-use bebion_compiler::bytecode::Bytecode;
+use bebion_core::{BebionEngine, BebionError, HotUpdate};
+use bebion_compiler::bytecode::{Bytecode, BytecodeModule};
+use bebion_compiler::SourceMap;
 use colored::*;
 use serde_json;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, error, info};
 
 pub fn run_file(
@@ -21,19 +23,17 @@ pub fn run_file(
         return Err(format!("File not found: {}", file_path.display()).into());
     }
 
-    // Read the file
-    let source = fs::read_to_string(file_path)
-        .map_err(|e| format!("Failed to read file {}: {}", file_path.display(), e))?;
-
-    debug!("Read {} bytes from {}", source.len(), file_path.display());
-
-    // Execute the script
+    // Load and execute the script through the engine's resolver hook, then
+    // run its timers and async work to completion
     let start_time = Instant::now();
     
-    match engine.execute_script(&source) {
-        Ok(_result) => {
+    match engine.set_argv(&script_argv(file_path, args))
+        .and_then(|()| engine.load_module(&file_path.to_string_lossy()))
+        .and_then(|module| engine.run_until_idle().map(|()| module))
+    {
+        Ok(module) => {
             let duration = start_time.elapsed();
-            debug!("Script executed successfully in {:?}", duration);
+            debug!("Script {} executed successfully in {:?}", module.id, duration);
             Ok(())
         }
         Err(err) => {
@@ -43,11 +43,122 @@ pub fn run_file(
     }
 }
 
+/// Run a file and apply changes to it and the modules it imports as they
+/// are saved. A changed module that accepts updates through
+/// `import.meta.hot.accept` runs again in place, and the modules that
+/// imported it see its new exports. Any other change restarts the script:
+/// the running engine is shut down and the file loaded again in a fresh
+/// one from `new_engine`, so nothing the old run scheduled or registered
+/// fires twice. Errors are reported and the watch goes on.
+pub fn watch_file<F>(
+    engine: &mut BebionEngine,
+    file_path: &Path,
+    args: &[String],
+    new_engine: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Fn() -> Result<BebionEngine, Box<dyn std::error::Error>>,
+{
+    info!("Watching file: {:?}", file_path);
+
+    start_watched(engine, file_path, args);
+    println!("{} Watching {} for changes", "↻".cyan().bold(), file_path.display());
+    let mut modified = module_times(engine);
+    loop {
+        // Keep the script's timers, promises and servers going between
+        // checks for changes
+        let poll_at = Instant::now() + WATCH_INTERVAL;
+        if let Err(err) = engine.run_for(WATCH_INTERVAL) {
+            print_execution_error(&err, file_path);
+        }
+        std::thread::sleep(poll_at.saturating_duration_since(Instant::now()));
+
+        let mut restart = false;
+        for (url, time) in module_times(engine) {
+            // Modules imported since the last check start out unchanged
+            let Some(previous) = modified.insert(url.clone(), time) else {
+                continue;
+            };
+            if previous == time {
+                continue;
+            }
+            let path = module_path(&url).unwrap_or_else(|| file_path.to_path_buf());
+            match engine.reload_module(&url) {
+                Ok(HotUpdate::Unchanged) => {}
+                Ok(HotUpdate::Applied) => {
+                    println!("{} Updated {}", "↻".cyan().bold(), path.display());
+                }
+                Ok(HotUpdate::Declined) => {
+                    restart = true;
+                    break;
+                }
+                Err(err) => print_execution_error(&err, &path),
+            }
+        }
+        if !restart {
+            continue;
+        }
+
+        engine.shutdown();
+        *engine = new_engine()?;
+        println!("{} Restarting {}", "↻".cyan().bold(), file_path.display());
+        start_watched(engine, file_path, args);
+        modified = module_times(engine);
+    }
+}
+
+/// Load the watched file, reporting rather than failing on errors
+fn start_watched(engine: &mut BebionEngine, file_path: &Path, args: &[String]) {
+    let loaded = engine.set_argv(&script_argv(file_path, args))
+        .and_then(|()| engine.load_module(&file_path.to_string_lossy()));
+    if let Err(err) = loaded {
+        print_execution_error(&err, file_path);
+    }
+}
+
+/// When each loaded module's file was last modified, by module URL
+fn module_times(engine: &BebionEngine) -> HashMap<String, Option<SystemTime>> {
+    engine
+        .modules()
+        .filter_map(|module| Some((module.id.clone(), modified_time(&module_path(&module.id)?))))
+        .collect()
+}
+
+/// The file behind a module URL, `None` if it is not a `file://` URL
+fn module_path(url: &str) -> Option<PathBuf> {
+    url.strip_prefix("file://").map(PathBuf::from)
+}
+
+/// `process.argv` for `file_path` run with `args`: the executable, the
+/// script, then its arguments, as in Node
+fn script_argv(file_path: &Path, args: &[String]) -> Vec<String> {
+    let executable = std::env::current_exe()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "bebion".to_string());
+    [executable, file_path.to_string_lossy().into_owned()]
+        .into_iter()
+        .chain(args.iter().cloned())
+        .collect()
+}
+
+/// How often `watch_file` polls the loaded modules' files for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The last component of `path`, as source maps name files
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
 pub fn compile_file(
     engine: &mut BebionEngine,
     input_path: &Path,
     output_path: Option<&PathBuf>,
     pretty: bool,
+    source_map: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     info!("Compiling file: {:?}", input_path);
     
@@ -67,6 +178,8 @@ pub fn compile_file(
 
     // Compile to bytecode
     let mut compiler = bebion_compiler::Compiler::new();
+    compiler.set_opt_level(engine.opt_level());
+    compiler.set_debug_info(engine.debug_info());
     let bytecode = compiler.compile(&ast)
         .map_err(|e| format!("Compile error: {}", e))?;
 
@@ -79,15 +192,16 @@ pub fn compile_file(
         path
     };
 
-    // Serialize bytecode
+    // Serialize bytecode, with one constant pool for all functions
+    let module = bytecode.to_module();
     let serialized = if pretty {
-        serde_json::to_string_pretty(&bytecode)?
+        serde_json::to_string_pretty(&module)?
     } else {
-        serde_json::to_string(&bytecode)?
+        serde_json::to_string(&module)?
     };
 
     // Write to output file
-    fs::write(&output_file, serialized)
+    fs::write(&output_file, &serialized)
         .map_err(|e| format!("Failed to write output file {}: {}", output_file.display(), e))?;
 
     println!(
@@ -97,11 +211,28 @@ pub fn compile_file(
         output_file.display()
     );
 
+    // Write the source map next to the bytecode, as `<output>.map`
+    if source_map {
+        let map = SourceMap::new(&bytecode, &file_name(&output_file), &file_name(input_path), Some(&source));
+        let mut map_file = output_file.clone().into_os_string();
+        map_file.push(".map");
+        let map_file = PathBuf::from(map_file);
+        fs::write(&map_file, serde_json::to_string(&map)?)
+            .map_err(|e| format!("Failed to write source map {}: {}", map_file.display(), e))?;
+        println!("  Source map: {}", map_file.display());
+    }
+
     // Show compilation stats
     println!("  Instructions: {}", bytecode.instructions.len());
-    println!("  Constants: {}", bytecode.constants.len());
+    println!("  Constants: {} ({} shared)", bytecode.constants.len(), module.constants.len());
     println!("  Names: {}", bytecode.names.len());
     println!("  Size: {} bytes", serialized.len());
+    if let Some(stats) = compiler.inline_stats() {
+        println!(
+            "  Inlined: {} call sites of {} functions ({} -> {} instructions)",
+            stats.call_sites, stats.candidates, stats.instructions_before, stats.instructions_after
+        );
+    }
 
     Ok(())
 }
@@ -121,17 +252,22 @@ pub fn run_bytecode_file(
     let bytecode_json = fs::read_to_string(file_path)
         .map_err(|e| format!("Failed to read file {}: {}", file_path.display(), e))?;
 
-    // Deserialize bytecode
-    let bytecode: Bytecode = serde_json::from_str(&bytecode_json)
-        .map_err(|e| format!("Failed to parse bytecode: {}", e))?;
+    // Deserialize bytecode. Files written before modules had a shared
+    // constant pool hold a plain `Bytecode`.
+    let bytecode = match serde_json::from_str::<BytecodeModule>(&bytecode_json) {
+        Ok(module) => module.into_bytecode()
+            .map_err(|e| format!("Failed to load bytecode: {}", e))?,
+        Err(_) => serde_json::from_str::<Bytecode>(&bytecode_json)
+            .map_err(|e| format!("Failed to parse bytecode: {}", e))?,
+    };
 
     debug!("Loaded bytecode with {} instructions", bytecode.instructions.len());
 
     // Execute the bytecode
     let start_time = Instant::now();
     
-    match engine.execute_bytecode(&bytecode) {
-        Ok(_result) => {
+    match engine.execute_bytecode(&bytecode).and_then(|_| engine.run_until_idle()) {
+        Ok(()) => {
             let duration = start_time.elapsed();
             debug!("Bytecode executed successfully in {:?}", duration);
             Ok(())
@@ -266,6 +402,8 @@ pub fn analyze_file(
 
     // Compile to bytecode
     let mut compiler = bebion_compiler::Compiler::new();
+    compiler.set_opt_level(engine.opt_level());
+    compiler.set_debug_info(engine.debug_info());
     let bytecode = compiler.compile(&ast)
         .map_err(|e| format!("Compile error: {}", e))?;
 
@@ -282,6 +420,19 @@ pub fn analyze_file(
     println!("  Constants: {}", bytecode.constants.len());
     println!("  Names: {}", bytecode.names.len());
     
+    let pool = bytecode.constant_pool_stats();
+    let without_dedup = pool.constants + pool.deduplicated;
+    println!("\n{}", "Constant Pool:".bright_blue().bold());
+    println!("  Entries (including functions): {}", pool.constants);
+    println!(
+        "  Deduplicated: {} ({:.1}% smaller than {} entries)",
+        pool.deduplicated,
+        if without_dedup > 0 { pool.deduplicated as f64 / without_dedup as f64 * 100.0 } else { 0.0 },
+        without_dedup
+    );
+    println!("  Shared across functions: {}", pool.shared);
+    println!("  Folded string expressions: {}", compiler.folded_constants());
+    
     // Analyze instruction distribution
     let mut instruction_counts = std::collections::HashMap::new();
     for instruction in &bytecode.instructions {
@@ -294,9 +445,38 @@ pub fn analyze_file(
     sorted_instructions.sort_by(|a, b| b.1.cmp(a.1));
     
     for (instruction, count) in sorted_instructions.iter().take(10) {
-        let percentage = (*count as f64 / bytecode.instructions.len() as f64) * 100.0;
+        let percentage = (**count as f64 / bytecode.instructions.len() as f64) * 100.0;
         println!("  {}: {} ({:.1}%)", instruction, count, percentage);
     }
 
     Ok(())
 }
+
+pub fn check_file(file_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    info!("Checking file: {:?}", file_path);
+    
+    if !file_path.exists() {
+        return Err(format!("File not found: {}", file_path.display()).into());
+    }
+
+    let source = fs::read_to_string(file_path)
+        .map_err(|e| format!("Failed to read file {}: {}", file_path.display(), e))?;
+
+    let mut parser = bebion_parser::Parser::new();
+    let (_program, errors) = parser.parse_with_recovery(&source);
+
+    if errors.is_empty() {
+        println!("{} No syntax errors in {}", "✓".green().bold(), file_path.display());
+        return Ok(());
+    }
+
+    for error in &errors {
+        eprintln!("{}: {} in {}",
+            "SyntaxError".red().bold(),
+            error,
+            file_path.display().to_string().yellow()
+        );
+    }
+    eprintln!("\n{} syntax error(s) found", errors.len());
+    std::process::exit(1);
+}
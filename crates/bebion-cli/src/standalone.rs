@@ -0,0 +1,319 @@
+//! Single-file executables (`bebion compile --standalone`)
+//!
+//! A standalone executable is a copy of the bebion binary with a package
+//! appended: the asset bytes, then a JSON manifest holding the compiled
+//! modules and where each asset lies, then a fixed-size trailer. On start
+//! the binary checks its own tail for the trailer and, when it is there,
+//! runs the package instead of reading its command line.
+//!
+//! ```text
+//! [bebion binary][assets][manifest][manifest length][package length][MAGIC]
+//! ```
+//!
+//! Both lengths are little-endian `u64`s; the package length covers the
+//! assets and the manifest.
+
+use bebion_compiler::bytecode::Bytecode;
+use bebion_core::{BebionEngine, BebionError, Resolution, ResolverHook, Source};
+use bebion_parser::ast::{AstNode, LiteralValue};
+use bebion_parser::Program;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Marks the end of a standalone executable
+const MAGIC: &[u8; 8] = b"BEBIONPK";
+/// Manifest length, package length and `MAGIC`
+const TRAILER_LEN: u64 = 24;
+/// Bumped when the manifest changes incompatibly
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// URL of the module to run
+    pub entry: String,
+    pub modules: Vec<PackagedModule>,
+    pub assets: Vec<PackagedAsset>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackagedModule {
+    pub url: String,
+    pub bytecode: Bytecode,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackagedAsset {
+    /// The path the asset was packaged from, or the specifier the entry
+    /// module imports it by
+    pub path: String,
+    /// Where the asset's bytes start in the package
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// A package read back from a standalone executable
+pub struct Package {
+    pub manifest: Manifest,
+    /// The asset bytes, addressed by `PackagedAsset::offset`
+    data: Vec<u8>,
+}
+
+impl Package {
+    /// The bytes of the asset packaged from `path`
+    pub fn asset(&self, path: &str) -> Option<&[u8]> {
+        let path = normalize(path);
+        let asset = self.manifest.assets.iter().find(|asset| asset.path == path)?;
+        self.data.get(asset.offset as usize..(asset.offset + asset.len) as usize)
+    }
+}
+
+/// `path` without a leading `./`, so either spelling finds an asset
+fn normalize(path: &str) -> &str {
+    path.strip_prefix("./").unwrap_or(path)
+}
+
+/// Serves a package's assets to the module loader, so a standalone
+/// executable reads them from itself instead of the filesystem
+struct PackageResolver {
+    assets: HashMap<String, Vec<u8>>,
+}
+
+impl ResolverHook for PackageResolver {
+    fn resolve(&self, specifier: &str, _referrer: Option<&str>) -> Result<Resolution, BebionError> {
+        let path = normalize(specifier);
+        if !self.assets.contains_key(path) {
+            return Err(BebionError::ModuleError(format!(
+                "Cannot find module '{}' in this executable",
+                specifier
+            )));
+        }
+        Ok(Resolution { url: path.to_string() })
+    }
+
+    fn load(&self, url: &str) -> Result<Source, BebionError> {
+        Ok(Source {
+            url: url.to_string(),
+            code: String::from_utf8_lossy(&self.load_bytes(url)?).into_owned(),
+        })
+    }
+
+    fn load_bytes(&self, url: &str) -> Result<Vec<u8>, BebionError> {
+        self.assets
+            .get(url)
+            .cloned()
+            .ok_or_else(|| BebionError::ModuleError(format!("Cannot find module '{}' in this executable", url)))
+    }
+}
+
+/// Build a standalone executable running `input`, with `assets` embedded
+pub fn compile_standalone(
+    engine: &mut BebionEngine,
+    input: &Path,
+    output: Option<&PathBuf>,
+    assets: &[PathBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    info!("Packaging {:?} as a standalone executable", input);
+
+    // Scripts can't import other modules yet, so the module graph is the
+    // entry module alone
+    let entry = engine.fetch_module(&input.to_string_lossy(), None)?;
+    let program = engine.parse_script(&entry.code)?;
+    let bytecode = engine.compile_program(&program)?;
+    let modules = vec![PackagedModule { url: entry.url.clone(), bytecode }];
+
+    let mut files = Vec::with_capacity(assets.len());
+    for path in assets {
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read asset {}: {}", path.display(), e))?;
+        files.push((normalize(&path.to_string_lossy()).to_string(), bytes));
+    }
+    // Text and byte imports are served from the package too, under the
+    // specifier the entry module imports them by
+    for specifier in asset_imports(&program) {
+        let path = normalize(specifier);
+        if files.iter().any(|(packaged, _)| packaged == path) {
+            continue;
+        }
+        let bytes = engine.fetch_asset(specifier, Some(&entry.url))?;
+        files.push((path.to_string(), bytes));
+    }
+
+    let mut data = Vec::new();
+    let mut packaged_assets = Vec::with_capacity(files.len());
+    for (path, bytes) in files {
+        packaged_assets.push(PackagedAsset {
+            path,
+            offset: data.len() as u64,
+            len: bytes.len() as u64,
+        });
+        data.extend_from_slice(&bytes);
+    }
+
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        entry: entry.url,
+        modules,
+        assets: packaged_assets,
+    };
+    let module_count = manifest.modules.len();
+    let asset_count = manifest.assets.len();
+    let manifest = serde_json::to_vec(&manifest)?;
+
+    let output = match output {
+        Some(path) => path.clone(),
+        None => {
+            let stem = input.file_stem().ok_or_else(|| format!("{} has no file name", input.display()))?;
+            let mut name = stem.to_os_string();
+            name.push(std::env::consts::EXE_SUFFIX);
+            input.with_file_name(name)
+        }
+    };
+
+    let runtime = std::env::current_exe()?;
+    let mut executable = runtime_image(&runtime)?;
+    let package_len = data.len() + manifest.len();
+    executable.extend_from_slice(&data);
+    executable.extend_from_slice(&manifest);
+    executable.extend_from_slice(&(manifest.len() as u64).to_le_bytes());
+    executable.extend_from_slice(&(package_len as u64).to_le_bytes());
+    executable.extend_from_slice(MAGIC);
+
+    let mut file = File::create(&output).map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+    file.write_all(&executable)?;
+    make_executable(&file)?;
+
+    println!(
+        "{} Packaged {} as {}",
+        "✓".green().bold(),
+        input.display(),
+        output.display()
+    );
+    println!("  Modules: {}", module_count);
+    println!("  Assets: {}", asset_count);
+    println!("  Size: {} bytes", executable.len());
+    Ok(())
+}
+
+/// Specifiers of the text and byte assets `program` imports
+fn asset_imports(program: &Program) -> Vec<&str> {
+    program
+        .body
+        .iter()
+        .filter(|node| matches!(node.import_attribute("type"), Some("text" | "bytes")))
+        .filter_map(|node| match node {
+            AstNode::ImportDeclaration { source, .. } => match source.as_ref() {
+                AstNode::Literal { value: LiteralValue::String(specifier), .. } => Some(specifier.as_str()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// The bebion binary at `path` without any package already appended, so
+/// packaging from a standalone executable doesn't nest packages
+fn runtime_image(path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut image = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if let Some(package_start) = package_start(&image) {
+        image.truncate(package_start);
+    }
+    Ok(image)
+}
+
+/// Where the package starts, if `image` ends with a trailer
+fn package_start(image: &[u8]) -> Option<usize> {
+    let trailer = image.get(image.len().checked_sub(TRAILER_LEN as usize)?..)?;
+    if &trailer[16..] != MAGIC {
+        return None;
+    }
+    let package_len = u64::from_le_bytes(trailer[8..16].try_into().ok()?) as usize;
+    image.len().checked_sub(TRAILER_LEN as usize + package_len)
+}
+
+#[cfg(unix)]
+fn make_executable(file: &File) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = file.metadata()?.permissions();
+    permissions.set_mode(permissions.mode() | 0o111);
+    file.set_permissions(permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_file: &File) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// The package appended to the running executable, if it is a standalone
+/// build. Only the tail of the file is read when it is not.
+pub fn embedded_package() -> Result<Option<Package>, Box<dyn std::error::Error>> {
+    let mut file = File::open(std::env::current_exe()?)?;
+    let len = file.metadata()?.len();
+    if len < TRAILER_LEN {
+        return Ok(None);
+    }
+
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    file.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+    file.read_exact(&mut trailer)?;
+    if &trailer[16..] != MAGIC {
+        return Ok(None);
+    }
+
+    let manifest_len = u64::from_le_bytes(trailer[..8].try_into()?);
+    let package_len = u64::from_le_bytes(trailer[8..16].try_into()?);
+    if manifest_len > package_len || package_len > len - TRAILER_LEN {
+        return Err("Corrupt standalone package: lengths exceed the executable".into());
+    }
+
+    let mut package = vec![0u8; package_len as usize];
+    file.seek(SeekFrom::Start(len - TRAILER_LEN - package_len))?;
+    file.read_exact(&mut package)?;
+
+    let data_len = (package_len - manifest_len) as usize;
+    let manifest: Manifest = serde_json::from_slice(&package[data_len..])
+        .map_err(|e| format!("Corrupt standalone package: {}", e))?;
+    if manifest.version != FORMAT_VERSION {
+        return Err(format!(
+            "Standalone package format {} is not supported by this runtime (expected {})",
+            manifest.version, FORMAT_VERSION
+        )
+        .into());
+    }
+    package.truncate(data_len);
+    debug!(
+        "Found standalone package: {} modules, {} assets",
+        manifest.modules.len(),
+        manifest.assets.len()
+    );
+    Ok(Some(Package { manifest, data: package }))
+}
+
+/// Run a standalone executable's package
+pub fn run_package(engine: &mut BebionEngine, package: Package) -> Result<(), Box<dyn std::error::Error>> {
+    crate::configure_colors();
+
+    let assets = package
+        .manifest
+        .assets
+        .iter()
+        .filter_map(|asset| Some((asset.path.clone(), package.asset(&asset.path)?.to_vec())))
+        .collect();
+    engine.set_resolver_hook(PackageResolver { assets });
+
+    let entry = package
+        .manifest
+        .modules
+        .iter()
+        .find(|module| module.url == package.manifest.entry)
+        .ok_or_else(|| format!("Corrupt standalone package: entry {} is missing", package.manifest.entry))?;
+    if let Err(err) = engine.execute_bytecode(&entry.bytecode).and_then(|_| engine.run_until_idle()) {
+        eprintln!("{}: {}", "Error".red().bold(), err);
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! `bebion test262`: conformance suite runs for engine development
+
+use bebion_test262::{Baseline, Harness, Outcome};
+use colored::*;
+use std::path::Path;
+use tracing::info;
+
+/// Run the tests under `path`, printing failures as they happen and a
+/// summary at the end. With a baseline, exits with status 1 if any test
+/// listed as passing now fails.
+pub fn run_test262(
+    path: &Path,
+    filter: Option<&str>,
+    baseline_path: Option<&Path>,
+    update_baseline: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let harness = Harness::locate(path)?;
+    let tests = harness.collect_tests(path, filter)?;
+    info!("Running {} test262 tests from {}", tests.len(), harness.root().display());
+
+    let report = harness.run(&tests, |result| match &result.outcome {
+        Outcome::Pass | Outcome::Skip(_) => {}
+        Outcome::Fail(reason) => println!("{} {}: {}", "✗".red().bold(), result.path, reason),
+    });
+
+    println!(
+        "\n{} passed, {} failed, {} skipped ({} total)",
+        report.passed().to_string().green(),
+        report.failed().to_string().red(),
+        report.skipped().to_string().yellow(),
+        report.results.len()
+    );
+    if !report.results.is_empty() {
+        let ran = report.passed() + report.failed();
+        println!("Pass rate: {:.1}%", report.passed() as f64 * 100.0 / ran.max(1) as f64);
+    }
+
+    let Some(baseline_path) = baseline_path else {
+        return Ok(());
+    };
+    let mut baseline = Baseline::load(baseline_path)?;
+
+    let regressions: Vec<_> = report.regressions(&baseline).map(|result| result.path.clone()).collect();
+    let newly_passing = report.newly_passing(&baseline).count();
+    if newly_passing > 0 {
+        println!("{} {} tests newly passing", "✓".green().bold(), newly_passing);
+    }
+    if !regressions.is_empty() {
+        println!("{} {} regressions:", "✗".red().bold(), regressions.len());
+        for path in &regressions {
+            println!("  {}", path);
+        }
+    }
+
+    if update_baseline {
+        report.update_baseline(&mut baseline);
+        baseline.save(baseline_path)?;
+        println!("Updated baseline {}", baseline_path.display());
+    } else if !regressions.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -1,7 +1,9 @@
 //! Bytecode definitions and operations
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use crate::{inline, CompileError, CompileResult};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Instruction {
@@ -53,30 +55,53 @@ pub enum Instruction {
     Jump(isize),            // Unconditional jump
     JumpIfFalse(isize),     // Jump if top of stack is falsy
     JumpIfTrue(isize),      // Jump if top of stack is truthy
+    JumpIfNullish(isize),   // Jump if top of stack is null or undefined
     
     // Function operations
     Call(usize),            // Call function with n arguments
+    CallMethod(usize, usize), // Call the named method of the receiver below n arguments, with it as `this`
+    CallWithReceiver(usize), // Call the function below n arguments, with the value below it as `this`
+    LoadThis,               // Push the receiver of the current call
     Return,                 // Return from function
     
     // Object operations
     NewObject,              // Create new object
     GetProperty,            // Get property from object
-    SetProperty,            // Set property on object
+    SetProperty,            // Set property on object, leaving the value (object, key, value)
     GetElement,             // Get array element
-    SetElement,             // Set array element
+    SetElement,             // Set array element, leaving the value (object, key, value)
+    DeleteProperty,         // Delete property, pushing whether it succeeded
+    HasProperty,            // Push whether the key is in the object or its prototype chain (`key in object`)
+    InstanceOf,             // Push whether the constructor's `prototype` is in the value's prototype chain
+    CopyDataProperties,     // Copy own enumerable properties of source onto target (`{...source}`)
+    DefineGetter,           // Define getter function on object (`{get key() {}}`)
+    DefineSetter,           // Define setter function on object (`{set key(v) {}}`)
     
     // Array operations
     NewArray(usize),        // Create new array with n elements
+    ArrayAppend,            // Append value to the array below it
+    ArraySpread,            // Append each value of an iterable to the array below it
     
     // Variable operations
     DeclareVar(usize),      // Declare variable
     DeclareLet(usize),      // Declare let variable
     DeclareConst(usize),    // Declare const variable
+    MarkUninitialized(usize), // Put a let/const slot in its temporal dead zone until it is declared
+    CheckInitialized(usize, usize), // Throw a ReferenceError naming the name if the slot is in its dead zone
+    DeclareGlobal(usize),   // Initialize a script-level let/const global, ending its dead zone
+    MarkGlobalUninitialized(usize), // Put a script-level let/const global in its dead zone until it is declared
     
     // Stack manipulation
     Pop,                    // Remove top of stack
     Duplicate,              // Duplicate top of stack
     Swap,                   // Swap top two stack items
+    Rot(usize),             // Move top of stack below the n - 1 items under it
+    Pick(usize),            // Push a copy of the item n below the top (Pick(0) duplicates)
+    
+    // Completion values: a script evaluates to the value of the last
+    // expression statement executed outside any function
+    StoreCompletion,        // Pop top of stack into the completion value
+    LoadCompletion,         // Push the completion value (initially undefined)
     
     // Special operations
     Nop,                    // No operation
@@ -85,18 +110,13 @@ pub enum Instruction {
     // Async operations
     Await,                  // Await async operation
     
-    // Exception handling
+    // Exception handling; the code's handler table says where exceptions go
     Throw,                  // Throw exception
-    TryBegin(usize),        // Begin try block
-    TryEnd,                 // End try block
-    CatchBegin,             // Begin catch block
-    CatchEnd,               // End catch block
-    FinallyBegin,           // Begin finally block
-    FinallyEnd,             // End finally block
     
     // Module operations
-    Import(usize),          // Import module
+    Import(usize),          // Pop the import type, push the module the constant specifier names
     Export(usize),          // Export value
+    ImportMeta(usize),      // Push `import.meta` of the module the constant URL names
     
     // Debug operations
     DebugInfo(usize, usize), // Line and column info
@@ -105,10 +125,15 @@ pub enum Instruction {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Constant {
     Number(f64),
-    String(String),
+    /// Shared by every use of the string in a script
+    String(Arc<str>),
     Boolean(bool),
     Null,
     Undefined,
+    RegExp {
+        pattern: String,
+        flags: String,
+    },
     Function {
         name: Option<String>,
         param_count: usize,
@@ -118,12 +143,159 @@ pub enum Constant {
     },
 }
 
+/// What the code at an exception handler does with the exception
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HandlerKind {
+    /// Binds or discards it and runs a catch body
+    Catch,
+    /// Runs a finalizer, then throws it again
+    Finally,
+}
+
+/// An entry of a code object's exception table. An exception thrown by an
+/// instruction in `start..end` continues at `handler`, with the frame's
+/// operand stack cut back to `stack_depth` values and the exception pushed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExceptionHandler {
+    pub start: usize,
+    pub end: usize,
+    pub handler: usize,
+    pub kind: HandlerKind,
+    /// Height of the frame's operand stack at `start`, filled in by frame
+    /// size analysis
+    #[serde(default)]
+    pub stack_depth: usize,
+}
+
+impl ExceptionHandler {
+    pub fn covers(&self, pc: usize) -> bool {
+        (self.start..self.end).contains(&pc)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Bytecode {
     pub instructions: Vec<Instruction>,
     pub constants: Vec<Constant>,
     pub names: Vec<String>,        // Variable/property names
-    pub source_map: HashMap<usize, (usize, usize)>, // instruction index -> (line, column)
+    /// Where statements start: instruction index -> (line, column)
+    pub source_map: BTreeMap<usize, (usize, usize)>,
+    /// Exception handlers, innermost first, so the first one covering an
+    /// instruction is the one that catches its exceptions
+    #[serde(default)]
+    pub handlers: Vec<ExceptionHandler>,
+    /// Constants that `add_constant` found already in the pool
+    #[serde(skip)]
+    pub deduplicated_constants: usize,
+    /// Where each primitive in the pool is, for `add_constant`
+    #[serde(skip)]
+    constant_indices: HashMap<PrimitiveKey, usize>,
+    /// How much of the pool `constant_indices` covers; constants pushed
+    /// directly are indexed on the next `add_constant`
+    #[serde(skip)]
+    indexed_constants: usize,
+    /// Local slots the code uses, which the VM allocates on frame entry
+    #[serde(default)]
+    pub local_count: usize,
+    /// Deepest the code's operand stack gets, reserved on frame entry
+    #[serde(default)]
+    pub max_stack_depth: usize,
+}
+
+/// Constant pool sizes across a script and its nested functions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConstantPoolStats {
+    /// Entries stored in the pools
+    pub constants: usize,
+    /// Entries a pool without deduplication would also have stored
+    pub deduplicated: usize,
+    /// Entries that repeat a primitive another function's pool already
+    /// holds, which a module's shared pool stores once
+    pub shared: usize,
+}
+
+/// A script as `.bbc` files store it. The primitive constants of the
+/// script and every nested function live in one pool, which each code
+/// object refers to by index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BytecodeModule {
+    pub constants: Vec<Constant>,
+    pub code: ModuleCode,
+}
+
+/// A code object of a `BytecodeModule`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleCode {
+    pub instructions: Vec<Instruction>,
+    pub constants: Vec<ModuleConstant>,
+    pub names: Vec<String>,
+    pub source_map: BTreeMap<usize, (usize, usize)>,
+    #[serde(default)]
+    pub handlers: Vec<ExceptionHandler>,
+    #[serde(default)]
+    pub local_count: usize,
+    #[serde(default)]
+    pub max_stack_depth: usize,
+}
+
+/// Shared entries, the bulk of most pools, are written as bare indices
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ModuleConstant {
+    /// An entry of the module's pool
+    Shared(usize),
+    RegExp {
+        pattern: String,
+        flags: String,
+    },
+    Function {
+        name: Option<String>,
+        param_count: usize,
+        code: ModuleCode,
+        is_async: bool,
+        is_generator: bool,
+    },
+}
+
+/// Hashable identity of a primitive constant. Numbers compare by bit
+/// pattern so `0` and `-0` stay distinct and `NaN` matches itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PrimitiveKey {
+    Number(u64),
+    String(Arc<str>),
+    Boolean(bool),
+    Null,
+    Undefined,
+}
+
+impl PrimitiveKey {
+    fn of(constant: &Constant) -> Option<Self> {
+        match constant {
+            Constant::Number(n) => Some(PrimitiveKey::Number(n.to_bits())),
+            Constant::String(s) => Some(PrimitiveKey::String(Arc::clone(s))),
+            Constant::Boolean(b) => Some(PrimitiveKey::Boolean(*b)),
+            Constant::Null => Some(PrimitiveKey::Null),
+            Constant::Undefined => Some(PrimitiveKey::Undefined),
+            Constant::RegExp { .. } | Constant::Function { .. } => None,
+        }
+    }
+}
+
+/// Builds a module's shared pool while its code objects are converted
+#[derive(Default)]
+struct SharedPool {
+    constants: Vec<Constant>,
+    indices: HashMap<PrimitiveKey, usize>,
+}
+
+impl SharedPool {
+    fn index_of(&mut self, constant: &Constant, key: PrimitiveKey) -> usize {
+        let constants = &mut self.constants;
+        *self.indices.entry(key).or_insert_with(|| {
+            constants.push(constant.clone());
+            constants.len() - 1
+        })
+    }
 }
 
 impl Bytecode {
@@ -132,7 +304,13 @@ impl Bytecode {
             instructions: Vec::new(),
             constants: Vec::new(),
             names: Vec::new(),
-            source_map: HashMap::new(),
+            source_map: BTreeMap::new(),
+            handlers: Vec::new(),
+            deduplicated_constants: 0,
+            constant_indices: HashMap::new(),
+            indexed_constants: 0,
+            local_count: 0,
+            max_stack_depth: 0,
         }
     }
 
@@ -148,12 +326,92 @@ impl Bytecode {
         }
     }
 
+    /// Add a constant to the pool, reusing an identical primitive already
+    /// there. Functions and regular expressions always get their own entry.
     pub fn add_constant(&mut self, constant: Constant) -> usize {
+        for (index, existing) in self.constants.iter().enumerate().skip(self.indexed_constants) {
+            if let Some(key) = PrimitiveKey::of(existing) {
+                self.constant_indices.entry(key).or_insert(index);
+            }
+        }
+        self.indexed_constants = self.constants.len();
+        
         let index = self.constants.len();
+        if let Some(key) = PrimitiveKey::of(&constant) {
+            if let Some(&existing) = self.constant_indices.get(&key) {
+                self.deduplicated_constants += 1;
+                return existing;
+            }
+            self.constant_indices.insert(key, index);
+        }
         self.constants.push(constant);
+        self.indexed_constants += 1;
         index
     }
 
+    pub fn constant_pool_stats(&self) -> ConstantPoolStats {
+        let mut stats = ConstantPoolStats::default();
+        let mut primitives = 0;
+        self.add_pool_stats(&mut stats, &mut primitives);
+        stats.shared = primitives - self.to_module().constants.len();
+        stats
+    }
+
+    fn add_pool_stats(&self, stats: &mut ConstantPoolStats, primitives: &mut usize) {
+        stats.constants += self.constants.len();
+        stats.deduplicated += self.deduplicated_constants;
+        for constant in &self.constants {
+            match constant {
+                Constant::Function { bytecode, .. } => bytecode.add_pool_stats(stats, primitives),
+                Constant::RegExp { .. } => {}
+                _ => *primitives += 1,
+            }
+        }
+    }
+
+    /// This script with its functions' primitive constants moved into one
+    /// shared pool
+    pub fn to_module(&self) -> BytecodeModule {
+        let mut pool = SharedPool::default();
+        let code = self.to_module_code(&mut pool);
+        BytecodeModule { constants: pool.constants, code }
+    }
+
+    fn to_module_code(&self, pool: &mut SharedPool) -> ModuleCode {
+        let constants = self
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                Constant::RegExp { pattern, flags } => ModuleConstant::RegExp {
+                    pattern: pattern.clone(),
+                    flags: flags.clone(),
+                },
+                Constant::Function { name, param_count, bytecode, is_async, is_generator } => {
+                    ModuleConstant::Function {
+                        name: name.clone(),
+                        param_count: *param_count,
+                        code: bytecode.to_module_code(pool),
+                        is_async: *is_async,
+                        is_generator: *is_generator,
+                    }
+                }
+                primitive => {
+                    let key = PrimitiveKey::of(primitive).expect("functions and regular expressions are handled above");
+                    ModuleConstant::Shared(pool.index_of(primitive, key))
+                }
+            })
+            .collect();
+        ModuleCode {
+            instructions: self.instructions.clone(),
+            constants,
+            names: self.names.clone(),
+            source_map: self.source_map.clone(),
+            handlers: self.handlers.clone(),
+            local_count: self.local_count,
+            max_stack_depth: self.max_stack_depth,
+        }
+    }
+
     pub fn add_name(&mut self, name: String) -> usize {
         if let Some(index) = self.names.iter().position(|n| n == &name) {
             index
@@ -168,6 +426,11 @@ impl Bytecode {
         self.source_map.insert(instruction_index, (line, column));
     }
 
+    /// The line and column of the statement instruction `pc` belongs to
+    pub fn source_position(&self, pc: usize) -> Option<(usize, usize)> {
+        self.source_map.range(..=pc).next_back().map(|(_, &position)| position)
+    }
+
     pub fn len(&self) -> usize {
         self.instructions.len()
     }
@@ -176,12 +439,25 @@ impl Bytecode {
         self.instructions.is_empty()
     }
 
+    /// Send exceptions thrown in `start..end` to a handler whose code
+    /// starts at the next instruction emitted
+    pub fn add_handler(&mut self, start: usize, end: usize, kind: HandlerKind) {
+        self.handlers.push(ExceptionHandler {
+            start,
+            end,
+            handler: self.len(),
+            kind,
+            stack_depth: 0,
+        });
+    }
+
     pub fn patch_jump(&mut self, jump_index: usize, target_index: usize) {
         let offset = target_index as isize - jump_index as isize - 1;
         match &mut self.instructions[jump_index] {
             Instruction::Jump(ref mut offset_ref) |
             Instruction::JumpIfFalse(ref mut offset_ref) |
-            Instruction::JumpIfTrue(ref mut offset_ref) => {
+            Instruction::JumpIfTrue(ref mut offset_ref) |
+            Instruction::JumpIfNullish(ref mut offset_ref) => {
                 *offset_ref = offset;
             }
             _ => panic!("Attempted to patch non-jump instruction"),
@@ -189,25 +465,99 @@ impl Bytecode {
     }
 
     pub fn optimize(&mut self) {
-        // Simple peephole optimizations
+        // Simple peephole optimizations. A `Pop` that code jumps to balances
+        // a value another path left on the stack, so it always stays.
+        let targets = self.jump_targets();
+        let old = std::mem::take(&mut self.instructions);
+        let mut index_map = Vec::with_capacity(old.len() + 1);
         let mut i = 0;
-        while i < self.instructions.len() {
-            match self.instructions.get(i..i + 2) {
+        while i < old.len() {
+            match &old[i..] {
                 // Remove redundant load/pop sequences
-                Some([Instruction::LoadConstant(_), Instruction::Pop]) => {
-                    self.instructions.drain(i..i + 2);
-                    continue;
+                [Instruction::LoadConstant(_), Instruction::Pop, ..] if !targets.contains(&(i + 1)) => {
+                    index_map.extend([self.instructions.len(); 2]);
+                    i += 2;
                 }
-                // Convert load constant + return to direct return constant
-                Some([Instruction::LoadConstant(idx), Instruction::Return]) => {
-                    let idx = *idx;
-                    self.instructions[i] = Instruction::LoadConstant(idx);
-                    self.instructions[i + 1] = Instruction::Return;
+                [instruction, ..] => {
+                    index_map.push(self.instructions.len());
+                    self.instructions.push(instruction.clone());
+                    i += 1;
                 }
-                _ => {}
+                [] => unreachable!(),
             }
-            i += 1;
         }
+        index_map.push(self.instructions.len());
+        if self.instructions.len() == old.len() {
+            return;
+        }
+
+        // Removed instructions map to the one after them
+        inline::relocate_jumps(&old, &mut self.instructions, &index_map);
+        for handler in &mut self.handlers {
+            handler.start = index_map[handler.start];
+            handler.end = index_map[handler.end];
+            handler.handler = index_map[handler.handler];
+        }
+        self.source_map = std::mem::take(&mut self.source_map)
+            .into_iter()
+            .map(|(index, position)| (index_map[index], position))
+            .collect();
+    }
+
+    /// Instructions that jumps or exception handlers continue at
+    fn jump_targets(&self) -> HashSet<usize> {
+        let jumps = self.instructions.iter().enumerate().filter_map(|(pc, instruction)| match instruction {
+            Instruction::Jump(offset)
+            | Instruction::JumpIfFalse(offset)
+            | Instruction::JumpIfTrue(offset)
+            | Instruction::JumpIfNullish(offset) => Some((pc as isize + offset + 1) as usize),
+            _ => None,
+        });
+        jumps.chain(self.handlers.iter().map(|handler| handler.handler)).collect()
+    }
+}
+
+impl BytecodeModule {
+    /// The script back as the VM runs it. Strings from the shared pool stay
+    /// shared between the functions that use them.
+    pub fn into_bytecode(self) -> CompileResult<Bytecode> {
+        self.code.into_bytecode(&self.constants)
+    }
+}
+
+impl ModuleCode {
+    fn into_bytecode(self, pool: &[Constant]) -> CompileResult<Bytecode> {
+        let constants = self
+            .constants
+            .into_iter()
+            .map(|constant| match constant {
+                ModuleConstant::Shared(index) => pool.get(index).cloned().ok_or_else(|| {
+                    CompileError::InternalError(format!("Shared constant {} is out of range", index))
+                }),
+                ModuleConstant::RegExp { pattern, flags } => Ok(Constant::RegExp { pattern, flags }),
+                ModuleConstant::Function { name, param_count, code, is_async, is_generator } => {
+                    Ok(Constant::Function {
+                        name,
+                        param_count,
+                        bytecode: code.into_bytecode(pool)?,
+                        is_async,
+                        is_generator,
+                    })
+                }
+            })
+            .collect::<CompileResult<_>>()?;
+        Ok(Bytecode {
+            instructions: self.instructions,
+            constants,
+            names: self.names,
+            source_map: self.source_map,
+            handlers: self.handlers,
+            deduplicated_constants: 0,
+            constant_indices: HashMap::new(),
+            indexed_constants: 0,
+            local_count: self.local_count,
+            max_stack_depth: self.max_stack_depth,
+        })
     }
 }
 
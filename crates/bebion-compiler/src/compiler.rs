@@ -1,34 +1,124 @@
 //! JavaScript to bytecode compiler
 
-use crate::bytecode::{Bytecode, Constant, Instruction};
+use crate::bytecode::{Bytecode, Constant, HandlerKind, Instruction};
+use crate::{fold, frame, inline};
 use crate::{CompileError, CompileResult};
 use bebion_parser::ast::*;
-use std::collections::HashMap;
+use bebion_parser::ScopeAnalysis;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use tracing::debug;
 
 pub struct Compiler {
     scopes: Vec<Scope>,
     loop_stack: Vec<LoopInfo>,
     function_depth: usize,
+    /// Local slots allocated so far in the script and each enclosing
+    /// function; block scopes take fresh slots so they never clobber the
+    /// parameters or outer locals of their frame
+    local_counts: Vec<usize>,
+    /// `try` statements of the current function whose block or catch body
+    /// is being compiled, innermost last
+    try_stack: Vec<TryContext>,
+    opt_level: OptLevel,
+    /// Whether statements are preceded by `DebugInfo` markers
+    debug_info: bool,
+    inline_stats: Option<inline::InlineStats>,
+    folded_constants: usize,
+    /// String constants of the script being compiled, so every function
+    /// that uses a string shares one copy of it
+    strings: HashSet<Arc<str>>,
+    /// URL of the module being compiled, which `import.meta` describes;
+    /// `None` for scripts
+    module_url: Option<String>,
+}
+
+/// How much optimization `compile` applies, as in `-O0` to `-O2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// Emit bytecode exactly as compiled
+    O0,
+    /// Peephole optimizations
+    #[default]
+    O1,
+    /// Also inline calls to tiny functions
+    O2,
+}
+
+impl OptLevel {
+    pub fn from_level(level: u8) -> Option<OptLevel> {
+        match level {
+            0 => Some(OptLevel::O0),
+            1 => Some(OptLevel::O1),
+            2 => Some(OptLevel::O2),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct Scope {
     variables: HashMap<String, Variable>,
     depth: usize,
+    /// The function whose frame holds the scope's slots, as a
+    /// `function_depth`
+    frame: usize,
 }
 
 #[derive(Debug, Clone)]
 struct Variable {
     index: usize,
-    kind: VarKind,
-    is_captured: bool,
+    /// Whether code compiled from here on runs after the declaration. A
+    /// `let` or `const` is declared at the start of its block but only
+    /// initialized where it appears; uses before that check the slot.
+    initialized: bool,
 }
 
 #[derive(Debug, Clone)]
 struct LoopInfo {
     break_jumps: Vec<usize>,
     continue_jumps: Vec<usize>,
+    /// `try` statements already open when the loop began
+    try_depth: usize,
+}
+
+/// A `try` statement whose block or catch body is being compiled
+#[derive(Debug, Clone)]
+struct TryContext {
+    /// Start of the code its handlers currently protect, unless a jump out
+    /// of the statement is being compiled
+    protected_from: Option<usize>,
+    /// Protected code before that, which the finalizers run on the way out
+    /// of the statement split up
+    protected: Vec<(usize, usize)>,
+    finalizer: Option<AstNode>,
+}
+
+impl TryContext {
+    fn new(start: usize, finalizer: Option<&AstNode>) -> Self {
+        Self {
+            protected_from: Some(start),
+            protected: Vec::new(),
+            finalizer: finalizer.cloned(),
+        }
+    }
+
+    /// Stop protecting code at `end`
+    fn suspend(&mut self, end: usize) {
+        let Some(start) = self.protected_from.take() else {
+            return;
+        };
+        match self.protected.last_mut() {
+            Some((_, last_end)) if *last_end == start => *last_end = end,
+            _ if start < end => self.protected.push((start, end)),
+            _ => {}
+        }
+    }
+
+    /// Protect code again from `start`
+    fn resume(&mut self, start: usize) {
+        self.protected_from = Some(start);
+    }
 }
 
 impl Compiler {
@@ -36,39 +126,127 @@ impl Compiler {
         let global_scope = Scope {
             variables: HashMap::new(),
             depth: 0,
+            frame: 0,
         };
         
         Self {
             scopes: vec![global_scope],
             loop_stack: Vec::new(),
             function_depth: 0,
+            local_counts: vec![0],
+            try_stack: Vec::new(),
+            opt_level: OptLevel::default(),
+            debug_info: false,
+            inline_stats: None,
+            folded_constants: 0,
+            strings: HashSet::new(),
+            module_url: None,
+        }
+    }
+
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.opt_level = opt_level;
+    }
+
+    pub fn opt_level(&self) -> OptLevel {
+        self.opt_level
+    }
+
+    /// Also mark where each statement starts with a `DebugInfo` instruction,
+    /// which the VM tracks as the line it is on
+    pub fn set_debug_info(&mut self, debug_info: bool) {
+        self.debug_info = debug_info;
+    }
+
+    pub fn debug_info(&self) -> bool {
+        self.debug_info
+    }
+
+    /// Compile what follows as the module at `url`, or as a script for
+    /// `None`
+    pub fn set_module_url(&mut self, url: Option<String>) {
+        self.module_url = url;
+    }
+
+    pub fn module_url(&self) -> Option<&str> {
+        self.module_url.as_deref()
+    }
+
+    /// What inlining did in the last `compile`, when it ran at `-O2`
+    pub fn inline_stats(&self) -> Option<inline::InlineStats> {
+        self.inline_stats
+    }
+
+    /// Expressions the last `compile` replaced with a single string constant
+    pub fn folded_constants(&self) -> usize {
+        self.folded_constants
+    }
+
+    /// A string constant backed by the script's single copy of `text`
+    fn string_constant(&mut self, text: &str) -> Constant {
+        if let Some(interned) = self.strings.get(text) {
+            return Constant::String(Arc::clone(interned));
         }
+        let interned: Arc<str> = Arc::from(text);
+        self.strings.insert(Arc::clone(&interned));
+        Constant::String(interned)
     }
 
     pub fn compile(&mut self, program: &Program) -> CompileResult<Bytecode> {
         debug!("Compiling program with {} statements", program.body.len());
         
+        // Redeclarations and assignments to constants fail the whole script
+        if let Some(error) = ScopeAnalysis::analyze(program).errors().first() {
+            return Err(CompileError::InvalidSyntax(error.to_string()));
+        }
+        
         let mut bytecode = Bytecode::new();
+        self.folded_constants = 0;
+        self.strings.clear();
+        // Each script runs in a frame of its own
+        self.scopes.truncate(1);
+        self.scopes[0].variables.clear();
+        self.local_counts = vec![0];
         
-        for statement in &program.body {
+        self.hoist_var_declarations(&program.body)?;
+        self.hoist_lexical_declarations(&program.body, &mut bytecode)?;
+        
+        // Imports are loaded before the rest of the module runs
+        let (imports, statements): (Vec<_>, Vec<_>) = program
+            .body
+            .iter()
+            .partition(|statement| matches!(statement, AstNode::ImportDeclaration { .. }));
+        for statement in imports.into_iter().chain(statements) {
             self.compile_statement(statement, &mut bytecode)?;
         }
         
-        // End with halt instruction
+        // The script's result is its completion value
+        bytecode.emit(Instruction::LoadCompletion);
         bytecode.emit(Instruction::Halt);
         
-        // Optimize the bytecode
-        bytecode.optimize();
+        // Optimize the bytecode. Inlining needs the whole script's call
+        // sites, so it runs here rather than per function.
+        self.inline_stats = (self.opt_level >= OptLevel::O2)
+            .then(|| inline::inline_small_functions(&mut bytecode, inline::DEFAULT_INLINE_THRESHOLD));
+        if self.opt_level >= OptLevel::O1 {
+            bytecode.optimize();
+        }
+        frame::compute_frame_sizes(&mut bytecode);
         
         debug!("Generated {} instructions", bytecode.len());
         Ok(bytecode)
     }
 
     fn compile_statement(&mut self, stmt: &AstNode, bytecode: &mut Bytecode) -> CompileResult<()> {
+        self.mark_statement(stmt, bytecode);
         match stmt {
             AstNode::ExpressionStatement { expression, .. } => {
                 self.compile_expression(expression, bytecode)?;
-                bytecode.emit(Instruction::Pop); // Discard expression result
+                if self.function_depth == 0 {
+                    bytecode.emit(Instruction::StoreCompletion);
+                } else {
+                    bytecode.emit(Instruction::Pop); // Discard expression result
+                }
             }
             
             AstNode::VariableDeclaration { declarations, kind, .. } => {
@@ -83,6 +261,7 @@ impl Compiler {
             
             AstNode::BlockStatement { body, .. } => {
                 self.begin_scope();
+                self.hoist_lexical_declarations(body, bytecode)?;
                 for statement in body {
                     self.compile_statement(statement, bytecode)?;
                 }
@@ -108,24 +287,42 @@ impl Compiler {
                     let undefined_idx = bytecode.add_constant(Constant::Undefined);
                     bytecode.emit(Instruction::LoadConstant(undefined_idx));
                 }
-                bytecode.emit(Instruction::Return);
-            }
-            
-            AstNode::BreakStatement { .. } => {
-                if let Some(loop_info) = self.loop_stack.last_mut() {
-                    let jump_idx = bytecode.emit(Instruction::Jump(0));
-                    loop_info.break_jumps.push(jump_idx);
+                
+                // The value waits in a local while finalizers run, so one
+                // that jumps elsewhere leaves the stack as it found it
+                if self.has_finalizers(0) {
+                    let value = self.hidden_local();
+                    bytecode.emit(Instruction::StoreLocal(value));
+                    let exited = self.leave_try_statements(0, bytecode)?;
+                    bytecode.emit(Instruction::LoadLocal(value));
+                    bytecode.emit(Instruction::Return);
+                    self.reenter_try_statements(exited, bytecode);
                 } else {
-                    return Err(CompileError::InvalidSyntax("break statement not in loop".to_string()));
+                    bytecode.emit(Instruction::Return);
                 }
             }
             
-            AstNode::ContinueStatement { .. } => {
-                if let Some(loop_info) = self.loop_stack.last_mut() {
+            AstNode::BreakStatement { .. } | AstNode::ContinueStatement { .. } => {
+                let is_break = matches!(stmt, AstNode::BreakStatement { .. });
+                let Some(try_depth) = self.loop_stack.last().map(|loop_info| loop_info.try_depth) else {
+                    let keyword = if is_break { "break" } else { "continue" };
+                    return Err(CompileError::InvalidSyntax(format!("{} statement not in loop", keyword)));
+                };
+                
+                let jump_idx = if self.has_finalizers(try_depth) {
+                    let exited = self.leave_try_statements(try_depth, bytecode)?;
                     let jump_idx = bytecode.emit(Instruction::Jump(0));
-                    loop_info.continue_jumps.push(jump_idx);
+                    self.reenter_try_statements(exited, bytecode);
+                    jump_idx
+                } else {
+                    bytecode.emit(Instruction::Jump(0))
+                };
+                
+                let loop_info = self.loop_stack.last_mut().expect("checked above");
+                if is_break {
+                    loop_info.break_jumps.push(jump_idx);
                 } else {
-                    return Err(CompileError::InvalidSyntax("continue statement not in loop".to_string()));
+                    loop_info.continue_jumps.push(jump_idx);
                 }
             }
             
@@ -138,6 +335,14 @@ impl Compiler {
                 self.compile_try_statement(block, handler.as_deref(), finalizer.as_deref(), bytecode)?;
             }
             
+            AstNode::ImportDeclaration { specifiers, source, .. } => {
+                self.compile_import_declaration(stmt, specifiers, source, bytecode)?;
+            }
+            
+            AstNode::ExportDeclaration { declaration, specifiers, .. } => {
+                self.compile_export_declaration(declaration.as_deref(), specifiers, bytecode)?;
+            }
+            
             _ => {
                 return Err(CompileError::UnsupportedFeature(
                     format!("Statement: {:?}", std::mem::discriminant(stmt))
@@ -149,6 +354,15 @@ impl Compiler {
     }
 
     fn compile_expression(&mut self, expr: &AstNode, bytecode: &mut Bytecode) -> CompileResult<()> {
+        if self.opt_level >= OptLevel::O1 {
+            if let Some(folded) = fold::constant_string(expr) {
+                self.folded_constants += 1;
+                let idx = bytecode.add_constant(self.string_constant(&folded));
+                bytecode.emit(Instruction::LoadConstant(idx));
+                return Ok(());
+            }
+        }
+        
         match expr {
             AstNode::Identifier { name, .. } => {
                 self.compile_identifier(name, bytecode)?;
@@ -156,21 +370,48 @@ impl Compiler {
             
             AstNode::Literal { value, .. } => {
                 let constant = match value {
-                    LiteralValue::String(s) => Constant::String(s.clone()),
+                    LiteralValue::String(s) => self.string_constant(s),
                     LiteralValue::Number(n) => Constant::Number(*n),
                     LiteralValue::Boolean(b) => Constant::Boolean(*b),
                     LiteralValue::Null => Constant::Null,
                     LiteralValue::Undefined => Constant::Undefined,
-                    LiteralValue::RegExp { pattern, flags } => {
-                        // treat regex as string
-                        Constant::String(format!("/{}/{}", pattern, flags))
-                    }
+                    LiteralValue::RegExp { pattern, flags } => Constant::RegExp {
+                        pattern: pattern.clone(),
+                        flags: flags.clone(),
+                    },
                 };
                 
                 let idx = bytecode.add_constant(constant);
                 bytecode.emit(Instruction::LoadConstant(idx));
             }
             
+            AstNode::BinaryExpression {
+                operator: operator @ (BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr | BinaryOperator::NullishCoalescing),
+                left,
+                right,
+                ..
+            } => {
+                // Keep the left value when it decides the result; the right
+                // operand is only evaluated when needed
+                self.compile_expression(left, bytecode)?;
+                bytecode.emit(Instruction::Duplicate);
+                let keep_left = match operator {
+                    BinaryOperator::LogicalAnd => bytecode.emit(Instruction::JumpIfFalse(0)),
+                    BinaryOperator::LogicalOr => bytecode.emit(Instruction::JumpIfTrue(0)),
+                    _ => {
+                        let use_right = bytecode.emit(Instruction::JumpIfNullish(0));
+                        let keep_left = bytecode.emit(Instruction::Jump(0));
+                        bytecode.patch_jump(use_right, bytecode.len());
+                        keep_left
+                    }
+                };
+                bytecode.emit(Instruction::Pop);
+                self.compile_expression(right, bytecode)?;
+                
+                let end_target = bytecode.len();
+                bytecode.patch_jump(keep_left, end_target);
+            }
+            
             AstNode::BinaryExpression { operator, left, right, .. } => {
                 self.compile_expression(left, bytecode)?;
                 self.compile_expression(right, bytecode)?;
@@ -190,20 +431,24 @@ impl Compiler {
                     BinaryOperator::Greater => Instruction::Greater,
                     BinaryOperator::LessEqual => Instruction::LessEqual,
                     BinaryOperator::GreaterEqual => Instruction::GreaterEqual,
-                    BinaryOperator::LogicalAnd => Instruction::LogicalAnd,
-                    BinaryOperator::LogicalOr => Instruction::LogicalOr,
                     BinaryOperator::BitwiseAnd => Instruction::BitwiseAnd,
                     BinaryOperator::BitwiseOr => Instruction::BitwiseOr,
                     BinaryOperator::BitwiseXor => Instruction::BitwiseXor,
                     BinaryOperator::LeftShift => Instruction::LeftShift,
                     BinaryOperator::RightShift => Instruction::RightShift,
                     BinaryOperator::UnsignedRightShift => Instruction::UnsignedRightShift,
+                    BinaryOperator::In => Instruction::HasProperty,
+                    BinaryOperator::InstanceOf => Instruction::InstanceOf,
                     _ => return Err(CompileError::UnsupportedFeature(format!("Binary operator: {:?}", operator))),
                 };
                 
                 bytecode.emit(instruction);
             }
             
+            AstNode::UnaryExpression { operator: UnaryOperator::Delete, argument, .. } => {
+                self.compile_delete(argument, bytecode)?;
+            }
+            
             AstNode::UnaryExpression { operator, argument, .. } => {
                 self.compile_expression(argument, bytecode)?;
                 
@@ -222,14 +467,17 @@ impl Compiler {
             AstNode::AssignmentExpression { left, right, operator, .. } => {
                 match operator {
                     AssignmentOperator::Assign => {
-                        self.compile_expression(right, bytecode)?;
-                        self.compile_assignment_target(left, bytecode)?;
+                        self.compile_assignment_target(left, false, bytecode, |this, bytecode| {
+                            this.compile_expression(right, bytecode)
+                        })?;
+                    }
+                    AssignmentOperator::LogicalAndAssign
+                    | AssignmentOperator::LogicalOrAssign
+                    | AssignmentOperator::NullishCoalescingAssign => {
+                        self.compile_logical_assignment(operator, left, right, bytecode)?;
                     }
                     _ => {
                         // For compound assignments, load current value, perform operation, then store
-                        self.compile_expression(left, bytecode)?;
-                        self.compile_expression(right, bytecode)?;
-                        
                         let op_instruction = match operator {
                             AssignmentOperator::AddAssign => Instruction::Add,
                             AssignmentOperator::SubAssign => Instruction::Subtract,
@@ -240,60 +488,77 @@ impl Compiler {
                             _ => return Err(CompileError::UnsupportedFeature(format!("Assignment operator: {:?}", operator))),
                         };
                         
-                        bytecode.emit(op_instruction);
-                        self.compile_assignment_target(left, bytecode)?;
+                        self.compile_assignment_target(left, true, bytecode, |this, bytecode| {
+                            this.compile_expression(right, bytecode)?;
+                            bytecode.emit(op_instruction);
+                            Ok(())
+                        })?;
                     }
                 }
             }
             
-            AstNode::CallExpression { callee, arguments, .. } => {
-                self.compile_expression(callee, bytecode)?;
-                
-                for arg in arguments {
-                    self.compile_expression(arg, bytecode)?;
-                }
-                
-                bytecode.emit(Instruction::Call(arguments.len()));
+            AstNode::CallExpression { .. } | AstNode::MemberExpression { .. } => {
+                // Outside a chain no link is optional, so nothing short-circuits
+                let mut short_circuits = Vec::new();
+                self.compile_chain_element(expr, &mut short_circuits, bytecode)?;
             }
             
-            AstNode::MemberExpression { object, property, computed, .. } => {
-                self.compile_expression(object, bytecode)?;
-                
-                if *computed {
-                    self.compile_expression(property, bytecode)?;
-                    bytecode.emit(Instruction::GetElement);
-                } else {
-                    self.compile_expression(property, bytecode)?;
-                    bytecode.emit(Instruction::GetProperty);
-                }
+            AstNode::ChainExpression { expression, .. } => {
+                let mut short_circuits = Vec::new();
+                self.compile_chain_element(expression, &mut short_circuits, bytecode)?;
+                self.finish_chain(short_circuits, Constant::Undefined, bytecode);
             }
             
             AstNode::ArrayExpression { elements, .. } => {
-                let mut element_count = 0;
+                // Elements before the first spread are built in one NewArray;
+                // the rest are appended so spreads see the array built so far
+                let leading = elements.iter()
+                    .take_while(|element| !matches!(element, Some(AstNode::SpreadElement { .. })))
+                    .count();
                 
-                for element in elements {
-                    if let Some(elem) = element {
-                        self.compile_expression(elem, bytecode)?;
-                        element_count += 1;
-                    } else {
-                        let undefined_idx = bytecode.add_constant(Constant::Undefined);
-                        bytecode.emit(Instruction::LoadConstant(undefined_idx));
-                        element_count += 1;
-                    }
+                for element in &elements[..leading] {
+                    self.compile_array_element(element.as_ref(), bytecode)?;
                 }
+                bytecode.emit(Instruction::NewArray(leading));
                 
-                bytecode.emit(Instruction::NewArray(element_count));
+                for element in &elements[leading..] {
+                    match element {
+                        Some(AstNode::SpreadElement { argument, .. }) => {
+                            self.compile_expression(argument, bytecode)?;
+                            bytecode.emit(Instruction::ArraySpread);
+                        }
+                        _ => {
+                            self.compile_array_element(element.as_ref(), bytecode)?;
+                            bytecode.emit(Instruction::ArrayAppend);
+                        }
+                    }
+                }
             }
             
             AstNode::ObjectExpression { properties, .. } => {
                 bytecode.emit(Instruction::NewObject);
                 
                 for property in properties {
-                    if let AstNode::Property { key, value, .. } = property {
-                        bytecode.emit(Instruction::Duplicate); // Duplicate object reference
-                        self.compile_expression(key, bytecode)?;
-                        self.compile_expression(value, bytecode)?;
-                        bytecode.emit(Instruction::SetProperty);
+                    match property {
+                        AstNode::Property { key, value, kind, computed, .. } => {
+                            bytecode.emit(Instruction::Duplicate); // Duplicate object reference
+                            self.compile_property_key(key, *computed, bytecode)?;
+                            self.compile_expression(value, bytecode)?;
+                            bytecode.emit(match kind {
+                                PropertyKind::Get => Instruction::DefineGetter,
+                                PropertyKind::Set => Instruction::DefineSetter,
+                                PropertyKind::Init | PropertyKind::Method => Instruction::SetProperty,
+                            });
+                            if matches!(kind, PropertyKind::Init | PropertyKind::Method) {
+                                bytecode.emit(Instruction::Pop); // Discard the stored value
+                            }
+                        }
+                        AstNode::SpreadElement { argument, .. } => {
+                            bytecode.emit(Instruction::Duplicate);
+                            self.compile_expression(argument, bytecode)?;
+                            bytecode.emit(Instruction::CopyDataProperties);
+                        }
+                        _ => {}
                     }
                 }
             }
@@ -302,6 +567,19 @@ impl Compiler {
                 self.compile_function_expression(id.as_deref(), params, body, *is_async, *is_generator, bytecode)?;
             }
             
+            AstNode::ArrowFunctionExpression { params, body, is_async, .. } => {
+                self.compile_function_expression(None, params, body, *is_async, false, bytecode)?;
+            }
+            
+            AstNode::AwaitExpression { argument, .. } => {
+                self.compile_expression(argument, bytecode)?;
+                bytecode.emit(Instruction::Await);
+            }
+            
+            AstNode::TemplateLiteral { quasis, expressions, .. } => {
+                self.compile_template_literal(quasis, expressions, bytecode)?;
+            }
+            
             AstNode::ConditionalExpression { test, consequent, alternate, .. } => {
                 self.compile_expression(test, bytecode)?;
                 
@@ -317,6 +595,16 @@ impl Compiler {
                 bytecode.patch_jump(end_jump, end_target);
             }
             
+            AstNode::SequenceExpression { expressions, .. } => {
+                // Every value but the last is discarded
+                for (i, expression) in expressions.iter().enumerate() {
+                    if i > 0 {
+                        bytecode.emit(Instruction::Pop);
+                    }
+                    self.compile_expression(expression, bytecode)?;
+                }
+            }
+            
             _ => {
                 return Err(CompileError::UnsupportedFeature(
                     format!("Expression: {:?}", std::mem::discriminant(expr))
@@ -327,14 +615,259 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compile one link of a member/call chain. Each optional link records a
+    /// jump taken when its base is nullish; `finish_chain` points all of them
+    /// at a single short-circuit exit for the whole chain.
+    fn compile_chain_element(
+        &mut self,
+        node: &AstNode,
+        short_circuits: &mut Vec<usize>,
+        bytecode: &mut Bytecode,
+    ) -> CompileResult<()> {
+        match node {
+            AstNode::MemberExpression { object, property, computed, optional, .. } => {
+                self.compile_chain_element(object, short_circuits, bytecode)?;
+                if *optional {
+                    self.emit_nullish_check(short_circuits, bytecode);
+                }
+                
+                self.compile_property_key(property, *computed, bytecode)?;
+                bytecode.emit(if *computed { Instruction::GetElement } else { Instruction::GetProperty });
+            }
+            
+            AstNode::CallExpression { callee, arguments, optional, .. } => {
+                // `a.m(...)` leaves the receiver on the stack for the call to
+                // look `m` up on and bind as `this`. `a[k](...)` and `a.m?.()`
+                // read the method themselves, to compute its key or test it,
+                // and keep the receiver below it for the call.
+                let AstNode::MemberExpression { object, property, computed, optional: member_optional, .. } =
+                    callee.as_ref()
+                else {
+                    self.compile_chain_element(callee, short_circuits, bytecode)?;
+                    if *optional {
+                        self.emit_nullish_check(short_circuits, bytecode);
+                    }
+                    self.compile_arguments(arguments, bytecode)?;
+                    bytecode.emit(Instruction::Call(arguments.len()));
+                    return Ok(());
+                };
+                
+                self.compile_chain_element(object, short_circuits, bytecode)?;
+                if *member_optional {
+                    self.emit_nullish_check(short_circuits, bytecode);
+                }
+                
+                match property.as_ref() {
+                    AstNode::Identifier { name, .. } if !*computed && !*optional => {
+                        self.compile_arguments(arguments, bytecode)?;
+                        let name_idx = bytecode.add_name(name.to_string());
+                        bytecode.emit(Instruction::CallMethod(name_idx, arguments.len()));
+                    }
+                    _ => {
+                        bytecode.emit(Instruction::Duplicate);
+                        self.compile_property_key(property, *computed, bytecode)?;
+                        bytecode.emit(if *computed { Instruction::GetElement } else { Instruction::GetProperty });
+                        
+                        // A nullish method short-circuits the chain once its
+                        // receiver is dropped too
+                        let nullish_method = if *optional {
+                            bytecode.emit(Instruction::Duplicate);
+                            Some(bytecode.emit(Instruction::JumpIfNullish(0)))
+                        } else {
+                            None
+                        };
+                        
+                        self.compile_arguments(arguments, bytecode)?;
+                        bytecode.emit(Instruction::CallWithReceiver(arguments.len()));
+                        
+                        if let Some(nullish_method) = nullish_method {
+                            let called = bytecode.emit(Instruction::Jump(0));
+                            let drop_receiver = bytecode.len();
+                            bytecode.patch_jump(nullish_method, drop_receiver);
+                            bytecode.emit(Instruction::Pop);
+                            short_circuits.push(bytecode.emit(Instruction::Jump(0)));
+                            let after_call = bytecode.len();
+                            bytecode.patch_jump(called, after_call);
+                        }
+                    }
+                }
+            }
+            
+            // Parenthesized chains and other bases form their own region
+            _ => self.compile_expression(node, bytecode)?,
+        }
+        
+        Ok(())
+    }
+
+    fn compile_arguments(&mut self, arguments: &[AstNode], bytecode: &mut Bytecode) -> CompileResult<()> {
+        for arg in arguments {
+            self.compile_expression(arg, bytecode)?;
+        }
+        Ok(())
+    }
+
+    fn emit_nullish_check(&mut self, short_circuits: &mut Vec<usize>, bytecode: &mut Bytecode) {
+        bytecode.emit(Instruction::Duplicate);
+        short_circuits.push(bytecode.emit(Instruction::JumpIfNullish(0)));
+    }
+
+    /// Emit the shared exit for a chain: on short-circuit, replace the nullish
+    /// base left on the stack with `result`
+    fn finish_chain(&mut self, short_circuits: Vec<usize>, result: Constant, bytecode: &mut Bytecode) {
+        if short_circuits.is_empty() {
+            return;
+        }
+        
+        let end_jump = bytecode.emit(Instruction::Jump(0));
+        
+        let short_circuit_target = bytecode.len();
+        for jump in short_circuits {
+            bytecode.patch_jump(jump, short_circuit_target);
+        }
+        bytecode.emit(Instruction::Pop);
+        let result_idx = bytecode.add_constant(result);
+        bytecode.emit(Instruction::LoadConstant(result_idx));
+        
+        let end_target = bytecode.len();
+        bytecode.patch_jump(end_jump, end_target);
+    }
+
+    /// Concatenate a template's text and substitutions left to right. The
+    /// leading text is always loaded so every `Add` sees a string; constant
+    /// substitutions are merged into the surrounding text.
+    fn compile_template_literal(&mut self, quasis: &[AstNode], expressions: &[AstNode], bytecode: &mut Bytecode) -> CompileResult<()> {
+        let mut text = String::new();
+        let mut started = false;
+        
+        for (i, quasi) in quasis.iter().enumerate() {
+            if let AstNode::Literal { value: LiteralValue::String(s), .. } = quasi {
+                text.push_str(s);
+            }
+            let Some(expression) = expressions.get(i) else {
+                continue;
+            };
+            
+            let constant = (self.opt_level >= OptLevel::O1).then(|| fold::constant_text(expression)).flatten();
+            if let Some(constant) = constant {
+                text.push_str(&constant);
+                continue;
+            }
+            
+            if !started || !text.is_empty() {
+                let idx = bytecode.add_constant(self.string_constant(&std::mem::take(&mut text)));
+                bytecode.emit(Instruction::LoadConstant(idx));
+                if started {
+                    bytecode.emit(Instruction::Add);
+                }
+                started = true;
+            }
+            self.compile_expression(expression, bytecode)?;
+            bytecode.emit(Instruction::Add);
+        }
+        
+        if !started || !text.is_empty() {
+            let idx = bytecode.add_constant(self.string_constant(&text));
+            bytecode.emit(Instruction::LoadConstant(idx));
+            if started {
+                bytecode.emit(Instruction::Add);
+            }
+        }
+        Ok(())
+    }
+
+    /// Push a property key: the name itself for `a.b`, the evaluated expression for `a[b]`
+    fn compile_property_key(&mut self, property: &AstNode, computed: bool, bytecode: &mut Bytecode) -> CompileResult<()> {
+        match property {
+            AstNode::Identifier { name, .. } if !computed => {
+                let idx = bytecode.add_constant(self.string_constant(name));
+                bytecode.emit(Instruction::LoadConstant(idx));
+                Ok(())
+            }
+            _ => self.compile_expression(property, bytecode),
+        }
+    }
+
+    /// Push an array literal element, with holes read as `undefined`
+    fn compile_array_element(&mut self, element: Option<&AstNode>, bytecode: &mut Bytecode) -> CompileResult<()> {
+        match element {
+            Some(elem) => self.compile_expression(elem, bytecode),
+            None => {
+                let undefined_idx = bytecode.add_constant(Constant::Undefined);
+                bytecode.emit(Instruction::LoadConstant(undefined_idx));
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_delete(&mut self, argument: &AstNode, bytecode: &mut Bytecode) -> CompileResult<()> {
+        match argument {
+            // `delete a?.b` is `true` when `a` is nullish
+            AstNode::ChainExpression { expression, .. } => {
+                let mut short_circuits = Vec::new();
+                match expression.as_ref() {
+                    AstNode::MemberExpression { object, property, computed, optional, .. } => {
+                        self.compile_chain_element(object, &mut short_circuits, bytecode)?;
+                        if *optional {
+                            self.emit_nullish_check(&mut short_circuits, bytecode);
+                        }
+                        self.compile_property_key(property, *computed, bytecode)?;
+                        bytecode.emit(Instruction::DeleteProperty);
+                    }
+                    other => {
+                        self.compile_chain_element(other, &mut short_circuits, bytecode)?;
+                        bytecode.emit(Instruction::Pop);
+                        let true_idx = bytecode.add_constant(Constant::Boolean(true));
+                        bytecode.emit(Instruction::LoadConstant(true_idx));
+                    }
+                }
+                self.finish_chain(short_circuits, Constant::Boolean(true), bytecode);
+            }
+            
+            AstNode::MemberExpression { object, property, computed, .. } => {
+                self.compile_expression(object, bytecode)?;
+                self.compile_property_key(property, *computed, bytecode)?;
+                bytecode.emit(Instruction::DeleteProperty);
+            }
+            
+            AstNode::Identifier { .. } => {
+                return Err(CompileError::UnsupportedFeature("delete of a binding".to_string()));
+            }
+            
+            // Deleting anything that is not a reference evaluates it and yields true
+            _ => {
+                self.compile_expression(argument, bytecode)?;
+                bytecode.emit(Instruction::Pop);
+                let true_idx = bytecode.add_constant(Constant::Boolean(true));
+                bytecode.emit(Instruction::LoadConstant(true_idx));
+            }
+        }
+        
+        Ok(())
+    }
+
     fn compile_identifier(&mut self, name: &str, bytecode: &mut Bytecode) -> CompileResult<()> {
-        if let Some(var) = self.resolve_variable(name) {
+        if name == "this" {
+            bytecode.emit(Instruction::LoadThis);
+        } else if name == "import.meta" {
+            let Some(url) = self.module_url.clone() else {
+                return Err(CompileError::InvalidSyntax("import.meta is only valid in a module".to_string()));
+            };
+            let url_idx = bytecode.add_constant(self.string_constant(&url));
+            bytecode.emit(Instruction::ImportMeta(url_idx));
+        } else if let Some(var) = self.resolve_variable(name) {
             if var.index < 256 {
-                bytecode.emit(Instruction::LoadLocal(var.index));
+                let (index, initialized) = (var.index, var.initialized);
+                if !initialized {
+                    let name_idx = bytecode.add_name(name.to_string());
+                    bytecode.emit(Instruction::CheckInitialized(index, name_idx));
+                }
+                bytecode.emit(Instruction::LoadLocal(index));
             } else {
                 return Err(CompileError::InternalError("Too many local variables".to_string()));
             }
         } else {
+            self.check_not_captured(name)?;
             let name_idx = bytecode.add_name(name.to_string());
             bytecode.emit(Instruction::LoadGlobal(name_idx));
         }
@@ -342,19 +875,58 @@ impl Compiler {
         Ok(())
     }
 
-    fn compile_assignment_target(&mut self, target: &AstNode, bytecode: &mut Bytecode) -> CompileResult<()> {
+    /// Pop the top of the stack into the variable `name`
+    fn compile_store_variable(&mut self, name: &str, bytecode: &mut Bytecode) -> CompileResult<()> {
+        match self.resolve_variable(name) {
+            Some(var) => {
+                let (index, initialized) = (var.index, var.initialized);
+                if !initialized {
+                    let name_idx = bytecode.add_name(name.to_string());
+                    bytecode.emit(Instruction::CheckInitialized(index, name_idx));
+                }
+                bytecode.emit(Instruction::StoreLocal(index));
+            }
+            None => {
+                self.check_not_captured(name)?;
+                let name_idx = bytecode.add_name(name.to_string());
+                bytecode.emit(Instruction::StoreGlobal(name_idx));
+            }
+        }
+        Ok(())
+    }
+
+    /// Store the value `compile_value` computes into `target`, leaving it on
+    /// the stack as the result of the assignment. A member target evaluates
+    /// its object and key before the value, as JS does. With `reads_target`
+    /// the target's current value is pushed first for `compile_value` to
+    /// combine, reusing the member target's object and key rather than
+    /// evaluating them again.
+    fn compile_assignment_target(
+        &mut self,
+        target: &AstNode,
+        reads_target: bool,
+        bytecode: &mut Bytecode,
+        compile_value: impl FnOnce(&mut Self, &mut Bytecode) -> CompileResult<()>,
+    ) -> CompileResult<()> {
         match target {
             AstNode::Identifier { name, .. } => {
-                if let Some(var) = self.resolve_variable(name) {
-                    bytecode.emit(Instruction::StoreLocal(var.index));
-                } else {
-                    let name_idx = bytecode.add_name(name.to_string());
-                    bytecode.emit(Instruction::StoreGlobal(name_idx));
+                if reads_target {
+                    self.compile_identifier(name, bytecode)?;
                 }
+                compile_value(self, bytecode)?;
+                bytecode.emit(Instruction::Duplicate);
+                self.compile_store_variable(name, bytecode)?;
             }
             AstNode::MemberExpression { object, property, computed, .. } => {
                 self.compile_expression(object, bytecode)?;
-                self.compile_expression(property, bytecode)?;
+                self.compile_property_key(property, *computed, bytecode)?;
+                if reads_target {
+                    // object key -> object key object key -> object key current
+                    bytecode.emit(Instruction::Pick(1));
+                    bytecode.emit(Instruction::Pick(1));
+                    bytecode.emit(if *computed { Instruction::GetElement } else { Instruction::GetProperty });
+                }
+                compile_value(self, bytecode)?;
                 
                 if *computed {
                     bytecode.emit(Instruction::SetElement);
@@ -370,19 +942,125 @@ impl Compiler {
         Ok(())
     }
 
+    /// `target &&= value`, `target ||= value` and `target ??= value`. The
+    /// value is only evaluated and stored when the target's current value
+    /// calls for it; otherwise the current value is the result and nothing
+    /// is written.
+    fn compile_logical_assignment(
+        &mut self,
+        operator: &AssignmentOperator,
+        target: &AstNode,
+        value: &AstNode,
+        bytecode: &mut Bytecode,
+    ) -> CompileResult<()> {
+        let member = match target {
+            AstNode::Identifier { name, .. } => {
+                self.compile_identifier(name, bytecode)?;
+                None
+            }
+            AstNode::MemberExpression { object, property, computed, .. } => {
+                // object key -> object key current
+                self.compile_expression(object, bytecode)?;
+                self.compile_property_key(property, *computed, bytecode)?;
+                bytecode.emit(Instruction::Pick(1));
+                bytecode.emit(Instruction::Pick(1));
+                bytecode.emit(if *computed { Instruction::GetElement } else { Instruction::GetProperty });
+                Some(*computed)
+            }
+            _ => {
+                return Err(CompileError::InvalidSyntax("Invalid assignment target".to_string()));
+            }
+        };
+        
+        bytecode.emit(Instruction::Duplicate);
+        let keep_current = match operator {
+            AssignmentOperator::LogicalAndAssign => bytecode.emit(Instruction::JumpIfFalse(0)),
+            AssignmentOperator::LogicalOrAssign => bytecode.emit(Instruction::JumpIfTrue(0)),
+            _ => {
+                let assign = bytecode.emit(Instruction::JumpIfNullish(0));
+                let keep_current = bytecode.emit(Instruction::Jump(0));
+                bytecode.patch_jump(assign, bytecode.len());
+                keep_current
+            }
+        };
+        bytecode.emit(Instruction::Pop);
+        self.compile_expression(value, bytecode)?;
+        
+        match (target, member) {
+            (AstNode::Identifier { name, .. }, None) => {
+                bytecode.emit(Instruction::Duplicate);
+                self.compile_store_variable(name, bytecode)?;
+                bytecode.patch_jump(keep_current, bytecode.len());
+            }
+            (_, Some(computed)) => {
+                bytecode.emit(if computed { Instruction::SetElement } else { Instruction::SetProperty });
+                let end_jump = bytecode.emit(Instruction::Jump(0));
+                
+                // object key current -> current
+                bytecode.patch_jump(keep_current, bytecode.len());
+                bytecode.emit(Instruction::Swap);
+                bytecode.emit(Instruction::Pop);
+                bytecode.emit(Instruction::Swap);
+                bytecode.emit(Instruction::Pop);
+                bytecode.patch_jump(end_jump, bytecode.len());
+            }
+            _ => unreachable!("only identifiers and members get this far"),
+        }
+        
+        Ok(())
+    }
+
+    /// Record where `stmt` starts in the source map, and in debug builds
+    /// mark it with a `DebugInfo` instruction. Blocks are left to the
+    /// statements in them.
+    fn mark_statement(&mut self, stmt: &AstNode, bytecode: &mut Bytecode) {
+        if matches!(stmt, AstNode::BlockStatement { .. }) {
+            return;
+        }
+        if let Some(loc) = stmt.loc() {
+            let (line, column) = (loc.start.line, loc.start.column);
+            bytecode.add_source_location(bytecode.len(), line, column);
+            if self.debug_info {
+                bytecode.emit(Instruction::DebugInfo(line, column));
+            }
+        }
+    }
+
+    /// Initialize a variable where its declaration appears. Every binding
+    /// was already declared when its scope began; a `var` without an
+    /// initializer leaves the value it has alone.
     fn compile_variable_declarator(&mut self, decl: &AstNode, kind: &VarKind, bytecode: &mut Bytecode) -> CompileResult<()> {
         if let AstNode::VariableDeclarator { id, init, .. } = decl {
             if let AstNode::Identifier { name, .. } = id.as_ref() {
-                // Compile initializer if present
                 if let Some(init_expr) = init {
                     self.compile_expression(init_expr, bytecode)?;
+                } else if *kind == VarKind::Var {
+                    return Ok(());
                 } else {
                     let undefined_idx = bytecode.add_constant(Constant::Undefined);
                     bytecode.emit(Instruction::LoadConstant(undefined_idx));
                 }
                 
-                // Declare variable
-                let var_index = self.declare_variable(name, kind.clone())?;
+                if self.declares_global(kind) {
+                    let name_idx = bytecode.add_name(name.clone());
+                    bytecode.emit(match kind {
+                        VarKind::Var => Instruction::StoreGlobal(name_idx),
+                        VarKind::Let | VarKind::Const => Instruction::DeclareGlobal(name_idx),
+                    });
+                    return Ok(());
+                }
+                
+                let scope = match kind {
+                    VarKind::Var => self.function_scope(),
+                    VarKind::Let | VarKind::Const => self.scopes.len() - 1,
+                };
+                let var_index = match self.scopes[scope].variables.get_mut(name) {
+                    Some(var) => {
+                        var.initialized = true;
+                        var.index
+                    }
+                    None => self.declare_variable(name, kind.clone())?,
+                };
                 
                 let instruction = match kind {
                     VarKind::Var => Instruction::DeclareVar(var_index),
@@ -397,6 +1075,85 @@ impl Compiler {
         Ok(())
     }
 
+    /// Load the module `source` names, binding it to the default import
+    /// as a constant. The host decides what the import type, from the
+    /// `type` attribute, means; no type is a JavaScript module.
+    fn compile_import_declaration(
+        &mut self,
+        declaration: &AstNode,
+        specifiers: &[AstNode],
+        source: &AstNode,
+        bytecode: &mut Bytecode,
+    ) -> CompileResult<()> {
+        let AstNode::Literal { value: LiteralValue::String(specifier), .. } = source else {
+            return Err(CompileError::InvalidSyntax("Module specifier must be a string".to_string()));
+        };
+        
+        let import_type = declaration.import_attribute("type").unwrap_or_default();
+        let type_idx = bytecode.add_constant(self.string_constant(import_type));
+        bytecode.emit(Instruction::LoadConstant(type_idx));
+        let specifier_idx = bytecode.add_constant(self.string_constant(specifier));
+        bytecode.emit(Instruction::Import(specifier_idx));
+        
+        match specifiers {
+            [] => {
+                bytecode.emit(Instruction::Pop);
+            }
+            [AstNode::Identifier { name, .. }] if self.declares_global(&VarKind::Const) => {
+                let name_idx = bytecode.add_name(name.clone());
+                bytecode.emit(Instruction::DeclareGlobal(name_idx));
+            }
+            [AstNode::Identifier { name, .. }] => {
+                let var_index = self.declare_variable(name, VarKind::Const)?;
+                bytecode.emit(Instruction::DeclareConst(var_index));
+            }
+            _ => {
+                return Err(CompileError::UnsupportedFeature("Named and namespace imports".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Hand the module loader the values an `export` names: that of
+    /// `export default`'s expression, or those of the globals a declaration
+    /// or `export { a, b }` binds
+    fn compile_export_declaration(
+        &mut self,
+        declaration: Option<&AstNode>,
+        specifiers: &[AstNode],
+        bytecode: &mut Bytecode,
+    ) -> CompileResult<()> {
+        if self.function_depth > 0 || self.scopes.len() > 1 {
+            return Err(CompileError::InvalidSyntax("Exports must be at the top level of a module".to_string()));
+        }
+        
+        let names = match (declaration, specifiers) {
+            (Some(expression), [_default]) => {
+                self.compile_expression(expression, bytecode)?;
+                let name_idx = bytecode.add_name("default".to_string());
+                bytecode.emit(Instruction::Export(name_idx));
+                return Ok(());
+            }
+            (Some(declaration), _) => {
+                self.compile_statement(declaration, bytecode)?;
+                declared_names(declaration)
+            }
+            (None, specifiers) => specifiers
+                .iter()
+                .filter_map(|specifier| match specifier {
+                    AstNode::Identifier { name, .. } => Some(name.clone()),
+                    _ => None,
+                })
+                .collect(),
+        };
+        for name in names {
+            self.compile_expression(&AstNode::Identifier { name: name.clone(), loc: None }, bytecode)?;
+            let name_idx = bytecode.add_name(name);
+            bytecode.emit(Instruction::Export(name_idx));
+        }
+        Ok(())
+    }
+
     fn compile_function_declaration(
         &mut self,
         id: &Option<Box<AstNode>>,
@@ -430,9 +1187,15 @@ impl Compiler {
         bytecode.emit(Instruction::LoadConstant(const_idx));
         
         if let Some(func_name) = name {
-            let name_idx = bytecode.add_name(func_name.clone());
-            bytecode.emit(Instruction::StoreGlobal(name_idx));
-            self.declare_variable(&func_name, VarKind::Var)?;
+            // Script-level functions are globals; nested ones are locals of
+            // the enclosing function
+            if self.function_depth > 0 {
+                let var_index = self.declare_variable(&func_name, VarKind::Var)?;
+                bytecode.emit(Instruction::StoreLocal(var_index));
+            } else {
+                let name_idx = bytecode.add_name(func_name.clone());
+                bytecode.emit(Instruction::StoreGlobal(name_idx));
+            }
         }
         
         Ok(())
@@ -481,7 +1244,11 @@ impl Compiler {
         _is_generator: bool,
     ) -> CompileResult<Bytecode> {
         self.function_depth += 1;
+        self.local_counts.push(0);
         self.begin_scope();
+        // Jumps and finalizers never cross into the enclosing function
+        let outer_loops = std::mem::take(&mut self.loop_stack);
+        let outer_try_statements = std::mem::take(&mut self.try_stack);
         
         let mut function_bytecode = Bytecode::new();
         
@@ -491,16 +1258,27 @@ impl Compiler {
                 self.declare_variable(name, VarKind::Var)?;
             }
         }
+        if let AstNode::BlockStatement { body: statements, .. } = body {
+            self.hoist_var_declarations(statements)?;
+        }
         
-        // Compile function body
-        self.compile_statement(body, &mut function_bytecode)?;
+        // Compile function body; an arrow with an expression body returns it
+        if let AstNode::BlockStatement { .. } = body {
+            self.compile_statement(body, &mut function_bytecode)?;
+        } else {
+            self.compile_expression(body, &mut function_bytecode)?;
+            function_bytecode.emit(Instruction::Return);
+        }
         
         // Ensure function returns undefined if no explicit return
         let undefined_idx = function_bytecode.add_constant(Constant::Undefined);
         function_bytecode.emit(Instruction::LoadConstant(undefined_idx));
         function_bytecode.emit(Instruction::Return);
         
+        self.loop_stack = outer_loops;
+        self.try_stack = outer_try_statements;
         self.end_scope();
+        self.local_counts.pop();
         self.function_depth -= 1;
         
         Ok(function_bytecode)
@@ -541,6 +1319,7 @@ impl Compiler {
         self.loop_stack.push(LoopInfo {
             break_jumps: Vec::new(),
             continue_jumps: Vec::new(),
+            try_depth: self.try_stack.len(),
         });
         
         self.compile_expression(test, bytecode)?;
@@ -581,7 +1360,14 @@ impl Compiler {
         
         // Compile initializer
         if let Some(init_stmt) = init {
-            self.compile_statement(init_stmt, bytecode)?;
+            self.hoist_lexical_declarations(std::slice::from_ref(init_stmt), bytecode)?;
+            if let AstNode::VariableDeclaration { .. } = init_stmt {
+                self.compile_statement(init_stmt, bytecode)?;
+            } else {
+                // An initializer expression such as `i = 0, j = n`
+                self.compile_expression(init_stmt, bytecode)?;
+                bytecode.emit(Instruction::Pop);
+            }
         }
         
         let loop_start = bytecode.len();
@@ -589,6 +1375,7 @@ impl Compiler {
         self.loop_stack.push(LoopInfo {
             break_jumps: Vec::new(),
             continue_jumps: Vec::new(),
+            try_depth: self.try_stack.len(),
         });
         
         // Compile test condition
@@ -637,6 +1424,30 @@ impl Compiler {
         Ok(())
     }
 
+    /// Compile `try`/`catch`/`finally`. The block and catch body are not
+    /// marked in the code; entries of the handler table cover them instead.
+    /// The finalizer is emitted on the normal path, on the exception path,
+    /// which rethrows the exception after it, and before each `return`,
+    /// `break` or `continue` that leaves the statement. A `finally` also
+    /// guards the catch body.
+    ///
+    /// ```text
+    /// start:
+    ///     <block>
+    ///     Jump(after_catch)
+    /// catch_handler:                 ; catches start..catch_handler
+    ///     <bind or pop the exception>, <catch body>
+    /// after_catch:
+    ///     <finalizer>
+    ///     Jump(end)
+    /// finally_handler:               ; catches start..after_catch
+    ///     StoreLocal(exception), <finalizer>, LoadLocal(exception)
+    ///     Throw
+    /// end:
+    /// ```
+    ///
+    /// The finalizers a jump out runs are left out of the protected code,
+    /// so an exception they throw goes to the enclosing handlers.
     fn compile_try_statement(
         &mut self,
         block: &AstNode,
@@ -644,49 +1455,120 @@ impl Compiler {
         finalizer: Option<&AstNode>,
         bytecode: &mut Bytecode,
     ) -> CompileResult<()> {
-        let try_begin = bytecode.emit(Instruction::TryBegin(0));
-        
+        self.try_stack.push(TryContext::new(bytecode.len(), finalizer));
         self.compile_statement(block, bytecode)?;
         
-        bytecode.emit(Instruction::TryEnd);
-        
-        let try_end_jump = bytecode.emit(Instruction::Jump(0));
-        
-        // Catch handler
-        let catch_start = bytecode.len();
-        bytecode.patch_jump(try_begin, catch_start);
-        
-        if let Some(catch_clause) = handler {
-            if let AstNode::CatchClause { param, body, .. } = catch_clause {
-                bytecode.emit(Instruction::CatchBegin);
-                
-                // Bind exception to parameter if present
-                if let Some(param_node) = param {
-                    if let AstNode::Identifier { name, .. } = param_node.as_ref() {
-                        let var_index = self.declare_variable(name, VarKind::Let)?;
-                        bytecode.emit(Instruction::StoreLocal(var_index));
-                    }
+        if let Some(AstNode::CatchClause { param, body, .. }) = handler {
+            let try_end_jump = bytecode.emit(Instruction::Jump(0));
+            
+            // The unwinder leaves the exception on the stack
+            let context = self.try_stack.last_mut().expect("pushed above");
+            context.suspend(bytecode.len());
+            for &(start, end) in &context.protected {
+                bytecode.add_handler(start, end, HandlerKind::Catch);
+            }
+            // Only the finalizer guards the catch body
+            if finalizer.is_some() {
+                context.resume(bytecode.len());
+            } else {
+                self.try_stack.pop();
+            }
+            
+            self.begin_scope();
+            match param.as_deref() {
+                Some(AstNode::Identifier { name, .. }) => {
+                    let var_index = self.declare_variable(name, VarKind::Let)?;
+                    bytecode.emit(Instruction::StoreLocal(var_index));
+                }
+                Some(_) => {
+                    return Err(CompileError::UnsupportedFeature("Destructuring catch parameter".to_string()));
+                }
+                None => {
+                    bytecode.emit(Instruction::Pop);
                 }
-                
-                self.compile_statement(body, bytecode)?;
-                
-                bytecode.emit(Instruction::CatchEnd);
             }
+            self.compile_statement(body, bytecode)?;
+            self.end_scope();
+            
+            bytecode.patch_jump(try_end_jump, bytecode.len());
         }
         
-        let catch_end = bytecode.len();
-        bytecode.patch_jump(try_end_jump, catch_end);
-        
-        // Finally block
-        if let Some(finally_stmt) = finalizer {
-            bytecode.emit(Instruction::FinallyBegin);
-            self.compile_statement(finally_stmt, bytecode)?;
-            bytecode.emit(Instruction::FinallyEnd);
+        if let Some(finalizer) = finalizer {
+            let mut context = self.try_stack.pop().expect("pushed above");
+            context.suspend(bytecode.len());
+            self.compile_finalizer(finalizer, bytecode)?;
+            let end_jump = bytecode.emit(Instruction::Jump(0));
+            
+            for &(start, end) in &context.protected {
+                bytecode.add_handler(start, end, HandlerKind::Finally);
+            }
+            let exception = self.hidden_local();
+            bytecode.emit(Instruction::StoreLocal(exception));
+            self.compile_statement(finalizer, bytecode)?;
+            bytecode.emit(Instruction::LoadLocal(exception));
+            bytecode.emit(Instruction::Throw);
+            
+            bytecode.patch_jump(end_jump, bytecode.len());
+        } else if !matches!(handler, Some(AstNode::CatchClause { .. })) {
+            self.try_stack.pop();
         }
         
         Ok(())
     }
 
+    /// Run `finalizer` on a normal or jumping path. A finalizer that
+    /// completes normally leaves the script's completion value as it was.
+    fn compile_finalizer(&mut self, finalizer: &AstNode, bytecode: &mut Bytecode) -> CompileResult<()> {
+        if self.function_depth > 0 {
+            return self.compile_statement(finalizer, bytecode);
+        }
+        let completion = self.hidden_local();
+        bytecode.emit(Instruction::LoadCompletion);
+        bytecode.emit(Instruction::StoreLocal(completion));
+        self.compile_statement(finalizer, bytecode)?;
+        bytecode.emit(Instruction::LoadLocal(completion));
+        bytecode.emit(Instruction::StoreCompletion);
+        Ok(())
+    }
+
+    /// Whether leaving the `try` statements above `depth` runs a finalizer
+    fn has_finalizers(&self, depth: usize) -> bool {
+        self.try_stack[depth..].iter().any(|context| context.finalizer.is_some())
+    }
+
+    /// Compile a jump out of the `try` statements above `depth` up to the
+    /// jump itself: run their finalizers innermost first, each protected
+    /// only by the statements outside its own. Returns the statements for
+    /// `reenter_try_statements` once the jump is emitted.
+    fn leave_try_statements(&mut self, depth: usize, bytecode: &mut Bytecode) -> CompileResult<Vec<TryContext>> {
+        let mut exited = Vec::new();
+        while self.try_stack.len() > depth {
+            let mut context = self.try_stack.pop().expect("checked by the loop");
+            context.suspend(bytecode.len());
+            if let Some(finalizer) = &context.finalizer {
+                let finalizer = finalizer.clone();
+                self.compile_finalizer(&finalizer, bytecode)?;
+            }
+            exited.push(context);
+        }
+        Ok(exited)
+    }
+
+    /// Protect the code after a jump out of `exited` again
+    fn reenter_try_statements(&mut self, exited: Vec<TryContext>, bytecode: &Bytecode) {
+        for mut context in exited.into_iter().rev() {
+            context.resume(bytecode.len());
+            self.try_stack.push(context);
+        }
+    }
+
+    /// A local slot of the current frame for the compiler's own use
+    fn hidden_local(&mut self) -> usize {
+        let count = self.local_counts.last_mut().expect("the script has a frame");
+        *count += 1;
+        *count - 1
+    }
+
     // Scope management
     
     fn begin_scope(&mut self) {
@@ -694,6 +1576,7 @@ impl Compiler {
         self.scopes.push(Scope {
             variables: HashMap::new(),
             depth,
+            frame: self.function_depth,
         });
     }
 
@@ -701,27 +1584,526 @@ impl Compiler {
         self.scopes.pop();
     }
 
+    /// The outermost scope of the function being compiled, where its
+    /// parameters and `var`s live
+    fn function_scope(&self) -> usize {
+        self.scopes
+            .iter()
+            .position(|scope| scope.frame == self.function_depth)
+            .expect("every frame has a scope")
+    }
+
+    /// Give `name` a fresh slot of the current frame: in the function's
+    /// scope for a `var`, in the current block otherwise
     fn declare_variable(&mut self, name: &str, kind: VarKind) -> CompileResult<usize> {
-        if let Some(scope) = self.scopes.last_mut() {
-            let index = scope.variables.len();
-            let variable = Variable {
-                index,
-                kind,
-                is_captured: false,
+        let scope = match kind {
+            VarKind::Var => self.function_scope(),
+            VarKind::Let | VarKind::Const => self.scopes.len() - 1,
+        };
+        let count = self.local_counts.last_mut()
+            .ok_or_else(|| CompileError::InternalError("No scope available".to_string()))?;
+        let index = *count;
+        *count += 1;
+        let variable = Variable {
+            index,
+            initialized: true,
+        };
+        self.scopes[scope].variables.insert(name.to_string(), variable);
+        Ok(index)
+    }
+
+    /// Declare the `var`s of the function or script made of `statements`,
+    /// wherever in its blocks they appear, so that uses before the
+    /// declaration already see its slot
+    fn hoist_var_declarations(&mut self, statements: &[AstNode]) -> CompileResult<()> {
+        // The script's `var`s are globals, which read as undefined until set
+        if self.declares_global(&VarKind::Var) {
+            return Ok(());
+        }
+        let mut names = Vec::new();
+        for statement in statements {
+            collect_var_names(statement, &mut names);
+        }
+        let scope = self.function_scope();
+        for name in names {
+            // Parameters and earlier declarations keep their slot
+            if !self.scopes[scope].variables.contains_key(&name) {
+                self.declare_variable(&name, VarKind::Var)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Declare the `let`s and `const`s of the block made of `statements`
+    /// as it begins. A binding the block may use before its declaration
+    /// runs is put in its temporal dead zone, so that those uses throw.
+    fn hoist_lexical_declarations(&mut self, statements: &[AstNode], bytecode: &mut Bytecode) -> CompileResult<()> {
+        for (position, statement) in statements.iter().enumerate() {
+            let statement = match statement {
+                AstNode::ExportDeclaration { declaration: Some(declaration), specifiers, .. } if specifiers.is_empty() => {
+                    declaration.as_ref()
+                }
+                _ => statement,
             };
-            scope.variables.insert(name.to_string(), variable);
-            Ok(index)
-        } else {
-            Err(CompileError::InternalError("No scope available".to_string()))
+            let AstNode::VariableDeclaration { declarations, kind: kind @ (VarKind::Let | VarKind::Const), .. } = statement else {
+                continue;
+            };
+            for declaration in declarations {
+                let AstNode::VariableDeclarator { id, .. } = declaration else {
+                    continue;
+                };
+                let AstNode::Identifier { name, .. } = id.as_ref() else {
+                    continue;
+                };
+                // Functions may run before the declaration whatever the
+                // order of the script's statements, so a global always
+                // starts out in its dead zone
+                if self.declares_global(kind) {
+                    let name_idx = bytecode.add_name(name.clone());
+                    bytecode.emit(Instruction::MarkGlobalUninitialized(name_idx));
+                    continue;
+                }
+                let index = self.declare_variable(name, kind.clone())?;
+                if let Some(var) = self.scopes.last_mut().and_then(|scope| scope.variables.get_mut(name)) {
+                    var.initialized = false;
+                }
+
+                let initializers = declarations.iter().filter_map(|declaration| match declaration {
+                    AstNode::VariableDeclarator { init, .. } => init.as_deref(),
+                    _ => None,
+                });
+                let used_before = statements[..position].iter().chain(initializers).any(|node| mentions(node, name));
+                if used_before {
+                    bytecode.emit(Instruction::MarkUninitialized(index));
+                }
+            }
         }
+        Ok(())
     }
 
+    /// The variable `name` refers to in the function being compiled.
+    /// Other functions' variables live in other frames, so they are not
+    /// visible here.
     fn resolve_variable(&self, name: &str) -> Option<&Variable> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(var) = scope.variables.get(name) {
-                return Some(var);
+        self.scopes
+            .iter()
+            .rev()
+            .take_while(|scope| scope.frame == self.function_depth)
+            .find_map(|scope| scope.variables.get(name))
+    }
+
+    /// Fail on a use of `name` that would otherwise fall back to a global
+    /// while an enclosing function has a variable of that name. Functions
+    /// do not capture their enclosing frames' variables, so the use cannot
+    /// reach it.
+    fn check_not_captured(&self, name: &str) -> CompileResult<()> {
+        let captured = self.scopes
+            .iter()
+            .any(|scope| scope.frame < self.function_depth && scope.variables.contains_key(name));
+        if captured {
+            return Err(CompileError::UnsupportedFeature(format!(
+                "Closure over '{}' from an enclosing function",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether a declaration of `kind` made here binds a global rather than
+    /// a local slot. The script's top-level `var`s, `let`s and `const`s are
+    /// globals, like its functions, so the functions it declares see them.
+    fn declares_global(&self, kind: &VarKind) -> bool {
+        self.function_depth == 0
+            && match kind {
+                VarKind::Var => true,
+                VarKind::Let | VarKind::Const => self.scopes.len() == 1,
+            }
+    }
+}
+
+/// Names a declaration statement binds
+fn declared_names(declaration: &AstNode) -> Vec<String> {
+    match declaration {
+        AstNode::VariableDeclaration { declarations, .. } => declarations
+            .iter()
+            .filter_map(|declarator| match declarator {
+                AstNode::VariableDeclarator { id, .. } => match id.as_ref() {
+                    AstNode::Identifier { name, .. } => Some(name.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect(),
+        AstNode::FunctionDeclaration { id: Some(id), .. } | AstNode::ClassDeclaration { id: Some(id), .. } => match id.as_ref() {
+            AstNode::Identifier { name, .. } => vec![name.clone()],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Names of the `var`s declared in `node`, outside nested functions
+fn collect_var_names(node: &AstNode, names: &mut Vec<String>) {
+    match node {
+        AstNode::VariableDeclaration { declarations, kind: VarKind::Var, .. } => {
+            for declaration in declarations {
+                if let AstNode::VariableDeclarator { id, .. } = declaration {
+                    if let AstNode::Identifier { name, .. } = id.as_ref() {
+                        names.push(name.clone());
+                    }
+                }
             }
         }
-        None
+        AstNode::FunctionDeclaration { .. } | AstNode::FunctionExpression { .. } | AstNode::ArrowFunctionExpression { .. } => {}
+        _ => {
+            for child in node.children() {
+                collect_var_names(child, names);
+            }
+        }
+    }
+}
+
+/// Whether an identifier `name` appears in `node`, outside nested
+/// functions. Property names count too, which errs on the safe side.
+fn mentions(node: &AstNode, name: &str) -> bool {
+    match node {
+        AstNode::Identifier { name: identifier, .. } => identifier == name,
+        AstNode::FunctionDeclaration { .. } | AstNode::FunctionExpression { .. } | AstNode::ArrowFunctionExpression { .. } => false,
+        _ => node.children().into_iter().any(|child| mentions(child, name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bebion_parser::Parser;
+
+    fn compile(source: &str, opt_level: OptLevel) -> Bytecode {
+        let program = Parser::new().parse(source).unwrap();
+        let mut compiler = Compiler::new();
+        compiler.set_opt_level(opt_level);
+        compiler.compile(&program).unwrap()
+    }
+
+    /// Where the jump at `pc` continues
+    fn jump_target(bytecode: &Bytecode, pc: usize) -> usize {
+        match bytecode.instructions[pc] {
+            Instruction::Jump(offset)
+            | Instruction::JumpIfFalse(offset)
+            | Instruction::JumpIfTrue(offset)
+            | Instruction::JumpIfNullish(offset) => (pc as isize + offset + 1) as usize,
+            ref other => panic!("{:?} at {} is not a jump", other, pc),
+        }
+    }
+
+    #[test]
+    fn optimize_keeps_a_pop_that_a_jump_lands_on() {
+        let bytecode = compile("(a && 1, b);", OptLevel::O1);
+        assert_eq!(
+            bytecode.instructions,
+            [
+                Instruction::LoadGlobal(0),
+                Instruction::Duplicate,
+                Instruction::JumpIfFalse(2),
+                Instruction::Pop,
+                Instruction::LoadConstant(0),
+                Instruction::Pop,
+                Instruction::LoadGlobal(1),
+                Instruction::StoreCompletion,
+                Instruction::LoadCompletion,
+                Instruction::Halt,
+            ]
+        );
+    }
+
+    #[test]
+    fn optimize_relocates_jumps_over_removed_instructions() {
+        let bytecode = compile("if (x) { (1, b); } c;", OptLevel::O1);
+        assert!(!bytecode.instructions.contains(&Instruction::LoadConstant(0)));
+        let target = jump_target(&bytecode, 1);
+        assert_eq!(bytecode.instructions[target], Instruction::LoadGlobal(2));
+    }
+
+    #[test]
+    fn nullish_coalescing_skips_the_right_operand_unless_nullish() {
+        let bytecode = compile("a ?? f();", OptLevel::O0);
+        assert_eq!(
+            bytecode.instructions[..7],
+            [
+                Instruction::LoadGlobal(0),
+                Instruction::Duplicate,
+                Instruction::JumpIfNullish(1),
+                Instruction::Jump(3),
+                Instruction::Pop,
+                Instruction::LoadGlobal(1),
+                Instruction::Call(0),
+            ]
+        );
+        // A non-nullish left value jumps past the call and stays as the result
+        assert_eq!(jump_target(&bytecode, 3), 7);
+        assert_eq!(bytecode.instructions[7], Instruction::StoreCompletion);
+    }
+
+    /// The code of the first function the script declares
+    fn function_code(bytecode: &Bytecode) -> &Bytecode {
+        bytecode
+            .constants
+            .iter()
+            .find_map(|constant| match constant {
+                Constant::Function { bytecode, .. } => Some(bytecode),
+                _ => None,
+            })
+            .expect("the script declares a function")
+    }
+
+    fn handler_ranges(bytecode: &Bytecode) -> Vec<(usize, usize, usize, HandlerKind)> {
+        bytecode
+            .handlers
+            .iter()
+            .map(|handler| (handler.start, handler.end, handler.handler, handler.kind))
+            .collect()
+    }
+
+    #[test]
+    fn return_through_finally_keeps_the_value_in_a_hidden_local() {
+        let bytecode = compile("function f() { try { return g(); } finally { h(); } }", OptLevel::O0);
+        let code = function_code(&bytecode);
+        assert_eq!(
+            code.instructions[..12],
+            [
+                Instruction::LoadGlobal(0),
+                Instruction::Call(0),
+                // The return value waits while the finalizer runs
+                Instruction::StoreLocal(0),
+                Instruction::LoadGlobal(1),
+                Instruction::Call(0),
+                Instruction::Pop,
+                Instruction::LoadLocal(0),
+                Instruction::Return,
+                // The finalizer on the normal path
+                Instruction::LoadGlobal(1),
+                Instruction::Call(0),
+                Instruction::Pop,
+                Instruction::Jump(6),
+            ]
+        );
+        // Only the call is protected; the finalizer a return runs is not
+        assert_eq!(handler_ranges(code), [(0, 3, 12, HandlerKind::Finally)]);
+        assert_eq!(
+            code.instructions[12..18],
+            [
+                Instruction::StoreLocal(1),
+                Instruction::LoadGlobal(1),
+                Instruction::Call(0),
+                Instruction::Pop,
+                Instruction::LoadLocal(1),
+                Instruction::Throw,
+            ]
+        );
+    }
+
+    #[test]
+    fn return_through_nested_finally_runs_the_innermost_first() {
+        let bytecode = compile(
+            "function f() { try { try { return 1; } finally { a(); } } finally { b(); } }",
+            OptLevel::O0,
+        );
+        let code = function_code(&bytecode);
+        let store = code.instructions.iter().position(|i| *i == Instruction::StoreLocal(0)).unwrap();
+        let reload = code.instructions.iter().position(|i| *i == Instruction::LoadLocal(0)).unwrap();
+        assert_eq!(code.instructions[reload + 1], Instruction::Return);
+        let finalizers: Vec<_> = code.instructions[store..reload]
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::LoadGlobal(name) => Some(code.names[*name].as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(finalizers, ["a", "b"]);
+        // The outer statement still protects the inner finalizer, but not
+        // its own
+        assert_eq!(
+            handler_ranges(code),
+            [
+                (0, 2, 14, HandlerKind::Finally),
+                (0, 5, 24, HandlerKind::Finally),
+                (10, 20, 24, HandlerKind::Finally),
+            ]
+        );
+    }
+
+    #[test]
+    fn break_and_continue_run_the_finalizer_outside_the_handler() {
+        let bytecode = compile(
+            "while (x) { try { if (a) break; if (b) continue; } finally { h(); } }",
+            OptLevel::O0,
+        );
+        let instructions = &bytecode.instructions;
+        // Each jump out saves the completion value around its own finalizer
+        assert_eq!(
+            instructions[4..12],
+            [
+                Instruction::LoadCompletion,
+                Instruction::StoreLocal(0),
+                Instruction::LoadGlobal(2),
+                Instruction::Call(0),
+                Instruction::StoreCompletion,
+                Instruction::LoadLocal(0),
+                Instruction::StoreCompletion,
+                Instruction::Jump(25),
+            ]
+        );
+        assert_eq!(instructions[14..16], [Instruction::LoadCompletion, Instruction::StoreLocal(1)]);
+        assert_eq!(instructions[19..21], [Instruction::LoadLocal(1), Instruction::StoreCompletion]);
+        assert_eq!(
+            handler_ranges(&bytecode),
+            [(2, 4, 30, HandlerKind::Finally), (12, 14, 30, HandlerKind::Finally)]
+        );
+        // `break` leaves the loop and `continue` goes back to the test
+        assert_eq!(instructions[jump_target(&bytecode, 11)], Instruction::LoadCompletion);
+        assert_eq!(jump_target(&bytecode, 21), 36);
+        assert_eq!(jump_target(&bytecode, 36), 0);
+    }
+
+    #[test]
+    fn throw_through_finally_rethrows_after_the_finalizer() {
+        let bytecode = compile("try { throw e; } finally { h(); }", OptLevel::O0);
+        assert_eq!(bytecode.instructions[..2], [Instruction::LoadGlobal(0), Instruction::Throw]);
+        assert_eq!(handler_ranges(&bytecode), [(0, 2, 10, HandlerKind::Finally)]);
+        assert_eq!(
+            bytecode.instructions[10..16],
+            [
+                Instruction::StoreLocal(1),
+                Instruction::LoadGlobal(1),
+                Instruction::Call(0),
+                Instruction::StoreCompletion,
+                Instruction::LoadLocal(1),
+                Instruction::Throw,
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_chain_short_circuits_the_rest_of_the_chain() {
+        let bytecode = compile("a?.b.c();", OptLevel::O0);
+        assert_eq!(
+            bytecode.instructions[..9],
+            [
+                Instruction::LoadGlobal(0),
+                Instruction::Duplicate,
+                Instruction::JumpIfNullish(4),
+                Instruction::LoadConstant(0),
+                Instruction::GetProperty,
+                // `.c()` stays a method call on `a.b`
+                Instruction::CallMethod(1, 0),
+                Instruction::Jump(2),
+                // A nullish `a` skips both the lookup and the call
+                Instruction::Pop,
+                Instruction::LoadConstant(1),
+            ]
+        );
+        assert_eq!(jump_target(&bytecode, 2), 7);
+        assert_eq!(jump_target(&bytecode, 6), 9);
+        assert_eq!(bytecode.names[1], "c");
+        assert_eq!(bytecode.constants[1], Constant::Undefined);
+    }
+
+    #[test]
+    fn delete_of_an_optional_chain_is_true_when_it_short_circuits() {
+        let bytecode = compile("delete a?.b;", OptLevel::O0);
+        assert_eq!(
+            bytecode.instructions[..8],
+            [
+                Instruction::LoadGlobal(0),
+                Instruction::Duplicate,
+                Instruction::JumpIfNullish(3),
+                Instruction::LoadConstant(0),
+                Instruction::DeleteProperty,
+                Instruction::Jump(2),
+                Instruction::Pop,
+                Instruction::LoadConstant(1),
+            ]
+        );
+        assert_eq!(jump_target(&bytecode, 2), 6);
+        assert_eq!(jump_target(&bytecode, 5), 8);
+        assert_eq!(bytecode.constants[1], Constant::Boolean(true));
+    }
+
+    #[test]
+    fn script_declarations_are_globals_visible_to_functions() {
+        let bytecode = compile("let n = 0; function g() { n = n + 1; }", OptLevel::O0);
+        assert!(bytecode
+            .instructions
+            .contains(&Instruction::MarkGlobalUninitialized(0)));
+        assert!(bytecode.instructions.contains(&Instruction::DeclareGlobal(0)));
+        let body = function_code(&bytecode);
+        assert_eq!(body.instructions[0], Instruction::LoadGlobal(0));
+        assert!(body.instructions.contains(&Instruction::StoreGlobal(0)));
+        assert_eq!(body.names[0], "n");
+    }
+
+    #[test]
+    fn closing_over_a_function_local_is_rejected() {
+        let program = Parser::new()
+            .parse("function outer(a) { return () => a; }")
+            .unwrap();
+        let error = Compiler::new().compile(&program).unwrap_err();
+        assert!(error.to_string().contains("Closure over 'a'"), "{}", error);
+    }
+
+    #[test]
+    fn optional_and_computed_calls_keep_the_receiver() {
+        let bytecode = compile("a.m?.();", OptLevel::O0);
+        assert_eq!(
+            bytecode.instructions[..13],
+            [
+                Instruction::LoadGlobal(0),
+                Instruction::Duplicate,
+                Instruction::LoadConstant(0),
+                Instruction::GetProperty,
+                Instruction::Duplicate,
+                Instruction::JumpIfNullish(2),
+                Instruction::CallWithReceiver(0),
+                Instruction::Jump(2),
+                Instruction::Pop,
+                Instruction::Jump(1),
+                Instruction::Jump(2),
+                Instruction::Pop,
+                Instruction::LoadConstant(1),
+            ]
+        );
+        // A nullish method drops the receiver before the chain's exit
+        assert_eq!(jump_target(&bytecode, 5), 8);
+        assert_eq!(jump_target(&bytecode, 9), 11);
+
+        let bytecode = compile("a[k](1);", OptLevel::O0);
+        assert_eq!(
+            bytecode.instructions[..6],
+            [
+                Instruction::LoadGlobal(0),
+                Instruction::Duplicate,
+                Instruction::LoadGlobal(1),
+                Instruction::GetElement,
+                Instruction::LoadConstant(0),
+                Instruction::CallWithReceiver(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn constants_are_deduplicated_by_value() {
+        let mut bytecode = Bytecode::new();
+        let one = bytecode.add_constant(Constant::Number(1.0));
+        let zero = bytecode.add_constant(Constant::Number(0.0));
+        let nan = bytecode.add_constant(Constant::Number(f64::NAN));
+        assert_eq!(bytecode.add_constant(Constant::Number(1.0)), one);
+        assert_ne!(bytecode.add_constant(Constant::Number(-0.0)), zero);
+        assert_eq!(bytecode.add_constant(Constant::Number(f64::NAN)), nan);
+
+        // Constants pushed without `add_constant` are found too
+        bytecode.constants.push(Constant::String("pushed".into()));
+        let pushed = bytecode.constants.len() - 1;
+        assert_eq!(bytecode.add_constant(Constant::String("pushed".into())), pushed);
+        assert_eq!(bytecode.deduplicated_constants, 3);
     }
 }
@@ -0,0 +1,83 @@
+//! Compile-time string folding
+//!
+//! Evaluates string concatenations and template literals whose operands are
+//! all literals, so `"a" + "b"` and `` `x${1}y` `` compile to a single
+//! constant. Only concatenations involving a string are folded; numeric
+//! arithmetic is left to the VM.
+
+use bebion_parser::ast::{AstNode, BinaryOperator, LiteralValue};
+
+enum Primitive {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    Null,
+    Undefined,
+}
+
+/// The string `node` always evaluates to, if it is a concatenation or
+/// template literal of constants
+pub fn constant_string(node: &AstNode) -> Option<String> {
+    match node {
+        AstNode::BinaryExpression { operator: BinaryOperator::Add, .. } | AstNode::TemplateLiteral { .. } => {
+            match evaluate(node)? {
+                Primitive::String(s) => Some(s),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The text a template substitution of `node` produces, if it is constant
+pub fn constant_text(node: &AstNode) -> Option<String> {
+    evaluate(node).and_then(to_string)
+}
+
+fn evaluate(node: &AstNode) -> Option<Primitive> {
+    match node {
+        AstNode::Literal { value, .. } => match value {
+            LiteralValue::String(s) => Some(Primitive::String(s.clone())),
+            LiteralValue::Number(n) => Some(Primitive::Number(*n)),
+            LiteralValue::Boolean(b) => Some(Primitive::Boolean(*b)),
+            LiteralValue::Null => Some(Primitive::Null),
+            LiteralValue::Undefined => Some(Primitive::Undefined),
+            LiteralValue::RegExp { .. } => None,
+        },
+        AstNode::TemplateLiteral { quasis, expressions, .. } => {
+            let mut text = String::new();
+            for (i, quasi) in quasis.iter().enumerate() {
+                if let AstNode::Literal { value: LiteralValue::String(s), .. } = quasi {
+                    text.push_str(s);
+                }
+                if let Some(expression) = expressions.get(i) {
+                    text.push_str(&constant_text(expression)?);
+                }
+            }
+            Some(Primitive::String(text))
+        }
+        AstNode::BinaryExpression { operator: BinaryOperator::Add, left, right, .. } => {
+            let (left, right) = (evaluate(left)?, evaluate(right)?);
+            if !matches!(left, Primitive::String(_)) && !matches!(right, Primitive::String(_)) {
+                return None;
+            }
+            Some(Primitive::String(to_string(left)? + &to_string(right)?))
+        }
+        _ => None,
+    }
+}
+
+/// ToString for the primitives whose text is certain; numbers are limited
+/// to integers short enough to print without an exponent
+fn to_string(value: Primitive) -> Option<String> {
+    match value {
+        Primitive::String(s) => Some(s),
+        // The literal pattern compares with ==, so -0 prints as "0" too
+        Primitive::Number(0.0) => Some("0".to_string()),
+        Primitive::Number(n) if n.fract() == 0.0 && n.abs() < 1e21 => Some(format!("{}", n)),
+        Primitive::Number(_) => None,
+        Primitive::Boolean(b) => Some(b.to_string()),
+        Primitive::Null => Some("null".to_string()),
+        Primitive::Undefined => Some("undefined".to_string()),
+    }
+}
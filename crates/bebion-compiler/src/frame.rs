@@ -0,0 +1,114 @@
+//! Frame size analysis
+//!
+//! Runs last, on the bytecode the VM will execute, and records for the
+//! script and every nested function how many local slots it uses and how
+//! deep its operand stack gets, so the VM can size each frame once on entry
+//! instead of growing it instruction by instruction. The same pass records
+//! the stack height each exception handler unwinds to.
+
+use crate::bytecode::{Bytecode, Constant, ExceptionHandler, Instruction};
+
+/// Fill in `local_count` and `max_stack_depth` of `bytecode` and the
+/// functions nested in it, and the `stack_depth` of their handlers
+pub fn compute_frame_sizes(bytecode: &mut Bytecode) {
+    for constant in &mut bytecode.constants {
+        if let Constant::Function { bytecode, .. } = constant {
+            compute_frame_sizes(bytecode);
+        }
+    }
+    bytecode.local_count = local_count(&bytecode.instructions);
+    bytecode.max_stack_depth = max_stack_depth(&bytecode.instructions, &mut bytecode.handlers);
+}
+
+fn local_count(instructions: &[Instruction]) -> usize {
+    instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::LoadLocal(slot)
+            | Instruction::StoreLocal(slot)
+            | Instruction::DeclareVar(slot)
+            | Instruction::DeclareLet(slot)
+            | Instruction::DeclareConst(slot)
+            | Instruction::MarkUninitialized(slot)
+            | Instruction::CheckInitialized(slot, _) => Some(slot + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// The most values the code keeps on its frame's part of the operand stack,
+/// following every branch and exception handler from the first instruction.
+/// The compiler leaves the stack the same height on every path into an
+/// instruction, so each one is visited once.
+fn max_stack_depth(instructions: &[Instruction], handlers: &mut [ExceptionHandler]) -> usize {
+    let mut depths: Vec<Option<usize>> = vec![None; instructions.len()];
+    let mut pending = vec![(0, 0)];
+    let mut max_depth = 0;
+
+    while let Some((pc, depth)) = pending.pop() {
+        let Some(instruction) = instructions.get(pc) else {
+            continue;
+        };
+        if depths[pc].is_some() {
+            continue;
+        }
+        depths[pc] = Some(depth);
+
+        // A handler starts with the exception on the stack its protected
+        // code was entered with
+        for handler in handlers.iter_mut().filter(|handler| handler.start == pc) {
+            handler.stack_depth = depth;
+            pending.push((handler.handler, depth + 1));
+            max_depth = max_depth.max(depth + 1);
+        }
+
+        let (pops, pushes) = stack_effect(instruction);
+        let after = depth.saturating_sub(pops) + pushes;
+        max_depth = max_depth.max(depth).max(after);
+
+        let target = |offset: isize| (pc as isize + offset + 1) as usize;
+        match instruction {
+            Instruction::Jump(offset) => pending.push((target(*offset), after)),
+            Instruction::JumpIfFalse(offset) | Instruction::JumpIfTrue(offset) | Instruction::JumpIfNullish(offset) => {
+                pending.push((target(*offset), after));
+                pending.push((pc + 1, after));
+            }
+            Instruction::Return | Instruction::Throw | Instruction::Halt => {}
+            _ => pending.push((pc + 1, after)),
+        }
+    }
+    max_depth
+}
+
+/// How many values `instruction` pops and then pushes
+fn stack_effect(instruction: &Instruction) -> (usize, usize) {
+    use Instruction::*;
+    match instruction {
+        LoadConstant(_) | LoadGlobal(_) | LoadLocal(_) | LoadCompletion | LoadThis | NewObject | ImportMeta(_) => (0, 1),
+        StoreGlobal(_) | StoreLocal(_) | DeclareVar(_) | DeclareLet(_) | DeclareConst(_) | DeclareGlobal(_) => (1, 0),
+        Add | Subtract | Multiply | Divide | Modulo | Power
+        | Equal | NotEqual | StrictEqual | StrictNotEqual
+        | Less | LessEqual | Greater | GreaterEqual
+        | LogicalAnd | LogicalOr
+        | BitwiseAnd | BitwiseOr | BitwiseXor
+        | LeftShift | RightShift | UnsignedRightShift => (2, 1),
+        LogicalNot | BitwiseNot | UnaryPlus | UnaryMinus | TypeOf => (1, 1),
+        JumpIfFalse(_) | JumpIfTrue(_) | JumpIfNullish(_) => (1, 0),
+        Call(arg_count) | CallMethod(_, arg_count) => (arg_count + 1, 1),
+        CallWithReceiver(arg_count) => (arg_count + 2, 1),
+        Return | Throw | StoreCompletion | Pop | Export(_) => (1, 0),
+        Await | Import(_) => (1, 1),
+        GetProperty | GetElement | DeleteProperty | HasProperty | InstanceOf => (2, 1),
+        SetProperty | SetElement => (3, 1),
+        DefineGetter | DefineSetter => (3, 0),
+        CopyDataProperties => (2, 0),
+        NewArray(count) => (*count, 1),
+        ArrayAppend | ArraySpread => (1, 0),
+        Duplicate => (1, 2),
+        Swap => (2, 2),
+        Rot(count) => (*count, *count),
+        Pick(depth) => (depth + 1, depth + 2),
+        Jump(_) | Nop | Halt | DebugInfo(..) | MarkUninitialized(_) | CheckInitialized(..) | MarkGlobalUninitialized(_) => (0, 0),
+    }
+}
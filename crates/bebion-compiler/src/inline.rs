@@ -0,0 +1,321 @@
+//! Inlining of tiny functions
+//!
+//! Runs on the script's bytecode once every top-level function declaration
+//! and call site is known. A function is inlined when it is declared once,
+//! never reassigned, is neither async nor a generator, creates no closures,
+//! does not refer to itself, has no exception handlers and its body is
+//! straight-line code under the size threshold. Call sites whose arguments are plain loads have the call
+//! replaced by the body, with the callee's locals moved into fresh slots of
+//! the caller's frame.
+
+use crate::bytecode::{Bytecode, Constant, Instruction};
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Largest function body, in instructions, that is copied into call sites
+pub const DEFAULT_INLINE_THRESHOLD: usize = 16;
+
+/// What an inlining pass did, for `-O2` diagnostics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InlineStats {
+    /// Functions that qualified for inlining
+    pub candidates: usize,
+    /// Call sites replaced by the callee's body
+    pub call_sites: usize,
+    /// Instruction count before and after the pass
+    pub instructions_before: usize,
+    pub instructions_after: usize,
+}
+
+/// A function body ready to be copied into a call site
+struct Inlinee {
+    /// Index of the declaration's store; earlier calls see `undefined`
+    declared_at: usize,
+    param_count: usize,
+    body: Vec<Instruction>,
+    /// Whether the body ends in a `return` that leaves a value on the stack
+    returns_value: bool,
+    constants: Vec<Constant>,
+    names: Vec<String>,
+}
+
+/// Inline calls to small functions declared in `bytecode`
+pub fn inline_small_functions(bytecode: &mut Bytecode, threshold: usize) -> InlineStats {
+    let mut stats = InlineStats {
+        instructions_before: bytecode.len(),
+        ..InlineStats::default()
+    };
+
+    let inlinees = collect_inlinees(bytecode, threshold);
+    stats.candidates = inlinees.len();
+    if inlinees.is_empty() {
+        stats.instructions_after = bytecode.len();
+        return stats;
+    }
+
+    // Slots past every local the script already uses. Inlined bodies run to
+    // completion one at a time, so every call site can share them.
+    let local_base = bytecode
+        .instructions
+        .iter()
+        .filter_map(local_slot)
+        .max()
+        .map_or(0, |slot| slot + 1);
+
+    let old = std::mem::take(&mut bytecode.instructions);
+    let mut instructions = Vec::with_capacity(old.len());
+    let mut index_map = Vec::with_capacity(old.len() + 1);
+    let mut constant_map: HashMap<(usize, usize), usize> = HashMap::new();
+
+    let mut i = 0;
+    while i < old.len() {
+        let site = match &old[i] {
+            Instruction::LoadGlobal(name_idx) => bytecode
+                .names
+                .get(*name_idx)
+                .and_then(|name| inlinees.get(name))
+                .filter(|(_, inlinee)| i > inlinee.declared_at)
+                .and_then(|(id, inlinee)| call_arguments(&old, i).map(|args| (*id, inlinee, args))),
+            _ => None,
+        };
+
+        let Some((id, inlinee, args)) = site else {
+            index_map.push(instructions.len());
+            instructions.push(old[i].clone());
+            i += 1;
+            continue;
+        };
+
+        // Everything from the callee load through the call maps to the start
+        // of the inlined sequence
+        let start = instructions.len();
+        for _ in 0..=args.len() + 1 {
+            index_map.push(start);
+        }
+
+        instructions.extend(args.iter().cloned());
+        let argc = args.len();
+        for slot in (0..argc).rev() {
+            if slot < inlinee.param_count {
+                instructions.push(Instruction::StoreLocal(local_base + slot));
+            } else {
+                instructions.push(Instruction::Pop);
+            }
+        }
+        if argc < inlinee.param_count {
+            let undefined = bytecode.add_constant(Constant::Undefined);
+            for slot in argc..inlinee.param_count {
+                instructions.push(Instruction::LoadConstant(undefined));
+                instructions.push(Instruction::StoreLocal(local_base + slot));
+            }
+        }
+
+        for instruction in &inlinee.body {
+            let relocated = match instruction {
+                Instruction::LoadLocal(slot) => Instruction::LoadLocal(local_base + slot),
+                Instruction::StoreLocal(slot) => Instruction::StoreLocal(local_base + slot),
+                Instruction::DeclareVar(slot) => Instruction::DeclareVar(local_base + slot),
+                Instruction::DeclareLet(slot) => Instruction::DeclareLet(local_base + slot),
+                Instruction::DeclareConst(slot) => Instruction::DeclareConst(local_base + slot),
+                Instruction::LoadConstant(idx) => {
+                    let constant = *constant_map
+                        .entry((id, *idx))
+                        .or_insert_with(|| bytecode.add_constant(inlinee.constants[*idx].clone()));
+                    Instruction::LoadConstant(constant)
+                }
+                Instruction::LoadGlobal(idx) => Instruction::LoadGlobal(bytecode.add_name(inlinee.names[*idx].clone())),
+                Instruction::StoreGlobal(idx) => Instruction::StoreGlobal(bytecode.add_name(inlinee.names[*idx].clone())),
+                Instruction::CallMethod(idx, argc) => {
+                    Instruction::CallMethod(bytecode.add_name(inlinee.names[*idx].clone()), *argc)
+                }
+                other => other.clone(),
+            };
+            instructions.push(relocated);
+        }
+        if !inlinee.returns_value {
+            let undefined = bytecode.add_constant(Constant::Undefined);
+            instructions.push(Instruction::LoadConstant(undefined));
+        }
+
+        stats.call_sites += 1;
+        i += args.len() + 2;
+    }
+    index_map.push(instructions.len());
+
+    relocate_jumps(&old, &mut instructions, &index_map);
+    for handler in &mut bytecode.handlers {
+        handler.start = index_map[handler.start];
+        handler.end = index_map[handler.end];
+        handler.handler = index_map[handler.handler];
+    }
+    bytecode.source_map = bytecode
+        .source_map
+        .iter()
+        .filter_map(|(&idx, &position)| index_map.get(idx).map(|&new_idx| (new_idx, position)))
+        .collect();
+    bytecode.instructions = instructions;
+
+    stats.instructions_after = bytecode.len();
+    debug!(
+        "Inlined {} call sites of {} functions ({} -> {} instructions)",
+        stats.call_sites, stats.candidates, stats.instructions_before, stats.instructions_after
+    );
+    stats
+}
+
+/// Find the functions declared at the top level that qualify for inlining,
+/// keyed by name
+fn collect_inlinees(bytecode: &Bytecode, threshold: usize) -> HashMap<String, (usize, Inlinee)> {
+    let mut stores: HashMap<&str, usize> = HashMap::new();
+    count_global_stores(bytecode, &mut stores);
+
+    let mut inlinees = HashMap::new();
+    for (idx, pair) in bytecode.instructions.windows(2).enumerate() {
+        let [Instruction::LoadConstant(const_idx), Instruction::StoreGlobal(name_idx)] = pair else {
+            continue;
+        };
+        let (Some(constant), Some(name)) = (bytecode.constants.get(*const_idx), bytecode.names.get(*name_idx)) else {
+            continue;
+        };
+        if stores.get(name.as_str()) != Some(&1) {
+            continue;
+        }
+        if let Some(inlinee) = inlinee(name, constant, idx + 1, threshold) {
+            let id = inlinees.len();
+            inlinees.insert(name.clone(), (id, inlinee));
+        }
+    }
+    inlinees
+}
+
+/// Count assignments to each global, including those made inside functions
+fn count_global_stores<'a>(bytecode: &'a Bytecode, stores: &mut HashMap<&'a str, usize>) {
+    for instruction in &bytecode.instructions {
+        if let Instruction::StoreGlobal(idx) | Instruction::DeclareGlobal(idx) = instruction {
+            if let Some(name) = bytecode.names.get(*idx) {
+                *stores.entry(name.as_str()).or_default() += 1;
+            }
+        }
+    }
+    for constant in &bytecode.constants {
+        if let Constant::Function { bytecode, .. } = constant {
+            count_global_stores(bytecode, stores);
+        }
+    }
+}
+
+fn inlinee(name: &str, constant: &Constant, declared_at: usize, threshold: usize) -> Option<Inlinee> {
+    let Constant::Function { param_count, bytecode, is_async: false, is_generator: false, .. } = constant else {
+        return None;
+    };
+    if !bytecode.handlers.is_empty() {
+        return None;
+    }
+
+    // Straight-line code stops at the first return; without one the body
+    // falls off the end
+    let end = bytecode.instructions.iter().position(|i| matches!(i, Instruction::Return));
+    let body = &bytecode.instructions[..end.unwrap_or(bytecode.instructions.len())];
+    if body.len() > threshold {
+        return None;
+    }
+
+    let refers_to_self = |idx: &usize| bytecode.names.get(*idx).map(String::as_str) == Some(name);
+    let inlinable = body.iter().all(|instruction| match instruction {
+        Instruction::LoadConstant(idx) => !matches!(bytecode.constants.get(*idx), Some(Constant::Function { .. }) | None),
+        Instruction::LoadGlobal(idx) | Instruction::StoreGlobal(idx) => !refers_to_self(idx),
+        instruction => is_straight_line(instruction),
+    });
+    if !inlinable {
+        return None;
+    }
+
+    Some(Inlinee {
+        declared_at,
+        param_count: *param_count,
+        body: body.to_vec(),
+        returns_value: end.is_some(),
+        constants: bytecode.constants.clone(),
+        names: bytecode.names.clone(),
+    })
+}
+
+/// Instructions that can be copied into another frame without changing
+/// behavior: no control flow, suspension or frame-level state
+fn is_straight_line(instruction: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        LoadLocal(_) | StoreLocal(_)
+            | Add | Subtract | Multiply | Divide | Modulo | Power
+            | Equal | NotEqual | StrictEqual | StrictNotEqual
+            | Less | LessEqual | Greater | GreaterEqual
+            | LogicalAnd | LogicalOr | LogicalNot
+            | BitwiseAnd | BitwiseOr | BitwiseXor | BitwiseNot
+            | LeftShift | RightShift | UnsignedRightShift
+            | UnaryPlus | UnaryMinus | TypeOf
+            | Call(_) | CallMethod(..) | CallWithReceiver(_)
+            | NewObject | GetProperty | SetProperty | GetElement | SetElement
+            | DeleteProperty | HasProperty | InstanceOf | CopyDataProperties
+            | NewArray(_) | ArrayAppend | ArraySpread
+            | DeclareVar(_) | DeclareLet(_) | DeclareConst(_)
+            | Pop | Duplicate | Swap | Rot(_) | Pick(_) | Nop | DebugInfo(..)
+    )
+}
+
+/// The argument loads of a call whose callee is loaded at `callee`, if every
+/// argument is a single side-effect-free load
+fn call_arguments(instructions: &[Instruction], callee: usize) -> Option<&[Instruction]> {
+    let mut end = callee + 1;
+    while let Some(Instruction::LoadConstant(_) | Instruction::LoadLocal(_) | Instruction::LoadGlobal(_)) =
+        instructions.get(end)
+    {
+        end += 1;
+    }
+
+    // Loads beyond the argument count belong to something else on the stack,
+    // so look for the call that consumes exactly the loads before it
+    while end > callee {
+        if instructions.get(end) == Some(&Instruction::Call(end - callee - 1)) {
+            return Some(&instructions[callee + 1..end]);
+        }
+        end -= 1;
+    }
+    None
+}
+
+fn local_slot(instruction: &Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::LoadLocal(slot)
+        | Instruction::StoreLocal(slot)
+        | Instruction::DeclareVar(slot)
+        | Instruction::DeclareLet(slot)
+        | Instruction::DeclareConst(slot) => Some(*slot),
+        _ => None,
+    }
+}
+
+/// Rewrite relative jump offsets after instructions moved. `index_map` maps
+/// each old instruction index (plus one past the end) to its new index.
+pub(crate) fn relocate_jumps(old: &[Instruction], instructions: &mut [Instruction], index_map: &[usize]) {
+    for (old_idx, instruction) in old.iter().enumerate() {
+        let offset = match instruction {
+            Instruction::Jump(offset)
+            | Instruction::JumpIfFalse(offset)
+            | Instruction::JumpIfTrue(offset)
+            | Instruction::JumpIfNullish(offset) => *offset,
+            _ => continue,
+        };
+
+        let target = (old_idx as isize + offset + 1).clamp(0, old.len() as isize) as usize;
+        let new_idx = index_map[old_idx];
+        let new_offset = index_map[target] as isize - new_idx as isize - 1;
+        match &mut instructions[new_idx] {
+            Instruction::Jump(offset)
+            | Instruction::JumpIfFalse(offset)
+            | Instruction::JumpIfTrue(offset)
+            | Instruction::JumpIfNullish(offset) => *offset = new_offset,
+            _ => {}
+        }
+    }
+}
@@ -4,9 +4,14 @@
 
 pub mod bytecode;
 pub mod compiler;
+pub mod fold;
+pub mod frame;
+pub mod inline;
+pub mod source_map;
 
-pub use compiler::Compiler;
-pub use bytecode::{Instruction, Bytecode};
+pub use compiler::{Compiler, OptLevel};
+pub use source_map::SourceMap;
+pub use bytecode::{Instruction, Bytecode, BytecodeModule, ConstantPoolStats, ExceptionHandler, HandlerKind};
 
 use std::fmt;
 
@@ -0,0 +1,105 @@
+//! Standard (version 3) JSON source maps for compiled bytecode
+//!
+//! Bytecode has no text to point into, so the map treats each code object
+//! as a generated line and each instruction index as a column: line 0 is
+//! the script, and the functions nested in it follow depth-first in the
+//! order of their constants. A segment maps the first instruction of each
+//! statement to where the statement starts in the source.
+
+use crate::bytecode::{Bytecode, Constant};
+use serde::Serialize;
+
+/// A source map as tools read it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceMap {
+    pub version: u8,
+    /// The bytecode file the map describes
+    pub file: String,
+    pub sources: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sources_content: Option<Vec<String>>,
+    pub names: Vec<String>,
+    pub mappings: String,
+}
+
+impl SourceMap {
+    /// The map of `bytecode`, compiled from the file `source`, to `file`.
+    /// `content` embeds the source text, for tools without the file.
+    pub fn new(bytecode: &Bytecode, file: &str, source: &str, content: Option<&str>) -> Self {
+        let mut code_objects = Vec::new();
+        collect_code_objects(bytecode, &mut code_objects);
+
+        let mut encoder = Encoder::default();
+        let lines: Vec<String> = code_objects.iter().map(|code| encoder.line(code)).collect();
+
+        Self {
+            version: 3,
+            file: file.to_string(),
+            sources: vec![source.to_string()],
+            sources_content: content.map(|content| vec![content.to_string()]),
+            names: Vec::new(),
+            mappings: lines.join(";"),
+        }
+    }
+}
+
+/// `bytecode` and its nested functions, depth-first
+fn collect_code_objects<'a>(bytecode: &'a Bytecode, code_objects: &mut Vec<&'a Bytecode>) {
+    code_objects.push(bytecode);
+    for constant in &bytecode.constants {
+        if let Constant::Function { bytecode, .. } = constant {
+            collect_code_objects(bytecode, code_objects);
+        }
+    }
+}
+
+/// Encodes segments relative to the previous one. Only the generated
+/// column starts over on each line.
+#[derive(Default)]
+struct Encoder {
+    line: i64,
+    column: i64,
+}
+
+impl Encoder {
+    fn line(&mut self, bytecode: &Bytecode) -> String {
+        let mut previous_index = 0;
+        let segments: Vec<String> = bytecode
+            .source_map
+            .iter()
+            .map(|(&index, &(line, column))| {
+                // Source positions are 1-based; source maps count from 0
+                let (line, column) = (line.saturating_sub(1) as i64, column.saturating_sub(1) as i64);
+                let mut segment = String::new();
+                encode_vlq(index as i64 - previous_index, &mut segment);
+                encode_vlq(0, &mut segment);
+                encode_vlq(line - self.line, &mut segment);
+                encode_vlq(column - self.column, &mut segment);
+                previous_index = index as i64;
+                (self.line, self.column) = (line, column);
+                segment
+            })
+            .collect();
+        segments.join(",")
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Append `value` as a base64 VLQ: the sign in the lowest bit, then five
+/// bits per digit with a continuation bit
+fn encode_vlq(value: i64, out: &mut String) {
+    let mut vlq = if value < 0 { ((-value) << 1) | 1 } else { value << 1 } as u64;
+    loop {
+        let mut digit = (vlq & 0b11111) as usize;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
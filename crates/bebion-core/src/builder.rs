@@ -0,0 +1,39 @@
+//! Configuring an engine before it starts
+//!
+//! `BebionEngine::builder()` gathers the settings that should hold from the
+//! first allocation on, such as heap limits, where calling setters on a
+//! running engine would be too late.
+
+use crate::{BebionEngine, BebionError};
+use bebion_gc::GcConfig;
+
+/// Settings for a new `BebionEngine`
+#[derive(Debug, Clone, Default)]
+pub struct EngineBuilder {
+    gc_config: GcConfig,
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the garbage collector as `config` says
+    pub fn gc_config(mut self, config: GcConfig) -> Self {
+        self.gc_config = config;
+        self
+    }
+
+    /// Fail scripts with an out-of-memory error once the heap holds more
+    /// than `bytes` after a full collection
+    pub fn max_heap_size(mut self, bytes: usize) -> Self {
+        self.gc_config.max_heap_size = Some(bytes);
+        self
+    }
+
+    pub fn build(self) -> Result<BebionEngine, BebionError> {
+        let mut engine = BebionEngine::new()?;
+        engine.set_gc_config(self.gc_config);
+        Ok(engine)
+    }
+}
@@ -0,0 +1,72 @@
+//! Notifications for hosts embedding the engine
+//!
+//! Editors, dashboards and other UIs register an `EngineObserver` to hear
+//! what the engine is doing instead of parsing its logs.
+
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// Something that happened in the engine
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    /// Compiled code is about to run
+    ScriptStarted,
+    /// A script and the jobs and events it queued ran to completion
+    ScriptFinished { elapsed: Duration },
+    /// A script threw an error nothing caught
+    UncaughtError { message: String },
+    /// A promise was rejected and no handler was attached to it by the end
+    /// of the event loop tick
+    UnhandledRejection { reason: String },
+    /// Live heap bytes passed the engine's pressure threshold; sent again
+    /// only after the heap has shrunk back below it
+    GcPressure { live_bytes: usize, threshold: usize },
+    /// A module ran for the first time
+    ModuleLoaded { url: String },
+    /// A module accepted a change to its code and ran again in place
+    ModuleReloaded { url: String },
+}
+
+/// What the engine does about promises rejected with no handler by the
+/// end of a tick, as in `--unhandled-rejections`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnhandledRejections {
+    /// Send observers `UnhandledRejection`, which the CLI prints as a
+    /// warning
+    #[default]
+    Warn,
+    /// Also fail the run, as an uncaught exception would
+    Strict,
+    /// Ignore them
+    None,
+}
+
+impl UnhandledRejections {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "warn" => Some(UnhandledRejections::Warn),
+            "strict" => Some(UnhandledRejections::Strict),
+            "none" => Some(UnhandledRejections::None),
+            _ => None,
+        }
+    }
+}
+
+/// Receives the engine's events as they happen
+pub trait EngineObserver: Send {
+    fn on_event(&mut self, event: &EngineEvent);
+}
+
+impl<F: FnMut(&EngineEvent) + Send> EngineObserver for F {
+    fn on_event(&mut self, event: &EngineEvent) {
+        self(event)
+    }
+}
+
+/// Forwards events to a channel, for hosts that handle them on another
+/// thread. Events sent after the receiver is dropped are discarded.
+impl EngineObserver for Sender<EngineEvent> {
+    fn on_event(&mut self, event: &EngineEvent) {
+        let _ = self.send(event.clone());
+    }
+}
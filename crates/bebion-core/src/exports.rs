@@ -0,0 +1,52 @@
+//! What modules export
+//!
+//! A module's `export`s hand their values to the engine as the module runs,
+//! `export default` under the name `default`. They are kept here by the
+//! module's URL, alive for as long as the engine, and read back when other
+//! modules import them.
+
+use bebion_gc::GcHandle;
+use bebion_runtime::{Runtime, RuntimeError, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub(crate) type SharedExports = Arc<Mutex<ModuleExports>>;
+
+/// The exports of each module run so far, by module URL
+#[derive(Default)]
+pub(crate) struct ModuleExports {
+    modules: HashMap<String, HashMap<String, Value>>,
+}
+
+impl ModuleExports {
+    /// What the module at `url` exported, by name
+    pub(crate) fn of(&self, url: &str) -> HashMap<String, Value> {
+        self.modules.get(url).cloned().unwrap_or_default()
+    }
+
+    /// Forget what the module at `url` exported, before it runs again
+    pub(crate) fn clear(&mut self, url: &str) {
+        self.modules.remove(url);
+    }
+
+    fn trace(&self, roots: &mut Vec<GcHandle>) {
+        let values = self.modules.values().flat_map(HashMap::values);
+        roots.extend(values.filter_map(Value::as_handle));
+    }
+}
+
+/// Record the exports of the module running now on `runtime`, and keep
+/// them alive
+pub(crate) fn install(runtime: &mut Runtime, exports: &SharedExports, current_module: Arc<Mutex<Option<String>>>) {
+    let recorded = Arc::clone(exports);
+    runtime.set_export_hook(move |_, name, value| {
+        let Some(url) = current_module.lock().unwrap().clone() else {
+            return Err(RuntimeError::SyntaxError(format!("Cannot export '{}' outside a module", name)));
+        };
+        recorded.lock().unwrap().modules.entry(url).or_default().insert(name.to_string(), value);
+        Ok(())
+    });
+
+    let traced = Arc::clone(exports);
+    runtime.add_root_source(move |roots| traced.lock().unwrap().trace(roots));
+}
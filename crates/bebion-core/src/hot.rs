@@ -0,0 +1,188 @@
+//! Hot module replacement
+//!
+//! Each module's `import.meta` has a `hot` object through which the module
+//! takes updates to itself while the program keeps running:
+//!
+//! - `import.meta.hot.accept(callback)` says the module can be replaced in
+//!   place. When its file changes, `BebionEngine::reload_module` runs the
+//!   new version, rebinds the names other modules imported from it, and
+//!   calls `callback` with an object of the new version's exports. The
+//!   callback is optional.
+//! - `import.meta.hot.dispose(callback)` registers cleanup for the old
+//!   version, such as clearing its timers, run just before the new one.
+//!   The callback gets an object it can leave state on.
+//! - `import.meta.hot.data` is that object in the new version, and an
+//!   empty object before the first update.
+//!
+//! Only the changed module runs again; the modules it imports keep their
+//! state. A module that never called `accept` declines updates, and the
+//! host restarts the program instead.
+
+use bebion_gc::GcHandle;
+use bebion_runtime::{NativeFunction, Runtime, RuntimeError, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub(crate) type SharedHotModules = Arc<Mutex<HotModules>>;
+
+/// The update handlers and bindings of each module, by module URL
+#[derive(Default)]
+pub(crate) struct HotModules {
+    modules: HashMap<String, HotModule>,
+}
+
+#[derive(Default)]
+struct HotModule {
+    /// The module's `import.meta`, made the first time it is read
+    meta: Option<Value>,
+    /// `import.meta.hot.data`
+    data: Option<Value>,
+    /// Whether the module called `import.meta.hot.accept`
+    accepted: bool,
+    accept_callbacks: Vec<Value>,
+    dispose_callbacks: Vec<Value>,
+    /// Globals that other modules' default imports bound to this module's
+    /// exports, with the export each holds
+    bindings: Vec<(String, String)>,
+    /// The old version's callbacks and the values handed to them, kept
+    /// alive while an update runs
+    updating: Vec<Value>,
+}
+
+/// The callbacks the old version of a module registered, which an update
+/// runs
+pub(crate) struct Handlers {
+    pub(crate) accept: Vec<Value>,
+    pub(crate) dispose: Vec<Value>,
+}
+
+impl HotModules {
+    /// Whether the module at `url` accepts updates to itself
+    pub(crate) fn accepts(&self, url: &str) -> bool {
+        self.modules.get(url).is_some_and(|module| module.accepted)
+    }
+
+    /// Record that the global `name` holds the export `export` of the
+    /// module at `url`, so an update rebinds it
+    pub(crate) fn bind(&mut self, url: &str, name: &str, export: &str) {
+        let binding = (name.to_string(), export.to_string());
+        let bindings = &mut self.modules.entry(url.to_string()).or_default().bindings;
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// The globals bound to exports of the module at `url`
+    pub(crate) fn bindings(&self, url: &str) -> Vec<(String, String)> {
+        self.modules.get(url).map(|module| module.bindings.clone()).unwrap_or_default()
+    }
+
+    /// Take the handlers of the module at `url` before it runs again, so
+    /// the new version starts with none, and give it `data` as
+    /// `import.meta.hot.data`
+    pub(crate) fn begin_update(&mut self, url: &str, data: Value) -> Handlers {
+        let module = self.modules.entry(url.to_string()).or_default();
+        module.meta = None;
+        module.accepted = false;
+        let handlers = Handlers {
+            accept: std::mem::take(&mut module.accept_callbacks),
+            dispose: std::mem::take(&mut module.dispose_callbacks),
+        };
+        module.updating.extend(handlers.accept.iter().chain(&handlers.dispose).cloned());
+        module.data = Some(data);
+        handlers
+    }
+
+    /// Keep `value` alive until the update of the module at `url` ends
+    pub(crate) fn retain(&mut self, url: &str, value: Value) {
+        self.modules.entry(url.to_string()).or_default().updating.push(value);
+    }
+
+    /// Let go of what the update of the module at `url` kept alive
+    pub(crate) fn end_update(&mut self, url: &str) {
+        if let Some(module) = self.modules.get_mut(url) {
+            module.updating.clear();
+        }
+    }
+
+    fn trace(&self, roots: &mut Vec<GcHandle>) {
+        for module in self.modules.values() {
+            let values = module
+                .meta
+                .iter()
+                .chain(&module.data)
+                .chain(&module.accept_callbacks)
+                .chain(&module.dispose_callbacks)
+                .chain(&module.updating);
+            roots.extend(values.filter_map(Value::as_handle));
+        }
+    }
+}
+
+/// Give the modules run on `runtime` their `import.meta`, and keep the
+/// handlers they register alive
+pub(crate) fn install(runtime: &mut Runtime, modules: &SharedHotModules) {
+    let shared = Arc::clone(modules);
+    runtime.set_import_meta_hook(move |runtime, url| {
+        if let Some(meta) = shared.lock().unwrap().modules.get(url).and_then(|module| module.meta.clone()) {
+            return Ok(meta);
+        }
+        let meta = create_meta(runtime, &shared, url);
+        shared.lock().unwrap().modules.entry(url.to_string()).or_default().meta = Some(meta.clone());
+        Ok(meta)
+    });
+
+    let traced = Arc::clone(modules);
+    runtime.add_root_source(move |roots| traced.lock().unwrap().trace(roots));
+}
+
+/// `import.meta` for the module at `url`: its `url` and its `hot` object
+fn create_meta(runtime: &mut Runtime, modules: &SharedHotModules, url: &str) -> Value {
+    let accept = {
+        let (modules, url) = (Arc::clone(modules), url.to_string());
+        NativeFunction::new("accept", move |runtime, args| {
+            let callback = optional_callback(runtime, "accept", args)?;
+            let mut modules = modules.lock().unwrap();
+            let module = modules.modules.entry(url.clone()).or_default();
+            module.accepted = true;
+            module.accept_callbacks.extend(callback);
+            Ok(Value::Undefined)
+        })
+    };
+    let dispose = {
+        let (modules, url) = (Arc::clone(modules), url.to_string());
+        NativeFunction::new("dispose", move |runtime, args| {
+            let Some(callback) = optional_callback(runtime, "dispose", args)? else {
+                return Err(RuntimeError::TypeError("import.meta.hot.dispose needs a callback".to_string()));
+            };
+            modules.lock().unwrap().modules.entry(url.clone()).or_default().dispose_callbacks.push(callback);
+            Ok(Value::Undefined)
+        })
+    };
+
+    let data = modules.lock().unwrap().modules.get(url).and_then(|module| module.data.clone());
+    let data = data.unwrap_or_else(|| runtime.create_object(HashMap::new()));
+    modules.lock().unwrap().modules.entry(url.to_string()).or_default().data = Some(data.clone());
+
+    let hot = runtime.create_object(HashMap::from([
+        ("accept".to_string(), Value::NativeFunction(accept)),
+        ("dispose".to_string(), Value::NativeFunction(dispose)),
+        ("data".to_string(), data),
+    ]));
+    runtime.create_object(HashMap::from([
+        ("url".to_string(), Value::from(url)),
+        ("hot".to_string(), hot),
+    ]))
+}
+
+/// The callback `method` was called with, if any
+fn optional_callback(runtime: &Runtime, method: &str, args: &[Value]) -> Result<Option<Value>, RuntimeError> {
+    match args.first() {
+        None | Some(Value::Undefined) => Ok(None),
+        Some(callback) if runtime.is_callable(callback) => Ok(Some(callback.clone())),
+        Some(_) => Err(RuntimeError::TypeError(format!(
+            "import.meta.hot.{} expects a function",
+            method
+        ))),
+    }
+}
@@ -0,0 +1,211 @@
+//! Conversion between engine values and JSON, for embedders
+//!
+//! Follows `JSON.stringify`: non-finite numbers become `null`, `undefined`
+//! and functions become `null` in arrays and are left out of objects, and an
+//! object met again while converting itself becomes `null` rather than
+//! recursing forever. Objects nested deeper than `JsonOptions::max_depth`
+//! become `null` too, and `value_to_json` can make both errors instead and
+//! pass each value through a replacer.
+
+use bebion_gc::{GarbageCollector, GcHandle, GcObjectType};
+use bebion_runtime::{JsString, Symbol, Value};
+use serde_json::{Map, Number};
+use std::collections::HashSet;
+
+/// Allocate `json` in the heap as an engine value
+pub(crate) fn to_value(gc: &mut GarbageCollector, json: &serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Boolean(*b),
+        serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+        serde_json::Value::String(s) => Value::String(JsString::from(s.as_str())),
+        serde_json::Value::Array(elements) => {
+            let elements = elements.iter().map(|element| to_handle(gc, element)).collect();
+            Value::Object(gc.allocate_array(elements))
+        }
+        serde_json::Value::Object(properties) => {
+            // Added in document order, so objects of one layout share a shape
+            let properties: Vec<_> = properties
+                .iter()
+                .map(|(key, value)| (key.clone(), to_handle(gc, value)))
+                .collect();
+            Value::Object(gc.allocate_object(properties))
+        }
+    }
+}
+
+fn to_handle(gc: &mut GarbageCollector, json: &serde_json::Value) -> GcHandle {
+    match to_value(gc, json) {
+        Value::Object(handle) => handle,
+        Value::Number(n) => gc.allocate_number(n),
+        Value::String(s) => gc.intern_string(&s.to_rust_string()),
+        Value::Boolean(b) => gc.allocate_boolean(b),
+        Value::Null => gc.allocate_null(),
+        // JSON has no functions or symbols
+        Value::Undefined | Value::Symbol(_) | Value::NativeFunction(_) => gc.allocate_undefined(),
+    }
+}
+
+/// How deep `JsonOptions` lets objects nest unless told otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// Called with each converted value and its property key or array index,
+/// `""` for the value converted itself; returns the value to use instead,
+/// or `None` to leave it out (`null` in arrays)
+pub type JsonReplacer = Box<dyn Fn(&str, serde_json::Value) -> Option<serde_json::Value> + Send + Sync>;
+
+/// How `BebionEngine::value_to_json` walks an object graph
+pub struct JsonOptions {
+    max_depth: usize,
+    strict: bool,
+    replacer: Option<JsonReplacer>,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            strict: false,
+            replacer: None,
+        }
+    }
+}
+
+impl JsonOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Objects and arrays nested deeper than `max_depth` below the value
+    /// converted become `null`, or an error when strict
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Fail on cycles and too deep nesting instead of writing `null`
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Pass every converted value through `replacer`. It sees values after
+    /// their contents are converted and replaced.
+    pub fn replacer(
+        mut self,
+        replacer: impl Fn(&str, serde_json::Value) -> Option<serde_json::Value> + Send + Sync + 'static,
+    ) -> Self {
+        self.replacer = Some(Box::new(replacer));
+        self
+    }
+}
+
+/// The JSON form of `value`, or `None` where `JSON.stringify` produces
+/// nothing (`undefined`, symbols and functions)
+pub(crate) fn from_value(gc: &GarbageCollector, value: &Value) -> Option<serde_json::Value> {
+    export(gc, value, &JsonOptions::default()).unwrap_or(None)
+}
+
+/// `from_value` with `options`; fails only when strict
+pub(crate) fn export(gc: &GarbageCollector, value: &Value, options: &JsonOptions) -> Result<Option<serde_json::Value>, String> {
+    let mut exporter = Exporter { gc, options, visiting: HashSet::new() };
+    let json = match value {
+        Value::Object(handle) => return exporter.convert("", *handle, 0),
+        Value::Number(n) => number(*n),
+        Value::String(s) => serde_json::Value::String(s.to_rust_string()),
+        Value::Boolean(b) => serde_json::Value::Bool(*b),
+        Value::Null => serde_json::Value::Null,
+        Value::Undefined | Value::Symbol(_) | Value::NativeFunction(_) => return Ok(None),
+    };
+    Ok(exporter.replace("", json))
+}
+
+struct Exporter<'a> {
+    gc: &'a GarbageCollector,
+    options: &'a JsonOptions,
+    /// Objects being converted, to tell a cycle
+    visiting: HashSet<GcHandle>,
+}
+
+impl Exporter<'_> {
+    fn replace(&self, key: &str, json: serde_json::Value) -> Option<serde_json::Value> {
+        match &self.options.replacer {
+            Some(replacer) => replacer(key, json),
+            None => Some(json),
+        }
+    }
+
+    /// `null` for a cycle or too deep nesting, or the error when strict
+    fn cut_off(&self, reason: impl FnOnce() -> String) -> Result<serde_json::Value, String> {
+        if self.options.strict {
+            Err(reason())
+        } else {
+            Ok(serde_json::Value::Null)
+        }
+    }
+
+    /// The JSON form of the object at `handle`, found under `key` and
+    /// nested `depth` levels below the value converted
+    fn convert(&mut self, key: &str, handle: GcHandle, depth: usize) -> Result<Option<serde_json::Value>, String> {
+        let gc = self.gc;
+        let Some(object) = gc.get_object_type(handle) else {
+            return Ok(None);
+        };
+        let json = match object {
+            GcObjectType::Number(n) => number(*n),
+            GcObjectType::String(s) => serde_json::Value::String(s.to_string()),
+            GcObjectType::Boolean(b) => serde_json::Value::Bool(*b),
+            GcObjectType::Null => serde_json::Value::Null,
+            GcObjectType::Undefined | GcObjectType::Symbol { .. } | GcObjectType::Function { .. } => return Ok(None),
+            // Reading an accessor property runs script, which extraction cannot
+            GcObjectType::Accessor { .. } => return Ok(None),
+            _ if self.visiting.contains(&handle) => {
+                self.cut_off(|| format!("Converting circular structure to JSON at key '{}'", key))?
+            }
+            _ if depth > self.options.max_depth => self.cut_off(|| {
+                format!("Object nested deeper than {} levels at key '{}'", self.options.max_depth, key)
+            })?,
+            GcObjectType::Array(elements) => {
+                self.visiting.insert(handle);
+                let mut array = Vec::with_capacity(elements.len());
+                for (index, element) in elements.iter().enumerate() {
+                    let element = self.convert(&index.to_string(), *element, depth + 1)?;
+                    array.push(element.unwrap_or(serde_json::Value::Null));
+                }
+                self.visiting.remove(&handle);
+                serde_json::Value::Array(array)
+            }
+            GcObjectType::Object(properties) => {
+                self.visiting.insert(handle);
+                // Symbol-keyed properties are left out, as by `JSON.stringify`
+                let mut object = Map::new();
+                for (key, &value) in properties.iter().filter(|(key, _)| !Symbol::is_property_key(key)) {
+                    if let Some(value) = self.convert(key, value, depth + 1)? {
+                        object.insert(key.to_string(), value);
+                    }
+                }
+                self.visiting.remove(&handle);
+                serde_json::Value::Object(object)
+            }
+            GcObjectType::Promise { .. }
+            | GcObjectType::RegExp { .. }
+            | GcObjectType::ArrayBuffer(_)
+            | GcObjectType::DetachedArrayBuffer
+            | GcObjectType::TypedArray { .. }
+            | GcObjectType::DataView { .. }
+            | GcObjectType::Map(_)
+            | GcObjectType::Set(_)
+            | GcObjectType::WeakMap(_)
+            | GcObjectType::WeakSet(_) => serde_json::Value::Object(Map::new()),
+        };
+        Ok(self.replace(key, json))
+    }
+}
+
+fn number(n: f64) -> serde_json::Value {
+    if n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0 {
+        serde_json::Value::Number(Number::from(n as i64))
+    } else {
+        Number::from_f64(n).map_or(serde_json::Value::Null, serde_json::Value::Number)
+    }
+}
@@ -2,28 +2,106 @@
 //! 
 //! The main engine that orchestrates all components of the runtime.
 
-use bebion_compiler::Compiler;
+use bebion_compiler::bytecode::Bytecode;
+use bebion_compiler::{Compiler, OptLevel};
 use bebion_gc::{GarbageCollector, GcHandle};
-use bebion_parser::Parser;
-use bebion_runtime::{EventLoop, Runtime};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use bebion_parser::ast::{self, AstNode, LiteralValue};
+use bebion_parser::{ExperimentalFeatures, Feature, ParseError, Parser, Program};
+#[cfg(feature = "event-loop")]
+use bebion_runtime::EventLoop;
+use bebion_runtime::{HostClock, HostRandom, IcStats, Runtime, RuntimeError, Tier, TierThresholds, VmStats};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info};
 
+pub mod builder;
+pub mod events;
+mod exports;
+mod hot;
+pub mod json;
+pub mod resolver;
+#[cfg(feature = "event-loop")]
+mod timers;
+mod uncaught;
+
+pub use bebion_gc::{
+    EdgeKind, EdgeName, GcConfig, GcEvent, GcRoot, GcStats, HandleScope, HeapObject, HeapQuery, HeapSnapshot,
+    ObjectInfo, SnapshotEdge, SnapshotNode, WeakHandle,
+};
+pub use bebion_runtime::clock::parse_iso_timestamp;
+pub use bebion_runtime::{FrameSnapshot, NativeFunction, PromiseInspection, SerializedValue, Value};
+pub use builder::EngineBuilder;
+pub use events::{EngineEvent, EngineObserver, UnhandledRejections};
+pub use json::{JsonOptions, JsonReplacer};
+pub use resolver::{FileSystemResolver, MemoryResolver, Resolution, ResolverHook, Source};
+pub use uncaught::{UncaughtError, UncaughtHandler};
+
 pub struct BebionEngine {
     parser: Parser,
     compiler: Compiler,
     runtime: Runtime,
+    #[cfg(feature = "event-loop")]
     event_loop: EventLoop,
+    /// Timers scripts scheduled, shared with the timer globals
+    #[cfg(feature = "event-loop")]
+    timers: timers::SharedTimers,
     gc: Arc<Mutex<GarbageCollector>>,
     modules: HashMap<String, ModuleInfo>,
+    /// Shared with the runtime's import hook, which loads assets through it
+    resolver: Arc<RwLock<Box<dyn ResolverHook>>>,
+    /// URL of the module running now, which its imports resolve against
+    current_module: Arc<Mutex<Option<String>>>,
+    /// What each module exported, shared with the runtime's export hook
+    exports: exports::SharedExports,
+    /// URLs of the modules running their imports now, to catch cycles
+    loading_modules: HashSet<String>,
+    /// The code each loaded module last ran, by module URL, to tell a
+    /// changed file from one only saved again
+    module_programs: HashMap<String, Program>,
+    /// `import.meta.hot` handlers, shared with the runtime's hook
+    hot_modules: hot::SharedHotModules,
+    observers: Vec<Box<dyn EngineObserver>>,
+    /// Live heap bytes above which observers get `GcPressure`
+    gc_pressure_threshold: usize,
+    /// Whether the heap is above the threshold, so `GcPressure` is sent
+    /// once per crossing
+    under_gc_pressure: bool,
+    unhandled_rejections: UnhandledRejections,
+    /// Listeners scripts added with `process.on`, shared with the global
+    error_hooks: uncaught::SharedHooks,
+    /// The host's last say on errors the script's listeners left
+    uncaught_handler: Option<UncaughtHandler>,
+    /// How long `run_until_idle` may keep the event loop turning
+    max_run_time: Option<Duration>,
 }
 
+/// Longest `run_until_idle` sleeps between checks while only in-flight
+/// async work remains
+#[cfg(feature = "event-loop")]
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Live heap size observers are warned about unless the host picks another
+pub const DEFAULT_GC_PRESSURE_THRESHOLD: usize = 256 * 1024 * 1024;
+
 #[derive(Debug, Clone)]
 pub struct ModuleInfo {
     pub id: String,
     pub path: String,
-    pub exports: HashMap<String, GcHandle>,
+    /// What the module exported, by name; `export default` is `default`
+    pub exports: HashMap<String, Value>,
+}
+
+/// What `BebionEngine::reload_module` did about a module's file changing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotUpdate {
+    /// The code only moved around or was saved again, so nothing ran
+    Unchanged,
+    /// The module ran again in place
+    Applied,
+    /// The module does not accept updates, so the program has to restart
+    /// to pick up the change
+    Declined,
 }
 
 #[derive(Debug)]
@@ -47,88 +125,1134 @@ impl std::fmt::Display for BebionError {
 
 impl std::error::Error for BebionError {}
 
+/// Serve `import ... with { type: "text" }` and `type: "bytes"` through
+/// the resolver, relative to the module running at the time
+fn install_import_hook(
+    runtime: &mut Runtime,
+    resolver: Arc<RwLock<Box<dyn ResolverHook>>>,
+    current_module: Arc<Mutex<Option<String>>>,
+) {
+    runtime.set_import_hook(move |runtime, specifier, import_type| {
+        let load = || -> Result<Vec<u8>, BebionError> {
+            let referrer = current_module.lock().unwrap().clone();
+            let resolver = resolver.read().unwrap();
+            let resolution = resolver.resolve(specifier, referrer.as_deref())?;
+            resolver.load_bytes(&resolution.url)
+        };
+        match import_type {
+            "text" => {
+                let bytes = load().map_err(|e| RuntimeError::Error(e.to_string()))?;
+                let text = String::from_utf8(bytes).map_err(|_| {
+                    RuntimeError::TypeError(format!("Cannot import '{}' as text: it is not valid UTF-8", specifier))
+                })?;
+                Ok(Value::from(text))
+            }
+            "bytes" => {
+                let bytes = load().map_err(|e| RuntimeError::Error(e.to_string()))?;
+                Ok(runtime.create_uint8_array(bytes))
+            }
+            "" => Err(RuntimeError::SyntaxError(format!(
+                "Cannot import '{}': importing JavaScript modules is not supported yet",
+                specifier
+            ))),
+            other => Err(RuntimeError::TypeError(format!(
+                "Cannot import '{}': unsupported import type \"{}\"",
+                specifier, other
+            ))),
+        }
+    });
+}
+
 impl BebionEngine {
+    /// Configure an engine before creating it
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
     pub fn new() -> Result<Self, BebionError> {
         info!("Initializing Bebion Engine");
         
         let gc = Arc::new(Mutex::new(GarbageCollector::new()));
         let parser = Parser::new();
         let compiler = Compiler::new();
-        let runtime = Runtime::new(Arc::clone(&gc));
-        let event_loop = EventLoop::new();
+        let mut runtime = Runtime::new(Arc::clone(&gc));
+        let resolver: Arc<RwLock<Box<dyn ResolverHook>>> =
+            Arc::new(RwLock::new(Box::new(FileSystemResolver::new())));
+        let current_module = Arc::new(Mutex::new(None));
+        install_import_hook(&mut runtime, Arc::clone(&resolver), Arc::clone(&current_module));
+        let module_exports = exports::SharedExports::default();
+        exports::install(&mut runtime, &module_exports, Arc::clone(&current_module));
+        let hot_modules = hot::SharedHotModules::default();
+        hot::install(&mut runtime, &hot_modules);
+        #[cfg(feature = "event-loop")]
+        let script_timers = timers::SharedTimers::default();
+        #[cfg(feature = "event-loop")]
+        timers::install(&mut runtime, &script_timers);
+        let error_hooks = uncaught::SharedHooks::default();
+        uncaught::install(&mut runtime, &error_hooks);
         
         Ok(Self {
             parser,
             compiler,
             runtime,
-            event_loop,
+            #[cfg(feature = "event-loop")]
+            event_loop: EventLoop::new(),
+            #[cfg(feature = "event-loop")]
+            timers: script_timers,
             gc,
             modules: HashMap::new(),
+            resolver,
+            current_module,
+            exports: module_exports,
+            loading_modules: HashSet::new(),
+            module_programs: HashMap::new(),
+            hot_modules,
+            observers: Vec::new(),
+            gc_pressure_threshold: DEFAULT_GC_PRESSURE_THRESHOLD,
+            under_gc_pressure: false,
+            unhandled_rejections: UnhandledRejections::default(),
+            error_hooks,
+            uncaught_handler: None,
+            max_run_time: None,
         })
     }
 
     pub fn execute_script(&mut self, source: &str) -> Result<GcHandle, BebionError> {
         debug!("Executing script: {} chars", source.len());
-        
-        // Parse the source code
+        let ast = self.parse_script(source)?;
+        self.execute_program(&ast)
+    }
+
+    pub fn parse_script(&mut self, source: &str) -> Result<Program, BebionError> {
         let ast = self.parser.parse(source)
             .map_err(|e| BebionError::ParseError(e.to_string()))?;
-        
         debug!("Parsed AST with {} nodes", ast.node_count());
-        
-        // Compile to bytecode
-        let bytecode = self.compiler.compile(&ast)
+        Ok(ast)
+    }
+
+    /// Whether `source` stops partway through, in an unclosed block,
+    /// template literal or comment say, so a REPL should read more lines
+    /// before running it. Complete input and input with other syntax errors
+    /// is ready to run.
+    pub fn is_incomplete(&mut self, source: &str) -> bool {
+        matches!(self.parser.parse(source), Err(ParseError::UnexpectedEof { .. }))
+    }
+
+    /// Compile and run a parsed program
+    pub fn execute_program(&mut self, ast: &Program) -> Result<GcHandle, BebionError> {
+        let bytecode = self.compile_program(ast)?;
+        self.execute_bytecode(&bytecode)
+    }
+
+    /// Compile `source` without running it
+    pub fn compile_script(&mut self, source: &str) -> Result<Bytecode, BebionError> {
+        let ast = self.parse_script(source)?;
+        self.compile_program(&ast)
+    }
+
+    /// Compile a parsed program without running it
+    pub fn compile_program(&mut self, ast: &Program) -> Result<Bytecode, BebionError> {
+        let bytecode = self.compiler.compile(ast)
             .map_err(|e| BebionError::CompileError(e.to_string()))?;
-        
         debug!("Generated {} bytes of bytecode", bytecode.len());
+        Ok(bytecode)
+    }
+
+    /// Run compiled code, then the jobs and events it queued
+    pub fn execute_bytecode(&mut self, bytecode: &Bytecode) -> Result<GcHandle, BebionError> {
+        self.notify(EngineEvent::ScriptStarted);
+        let start_time = Instant::now();
         
-        // Execute in runtime
-        let result = self.runtime.execute(&bytecode)
-            .map_err(|e| BebionError::RuntimeError(e.to_string()))?;
-        
-        // Process event loop
-        self.event_loop.process_pending();
+        // Execute in runtime; a handled error leaves the script's result
+        // undefined
+        let result = match self.runtime.execute(bytecode) {
+            Ok(result) => result,
+            Err(e) => {
+                let exception = self.runtime.exception_value(&e);
+                self.handle_uncaught_exception(e, exception)?;
+                self.runtime
+                    .value_to_gc_handle(Value::Undefined)
+                    .map_err(|e| BebionError::RuntimeError(e.to_string()))?
+            }
+        };
         
+        self.turn_event_loop()?;
+        self.check_gc_pressure();
+        self.notify(EngineEvent::ScriptFinished { elapsed: start_time.elapsed() });
         Ok(result)
     }
 
-    pub fn load_module(&mut self, path: &str) -> Result<ModuleInfo, BebionError> {
-        info!("Loading module: {}", path);
+    /// Run the microtasks queued so far, then one turn of the event loop:
+    /// the timers that are due, each followed by its callback and the
+    /// microtasks it queued, and a task
+    fn turn_event_loop(&mut self) -> Result<(), BebionError> {
+        self.runtime.run_jobs();
+        #[cfg(feature = "event-loop")]
+        {
+            timers::sync_signals(&self.timers, &mut self.runtime);
+            timers::schedule(&self.timers, &mut self.event_loop);
+            let (runtime, script_timers) = (&mut self.runtime, &self.timers);
+            self.event_loop.process_pending_with(|| {
+                timers::run_due(script_timers, runtime);
+                runtime.run_jobs();
+                // Settle the waits the callbacks aborted before later
+                // timers fire
+                timers::sync_signals(script_timers, runtime);
+                runtime.run_jobs();
+            });
+            // Hand over the timers the callbacks set and the intervals to
+            // rearm, so the loop is not idle while they are pending
+            timers::schedule(&self.timers, &mut self.event_loop);
+        }
         
-        if let Some(cached) = self.modules.get(path) {
-            debug!("Using cached module: {}", path);
-            return Ok(cached.clone());
+        self.check_job_errors()?;
+        self.check_unhandled_rejections()
+    }
+
+    /// Hand the errors timers, `queueMicrotask` callbacks and `abort`
+    /// listeners threw to the error hooks, failing the run with the first
+    /// one nothing handles
+    fn check_job_errors(&mut self) -> Result<(), BebionError> {
+        for (error, exception) in self.runtime.take_job_exceptions() {
+            self.handle_uncaught_exception(error, exception)?;
+        }
+        Ok(())
+    }
+
+    /// Give the script's `uncaughtException` listeners, then the host's
+    /// handler, the chance to handle `error`, which threw `exception`.
+    /// Fails the run with it if neither does, and with what a listener
+    /// threw if one does.
+    fn handle_uncaught_exception(&mut self, error: RuntimeError, exception: Option<Value>) -> Result<(), BebionError> {
+        let error = match uncaught::dispatch_exception(&mut self.runtime, &self.error_hooks, exception) {
+            Ok(true) => return Ok(()),
+            Ok(false) => error,
+            // Fatal, so a listener that throws cannot loop
+            Err(thrown) => {
+                let message = thrown.to_string();
+                self.notify(EngineEvent::UncaughtError { message: message.clone() });
+                return Err(BebionError::RuntimeError(message));
+            }
+        };
+
+        let message = error.to_string();
+        if self.host_handles(&UncaughtError::Exception { message: message.clone() }) {
+            return Ok(());
+        }
+        self.notify(EngineEvent::UncaughtError { message: message.clone() });
+        Err(BebionError::RuntimeError(message))
+    }
+
+    /// Whether the host's handler took care of `error`
+    fn host_handles(&mut self, error: &UncaughtError) -> bool {
+        self.uncaught_handler.as_mut().is_some_and(|handler| handler(error))
+    }
+
+    /// Turn the event loop until no microtasks, tasks, timers or in-flight
+    /// async work remain, as a script run to completion does before the
+    /// process exits. `execute_script` runs only the first turn.
+    pub fn run_until_idle(&mut self) -> Result<(), BebionError> {
+        let start_time = Instant::now();
+        let deadline = self.max_run_time.map(|max_run_time| start_time + max_run_time);
+        if !self.run_until(deadline)? {
+            return Err(BebionError::RuntimeError(format!(
+                "Event loop still busy after the maximum run time of {:?}",
+                self.max_run_time.unwrap_or_default()
+            )));
         }
         
-        // Read file content
-        let source = std::fs::read_to_string(path)
-            .map_err(|e| BebionError::ModuleError(format!("Failed to read {}: {}", path, e)))?;
+        debug!("Event loop idle after {:?}", start_time.elapsed());
+        Ok(())
+    }
+
+    /// Turn the event loop for up to `budget`, stopping early once it is
+    /// idle, and say whether it is. A host with work of its own, such as a
+    /// watcher polling for changes, runs a long-lived script in slices
+    /// this way.
+    pub fn run_for(&mut self, budget: Duration) -> Result<bool, BebionError> {
+        self.run_until(Some(Instant::now() + budget))
+    }
+
+    /// Turn the event loop until it is idle or `deadline` passes, sleeping
+    /// between turns while only timers are pending
+    fn run_until(&mut self, deadline: Option<Instant>) -> Result<bool, BebionError> {
+        #[cfg(feature = "event-loop")]
+        self.event_loop.start();
         
-        // Execute module
-        let result = self.execute_script(&source)?;
+        let idle = loop {
+            self.turn_event_loop()?;
+            if self.is_idle() {
+                break true;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break false;
+            }
+            self.wait_for_work(deadline);
+        };
+        
+        self.check_gc_pressure();
+        Ok(idle)
+    }
+
+    /// Stop `run_until_idle` with an error once the event loop has been
+    /// turning for `max_run_time`; `None` lets it run as long as there is
+    /// work, such as for a server
+    pub fn set_max_run_time(&mut self, max_run_time: Option<Duration>) {
+        self.max_run_time = max_run_time;
+    }
+
+    fn is_idle(&mut self) -> bool {
+        let idle = !self.runtime.has_pending_jobs();
+        #[cfg(feature = "event-loop")]
+        let idle = idle && self.event_loop.is_idle();
+        idle
+    }
+
+    /// Sleep until the next timer is due, waking early to check on
+    /// in-flight work and never past `deadline`
+    fn wait_for_work(&self, deadline: Option<Instant>) {
+        #[cfg(feature = "event-loop")]
+        let wait = self.event_loop.time_until_ready().unwrap_or(IDLE_POLL_INTERVAL).min(IDLE_POLL_INTERVAL);
+        #[cfg(not(feature = "event-loop"))]
+        let wait = Duration::ZERO;
+        let wait = match deadline {
+            Some(deadline) => wait.min(deadline.saturating_duration_since(Instant::now())),
+            None => wait,
+        };
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Hand the promises the tick left rejected with no handler to the
+    /// script's `unhandledRejection` listeners, or the host's handler, and
+    /// report the rest, failing the run in strict mode
+    fn check_unhandled_rejections(&mut self) -> Result<(), BebionError> {
+        let rejections = self.runtime.take_rejected_promises();
+        let rejections = match uncaught::dispatch_rejections(&mut self.runtime, &self.error_hooks, rejections) {
+            Ok(rejections) => rejections,
+            // What a listener threw is uncaught like any other exception
+            Err(error) => {
+                let exception = self.runtime.exception_value(&error);
+                return self.handle_uncaught_exception(error, exception);
+            }
+        };
+        let mut reasons: Vec<String> =
+            rejections.iter().map(|(_, reason)| self.runtime.describe_exception(reason)).collect();
+        reasons.retain(|reason| !self.host_handles(&UncaughtError::Rejection { reason: reason.clone() }));
+        if self.unhandled_rejections == UnhandledRejections::None {
+            return Ok(());
+        }
+        for reason in &reasons {
+            self.notify(EngineEvent::UnhandledRejection { reason: reason.clone() });
+        }
+        match reasons.first() {
+            Some(reason) if self.unhandled_rejections == UnhandledRejections::Strict => {
+                let message = format!("Unhandled promise rejection: {}", reason);
+                self.notify(EngineEvent::UncaughtError { message: message.clone() });
+                Err(BebionError::RuntimeError(message))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Compile `source` to run one instruction per `debug_step`, for
+    /// debuggers. Abandons any run being stepped.
+    pub fn debug_script(&mut self, source: &str) -> Result<(), BebionError> {
+        let ast = self.parse_script(source)?;
+        let bytecode = self.compiler.compile(&ast)
+            .map_err(|e| BebionError::CompileError(e.to_string()))?;
+        self.runtime.begin_stepping(&bytecode);
+        Ok(())
+    }
+
+    /// Run the debugged script's next instruction. Returns its completion
+    /// value once it has finished, after running the jobs it queued.
+    pub fn debug_step(&mut self) -> Result<Option<Value>, BebionError> {
+        let finished = self.runtime.step()
+            .map_err(|e| BebionError::RuntimeError(e.to_string()))?;
+        if finished.is_some() {
+            self.runtime.run_jobs();
+        }
+        Ok(finished)
+    }
+
+    /// Where the debugged script is paused, `None` when nothing is stepped
+    pub fn debug_frame(&self) -> Option<FrameSnapshot> {
+        self.runtime.current_frame()
+    }
+
+    /// Stop stepping without finishing the script
+    pub fn debug_abort(&mut self) {
+        self.runtime.end_stepping();
+    }
+
+    /// The value of a script result, e.g. to echo it in the REPL
+    pub fn value_of(&self, handle: GcHandle) -> Value {
+        self.runtime.handle_to_value(handle)
+    }
+
+    /// The JSON form of a script result, `None` when it is `undefined` or a
+    /// function
+    pub fn json_of(&self, handle: GcHandle) -> Option<serde_json::Value> {
+        let value = self.value_of(handle);
+        json::from_value(&self.gc.lock().unwrap(), &value)
+    }
+
+    /// Convert the object graph of a script result to JSON as `options`
+    /// say, for embedders extracting results. Values with no JSON form
+    /// give `null`; strict options make cycles and too deep nesting an
+    /// error.
+    pub fn value_to_json(&self, handle: GcHandle, options: &JsonOptions) -> Result<serde_json::Value, BebionError> {
+        let value = self.value_of(handle);
+        json::export(&self.gc.lock().unwrap(), &value, options)
+            .map(|json| json.unwrap_or(serde_json::Value::Null))
+            .map_err(BebionError::RuntimeError)
+    }
+
+    /// The state of a script result if it is a promise, e.g. to show
+    /// `Promise { <pending> }` in the REPL
+    pub fn promise_state(&self, handle: GcHandle) -> Option<PromiseInspection> {
+        self.runtime.promise_state(&Value::Object(handle))
+    }
+
+    /// Turn the event loop until the promise `handle` settles, for host
+    /// code that needs a script's async result synchronously. Returns the
+    /// fulfilled value, or the rejection as an error; other values are
+    /// returned as they are. Fails if the promise is still pending after
+    /// `timeout` or once nothing is left that could settle it.
+    pub fn await_value(&mut self, handle: GcHandle, timeout: Duration) -> Result<Value, BebionError> {
+        if self.promise_state(handle).is_none() {
+            return Ok(self.value_of(handle));
+        }
+        // The host handles the rejection, so strict mode must not fail the
+        // turn it comes in
+        self.runtime.handle_rejection(handle);
+        let start_time = Instant::now();
+        #[cfg(feature = "event-loop")]
+        self.event_loop.start();
+
+        loop {
+            match self.promise_state(handle) {
+                Some(PromiseInspection::Fulfilled(value)) => return Ok(value),
+                Some(PromiseInspection::Rejected(reason)) => {
+                    return Err(BebionError::RuntimeError(self.runtime.describe_exception(&reason)));
+                }
+                _ => {}
+            }
+            if start_time.elapsed() >= timeout {
+                return Err(BebionError::RuntimeError(format!("Promise still pending after {:?}", timeout)));
+            }
+            self.turn_event_loop()?;
+            if matches!(self.promise_state(handle), Some(PromiseInspection::Pending)) {
+                if self.is_idle() {
+                    return Err(BebionError::RuntimeError(
+                        "Promise can never settle: the event loop has no work left".to_string(),
+                    ));
+                }
+                self.wait_for_work(Some(start_time + timeout));
+            }
+        }
+    }
+
+    /// Copy a script result out of the heap with the structured clone
+    /// algorithm, e.g. to hand it to another engine or keep it as a
+    /// snapshot. Fails on functions, symbols, promises and weak
+    /// collections.
+    pub fn serialize_value(&self, handle: GcHandle) -> Result<SerializedValue, BebionError> {
+        self.runtime
+            .serialize(&self.value_of(handle))
+            .map_err(|e| BebionError::RuntimeError(e.to_string()))
+    }
+
+    /// Recreate a serialized value in this engine's heap, e.g. to define
+    /// it as a global
+    pub fn deserialize_value(&mut self, serialized: &SerializedValue) -> Value {
+        self.runtime.deserialize(serialized)
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.runtime.set_global(name, value);
+    }
+
+    /// Set `process.argv`, the command line scripts see
+    pub fn set_argv(&mut self, argv: &[String]) -> Result<(), BebionError> {
+        let process = self.runtime.get_global("process").cloned().unwrap_or(Value::Undefined);
+        let argv = argv.iter().map(|arg| Value::from(arg.as_str())).collect();
+        let argv = self.runtime.create_array(argv);
+        self.runtime.set_property(&process, "argv", argv)
+            .map_err(|e| BebionError::RuntimeError(e.to_string()))
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.runtime.get_global(name).cloned()
+    }
+
+    /// Define a global from JSON, allocating its objects and arrays
+    pub fn set_global_json(&mut self, name: &str, value: &serde_json::Value) {
+        let value = json::to_value(&mut self.gc.lock().unwrap(), value);
+        self.runtime.set_global(name, value);
+    }
+
+    /// Define a global function whose arguments and result cross as JSON.
+    /// Arguments with no JSON form arrive as `null`, and an `Err` is thrown
+    /// as an `Error`.
+    pub fn set_global_json_function<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&[serde_json::Value]) -> Result<serde_json::Value, String> + Send + Sync + 'static,
+    {
+        let gc = Arc::clone(&self.gc);
+        self.runtime.set_global_function(name, move |_, args| {
+            let args: Vec<_> = {
+                let gc = gc.lock().unwrap();
+                args.iter()
+                    .map(|arg| json::from_value(&gc, arg).unwrap_or(serde_json::Value::Null))
+                    .collect()
+            };
+            let result = function(&args).map_err(RuntimeError::Error)?;
+            Ok(json::to_value(&mut gc.lock().unwrap(), &result))
+        });
+    }
+
+    /// The JSON form of a global, `None` when it is not defined or has no
+    /// JSON form
+    pub fn get_global_json(&self, name: &str) -> Option<serde_json::Value> {
+        let value = self.runtime.get_global(name)?;
+        json::from_value(&self.gc.lock().unwrap(), value)
+    }
+
+    /// Replace how modules are found and read; modules already loaded stay
+    /// cached
+    pub fn set_resolver_hook(&mut self, hook: impl ResolverHook + 'static) {
+        *self.resolver.write().unwrap() = Box::new(hook);
+    }
+
+    /// Resolve `specifier` from the module at URL `referrer` and read its
+    /// source through the resolver hook, without running it
+    pub fn fetch_module(&self, specifier: &str, referrer: Option<&str>) -> Result<Source, BebionError> {
+        let resolver = self.resolver.read().unwrap();
+        let resolution = resolver.resolve(specifier, referrer)?;
+        resolver.load(&resolution.url)
+    }
+
+    /// Resolve `specifier` from the module at URL `referrer` and read the
+    /// raw bytes behind it, as `import ... with { type: "bytes" }` does
+    pub fn fetch_asset(&self, specifier: &str, referrer: Option<&str>) -> Result<Vec<u8>, BebionError> {
+        let resolver = self.resolver.read().unwrap();
+        let resolution = resolver.resolve(specifier, referrer)?;
+        resolver.load_bytes(&resolution.url)
+    }
+
+    /// Load the entry module `specifier`
+    pub fn load_module(&mut self, specifier: &str) -> Result<ModuleInfo, BebionError> {
+        self.import_module(specifier, None)
+    }
+
+    /// Load the module `specifier` names when imported from the module at
+    /// URL `referrer`, running it and the modules it imports the first
+    /// time its URL is seen
+    pub fn import_module(&mut self, specifier: &str, referrer: Option<&str>) -> Result<ModuleInfo, BebionError> {
+        info!("Loading module: {}", specifier);
+        
+        let resolution = self.resolver.read().unwrap().resolve(specifier, referrer)?;
+        if let Some(cached) = self.modules.get(&resolution.url) {
+            debug!("Using cached module: {}", resolution.url);
+            return Ok(cached.clone());
+        }
+        if !self.loading_modules.insert(resolution.url.clone()) {
+            return Err(BebionError::ModuleError(format!(
+                "Cannot import '{}': circular imports are not supported",
+                specifier
+            )));
+        }
+        
+        let result = self.run_module_source(&resolution.url);
+        self.loading_modules.remove(&resolution.url);
+        let source = result?;
         
-        // Create module info
         let module_info = ModuleInfo {
-            id: path.to_string(),
-            path: path.to_string(),
-            exports: HashMap::new(),
+            id: resolution.url.clone(),
+            exports: self.exports.lock().unwrap().of(&source.url),
+            path: source.url,
         };
         
-        self.modules.insert(path.to_string(), module_info.clone());
+        self.modules.insert(resolution.url.clone(), module_info.clone());
+        self.notify(EngineEvent::ModuleLoaded { url: resolution.url });
         
         Ok(module_info)
     }
 
+    /// Load the module at `url` and run it afresh
+    fn run_module_source(&mut self, url: &str) -> Result<Source, BebionError> {
+        let source = self.resolver.read().unwrap().load(url)?;
+        let program = self.parse_script(&source.code)?;
+        self.module_programs.insert(url.to_string(), program.clone());
+        self.exports.lock().unwrap().clear(&source.url);
+        self.run_module(&source.url, program)?;
+        Ok(source)
+    }
+
+    /// The modules loaded so far
+    pub fn modules(&self) -> impl Iterator<Item = &ModuleInfo> {
+        self.modules.values()
+    }
+
+    /// Apply a change to the loaded module with URL `url`, reading its
+    /// code again through the resolver. If the code changed and the module
+    /// accepts updates through `import.meta.hot.accept`, it runs again in
+    /// place and the names other modules imported from it are rebound to
+    /// its new exports. The modules it imports are not run again.
+    pub fn reload_module(&mut self, url: &str) -> Result<HotUpdate, BebionError> {
+        let Some(path) = self.modules.get(url).map(|module| module.path.clone()) else {
+            return Err(BebionError::ModuleError(format!("Module '{}' is not loaded", url)));
+        };
+        let source = self.resolver.read().unwrap().load(url)?;
+        let program = self.parse_script(&source.code)?;
+        if self.module_programs.get(url).is_some_and(|old| ast::diff(old, &program).is_empty()) {
+            return Ok(HotUpdate::Unchanged);
+        }
+        if !self.hot_modules.lock().unwrap().accepts(&path) {
+            return Ok(HotUpdate::Declined);
+        }
+        info!("Hot updating module: {}", url);
+        
+        let result = self.update_module(url, &path, program);
+        self.hot_modules.lock().unwrap().end_update(&path);
+        result?;
+        
+        self.notify(EngineEvent::ModuleReloaded { url: url.to_string() });
+        Ok(HotUpdate::Applied)
+    }
+
+    /// Dispose of the running version of the module at `url`, run
+    /// `program` in its place and hand the new exports to the old
+    /// version's accept callbacks
+    fn update_module(&mut self, url: &str, path: &str, program: Program) -> Result<(), BebionError> {
+        let data = self.runtime.create_object(HashMap::new());
+        let handlers = self.hot_modules.lock().unwrap().begin_update(path, data.clone());
+        for callback in &handlers.dispose {
+            self.runtime.call_function(callback, &[data.clone()])
+                .map_err(|e| BebionError::RuntimeError(e.to_string()))?;
+        }
+        
+        self.module_programs.insert(url.to_string(), program.clone());
+        self.exports.lock().unwrap().clear(path);
+        self.run_module(path, program)?;
+        
+        let exports = self.exports.lock().unwrap().of(path);
+        for (name, export) in self.hot_modules.lock().unwrap().bindings(path) {
+            if let Some(value) = exports.get(&export) {
+                self.runtime.set_global(&name, value.clone());
+            }
+        }
+        if let Some(module) = self.modules.get_mut(url) {
+            module.exports = exports.clone();
+        }
+        
+        let namespace = self.runtime.create_object(exports);
+        self.hot_modules.lock().unwrap().retain(path, namespace.clone());
+        for callback in &handlers.accept {
+            self.runtime.call_function(callback, &[namespace.clone()])
+                .map_err(|e| BebionError::RuntimeError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Run `source` as the code of the module at URL `url`, such as a
+    /// REPL's synthetic module. Its JavaScript imports go through the
+    /// module loader and its text and byte imports through the resolver,
+    /// both relative to `url`.
+    pub fn execute_as_module(&mut self, url: &str, source: &str) -> Result<GcHandle, BebionError> {
+        let program = self.parse_script(source)?;
+        self.run_module(url, program)
+    }
+
+    /// Run the modules `program` imports, bind its default imports, then
+    /// run the rest of it as the module at URL `url`
+    fn run_module(&mut self, url: &str, mut program: Program) -> Result<GcHandle, BebionError> {
+        let (modules, body): (Vec<_>, Vec<_>) = program.body.into_iter().partition(|statement| {
+            matches!(statement, AstNode::ImportDeclaration { .. }) && statement.import_attribute("type").is_none()
+        });
+        program.body = body;
+        
+        for import in &modules {
+            let AstNode::ImportDeclaration { specifiers, source, .. } = import else {
+                continue;
+            };
+            let AstNode::Literal { value: LiteralValue::String(specifier), .. } = source.as_ref() else {
+                return Err(BebionError::ParseError("Module specifier must be a string".to_string()));
+            };
+            let module = self.import_module(specifier, Some(url))?;
+            if let Some(AstNode::Identifier { name, .. }) = specifiers.first() {
+                let value = module.exports.get("default").cloned().ok_or_else(|| {
+                    BebionError::ModuleError(format!("Module '{}' has no default export", specifier))
+                })?;
+                self.set_global(name, value);
+                self.hot_modules.lock().unwrap().bind(&module.path, name, "default");
+            }
+        }
+        
+        let importer = self.current_module.lock().unwrap().replace(url.to_string());
+        let compiling = self.compiler.module_url().map(String::from);
+        self.compiler.set_module_url(Some(url.to_string()));
+        let result = self.execute_program(&program);
+        self.compiler.set_module_url(compiling);
+        *self.current_module.lock().unwrap() = importer;
+        result
+    }
+
+    /// Enable an experimental language feature for subsequently parsed code
+    pub fn enable_feature(&mut self, feature: Feature) {
+        info!("Enabling experimental feature: {}", feature);
+        self.parser.features_mut().enable(feature);
+    }
+
+    pub fn experimental_features(&self) -> &ExperimentalFeatures {
+        self.parser.features()
+    }
+
+    /// Optimization level for subsequently compiled code
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        info!("Compiling at {:?}", opt_level);
+        self.compiler.set_opt_level(opt_level);
+    }
+
+    pub fn opt_level(&self) -> OptLevel {
+        self.compiler.opt_level()
+    }
+
+    /// Compile subsequent code with `DebugInfo` markers at each statement,
+    /// which the VM tracks as the line it is on
+    pub fn set_debug_info(&mut self, debug_info: bool) {
+        info!("Debug info {}", if debug_info { "enabled" } else { "disabled" });
+        self.compiler.set_debug_info(debug_info);
+    }
+
+    pub fn debug_info(&self) -> bool {
+        self.compiler.debug_info()
+    }
+
+    /// Seed `Math.random` so runs are reproducible; crypto keeps OS entropy
+    pub fn set_random_seed(&mut self, seed: u64) {
+        info!("Seeding Math.random with {}", seed);
+        self.runtime.random().reseed(seed);
+    }
+
+    pub fn random(&self) -> &Arc<HostRandom> {
+        self.runtime.random()
+    }
+
+    /// Freeze `Date.now` at `epoch_ms` milliseconds since the epoch and seed
+    /// `Math.random` from the same instant, so a script sees the same time
+    /// and random numbers on every run. Timers then run in virtual time. A
+    /// later `set_random_seed` replaces the seed.
+    pub fn freeze_time(&mut self, epoch_ms: f64) {
+        info!("Freezing time at {}", epoch_ms);
+        self.runtime.clock().freeze(epoch_ms);
+        self.runtime.random().reseed(epoch_ms as u64);
+        #[cfg(feature = "event-loop")]
+        self.event_loop.use_virtual_time(Arc::clone(self.runtime.clock()));
+    }
+
+    pub fn clock(&self) -> &Arc<HostClock> {
+        self.runtime.clock()
+    }
+
+    /// Execution counts for loops and functions, hottest first
+    pub fn vm_stats(&self) -> VmStats {
+        self.runtime.vm_stats()
+    }
+
+    /// Hit rates of the inline caches at property accesses and calls
+    pub fn ic_stats(&self) -> IcStats {
+        self.runtime.ic_stats()
+    }
+
+    /// Register a tier, such as a JIT, to be notified when code becomes hot
+    pub fn add_tier(&mut self, tier: Box<dyn Tier>) {
+        info!("Registering execution tier: {}", tier.name());
+        self.runtime.add_tier(tier);
+    }
+
+    /// Compile hot functions to native code
+    #[cfg(feature = "jit")]
+    pub fn enable_jit(&mut self) {
+        self.add_tier(Box::new(bebion_jit::JitTier::new()));
+    }
+
+    /// Define the `ffi` global, through which scripts load the native
+    /// libraries that manifests describe
+    #[cfg(feature = "ffi")]
+    pub fn enable_ffi(&mut self) {
+        let manager = Arc::new(Mutex::new(bebion_ffi::FfiManager::new()));
+        bebion_ffi::FfiManager::install(&manager, &mut self.runtime);
+    }
+
+    pub fn set_tier_thresholds(&mut self, thresholds: TierThresholds) {
+        self.runtime.set_tier_thresholds(thresholds);
+    }
+
+    /// Send the engine's events to `observer` as well as any registered
+    /// before it
+    pub fn add_observer(&mut self, observer: impl EngineObserver + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// What to do about promises rejected with no handler
+    pub fn set_unhandled_rejections(&mut self, mode: UnhandledRejections) {
+        info!("Unhandled rejections: {:?}", mode);
+        self.unhandled_rejections = mode;
+    }
+
+    /// Decide the fate of errors a script leaves uncaught and has no
+    /// `process.on` listener for: `handler` returns true to carry on, and
+    /// false to fail the run, or for a rejection to apply
+    /// `set_unhandled_rejections`. Replaces the previous handler.
+    pub fn set_uncaught_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&UncaughtError) -> bool + Send + 'static,
+    {
+        self.uncaught_handler = Some(Box::new(handler));
+    }
+
+    /// Live heap bytes above which observers are sent `GcPressure`
+    pub fn set_gc_pressure_threshold(&mut self, bytes: usize) {
+        self.gc_pressure_threshold = bytes;
+    }
+
+    /// The engine's collector, e.g. to keep a handle alive across runs with
+    /// `GcRoot`. Collections keep what scripts can reach, but not handles
+    /// the host holds.
+    pub fn gc(&self) -> Arc<Mutex<GarbageCollector>> {
+        Arc::clone(&self.gc)
+    }
+
+    /// Tune when the garbage collector runs
+    pub fn set_gc_config(&mut self, config: GcConfig) {
+        self.gc.lock().unwrap().set_config(config);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.runtime.gc_stats()
+    }
+
+    /// The collections since the last call, oldest first
+    pub fn take_gc_events(&mut self) -> Vec<GcEvent> {
+        self.gc.lock().unwrap().take_events()
+    }
+
+    /// Call `listener` as each collection finishes, e.g. to trace them.
+    /// It runs with the collector locked, so it must not use the engine.
+    pub fn set_gc_listener<F>(&mut self, listener: F)
+    where
+        F: FnMut(&GcEvent) + Send + 'static,
+    {
+        self.gc.lock().unwrap().set_collection_listener(Some(Box::new(listener)));
+    }
+
+    fn notify(&mut self, event: EngineEvent) {
+        for observer in &mut self.observers {
+            observer.on_event(&event);
+        }
+    }
+
+    fn check_gc_pressure(&mut self) {
+        let live_bytes = self.gc.lock().unwrap().stats().bytes_allocated;
+        let under_pressure = live_bytes > self.gc_pressure_threshold;
+        if under_pressure && !self.under_gc_pressure {
+            self.notify(EngineEvent::GcPressure { live_bytes, threshold: self.gc_pressure_threshold });
+        }
+        self.under_gc_pressure = under_pressure;
+    }
+
     pub fn gc_collect(&mut self) -> usize {
-        let mut gc = self.gc.lock().unwrap();
-        let collected = gc.collect();
+        let collected = self.runtime.gc_collect();
         debug!("GC collected {} objects", collected);
         collected
     }
 
+    /// The heap objects `filter` accepts, in handle order, for tools
+    /// that inspect what scripts keep alive; `value_of` reads one back
+    pub fn heap_objects(&self, filter: impl Fn(&ObjectInfo) -> bool) -> Vec<ObjectInfo> {
+        self.gc.lock().unwrap().iter_objects(filter).collect()
+    }
+
+    /// Snapshot the heap for memory tools, e.g. to write out as a DevTools
+    /// `.heapsnapshot`
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        self.runtime.heap_snapshot()
+    }
+
+    /// The heap objects matching `query`, largest retainer first
+    pub fn query_heap(&self, query: &HeapQuery) -> Vec<HeapObject> {
+        self.gc.lock().unwrap().query(query)
+    }
+
+    /// The heap objects that reference `handle`
+    pub fn heap_referrers(&self, handle: GcHandle) -> Vec<GcHandle> {
+        self.gc.lock().unwrap().referrers(handle)
+    }
+
     pub fn shutdown(&mut self) {
         info!("Shutting down Bebion Engine");
+        #[cfg(feature = "event-loop")]
         self.event_loop.stop();
         self.gc_collect();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The order a script's `log` array records, once the event loop is idle
+    fn event_order(source: &str) -> serde_json::Value {
+        let mut engine = BebionEngine::new().unwrap();
+        engine.execute_script(&format!("var log = []; {}", source)).unwrap();
+        engine.run_until_idle().unwrap();
+        engine.get_global_json("log").unwrap()
+    }
+
+    // The tree has no `Promise` global yet, so promise reactions come from
+    // `await`, whose continuation is queued as a microtask
+
+    #[test]
+    fn microtasks_run_before_timers() {
+        let order = event_order(
+            "setTimeout(() => log.push('timeout'), 0);
+             async function resume() { await null; log.push('await'); }
+             resume();
+             queueMicrotask(() => log.push('microtask'));
+             log.push('sync');",
+        );
+        assert_eq!(order, serde_json::json!(["sync", "await", "microtask", "timeout"]));
+    }
+
+    #[test]
+    fn microtasks_drain_after_each_timer() {
+        let order = event_order(
+            "setTimeout(() => { log.push('t1'); queueMicrotask(() => log.push('m1')); }, 0);
+             setTimeout(() => { log.push('t2'); queueMicrotask(() => log.push('m2')); }, 0);",
+        );
+        assert_eq!(order, serde_json::json!(["t1", "m1", "t2", "m2"]));
+    }
+
+    #[test]
+    fn microtasks_queued_by_microtasks_run_before_timers() {
+        let order = event_order(
+            "setTimeout(() => log.push('timeout'), 0);
+             async function chain() {
+                 await null;
+                 log.push('a');
+                 queueMicrotask(() => log.push('b'));
+                 await null;
+                 log.push('c');
+             }
+             chain();",
+        );
+        assert_eq!(order, serde_json::json!(["a", "b", "c", "timeout"]));
+    }
+
+    /// The completion value of `source` run in a fresh engine
+    fn evaluate(source: &str) -> serde_json::Value {
+        let mut engine = BebionEngine::new().unwrap();
+        let result = engine.execute_script(source).unwrap();
+        engine.json_of(result).unwrap()
+    }
+
+    #[test]
+    fn functions_read_and_write_script_declarations() {
+        assert_eq!(evaluate("var x = 1; function f() { return x; } f();"), serde_json::json!(1));
+        assert_eq!(
+            evaluate("let n = 0; function g() { n = n + 1; } g(); g(); n;"),
+            serde_json::json!(2)
+        );
+    }
+
+    #[test]
+    fn script_lexical_declarations_are_uninitialized_until_declared() {
+        let result = evaluate(
+            "function early() { return late; }
+             var message;
+             try { early(); } catch (e) { message = e.message; }
+             let late = 1;
+             [message, early()];",
+        );
+        assert_eq!(
+            result,
+            serde_json::json!(["Cannot access 'late' before initialization", 1])
+        );
+    }
+
+    #[test]
+    fn optional_and_computed_calls_bind_this() {
+        let result = evaluate(
+            "var a = { v: 5, m() { return this.v; } }; var k = 'm'; var u;
+             [a.m?.(), a[k](), a?.[k]?.(), a.nope?.(), u?.m()];",
+        );
+        assert_eq!(result, serde_json::json!([5, 5, 5, null, null]));
+    }
+
+    #[test]
+    fn accessor_properties_call_their_getter_and_setter() {
+        let result = evaluate(
+            "var o = { _v: 1, get v() { return this._v * 10; }, set v(x) { this._v = x; } };
+             o.v = 4;
+             var child = { __proto__: o };
+             child.v = 5;
+             var only = { get g() { return 7; } };
+             only.g = 1;
+             var copy = { ...o };
+             o._v = 6;
+             [o.v, child._v, o._v, only.g, copy.v];",
+        );
+        assert_eq!(result, serde_json::json!([60, 5, 6, 7, 40]));
+    }
+
+    #[test]
+    fn default_imports_bind_what_modules_export() {
+        let mut resolver = MemoryResolver::new();
+        resolver.insert("counter.js", "let count = 2; export const step = 3; export default count * step;");
+        resolver.insert("empty.js", "export const unused = 1;");
+        let mut engine = BebionEngine::new().unwrap();
+        engine.set_resolver_hook(resolver);
+
+        let result = engine
+            .execute_as_module("repl.js", "import total from 'counter.js'; total + step;")
+            .unwrap();
+        assert_eq!(engine.json_of(result), Some(serde_json::json!(9)));
+        let module = engine.load_module("counter.js").unwrap();
+        assert_eq!(module.exports.len(), 2);
+
+        let error = engine.execute_as_module("repl.js", "import nothing from 'empty.js';").unwrap_err();
+        assert!(error.to_string().contains("has no default export"));
+        assert!(engine.execute_script("export default 1;").is_err());
+    }
+
+    #[test]
+    fn accepted_modules_reload_in_place() {
+        let greeting = |word: &str| {
+            format!(
+                "let loads = (import.meta.hot.data.loads || 0) + 1;
+                 import.meta.hot.dispose((data) => {{ data.loads = loads; }});
+                 import.meta.hot.accept((module) => {{ updated = module.default; }});
+                 export default '{} ' + loads + ' from ' + import.meta.url;",
+                word
+            )
+        };
+        let resolver = |greeting: &str, config: &str| {
+            let mut resolver = MemoryResolver::new();
+            resolver.insert("main.js", "import config from 'config.js'; import message from 'greeting.js'; var updated; var runs = 1;");
+            resolver.insert("config.js", config);
+            resolver.insert("greeting.js", greeting);
+            resolver
+        };
+        let mut engine = BebionEngine::new().unwrap();
+        engine.set_resolver_hook(resolver(&greeting("hello"), "export default 1;"));
+        engine.load_module("main.js").unwrap();
+        assert_eq!(engine.get_global_json("message"), Some(serde_json::json!("hello 1 from greeting.js")));
+
+        engine.set_resolver_hook(resolver(&format!("  {}", greeting("hello")), "export default 1;"));
+        assert_eq!(engine.reload_module("greeting.js").unwrap(), HotUpdate::Unchanged);
+
+        engine.set_global("runs", Value::Number(5.0));
+        engine.set_resolver_hook(resolver(&greeting("hi"), "export default 2;"));
+        assert_eq!(engine.reload_module("greeting.js").unwrap(), HotUpdate::Applied);
+        assert_eq!(engine.get_global_json("message"), Some(serde_json::json!("hi 2 from greeting.js")));
+        assert_eq!(engine.get_global_json("updated"), Some(serde_json::json!("hi 2 from greeting.js")));
+        assert_eq!(engine.get_global_json("runs"), Some(serde_json::json!(5)));
+        assert_eq!(engine.reload_module("config.js").unwrap(), HotUpdate::Declined);
+        assert_eq!(engine.get_global_json("config"), Some(serde_json::json!(1)));
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn ffi_global_loads_manifests() {
+        let mut engine = BebionEngine::new().unwrap();
+        engine.enable_ffi();
+        let result = engine
+            .execute_script(
+                "var message;
+                 try { ffi.load('{ \"name\": \"missing\", \"path\": \"/no/such/library.so\", \"functions\": [] }'); }
+                 catch (e) { message = e.message; }
+                 message;",
+            )
+            .unwrap();
+        let message = engine.json_of(result).unwrap();
+        assert!(message.as_str().unwrap().contains("/no/such/library.so"), "{}", message);
+    }
+
+    /// The completion value of `source` run in a fresh engine, with hot
+    /// code compiled on its first call or loop iteration when `jit` is set
+    #[cfg(feature = "jit")]
+    fn run(source: &str, jit: bool) -> Option<serde_json::Value> {
+        let mut engine = BebionEngine::new().unwrap();
+        if jit {
+            engine.enable_jit();
+            engine.set_tier_thresholds(TierThresholds { loop_iterations: 1, function_calls: 1 });
+        }
+        let result = engine.execute_script(source).unwrap();
+        engine.json_of(result)
+    }
+
+    #[cfg(feature = "jit")]
+    fn assert_jit_matches_interpreter(source: &str) {
+        assert_eq!(run(source, true), run(source, false), "{}", source);
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn numeric_code_matches_interpreter() {
+        for source in [
+            "function sum(n) { let s = 0; for (let i = 0; i < n; i = i + 1) { s += i; } return s; } sum(10000);",
+            "function fib(n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); } fib(20);",
+            "function f(x) { return x / 0; } [f(1), f(0 - 1), f(0)];",
+            "function f(x, y) { return x % y + x ** 0.5 - x / 2 + y * 3; } let r = 0; for (let i = 1; i < 500; i = i + 1) { r = f(i, 7); } r;",
+            "function f(a, b) { return a < b && b <= 10; } [f(1, 2), f(2, 1), f(1, 11)];",
+            "function f(n) { let x; if (n > 5) { x = n; } return x; } [f(1), f(9)];",
+        ] {
+            assert_jit_matches_interpreter(source);
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn property_access_matches_interpreter() {
+        for source in [
+            // Reads and writes go through the inline caches from native code
+            "const o = { k: 3 }; function f(n) { let s = 0; for (let i = 0; i < n; i = i + 1) { s += o.k; } return s; } f(100);",
+            "const o = { n: 0 }; function f(n) { for (let i = 0; i < n; i = i + 1) { o.n = o.n + i; } return o.n; } [f(100), f(10)];",
+            "const a = [1, 2, 3]; function f(i) { return a[i] * 2; } [f(0), f(1), f(2), f(3)];",
+            // Getters and setters run from the stubs
+            "const o = { n: 1, get k() { return this.n * 2; }, set k(v) { this.n = v; } }; function f(n) { let s = 0; for (let i = 0; i < n; i = i + 1) { o.k = i; s += o.k; } return s; } f(50);",
+            // A read giving something other than a number leaves after the read
+            "const o = { k: 'a' }; function f(n) { let s = 0; for (let i = 0; i < n; i = i + 1) { s = s + o.k; } return s; } f(3);",
+            // A read that throws is caught by the function's own handler
+            "var o; function f(n) { try { return o.k; } catch (e) { return n; } } [f(1), f(2)];",
+        ] {
+            assert_jit_matches_interpreter(source);
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn side_exits_match_interpreter() {
+        for source in [
+            // Calls leave native code mid-frame
+            "function g(x) { return x * 2; } function f(n) { let s = 1; for (let i = 0; i < n; i = i + 1) { s = g(s) % 1000; } return s; } f(50);",
+            // Arguments the code was not compiled for
+            "function f(a, b) { return a + b; } [f(1, 2), f('a', 2), f(1, 2)];",
+            "function f(x) { return x * 2; } for (let i = 0; i < 10; i = i + 1) { f(i); } [f(3), f(undefined), f(true)];",
+        ] {
+            assert_jit_matches_interpreter(source);
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn exceptions_match_interpreter() {
+        assert_jit_matches_interpreter(
+            "function f(n) { if (n > 3) { throw n; } return n; } let r = []; for (let i = 0; i < 6; i = i + 1) { try { r.push(f(i)); } catch (e) { r.push(0 - e); } } r;",
+        );
+    }
+}
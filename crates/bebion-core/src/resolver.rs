@@ -0,0 +1,150 @@
+//! Module resolution
+//!
+//! The engine finds module source through a `ResolverHook`: `resolve` turns
+//! a specifier, as written by the importing module, into the URL that
+//! identifies the module, and `load` fetches the source for that URL.
+//! Modules are cached by URL, so two specifiers resolving to the same URL
+//! share one instance. Hosts that keep code in a database, an archive or
+//! memory install their own hook; `FileSystemResolver` is the default.
+
+use crate::BebionError;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Where a specifier points
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// The module's identity and the key it is cached under
+    pub url: String,
+}
+
+/// A module's code
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// The URL the code came from, which relative specifiers inside it
+    /// resolve against
+    pub url: String,
+    pub code: String,
+}
+
+pub trait ResolverHook: Send + Sync {
+    /// The module `specifier` names when imported from `referrer`, the URL
+    /// of the importing module; `None` for the entry point
+    fn resolve(&self, specifier: &str, referrer: Option<&str>) -> Result<Resolution, BebionError>;
+
+    /// The source of a module `resolve` returned
+    fn load(&self, url: &str) -> Result<Source, BebionError>;
+
+    /// The raw contents behind `url`, for `import ... with { type: "bytes" }`.
+    /// Hooks that can serve binary data override this; the default encodes
+    /// `load`'s source as UTF-8.
+    fn load_bytes(&self, url: &str) -> Result<Vec<u8>, BebionError> {
+        Ok(self.load(url)?.code.into_bytes())
+    }
+}
+
+/// Resolves specifiers to files: relative ones against the importing
+/// module's directory, or against the working directory for the entry
+/// point, trying the path as given, with `.js` and as a directory's
+/// `index.js`. URLs are `file://` URLs of canonical paths.
+#[derive(Debug, Clone, Default)]
+pub struct FileSystemResolver;
+
+impl FileSystemResolver {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn candidates(path: &Path) -> [PathBuf; 3] {
+        let mut with_extension = path.as_os_str().to_owned();
+        with_extension.push(".js");
+        [path.to_path_buf(), PathBuf::from(with_extension), path.join("index.js")]
+    }
+}
+
+const FILE_SCHEME: &str = "file://";
+
+impl ResolverHook for FileSystemResolver {
+    fn resolve(&self, specifier: &str, referrer: Option<&str>) -> Result<Resolution, BebionError> {
+        let path = match (specifier.strip_prefix(FILE_SCHEME), referrer) {
+            (Some(path), _) => PathBuf::from(path),
+            (None, None) => PathBuf::from(specifier),
+            (None, Some(referrer)) => {
+                let is_relative = specifier.starts_with("./") || specifier.starts_with("../");
+                if !is_relative && !Path::new(specifier).is_absolute() {
+                    return Err(BebionError::ModuleError(format!(
+                        "Cannot resolve bare specifier '{}' from {}",
+                        specifier, referrer
+                    )));
+                }
+                let referrer = Path::new(referrer.strip_prefix(FILE_SCHEME).unwrap_or(referrer));
+                referrer.parent().unwrap_or(Path::new("")).join(specifier)
+            }
+        };
+
+        let found = Self::candidates(&path).into_iter().find(|candidate| candidate.is_file());
+        let Some(found) = found else {
+            return Err(BebionError::ModuleError(format!("Cannot find module '{}'", specifier)));
+        };
+        let canonical = found
+            .canonicalize()
+            .map_err(|e| BebionError::ModuleError(format!("Failed to resolve {}: {}", found.display(), e)))?;
+        Ok(Resolution {
+            url: format!("{}{}", FILE_SCHEME, canonical.display()),
+        })
+    }
+
+    fn load(&self, url: &str) -> Result<Source, BebionError> {
+        let path = url.strip_prefix(FILE_SCHEME).unwrap_or(url);
+        let code = std::fs::read_to_string(path)
+            .map_err(|e| BebionError::ModuleError(format!("Failed to read {}: {}", path, e)))?;
+        Ok(Source {
+            url: url.to_string(),
+            code,
+        })
+    }
+
+    fn load_bytes(&self, url: &str) -> Result<Vec<u8>, BebionError> {
+        let path = url.strip_prefix(FILE_SCHEME).unwrap_or(url);
+        std::fs::read(path).map_err(|e| BebionError::ModuleError(format!("Failed to read {}: {}", path, e)))
+    }
+}
+
+/// Serves modules from a map of URL to code. Specifiers are looked up as
+/// given, so the host chooses how they are spelled.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    modules: HashMap<String, String>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, url: impl Into<String>, code: impl Into<String>) {
+        self.modules.insert(url.into(), code.into());
+    }
+}
+
+impl ResolverHook for MemoryResolver {
+    fn resolve(&self, specifier: &str, _referrer: Option<&str>) -> Result<Resolution, BebionError> {
+        if !self.modules.contains_key(specifier) {
+            return Err(BebionError::ModuleError(format!("Cannot find module '{}'", specifier)));
+        }
+        Ok(Resolution {
+            url: specifier.to_string(),
+        })
+    }
+
+    fn load(&self, url: &str) -> Result<Source, BebionError> {
+        let code = self
+            .modules
+            .get(url)
+            .ok_or_else(|| BebionError::ModuleError(format!("Cannot find module '{}'", url)))?;
+        Ok(Source {
+            url: url.to_string(),
+            code: code.clone(),
+        })
+    }
+}
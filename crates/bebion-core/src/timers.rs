@@ -0,0 +1,291 @@
+//! `setTimeout`, `setInterval`, `setImmediate` and their `clear` functions,
+//! `scheduler.wait`, and the timers behind `AbortSignal.timeout`
+//!
+//! The globals record timers in a table shared with the engine. Each turn
+//! of the event loop the engine hands new timers to the `EventLoop` and
+//! cancels cleared ones. A timer firing only marks itself due; the engine
+//! then calls its callback and drains the microtasks the callback queued
+//! before the next timer fires.
+//!
+//! `scheduler.wait(delay, { signal })` is a timer that resolves a promise;
+//! aborting its signal clears it and rejects the promise with the signal's
+//! reason. The timers of `AbortSignal.timeout` do not keep the engine
+//! running, like Node's.
+
+use bebion_gc::GcHandle;
+use bebion_runtime::{AbortSignal, EventLoop, NativeFunction, Runtime, RuntimeError, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub(crate) type SharedTimers = Arc<Mutex<ScriptTimers>>;
+
+/// Timers scheduled from JavaScript
+#[derive(Default)]
+pub(crate) struct ScriptTimers {
+    /// Id of the last timer scheduled; ids start at 1 so they are truthy
+    last_id: u64,
+    timers: HashMap<u64, ScriptTimer>,
+    /// Timers not handed to the event loop yet, and intervals to rearm
+    unscheduled: Vec<u64>,
+    /// Event loop timers of cleared timers, to cancel
+    cancelled: Vec<u64>,
+    /// Timers the event loop has fired, oldest first
+    due: VecDeque<u64>,
+}
+
+struct ScriptTimer {
+    action: TimerAction,
+    delay: Duration,
+    repeat: bool,
+    /// Whether the timer keeps the engine running until it fires
+    referenced: bool,
+    /// The signal that clears the timer when aborted, as the script passed
+    /// it and as the host sees it
+    signal: Option<(Value, AbortSignal)>,
+    /// The event loop's id for the timer while it is scheduled there
+    loop_id: Option<u64>,
+}
+
+/// What a timer does when it fires
+#[derive(Clone)]
+enum TimerAction {
+    /// Call a callback with extra arguments
+    Call { callback: Value, args: Vec<Value> },
+    /// Resolve the promise `scheduler.wait` returned
+    Resolve(GcHandle),
+    /// Abort a signal from `AbortSignal.timeout`
+    Abort(Value),
+}
+
+impl ScriptTimer {
+    fn new(action: TimerAction, delay: Duration) -> Self {
+        Self {
+            action,
+            delay,
+            repeat: false,
+            referenced: true,
+            signal: None,
+            loop_id: None,
+        }
+    }
+}
+
+impl ScriptTimers {
+    fn add(&mut self, timer: ScriptTimer) -> u64 {
+        self.last_id += 1;
+        self.timers.insert(self.last_id, timer);
+        self.unscheduled.push(self.last_id);
+        self.last_id
+    }
+
+    /// Push the objects the timers hold, for the collector
+    fn trace(&self, roots: &mut Vec<GcHandle>) {
+        for timer in self.timers.values() {
+            let values: Vec<&Value> = match &timer.action {
+                TimerAction::Call { callback, args } => std::iter::once(callback).chain(args).collect(),
+                TimerAction::Resolve(promise) => {
+                    roots.push(*promise);
+                    Vec::new()
+                }
+                TimerAction::Abort(signal) => vec![signal],
+            };
+            let signal = timer.signal.as_ref().map(|(signal, _)| signal);
+            roots.extend(values.into_iter().chain(signal).filter_map(Value::as_handle));
+        }
+    }
+
+    fn clear(&mut self, id: u64) -> Option<ScriptTimer> {
+        let timer = self.timers.remove(&id)?;
+        if let Some(loop_id) = timer.loop_id {
+            self.cancelled.push(loop_id);
+        }
+        Some(timer)
+    }
+}
+
+/// Define the timer globals on `runtime` and keep what the timers hold
+/// alive
+pub(crate) fn install(runtime: &mut Runtime, timers: &SharedTimers) {
+    install_scheduler(runtime, timers, "setTimeout", false, true);
+    install_scheduler(runtime, timers, "setInterval", true, true);
+    install_scheduler(runtime, timers, "setImmediate", false, false);
+    for name in ["clearTimeout", "clearInterval", "clearImmediate"] {
+        let timers = Arc::clone(timers);
+        runtime.set_global_function(name, move |_, args| {
+            if let Some(Value::Number(id)) = args.first() {
+                let mut state = timers.lock().unwrap();
+                // Only the timers the ids were handed out for
+                if state.timers.get(&(*id as u64)).is_some_and(|timer| matches!(timer.action, TimerAction::Call { .. })) {
+                    state.clear(*id as u64);
+                }
+            }
+            Ok(Value::Undefined)
+        });
+    }
+    install_wait(runtime, timers);
+
+    let traced = Arc::clone(timers);
+    runtime.add_root_source(move |roots| traced.lock().unwrap().trace(roots));
+}
+
+/// Define `name(callback, [delay,] ...args)`, which schedules `callback`
+/// and returns the timer's id
+fn install_scheduler(runtime: &mut Runtime, timers: &SharedTimers, name: &'static str, repeat: bool, takes_delay: bool) {
+    let timers = Arc::clone(timers);
+    runtime.set_global_function(name, move |runtime, args| {
+        let callback = args.first().cloned().unwrap_or(Value::Undefined);
+        if !runtime.is_callable(&callback) {
+            return Err(RuntimeError::TypeError(format!(
+                "The \"callback\" argument of {} must be a function",
+                name
+            )));
+        }
+
+        let (delay, extra) = if takes_delay {
+            let delay = args.get(1).map_or(Ok(0.0), |delay| delay.to_number())?;
+            (delay, args.get(2..).unwrap_or_default())
+        } else {
+            (0.0, args.get(1..).unwrap_or_default())
+        };
+        // Like Node, delays that are not positive finite numbers mean 1ms
+        let delay = if delay.is_finite() && delay >= 1.0 { delay as u64 } else { u64::from(takes_delay) };
+
+        let action = TimerAction::Call { callback, args: extra.to_vec() };
+        let id = timers.lock().unwrap().add(ScriptTimer {
+            repeat,
+            ..ScriptTimer::new(action, Duration::from_millis(delay))
+        });
+        Ok(Value::Number(id as f64))
+    });
+}
+
+/// Define `scheduler.wait(delay, { signal })`, which returns a promise that
+/// resolves once `delay` milliseconds have passed, or rejects with the
+/// signal's reason if it is aborted first
+fn install_wait(runtime: &mut Runtime, timers: &SharedTimers) {
+    let timers = Arc::clone(timers);
+    let wait = NativeFunction::new("wait", move |runtime, args| {
+        let delay = args.first().map_or(Ok(0.0), |delay| delay.to_number())?;
+        let delay = if delay.is_finite() && delay >= 0.0 { delay as u64 } else { 0 };
+        let signal = match args.get(1) {
+            Some(options @ Value::Object(_)) => match runtime.get_property(options, "signal")? {
+                Value::Undefined => None,
+                signal => {
+                    let host = runtime.abort_signal(&signal).ok_or_else(|| {
+                        RuntimeError::TypeError("The \"signal\" option must be an AbortSignal".to_string())
+                    })?;
+                    Some((signal, host))
+                }
+            },
+            _ => None,
+        };
+
+        let promise = runtime.create_promise();
+        match signal {
+            Some((signal, host)) if host.is_aborted() => {
+                let reason = runtime.abort_reason(&signal);
+                runtime.reject_promise(promise, reason);
+            }
+            signal => {
+                timers.lock().unwrap().add(ScriptTimer {
+                    signal,
+                    ..ScriptTimer::new(TimerAction::Resolve(promise), Duration::from_millis(delay))
+                });
+            }
+        }
+        Ok(Value::Object(promise))
+    });
+    let scheduler = runtime.create_object(HashMap::from([("wait".to_string(), Value::NativeFunction(wait))]));
+    runtime.set_global("scheduler", scheduler);
+}
+
+/// Take up the timers of `AbortSignal.timeout` signals created since the
+/// last call, and clear the timers whose signal has been aborted,
+/// rejecting their promises
+pub(crate) fn sync_signals(timers: &SharedTimers, runtime: &mut Runtime) {
+    let aborted: Vec<ScriptTimer> = {
+        let mut state = timers.lock().unwrap();
+        for (signal, milliseconds) in runtime.take_abort_timeouts() {
+            state.add(ScriptTimer {
+                referenced: false,
+                ..ScriptTimer::new(TimerAction::Abort(signal), Duration::from_millis(milliseconds as u64))
+            });
+        }
+        let ids: Vec<u64> = state
+            .timers
+            .iter()
+            .filter(|(_, timer)| timer.signal.as_ref().is_some_and(|(_, host)| host.is_aborted()))
+            .map(|(&id, _)| id)
+            .collect();
+        ids.into_iter().filter_map(|id| state.clear(id)).collect()
+    };
+    for timer in aborted {
+        if let (TimerAction::Resolve(promise), Some((signal, _))) = (timer.action, timer.signal) {
+            let reason = runtime.abort_reason(&signal);
+            runtime.reject_promise(promise, reason);
+        }
+    }
+}
+
+/// Hand timers scheduled since the last call to the event loop, and cancel
+/// the ones cleared since
+pub(crate) fn schedule(timers: &SharedTimers, event_loop: &mut EventLoop) {
+    let mut state = timers.lock().unwrap();
+    for loop_id in std::mem::take(&mut state.cancelled) {
+        event_loop.clear_timeout(loop_id);
+    }
+    for id in std::mem::take(&mut state.unscheduled) {
+        let Some(timer) = state.timers.get_mut(&id) else {
+            continue;
+        };
+        let due = Arc::clone(timers);
+        let loop_id = event_loop.set_timeout(move || due.lock().unwrap().due.push_back(id), timer.delay);
+        if !timer.referenced {
+            event_loop.unref_timer(loop_id);
+        }
+        timer.loop_id = Some(loop_id);
+    }
+}
+
+/// Call the callbacks of the timers that have fired, resolve their promises
+/// and abort their signals, reporting the errors callbacks threw as job
+/// errors. Intervals are rearmed before their callback runs, so the
+/// callback can clear them.
+pub(crate) fn run_due(timers: &SharedTimers, runtime: &mut Runtime) {
+    loop {
+        // The callback may schedule or clear timers, so the table is not
+        // locked while it runs
+        let action = {
+            let mut state = timers.lock().unwrap();
+            let Some(id) = state.due.pop_front() else {
+                break;
+            };
+            let Some(timer) = state.timers.get_mut(&id) else {
+                continue;
+            };
+            if timer.repeat {
+                timer.loop_id = None;
+                let action = timer.action.clone();
+                state.unscheduled.push(id);
+                action
+            } else {
+                state.timers.remove(&id).expect("due timer").action
+            }
+        };
+        let result = match action {
+            TimerAction::Call { callback, args } => runtime.call_function(&callback, &args).map(drop),
+            TimerAction::Resolve(promise) => {
+                runtime.resolve_promise(promise, Value::Undefined);
+                Ok(())
+            }
+            TimerAction::Abort(signal) => {
+                let reason = runtime.create_error("TimeoutError", "The operation was aborted due to timeout");
+                runtime.abort(&signal, reason)
+            }
+        };
+        if let Err(error) = result {
+            runtime.report_job_error(error);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Last-chance handlers for errors nothing caught
+//!
+//! Scripts install them with `process.on("uncaughtException", listener)`
+//! and `process.on("unhandledRejection", listener)`, and remove them with
+//! `process.off`. Hosts install one with `BebionEngine::set_uncaught_handler`.
+//!
+//! An exception that escapes a script, a timer callback or a microtask goes
+//! to the `uncaughtException` listeners as `(error, "uncaughtException")`.
+//! A promise rejected with no handler by the end of a tick goes to the
+//! `unhandledRejection` listeners as `(reason, promise)`. When a script has
+//! listeners for the event, they handle it and the engine carries on;
+//! otherwise the host's handler decides, and without one the engine does
+//! what it always has: fail the run, or apply `UnhandledRejections`.
+//!
+//! Listeners run in the order they were added, from a snapshot taken when
+//! the error arrives, so adding or removing listeners takes effect for the
+//! next error. A listener that throws ends the dispatch: an exception
+//! thrown by an `unhandledRejection` listener goes to the
+//! `uncaughtException` listeners, but one thrown by an
+//! `uncaughtException` listener fails the run, so a broken handler cannot
+//! loop. Errors scripts cannot catch, such as running out of memory, skip
+//! the script's listeners.
+
+use bebion_gc::GcHandle;
+use bebion_runtime::{NativeFunction, Runtime, RuntimeError, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub(crate) type SharedHooks = Arc<Mutex<ErrorHooks>>;
+
+/// An error that reached the host's handler
+#[derive(Debug, Clone, PartialEq)]
+pub enum UncaughtError {
+    /// An exception nothing caught, described as `name: message`
+    Exception { message: String },
+    /// A promise rejected with no handler, with its reason described
+    Rejection { reason: String },
+}
+
+/// What a host's handler tells the engine to do about an uncaught error
+pub type UncaughtHandler = Box<dyn FnMut(&UncaughtError) -> bool + Send>;
+
+/// The events scripts can listen for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorEvent {
+    UncaughtException,
+    UnhandledRejection,
+}
+
+impl ErrorEvent {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "uncaughtException" => Some(ErrorEvent::UncaughtException),
+            "unhandledRejection" => Some(ErrorEvent::UnhandledRejection),
+            _ => None,
+        }
+    }
+}
+
+/// The listeners scripts added, and the values being handed to them
+#[derive(Default)]
+pub(crate) struct ErrorHooks {
+    uncaught_exception: Vec<Value>,
+    unhandled_rejection: Vec<Value>,
+    /// Errors and rejections waiting for or being handed to listeners,
+    /// kept alive until the listeners are done
+    pending: Vec<Value>,
+    /// Whether `uncaughtException` listeners are running now
+    dispatching: bool,
+}
+
+impl ErrorHooks {
+    fn listeners_mut(&mut self, event: ErrorEvent) -> &mut Vec<Value> {
+        match event {
+            ErrorEvent::UncaughtException => &mut self.uncaught_exception,
+            ErrorEvent::UnhandledRejection => &mut self.unhandled_rejection,
+        }
+    }
+
+    fn trace(&self, roots: &mut Vec<GcHandle>) {
+        let values = self.uncaught_exception.iter().chain(&self.unhandled_rejection).chain(&self.pending);
+        roots.extend(values.filter_map(Value::as_handle));
+    }
+}
+
+/// Define `process.on` and `process.off` on `runtime` and keep the
+/// listeners alive
+pub(crate) fn install(runtime: &mut Runtime, hooks: &SharedHooks) {
+    let on = {
+        let hooks = Arc::clone(hooks);
+        NativeFunction::new("on", move |runtime, args| {
+            let (event, listener) = event_arguments(runtime, args)?;
+            hooks.lock().unwrap().listeners_mut(event).push(listener);
+            Ok(Value::Undefined)
+        })
+    };
+    let off = {
+        let hooks = Arc::clone(hooks);
+        NativeFunction::new("off", move |runtime, args| {
+            let (event, listener) = event_arguments(runtime, args)?;
+            let mut hooks = hooks.lock().unwrap();
+            let listeners = hooks.listeners_mut(event);
+            if let Some(index) = listeners.iter().rposition(|added| added.strict_equals(&listener)) {
+                listeners.remove(index);
+            }
+            Ok(Value::Undefined)
+        })
+    };
+    let process = runtime.create_object(HashMap::from([
+        ("on".to_string(), Value::NativeFunction(on)),
+        ("off".to_string(), Value::NativeFunction(off)),
+    ]));
+    runtime.set_global("process", process);
+
+    let traced = Arc::clone(hooks);
+    runtime.add_root_source(move |roots| traced.lock().unwrap().trace(roots));
+}
+
+/// The event and listener `process.on` and `process.off` were called with
+fn event_arguments(runtime: &Runtime, args: &[Value]) -> Result<(ErrorEvent, Value), RuntimeError> {
+    let name = args.first().map(Value::to_string).unwrap_or_default();
+    let event = ErrorEvent::from_name(&name).ok_or_else(|| {
+        RuntimeError::TypeError(format!(
+            "Unsupported process event '{}': expected 'uncaughtException' or 'unhandledRejection'",
+            name
+        ))
+    })?;
+    match args.get(1) {
+        Some(listener) if runtime.is_callable(listener) => Ok((event, listener.clone())),
+        _ => Err(RuntimeError::TypeError("The \"listener\" argument must be a function".to_string())),
+    }
+}
+
+/// Hand `exception` to the script's `uncaughtException` listeners.
+/// Returns `Ok(true)` if they handled it, `Ok(false)` if there are none to
+/// (or it is `None`, for an error scripts cannot catch), and the error a
+/// listener threw if one did.
+pub(crate) fn dispatch_exception(
+    runtime: &mut Runtime,
+    hooks: &SharedHooks,
+    exception: Option<Value>,
+) -> Result<bool, RuntimeError> {
+    let Some(exception) = exception else {
+        return Ok(false);
+    };
+    let listeners = {
+        let hooks = hooks.lock().unwrap();
+        if hooks.dispatching || hooks.uncaught_exception.is_empty() {
+            return Ok(false);
+        }
+        hooks.uncaught_exception.clone()
+    };
+
+    {
+        let mut hooks = hooks.lock().unwrap();
+        hooks.dispatching = true;
+        hooks.pending.push(exception.clone());
+    }
+    let result = listeners
+        .iter()
+        .try_for_each(|listener| {
+            runtime.call_function(listener, &[exception.clone(), Value::from("uncaughtException")]).map(drop)
+        });
+    {
+        let mut hooks = hooks.lock().unwrap();
+        hooks.dispatching = false;
+        hooks.pending.pop();
+    }
+    result.map(|_| true)
+}
+
+/// Hand `rejections` to the script's `unhandledRejection` listeners.
+/// Returns the ones there are no listeners for, and the error a listener
+/// threw if one did.
+pub(crate) fn dispatch_rejections(
+    runtime: &mut Runtime,
+    hooks: &SharedHooks,
+    rejections: Vec<(GcHandle, Value)>,
+) -> Result<Vec<(GcHandle, Value)>, RuntimeError> {
+    let listeners = hooks.lock().unwrap().unhandled_rejection.clone();
+    if listeners.is_empty() || rejections.is_empty() {
+        return Ok(rejections);
+    }
+
+    let pending: Vec<Value> = rejections
+        .iter()
+        .flat_map(|(promise, reason)| [Value::Object(*promise), reason.clone()])
+        .collect();
+    let count = pending.len();
+    hooks.lock().unwrap().pending.extend(pending);
+    let result = rejections.iter().try_for_each(|(promise, reason)| {
+        listeners
+            .iter()
+            .try_for_each(|listener| runtime.call_function(listener, &[reason.clone(), Value::Object(*promise)]).map(drop))
+    });
+    let mut hooks = hooks.lock().unwrap();
+    let kept = hooks.pending.len() - count;
+    hooks.pending.truncate(kept);
+    result.map(|_| Vec::new())
+}
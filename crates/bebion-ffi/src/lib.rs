@@ -2,12 +2,18 @@
 //! 
 //! Provides integration with native modules and WASI.
 
+pub mod manifest;
 pub mod native;
+#[cfg(feature = "wasi")]
 pub mod wasi;
 
-use bebion_runtime::{Runtime, Value};
+pub use manifest::{FunctionManifest, LibraryManifest, ManifestFormat};
+
+use bebion_runtime::{NativeFunction, Runtime, RuntimeError, Value};
 use std::collections::HashMap;
 use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone)]
 pub enum FfiError {
@@ -16,6 +22,7 @@ pub enum FfiError {
     InvalidArguments(String),
     RuntimeError(String),
     WasmError(String),
+    InvalidManifest(String),
 }
 
 impl fmt::Display for FfiError {
@@ -26,6 +33,7 @@ impl fmt::Display for FfiError {
             FfiError::InvalidArguments(msg) => write!(f, "Invalid arguments: {}", msg),
             FfiError::RuntimeError(msg) => write!(f, "Runtime error: {}", msg),
             FfiError::WasmError(msg) => write!(f, "WASM error: {}", msg),
+            FfiError::InvalidManifest(msg) => write!(f, "Invalid manifest: {}", msg),
         }
     }
 }
@@ -37,6 +45,10 @@ pub type FfiResult<T> = Result<T, FfiError>;
 /// FFI manager for handling native libraries and WASI modules
 pub struct FfiManager {
     native_libraries: HashMap<String, native::NativeLibrary>,
+    /// Manifests by library name, whose functions are registered whenever
+    /// the library is loaded
+    manifests: HashMap<String, LibraryManifest>,
+    #[cfg(feature = "wasi")]
     wasi_modules: HashMap<String, wasi::WasiModule>,
 }
 
@@ -44,18 +56,100 @@ impl FfiManager {
     pub fn new() -> Self {
         Self {
             native_libraries: HashMap::new(),
+            manifests: HashMap::new(),
+            #[cfg(feature = "wasi")]
             wasi_modules: HashMap::new(),
         }
     }
 
-    /// Load a native library
+    /// Load a native library, registering the functions of its manifest
     pub fn load_native_library(&mut self, name: &str, path: &str) -> FfiResult<()> {
-        let library = native::NativeLibrary::load(path)?;
+        let mut library = native::NativeLibrary::load(path)?;
+        if let Some(manifest) = self.manifests.get(name) {
+            for signature in manifest.signatures() {
+                library.register_function(signature)?;
+            }
+        }
         self.native_libraries.insert(name.to_string(), library);
         Ok(())
     }
 
+    /// Register `manifest` for its library, then load the library from the
+    /// manifest's path. Returns the name the library was loaded under.
+    pub fn register_manifest(&mut self, manifest: LibraryManifest) -> FfiResult<String> {
+        let (name, path) = (manifest.name.clone(), manifest.path.clone());
+        self.manifests.insert(name.clone(), manifest);
+        self.load_native_library(&name, &path)?;
+        Ok(name)
+    }
+
+    /// Read the JSON or TOML manifest at `path` and load its library, see
+    /// `register_manifest`
+    pub fn load_manifest(&mut self, path: impl AsRef<Path>) -> FfiResult<String> {
+        let manifest = LibraryManifest::read(path.as_ref())?;
+        self.register_manifest(manifest)
+    }
+
+    /// Write the functions registered for `library` to `path` as a manifest,
+    /// in the format the extension names
+    pub fn save_manifest(&self, library: &str, path: impl AsRef<Path>) -> FfiResult<()> {
+        let lib = self.native_libraries.get(library)
+            .ok_or_else(|| FfiError::LibraryNotFound(library.to_string()))?;
+        let mut functions: Vec<FunctionManifest> = lib.get_function_names()
+            .iter()
+            .filter_map(|name| lib.get_function_signature(name))
+            .map(FunctionManifest::from)
+            .collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        let manifest = LibraryManifest {
+            name: library.to_string(),
+            path: lib.path().to_string(),
+            functions,
+        };
+        manifest.write(path.as_ref())
+    }
+
+    /// Define the `ffi` global on `runtime`. `ffi.load(manifest)` takes the
+    /// path of a manifest, or the JSON text of one, loads its library and
+    /// returns an object with a function for each function it lists.
+    pub fn install(manager: &Arc<Mutex<FfiManager>>, runtime: &mut Runtime) {
+        let manager = Arc::clone(manager);
+        let load = NativeFunction::new("load", move |runtime, args| {
+            let manifest = match args.first() {
+                Some(Value::String(manifest)) => manifest.to_rust_string(),
+                _ => return Err(RuntimeError::TypeError("ffi.load expects a manifest path or JSON".to_string())),
+            };
+            let manifest = if manifest.trim_start().starts_with('{') {
+                LibraryManifest::parse(&manifest, ManifestFormat::Json)
+            } else {
+                LibraryManifest::read(Path::new(&manifest))
+            }
+            .map_err(|e| RuntimeError::Error(e.to_string()))?;
+
+            let functions: Vec<String> = manifest.functions.iter().map(|function| function.name.clone()).collect();
+            let library = manager.lock().unwrap()
+                .register_manifest(manifest)
+                .map_err(|e| RuntimeError::Error(e.to_string()))?;
+            let bindings = functions
+                .into_iter()
+                .map(|function| {
+                    let (manager, library, name) = (Arc::clone(&manager), library.clone(), function.clone());
+                    let binding = NativeFunction::new(function.clone(), move |_, args| {
+                        manager.lock().unwrap()
+                            .call_native_function(&library, &name, args.to_vec())
+                            .map_err(|e| RuntimeError::Error(e.to_string()))
+                    });
+                    (function, Value::NativeFunction(binding))
+                })
+                .collect();
+            Ok(runtime.create_object(bindings))
+        });
+        let ffi = runtime.create_object(HashMap::from([("load".to_string(), Value::NativeFunction(load))]));
+        runtime.set_global("ffi", ffi);
+    }
+
     /// Load a WASI module
+    #[cfg(feature = "wasi")]
     pub fn load_wasi_module(&mut self, name: &str, path: &str) -> FfiResult<()> {
         let module = wasi::WasiModule::load(path)?;
         self.wasi_modules.insert(name.to_string(), module);
@@ -76,6 +170,7 @@ impl FfiManager {
     }
 
     /// Call a WASI function
+    #[cfg(feature = "wasi")]
     pub fn call_wasi_function(
         &mut self,
         module: &str,
@@ -92,9 +187,12 @@ impl FfiManager {
     pub fn initialize_runtime(&self, runtime: &mut Runtime) -> FfiResult<()> {
         // Set up global FFI functions
         runtime.set_global("loadNativeLibrary", Value::Undefined); // Would need proper function
-        runtime.set_global("loadWasiModule", Value::Undefined);
         runtime.set_global("callNative", Value::Undefined);
-        runtime.set_global("callWasi", Value::Undefined);
+        #[cfg(feature = "wasi")]
+        {
+            runtime.set_global("loadWasiModule", Value::Undefined);
+            runtime.set_global("callWasi", Value::Undefined);
+        }
         
         Ok(())
     }
@@ -105,6 +203,7 @@ impl FfiManager {
     }
 
     /// Get list of loaded WASI modules
+    #[cfg(feature = "wasi")]
     pub fn get_loaded_modules(&self) -> Vec<String> {
         self.wasi_modules.keys().cloned().collect()
     }
@@ -0,0 +1,130 @@
+//! Library manifests
+//!
+//! A manifest describes a native library: the name it is loaded under, the
+//! path of the shared library, and the signature and documentation of each
+//! function to bind. A library path with a directory part is relative to
+//! the manifest; a bare file name is left to the system's library search.
+//! Manifests are JSON or TOML, told apart by the file extension:
+//!
+//! ```toml
+//! name = "m"
+//! path = "libm.so.6"
+//!
+//! [[functions]]
+//! name = "cos"
+//! parameters = ["float64"]
+//! returns = "float64"
+//! doc = "Cosine of an angle in radians"
+//! ```
+//!
+//! A function without `returns` returns nothing.
+
+use crate::native::{FunctionSignature, NativeType};
+use crate::{FfiError, FfiResult};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Description of a native library and the functions bound from it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryManifest {
+    pub name: String,
+    pub path: String,
+    #[serde(default)]
+    pub functions: Vec<FunctionManifest>,
+}
+
+/// One function of a `LibraryManifest`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionManifest {
+    pub name: String,
+    #[serde(default)]
+    pub parameters: Vec<NativeType>,
+    #[serde(default = "void")]
+    pub returns: NativeType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+}
+
+fn void() -> NativeType {
+    NativeType::Void
+}
+
+/// How a manifest is written down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Json,
+    Toml,
+}
+
+impl ManifestFormat {
+    /// The format of the manifest at `path`: TOML for a `.toml` file, JSON
+    /// otherwise
+    pub fn of_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => ManifestFormat::Toml,
+            _ => ManifestFormat::Json,
+        }
+    }
+}
+
+impl LibraryManifest {
+    /// Parse a manifest written in `format`
+    pub fn parse(text: &str, format: ManifestFormat) -> FfiResult<Self> {
+        let parsed = match format {
+            ManifestFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            ManifestFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+        };
+        parsed.map_err(FfiError::InvalidManifest)
+    }
+
+    /// Write the manifest down in `format`
+    pub fn render(&self, format: ManifestFormat) -> FfiResult<String> {
+        let rendered = match format {
+            ManifestFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            ManifestFormat::Toml => toml::to_string_pretty(self).map_err(|e| e.to_string()),
+        };
+        rendered.map_err(FfiError::InvalidManifest)
+    }
+
+    /// Read the manifest at `path`, resolving the library path against the
+    /// manifest's directory
+    pub fn read(path: &Path) -> FfiResult<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| FfiError::InvalidManifest(format!("Failed to read {}: {}", path.display(), e)))?;
+        let mut manifest = Self::parse(&text, ManifestFormat::of_path(path))?;
+        let library = Path::new(&manifest.path);
+        if let (Some(directory), true) = (path.parent(), library.components().count() > 1) {
+            let resolved = directory.join(library).to_string_lossy().into_owned();
+            manifest.path = resolved;
+        }
+        Ok(manifest)
+    }
+
+    /// Write the manifest to `path`, in the format its extension names
+    pub fn write(&self, path: &Path) -> FfiResult<()> {
+        let text = self.render(ManifestFormat::of_path(path))?;
+        std::fs::write(path, text)
+            .map_err(|e| FfiError::InvalidManifest(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    /// The signatures to register for the functions
+    pub fn signatures(&self) -> impl Iterator<Item = FunctionSignature> + '_ {
+        self.functions.iter().map(|function| FunctionSignature {
+            name: function.name.clone(),
+            parameter_types: function.parameters.clone(),
+            return_type: function.returns.clone(),
+            doc: function.doc.clone(),
+        })
+    }
+}
+
+impl From<&FunctionSignature> for FunctionManifest {
+    fn from(signature: &FunctionSignature) -> Self {
+        Self {
+            name: signature.name.clone(),
+            parameters: signature.parameter_types.clone(),
+            returns: signature.return_type.clone(),
+            doc: signature.doc.clone(),
+        }
+    }
+}
@@ -3,6 +3,7 @@
 use crate::{FfiError, FfiResult};
 use bebion_runtime::Value;
 use libloading::{Library, Symbol};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_double, c_int, c_void};
@@ -11,6 +12,7 @@ use tracing::{debug, error};
 /// Represents a loaded native library
 pub struct NativeLibrary {
     library: Library,
+    path: String,
     functions: HashMap<String, FunctionSignature>,
 }
 
@@ -20,10 +22,13 @@ pub struct FunctionSignature {
     pub name: String,
     pub parameter_types: Vec<NativeType>,
     pub return_type: NativeType,
+    /// What the function does, for generated bindings
+    pub doc: Option<String>,
 }
 
 /// Native data types supported by the FFI
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum NativeType {
     Void,
     Int32,
@@ -54,6 +59,7 @@ impl NativeLibrary {
 
         Ok(Self {
             library,
+            path: path.to_string(),
             functions: HashMap::new(),
         })
     }
@@ -70,8 +76,8 @@ impl NativeLibrary {
                 .map_err(|_| FfiError::SymbolNotFound(signature.name.clone()))?;
         }
 
-        self.functions.insert(signature.name.clone(), signature);
         debug!("Registered function: {}", &signature.name);
+        self.functions.insert(signature.name.clone(), signature);
         
         Ok(())
     }
@@ -113,7 +119,7 @@ impl NativeLibrary {
                 (Value::Number(n), NativeType::Int32) => NativeArg::Int32(*n as i32),
                 (Value::Number(n), NativeType::Float64) => NativeArg::Float64(*n),
                 (Value::String(s), NativeType::String) => {
-                    let c_string = CString::new(s.as_str())
+                    let c_string = CString::new(s.to_rust_string())
                         .map_err(|_| FfiError::InvalidArguments("Invalid string argument".to_string()))?;
                     NativeArg::String(c_string)
                 }
@@ -157,7 +163,7 @@ impl NativeLibrary {
                 Ok(Value::Number(result as f64))
             }
             ([], NativeType::Float64) => {
-                let func: Symbol<FloatFloatFn> = self.library
+                let func: Symbol<FloatFn> = self.library
                     .get(symbol_name.as_bytes())
                     .map_err(|_| FfiError::SymbolNotFound(name.to_string()))?;
                 let result = func();
@@ -173,7 +179,7 @@ impl NativeLibrary {
                 } else {
                     let c_str = CStr::from_ptr(result_ptr);
                     let rust_str = c_str.to_string_lossy().into_owned();
-                    Ok(Value::String(rust_str))
+                    Ok(Value::from(rust_str))
                 }
             }
 
@@ -214,7 +220,7 @@ impl NativeLibrary {
                     } else {
                         let c_str = CStr::from_ptr(result_ptr);
                         let rust_str = c_str.to_string_lossy().into_owned();
-                        Ok(Value::String(rust_str))
+                        Ok(Value::from(rust_str))
                     }
                 } else {
                     Err(FfiError::InvalidArguments("Expected string argument".to_string()))
@@ -240,6 +246,11 @@ impl NativeLibrary {
     pub fn get_function_signature(&self, name: &str) -> Option<&FunctionSignature> {
         self.functions.get(name)
     }
+
+    /// Path the library was loaded from
+    pub fn path(&self) -> &str {
+        &self.path
+    }
 }
 
 /// Native argument wrapper
@@ -260,6 +271,7 @@ impl FunctionSignature {
             name,
             parameter_types,
             return_type,
+            doc: None,
         }
     }
 }
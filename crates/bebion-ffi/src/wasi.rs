@@ -3,7 +3,7 @@
 use crate::{FfiError, FfiResult};
 use bebion_runtime::Value;
 use std::collections::HashMap;
-use tracing::{debug, error};
+use tracing::debug;
 
 #[cfg(not(target_family = "wasm"))]
 use wasmtime::{Engine, Instance, Linker, Module, Store, WasmParams, WasmResults};
@@ -42,20 +42,10 @@ impl WasiModule {
             let module = Module::from_file(&engine, path)
                 .map_err(|e| FfiError::WasmError(format!("Failed to load module: {}", e)))?;
 
-            let mut linker = Linker::new(&engine);
-            
-            // Add WASI imports
-            wasmtime_wasi::add_to_linker(&mut linker, |s| s)
-                .map_err(|e| FfiError::WasmError(format!("Failed to add WASI to linker: {}", e)))?;
-
-            let wasi = wasmtime_wasi::WasiCtxBuilder::new()
-                .inherit_stdio()
-                .inherit_args()
-                .map_err(|e| FfiError::WasmError(format!("Failed to create WASI context: {}", e)))?
-                .build();
-
+            // No WASI implementation is linked in, so only modules that
+            // import nothing from the host instantiate
+            let linker = Linker::new(&engine);
             let mut store = Store::new(&engine, WasiState::default());
-            store.data_mut().wasi = wasi;
 
             let instance = linker
                 .instantiate(&mut store, &module)
@@ -146,7 +136,7 @@ impl WasiModule {
                     if n.fract() == 0.0 && *n >= i32::MIN as f64 && *n <= i32::MAX as f64 {
                         wasmtime::Val::I32(*n as i32)
                     } else {
-                        wasmtime::Val::F64(*n)
+                        wasmtime::Val::F64(n.to_bits())
                     }
                 }
                 Value::Boolean(b) => wasmtime::Val::I32(if *b { 1 } else { 0 }),
@@ -169,7 +159,7 @@ impl WasiModule {
             wasmtime::Val::I32(i) => Ok(Value::Number(*i as f64)),
             wasmtime::Val::I64(i) => Ok(Value::Number(*i as f64)),
             wasmtime::Val::F32(f) => Ok(Value::Number(*f as f64)),
-            wasmtime::Val::F64(f) => Ok(Value::Number(*f)),
+            wasmtime::Val::F64(f) => Ok(Value::Number(f64::from_bits(*f))),
             _ => Err(FfiError::InvalidArguments(
                 "Unsupported WASM return type".to_string()
             )),
@@ -227,19 +217,6 @@ impl WasiModule {
     }
 }
 
-// Add the missing WasiState field
-#[cfg(not(target_family = "wasm"))]
-impl WasiState {
-    fn new() -> Self {
-        Self::default()
-    }
-}
-
-#[cfg(not(target_family = "wasm"))]
-impl WasiState {
-    pub wasi: wasmtime_wasi::WasiCtx,
-}
-
 // Helper functions for common WASI patterns
 impl WasiModule {
     /// Create a simple calculator WASI module interface
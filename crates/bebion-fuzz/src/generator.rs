@@ -0,0 +1,292 @@
+//! Random programs for differential runs
+//!
+//! Programs only use deterministic, fully specified parts of the language.
+//! They always terminate: loops count up to a small bound, and functions
+//! see only their parameters and locals and call only functions declared
+//! before them. Compound expressions are fully parenthesized so operator
+//! precedence never makes a program invalid. The last statement joins the
+//! top-level variables into the completion value, so a divergence in any
+//! of them shows.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Numbers that tend to find bugs in conversions and arithmetic
+pub const INTERESTING_NUMBERS: &[&str] = &[
+    "0", "1", "2", "3", "7", "10", "255", "0.5", "0.1", "1.5", "2147483647", "2147483648", "4294967295",
+    "4294967296", "9007199254740991", "1e21", "1e-7", "5e-324",
+];
+
+/// Strings that tend to find bugs in conversions and comparisons
+pub const INTERESTING_STRINGS: &[&str] = &["", "a", "abc", "0", "1", "10", " ", "-1", "1e3", "NaN", "true", "null"];
+
+const BINARY_OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "%", "**", "<", ">", "<=", ">=", "==", "!=", "===", "!==", "&", "|", "^", "<<", ">>",
+    ">>>", "&&", "||", "??",
+];
+
+const UNARY_OPERATORS: &[&str] = &["-", "+", "!", "~", "typeof "];
+
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// Most statements in any one block
+    pub max_statements: usize,
+    /// Deepest nesting of blocks, and of expressions
+    pub max_depth: usize,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            max_statements: 8,
+            max_depth: 3,
+        }
+    }
+}
+
+struct Variable {
+    name: String,
+    /// Loop counters and catch parameters are read but never assigned
+    assignable: bool,
+}
+
+pub struct Generator {
+    rng: StdRng,
+    config: GeneratorConfig,
+    /// Variables in scope, innermost block last
+    scopes: Vec<Vec<Variable>>,
+    /// Functions declared so far, with their parameter counts
+    functions: Vec<(String, usize)>,
+    next_id: usize,
+    try_depth: usize,
+    output: String,
+    indent: usize,
+}
+
+impl Generator {
+    pub fn new(seed: u64, config: GeneratorConfig) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            config,
+            scopes: Vec::new(),
+            functions: Vec::new(),
+            next_id: 0,
+            try_depth: 0,
+            output: String::new(),
+            indent: 0,
+        }
+    }
+
+    /// The next program
+    pub fn generate(&mut self) -> String {
+        self.scopes = vec![Vec::new()];
+        self.functions.clear();
+        self.next_id = 0;
+        self.try_depth = 0;
+        self.output.clear();
+        self.indent = 0;
+
+        let count = self.rng.gen_range(1..=self.config.max_statements);
+        for _ in 0..count {
+            self.statement(0);
+        }
+
+        let names: Vec<_> = self.scopes[0].iter().map(|var| var.name.clone()).collect();
+        let completion = if names.is_empty() {
+            self.expression(0)
+        } else {
+            format!("\"\" + {}", names.join(" + \"|\" + "))
+        };
+        self.line(&format!("{};", completion));
+
+        std::mem::take(&mut self.output)
+    }
+
+    fn statement(&mut self, depth: usize) {
+        let nested = depth < self.config.max_depth;
+        match self.rng.gen_range(0..100) {
+            0..=34 => self.let_declaration(),
+            35..=54 if self.has_assignable() => self.assignment(),
+            55..=64 if nested => self.if_statement(depth),
+            65..=74 if nested => self.while_loop(depth),
+            75..=84 if depth == 0 => self.function_declaration(),
+            85..=92 if nested => self.try_statement(depth),
+            93..=99 if self.try_depth > 0 => {
+                let value = self.expression(0);
+                self.line(&format!("throw {};", value));
+            }
+            _ => self.let_declaration(),
+        }
+    }
+
+    fn let_declaration(&mut self) {
+        let value = self.expression(0);
+        let name = self.declare("v", true);
+        self.line(&format!("let {} = {};", name, value));
+    }
+
+    fn assignment(&mut self) {
+        let assignable: Vec<_> = self.visible().filter(|var| var.assignable).map(|var| var.name.clone()).collect();
+        let name = assignable.choose(&mut self.rng).cloned().unwrap_or_default();
+        let operator = ["=", "+=", "-=", "*="].choose(&mut self.rng).copied().unwrap_or("=");
+        let value = self.expression(0);
+        self.line(&format!("{} {} {};", name, operator, value));
+    }
+
+    fn if_statement(&mut self, depth: usize) {
+        let test = self.expression(0);
+        self.line(&format!("if ({}) {{", test));
+        self.block(depth + 1, &[]);
+        self.line("} else {");
+        self.block(depth + 1, &[]);
+        self.line("}");
+    }
+
+    fn while_loop(&mut self, depth: usize) {
+        let counter = self.declare("i", false);
+        let bound = self.rng.gen_range(0..5);
+        self.line(&format!("let {} = 0;", counter));
+        self.line(&format!("while ({} < {}) {{", counter, bound));
+        self.block(depth + 1, &[]);
+        self.indent += 1;
+        self.line(&format!("{} = {} + 1;", counter, counter));
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn function_declaration(&mut self) {
+        let name = format!("f{}", self.next_id);
+        self.next_id += 1;
+        let param_count = self.rng.gen_range(0..=2);
+        let params: Vec<_> = (0..param_count).map(|i| format!("{}_p{}", name, i)).collect();
+
+        self.line(&format!("function {}({}) {{", name, params.join(", ")));
+        // The body sees only its own parameters and locals
+        let outer = std::mem::take(&mut self.scopes);
+        let outer_try_depth = std::mem::replace(&mut self.try_depth, 0);
+        self.block(1, &params);
+        self.scopes = vec![params.iter().map(|param| Variable { name: param.clone(), assignable: true }).collect()];
+        let value = self.expression(0);
+        self.scopes = outer;
+        self.try_depth = outer_try_depth;
+        self.indent += 1;
+        self.line(&format!("return {};", value));
+        self.indent -= 1;
+        self.line("}");
+
+        self.functions.push((name, param_count));
+    }
+
+    fn try_statement(&mut self, depth: usize) {
+        self.line("try {");
+        self.try_depth += 1;
+        self.block(depth + 1, &[]);
+        self.try_depth -= 1;
+
+        let param = format!("e{}", self.next_id);
+        self.next_id += 1;
+        self.line(&format!("}} catch ({}) {{", param));
+        self.scopes.push(vec![Variable { name: param, assignable: false }]);
+        self.indent += 1;
+        let count = self.rng.gen_range(0..=2);
+        for _ in 0..count {
+            self.statement(depth + 1);
+        }
+        self.indent -= 1;
+        self.scopes.pop();
+        self.line("}");
+    }
+
+    /// Statements of a nested block, with `params` in scope
+    fn block(&mut self, depth: usize, params: &[String]) {
+        self.scopes.push(params.iter().map(|param| Variable { name: param.clone(), assignable: true }).collect());
+        self.indent += 1;
+        let count = self.rng.gen_range(0..=self.config.max_statements / 2);
+        for _ in 0..count {
+            self.statement(depth);
+        }
+        self.indent -= 1;
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, depth: usize) -> String {
+        if depth >= self.config.max_depth {
+            return self.atom();
+        }
+        let depth = depth + 1;
+
+        match self.rng.gen_range(0..100) {
+            0..=29 => {
+                let left = self.expression(depth);
+                let operator = BINARY_OPERATORS.choose(&mut self.rng).copied().unwrap_or("+");
+                let right = self.expression(depth);
+                format!("({} {} {})", left, operator, right)
+            }
+            30..=39 => {
+                let operator = UNARY_OPERATORS.choose(&mut self.rng).copied().unwrap_or("-");
+                format!("({}{})", operator, self.expression(depth))
+            }
+            40..=47 => {
+                let test = self.expression(depth);
+                let consequent = self.expression(depth);
+                let alternate = self.expression(depth);
+                format!("({} ? {} : {})", test, consequent, alternate)
+            }
+            48..=54 if !self.functions.is_empty() => {
+                let (name, param_count) = self.functions.choose(&mut self.rng).cloned().unwrap_or_default();
+                let args: Vec<_> = (0..param_count).map(|_| self.expression(depth)).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+            55..=59 => {
+                let elements: Vec<_> = (0..self.rng.gen_range(0..4)).map(|_| self.expression(depth)).collect();
+                format!("[{}][{}]", elements.join(", "), self.rng.gen_range(0..4))
+            }
+            60..=64 => {
+                let a = self.expression(depth);
+                let b = self.expression(depth);
+                let key = ["a", "b", "c"].choose(&mut self.rng).copied().unwrap_or("a");
+                format!("({{a: {}, b: {}}}).{}", a, b, key)
+            }
+            65..=69 => format!("({}).length", self.expression(depth)),
+            _ => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> String {
+        let variables: Vec<_> = self.visible().map(|var| var.name.clone()).collect();
+        match self.rng.gen_range(0..100) {
+            0..=39 if !variables.is_empty() => variables.choose(&mut self.rng).cloned().unwrap_or_default(),
+            0..=49 => INTERESTING_NUMBERS.choose(&mut self.rng).copied().unwrap_or("0").to_string(),
+            50..=79 => self.rng.gen_range(0..10).to_string(),
+            80..=91 => format!("\"{}\"", INTERESTING_STRINGS.choose(&mut self.rng).copied().unwrap_or("")),
+            92..=97 => self.rng.gen_bool(0.5).to_string(),
+            _ => "null".to_string(),
+        }
+    }
+
+    fn declare(&mut self, prefix: &str, assignable: bool) -> String {
+        let name = format!("{}{}", prefix, self.next_id);
+        self.next_id += 1;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Variable { name: name.clone(), assignable });
+        }
+        name
+    }
+
+    fn visible(&self) -> impl Iterator<Item = &Variable> {
+        self.scopes.iter().flatten()
+    }
+
+    fn has_assignable(&self) -> bool {
+        self.visible().any(|var| var.assignable)
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.output.push_str("    ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+}
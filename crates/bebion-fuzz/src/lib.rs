@@ -0,0 +1,144 @@
+//! Differential fuzzing
+//!
+//! Programs, either generated from scratch or mutated from seed files, run
+//! under bebion and under an oracle engine; any difference in completion
+//! value or uncaught error kind is a divergence. Divergences are grouped by
+//! what each engine did, and each group keeps the smallest reproducer seen,
+//! reduced by statement deletion.
+
+pub mod generator;
+pub mod mutator;
+pub mod oracle;
+pub mod reduce;
+
+pub use generator::{Generator, GeneratorConfig};
+pub use mutator::Mutator;
+#[cfg(feature = "boa")]
+pub use oracle::BoaOracle;
+pub use oracle::{BebionOracle, CommandOracle, Observation, Oracle};
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::debug;
+
+/// A program bebion and the oracle disagree on
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub source: String,
+    pub bebion: Observation,
+    pub oracle: Observation,
+}
+
+impl Divergence {
+    /// Divergences with the same signature are most likely the same bug
+    pub fn signature(&self) -> String {
+        format!("bebion {} / oracle {}", self.bebion.summary(), self.oracle.summary())
+    }
+}
+
+/// Every divergence with one signature
+#[derive(Debug, Clone)]
+pub struct DivergenceGroup {
+    pub count: usize,
+    /// The shortest reproducer seen
+    pub example: Divergence,
+}
+
+pub struct Fuzzer {
+    oracle: Box<dyn Oracle>,
+    timeout: Duration,
+    reduce: bool,
+    cases: usize,
+    groups: BTreeMap<String, DivergenceGroup>,
+    hung: bool,
+}
+
+impl Fuzzer {
+    pub fn new(oracle: Box<dyn Oracle>) -> Self {
+        Self {
+            oracle,
+            timeout: Duration::from_secs(5),
+            reduce: true,
+            cases: 0,
+            groups: BTreeMap::new(),
+            hung: false,
+        }
+    }
+
+    /// How long either engine may run one program
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Whether reproducers are reduced before they are recorded
+    pub fn set_reduce(&mut self, reduce: bool) {
+        self.reduce = reduce;
+    }
+
+    pub fn oracle_name(&self) -> &str {
+        self.oracle.name()
+    }
+
+    /// Run `source` under both engines and record a divergence. Returns its
+    /// signature if the engines disagreed.
+    pub fn check(&mut self, source: &str) -> Option<String> {
+        self.cases += 1;
+        let divergence = self.diverges(source)?;
+        let signature = divergence.signature();
+        debug!("Divergence {}", signature);
+
+        let divergence = if self.reduce {
+            let reduced = reduce::reduce(source, |candidate| {
+                self.diverges(candidate).is_some_and(|candidate| candidate.signature() == signature)
+            });
+            self.diverges(&reduced).unwrap_or(divergence)
+        } else {
+            divergence
+        };
+
+        match self.groups.get_mut(&signature) {
+            Some(group) => {
+                group.count += 1;
+                if divergence.source.len() < group.example.source.len() {
+                    group.example = divergence;
+                }
+            }
+            None => {
+                self.groups.insert(signature.clone(), DivergenceGroup { count: 1, example: divergence });
+            }
+        }
+        Some(signature)
+    }
+
+    /// Programs checked so far
+    pub fn cases(&self) -> usize {
+        self.cases
+    }
+
+    /// Divergences by signature
+    pub fn groups(&self) -> &BTreeMap<String, DivergenceGroup> {
+        &self.groups
+    }
+
+    /// Whether bebion has timed out. Its engine thread keeps running, so a
+    /// campaign should stop here rather than pile up spinning threads.
+    pub fn hung(&self) -> bool {
+        self.hung
+    }
+
+    fn diverges(&mut self, source: &str) -> Option<Divergence> {
+        let bebion = BebionOracle.run(source, self.timeout);
+        self.hung |= bebion == Observation::Timeout;
+        let oracle = self.oracle.run(source, self.timeout);
+
+        // Programs the oracle rejects or cannot finish say nothing about bebion
+        if matches!(oracle, Observation::Timeout | Observation::Crash(_)) || bebion.agrees_with(&oracle) {
+            return None;
+        }
+        Some(Divergence {
+            source: source.to_string(),
+            bebion,
+            oracle,
+        })
+    }
+}
@@ -0,0 +1,166 @@
+use bebion_fuzz::{CommandOracle, Fuzzer, Generator, GeneratorConfig, Mutator, Oracle};
+use bebion_parser::{Parser as JsParser, Program};
+use clap::Parser;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn, Level};
+
+#[derive(Parser)]
+#[command(name = "bebion-fuzz")]
+#[command(about = "Find programs bebion runs differently from another engine")]
+struct Args {
+    /// Programs to check
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    iterations: usize,
+
+    /// Seed for program generation and mutation; random when omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Mutate the .js files in this directory instead of generating programs
+    #[arg(long, value_name = "DIR")]
+    seeds: Option<PathBuf>,
+
+    /// `boa` for the bundled Boa engine, or a command that runs a script
+    /// file, such as `node` or `qjs`
+    #[arg(long, default_value = "boa")]
+    oracle: String,
+
+    /// Milliseconds either engine may spend on one program
+    #[arg(long, default_value_t = 5000)]
+    timeout: u64,
+
+    /// Write each group's reproducer to this directory
+    #[arg(long, value_name = "DIR")]
+    out: Option<PathBuf>,
+
+    /// Keep reproducers as found instead of reducing them
+    #[arg(long)]
+    no_reduce: bool,
+
+    /// Most statements per block in generated programs
+    #[arg(long, default_value_t = 8)]
+    max_statements: usize,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_max_level(Level::INFO)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let args = Args::parse();
+    let seed = args.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    info!("Fuzzing with seed {}", seed);
+
+    let mut fuzzer = Fuzzer::new(oracle(&args.oracle)?);
+    fuzzer.set_timeout(Duration::from_millis(args.timeout));
+    fuzzer.set_reduce(!args.no_reduce);
+
+    let mut next_program: Box<dyn FnMut() -> Option<String>> = match &args.seeds {
+        Some(dir) => {
+            let seeds = load_seeds(dir)?;
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            let mut mutator = Mutator::new(seed);
+            Box::new(move || mutator.mutate(seeds.choose(&mut rng)?))
+        }
+        None => {
+            let config = GeneratorConfig {
+                max_statements: args.max_statements.max(1),
+                ..GeneratorConfig::default()
+            };
+            let mut generator = Generator::new(seed, config);
+            Box::new(move || Some(generator.generate()))
+        }
+    };
+
+    for i in 0..args.iterations {
+        let Some(source) = next_program() else {
+            continue;
+        };
+        if let Some(signature) = fuzzer.check(&source) {
+            info!("Case {}: {}", i, signature);
+        }
+        if fuzzer.hung() {
+            warn!("bebion timed out; stopping since its engine thread is still running");
+            break;
+        }
+    }
+
+    report(&fuzzer, args.out.as_ref())?;
+    if !fuzzer.groups().is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn oracle(name: &str) -> Result<Box<dyn Oracle>, String> {
+    #[cfg(feature = "boa")]
+    if name == "boa" {
+        return Ok(Box::new(bebion_fuzz::BoaOracle));
+    }
+    CommandOracle::new(name)
+        .map(|oracle| Box::new(oracle) as Box<dyn Oracle>)
+        .ok_or_else(|| "The oracle command is empty".to_string())
+}
+
+fn load_seeds(dir: &PathBuf) -> Result<Vec<Program>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Cannot read {}: {}", dir.display(), e))?;
+    let mut seeds = Vec::new();
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.extension().is_none_or(|extension| extension != "js") {
+            continue;
+        }
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| JsParser::new().parse(&source).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(program) => seeds.push(program),
+            Err(error) => warn!("Skipping seed {}: {}", path.display(), error),
+        }
+    }
+
+    if seeds.is_empty() {
+        return Err(format!("No usable .js seeds in {}", dir.display()));
+    }
+    info!("Loaded {} seeds", seeds.len());
+    Ok(seeds)
+}
+
+fn report(fuzzer: &Fuzzer, out: Option<&PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    println!(
+        "{} programs, {} kinds of divergence from {}",
+        fuzzer.cases(),
+        fuzzer.groups().len(),
+        fuzzer.oracle_name()
+    );
+    if let Some(out) = out {
+        fs::create_dir_all(out)?;
+    }
+
+    for (i, (signature, group)) in fuzzer.groups().iter().enumerate() {
+        let example = &group.example;
+        println!("\n[{}] {} ({} programs)", i + 1, signature, group.count);
+        println!("  bebion: {}", example.bebion);
+        println!("  {}: {}", fuzzer.oracle_name(), example.oracle);
+        for line in example.source.lines() {
+            println!("  | {}", line);
+        }
+
+        if let Some(out) = out {
+            let path = out.join(format!("divergence-{}.js", i + 1));
+            let header = format!(
+                "// {}\n// bebion: {}\n// {}: {}\n",
+                signature,
+                example.bebion,
+                fuzzer.oracle_name(),
+                example.oracle
+            );
+            fs::write(&path, header + &example.source)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+//! Seed program mutation
+//!
+//! A mutant is a seed with a few literals and operators swapped for others
+//! of the same kind, printed back through the code generator. Mutants stay
+//! syntactically valid but, unlike generated programs, may not terminate.
+
+use crate::generator::{INTERESTING_NUMBERS, INTERESTING_STRINGS};
+use bebion_parser::ast::visit::{self, VisitorMut};
+use bebion_parser::ast::{AstNode, BinaryOperator, LiteralValue};
+use bebion_parser::{CodegenOptions, Program};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Operators that can replace each other without changing the program's
+/// shape
+const OPERATOR_GROUPS: &[&[BinaryOperator]] = &[
+    &[
+        BinaryOperator::Add,
+        BinaryOperator::Sub,
+        BinaryOperator::Mul,
+        BinaryOperator::Div,
+        BinaryOperator::Mod,
+        BinaryOperator::Pow,
+    ],
+    &[
+        BinaryOperator::Equal,
+        BinaryOperator::NotEqual,
+        BinaryOperator::StrictEqual,
+        BinaryOperator::StrictNotEqual,
+        BinaryOperator::Less,
+        BinaryOperator::Greater,
+        BinaryOperator::LessEqual,
+        BinaryOperator::GreaterEqual,
+    ],
+    &[
+        BinaryOperator::LeftShift,
+        BinaryOperator::RightShift,
+        BinaryOperator::UnsignedRightShift,
+        BinaryOperator::BitwiseAnd,
+        BinaryOperator::BitwiseOr,
+        BinaryOperator::BitwiseXor,
+    ],
+];
+
+/// Most mutations applied to one seed
+const MAX_MUTATIONS: usize = 3;
+
+pub struct Mutator {
+    rng: StdRng,
+}
+
+impl Mutator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Source of a mutant of `program`, or `None` if it has nothing to mutate
+    pub fn mutate(&mut self, program: &Program) -> Option<String> {
+        let mut counter = Mutations {
+            rng: &mut self.rng,
+            candidates: 0,
+            targets: Vec::new(),
+        };
+        let mut program = program.clone();
+        visit::walk_program_mut(&mut counter, &mut program);
+        let candidates = counter.candidates;
+        if candidates == 0 {
+            return None;
+        }
+
+        let count = self.rng.gen_range(1..=MAX_MUTATIONS.min(candidates));
+        let targets = rand::seq::index::sample(&mut self.rng, candidates, count).into_vec();
+        let mut mutations = Mutations {
+            rng: &mut self.rng,
+            candidates: 0,
+            targets,
+        };
+        visit::walk_program_mut(&mut mutations, &mut program);
+
+        Some(bebion_parser::generate(&program, &CodegenOptions::default()))
+    }
+}
+
+/// Counts the nodes that can be mutated and mutates those whose position
+/// in visiting order is in `targets`
+struct Mutations<'a> {
+    rng: &'a mut StdRng,
+    candidates: usize,
+    targets: Vec<usize>,
+}
+
+impl Mutations<'_> {
+    /// Whether the next candidate node should be mutated
+    fn next_is_target(&mut self) -> bool {
+        let index = self.candidates;
+        self.candidates += 1;
+        self.targets.contains(&index)
+    }
+}
+
+impl VisitorMut for Mutations<'_> {
+    fn visit_literal_mut(&mut self, node: &mut AstNode) {
+        let AstNode::Literal { value, raw, .. } = node else {
+            return;
+        };
+        if !matches!(value, LiteralValue::Number(_) | LiteralValue::String(_) | LiteralValue::Boolean(_)) {
+            return;
+        }
+        if !self.next_is_target() {
+            return;
+        }
+
+        let mutated = match &*value {
+            LiteralValue::Number(_) => {
+                let text = INTERESTING_NUMBERS.choose(self.rng).copied().unwrap_or("0");
+                LiteralValue::Number(text.parse().unwrap_or(0.0))
+            }
+            LiteralValue::String(_) => {
+                LiteralValue::String(INTERESTING_STRINGS.choose(self.rng).copied().unwrap_or("").to_string())
+            }
+            LiteralValue::Boolean(b) => LiteralValue::Boolean(!*b),
+            other => other.clone(),
+        };
+        *value = mutated;
+        // The code generator prints the value when there is no raw text
+        raw.clear();
+    }
+
+    fn visit_binary_expression_mut(&mut self, node: &mut AstNode) {
+        if let AstNode::BinaryExpression { operator, .. } = node {
+            let group = OPERATOR_GROUPS.iter().find(|group| group.contains(operator));
+            if let Some(group) = group {
+                if self.next_is_target() {
+                    *operator = group.choose(self.rng).cloned().unwrap_or(BinaryOperator::Add);
+                }
+            }
+        }
+        visit::walk_binary_expression_mut(self, node);
+    }
+}
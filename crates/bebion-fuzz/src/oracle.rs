@@ -0,0 +1,325 @@
+//! Engines a program runs under, and what running it produced
+//!
+//! Every engine reports an `Observation` of the script's completion value
+//! or its uncaught exception, rendered the same way so two engines can be
+//! compared directly. In-process engines run on a worker thread so a hung
+//! program can be given up on; the thread itself cannot be stopped.
+
+use bebion_core::{BebionEngine, BebionError, Value};
+use bebion_runtime::number::number_to_string;
+use std::fmt;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Observation {
+    /// The completion value. Primitives print as `String(value)` would,
+    /// objects and functions as `[object]`.
+    Value(String),
+    /// An uncaught exception: the error's name, or `Thrown` when the value
+    /// thrown is not an error
+    Error { kind: String, message: String },
+    /// The engine panicked or its process died
+    Crash(String),
+    Timeout,
+}
+
+impl Observation {
+    /// Whether two engines behaved the same. Error messages differ between
+    /// engines, so only the kinds are compared.
+    pub fn agrees_with(&self, other: &Observation) -> bool {
+        match (self, other) {
+            (Observation::Value(a), Observation::Value(b)) => a == b,
+            (Observation::Error { kind: a, .. }, Observation::Error { kind: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Short description for grouping divergences: the error kind or the
+    /// kind of outcome. Internal errors keep their message, since each one
+    /// is usually a different missing feature.
+    pub fn summary(&self) -> String {
+        match self {
+            Observation::Value(_) => "value".to_string(),
+            Observation::Error { kind, message } if kind == "InternalError" => message.clone(),
+            Observation::Error { kind, .. } => kind.clone(),
+            Observation::Crash(_) => "crash".to_string(),
+            Observation::Timeout => "timeout".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Observation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Observation::Value(value) => write!(f, "{}", value),
+            Observation::Error { message, .. } => write!(f, "{}", message),
+            Observation::Crash(reason) => write!(f, "crashed: {}", reason),
+            Observation::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+pub trait Oracle {
+    fn name(&self) -> &str;
+
+    /// Run `source` as a script, giving up after `timeout`
+    fn run(&self, source: &str, timeout: Duration) -> Observation;
+}
+
+/// Bebion itself, with a fresh engine per program
+pub struct BebionOracle;
+
+impl Oracle for BebionOracle {
+    fn name(&self) -> &str {
+        "bebion"
+    }
+
+    fn run(&self, source: &str, timeout: Duration) -> Observation {
+        let source = source.to_string();
+        run_on_thread(timeout, move || {
+            let mut engine = match BebionEngine::new() {
+                Ok(engine) => engine,
+                Err(error) => return Observation::Crash(error.to_string()),
+            };
+            match engine.execute_script(&source) {
+                Ok(handle) => Observation::Value(render_bebion(&engine.value_of(handle))),
+                Err(error) => bebion_error(error),
+            }
+        })
+    }
+}
+
+fn render_bebion(value: &Value) -> String {
+    match value {
+        Value::Object(_) => "[object]".to_string(),
+        Value::Symbol(_) => "[symbol]".to_string(),
+        primitive => primitive.to_string(),
+    }
+}
+
+fn bebion_error(error: BebionError) -> Observation {
+    let message = match error {
+        BebionError::ParseError(message) => {
+            return Observation::Error { kind: "SyntaxError".to_string(), message };
+        }
+        // Bebion refusing to compile valid code diverges from any engine
+        BebionError::CompileError(message) => {
+            return Observation::Error { kind: "CompileError".to_string(), message };
+        }
+        BebionError::RuntimeError(message) | BebionError::ModuleError(message) => message,
+    };
+
+    let first_line = message.lines().next().unwrap_or_default();
+    let (uncaught, description) = match first_line.strip_prefix("Uncaught ") {
+        Some(description) => (true, description),
+        None => (false, first_line),
+    };
+    let name = description
+        .split_once(':')
+        .map(|(name, _)| name)
+        .filter(|name| name.ends_with("Error") && !name.contains(' '));
+    let kind = match name {
+        Some(name) => name,
+        None if uncaught => "Thrown",
+        None => "InternalError",
+    };
+    Observation::Error {
+        kind: kind.to_string(),
+        message: first_line.to_string(),
+    }
+}
+
+/// The Boa engine, linked in
+#[cfg(feature = "boa")]
+pub struct BoaOracle;
+
+#[cfg(feature = "boa")]
+impl Oracle for BoaOracle {
+    fn name(&self) -> &str {
+        "boa"
+    }
+
+    fn run(&self, source: &str, timeout: Duration) -> Observation {
+        use boa_engine::{Context, JsValue, Source};
+
+        let source = source.to_string();
+        run_on_thread(timeout, move || {
+            let mut context = Context::default();
+            match context.eval(Source::from_bytes(&source)) {
+                Ok(value) => Observation::Value(match value {
+                    JsValue::Undefined => "undefined".to_string(),
+                    JsValue::Null => "null".to_string(),
+                    JsValue::Boolean(b) => b.to_string(),
+                    JsValue::String(s) => s.to_std_string_escaped(),
+                    JsValue::Rational(n) => number_to_string(n),
+                    JsValue::Integer(n) => number_to_string(n.into()),
+                    JsValue::BigInt(n) => format!("{}n", n),
+                    JsValue::Symbol(_) => "[symbol]".to_string(),
+                    JsValue::Object(_) => "[object]".to_string(),
+                }),
+                Err(error) => match error.try_native(&mut context) {
+                    Ok(native) => Observation::Error {
+                        kind: native.kind.to_string(),
+                        message: native.to_string(),
+                    },
+                    Err(_) => Observation::Error {
+                        kind: "Thrown".to_string(),
+                        message: error.to_string(),
+                    },
+                },
+            }
+        })
+    }
+}
+
+/// An engine run as a command, such as `node` or `qjs`, given a script
+/// that prints the observation. The engine needs indirect `eval` and either
+/// `console.log` or `print`.
+pub struct CommandOracle {
+    program: String,
+    args: Vec<String>,
+}
+
+/// Evaluates `__source` and prints `value:<value>` or `error:<kind>:<message>`
+const COMMAND_WRAPPER: &str = r#"
+var __result;
+try {
+    var __value = (0, eval)(__source);
+    var __type = typeof __value;
+    __result = "value:" + (__value === null ? "null"
+        : __type === "object" || __type === "function" ? "[object]"
+        : __type === "symbol" ? "[symbol]"
+        : __type === "bigint" ? String(__value) + "n"
+        : String(__value));
+} catch (e) {
+    __result = e instanceof Error ? "error:" + e.name + ":" + e.name + ": " + e.message : "error:Thrown:" + String(e);
+}
+(typeof console !== "undefined" ? console.log : print)(__result);
+"#;
+
+impl CommandOracle {
+    /// Parse a command line such as `qjs --std`; arguments are split on
+    /// whitespace
+    pub fn new(command: &str) -> Option<Self> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        Some(Self {
+            program: words.next()?,
+            args: words.collect(),
+        })
+    }
+}
+
+impl Oracle for CommandOracle {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn run(&self, source: &str, timeout: Duration) -> Observation {
+        static NEXT_SCRIPT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "bebion-fuzz-{}-{}.js",
+            std::process::id(),
+            NEXT_SCRIPT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let script = format!("var __source = {};\n{}", js_string(source), COMMAND_WRAPPER);
+        if let Err(error) = std::fs::write(&path, script) {
+            return Observation::Crash(format!("cannot write {}: {}", path.display(), error));
+        }
+
+        let observation = self.run_script(&path, timeout);
+        let _ = std::fs::remove_file(&path);
+        observation
+    }
+}
+
+impl CommandOracle {
+    fn run_script(&self, path: &std::path::Path, timeout: Duration) -> Observation {
+        let mut child = match Command::new(&self.program)
+            .args(&self.args)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(error) => return Observation::Crash(format!("cannot run {}: {}", self.program, error)),
+        };
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+                Ok(None) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Observation::Timeout;
+                }
+                Err(error) => return Observation::Crash(error.to_string()),
+            }
+        };
+
+        let mut output = String::new();
+        if let Some(mut stdout) = child.stdout.take() {
+            let _ = stdout.read_to_string(&mut output);
+        }
+        let output = output.strip_suffix('\n').unwrap_or(&output);
+
+        if let Some(value) = output.strip_prefix("value:") {
+            Observation::Value(value.to_string())
+        } else if let Some((kind, message)) = output.strip_prefix("error:").and_then(|error| error.split_once(':')) {
+            Observation::Error {
+                kind: kind.to_string(),
+                message: message.to_string(),
+            }
+        } else {
+            Observation::Crash(format!("exited with {} and no result", status))
+        }
+    }
+}
+
+/// `source` as a JS string literal
+fn js_string(source: &str) -> String {
+    let mut quoted = String::with_capacity(source.len() + 2);
+    quoted.push('"');
+    for c in source.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\u{2028}' | '\u{2029}' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Run `run` on a new thread, reporting a panic as a crash. On timeout the
+/// thread is left running.
+fn run_on_thread(timeout: Duration, run: impl FnOnce() -> Observation + Send + 'static) -> Observation {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let observation = panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or_else(|payload| {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panicked".to_string());
+            Observation::Crash(reason)
+        });
+        let _ = sender.send(observation);
+    });
+
+    receiver.recv_timeout(timeout).unwrap_or(Observation::Timeout)
+}
@@ -0,0 +1,72 @@
+//! Test case reduction
+//!
+//! Deletes statements, at any nesting depth, one at a time for as long as
+//! the program keeps diverging the same way. Each candidate is printed back
+//! through the code generator and rerun under both engines.
+
+use bebion_parser::{AstNode, CodegenOptions, Parser, Program};
+
+/// The smallest program found by deleting statements from `source` for
+/// which `still_diverges` holds. Returns `source` unchanged if it does not
+/// parse.
+pub fn reduce(source: &str, mut still_diverges: impl FnMut(&str) -> bool) -> String {
+    let Ok(mut program) = Parser::new().parse(source) else {
+        return source.to_string();
+    };
+    let options = CodegenOptions::default();
+    let mut reduced = source.to_string();
+
+    // Repeat until no single deletion keeps the divergence, since deleting
+    // one statement can make another deletable
+    loop {
+        let mut progress = false;
+        let mut index = 0;
+        loop {
+            let mut candidate = program.clone();
+            let mut remaining = index;
+            if !remove_statement(&mut candidate.body, &mut remaining) {
+                break;
+            }
+            let source = bebion_parser::generate(&candidate, &options);
+            if still_diverges(&source) {
+                program = candidate;
+                reduced = source;
+                progress = true;
+            } else {
+                index += 1;
+            }
+        }
+        if !progress {
+            return reduced;
+        }
+    }
+}
+
+/// Remove the `index`th statement in a pre-order walk of `statements` and
+/// the blocks nested in them. Returns false if there are not that many.
+fn remove_statement(statements: &mut Vec<AstNode>, index: &mut usize) -> bool {
+    let mut i = 0;
+    while i < statements.len() {
+        if *index == 0 {
+            statements.remove(i);
+            return true;
+        }
+        *index -= 1;
+        if remove_nested_statement(&mut statements[i], index) {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+fn remove_nested_statement(node: &mut AstNode, index: &mut usize) -> bool {
+    match node {
+        AstNode::BlockStatement { body, .. } => remove_statement(body, index),
+        AstNode::Program(Program { body, .. }) => remove_statement(body, index),
+        node => node
+            .children_mut()
+            .into_iter()
+            .any(|child| remove_nested_statement(child, index)),
+    }
+}
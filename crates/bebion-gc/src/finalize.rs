@@ -0,0 +1,56 @@
+//! Finalizers and external memory
+//!
+//! Objects that wrap a native resource, such as a file handle, a socket or
+//! a pointer handed out by a native library, register a finalizer to
+//! release it once the object is collected. A collection does not run the
+//! finalizers of the objects it frees: it queues them for the owner of the
+//! collector to take with `take_finalizers` and run once it has unlocked
+//! the collector, so a finalizer may use the heap.
+//!
+//! Native memory an object keeps alive, such as a large buffer outside the
+//! heap, is recorded with `set_external_bytes`. It counts towards the
+//! object's size and so towards the collection thresholds, and stops
+//! counting once the object is collected.
+
+use crate::{GarbageCollector, GcHandle};
+
+/// Releases the native resource of a collected object
+pub type Finalizer = Box<dyn FnOnce() + Send>;
+
+impl GarbageCollector {
+    /// Queue `finalizer` to run once `handle` is collected. Returns false if
+    /// there is no such object.
+    pub fn register_finalizer(&mut self, handle: GcHandle, finalizer: Finalizer) -> bool {
+        if !self.objects.contains_key(&handle) {
+            return false;
+        }
+        self.finalizers.entry(handle).or_default().push(finalizer);
+        true
+    }
+
+    /// The finalizers of the objects collected since the last call, to run
+    /// with the collector unlocked
+    pub fn take_finalizers(&mut self) -> Vec<Finalizer> {
+        std::mem::take(&mut self.ready_finalizers)
+    }
+
+    /// Record that `handle` keeps `bytes` of native memory alive, replacing
+    /// what was recorded before. Returns false if there is no such object.
+    pub fn set_external_bytes(&mut self, handle: GcHandle, bytes: usize) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let previous = std::mem::replace(&mut object.external_bytes, bytes);
+        object.size = object.size - previous + bytes;
+        self.bytes_allocated = self.bytes_allocated.saturating_sub(previous) + bytes;
+        self.external_bytes = self.external_bytes - previous + bytes;
+        true
+    }
+
+    /// Queue the finalizers of `handle` as it is collected
+    pub(crate) fn finalize(&mut self, handle: GcHandle) {
+        if let Some(finalizers) = self.finalizers.remove(&handle) {
+            self.ready_finalizers.extend(finalizers);
+        }
+    }
+}
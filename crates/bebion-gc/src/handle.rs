@@ -0,0 +1,105 @@
+//! Handles and their liveness
+//!
+//! A `GcHandle` names a slot of the heap and the epoch the slot was in when
+//! the object was allocated. Collecting the object moves the slot on to the
+//! next epoch and frees it for a later allocation, so a handle kept past its
+//! object's death never reaches whatever takes the slot over: lookups with
+//! it find nothing, and `is_stale` tells such a handle from one the heap
+//! never handed out.
+//!
+//! A `WeakHandle` refers to an object without keeping it alive. Embedders
+//! hold one across collections and `upgrade` it to a `GcHandle` while the
+//! object lasts.
+
+use crate::GarbageCollector;
+
+/// Handle to a garbage-collected object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GcHandle {
+    index: usize,
+    epoch: u32,
+}
+
+impl GcHandle {
+    /// The handle to slot `id` in its first epoch
+    pub fn new(id: usize) -> Self {
+        Self { index: id, epoch: 0 }
+    }
+
+    /// The slot the object lives in, which later objects may reuse
+    pub fn id(&self) -> usize {
+        self.index
+    }
+
+    /// How many objects had the slot before this one
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+}
+
+/// Reference to a garbage-collected object that does not keep it alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WeakHandle(GcHandle);
+
+impl WeakHandle {
+    /// The object, if `gc` has not collected it
+    pub fn upgrade(&self, gc: &GarbageCollector) -> Option<GcHandle> {
+        gc.is_live(self.0).then_some(self.0)
+    }
+}
+
+/// Epoch of each heap slot and the slots free for reuse
+#[derive(Debug, Default)]
+pub(crate) struct SlotTable {
+    epochs: Vec<u32>,
+    free: Vec<usize>,
+}
+
+impl SlotTable {
+    /// A handle to a free slot, reusing one of a collected object first.
+    /// Slot 0 is never handed out, so `GcHandle::new(0)` names no object.
+    pub fn claim(&mut self) -> GcHandle {
+        if let Some(index) = self.free.pop() {
+            return GcHandle { index, epoch: self.epochs[index] };
+        }
+        if self.epochs.is_empty() {
+            self.epochs.push(0);
+        }
+        self.epochs.push(0);
+        GcHandle { index: self.epochs.len() - 1, epoch: 0 }
+    }
+
+    /// Free the slot of `handle` once its object is collected, moving it on
+    /// to the next epoch
+    pub fn release(&mut self, handle: GcHandle) {
+        let epoch = &mut self.epochs[handle.index];
+        *epoch = epoch.wrapping_add(1);
+        self.free.push(handle.index);
+    }
+
+    /// Whether `handle` was handed out and its slot has moved on since
+    pub fn is_stale(&self, handle: GcHandle) -> bool {
+        self.epochs
+            .get(handle.index)
+            .is_some_and(|&epoch| handle.index != 0 && epoch != handle.epoch)
+    }
+}
+
+impl GarbageCollector {
+    /// Whether `handle` refers to an object that has not been collected
+    pub fn is_live(&self, handle: GcHandle) -> bool {
+        self.objects.contains_key(&handle)
+    }
+
+    /// Whether the object `handle` referred to has been collected. Such a
+    /// handle reads as a missing object everywhere, even once its slot
+    /// holds another.
+    pub fn is_stale(&self, handle: GcHandle) -> bool {
+        self.slots.is_stale(handle)
+    }
+
+    /// A reference to `handle` that does not keep it alive
+    pub fn downgrade(&self, handle: GcHandle) -> WeakHandle {
+        WeakHandle(handle)
+    }
+}
@@ -0,0 +1,69 @@
+//! Interned strings
+//!
+//! Strings are immutable, so every string value with the same contents can
+//! be the same heap object. `intern_string` hands out that object, sharing
+//! one allocation between all the property values, array elements and
+//! literals that hold the string, and two interned strings are equal exactly
+//! when their handles are. The table does not keep its strings alive: an
+//! interned string is collected like any other once nothing references it,
+//! and the next `intern_string` of its contents allocates a fresh one.
+//!
+//! Strings longer than `MAX_INTERNED_LENGTH` bytes are rarely repeated and
+//! costly to hash, so they are allocated fresh every time.
+
+use crate::{GarbageCollector, GcHandle, GcObjectType};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Longest string, in bytes, that `intern_string` shares
+pub const MAX_INTERNED_LENGTH: usize = 256;
+
+/// The interned string objects by contents
+#[derive(Debug, Default)]
+pub(crate) struct StringTable {
+    handles: HashMap<Arc<str>, GcHandle>,
+}
+
+impl StringTable {
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Forget `handle` as it is collected, if it is the interned string
+    /// for `contents`
+    pub fn forget(&mut self, contents: &str, handle: GcHandle) {
+        if self.handles.get(contents) == Some(&handle) {
+            self.handles.remove(contents);
+        }
+    }
+}
+
+impl GarbageCollector {
+    /// The string object holding `contents`, shared with every other
+    /// interned string of the same contents
+    pub fn intern_string(&mut self, contents: &str) -> GcHandle {
+        if contents.len() > MAX_INTERNED_LENGTH {
+            return self.allocate_string(contents.to_string());
+        }
+        if let Some(&handle) = self.strings.handles.get(contents) {
+            return handle;
+        }
+        let shared: Arc<str> = Arc::from(contents);
+        let handle = self.allocate(GcObjectType::String(Arc::clone(&shared)));
+        self.strings.handles.insert(shared, handle);
+        handle
+    }
+
+    /// The interned string object for `contents`, if one is alive
+    pub fn interned(&self, contents: &str) -> Option<GcHandle> {
+        self.strings.handles.get(contents).copied()
+    }
+
+    /// Whether `handle` is the interned string for its contents
+    pub fn is_interned(&self, handle: GcHandle) -> bool {
+        match self.get_object_type(handle) {
+            Some(GcObjectType::String(contents)) => self.interned(contents) == Some(handle),
+            _ => false,
+        }
+    }
+}
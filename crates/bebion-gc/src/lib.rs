@@ -2,15 +2,33 @@
 //! 
 //! Incremental, generational garbage collector with mark-and-sweep.
 
-use std::collections::{HashMap, HashSet};
+mod finalize;
+mod handle;
+mod intern;
+mod query;
+mod root;
+mod shape;
+mod snapshot;
+mod telemetry;
+
+pub use finalize::Finalizer;
+pub use handle::{GcHandle, WeakHandle};
+pub use intern::MAX_INTERNED_LENGTH;
+pub use query::{HeapObject, HeapQuery, ObjectInfo};
+pub use root::{GcRoot, HandleScope};
+pub use shape::{PropertyMap, Shape, ShapeId, MAX_SHAPED_PROPERTIES};
+pub use snapshot::{EdgeKind, EdgeName, HeapSnapshot, SnapshotEdge, SnapshotNode};
+pub use telemetry::{CollectionListener, GcEvent, MAX_QUEUED_EVENTS};
+
+use handle::SlotTable;
+use intern::StringTable;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ptr::NonNull;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{debug, trace};
 
-/// Handle to a garbage-collected object
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct GcHandle(usize);
-
 /// Generation of a garbage-collected object
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Generation {
@@ -22,11 +40,17 @@ pub enum Generation {
 #[derive(Debug, Clone)]
 pub enum GcObjectType {
     Number(f64),
-    String(String),
+    /// Shared with the string table when interned
+    String(Arc<str>),
     Boolean(bool),
     Null,
     Undefined,
-    Object(HashMap<String, GcHandle>),
+    /// A symbol, identified by `id`
+    Symbol {
+        id: u64,
+        description: Option<String>,
+    },
+    Object(PropertyMap),
     Array(Vec<GcHandle>),
     Function {
         name: Option<String>,
@@ -38,6 +62,42 @@ pub enum GcObjectType {
         value: Option<GcHandle>,
         callbacks: Vec<GcHandle>,
     },
+    RegExp {
+        pattern: String,
+        flags: String,
+        last_index: usize,
+    },
+    ArrayBuffer(Vec<u8>),
+    /// An `ArrayBuffer` whose bytes were transferred away; it and its views
+    /// are empty from then on
+    DetachedArrayBuffer,
+    /// A view of `length` elements of `buffer`, starting `byte_offset`
+    /// bytes in
+    TypedArray {
+        kind: TypedArrayKind,
+        buffer: GcHandle,
+        byte_offset: usize,
+        length: usize,
+    },
+    DataView {
+        buffer: GcHandle,
+        byte_offset: usize,
+        byte_length: usize,
+    },
+    Map(OrderedMap),
+    /// Each entry's key is also its value
+    Set(OrderedMap),
+    /// Entries hold their values only while the key is otherwise reachable,
+    /// and go away when the key is collected
+    WeakMap(HashMap<GcHandle, GcHandle>),
+    /// Members go away when they are collected
+    WeakSet(HashSet<GcHandle>),
+    /// The getter and setter of an accessor property, held in the
+    /// property's slot in place of a value
+    Accessor {
+        getter: Option<GcHandle>,
+        setter: Option<GcHandle>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +107,159 @@ pub enum PromiseState {
     Rejected,
 }
 
+/// The element type of a typed array
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypedArrayKind {
+    Int8,
+    Uint8,
+    Uint8Clamped,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl TypedArrayKind {
+    /// Bytes per element
+    pub fn element_size(self) -> usize {
+        match self {
+            TypedArrayKind::Int8 | TypedArrayKind::Uint8 | TypedArrayKind::Uint8Clamped => 1,
+            TypedArrayKind::Int16 | TypedArrayKind::Uint16 => 2,
+            TypedArrayKind::Int32 | TypedArrayKind::Uint32 | TypedArrayKind::Float32 => 4,
+            TypedArrayKind::Float64 => 8,
+        }
+    }
+
+    /// The constructor's name, e.g. `"Uint8Array"`
+    pub fn name(self) -> &'static str {
+        match self {
+            TypedArrayKind::Int8 => "Int8Array",
+            TypedArrayKind::Uint8 => "Uint8Array",
+            TypedArrayKind::Uint8Clamped => "Uint8ClampedArray",
+            TypedArrayKind::Int16 => "Int16Array",
+            TypedArrayKind::Uint16 => "Uint16Array",
+            TypedArrayKind::Int32 => "Int32Array",
+            TypedArrayKind::Uint32 => "Uint32Array",
+            TypedArrayKind::Float32 => "Float32Array",
+            TypedArrayKind::Float64 => "Float64Array",
+        }
+    }
+}
+
+/// A `Map` or `Set` key. Keys compare with SameValueZero: primitives by
+/// value, with NaN equal to itself and -0 equal to +0, and objects by
+/// identity.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Undefined,
+    Null,
+    Boolean(bool),
+    /// The number's bits, with every NaN and both zeros folded together
+    Number(u64),
+    String(String),
+    /// The symbol's id
+    Symbol(u64),
+    Object(GcHandle),
+}
+
+impl MapKey {
+    pub fn number(n: f64) -> Self {
+        let n = if n.is_nan() {
+            f64::NAN
+        } else if n == 0.0 {
+            0.0
+        } else {
+            n
+        };
+        MapKey::Number(n.to_bits())
+    }
+}
+
+/// The entries of a `Map` or `Set` in insertion order. A removed entry
+/// leaves a gap, so positions stay put while the map is iterated; only an
+/// insert into a map with more gaps than entries closes them.
+#[derive(Debug, Clone, Default)]
+pub struct OrderedMap {
+    /// Key, key handle and value of each entry
+    entries: Vec<Option<(MapKey, GcHandle, GcHandle)>>,
+    /// Position of each key in `entries`
+    index: HashMap<MapKey, usize>,
+}
+
+impl OrderedMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<GcHandle> {
+        let position = *self.index.get(key)?;
+        self.entries[position].as_ref().map(|(_, _, value)| *value)
+    }
+
+    pub fn contains_key(&self, key: &MapKey) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Set `key`, held as `key_handle`, to `value`. An existing entry keeps
+    /// its place. Returns the value it replaced.
+    pub fn insert(&mut self, key: MapKey, key_handle: GcHandle, value: GcHandle) -> Option<GcHandle> {
+        if let Some(&position) = self.index.get(&key) {
+            let (_, _, previous) = self.entries[position].as_mut()?;
+            return Some(std::mem::replace(previous, value));
+        }
+
+        if self.entries.len() - self.index.len() > self.index.len().max(8) {
+            self.compact();
+        }
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push(Some((key, key_handle, value)));
+        None
+    }
+
+    /// Remove `key`, returning its key handle and value
+    pub fn remove(&mut self, key: &MapKey) -> Option<(GcHandle, GcHandle)> {
+        let position = self.index.remove(key)?;
+        self.entries[position].take().map(|(_, key_handle, value)| (key_handle, value))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    /// The key handle and value at `position`, unless that entry was removed
+    pub fn entry_at(&self, position: usize) -> Option<(GcHandle, GcHandle)> {
+        self.entries.get(position)?.as_ref().map(|(_, key_handle, value)| (*key_handle, *value))
+    }
+
+    /// Positions in use, counting the gaps removed entries left
+    pub fn positions(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Key handles and values in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (GcHandle, GcHandle)> + '_ {
+        self.entries.iter().flatten().map(|(_, key_handle, value)| (*key_handle, *value))
+    }
+
+    fn compact(&mut self) {
+        self.entries.retain(Option::is_some);
+        for (position, (key, _, _)) in self.entries.iter().flatten().enumerate() {
+            self.index.insert(key.clone(), position);
+        }
+    }
+}
+
 /// Garbage-collected object
 #[derive(Debug)]
 struct GcObject {
@@ -55,61 +268,140 @@ struct GcObject {
     marked: bool,
     size: usize,
     references: HashSet<GcHandle>,
+    prototype: Option<GcHandle>,
+    /// Native memory the object keeps alive, included in `size`
+    external_bytes: usize,
 }
 
+/// Estimated bytes per `Map` or `Set` entry
+const MAP_ENTRY_SIZE: usize = 24;
+/// Estimated bytes per `WeakMap` or `WeakSet` entry
+const WEAK_ENTRY_SIZE: usize = 16;
+
 /// Garbage collector state
 pub struct GarbageCollector {
     objects: HashMap<GcHandle, GcObject>,
-    next_handle: AtomicUsize,
-    root_set: HashSet<GcHandle>,
+    slots: SlotTable,
+    /// Rooted handles and how many times each was rooted
+    root_set: HashMap<GcHandle, usize>,
+    /// Objects rooted by each open `HandleScope`, innermost last
+    scopes: Vec<Vec<GcHandle>>,
+    /// Raised instead of collecting once collections are deferred
+    collection_requested: Option<Arc<AtomicBool>>,
+    /// Finalizers of live objects
+    finalizers: HashMap<GcHandle, Vec<Finalizer>>,
+    /// Finalizers of collected objects, waiting to be taken
+    ready_finalizers: Vec<Finalizer>,
     young_objects: HashSet<GcHandle>,
     old_objects: HashSet<GcHandle>,
+    /// Old objects that may refer to young ones, which a minor collection
+    /// scans in place of the whole old generation
+    remembered: HashSet<GcHandle>,
+    strings: StringTable,
     
     // Statistics
     total_allocations: usize,
     total_collections: usize,
     bytes_allocated: usize,
     bytes_freed: usize,
+    external_bytes: usize,
     
-    // Collection thresholds
+    // Collection thresholds, adapted after each collection
+    config: GcConfig,
     young_threshold: usize,
-    old_threshold: usize,
-    collection_frequency: usize,
+    full_threshold: usize,
+    /// Live bytes as the last collection left them
+    live_bytes: usize,
+    last_pause: Duration,
+    allocations_since_collection: usize,
+    bytes_since_collection: usize,
+    /// Collections not taken with `take_events` yet
+    events: VecDeque<GcEvent>,
+    collection_listener: Option<CollectionListener>,
 }
 
-impl GcHandle {
-    pub fn new(id: usize) -> Self {
-        Self(id)
-    }
-    
-    pub fn id(&self) -> usize {
-        self.0
+/// When the collector runs. A minor collection starts once the heap has
+/// grown by `young_threshold` bytes since the last collection, and a full
+/// one instead once it holds more than `old_threshold` live bytes.
+///
+/// Both thresholds adapt. A minor collection that pauses longer than
+/// `pause_budget` halves the young threshold, and one taking under half the
+/// budget doubles it, within `min_young_threshold..=max_young_threshold`.
+/// A full collection moves the old threshold to `heap_growth_factor` times
+/// the live bytes it leaves, but never below `old_threshold`.
+///
+/// With `allocation_interval` set, a minor collection also starts after
+/// that many allocations, however small. With `max_heap_size` set, an
+/// allocation past it forces a full collection, and if the heap is still
+/// over the limit afterwards `heap_limit_exceeded` says so for the owner
+/// to fail with an out-of-memory error.
+#[derive(Debug, Clone)]
+pub struct GcConfig {
+    pub young_threshold: usize,
+    pub min_young_threshold: usize,
+    pub max_young_threshold: usize,
+    pub old_threshold: usize,
+    pub heap_growth_factor: f64,
+    pub pause_budget: Duration,
+    pub allocation_interval: Option<usize>,
+    pub max_heap_size: Option<usize>,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            young_threshold: 1024 * 1024,           // 1MB
+            min_young_threshold: 256 * 1024,        // 256KB
+            max_young_threshold: 16 * 1024 * 1024,  // 16MB
+            old_threshold: 10 * 1024 * 1024,        // 10MB
+            heap_growth_factor: 2.0,
+            pause_budget: Duration::from_millis(5),
+            allocation_interval: None,
+            max_heap_size: None,
+        }
     }
 }
 
 impl GarbageCollector {
     pub fn new() -> Self {
+        Self::with_config(GcConfig::default())
+    }
+
+    /// A collector that runs as `config` says
+    pub fn with_config(config: GcConfig) -> Self {
         Self {
             objects: HashMap::new(),
-            next_handle: AtomicUsize::new(1),
-            root_set: HashSet::new(),
+            slots: SlotTable::default(),
+            root_set: HashMap::new(),
+            scopes: Vec::new(),
+            collection_requested: None,
+            finalizers: HashMap::new(),
+            ready_finalizers: Vec::new(),
             young_objects: HashSet::new(),
             old_objects: HashSet::new(),
+            remembered: HashSet::new(),
+            strings: StringTable::default(),
             
             total_allocations: 0,
             total_collections: 0,
             bytes_allocated: 0,
             bytes_freed: 0,
+            external_bytes: 0,
             
-            young_threshold: 1024 * 1024,      // 1MB
-            old_threshold: 10 * 1024 * 1024,   // 10MB
-            collection_frequency: 100,
+            young_threshold: config.young_threshold,
+            full_threshold: config.old_threshold,
+            config,
+            live_bytes: 0,
+            last_pause: Duration::ZERO,
+            allocations_since_collection: 0,
+            bytes_since_collection: 0,
+            events: VecDeque::new(),
+            collection_listener: None,
         }
     }
 
     /// Allocate a new object and return its handle
     pub fn allocate(&mut self, object_type: GcObjectType) -> GcHandle {
-        let handle = GcHandle(self.next_handle.fetch_add(1, Ordering::SeqCst));
         let size = self.calculate_object_size(&object_type);
         
         let references = self.extract_references(&object_type);
@@ -120,34 +412,30 @@ impl GarbageCollector {
             marked: false,
             size,
             references,
+            prototype: None,
+            external_bytes: 0,
         };
         
+        // Trigger collection if threshold reached, before the new object
+        // joins the heap so its creator gets it back alive
+        self.total_allocations += 1;
+        self.allocations_since_collection += 1;
+        if self.should_collect() {
+            self.trigger_collection();
+        }
+        
+        let handle = self.slots.claim();
         self.objects.insert(handle, object);
         self.young_objects.insert(handle);
-        
-        self.total_allocations += 1;
+        self.root_in_scope(handle);
         self.bytes_allocated += size;
+        self.bytes_since_collection += size;
         
-        trace!("Allocated object {} with size {} bytes", handle.0, size);
-        
-        // Trigger collection if threshold reached
-        if self.should_collect() {
-            self.collect();
-        }
+        trace!("Allocated object {} with size {} bytes", handle.id(), size);
         
         handle
     }
 
-    /// Add a handle to the root set
-    pub fn add_root(&mut self, handle: GcHandle) {
-        self.root_set.insert(handle);
-    }
-
-    /// Remove a handle from the root set
-    pub fn remove_root(&mut self, handle: GcHandle) {
-        self.root_set.remove(&handle);
-    }
-
     /// Get the type of an object
     pub fn get_object_type(&self, handle: GcHandle) -> Option<&GcObjectType> {
         self.objects.get(&handle).map(|obj| &obj.object_type)
@@ -155,16 +443,19 @@ impl GarbageCollector {
 
     /// Update an object's type (for mutation)
     pub fn update_object(&mut self, handle: GcHandle, new_type: GcObjectType) -> bool {
+        let type_size = self.calculate_object_size(&new_type);
+        let new_references = self.extract_references(&new_type);
         if let Some(object) = self.objects.get_mut(&handle) {
             let old_size = object.size;
-            let new_size = self.calculate_object_size(&new_type);
-            let new_references = self.extract_references(&new_type);
+            let new_size = type_size + object.external_bytes;
             
             object.object_type = new_type;
             object.size = new_size;
             object.references = new_references;
+            object.references.extend(object.prototype);
             
             self.bytes_allocated = self.bytes_allocated.saturating_sub(old_size) + new_size;
+            self.record_writes(handle);
             
             true
         } else {
@@ -172,30 +463,326 @@ impl GarbageCollector {
         }
     }
 
+    /// The getter and setter of `handle` if it is an accessor
+    pub fn accessor(&self, handle: GcHandle) -> Option<(Option<GcHandle>, Option<GcHandle>)> {
+        match self.get_object_type(handle)? {
+            GcObjectType::Accessor { getter, setter } => Some((*getter, *setter)),
+            _ => None,
+        }
+    }
+
+    /// The object `handle` inherits properties from
+    pub fn get_prototype(&self, handle: GcHandle) -> Option<GcHandle> {
+        self.objects.get(&handle).and_then(|obj| obj.prototype)
+    }
+
+    /// Point the prototype slot of `handle` at `prototype`. Returns false if
+    /// there is no such object.
+    pub fn set_prototype(&mut self, handle: GcHandle, prototype: Option<GcHandle>) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+
+        let previous = std::mem::replace(&mut object.prototype, prototype);
+        object.references.extend(prototype);
+        if let Some(previous) = previous.filter(|&previous| !Self::holds(object, previous)) {
+            object.references.remove(&previous);
+        }
+        if let Some(prototype) = prototype {
+            self.record_write(handle, prototype);
+        }
+        true
+    }
+
+    /// Set property `key` of an object in place, keeping its size and
+    /// references current. Returns false if `handle` is not an object.
+    pub fn set_property(&mut self, handle: GcHandle, key: &str, value: GcHandle) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let GcObjectType::Object(properties) = &mut object.object_type else {
+            return false;
+        };
+
+        // An existing property is updated in place, and writing the handle
+        // it already holds, such as the same interned string, changes nothing
+        let previous = match properties.get_mut(key) {
+            Some(slot) if *slot == value => return true,
+            Some(slot) => Some(std::mem::replace(slot, value)),
+            None => {
+                properties.insert(key, value);
+                object.size += 16;
+                self.bytes_allocated += 16;
+                None
+            }
+        };
+        Self::replace_reference(object, previous, value);
+        self.record_write(handle, value);
+        true
+    }
+
+    /// Set element `index` of an array in place, first growing the array
+    /// with `fill` if it is too short. Returns false if `handle` is not an
+    /// array.
+    pub fn set_element(&mut self, handle: GcHandle, index: usize, value: GcHandle, fill: GcHandle) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let GcObjectType::Array(elements) = &mut object.object_type else {
+            return false;
+        };
+
+        if index >= elements.len() {
+            let added = index + 1 - elements.len();
+            elements.resize(index + 1, fill);
+            object.size += added * 8;
+            self.bytes_allocated += added * 8;
+            object.references.insert(fill);
+        }
+        let previous = std::mem::replace(&mut elements[index], value);
+        Self::replace_reference(object, Some(previous), value);
+        self.record_write(handle, fill);
+        self.record_write(handle, value);
+        true
+    }
+
+    /// Delete property `key` of an object in place, keeping its size and
+    /// references current. Returns false if `handle` is not an object.
+    pub fn remove_property(&mut self, handle: GcHandle, key: &str) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let GcObjectType::Object(properties) = &mut object.object_type else {
+            return false;
+        };
+
+        if let Some(previous) = properties.remove(key) {
+            object.size = object.size.saturating_sub(16);
+            self.bytes_allocated = self.bytes_allocated.saturating_sub(16);
+            if !Self::holds(object, previous) {
+                object.references.remove(&previous);
+            }
+        }
+        true
+    }
+
+    /// Record that `object` now refers to `value`, and drop `previous` from
+    /// its references unless it is still held elsewhere in the object
+    fn replace_reference(object: &mut GcObject, previous: Option<GcHandle>, value: GcHandle) {
+        object.references.insert(value);
+
+        let Some(previous) = previous.filter(|&previous| previous != value) else {
+            return;
+        };
+        if !Self::holds(object, previous) {
+            object.references.remove(&previous);
+        }
+    }
+
+    /// Write barrier: remember `holder` once an old object is made to refer
+    /// to the young object `value`, so minor collections see the reference
+    fn record_write(&mut self, holder: GcHandle, value: GcHandle) {
+        if self.young_objects.contains(&value) && self.old_objects.contains(&holder) {
+            self.remembered.insert(holder);
+        }
+    }
+
+    /// Write barrier for a change that may have replaced any of `holder`'s
+    /// references
+    fn record_writes(&mut self, holder: GcHandle) {
+        if !self.old_objects.contains(&holder) {
+            return;
+        }
+        let holds_young = self.objects[&holder].references.iter().any(|handle| self.young_objects.contains(handle));
+        if holds_young {
+            self.remembered.insert(holder);
+        }
+    }
+
+    /// Whether a property, element or the prototype slot of `object` is
+    /// `handle`
+    fn holds(object: &GcObject, handle: GcHandle) -> bool {
+        object.prototype == Some(handle)
+            || match &object.object_type {
+                GcObjectType::Object(properties) => properties.values().any(|&value| value == handle),
+                GcObjectType::Array(elements) => elements.contains(&handle),
+                GcObjectType::Map(map) | GcObjectType::Set(map) => {
+                    map.iter().any(|(key, value)| key == handle || value == handle)
+                }
+                _ => false,
+            }
+    }
+
+    /// The bytes of an `ArrayBuffer`, to read or write in place. Returns
+    /// `None` if `handle` is not one.
+    pub fn buffer_bytes_mut(&mut self, handle: GcHandle) -> Option<&mut [u8]> {
+        match &mut self.objects.get_mut(&handle)?.object_type {
+            GcObjectType::ArrayBuffer(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Take an `ArrayBuffer`'s bytes without copying them, leaving it
+    /// detached. Returns `None` if `handle` is not an attached buffer.
+    pub fn detach_buffer(&mut self, handle: GcHandle) -> Option<Vec<u8>> {
+        let bytes = match &mut self.objects.get_mut(&handle)?.object_type {
+            GcObjectType::ArrayBuffer(bytes) => std::mem::take(bytes),
+            _ => return None,
+        };
+        self.update_object(handle, GcObjectType::DetachedArrayBuffer);
+        Some(bytes)
+    }
+
+    /// Set `key`, held as `key_handle`, to `value` in a `Map`, or add it to
+    /// a `Set`, where `value` should be `key_handle`. Returns false if
+    /// `handle` is neither.
+    pub fn map_insert(&mut self, handle: GcHandle, key: MapKey, key_handle: GcHandle, value: GcHandle) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let (GcObjectType::Map(map) | GcObjectType::Set(map)) = &mut object.object_type else {
+            return false;
+        };
+
+        let previous = map.insert(key, key_handle, value);
+        if previous.is_none() {
+            object.size += MAP_ENTRY_SIZE;
+            self.bytes_allocated += MAP_ENTRY_SIZE;
+            object.references.insert(key_handle);
+        }
+        Self::replace_reference(object, previous, value);
+        self.record_write(handle, key_handle);
+        self.record_write(handle, value);
+        true
+    }
+
+    /// Remove `key` from a `Map` or `Set`. Returns whether it was there.
+    pub fn map_remove(&mut self, handle: GcHandle, key: &MapKey) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let (GcObjectType::Map(map) | GcObjectType::Set(map)) = &mut object.object_type else {
+            return false;
+        };
+        let Some((key_handle, value)) = map.remove(key) else {
+            return false;
+        };
+
+        for removed in [key_handle, value] {
+            if !Self::holds(object, removed) {
+                object.references.remove(&removed);
+            }
+        }
+        true
+    }
+
+    /// Empty a `Map`, `Set`, `WeakMap` or `WeakSet`. Returns false if
+    /// `handle` is none of them.
+    pub fn collection_clear(&mut self, handle: GcHandle) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        match &mut object.object_type {
+            GcObjectType::Map(map) | GcObjectType::Set(map) => map.clear(),
+            GcObjectType::WeakMap(entries) => entries.clear(),
+            GcObjectType::WeakSet(members) => members.clear(),
+            _ => return false,
+        }
+        object.references.clear();
+        object.references.extend(object.prototype);
+        self.bytes_allocated -= object.size;
+        object.size = 0;
+        true
+    }
+
+    /// Set object `key` to `value` in a `WeakMap`, or add it to a `WeakSet`,
+    /// which ignores `value`. Neither keeps `key` alive. Returns false if
+    /// `handle` is neither.
+    pub fn weak_insert(&mut self, handle: GcHandle, key: GcHandle, value: GcHandle) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let added = match &mut object.object_type {
+            GcObjectType::WeakMap(entries) => entries.insert(key, value).is_none(),
+            GcObjectType::WeakSet(members) => members.insert(key),
+            _ => return false,
+        };
+        if added {
+            object.size += WEAK_ENTRY_SIZE;
+            self.bytes_allocated += WEAK_ENTRY_SIZE;
+        }
+        // A weak map's values are marked through it, unlike its keys
+        self.record_write(handle, value);
+        true
+    }
+
+    /// Remove `key` from a `WeakMap` or `WeakSet`. Returns whether it was
+    /// there.
+    pub fn weak_remove(&mut self, handle: GcHandle, key: GcHandle) -> bool {
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let removed = match &mut object.object_type {
+            GcObjectType::WeakMap(entries) => entries.remove(&key).is_some(),
+            GcObjectType::WeakSet(members) => members.remove(&key),
+            _ => false,
+        };
+        if removed {
+            object.size -= WEAK_ENTRY_SIZE;
+            self.bytes_allocated -= WEAK_ENTRY_SIZE;
+        }
+        removed
+    }
+
     /// Perform garbage collection
     pub fn collect(&mut self) -> usize {
+        self.collect_since(Instant::now())
+    }
+
+    /// Collect, counting the owner as stopped since `paused_at`
+    fn collect_since(&mut self, paused_at: Instant) -> usize {
         debug!("Starting garbage collection cycle {}", self.total_collections + 1);
         
         let initial_count = self.objects.len();
         let initial_bytes = self.bytes_allocated;
+        let start = Instant::now();
+        if let Some(requested) = &self.collection_requested {
+            requested.store(false, Ordering::Relaxed);
+        }
         
-        // Decide whether to collect young generation only or full collection
-        let full_collection = self.total_collections % 10 == 0;
+        // Collect the whole heap only once it has outgrown its budget
+        let full_collection = self.bytes_allocated > self.full_threshold || self.heap_limit_exceeded();
+        let allocations = std::mem::take(&mut self.allocations_since_collection);
+        let allocated_bytes = std::mem::take(&mut self.bytes_since_collection);
         
         if full_collection {
-            self.full_collect()
+            self.full_collect();
         } else {
-            self.minor_collect()
+            self.minor_collect();
         }
         
         let final_count = self.objects.len();
         let final_bytes = self.bytes_allocated;
         
         let collected_objects = initial_count - final_count;
-        let collected_bytes = initial_bytes - final_bytes;
+        let collected_bytes = initial_bytes.saturating_sub(final_bytes);
         
         self.total_collections += 1;
         self.bytes_freed += collected_bytes;
+        self.last_pause = start.elapsed();
+        self.live_bytes = final_bytes;
+        self.adapt_thresholds(full_collection);
+        self.record_event(GcEvent {
+            sequence: self.total_collections,
+            generation: if full_collection { Generation::Old } else { Generation::Young },
+            duration: self.last_pause,
+            pause: paused_at.elapsed(),
+            objects_freed: collected_objects,
+            bytes_freed: collected_bytes,
+            live_bytes: final_bytes,
+            allocations,
+            allocated_bytes,
+        });
         
         debug!(
             "Completed GC cycle: collected {} objects ({} bytes), {} objects remaining",
@@ -205,13 +792,39 @@ impl GarbageCollector {
         collected_objects
     }
 
+    /// Tune the thresholds after a collection, as `GcConfig` describes
+    fn adapt_thresholds(&mut self, full_collection: bool) {
+        let config = &self.config;
+        if full_collection {
+            let target = (self.live_bytes as f64 * config.heap_growth_factor) as usize;
+            self.full_threshold = target.max(config.old_threshold);
+        } else if self.last_pause > config.pause_budget {
+            self.young_threshold = (self.young_threshold / 2).max(config.min_young_threshold);
+        } else if self.last_pause < config.pause_budget / 2 {
+            self.young_threshold = self.young_threshold.saturating_mul(2).min(config.max_young_threshold);
+        }
+        debug!(
+            "GC thresholds now {} young bytes, {} live bytes after a {:?} pause",
+            self.young_threshold, self.full_threshold, self.last_pause
+        );
+    }
+
     /// Minor collection (young generation only)
     fn minor_collect(&mut self) -> usize {
         debug!("Performing minor collection (young generation)");
         
-        // Mark phase - start from roots
+        // Mark phase - old objects all survive, so they start out marked and
+        // marking stops at them. The young objects they hold are found by
+        // scanning the remembered ones along with the roots.
         self.clear_marks();
-        self.mark_from_roots();
+        for handle in &self.old_objects {
+            if let Some(object) = self.objects.get_mut(handle) {
+                object.marked = true;
+            }
+        }
+        let sources: Vec<_> = self.root_set.keys().chain(&self.remembered).copied().collect();
+        self.scan_objects(sources);
+        self.mark_weak_map_values();
         
         // Promote surviving young objects to old generation
         let mut promoted = Vec::new();
@@ -230,6 +843,10 @@ impl GarbageCollector {
             self.old_objects.insert(handle);
         }
         
+        // Every young object is about to be promoted or freed, so no old
+        // object will refer to a young one
+        self.remembered.clear();
+        
         // Sweep phase - collect unmarked young objects
         let mut to_remove = Vec::new();
         for &handle in &self.young_objects {
@@ -240,6 +857,7 @@ impl GarbageCollector {
             }
         }
         
+        self.prune_weak_entries(&to_remove);
         self.remove_objects(&to_remove)
     }
 
@@ -250,6 +868,7 @@ impl GarbageCollector {
         // Mark phase - start from roots
         self.clear_marks();
         self.mark_from_roots();
+        self.mark_weak_map_values();
         
         // Sweep phase - collect all unmarked objects
         let mut to_remove = Vec::new();
@@ -259,6 +878,7 @@ impl GarbageCollector {
             }
         }
         
+        self.prune_weak_entries(&to_remove);
         self.remove_objects(&to_remove)
     }
 
@@ -271,39 +891,128 @@ impl GarbageCollector {
 
     /// Mark objects reachable from roots
     fn mark_from_roots(&mut self) {
-        let roots: Vec<_> = self.root_set.iter().cloned().collect();
-        for root in roots {
-            self.mark_object(root);
+        let roots: Vec<_> = self.root_set.keys().copied().collect();
+        self.mark_objects(roots);
+    }
+
+    /// Mark `handles` and every object reachable from them. Marking is
+    /// tri-color with an explicit gray work list rather than recursion, so
+    /// a long chain of objects cannot overflow the Rust stack: an object is
+    /// marked when it turns gray and its references are pushed as gray in
+    /// turn when it is popped and turns black.
+    fn mark_objects(&mut self, handles: impl IntoIterator<Item = GcHandle>) {
+        let mut gray: Vec<GcHandle> = Vec::new();
+        for handle in handles {
+            self.shade(handle, &mut gray);
         }
+        self.trace(gray);
     }
 
-    /// Mark an object and all objects it references
-    fn mark_object(&mut self, handle: GcHandle) {
-        if let Some(object) = self.objects.get_mut(&handle) {
-            if object.marked {
-                return; // Already marked
+    /// Like `mark_objects`, but scan `handles` for references even if they
+    /// are already marked
+    fn scan_objects(&mut self, handles: impl IntoIterator<Item = GcHandle>) {
+        let mut gray: Vec<GcHandle> = Vec::new();
+        for handle in handles {
+            if let Some(object) = self.objects.get_mut(&handle) {
+                object.marked = true;
+                gray.push(handle);
             }
-            
-            object.marked = true;
-            let references: Vec<_> = object.references.iter().cloned().collect();
-            
-            // Mark all referenced objects
+        }
+        self.trace(gray);
+    }
+
+    /// Blacken gray objects until none are left
+    fn trace(&mut self, mut gray: Vec<GcHandle>) {
+        while let Some(handle) = gray.pop() {
+            let references: Vec<_> = self.objects[&handle].references.iter().copied().collect();
             for referenced_handle in references {
-                self.mark_object(referenced_handle);
+                self.shade(referenced_handle, &mut gray);
+            }
+        }
+    }
+
+    /// Turn `handle` gray if it is still white
+    fn shade(&mut self, handle: GcHandle, gray: &mut Vec<GcHandle>) {
+        if let Some(object) = self.objects.get_mut(&handle) {
+            if !object.marked {
+                object.marked = true;
+                gray.push(handle);
+            }
+        }
+    }
+
+    /// Mark the value of each entry of a marked `WeakMap` whose key is
+    /// marked. A value marked this way can make more keys reachable, so
+    /// repeat until nothing changes.
+    fn mark_weak_map_values(&mut self) {
+        let is_marked = |objects: &HashMap<GcHandle, GcObject>, handle: &GcHandle| {
+            objects.get(handle).is_some_and(|object| object.marked)
+        };
+        loop {
+            let reachable: Vec<GcHandle> = self
+                .objects
+                .values()
+                .filter(|object| object.marked)
+                .filter_map(|object| match &object.object_type {
+                    GcObjectType::WeakMap(entries) => Some(entries),
+                    _ => None,
+                })
+                .flatten()
+                .filter(|(key, value)| is_marked(&self.objects, key) && !is_marked(&self.objects, value))
+                .map(|(_, value)| *value)
+                .collect();
+            if reachable.is_empty() {
+                break;
             }
+            self.mark_objects(reachable);
         }
     }
 
+    /// Drop the entries of weak collections whose keys are about to be
+    /// removed
+    fn prune_weak_entries(&mut self, removed: &[GcHandle]) {
+        if removed.is_empty() {
+            return;
+        }
+        let removed: HashSet<_> = removed.iter().copied().collect();
+        let mut freed = 0;
+        for object in self.objects.values_mut() {
+            let pruned = match &mut object.object_type {
+                GcObjectType::WeakMap(entries) => {
+                    let before = entries.len();
+                    entries.retain(|key, _| !removed.contains(key));
+                    before - entries.len()
+                }
+                GcObjectType::WeakSet(members) => {
+                    let before = members.len();
+                    members.retain(|member| !removed.contains(member));
+                    before - members.len()
+                }
+                _ => continue,
+            };
+            object.size -= pruned * WEAK_ENTRY_SIZE;
+            freed += pruned * WEAK_ENTRY_SIZE;
+        }
+        self.bytes_allocated = self.bytes_allocated.saturating_sub(freed);
+    }
+
     /// Remove a list of objects from the collector
     fn remove_objects(&mut self, handles: &[GcHandle]) -> usize {
         let mut freed_bytes = 0;
         
         for &handle in handles {
             if let Some(object) = self.objects.remove(&handle) {
+                if let GcObjectType::String(contents) = &object.object_type {
+                    self.strings.forget(contents, handle);
+                }
                 freed_bytes += object.size;
+                self.external_bytes -= object.external_bytes;
                 self.young_objects.remove(&handle);
                 self.old_objects.remove(&handle);
+                self.remembered.remove(&handle);
                 self.root_set.remove(&handle);
+                self.slots.release(handle);
+                self.finalize(handle);
             }
         }
         
@@ -313,13 +1022,11 @@ impl GarbageCollector {
 
     /// Check if collection should be triggered
     fn should_collect(&self) -> bool {
-        let young_bytes: usize = self.young_objects.iter()
-            .filter_map(|&h| self.objects.get(&h))
-            .map(|obj| obj.size)
-            .sum();
-        
-        young_bytes > self.young_threshold ||
-        self.total_allocations % self.collection_frequency == 0
+        let growth = self.bytes_allocated.saturating_sub(self.live_bytes);
+        growth > self.young_threshold
+            || self.bytes_allocated > self.full_threshold
+            || self.heap_limit_exceeded()
+            || self.config.allocation_interval.is_some_and(|interval| self.allocations_since_collection >= interval)
     }
 
     /// Calculate the size of an object in bytes
@@ -329,12 +1036,21 @@ impl GarbageCollector {
             GcObjectType::Boolean(_) => 1,
             GcObjectType::Null | GcObjectType::Undefined => 0,
             GcObjectType::String(s) => s.len(),
+            GcObjectType::Symbol { description, .. } => 8 + description.as_ref().map_or(0, String::len),
             GcObjectType::Object(map) => map.len() * 16, // Rough estimate
             GcObjectType::Array(arr) => arr.len() * 8,
             GcObjectType::Function { bytecode, closure, .. } => {
                 bytecode.len() + closure.len() * 16
             }
             GcObjectType::Promise { .. } => 64, // Rough estimate
+            GcObjectType::RegExp { pattern, flags, .. } => pattern.len() + flags.len() + 8,
+            GcObjectType::ArrayBuffer(bytes) => bytes.len(),
+            GcObjectType::DetachedArrayBuffer => 0,
+            GcObjectType::TypedArray { .. } | GcObjectType::DataView { .. } => 24,
+            GcObjectType::Map(map) | GcObjectType::Set(map) => map.len() * MAP_ENTRY_SIZE,
+            GcObjectType::WeakMap(entries) => entries.len() * WEAK_ENTRY_SIZE,
+            GcObjectType::WeakSet(members) => members.len() * WEAK_ENTRY_SIZE,
+            GcObjectType::Accessor { .. } => 16,
         }
     }
 
@@ -366,6 +1082,20 @@ impl GarbageCollector {
                     references.insert(handle);
                 }
             }
+            GcObjectType::TypedArray { buffer, .. } | GcObjectType::DataView { buffer, .. } => {
+                references.insert(*buffer);
+            }
+            GcObjectType::Map(map) | GcObjectType::Set(map) => {
+                for (key, value) in map.iter() {
+                    references.insert(key);
+                    references.insert(value);
+                }
+            }
+            GcObjectType::Accessor { getter, setter } => {
+                references.extend(getter.iter().chain(setter));
+            }
+            // Weak collections hold nothing alive; marking and sweeping
+            // treat them specially
             _ => {}
         }
         
@@ -383,18 +1113,45 @@ impl GarbageCollector {
             total_collections: self.total_collections,
             bytes_allocated: self.bytes_allocated,
             bytes_freed: self.bytes_freed,
+            external_bytes: self.external_bytes,
+            interned_strings: self.strings.len(),
+            young_threshold: self.young_threshold,
+            full_threshold: self.full_threshold,
+            last_pause: self.last_pause,
         }
     }
 
     /// Force a full garbage collection
     pub fn force_collect(&mut self) -> usize {
-        self.full_collect()
+        let collected = self.full_collect();
+        self.live_bytes = self.bytes_allocated;
+        collected
     }
 
     /// Set collection thresholds
     pub fn set_thresholds(&mut self, young_threshold: usize, old_threshold: usize) {
-        self.young_threshold = young_threshold;
-        self.old_threshold = old_threshold;
+        self.set_config(GcConfig {
+            young_threshold,
+            old_threshold,
+            ..self.config.clone()
+        });
+    }
+
+    /// How the collector decides when to run
+    pub fn config(&self) -> &GcConfig {
+        &self.config
+    }
+
+    /// Run as `config` says from now on, starting again from its thresholds
+    pub fn set_config(&mut self, config: GcConfig) {
+        self.young_threshold = config.young_threshold;
+        self.full_threshold = config.old_threshold;
+        self.config = config;
+    }
+
+    /// Whether the heap holds more than `GcConfig::max_heap_size` bytes
+    pub fn heap_limit_exceeded(&self) -> bool {
+        self.config.max_heap_size.is_some_and(|max| self.bytes_allocated > max)
     }
 }
 
@@ -409,6 +1166,16 @@ pub struct GcStats {
     pub total_collections: usize,
     pub bytes_allocated: usize,
     pub bytes_freed: usize,
+    /// Native memory live objects keep alive, included in `bytes_allocated`
+    pub external_bytes: usize,
+    /// Live strings in the intern table
+    pub interned_strings: usize,
+    /// Heap growth that triggers the next minor collection
+    pub young_threshold: usize,
+    /// Live bytes that make the next collection a full one
+    pub full_threshold: usize,
+    /// How long the last collection took
+    pub last_pause: Duration,
 }
 
 impl Default for GarbageCollector {
@@ -424,7 +1191,7 @@ impl GarbageCollector {
     }
     
     pub fn allocate_string(&mut self, value: String) -> GcHandle {
-        self.allocate(GcObjectType::String(value))
+        self.allocate(GcObjectType::String(value.into()))
     }
     
     pub fn allocate_boolean(&mut self, value: bool) -> GcHandle {
@@ -439,8 +1206,9 @@ impl GarbageCollector {
         self.allocate(GcObjectType::Undefined)
     }
     
-    pub fn allocate_object(&mut self, properties: HashMap<String, GcHandle>) -> GcHandle {
-        self.allocate(GcObjectType::Object(properties))
+    /// A plain object with `properties`, added in iteration order
+    pub fn allocate_object(&mut self, properties: impl IntoIterator<Item = (String, GcHandle)>) -> GcHandle {
+        self.allocate(GcObjectType::Object(properties.into_iter().collect()))
     }
     
     pub fn allocate_array(&mut self, elements: Vec<GcHandle>) -> GcHandle {
@@ -455,4 +1223,78 @@ impl GarbageCollector {
     ) -> GcHandle {
         self.allocate(GcObjectType::Function { name, bytecode, closure })
     }
+    
+    pub fn allocate_regexp(&mut self, pattern: String, flags: String) -> GcHandle {
+        self.allocate(GcObjectType::RegExp { pattern, flags, last_index: 0 })
+    }
+    
+    /// A zero-filled `ArrayBuffer` of `byte_length` bytes
+    pub fn allocate_array_buffer(&mut self, byte_length: usize) -> GcHandle {
+        self.allocate(GcObjectType::ArrayBuffer(vec![0; byte_length]))
+    }
+    
+    pub fn allocate_typed_array(
+        &mut self,
+        kind: TypedArrayKind,
+        buffer: GcHandle,
+        byte_offset: usize,
+        length: usize,
+    ) -> GcHandle {
+        self.allocate(GcObjectType::TypedArray { kind, buffer, byte_offset, length })
+    }
+    
+    pub fn allocate_data_view(&mut self, buffer: GcHandle, byte_offset: usize, byte_length: usize) -> GcHandle {
+        self.allocate(GcObjectType::DataView { buffer, byte_offset, byte_length })
+    }
+    
+    pub fn allocate_map(&mut self) -> GcHandle {
+        self.allocate(GcObjectType::Map(OrderedMap::new()))
+    }
+    
+    pub fn allocate_set(&mut self) -> GcHandle {
+        self.allocate(GcObjectType::Set(OrderedMap::new()))
+    }
+    
+    pub fn allocate_weak_map(&mut self) -> GcHandle {
+        self.allocate(GcObjectType::WeakMap(HashMap::new()))
+    }
+    
+    pub fn allocate_weak_set(&mut self) -> GcHandle {
+        self.allocate(GcObjectType::WeakSet(HashSet::new()))
+    }
+    
+    pub fn allocate_promise(&mut self) -> GcHandle {
+        self.allocate(GcObjectType::Promise {
+            state: PromiseState::Pending,
+            value: None,
+            callbacks: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_a_million_node_chain_without_recursing() {
+        const LENGTH: usize = 1_000_000;
+        let mut gc = GarbageCollector::new();
+        gc.set_thresholds(usize::MAX, usize::MAX);
+
+        let mut head = gc.allocate_null();
+        for _ in 0..LENGTH {
+            head = gc.allocate_object([("next".to_string(), head)]);
+        }
+        gc.add_root(head);
+
+        // A minor collection marks the young chain, a full one the promoted one
+        assert_eq!(gc.collect(), 0);
+        assert_eq!(gc.force_collect(), 0);
+        assert_eq!(gc.stats().total_objects, LENGTH + 1);
+
+        gc.remove_root(head);
+        gc.force_collect();
+        assert_eq!(gc.stats().total_objects, 0);
+    }
 }
@@ -0,0 +1,265 @@
+//! Heap enumeration and queries for embedders
+//!
+//! `iter_objects` walks every object in the heap and `query` finds objects
+//! by kind, retained size or referrer, so a host can see what a script is
+//! keeping alive. An object's retained size is its own size plus that of
+//! everything reachable only through it, taken from the dominator tree of
+//! the object graph below the root set. Weak collection entries retain
+//! nothing, as for marking.
+
+use crate::{GarbageCollector, GcHandle, GcObjectType, Generation};
+use std::collections::{HashMap, HashSet};
+
+/// What the heap knows about one object
+#[derive(Debug, Clone)]
+pub struct ObjectInfo {
+    pub handle: GcHandle,
+    /// `GcObjectType::kind_name` of the object
+    pub kind: &'static str,
+    pub generation: Generation,
+    /// Estimated bytes of the object itself
+    pub size: usize,
+    pub is_root: bool,
+    pub prototype: Option<GcHandle>,
+    /// Objects it references, its prototype included, in handle order
+    pub references: Vec<GcHandle>,
+}
+
+/// An object `query` matched
+#[derive(Debug, Clone)]
+pub struct HeapObject {
+    pub info: ObjectInfo,
+    /// Estimated bytes that would be freed with the object: its own size
+    /// for garbage not collected yet
+    pub retained_size: usize,
+}
+
+/// Conditions for `GarbageCollector::query`; objects must meet all that are
+/// set
+#[derive(Debug, Clone, Default)]
+pub struct HeapQuery {
+    kind: Option<String>,
+    min_retained_size: usize,
+    referrer: Option<GcHandle>,
+    limit: Option<usize>,
+}
+
+impl HeapQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only objects of `kind`, as `GcObjectType::kind_name` names it
+    pub fn kind(mut self, kind: &str) -> Self {
+        self.kind = Some(kind.to_string());
+        self
+    }
+
+    /// Only objects retaining at least `bytes`
+    pub fn min_retained_size(mut self, bytes: usize) -> Self {
+        self.min_retained_size = bytes;
+        self
+    }
+
+    /// Only objects `referrer` references
+    pub fn referenced_by(mut self, referrer: GcHandle) -> Self {
+        self.referrer = Some(referrer);
+        self
+    }
+
+    /// At most `count` objects, the largest retainers
+    pub fn limit(mut self, count: usize) -> Self {
+        self.limit = Some(count);
+        self
+    }
+}
+
+impl GcObjectType {
+    /// The name heap queries know the kind of object by
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            GcObjectType::Number(_) => "Number",
+            GcObjectType::String(_) => "String",
+            GcObjectType::Boolean(_) => "Boolean",
+            GcObjectType::Null => "Null",
+            GcObjectType::Undefined => "Undefined",
+            GcObjectType::Symbol { .. } => "Symbol",
+            GcObjectType::Object(_) => "Object",
+            GcObjectType::Array(_) => "Array",
+            GcObjectType::Function { .. } => "Function",
+            GcObjectType::Promise { .. } => "Promise",
+            GcObjectType::RegExp { .. } => "RegExp",
+            GcObjectType::ArrayBuffer(_) | GcObjectType::DetachedArrayBuffer => "ArrayBuffer",
+            GcObjectType::TypedArray { kind, .. } => kind.name(),
+            GcObjectType::DataView { .. } => "DataView",
+            GcObjectType::Map(_) => "Map",
+            GcObjectType::Set(_) => "Set",
+            GcObjectType::WeakMap(_) => "WeakMap",
+            GcObjectType::WeakSet(_) => "WeakSet",
+            GcObjectType::Accessor { .. } => "Accessor",
+        }
+    }
+}
+
+impl GarbageCollector {
+    /// What the heap knows about `handle`, if it is alive
+    pub fn object_info(&self, handle: GcHandle) -> Option<ObjectInfo> {
+        let object = self.objects.get(&handle)?;
+        let mut references: Vec<GcHandle> = object.references.iter().copied().collect();
+        references.sort_by_key(GcHandle::id);
+        Some(ObjectInfo {
+            handle,
+            kind: object.object_type.kind_name(),
+            generation: object.generation,
+            size: object.size,
+            is_root: self.is_root(handle),
+            prototype: object.prototype,
+            references,
+        })
+    }
+
+    /// Every object `filter` accepts, in handle order
+    pub fn iter_objects<'a>(
+        &'a self,
+        filter: impl Fn(&ObjectInfo) -> bool + 'a,
+    ) -> impl Iterator<Item = ObjectInfo> + 'a {
+        let mut handles: Vec<GcHandle> = self.objects.keys().copied().collect();
+        handles.sort_by_key(GcHandle::id);
+        handles
+            .into_iter()
+            .filter_map(|handle| self.object_info(handle))
+            .filter(move |info| filter(info))
+    }
+
+    /// The objects that reference `handle`, in handle order
+    pub fn referrers(&self, handle: GcHandle) -> Vec<GcHandle> {
+        let mut referrers: Vec<GcHandle> = self
+            .objects
+            .iter()
+            .filter(|(_, object)| object.references.contains(&handle))
+            .map(|(&referrer, _)| referrer)
+            .collect();
+        referrers.sort_by_key(GcHandle::id);
+        referrers
+    }
+
+    /// The objects matching `query` with their retained sizes, largest
+    /// retainer first
+    pub fn query(&self, query: &HeapQuery) -> Vec<HeapObject> {
+        let referenced = query
+            .referrer
+            .map(|referrer| self.objects.get(&referrer).map(|object| &object.references));
+        let retained_sizes = self.retained_sizes();
+
+        let mut matches: Vec<HeapObject> = self
+            .iter_objects(|info| query.kind.as_deref().is_none_or(|kind| info.kind == kind))
+            .filter(|info| {
+                referenced.is_none_or(|references| references.is_some_and(|references| references.contains(&info.handle)))
+            })
+            .map(|info| HeapObject {
+                retained_size: retained_sizes.get(&info.handle).copied().unwrap_or(info.size),
+                info,
+            })
+            .filter(|object| object.retained_size >= query.min_retained_size)
+            .collect();
+        matches.sort_by(|a, b| b.retained_size.cmp(&a.retained_size).then(a.info.handle.id().cmp(&b.info.handle.id())));
+        if let Some(limit) = query.limit {
+            matches.truncate(limit);
+        }
+        matches
+    }
+
+    /// The retained size of every object reachable from the roots.
+    ///
+    /// Numbers the reachable objects in depth-first postorder below a
+    /// virtual root that references the root set, finds immediate
+    /// dominators with the iterative algorithm of Cooper, Harvey and
+    /// Kennedy, and sums sizes up the dominator tree. A dominator always
+    /// finishes after the objects it dominates, so one pass in postorder
+    /// adds each subtree into its parent.
+    pub fn retained_sizes(&self) -> HashMap<GcHandle, usize> {
+        let successors = |node: Option<GcHandle>| -> Vec<GcHandle> {
+            let mut next: Vec<GcHandle> = match node {
+                None => self.root_set.keys().copied().collect(),
+                Some(handle) => self.objects[&handle].references.iter().copied().collect(),
+            };
+            next.retain(|handle| self.objects.contains_key(handle));
+            next
+        };
+
+        // `None` stands for the virtual root, which comes last
+        let mut postorder: Vec<Option<GcHandle>> = Vec::new();
+        let mut number: HashMap<GcHandle, usize> = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![(None, successors(None))];
+        while let Some((node, pending)) = stack.last_mut() {
+            let node = *node;
+            match pending.pop() {
+                Some(next) => {
+                    if visited.insert(next) {
+                        stack.push((Some(next), successors(Some(next))));
+                    }
+                }
+                None => {
+                    stack.pop();
+                    if let Some(handle) = node {
+                        number.insert(handle, postorder.len());
+                    }
+                    postorder.push(node);
+                }
+            }
+        }
+
+        let root = postorder.len() - 1;
+        let mut predecessors = vec![Vec::new(); postorder.len()];
+        for (n, &node) in postorder.iter().enumerate() {
+            for successor in successors(node) {
+                predecessors[number[&successor]].push(n);
+            }
+        }
+
+        let mut idom: Vec<Option<usize>> = vec![None; postorder.len()];
+        idom[root] = Some(root);
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while a < b {
+                    a = idom[a].expect("processed node");
+                }
+                while b < a {
+                    b = idom[b].expect("processed node");
+                }
+            }
+            a
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for n in (0..root).rev() {
+                let mut new_idom = None;
+                for &p in &predecessors[n] {
+                    if idom[p].is_some() {
+                        new_idom = Some(new_idom.map_or(p, |current| intersect(&idom, p, current)));
+                    }
+                }
+                if new_idom.is_some() && idom[n] != new_idom {
+                    idom[n] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut retained: Vec<usize> = postorder
+            .iter()
+            .map(|node| node.map_or(0, |handle| self.objects[&handle].size))
+            .collect();
+        for n in 0..root {
+            let dominator = idom[n].expect("reachable node");
+            retained[dominator] += retained[n];
+        }
+        postorder
+            .into_iter()
+            .zip(retained)
+            .filter_map(|(node, size)| node.map(|handle| (handle, size)))
+            .collect()
+    }
+}
@@ -0,0 +1,162 @@
+//! Roots held by the host
+//!
+//! Roots are counted: a handle added to the root set n times stays a root
+//! until it is removed n times, so independent holders never unroot each
+//! other's objects. Rather than pairing `add_root` and `remove_root` by
+//! hand, embedders hold a `GcRoot` guard for a single object, or open a
+//! `HandleScope` that roots everything allocated through it until it is
+//! dropped.
+//!
+//! A collector that `defer_collections` runs no collection on its own:
+//! allocations that would trigger one raise a flag instead, and the owner
+//! collects at its next safepoint with `collect_with_roots`, passing the
+//! handles it holds outside the heap. The VM does this for its stack,
+//! frames and globals.
+
+use crate::{GarbageCollector, GcHandle};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+/// Keeps one object rooted until dropped. Dropping locks the collector, so
+/// drop the guard while the collector is unlocked.
+pub struct GcRoot<'gc> {
+    gc: &'gc Mutex<GarbageCollector>,
+    handle: GcHandle,
+}
+
+impl<'gc> GcRoot<'gc> {
+    pub fn new(gc: &'gc Mutex<GarbageCollector>, handle: GcHandle) -> Self {
+        gc.lock().unwrap_or_else(PoisonError::into_inner).add_root(handle);
+        Self { gc, handle }
+    }
+
+    /// The rooted object
+    pub fn handle(&self) -> GcHandle {
+        self.handle
+    }
+}
+
+impl Drop for GcRoot<'_> {
+    fn drop(&mut self) {
+        self.gc.lock().unwrap_or_else(PoisonError::into_inner).remove_root(self.handle);
+    }
+}
+
+/// Roots every object allocated through it until dropped, like V8's handle
+/// scopes. Scopes nest: a scope opened on another one closes first, and
+/// `escape` hands an object over to the enclosing scope.
+pub struct HandleScope<'gc> {
+    gc: &'gc mut GarbageCollector,
+}
+
+impl<'gc> HandleScope<'gc> {
+    pub fn new(gc: &'gc mut GarbageCollector) -> Self {
+        gc.scopes.push(Vec::new());
+        Self { gc }
+    }
+
+    /// Root `handle` until the scope closes, e.g. one allocated before it
+    /// was opened
+    pub fn root(&mut self, handle: GcHandle) -> GcHandle {
+        self.gc.root_in_scope(handle);
+        handle
+    }
+
+    /// Keep `handle` rooted by the enclosing scope once this one closes.
+    /// Outside any other scope the handle is left unrooted, for the caller
+    /// to root.
+    pub fn escape(&mut self, handle: GcHandle) -> GcHandle {
+        let depth = self.gc.scopes.len();
+        if depth >= 2 {
+            self.gc.scopes[depth - 2].push(handle);
+            self.gc.add_root(handle);
+        }
+        handle
+    }
+}
+
+impl Deref for HandleScope<'_> {
+    type Target = GarbageCollector;
+
+    fn deref(&self) -> &GarbageCollector {
+        self.gc
+    }
+}
+
+impl DerefMut for HandleScope<'_> {
+    fn deref_mut(&mut self) -> &mut GarbageCollector {
+        self.gc
+    }
+}
+
+impl Drop for HandleScope<'_> {
+    fn drop(&mut self) {
+        for handle in self.gc.scopes.pop().unwrap_or_default() {
+            self.gc.remove_root(handle);
+        }
+    }
+}
+
+impl GarbageCollector {
+    /// Add a handle to the root set
+    pub fn add_root(&mut self, handle: GcHandle) {
+        *self.root_set.entry(handle).or_insert(0) += 1;
+    }
+
+    /// Remove a handle from the root set, once for every time it was added
+    pub fn remove_root(&mut self, handle: GcHandle) {
+        if let Some(count) = self.root_set.get_mut(&handle) {
+            *count -= 1;
+            if *count == 0 {
+                self.root_set.remove(&handle);
+            }
+        }
+    }
+
+    /// Whether `handle` is in the root set
+    pub fn is_root(&self, handle: GcHandle) -> bool {
+        self.root_set.contains_key(&handle)
+    }
+
+    /// Root `handle` in the innermost open `HandleScope`, if any
+    pub(crate) fn root_in_scope(&mut self, handle: GcHandle) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(handle);
+            self.add_root(handle);
+        }
+    }
+
+    /// Stop collecting during allocation. Allocations that would trigger a
+    /// collection set the returned flag instead, and the caller runs
+    /// `collect_with_roots` at its next safepoint.
+    pub fn defer_collections(&mut self) -> Arc<AtomicBool> {
+        Arc::clone(self.collection_requested.get_or_insert_with(Default::default))
+    }
+
+    /// Collect with `roots` added to the root set for the collection
+    pub fn collect_with_roots(&mut self, roots: impl IntoIterator<Item = GcHandle>) -> usize {
+        let paused_at = Instant::now();
+        let roots: Vec<GcHandle> = roots.into_iter().collect();
+        for &handle in &roots {
+            self.add_root(handle);
+        }
+        let collected = self.collect_since(paused_at);
+        for &handle in &roots {
+            self.remove_root(handle);
+        }
+        collected
+    }
+
+    /// Collect now, or at the owner's next safepoint if collections are
+    /// deferred
+    pub(crate) fn trigger_collection(&mut self) {
+        match &self.collection_requested {
+            Some(requested) => requested.store(true, Ordering::Relaxed),
+            None => {
+                self.collect();
+            }
+        }
+    }
+}
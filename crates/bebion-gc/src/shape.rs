@@ -0,0 +1,343 @@
+//! Object shapes
+//!
+//! A plain object keeps its property names in a shape (hidden class) and
+//! their values in a dense slot vector in the same order. Objects that gain
+//! the same properties in the same order share one shape: every shape
+//! remembers the shapes one added property away, so building an object
+//! walks a transition tree from the empty root shape instead of hashing
+//! each name into a map of its own. A shape's id stands for its whole
+//! layout, which lets the VM cache where a property lives and later read it
+//! with `get_property_slot` after one comparison.
+//!
+//! Deleting a property, or adding more than `MAX_SHAPED_PROPERTIES`, moves
+//! an object to a dictionary shape of its own. A dictionary shape changes
+//! in place and takes a new id whenever its layout does, so a cached slot
+//! never outlives the layout it was found in.
+
+use crate::{GarbageCollector, GcHandle, GcObjectType};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Index;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+/// Properties an object can have before it leaves the transition tree for
+/// a dictionary shape
+pub const MAX_SHAPED_PROPERTIES: usize = 64;
+
+static NEXT_SHAPE_ID: AtomicU64 = AtomicU64::new(1);
+static ROOT_SHAPE: OnceLock<Arc<Shape>> = OnceLock::new();
+
+/// Identifies a shape's layout: two objects with the same id keep the same
+/// property in the same slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShapeId(u64);
+
+impl ShapeId {
+    fn next() -> Self {
+        ShapeId(NEXT_SHAPE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// The property names of the objects that share it, in slot order
+pub struct Shape {
+    id: ShapeId,
+    /// Kept alive so a transition that dies can be found again with the
+    /// same id, rather than recreated
+    parent: Option<Arc<Shape>>,
+    keys: Vec<Arc<str>>,
+    slots: HashMap<Arc<str>, usize>,
+    /// Shapes with one more property, by its name
+    transitions: Mutex<HashMap<Arc<str>, Weak<Shape>>>,
+    dictionary: bool,
+}
+
+impl Shape {
+    /// The shape of an object without properties
+    pub fn root() -> Arc<Shape> {
+        Arc::clone(ROOT_SHAPE.get_or_init(|| {
+            Arc::new(Shape {
+                id: ShapeId::next(),
+                parent: None,
+                keys: Vec::new(),
+                slots: HashMap::new(),
+                transitions: Mutex::new(HashMap::new()),
+                dictionary: false,
+            })
+        }))
+    }
+
+    pub fn id(&self) -> ShapeId {
+        self.id
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The shape this one adds its last property to
+    pub fn parent(&self) -> Option<&Arc<Shape>> {
+        self.parent.as_ref()
+    }
+
+    /// Whether this shape belongs to one object and changes with it
+    pub fn is_dictionary(&self) -> bool {
+        self.dictionary
+    }
+
+    /// The slot holding property `key`
+    pub fn slot(&self, key: &str) -> Option<usize> {
+        self.slots.get(key).copied()
+    }
+
+    /// Property names in slot order, which is the order they were added in
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|key| &**key)
+    }
+
+    /// The shared shape with `key` added after this one's properties
+    fn transition(self: &Arc<Self>, key: &str) -> Arc<Shape> {
+        let mut transitions = self.transitions.lock().unwrap();
+        if let Some(shape) = transitions.get(key).and_then(Weak::upgrade) {
+            return shape;
+        }
+
+        let key: Arc<str> = Arc::from(key);
+        let mut keys = self.keys.clone();
+        keys.push(Arc::clone(&key));
+        let mut slots = self.slots.clone();
+        slots.insert(Arc::clone(&key), keys.len() - 1);
+        let shape = Arc::new(Shape {
+            id: ShapeId::next(),
+            parent: Some(Arc::clone(self)),
+            keys,
+            slots,
+            transitions: Mutex::new(HashMap::new()),
+            dictionary: false,
+        });
+        transitions.retain(|_, shape| shape.strong_count() > 0);
+        transitions.insert(key, Arc::downgrade(&shape));
+        shape
+    }
+
+    /// A dictionary shape with this shape's layout
+    fn to_dictionary(&self) -> Shape {
+        Shape {
+            id: ShapeId::next(),
+            parent: None,
+            keys: self.keys.clone(),
+            slots: self.slots.clone(),
+            transitions: Mutex::new(HashMap::new()),
+            dictionary: true,
+        }
+    }
+}
+
+impl fmt::Debug for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shape")
+            .field("id", &self.id)
+            .field("keys", &self.keys)
+            .field("dictionary", &self.dictionary)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The properties of a plain object: a shape and the value of each of its
+/// slots. Iterates in the order properties were added.
+#[derive(Clone)]
+pub struct PropertyMap {
+    shape: Arc<Shape>,
+    values: Vec<GcHandle>,
+}
+
+impl PropertyMap {
+    pub fn new() -> Self {
+        Self { shape: Shape::root(), values: Vec::new() }
+    }
+
+    pub fn shape(&self) -> &Arc<Shape> {
+        &self.shape
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.shape.slot(key).is_some()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&GcHandle> {
+        self.shape.slot(key).map(|slot| &self.values[slot])
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut GcHandle> {
+        self.shape.slot(key).map(|slot| &mut self.values[slot])
+    }
+
+    /// The value in `slot`
+    pub fn get_slot(&self, slot: usize) -> Option<GcHandle> {
+        self.values.get(slot).copied()
+    }
+
+    /// Set property `key`, returning the value it replaced. A new property
+    /// takes the next slot and moves the object to the shape with it.
+    pub fn insert(&mut self, key: &str, value: GcHandle) -> Option<GcHandle> {
+        if let Some(slot) = self.shape.slot(key) {
+            return Some(std::mem::replace(&mut self.values[slot], value));
+        }
+
+        if self.shape.dictionary || self.values.len() >= MAX_SHAPED_PROPERTIES {
+            let shape = self.dictionary_mut();
+            let key: Arc<str> = Arc::from(key);
+            shape.slots.insert(Arc::clone(&key), shape.keys.len());
+            shape.keys.push(key);
+        } else {
+            self.shape = self.shape.transition(key);
+        }
+        self.values.push(value);
+        None
+    }
+
+    /// Delete property `key`, returning its value. The object keeps a
+    /// dictionary shape from then on.
+    pub fn remove(&mut self, key: &str) -> Option<GcHandle> {
+        let slot = self.shape.slot(key)?;
+        let shape = self.dictionary_mut();
+        shape.keys.remove(slot);
+        shape.slots.remove(key);
+        for (index, key) in shape.keys.iter().enumerate().skip(slot) {
+            shape.slots.insert(Arc::clone(key), index);
+        }
+        Some(self.values.remove(slot))
+    }
+
+    /// The object's own dictionary shape, made first if it has none, with
+    /// a new id as its layout is about to change
+    fn dictionary_mut(&mut self) -> &mut Shape {
+        if !self.shape.dictionary || Arc::get_mut(&mut self.shape).is_none() {
+            self.shape = Arc::new(self.shape.to_dictionary());
+        }
+        let shape = Arc::get_mut(&mut self.shape).expect("dictionary shape is unshared");
+        shape.id = ShapeId::next();
+        shape
+    }
+
+    /// Properties in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &GcHandle)> {
+        self.shape.keys().zip(&self.values)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.shape.keys()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &GcHandle> {
+        self.values.iter()
+    }
+}
+
+impl Default for PropertyMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for PropertyMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl Index<&str> for PropertyMap {
+    type Output = GcHandle;
+
+    fn index(&self, key: &str) -> &GcHandle {
+        self.get(key).expect("no such property")
+    }
+}
+
+impl<K: AsRef<str>> Extend<(K, GcHandle)> for PropertyMap {
+    fn extend<I: IntoIterator<Item = (K, GcHandle)>>(&mut self, properties: I) {
+        for (key, value) in properties {
+            self.insert(key.as_ref(), value);
+        }
+    }
+}
+
+impl<K: AsRef<str>> FromIterator<(K, GcHandle)> for PropertyMap {
+    fn from_iter<I: IntoIterator<Item = (K, GcHandle)>>(properties: I) -> Self {
+        let mut map = PropertyMap::new();
+        map.extend(properties);
+        map
+    }
+}
+
+impl GarbageCollector {
+    /// The shape of the object `handle`, if it is a plain object whose
+    /// shape can be cached
+    pub fn shape_id(&self, handle: GcHandle) -> Option<ShapeId> {
+        match &self.objects.get(&handle)?.object_type {
+            GcObjectType::Object(properties) if !properties.shape.dictionary => Some(properties.shape.id),
+            _ => None,
+        }
+    }
+
+    /// Where property `key` of the object `handle` lives, to cache and
+    /// read back with `get_property_slot`. `None` if it is not a plain
+    /// object with that property, or its shape is a dictionary, which
+    /// would change under the cache.
+    pub fn property_slot(&self, handle: GcHandle, key: &str) -> Option<(ShapeId, usize)> {
+        match &self.objects.get(&handle)?.object_type {
+            GcObjectType::Object(properties) if !properties.shape.dictionary => {
+                Some((properties.shape.id, properties.shape.slot(key)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// The value in `slot` of the object `handle`, if its shape is still
+    /// `shape`
+    pub fn get_property_slot(&self, handle: GcHandle, shape: ShapeId, slot: usize) -> Option<GcHandle> {
+        match &self.objects.get(&handle)?.object_type {
+            GcObjectType::Object(properties) if properties.shape.id == shape => properties.get_slot(slot),
+            _ => None,
+        }
+    }
+
+    /// Write `value` into `slot` of the object `handle` if its shape is
+    /// still `shape`, keeping its references current. Returns false,
+    /// changing nothing, otherwise or if the slot holds an accessor, whose
+    /// setter a store has to call instead.
+    pub fn set_property_slot(&mut self, handle: GcHandle, shape: ShapeId, slot: usize, value: GcHandle) -> bool {
+        let current = self.get_property_slot(handle, shape, slot);
+        if current.is_some_and(|current| self.accessor(current).is_some()) {
+            return false;
+        }
+        let Some(object) = self.objects.get_mut(&handle) else {
+            return false;
+        };
+        let GcObjectType::Object(properties) = &mut object.object_type else {
+            return false;
+        };
+        if properties.shape.id != shape || slot >= properties.values.len() {
+            return false;
+        }
+
+        let previous = std::mem::replace(&mut properties.values[slot], value);
+        if previous != value {
+            Self::replace_reference(object, Some(previous), value);
+            self.record_write(handle, value);
+        }
+        true
+    }
+}
@@ -0,0 +1,201 @@
+//! Heap snapshots
+//!
+//! A `HeapSnapshot` is the whole object graph at one moment: every object
+//! with its kind, a display name and its self and retained sizes, and every
+//! reference between objects as a named edge. It serializes as is, and
+//! tools convert it to formats such as Chrome DevTools' `.heapsnapshot` to
+//! find what keeps memory alive.
+//!
+//! Nodes are named by slot, like `GcHandle::id`, so an id is unique within
+//! one snapshot but may name another object in a later one.
+
+use crate::{GarbageCollector, GcHandle, GcObject, GcObjectType};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Longest string value kept as a node name
+const MAX_NAME_LENGTH: usize = 256;
+
+/// The object graph of the heap
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HeapSnapshot {
+    /// Every object, in handle order
+    pub nodes: Vec<SnapshotNode>,
+    /// Every reference, grouped by referrer in node order
+    pub edges: Vec<SnapshotEdge>,
+}
+
+/// One object of a `HeapSnapshot`
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotNode {
+    pub id: usize,
+    /// `GcObjectType::kind_name` of the object
+    pub kind: &'static str,
+    /// The value of a string, the name of a function, otherwise the kind
+    pub name: String,
+    pub self_size: usize,
+    /// Bytes that would be freed with the object, or its own size if it is
+    /// unreachable from the roots
+    pub retained_size: usize,
+    pub is_root: bool,
+}
+
+/// How one object refers to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    /// A named property, or the prototype as `__proto__`
+    Property,
+    /// An array element
+    Element,
+    /// A variable a function closes over
+    Context,
+    /// A reference the engine keeps, such as a view's buffer
+    Internal,
+    /// A weak collection entry, which keeps nothing alive
+    Weak,
+}
+
+/// What an edge is called
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum EdgeName {
+    Name(String),
+    Index(usize),
+}
+
+/// One reference of a `HeapSnapshot`
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotEdge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+    pub name: EdgeName,
+}
+
+impl GarbageCollector {
+    /// Snapshot the heap, with retained sizes below the root set
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        let retained_sizes = self.retained_sizes();
+        let mut handles: Vec<GcHandle> = self.objects.keys().copied().collect();
+        handles.sort_by_key(GcHandle::id);
+
+        let mut snapshot = HeapSnapshot::default();
+        for handle in handles {
+            let object = &self.objects[&handle];
+            snapshot.nodes.push(SnapshotNode {
+                id: handle.id(),
+                kind: object.object_type.kind_name(),
+                name: node_name(&object.object_type),
+                self_size: object.size,
+                retained_size: retained_sizes.get(&handle).copied().unwrap_or(object.size),
+                is_root: self.is_root(handle),
+            });
+            snapshot.edges.extend(
+                edges_of(object)
+                    .into_iter()
+                    .filter(|(_, _, to)| self.objects.contains_key(to))
+                    .map(|(kind, name, to)| SnapshotEdge { from: handle.id(), to: to.id(), kind, name }),
+            );
+        }
+        snapshot
+    }
+
+    /// Snapshot the heap with `roots` added to the root set, like
+    /// `collect_with_roots`
+    pub fn heap_snapshot_with_roots(&mut self, roots: impl IntoIterator<Item = GcHandle>) -> HeapSnapshot {
+        let roots: Vec<GcHandle> = roots.into_iter().collect();
+        for &handle in &roots {
+            self.add_root(handle);
+        }
+        let snapshot = self.heap_snapshot();
+        for &handle in &roots {
+            self.remove_root(handle);
+        }
+        snapshot
+    }
+}
+
+fn node_name(object_type: &GcObjectType) -> String {
+    match object_type {
+        GcObjectType::String(value) => value.chars().take(MAX_NAME_LENGTH).collect(),
+        GcObjectType::Function { name, .. } => name.clone().unwrap_or_else(|| "(anonymous)".to_string()),
+        GcObjectType::Symbol { description, .. } => format!("Symbol({})", description.as_deref().unwrap_or("")),
+        other => other.kind_name().to_string(),
+    }
+}
+
+/// Named references in name order
+fn by_name(entries: &HashMap<String, GcHandle>) -> Vec<(EdgeName, GcHandle)> {
+    let mut entries: Vec<(&String, &GcHandle)> = entries.iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+    entries.into_iter().map(|(key, &value)| (EdgeName::Name(key.clone()), value)).collect()
+}
+
+/// The references of `object`, named, in a stable order: properties as
+/// they were added, closure variables by name
+fn edges_of(object: &GcObject) -> Vec<(EdgeKind, EdgeName, GcHandle)> {
+    let name = |name: &str| EdgeName::Name(name.to_string());
+
+    let mut edges = Vec::new();
+    match &object.object_type {
+        GcObjectType::Object(properties) => {
+            edges.extend(properties.iter().map(|(key, &value)| (EdgeKind::Property, name(key), value)));
+        }
+        GcObjectType::Array(elements) => {
+            edges.extend(
+                elements
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &value)| (EdgeKind::Element, EdgeName::Index(index), value)),
+            );
+        }
+        GcObjectType::Function { closure, .. } => {
+            edges.extend(by_name(closure).into_iter().map(|(variable, value)| (EdgeKind::Context, variable, value)));
+        }
+        GcObjectType::Promise { value, callbacks, .. } => {
+            edges.extend(value.map(|value| (EdgeKind::Internal, name("value"), value)));
+            edges.extend(callbacks.iter().map(|&callback| (EdgeKind::Internal, name("callback"), callback)));
+        }
+        GcObjectType::TypedArray { buffer, .. } | GcObjectType::DataView { buffer, .. } => {
+            edges.push((EdgeKind::Internal, name("buffer"), *buffer));
+        }
+        GcObjectType::Map(entries) => {
+            for (key, value) in entries.iter() {
+                edges.push((EdgeKind::Internal, name("key"), key));
+                edges.push((EdgeKind::Internal, name("value"), value));
+            }
+        }
+        GcObjectType::Set(entries) => {
+            edges.extend(entries.iter().map(|(key, _)| (EdgeKind::Internal, name("value"), key)));
+        }
+        GcObjectType::WeakMap(entries) => {
+            let mut entries: Vec<(&GcHandle, &GcHandle)> = entries.iter().collect();
+            entries.sort_by_key(|(key, _)| key.id());
+            for (&key, &value) in entries {
+                edges.push((EdgeKind::Weak, name("key"), key));
+                edges.push((EdgeKind::Weak, name("value"), value));
+            }
+        }
+        GcObjectType::WeakSet(members) => {
+            let mut members: Vec<GcHandle> = members.iter().copied().collect();
+            members.sort_by_key(GcHandle::id);
+            edges.extend(members.into_iter().map(|member| (EdgeKind::Weak, name("value"), member)));
+        }
+        GcObjectType::Accessor { getter, setter } => {
+            edges.extend(getter.map(|getter| (EdgeKind::Internal, name("get"), getter)));
+            edges.extend(setter.map(|setter| (EdgeKind::Internal, name("set"), setter)));
+        }
+        GcObjectType::Number(_)
+        | GcObjectType::String(_)
+        | GcObjectType::Boolean(_)
+        | GcObjectType::Null
+        | GcObjectType::Undefined
+        | GcObjectType::Symbol { .. }
+        | GcObjectType::RegExp { .. }
+        | GcObjectType::ArrayBuffer(_)
+        | GcObjectType::DetachedArrayBuffer => {}
+    }
+    edges.extend(object.prototype.map(|prototype| (EdgeKind::Property, name("__proto__"), prototype)));
+    edges
+}
@@ -0,0 +1,86 @@
+//! Per-collection telemetry
+//!
+//! Every collection leaves a `GcEvent` saying what kind it was, how long it
+//! took and stopped the owner for, and what it freed. Events queue up for
+//! `take_events`, keeping only the latest `MAX_QUEUED_EVENTS` if nobody
+//! takes them, and a listener set with `set_collection_listener` hears of
+//! each one as it happens, e.g. to print a trace line.
+
+use crate::{GarbageCollector, Generation};
+use std::fmt;
+use std::time::Duration;
+
+/// Events kept for `take_events` before the oldest are dropped
+pub const MAX_QUEUED_EVENTS: usize = 1024;
+
+/// Hears of each collection as it finishes, with the collector locked
+pub type CollectionListener = Box<dyn FnMut(&GcEvent) + Send>;
+
+/// What one collection did
+#[derive(Debug, Clone, PartialEq)]
+pub struct GcEvent {
+    /// Which collection this was, counting from 1
+    pub sequence: usize,
+    /// `Young` for a minor collection, `Old` for a full one
+    pub generation: Generation,
+    /// Time spent marking and sweeping
+    pub duration: Duration,
+    /// Time the owner was stopped: `duration` plus rooting the handles it
+    /// passed to `collect_with_roots`
+    pub pause: Duration,
+    pub objects_freed: usize,
+    pub bytes_freed: usize,
+    /// Heap bytes left afterwards
+    pub live_bytes: usize,
+    /// Objects and bytes allocated since the collection before
+    pub allocations: usize,
+    pub allocated_bytes: usize,
+}
+
+impl fmt::Display for GcEvent {
+    /// One line in the style of V8's `--trace-gc`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.generation {
+            Generation::Young => "minor",
+            Generation::Old => "full",
+        };
+        write!(
+            f,
+            "#{} {}: {:.3}ms pause ({:.3}ms collecting), {} -> {} bytes, freed {} objects ({} bytes), {} allocations ({} bytes) since last",
+            self.sequence,
+            kind,
+            self.pause.as_secs_f64() * 1000.0,
+            self.duration.as_secs_f64() * 1000.0,
+            self.live_bytes + self.bytes_freed,
+            self.live_bytes,
+            self.objects_freed,
+            self.bytes_freed,
+            self.allocations,
+            self.allocated_bytes,
+        )
+    }
+}
+
+impl GarbageCollector {
+    /// The collections since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<GcEvent> {
+        self.events.drain(..).collect()
+    }
+
+    /// Call `listener` as each collection finishes, replacing any listener
+    /// set before
+    pub fn set_collection_listener(&mut self, listener: Option<CollectionListener>) {
+        self.collection_listener = listener;
+    }
+
+    /// Queue `event` and tell the listener
+    pub(crate) fn record_event(&mut self, event: GcEvent) {
+        if let Some(listener) = &mut self.collection_listener {
+            listener(&event);
+        }
+        if self.events.len() == MAX_QUEUED_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
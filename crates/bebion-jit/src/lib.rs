@@ -0,0 +1,141 @@
+//! Bebion Baseline JIT
+//! 
+//! Compiles hot functions to native code with Cranelift. The tier registers
+//! with the VM's tiering hooks, compiles a function once its entry count
+//! crosses the hot threshold, and hands the VM native code for later calls.
+//! Numeric code (arithmetic, comparisons, locals and branches) runs natively;
+//! at anything else the code side-exits and the interpreter finishes the
+//! frame from that instruction.
+//!
+//! Globals, string keys and property access call back into the VM, which
+//! answers reads and writes from its inline caches. Values other than
+//! numbers, booleans and `undefined` are boxed for the run and held in
+//! slots by index; a property read that gives one leaves native code after
+//! the read. Calls leave native code too.
+
+pub mod translate;
+
+pub use translate::{JitCompiler, JitError, JitResult, Kind, NativeContext, NativeFunction, NativeResult};
+
+use bebion_runtime::tier::{CodeId, CompiledCode, HotFunction, NativeHost, NativeOutcome, Tier};
+use bebion_runtime::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// The baseline tier: compiles each hot function once
+pub struct JitTier {
+    /// `None` when Cranelift does not support the host
+    compiler: Option<JitCompiler>,
+    compiled: HashMap<CodeId, Arc<JitCode>>,
+    rejected: HashSet<CodeId>,
+}
+
+impl JitTier {
+    pub fn new() -> Self {
+        let compiler = JitCompiler::new()
+            .map_err(|e| warn!("JIT disabled: {}", e))
+            .ok();
+
+        Self {
+            compiler,
+            compiled: HashMap::new(),
+            rejected: HashSet::new(),
+        }
+    }
+
+    pub fn compiled_count(&self) -> usize {
+        self.compiled.len()
+    }
+}
+
+impl Default for JitTier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tier for JitTier {
+    fn name(&self) -> &str {
+        "baseline-jit"
+    }
+
+    fn on_hot_function(&mut self, hot_function: &HotFunction) {
+        if self.compiled.contains_key(&hot_function.code) || self.rejected.contains(&hot_function.code) {
+            return;
+        }
+        let Some(compiler) = self.compiler.as_mut() else {
+            return;
+        };
+
+        let name = hot_function.name.as_deref().unwrap_or("<anonymous>");
+        match compiler.compile(&hot_function.bytecode, hot_function.entry_locals) {
+            Ok(function) => {
+                debug!("JIT compiled {} ({} side exits)", name, function.exits.len());
+                let code = JitCode {
+                    function,
+                    _bytecode: Arc::clone(&hot_function.bytecode),
+                };
+                self.compiled.insert(hot_function.code, Arc::new(code));
+            }
+            Err(e) => {
+                debug!("Not compiling {}: {}", name, e);
+                self.rejected.insert(hot_function.code);
+            }
+        }
+    }
+
+    fn compiled_code(&self, code: CodeId) -> Option<Arc<dyn CompiledCode>> {
+        self.compiled.get(&code).map(|code| Arc::clone(code) as Arc<dyn CompiledCode>)
+    }
+}
+
+struct JitCode {
+    function: NativeFunction,
+    /// Keeps the bytecode, and with it the `CodeId`, from being reused
+    _bytecode: Arc<bebion_compiler::bytecode::Bytecode>,
+}
+
+impl CompiledCode for JitCode {
+    fn invoke(&self, host: &mut dyn NativeHost, locals: &mut Vec<Value>) -> Option<NativeOutcome> {
+        let function = &self.function;
+
+        // The code was compiled for numeric arguments with every other local
+        // still undefined
+        let mut slots = vec![0.0; function.local_count];
+        for (slot, value) in locals.iter().enumerate() {
+            match value {
+                Value::Number(n) if slot < function.entry_locals => slots[slot] = *n,
+                Value::Undefined if slot >= function.entry_locals => {}
+                _ => return None,
+            }
+        }
+        if locals.len() < function.entry_locals {
+            return None;
+        }
+
+        let mut context = NativeContext::new(host);
+        let mut stack = vec![0.0; function.max_stack];
+        match function.invoke(&mut slots, &mut stack, &mut context) {
+            NativeResult::Returned(value, kind) => Some(NativeOutcome::Returned(context.value(value, kind))),
+            NativeResult::Exit(index) => {
+                let exit = &function.exits[index];
+                locals.resize(function.local_count, Value::Undefined);
+                for (slot, kind) in exit.state.locals.iter().enumerate() {
+                    locals[slot] = context.value(slots[slot], *kind);
+                }
+                let stack = exit
+                    .state
+                    .stack
+                    .iter()
+                    .enumerate()
+                    .map(|(depth, kind)| context.value(stack[depth], *kind))
+                    .collect();
+                Some(match context.take_error() {
+                    Some(error) => NativeOutcome::Threw { pc: exit.pc, stack, error },
+                    None => NativeOutcome::SideExit { pc: exit.pc, stack },
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,921 @@
+//! Translation of bytecode to native code through Cranelift
+//!
+//! Compiled code keeps every value in an `f64` and tracks statically whether
+//! it holds a number, a boolean (0 or 1), `undefined`, or the index of any
+//! other value boxed in the `NativeContext`, so only numeric code needs
+//! native lowering. Globals, non-numeric constants and property access call
+//! stubs that go through the VM and its inline caches. Each instruction is
+//! checked against the kinds of its operands; anything else becomes a side
+//! exit that writes the locals and operand stack back and lets the
+//! interpreter continue from that pc.
+
+use bebion_compiler::bytecode::{Bytecode, Constant, Instruction};
+use bebion_runtime::tier::NativeHost;
+use bebion_runtime::{RuntimeError, RuntimeResult, Value};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{types, AbiParam, Block, FuncRef, InstBuilder, MemFlags, Signature, Value as IrValue};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::c_void;
+use std::fmt;
+
+/// What a compiled value holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Number,
+    Boolean,
+    Undefined,
+    /// Any other value; the slot holds its index in `NativeContext`
+    Boxed,
+}
+
+impl Kind {
+    fn code(self) -> i64 {
+        match self {
+            Kind::Number => 0,
+            Kind::Boolean => 1,
+            Kind::Undefined => 2,
+            Kind::Boxed => 3,
+        }
+    }
+
+    fn from_code(code: i64) -> Kind {
+        match code {
+            0 => Kind::Number,
+            1 => Kind::Boolean,
+            2 => Kind::Undefined,
+            _ => Kind::Boxed,
+        }
+    }
+}
+
+/// Kinds of the locals and operand stack before an instruction runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameState {
+    pub locals: Vec<Kind>,
+    pub stack: Vec<Kind>,
+}
+
+/// A point where compiled code hands the frame back to the interpreter
+#[derive(Debug, Clone)]
+pub struct SideExit {
+    /// Instruction the interpreter resumes at
+    pub pc: usize,
+    pub state: FrameState,
+}
+
+#[derive(Debug, Clone)]
+pub enum JitError {
+    /// The bytecode uses something the baseline tier does not compile
+    Unsupported(String),
+    /// Cranelift rejected the function or the host
+    Codegen(String),
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JitError::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
+            JitError::Codegen(msg) => write!(f, "Code generation failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for JitError {}
+
+pub type JitResult<T> = Result<T, JitError>;
+
+/// Compiled code is called with the locals, which it reads on entry and
+/// writes back at a side exit, an output buffer that receives the operand
+/// stack at a side exit or the return value, and its `NativeContext`. It
+/// returns the side exit index, or `-1 - kind` of the returned value.
+type Entry = unsafe extern "C" fn(*mut f64, *mut f64, *mut c_void) -> i64;
+
+/// What one run of compiled code reaches through its context pointer: the VM
+/// and the values its slots cannot hold as numbers
+pub struct NativeContext<'a> {
+    host: &'a mut dyn NativeHost,
+    /// Values of `Kind::Boxed` slots
+    boxes: Vec<Value>,
+    /// Box last handed out by the stub at each pc, reused while the value
+    /// stays the same so a loop does not box it again on every iteration
+    site_boxes: HashMap<usize, usize>,
+    /// What a call back into the VM raised, reported with its side exit
+    error: Option<RuntimeError>,
+}
+
+impl<'a> NativeContext<'a> {
+    pub fn new(host: &'a mut dyn NativeHost) -> Self {
+        Self {
+            host,
+            boxes: Vec::new(),
+            site_boxes: HashMap::new(),
+            error: None,
+        }
+    }
+
+    /// The value a slot of `kind` holding `bits` stands for
+    pub fn value(&self, bits: f64, kind: Kind) -> Value {
+        match kind {
+            Kind::Number => Value::Number(bits),
+            Kind::Boolean => Value::Boolean(bits != 0.0),
+            Kind::Undefined => Value::Undefined,
+            Kind::Boxed => self.boxes[bits as usize].clone(),
+        }
+    }
+
+    /// The error raised before the last side exit, if that is why it left
+    pub fn take_error(&mut self) -> Option<RuntimeError> {
+        self.error.take()
+    }
+
+    fn box_value(&mut self, pc: usize, value: Value) -> f64 {
+        if let Some(&index) = self.site_boxes.get(&pc) {
+            if self.boxes[index] == value {
+                return index as f64;
+            }
+        }
+        self.boxes.push(value);
+        let index = self.boxes.len() - 1;
+        self.site_boxes.insert(pc, index);
+        index as f64
+    }
+
+    /// Write what a call back into the VM produced to `out` and return the
+    /// `STUB_*` status. Numbers are passed unboxed only if `unbox_numbers`.
+    fn finish(&mut self, pc: usize, result: RuntimeResult<Value>, unbox_numbers: bool, out: *mut f64) -> i64 {
+        let (bits, status) = match result {
+            Ok(Value::Number(n)) if unbox_numbers => (n, STUB_NUMBER),
+            Ok(value) => (self.box_value(pc, value), STUB_BOXED),
+            Err(error) => {
+                self.error = Some(error);
+                return STUB_ERROR;
+            }
+        };
+        // SAFETY: compiled code passes a slot of its output buffer
+        unsafe { *out = bits };
+        status
+    }
+}
+
+/// How a call into compiled code ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NativeResult {
+    Returned(f64, Kind),
+    /// Index into `NativeFunction::exits`; the buffers hold the frame state
+    Exit(usize),
+}
+
+/// A function body compiled to native code
+pub struct NativeFunction {
+    entry: Entry,
+    /// Leading locals that must be numbers on entry; the rest start undefined
+    pub entry_locals: usize,
+    pub local_count: usize,
+    /// Size of the output buffer
+    pub max_stack: usize,
+    pub exits: Vec<SideExit>,
+}
+
+impl NativeFunction {
+    /// Run the function. `locals` needs `local_count` slots and `stack`
+    /// `max_stack`; both are left holding the frame state after a side exit.
+    pub fn invoke(&self, locals: &mut [f64], stack: &mut [f64], context: &mut NativeContext<'_>) -> NativeResult {
+        assert!(locals.len() >= self.local_count, "too few local slots");
+        assert!(stack.len() >= self.max_stack, "operand buffer too small");
+
+        // SAFETY: the buffers are at least as large as the code indexes, and
+        // the stubs only use the context for the duration of the call
+        let context = context as *mut NativeContext<'_> as *mut c_void;
+        let code = unsafe { (self.entry)(locals.as_mut_ptr(), stack.as_mut_ptr(), context) };
+        if code >= 0 {
+            NativeResult::Exit(code as usize)
+        } else {
+            NativeResult::Returned(stack[0], Kind::from_code(-1 - code))
+        }
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("entry_locals", &self.entry_locals)
+            .field("local_count", &self.local_count)
+            .field("max_stack", &self.max_stack)
+            .field("exits", &self.exits.len())
+            .finish()
+    }
+}
+
+extern "C" fn bebion_jit_fmod(left: f64, right: f64) -> f64 {
+    left % right
+}
+
+/// Statuses the stubs return: their result is in the output slot as a
+/// number or a box index, or the VM raised an error
+const STUB_NUMBER: i64 = 0;
+const STUB_BOXED: i64 = 1;
+const STUB_ERROR: i64 = -1;
+
+/// SAFETY: compiled code passes the context it was invoked with
+unsafe fn native_context<'a>(context: *mut c_void) -> &'a mut NativeContext<'a> {
+    &mut *(context as *mut NativeContext<'a>)
+}
+
+extern "C" fn bebion_jit_load_constant(context: *mut c_void, pc: i64, index: i64, out: *mut f64) -> i64 {
+    let context = unsafe { native_context(context) };
+    let result = context.host.load_constant(index as usize);
+    context.finish(pc as usize, result, false, out)
+}
+
+extern "C" fn bebion_jit_load_global(context: *mut c_void, pc: i64, index: i64, out: *mut f64) -> i64 {
+    let context = unsafe { native_context(context) };
+    let result = context.host.load_global(index as usize);
+    context.finish(pc as usize, result, false, out)
+}
+
+extern "C" fn bebion_jit_get_property(
+    context: *mut c_void,
+    pc: i64,
+    object: f64,
+    object_kind: i64,
+    key: f64,
+    key_kind: i64,
+    out: *mut f64,
+) -> i64 {
+    let context = unsafe { native_context(context) };
+    let object = context.value(object, Kind::from_code(object_kind));
+    let key = context.value(key, Kind::from_code(key_kind));
+    let result = context.host.get_property(pc as usize, &object, &key);
+    context.finish(pc as usize, result, true, out)
+}
+
+#[allow(clippy::too_many_arguments)]
+extern "C" fn bebion_jit_set_property(
+    context: *mut c_void,
+    pc: i64,
+    object: f64,
+    object_kind: i64,
+    key: f64,
+    key_kind: i64,
+    value: f64,
+    value_kind: i64,
+) -> i64 {
+    let context = unsafe { native_context(context) };
+    let object = context.value(object, Kind::from_code(object_kind));
+    let key = context.value(key, Kind::from_code(key_kind));
+    let value = context.value(value, Kind::from_code(value_kind));
+    match context.host.set_property(pc as usize, &object, &key, value) {
+        Ok(()) => STUB_NUMBER,
+        Err(error) => {
+            context.error = Some(error);
+            STUB_ERROR
+        }
+    }
+}
+
+/// Functions compiled code calls, as declared in the module
+struct Stubs<T> {
+    fmod: T,
+    load_constant: T,
+    load_global: T,
+    get_property: T,
+    set_property: T,
+}
+
+/// Owns the Cranelift module that compiled code lives in. Code memory is
+/// never freed, so `NativeFunction`s stay valid for the life of the process.
+pub struct JitCompiler {
+    module: JITModule,
+    ctx: Context,
+    builder_context: FunctionBuilderContext,
+    stubs: Stubs<FuncId>,
+    compiled: usize,
+}
+
+impl JitCompiler {
+    /// Set up code generation for the host, failing if Cranelift does not
+    /// support it
+    pub fn new() -> JitResult<Self> {
+        let codegen_error = |e: &dyn fmt::Display| JitError::Codegen(e.to_string());
+
+        let mut flags = settings::builder();
+        flags.set("use_colocated_libcalls", "false").map_err(|e| codegen_error(&e))?;
+        flags.set("is_pic", "false").map_err(|e| codegen_error(&e))?;
+        flags.set("opt_level", "speed").map_err(|e| codegen_error(&e))?;
+        let isa = cranelift_native::builder()
+            .map_err(|e| codegen_error(&e))?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| codegen_error(&e))?;
+
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("bebion_jit_fmod", bebion_jit_fmod as *const u8);
+        builder.symbol("bebion_jit_load_constant", bebion_jit_load_constant as *const u8);
+        builder.symbol("bebion_jit_load_global", bebion_jit_load_global as *const u8);
+        builder.symbol("bebion_jit_get_property", bebion_jit_get_property as *const u8);
+        builder.symbol("bebion_jit_set_property", bebion_jit_set_property as *const u8);
+        let mut module = JITModule::new(builder);
+
+        let pointer = module.target_config().pointer_type();
+        let (f64, i64) = (AbiParam::new(types::F64), AbiParam::new(types::I64));
+        let mut declare = |name: &str, params: &[AbiParam], returns: AbiParam| {
+            let mut signature = module.make_signature();
+            signature.params.extend_from_slice(params);
+            signature.returns.push(returns);
+            module
+                .declare_function(name, Linkage::Import, &signature)
+                .map_err(|e| codegen_error(&e))
+        };
+        let pointer = AbiParam::new(pointer);
+        let stubs = Stubs {
+            fmod: declare("bebion_jit_fmod", &[f64, f64], f64)?,
+            load_constant: declare("bebion_jit_load_constant", &[pointer, i64, i64, pointer], i64)?,
+            load_global: declare("bebion_jit_load_global", &[pointer, i64, i64, pointer], i64)?,
+            get_property: declare("bebion_jit_get_property", &[pointer, i64, f64, i64, f64, i64, pointer], i64)?,
+            set_property: declare("bebion_jit_set_property", &[pointer, i64, f64, i64, f64, i64, f64, i64], i64)?,
+        };
+
+        Ok(Self {
+            ctx: module.make_context(),
+            module,
+            builder_context: FunctionBuilderContext::new(),
+            stubs,
+            compiled: 0,
+        })
+    }
+
+    /// Compile a function body whose first `entry_locals` locals are numeric
+    /// arguments
+    pub fn compile(&mut self, bytecode: &Bytecode, entry_locals: usize) -> JitResult<NativeFunction> {
+        let analysis = analyze(bytecode, entry_locals)?;
+        let codegen_error = |e: &dyn fmt::Display| JitError::Codegen(e.to_string());
+
+        let pointer = self.module.target_config().pointer_type();
+        let mut signature = Signature::new(self.module.isa().default_call_conv());
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(pointer));
+        signature.returns.push(AbiParam::new(types::I64));
+
+        let name = format!("bebion_fn_{}", self.compiled);
+        let id = self
+            .module
+            .declare_function(&name, Linkage::Local, &signature)
+            .map_err(|e| codegen_error(&e))?;
+
+        self.ctx.func.signature = signature;
+        let exits = {
+            let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+            let stubs = Stubs {
+                fmod: self.module.declare_func_in_func(self.stubs.fmod, builder.func),
+                load_constant: self.module.declare_func_in_func(self.stubs.load_constant, builder.func),
+                load_global: self.module.declare_func_in_func(self.stubs.load_global, builder.func),
+                get_property: self.module.declare_func_in_func(self.stubs.get_property, builder.func),
+                set_property: self.module.declare_func_in_func(self.stubs.set_property, builder.func),
+            };
+            let mut translator = Translator::new(&mut builder, bytecode, &analysis, stubs);
+            translator.translate();
+            let exits = translator.exits;
+            builder.seal_all_blocks();
+            builder.finalize();
+            exits
+        };
+
+        let defined = self.module.define_function(id, &mut self.ctx);
+        self.module.clear_context(&mut self.ctx);
+        defined.map_err(|e| codegen_error(&e))?;
+        self.module.finalize_definitions().map_err(|e| codegen_error(&e))?;
+        self.compiled += 1;
+
+        let code = self.module.get_finalized_function(id);
+        // SAFETY: the function was declared with the `Entry` signature
+        let entry = unsafe { std::mem::transmute::<*const u8, Entry>(code) };
+
+        Ok(NativeFunction {
+            entry,
+            entry_locals,
+            local_count: analysis.local_count,
+            max_stack: analysis.max_stack.max(1),
+            exits,
+        })
+    }
+}
+
+/// Kinds at every reachable instruction
+struct Analysis {
+    states: Vec<Option<FrameState>>,
+    local_count: usize,
+    max_stack: usize,
+}
+
+/// What an instruction does to control flow, given its operand kinds
+enum Step {
+    Next(FrameState),
+    Jump(usize, FrameState),
+    /// Conditional jump; both successors see the same state
+    Branch { target: usize, state: FrameState },
+    Return,
+    Exit,
+}
+
+fn analyze(bytecode: &Bytecode, entry_locals: usize) -> JitResult<Analysis> {
+    let instructions = &bytecode.instructions;
+    let local_count = instructions
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::LoadLocal(slot)
+            | Instruction::StoreLocal(slot)
+            | Instruction::DeclareVar(slot)
+            | Instruction::DeclareLet(slot)
+            | Instruction::DeclareConst(slot) => Some(slot + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+        .max(entry_locals);
+
+    let mut locals = vec![Kind::Undefined; local_count];
+    locals[..entry_locals].fill(Kind::Number);
+
+    let mut states: Vec<Option<FrameState>> = vec![None; instructions.len()];
+    let mut max_stack = 0;
+    let mut worklist = VecDeque::from([0]);
+    if instructions.is_empty() {
+        return Err(JitError::Unsupported("empty function".to_string()));
+    }
+    states[0] = Some(FrameState { locals, stack: Vec::new() });
+
+    let merge = |states: &mut Vec<Option<FrameState>>, worklist: &mut VecDeque<usize>, pc: usize, state: FrameState| {
+        match &states[pc] {
+            None => {
+                states[pc] = Some(state);
+                worklist.push_back(pc);
+                Ok(())
+            }
+            Some(existing) if *existing == state => Ok(()),
+            Some(_) => Err(JitError::Unsupported(format!("value kinds differ where control flow merges at pc {}", pc))),
+        }
+    };
+
+    while let Some(pc) = worklist.pop_front() {
+        let state = states[pc].clone().expect("queued instructions have a state");
+        max_stack = max_stack.max(state.stack.len());
+
+        match step(bytecode, pc, &state, local_count) {
+            Step::Next(next) => merge(&mut states, &mut worklist, pc + 1, next)?,
+            Step::Jump(target, next) => merge(&mut states, &mut worklist, target, next)?,
+            Step::Branch { target, state: next } => {
+                merge(&mut states, &mut worklist, pc + 1, next.clone())?;
+                merge(&mut states, &mut worklist, target, next)?;
+            }
+            Step::Return | Step::Exit => {}
+        }
+    }
+
+    if matches!(step(bytecode, 0, states[0].as_ref().unwrap(), local_count), Step::Exit) {
+        return Err(JitError::Unsupported(format!("{:?} at entry", instructions[0])));
+    }
+
+    Ok(Analysis { states, local_count, max_stack })
+}
+
+fn step(bytecode: &Bytecode, pc: usize, state: &FrameState, local_count: usize) -> Step {
+    use Instruction::*;
+
+    let len = bytecode.instructions.len();
+    let mut next = state.clone();
+    let depth = next.stack.len();
+    let top = |n: usize| if depth > n { Some(next.stack[depth - 1 - n]) } else { None };
+    let (top0, top1) = (top(0), top(1));
+
+    let in_range = |target: isize| usize::try_from(target).ok().filter(|&target| target < len);
+    let jump_target = |offset: isize| in_range(pc as isize + offset + 1);
+    if pc + 1 >= len && !matches!(bytecode.instructions[pc], Return | Jump(_)) {
+        // Falling off the end is left to the interpreter
+        return Step::Exit;
+    }
+
+    match &bytecode.instructions[pc] {
+        LoadConstant(idx) => match bytecode.constants.get(*idx) {
+            Some(Constant::Number(_)) => next.stack.push(Kind::Number),
+            Some(Constant::Boolean(_)) => next.stack.push(Kind::Boolean),
+            Some(Constant::Undefined) => next.stack.push(Kind::Undefined),
+            Some(Constant::String(_) | Constant::Null) => next.stack.push(Kind::Boxed),
+            _ => return Step::Exit,
+        },
+        LoadLocal(slot) if *slot < local_count => next.stack.push(next.locals[*slot]),
+        LoadGlobal(_) => next.stack.push(Kind::Boxed),
+        // Property reads are expected to give numbers; anything else leaves
+        // native code after the read
+        GetProperty | GetElement if depth >= 2 => {
+            next.stack.truncate(depth - 2);
+            next.stack.push(Kind::Number);
+        }
+        SetProperty | SetElement if depth >= 3 => {
+            let value = next.stack[depth - 1];
+            next.stack.truncate(depth - 3);
+            next.stack.push(value);
+        }
+        StoreLocal(slot) | DeclareVar(slot) | DeclareLet(slot) | DeclareConst(slot) => match top0 {
+            Some(kind) => {
+                next.stack.pop();
+                next.locals[*slot] = kind;
+            }
+            None => return Step::Exit,
+        },
+        Add | Subtract | Multiply | Divide | Modulo | Less | Greater | LessEqual | GreaterEqual => {
+            if top0 != Some(Kind::Number) || top1 != Some(Kind::Number) {
+                return Step::Exit;
+            }
+            let result = match &bytecode.instructions[pc] {
+                Add | Subtract | Multiply | Divide | Modulo => Kind::Number,
+                _ => Kind::Boolean,
+            };
+            next.stack.truncate(depth - 2);
+            next.stack.push(result);
+        }
+        Equal | StrictEqual if top1.is_some() && top0 != Some(Kind::Boxed) && top1 != Some(Kind::Boxed) => {
+            next.stack.truncate(depth - 2);
+            next.stack.push(Kind::Boolean);
+        }
+        LogicalAnd | LogicalOr => match (top1, top0) {
+            (Some(left), Some(right)) if left == right && left != Kind::Boxed => {
+                next.stack.pop();
+            }
+            _ => return Step::Exit,
+        },
+        LogicalNot if top0.is_some() && top0 != Some(Kind::Boxed) => {
+            next.stack.pop();
+            next.stack.push(Kind::Boolean);
+        }
+        Pop if top0.is_some() => {
+            next.stack.pop();
+        }
+        Duplicate => match top0 {
+            Some(kind) => next.stack.push(kind),
+            None => return Step::Exit,
+        },
+        Nop | DebugInfo(..) => {}
+        Jump(offset) => {
+            return match jump_target(*offset) {
+                Some(target) => Step::Jump(target, next),
+                None => Step::Exit,
+            }
+        }
+        JumpIfFalse(offset) | JumpIfTrue(offset) if top0.is_some() && top0 != Some(Kind::Boxed) => {
+            next.stack.pop();
+            return match jump_target(*offset) {
+                Some(target) => Step::Branch { target, state: next },
+                None => Step::Exit,
+            };
+        }
+        Return if top0.is_some() => return Step::Return,
+        _ => return Step::Exit,
+    }
+
+    Step::Next(next)
+}
+
+/// Emits one Cranelift block per reachable instruction. Locals and operand
+/// stack slots are Cranelift variables, so values flow across blocks without
+/// explicit block parameters.
+struct Translator<'a, 'b> {
+    builder: &'a mut FunctionBuilder<'b>,
+    bytecode: &'a Bytecode,
+    analysis: &'a Analysis,
+    stubs: Stubs<FuncRef>,
+    blocks: Vec<Option<Block>>,
+    locals_ptr: IrValue,
+    out_ptr: IrValue,
+    context_ptr: IrValue,
+    exits: Vec<SideExit>,
+}
+
+impl<'a, 'b> Translator<'a, 'b> {
+    fn new(
+        builder: &'a mut FunctionBuilder<'b>,
+        bytecode: &'a Bytecode,
+        analysis: &'a Analysis,
+        stubs: Stubs<FuncRef>,
+    ) -> Self {
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let locals_ptr = builder.block_params(entry)[0];
+        let out_ptr = builder.block_params(entry)[1];
+        let context_ptr = builder.block_params(entry)[2];
+
+        let blocks = analysis
+            .states
+            .iter()
+            .map(|state| state.as_ref().map(|_| builder.create_block()))
+            .collect();
+
+        Self {
+            builder,
+            bytecode,
+            analysis,
+            stubs,
+            blocks,
+            locals_ptr,
+            out_ptr,
+            context_ptr,
+            exits: Vec::new(),
+        }
+    }
+
+    fn local(&self, slot: usize) -> Variable {
+        Variable::new(slot)
+    }
+
+    fn stack_slot(&self, depth: usize) -> Variable {
+        Variable::new(self.analysis.local_count + depth)
+    }
+
+    fn block(&self, pc: usize) -> Block {
+        self.blocks[pc].expect("successors of reachable instructions are reachable")
+    }
+
+    fn translate(&mut self) {
+        for slot in 0..self.analysis.local_count {
+            let var = self.local(slot);
+            self.builder.declare_var(var, types::F64);
+            let value = self.builder.ins().load(types::F64, MemFlags::trusted(), self.locals_ptr, (slot * 8) as i32);
+            self.builder.def_var(var, value);
+        }
+        let zero = self.builder.ins().f64const(0.0);
+        for depth in 0..self.analysis.max_stack {
+            let var = self.stack_slot(depth);
+            self.builder.declare_var(var, types::F64);
+            self.builder.def_var(var, zero);
+        }
+        let first = self.block(0);
+        self.builder.ins().jump(first, &[]);
+
+        let analysis = self.analysis;
+        for pc in 0..self.bytecode.instructions.len() {
+            let Some(state) = &analysis.states[pc] else {
+                continue;
+            };
+            let block = self.block(pc);
+            self.builder.switch_to_block(block);
+            self.translate_instruction(pc, state);
+        }
+    }
+
+    fn translate_instruction(&mut self, pc: usize, state: &FrameState) {
+        use Instruction::*;
+
+        let step = step(self.bytecode, pc, state, self.analysis.local_count);
+        if matches!(step, Step::Exit) {
+            self.side_exit(pc, state);
+            return;
+        }
+
+        let depth = state.stack.len();
+        let bytecode = self.bytecode;
+        let instruction = &bytecode.instructions[pc];
+        match instruction {
+            LoadConstant(idx) if matches!(bytecode.constants[*idx], Constant::String(_) | Constant::Null) => {
+                let index = self.builder.ins().iconst(types::I64, *idx as i64);
+                self.load_boxed(self.stubs.load_constant, index, pc, state);
+            }
+            LoadGlobal(idx) => {
+                let index = self.builder.ins().iconst(types::I64, *idx as i64);
+                self.load_boxed(self.stubs.load_global, index, pc, state);
+            }
+            GetProperty | GetElement => {
+                let Step::Next(after) = &step else {
+                    unreachable!("property reads continue at the next instruction");
+                };
+                self.get_property(pc, state, after);
+            }
+            SetProperty | SetElement => self.set_property(pc, state),
+            LoadConstant(idx) => {
+                let value = match bytecode.constants[*idx] {
+                    Constant::Number(n) => n,
+                    Constant::Boolean(b) => if b { 1.0 } else { 0.0 },
+                    _ => 0.0,
+                };
+                let value = self.builder.ins().f64const(value);
+                self.builder.def_var(self.stack_slot(depth), value);
+            }
+            LoadLocal(slot) => {
+                let value = self.builder.use_var(self.local(*slot));
+                self.builder.def_var(self.stack_slot(depth), value);
+            }
+            StoreLocal(slot) | DeclareVar(slot) | DeclareLet(slot) | DeclareConst(slot) => {
+                let value = self.builder.use_var(self.stack_slot(depth - 1));
+                self.builder.def_var(self.local(*slot), value);
+            }
+            Add | Subtract | Multiply | Divide | Modulo | Less | Greater | LessEqual | GreaterEqual | Equal
+            | StrictEqual | LogicalAnd | LogicalOr => {
+                let left = self.builder.use_var(self.stack_slot(depth - 2));
+                let right = self.builder.use_var(self.stack_slot(depth - 1));
+                let (left_kind, kind) = (state.stack[depth - 2], state.stack[depth - 1]);
+                let result = match instruction {
+                    Add => self.builder.ins().fadd(left, right),
+                    Subtract => self.builder.ins().fsub(left, right),
+                    Multiply => self.builder.ins().fmul(left, right),
+                    Divide => self.builder.ins().fdiv(left, right),
+                    Modulo => {
+                        let call = self.builder.ins().call(self.stubs.fmod, &[left, right]);
+                        self.builder.inst_results(call)[0]
+                    }
+                    Less => self.compare(FloatCC::LessThan, left, right),
+                    Greater => self.compare(FloatCC::GreaterThan, left, right),
+                    LessEqual => self.compare(FloatCC::LessThanOrEqual, left, right),
+                    GreaterEqual => self.compare(FloatCC::GreaterThanOrEqual, left, right),
+                    // `undefined` only equals itself. Booleans are stored as
+                    // 0 and 1, which is also how `==` compares them to numbers.
+                    Equal | StrictEqual if left_kind == Kind::Undefined && kind == Kind::Undefined => {
+                        self.builder.ins().f64const(1.0)
+                    }
+                    StrictEqual if left_kind != kind => self.builder.ins().f64const(0.0),
+                    Equal if left_kind == Kind::Undefined || kind == Kind::Undefined => self.builder.ins().f64const(0.0),
+                    Equal | StrictEqual => self.compare(FloatCC::Equal, left, right),
+                    LogicalAnd => {
+                        let truthy = self.truthy(kind, left);
+                        self.builder.ins().select(truthy, right, left)
+                    }
+                    _ => {
+                        let truthy = self.truthy(kind, left);
+                        self.builder.ins().select(truthy, left, right)
+                    }
+                };
+                self.builder.def_var(self.stack_slot(depth - 2), result);
+            }
+            LogicalNot => {
+                let value = self.builder.use_var(self.stack_slot(depth - 1));
+                let truthy = self.truthy(state.stack[depth - 1], value);
+                let falsy = self.builder.ins().icmp_imm(IntCC::Equal, truthy, 0);
+                let result = self.bool_to_f64(falsy);
+                self.builder.def_var(self.stack_slot(depth - 1), result);
+            }
+            Duplicate => {
+                let value = self.builder.use_var(self.stack_slot(depth - 1));
+                self.builder.def_var(self.stack_slot(depth), value);
+            }
+            Return => {
+                let value = self.builder.use_var(self.stack_slot(depth - 1));
+                self.builder.ins().store(MemFlags::trusted(), value, self.out_ptr, 0);
+                let code = self.builder.ins().iconst(types::I64, -1 - state.stack[depth - 1].code());
+                self.builder.ins().return_(&[code]);
+                return;
+            }
+            _ => {}
+        }
+
+        match step {
+            Step::Next(_) => {
+                let next = self.block(pc + 1);
+                self.builder.ins().jump(next, &[]);
+            }
+            Step::Jump(target, _) => {
+                let target = self.block(target);
+                self.builder.ins().jump(target, &[]);
+            }
+            Step::Branch { target, .. } => {
+                let value = self.builder.use_var(self.stack_slot(depth - 1));
+                let truthy = self.truthy(state.stack[depth - 1], value);
+                let (target, fallthrough) = (self.block(target), self.block(pc + 1));
+                if matches!(instruction, JumpIfTrue(_)) {
+                    self.builder.ins().brif(truthy, target, &[], fallthrough, &[]);
+                } else {
+                    self.builder.ins().brif(truthy, fallthrough, &[], target, &[]);
+                }
+            }
+            Step::Return | Step::Exit => unreachable!("handled above"),
+        }
+    }
+
+    /// The address of operand stack slot `depth` in the output buffer,
+    /// where stubs write their result
+    fn out_slot(&mut self, depth: usize) -> IrValue {
+        self.builder.ins().iadd_imm(self.out_ptr, (depth * 8) as i64)
+    }
+
+    fn pc_value(&mut self, pc: usize) -> IrValue {
+        self.builder.ins().iconst(types::I64, pc as i64)
+    }
+
+    fn kind_value(&mut self, kind: Kind) -> IrValue {
+        self.builder.ins().iconst(types::I64, kind.code())
+    }
+
+    /// Leave through a side exit at `pc` if a stub reported an error, and
+    /// continue in a new block otherwise
+    fn exit_on_error(&mut self, status: IrValue, pc: usize, state: &FrameState) {
+        let (error, ok) = (self.builder.create_block(), self.builder.create_block());
+        let failed = self.builder.ins().icmp_imm(IntCC::Equal, status, STUB_ERROR);
+        self.builder.ins().brif(failed, error, &[], ok, &[]);
+        self.builder.switch_to_block(error);
+        self.side_exit(pc, state);
+        self.builder.switch_to_block(ok);
+    }
+
+    /// Push the value a stub boxes for the `index` the instruction names
+    fn load_boxed(&mut self, stub: FuncRef, index: IrValue, pc: usize, state: &FrameState) {
+        let depth = state.stack.len();
+        let (pc_value, out) = (self.pc_value(pc), self.out_slot(depth));
+        let call = self.builder.ins().call(stub, &[self.context_ptr, pc_value, index, out]);
+        let status = self.builder.inst_results(call)[0];
+        self.exit_on_error(status, pc, state);
+        let value = self.builder.ins().load(types::F64, MemFlags::trusted(), out, 0);
+        self.builder.def_var(self.stack_slot(depth), value);
+    }
+
+    /// Read a property through the VM's inline cache. A number continues
+    /// natively; any other value leaves native code after the read.
+    fn get_property(&mut self, pc: usize, state: &FrameState, after: &FrameState) {
+        let depth = state.stack.len();
+        let object = self.builder.use_var(self.stack_slot(depth - 2));
+        let key = self.builder.use_var(self.stack_slot(depth - 1));
+        let (object_kind, key_kind) = (self.kind_value(state.stack[depth - 2]), self.kind_value(state.stack[depth - 1]));
+        let (pc_value, out) = (self.pc_value(pc), self.out_slot(depth - 2));
+        let call = self.builder.ins().call(
+            self.stubs.get_property,
+            &[self.context_ptr, pc_value, object, object_kind, key, key_kind, out],
+        );
+        let status = self.builder.inst_results(call)[0];
+        self.exit_on_error(status, pc, state);
+
+        let value = self.builder.ins().load(types::F64, MemFlags::trusted(), out, 0);
+        let (boxed, number) = (self.builder.create_block(), self.builder.create_block());
+        let is_number = self.builder.ins().icmp_imm(IntCC::Equal, status, STUB_NUMBER);
+        self.builder.ins().brif(is_number, number, &[], boxed, &[]);
+
+        self.builder.switch_to_block(boxed);
+        self.builder.def_var(self.stack_slot(depth - 2), value);
+        let mut boxed_state = after.clone();
+        *boxed_state.stack.last_mut().expect("the read pushed its result") = Kind::Boxed;
+        self.side_exit(pc + 1, &boxed_state);
+
+        self.builder.switch_to_block(number);
+        self.builder.def_var(self.stack_slot(depth - 2), value);
+    }
+
+    /// Write a property through the VM's inline cache, leaving the value as
+    /// the result
+    fn set_property(&mut self, pc: usize, state: &FrameState) {
+        let depth = state.stack.len();
+        let object = self.builder.use_var(self.stack_slot(depth - 3));
+        let key = self.builder.use_var(self.stack_slot(depth - 2));
+        let value = self.builder.use_var(self.stack_slot(depth - 1));
+        let object_kind = self.kind_value(state.stack[depth - 3]);
+        let key_kind = self.kind_value(state.stack[depth - 2]);
+        let value_kind = self.kind_value(state.stack[depth - 1]);
+        let pc_value = self.pc_value(pc);
+        let call = self.builder.ins().call(
+            self.stubs.set_property,
+            &[self.context_ptr, pc_value, object, object_kind, key, key_kind, value, value_kind],
+        );
+        let status = self.builder.inst_results(call)[0];
+        self.exit_on_error(status, pc, state);
+        self.builder.def_var(self.stack_slot(depth - 3), value);
+    }
+
+    /// Write the frame state back and return the exit's index
+    fn side_exit(&mut self, pc: usize, state: &FrameState) {
+        for slot in 0..self.analysis.local_count {
+            let value = self.builder.use_var(self.local(slot));
+            self.builder.ins().store(MemFlags::trusted(), value, self.locals_ptr, (slot * 8) as i32);
+        }
+        for depth in 0..state.stack.len() {
+            let value = self.builder.use_var(self.stack_slot(depth));
+            self.builder.ins().store(MemFlags::trusted(), value, self.out_ptr, (depth * 8) as i32);
+        }
+
+        let index = self.builder.ins().iconst(types::I64, self.exits.len() as i64);
+        self.builder.ins().return_(&[index]);
+        self.exits.push(SideExit { pc, state: state.clone() });
+    }
+
+    fn compare(&mut self, cc: FloatCC, left: IrValue, right: IrValue) -> IrValue {
+        let flag = self.builder.ins().fcmp(cc, left, right);
+        self.bool_to_f64(flag)
+    }
+
+    fn bool_to_f64(&mut self, flag: IrValue) -> IrValue {
+        let wide = self.builder.ins().uextend(types::I32, flag);
+        self.builder.ins().fcvt_from_uint(types::F64, wide)
+    }
+
+    /// JavaScript truthiness as an `i8` flag: numbers are truthy unless zero
+    /// or NaN, which `OrderedNotEqual` against zero captures in one compare
+    fn truthy(&mut self, kind: Kind, value: IrValue) -> IrValue {
+        match kind {
+            Kind::Undefined => self.builder.ins().iconst(types::I8, 0),
+            Kind::Number | Kind::Boolean => {
+                let zero = self.builder.ins().f64const(0.0);
+                self.builder.ins().fcmp(FloatCC::OrderedNotEqual, value, zero)
+            }
+            Kind::Boxed => unreachable!("boxed values leave native code before a truth test"),
+        }
+    }
+}
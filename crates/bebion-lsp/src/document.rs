@@ -0,0 +1,228 @@
+//! An open document and everything the server knows about it
+//!
+//! Each change reparses the whole text with error recovery and reruns scope
+//! analysis; requests are answered from that snapshot. The AST reports byte
+//! offsets while LSP positions count UTF-16 code units, so all conversion
+//! goes through `LineIndex`.
+
+use bebion_parser::ast::{AstNode, Program, SourceLocation};
+use bebion_parser::lexer::{Lexer, LexerConfig, TokenType};
+use bebion_parser::scope::{BindingKind, ValueKind};
+use bebion_parser::{CodegenOptions, ParseError, Parser, ScopeAnalysis};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, DocumentSymbol, FormattingOptions, Position, Range, SymbolKind, TextEdit,
+};
+
+pub struct Document {
+    text: String,
+    lines: LineIndex,
+    program: Program,
+    parse_errors: Vec<ParseError>,
+    analysis: ScopeAnalysis,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let (program, parse_errors) = Parser::new().parse_with_recovery(&text);
+        let analysis = ScopeAnalysis::analyze(&program);
+        Self {
+            lines: LineIndex::new(&text),
+            text,
+            program,
+            parse_errors,
+            analysis,
+        }
+    }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let parse_errors = self.parse_errors.iter().map(|error| {
+            let (line, column) = match error {
+                ParseError::UnexpectedToken { line, column, .. }
+                | ParseError::SyntaxError { line, column, .. }
+                | ParseError::LexicalError { line, column, .. }
+                | ParseError::UnexpectedEof { line, column, .. } => (*line, *column),
+            };
+            let start = self.lines.offset_at_line_column(&self.text, line, column);
+            diagnostic(self.range(start, self.lines.next_char(&self.text, start)), error.to_string())
+        });
+
+        let semantic_errors = self.analysis.errors().iter().map(|error| {
+            let range = error.loc.as_ref().map_or_else(Range::default, |loc| self.loc_range(loc));
+            diagnostic(range, error.message.clone())
+        });
+
+        parse_errors.chain(semantic_errors).collect()
+    }
+
+    /// Where the variable under `position` is declared
+    pub fn definition(&self, position: Position) -> Option<Range> {
+        let binding = self.analysis.binding_at(self.lines.offset_at(&self.text, position))?;
+        self.analysis.binding(binding).loc.as_ref().map(|loc| self.loc_range(loc))
+    }
+
+    /// Markdown describing the variable under `position`, and the range of
+    /// the identifier
+    pub fn hover(&self, position: Position) -> Option<(String, Range)> {
+        let offset = self.lines.offset_at(&self.text, position);
+        let binding = self.analysis.binding(self.analysis.binding_at(offset)?);
+        let signature = match (&binding.kind, &binding.value) {
+            (BindingKind::Function, ValueKind::Function { params, is_async }) => {
+                let prefix = if *is_async { "async " } else { "" };
+                format!("{}function {}({})", prefix, binding.name, params.join(", "))
+            }
+            (BindingKind::Class, _) => format!("class {}", binding.name),
+            (kind, value) => format!("{} {}: {}", kind, binding.name, value),
+        };
+
+        let range = match self.program.find_node_at(offset) {
+            Some(node @ AstNode::Identifier { .. }) => node.loc().map(|loc| self.loc_range(loc)),
+            _ => None,
+        }?;
+        Some((format!("```javascript\n{}\n```", signature), range))
+    }
+
+    /// Every declaration except parameters, in source order
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        self.analysis
+            .bindings()
+            .iter()
+            .filter_map(|binding| {
+                let kind = match binding.kind {
+                    BindingKind::Var | BindingKind::Let => SymbolKind::VARIABLE,
+                    BindingKind::Const => SymbolKind::CONSTANT,
+                    BindingKind::Function => SymbolKind::FUNCTION,
+                    BindingKind::Class => SymbolKind::CLASS,
+                    BindingKind::Parameter | BindingKind::CatchParameter => return None,
+                };
+                let range = self.loc_range(binding.loc.as_ref()?);
+
+                #[allow(deprecated)]
+                Some(DocumentSymbol {
+                    name: binding.name.clone(),
+                    detail: Some(binding.value.to_string()),
+                    kind,
+                    tags: None,
+                    deprecated: None,
+                    range,
+                    selection_range: range,
+                    children: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Reprint the whole document. Declines while it has syntax errors, and
+    /// when it has comments, which the code generator would drop.
+    pub fn format(&self, options: &FormattingOptions) -> Option<Vec<TextEdit>> {
+        if !self.parse_errors.is_empty() || self.has_comments() {
+            return None;
+        }
+
+        let indent = if options.insert_spaces {
+            " ".repeat(options.tab_size as usize)
+        } else {
+            "\t".to_string()
+        };
+        let codegen_options = CodegenOptions {
+            indent,
+            ..CodegenOptions::default()
+        };
+        let formatted = bebion_parser::generate(&self.program, &codegen_options);
+        if formatted == self.text {
+            return Some(Vec::new());
+        }
+
+        Some(vec![TextEdit {
+            range: self.range(0, self.text.len()),
+            new_text: formatted,
+        }])
+    }
+
+    fn has_comments(&self) -> bool {
+        let config = LexerConfig {
+            emit_comments: true,
+            ..LexerConfig::default()
+        };
+        Lexer::with_config(&self.text, config).tokenize().map_or(true, |tokens| {
+            tokens
+                .iter()
+                .any(|token| matches!(token.token_type, TokenType::LineComment(_) | TokenType::BlockComment(_)))
+        })
+    }
+
+    fn range(&self, start: usize, end: usize) -> Range {
+        Range::new(self.lines.position_at(&self.text, start), self.lines.position_at(&self.text, end))
+    }
+
+    fn loc_range(&self, loc: &SourceLocation) -> Range {
+        self.range(loc.start.offset, loc.end.offset)
+    }
+}
+
+fn diagnostic(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("bebion".to_string()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// Byte offset of each line start, with lines split at `\n` as LSP does
+struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { line_starts }
+    }
+
+    fn position_at(&self, text: &str, offset: usize) -> Position {
+        let offset = offset.min(text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        let character = text
+            .get(line_start..offset)
+            .map_or(0, |prefix| prefix.encode_utf16().count());
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset_at(&self, text: &str, position: Position) -> usize {
+        let Some(&line_start) = self.line_starts.get(position.line as usize) else {
+            return text.len();
+        };
+
+        let mut units = 0;
+        for (i, c) in text[line_start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return line_start + i;
+            }
+            units += c.len_utf16();
+        }
+        text.len()
+    }
+
+    /// Offset of a 1-based line and character column, as the parser reports
+    fn offset_at_line_column(&self, text: &str, line: usize, column: usize) -> usize {
+        let Some(&line_start) = self.line_starts.get(line.saturating_sub(1)) else {
+            return text.len();
+        };
+        text[line_start..]
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(text.len(), |(i, _)| line_start + i)
+    }
+
+    /// End of the character at `offset`, so an error has something to mark
+    fn next_char(&self, text: &str, offset: usize) -> usize {
+        text.get(offset..)
+            .and_then(|rest| rest.chars().next())
+            .filter(|&c| c != '\n')
+            .map_or(offset, |c| offset + c.len_utf8())
+    }
+}
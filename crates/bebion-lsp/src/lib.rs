@@ -0,0 +1,197 @@
+//! Bebion Language Server
+//!
+//! Speaks LSP over stdio: diagnostics from the error-tolerant parser and
+//! scope analysis, go-to-definition, hover, document symbols and whole
+//! document formatting through the code generator. Documents are synced in
+//! full on every change.
+
+mod document;
+
+use document::Document;
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{DocumentSymbolRequest, Formatting, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    DocumentSymbolResponse, GotoDefinitionResponse, Hover, HoverContents, HoverProviderCapability, Location,
+    MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use tracing::{debug, info};
+
+pub type LspResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Serve LSP on stdin and stdout until the client shuts the server down
+pub fn run_stdio() -> LspResult<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let server_capabilities = serde_json::to_value(capabilities())?;
+    connection.initialize(server_capabilities)?;
+    info!("bebion-lsp initialized");
+
+    Server::new(&connection).run()?;
+    // The writer thread finishes once the connection is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+pub struct Server<'a> {
+    connection: &'a Connection,
+    documents: HashMap<Url, Document>,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(connection: &'a Connection) -> Self {
+        Self {
+            connection,
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Handle messages until a shutdown request
+    pub fn run(&mut self) -> LspResult<()> {
+        for message in &self.connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> LspResult<()> {
+        debug!("Request {}", request.method);
+
+        let response = match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                let (id, params) = extract::<GotoDefinition>(request)?;
+                let position = params.text_document_position_params;
+                let uri = position.text_document.uri;
+                let result = self.documents.get(&uri).and_then(|document| {
+                    let range = document.definition(position.position)?;
+                    Some(GotoDefinitionResponse::Scalar(Location::new(uri.clone(), range)))
+                });
+                Response::new_ok(id, result)
+            }
+            HoverRequest::METHOD => {
+                let (id, params) = extract::<HoverRequest>(request)?;
+                let position = params.text_document_position_params;
+                let result = self
+                    .documents
+                    .get(&position.text_document.uri)
+                    .and_then(|document| document.hover(position.position))
+                    .map(|(value, range)| Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: Some(range),
+                    });
+                Response::new_ok(id, result)
+            }
+            DocumentSymbolRequest::METHOD => {
+                let (id, params) = extract::<DocumentSymbolRequest>(request)?;
+                let result = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .map(|document| DocumentSymbolResponse::Nested(document.symbols()));
+                Response::new_ok(id, result)
+            }
+            Formatting::METHOD => {
+                let (id, params) = extract::<Formatting>(request)?;
+                let result = self
+                    .documents
+                    .get(&params.text_document.uri)
+                    .and_then(|document| document.format(&params.options));
+                Response::new_ok(id, result)
+            }
+            _ => Response::new_err(
+                request.id,
+                lsp_server::ErrorCode::MethodNotFound as i32,
+                format!("Unhandled method {}", request.method),
+            ),
+        };
+
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> LspResult<()> {
+        debug!("Notification {}", notification.method);
+
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = extract_notification::<DidOpenTextDocument>(notification)?;
+                let document = params.text_document;
+                self.update(document.uri, document.text, Some(document.version))?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = extract_notification::<DidChangeTextDocument>(notification)?;
+                // Full sync: the last change holds the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.update(params.text_document.uri, change.text, Some(params.text_document.version))?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = extract_notification::<DidCloseTextDocument>(notification)?;
+                self.documents.remove(&params.text_document.uri);
+                self.publish_diagnostics(params.text_document.uri, Vec::new(), None)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, uri: Url, text: String, version: Option<i32>) -> LspResult<()> {
+        let document = Document::new(text);
+        let diagnostics = document.diagnostics();
+        self.documents.insert(uri.clone(), document);
+        self.publish_diagnostics(uri, diagnostics, version)
+    }
+
+    fn publish_diagnostics(
+        &self,
+        uri: Url,
+        diagnostics: Vec<lsp_types::Diagnostic>,
+        version: Option<i32>,
+    ) -> LspResult<()> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version,
+        };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection.sender.send(Message::Notification(notification))?;
+        Ok(())
+    }
+}
+
+fn extract<R: lsp_types::request::Request>(request: Request) -> LspResult<(RequestId, R::Params)> {
+    request.extract(R::METHOD).map_err(|error: ExtractError<Request>| error.to_string().into())
+}
+
+fn extract_notification<N: lsp_types::notification::Notification>(notification: Notification) -> LspResult<N::Params> {
+    notification
+        .extract(N::METHOD)
+        .map_err(|error: ExtractError<Notification>| error.to_string().into())
+}
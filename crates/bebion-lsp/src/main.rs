@@ -0,0 +1,11 @@
+use tracing::Level;
+
+fn main() -> bebion_lsp::LspResult<()> {
+    // stdout carries the protocol, so logs go to stderr
+    tracing_subscriber::fmt()
+        .with_max_level(Level::INFO)
+        .with_writer(std::io::stderr)
+        .init();
+
+    bebion_lsp::run_stdio()
+}
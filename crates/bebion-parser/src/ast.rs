@@ -3,6 +3,12 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+pub mod diff;
+pub mod visit;
+
+pub use diff::{diff, AstChange};
+pub use visit::*;
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Program {
     pub body: Vec<AstNode>,
@@ -19,6 +25,8 @@ pub enum SourceType {
 pub struct Location {
     pub line: usize,
     pub column: usize,
+    /// Byte offset into the source text
+    pub offset: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -27,6 +35,13 @@ pub struct SourceLocation {
     pub end: Location,
 }
 
+impl SourceLocation {
+    /// Whether the byte `offset` falls within `start..end`
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start.offset <= offset && offset < self.end.offset
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AstNode {
     Program(Program),
@@ -90,14 +105,19 @@ pub enum AstNode {
     CallExpression { 
         callee: Box<AstNode>, 
         arguments: Vec<AstNode>, 
+        optional: bool,
         loc: Option<SourceLocation> 
     },
     MemberExpression { 
         object: Box<AstNode>, 
         property: Box<AstNode>, 
         computed: bool, 
+        optional: bool,
         loc: Option<SourceLocation> 
     },
+    /// Wraps a member/call chain containing `?.`; a nullish optional link
+    /// short-circuits the whole chain to `undefined`
+    ChainExpression { expression: Box<AstNode>, loc: Option<SourceLocation> },
     BinaryExpression { 
         operator: BinaryOperator, 
         left: Box<AstNode>, 
@@ -128,6 +148,11 @@ pub enum AstNode {
         alternate: Box<AstNode>, 
         loc: Option<SourceLocation> 
     },
+    /// `a, b, c`: each expression in turn, evaluating to the last
+    SequenceExpression {
+        expressions: Vec<AstNode>,
+        loc: Option<SourceLocation>
+    },
     
     // ES2015+ Features
     TemplateLiteral { 
@@ -139,13 +164,40 @@ pub enum AstNode {
         id: Option<Box<AstNode>>, 
         superclass: Option<Box<AstNode>>, 
         body: Box<AstNode>, 
+        decorators: Vec<AstNode>,
+        loc: Option<SourceLocation> 
+    },
+    ClassBody { body: Vec<AstNode>, loc: Option<SourceLocation> },
+    MethodDefinition { 
+        key: Box<AstNode>, 
+        value: Box<AstNode>, 
+        kind: PropertyKind, 
+        is_static: bool, 
+        computed: bool, 
+        decorators: Vec<AstNode>,
         loc: Option<SourceLocation> 
     },
+    PropertyDefinition { 
+        key: Box<AstNode>, 
+        value: Option<Box<AstNode>>, 
+        is_static: bool, 
+        computed: bool, 
+        decorators: Vec<AstNode>,
+        loc: Option<SourceLocation> 
+    },
+    /// `@expression`, only produced with the `decorators` experimental feature
+    Decorator { expression: Box<AstNode>, loc: Option<SourceLocation> },
+    /// `import name from "source" with { type: "text" }`; a default import
+    /// is an `Identifier` specifier, and each import attribute a `Property`
     ImportDeclaration { 
         specifiers: Vec<AstNode>, 
         source: Box<AstNode>, 
+        attributes: Vec<AstNode>,
         loc: Option<SourceLocation> 
     },
+    /// `export default expression` has the expression as its declaration
+    /// and a single `default` identifier specifier; `export { a, b }` has
+    /// only identifier specifiers
     ExportDeclaration { 
         declaration: Option<Box<AstNode>>, 
         specifiers: Vec<AstNode>, 
@@ -153,6 +205,9 @@ pub enum AstNode {
         loc: Option<SourceLocation> 
     },
     
+    /// `...argument` in array literals and object literals
+    SpreadElement { argument: Box<AstNode>, loc: Option<SourceLocation> },
+    
     // Async/Await
     AwaitExpression { argument: Box<AstNode>, loc: Option<SourceLocation> },
     
@@ -238,27 +293,392 @@ impl Program {
     }
 
     pub fn node_count(&self) -> usize {
-        fn count_nodes(node: &AstNode) -> usize {
-            match node {
-                AstNode::Program(program) => {
-                    1 + program.body.iter().map(count_nodes).sum::<usize>()
-                }
-                AstNode::BlockStatement { body, .. } => {
-                    1 + body.iter().map(count_nodes).sum::<usize>()
-                }
-                AstNode::FunctionDeclaration { params, body, .. } => {
-                    1 + params.iter().map(count_nodes).sum::<usize>() + count_nodes(body)
-                }
-                AstNode::BinaryExpression { left, right, .. } => {
-                    1 + count_nodes(left) + count_nodes(right)
-                }
-                AstNode::CallExpression { callee, arguments, .. } => {
-                    1 + count_nodes(callee) + arguments.iter().map(count_nodes).sum::<usize>()
+        struct NodeCounter(usize);
+        
+        impl Visitor for NodeCounter {
+            fn visit_node(&mut self, node: &AstNode) {
+                self.0 += 1;
+                walk_node(self, node);
+            }
+        }
+        
+        // The program itself counts as one node
+        let mut counter = NodeCounter(1);
+        counter.visit_program(self);
+        counter.0
+    }
+
+    /// The innermost node whose span contains the byte `offset`
+    pub fn find_node_at(&self, offset: usize) -> Option<&AstNode> {
+        fn innermost(node: &AstNode, offset: usize) -> Option<&AstNode> {
+            if !node.loc().is_some_and(|loc| loc.contains(offset)) {
+                return None;
+            }
+            node.children()
+                .into_iter()
+                .find_map(|child| innermost(child, offset))
+                .or(Some(node))
+        }
+        
+        self.body.iter().find_map(|node| innermost(node, offset))
+    }
+}
+
+impl AstNode {
+    pub fn loc(&self) -> Option<&SourceLocation> {
+        match self {
+            AstNode::Program(_) => None,
+            AstNode::ExpressionStatement { loc, .. }
+            | AstNode::BlockStatement { loc, .. }
+            | AstNode::VariableDeclaration { loc, .. }
+            | AstNode::FunctionDeclaration { loc, .. }
+            | AstNode::ReturnStatement { loc, .. }
+            | AstNode::IfStatement { loc, .. }
+            | AstNode::WhileStatement { loc, .. }
+            | AstNode::ForStatement { loc, .. }
+            | AstNode::BreakStatement { loc, .. }
+            | AstNode::ContinueStatement { loc, .. }
+            | AstNode::ThrowStatement { loc, .. }
+            | AstNode::TryStatement { loc, .. }
+            | AstNode::Identifier { loc, .. }
+            | AstNode::Literal { loc, .. }
+            | AstNode::ArrayExpression { loc, .. }
+            | AstNode::ObjectExpression { loc, .. }
+            | AstNode::FunctionExpression { loc, .. }
+            | AstNode::ArrowFunctionExpression { loc, .. }
+            | AstNode::CallExpression { loc, .. }
+            | AstNode::MemberExpression { loc, .. }
+            | AstNode::ChainExpression { loc, .. }
+            | AstNode::BinaryExpression { loc, .. }
+            | AstNode::UnaryExpression { loc, .. }
+            | AstNode::AssignmentExpression { loc, .. }
+            | AstNode::UpdateExpression { loc, .. }
+            | AstNode::ConditionalExpression { loc, .. }
+            | AstNode::SequenceExpression { loc, .. }
+            | AstNode::TemplateLiteral { loc, .. }
+            | AstNode::ClassDeclaration { loc, .. }
+            | AstNode::ClassBody { loc, .. }
+            | AstNode::MethodDefinition { loc, .. }
+            | AstNode::PropertyDefinition { loc, .. }
+            | AstNode::Decorator { loc, .. }
+            | AstNode::ImportDeclaration { loc, .. }
+            | AstNode::ExportDeclaration { loc, .. }
+            | AstNode::SpreadElement { loc, .. }
+            | AstNode::AwaitExpression { loc, .. }
+            | AstNode::VariableDeclarator { loc, .. }
+            | AstNode::Property { loc, .. }
+            | AstNode::CatchClause { loc, .. } => loc.as_ref(),
+        }
+    }
+
+    pub fn loc_mut(&mut self) -> Option<&mut SourceLocation> {
+        match self {
+            AstNode::Program(_) => None,
+            AstNode::ExpressionStatement { loc, .. }
+            | AstNode::BlockStatement { loc, .. }
+            | AstNode::VariableDeclaration { loc, .. }
+            | AstNode::FunctionDeclaration { loc, .. }
+            | AstNode::ReturnStatement { loc, .. }
+            | AstNode::IfStatement { loc, .. }
+            | AstNode::WhileStatement { loc, .. }
+            | AstNode::ForStatement { loc, .. }
+            | AstNode::BreakStatement { loc, .. }
+            | AstNode::ContinueStatement { loc, .. }
+            | AstNode::ThrowStatement { loc, .. }
+            | AstNode::TryStatement { loc, .. }
+            | AstNode::Identifier { loc, .. }
+            | AstNode::Literal { loc, .. }
+            | AstNode::ArrayExpression { loc, .. }
+            | AstNode::ObjectExpression { loc, .. }
+            | AstNode::FunctionExpression { loc, .. }
+            | AstNode::ArrowFunctionExpression { loc, .. }
+            | AstNode::CallExpression { loc, .. }
+            | AstNode::MemberExpression { loc, .. }
+            | AstNode::ChainExpression { loc, .. }
+            | AstNode::BinaryExpression { loc, .. }
+            | AstNode::UnaryExpression { loc, .. }
+            | AstNode::AssignmentExpression { loc, .. }
+            | AstNode::UpdateExpression { loc, .. }
+            | AstNode::ConditionalExpression { loc, .. }
+            | AstNode::SequenceExpression { loc, .. }
+            | AstNode::TemplateLiteral { loc, .. }
+            | AstNode::ClassDeclaration { loc, .. }
+            | AstNode::ClassBody { loc, .. }
+            | AstNode::MethodDefinition { loc, .. }
+            | AstNode::PropertyDefinition { loc, .. }
+            | AstNode::Decorator { loc, .. }
+            | AstNode::ImportDeclaration { loc, .. }
+            | AstNode::ExportDeclaration { loc, .. }
+            | AstNode::SpreadElement { loc, .. }
+            | AstNode::AwaitExpression { loc, .. }
+            | AstNode::VariableDeclarator { loc, .. }
+            | AstNode::Property { loc, .. }
+            | AstNode::CatchClause { loc, .. } => loc.as_mut(),
+        }
+    }
+
+    /// The string value of import attribute `key` of an
+    /// `ImportDeclaration`, e.g. `"text"` for `type` in
+    /// `with { type: "text" }`
+    pub fn import_attribute(&self, key: &str) -> Option<&str> {
+        let AstNode::ImportDeclaration { attributes, .. } = self else {
+            return None;
+        };
+        attributes.iter().find_map(|attribute| match attribute {
+            AstNode::Property { key: name, value, .. } => {
+                let name = match name.as_ref() {
+                    AstNode::Identifier { name, .. } => name.as_str(),
+                    AstNode::Literal { value: LiteralValue::String(name), .. } => name.as_str(),
+                    _ => return None,
+                };
+                match value.as_ref() {
+                    AstNode::Literal { value: LiteralValue::String(value), .. } if name == key => Some(value.as_str()),
+                    _ => None,
                 }
-                _ => 1,
+            }
+            _ => None,
+        })
+    }
+
+    /// Direct child nodes in source order
+    pub fn children(&self) -> Vec<&AstNode> {
+        let mut children = Vec::new();
+        
+        match self {
+            AstNode::Program(program) => children.extend(program.body.iter()),
+            AstNode::ExpressionStatement { expression, .. } => children.push(&**expression),
+            AstNode::BlockStatement { body, .. } => children.extend(body.iter()),
+            AstNode::VariableDeclaration { declarations, .. } => children.extend(declarations.iter()),
+            AstNode::FunctionDeclaration { id, params, body, .. }
+            | AstNode::FunctionExpression { id, params, body, .. } => {
+                children.extend(id.as_deref());
+                children.extend(params.iter());
+                children.push(&**body);
+            }
+            AstNode::ReturnStatement { argument, .. } => children.extend(argument.as_deref()),
+            AstNode::IfStatement { test, consequent, alternate, .. } => {
+                children.push(&**test);
+                children.push(&**consequent);
+                children.extend(alternate.as_deref());
+            }
+            AstNode::WhileStatement { test, body, .. } => {
+                children.push(&**test);
+                children.push(&**body);
+            }
+            AstNode::ForStatement { init, test, update, body, .. } => {
+                children.extend(init.as_deref());
+                children.extend(test.as_deref());
+                children.extend(update.as_deref());
+                children.push(&**body);
+            }
+            AstNode::BreakStatement { label, .. } | AstNode::ContinueStatement { label, .. } => {
+                children.extend(label.as_deref());
+            }
+            AstNode::ThrowStatement { argument, .. }
+            | AstNode::UnaryExpression { argument, .. }
+            | AstNode::UpdateExpression { argument, .. }
+            | AstNode::SpreadElement { argument, .. }
+            | AstNode::AwaitExpression { argument, .. } => children.push(&**argument),
+            AstNode::TryStatement { block, handler, finalizer, .. } => {
+                children.push(&**block);
+                children.extend(handler.as_deref());
+                children.extend(finalizer.as_deref());
+            }
+            AstNode::Identifier { .. } | AstNode::Literal { .. } => {}
+            AstNode::ArrayExpression { elements, .. } => children.extend(elements.iter().flatten()),
+            AstNode::ObjectExpression { properties, .. } => children.extend(properties.iter()),
+            AstNode::ArrowFunctionExpression { params, body, .. } => {
+                children.extend(params.iter());
+                children.push(&**body);
+            }
+            AstNode::CallExpression { callee, arguments, .. } => {
+                children.push(&**callee);
+                children.extend(arguments.iter());
+            }
+            AstNode::MemberExpression { object, property, .. } => {
+                children.push(&**object);
+                children.push(&**property);
+            }
+            AstNode::ChainExpression { expression, .. } | AstNode::Decorator { expression, .. } => {
+                children.push(&**expression);
+            }
+            AstNode::BinaryExpression { left, right, .. }
+            | AstNode::AssignmentExpression { left, right, .. } => {
+                children.push(&**left);
+                children.push(&**right);
+            }
+            AstNode::ConditionalExpression { test, consequent, alternate, .. } => {
+                children.push(&**test);
+                children.push(&**consequent);
+                children.push(&**alternate);
+            }
+            AstNode::SequenceExpression { expressions, .. } => children.extend(expressions.iter()),
+            AstNode::TemplateLiteral { quasis, expressions, .. } => {
+                children.extend(quasis.iter());
+                children.extend(expressions.iter());
+            }
+            AstNode::ClassDeclaration { id, superclass, body, decorators, .. } => {
+                children.extend(decorators.iter());
+                children.extend(id.as_deref());
+                children.extend(superclass.as_deref());
+                children.push(&**body);
+            }
+            AstNode::ClassBody { body, .. } => children.extend(body.iter()),
+            AstNode::MethodDefinition { key, value, decorators, .. } => {
+                children.extend(decorators.iter());
+                children.push(&**key);
+                children.push(&**value);
+            }
+            AstNode::PropertyDefinition { key, value, decorators, .. } => {
+                children.extend(decorators.iter());
+                children.push(&**key);
+                children.extend(value.as_deref());
+            }
+            AstNode::ImportDeclaration { specifiers, source, attributes, .. } => {
+                children.extend(specifiers.iter());
+                children.push(&**source);
+                children.extend(attributes.iter());
+            }
+            AstNode::ExportDeclaration { declaration, specifiers, source, .. } => {
+                children.extend(declaration.as_deref());
+                children.extend(specifiers.iter());
+                children.extend(source.as_deref());
+            }
+            AstNode::VariableDeclarator { id, init, .. } => {
+                children.push(&**id);
+                children.extend(init.as_deref());
+            }
+            AstNode::Property { key, value, .. } => {
+                children.push(&**key);
+                children.push(&**value);
+            }
+            AstNode::CatchClause { param, body, .. } => {
+                children.extend(param.as_deref());
+                children.push(&**body);
+            }
+        }
+        
+        children
+    }
+
+    /// Mutable direct child nodes in source order
+    pub fn children_mut(&mut self) -> Vec<&mut AstNode> {
+        let mut children = Vec::new();
+        
+        match self {
+            AstNode::Program(program) => children.extend(program.body.iter_mut()),
+            AstNode::ExpressionStatement { expression, .. } => children.push(&mut **expression),
+            AstNode::BlockStatement { body, .. } => children.extend(body.iter_mut()),
+            AstNode::VariableDeclaration { declarations, .. } => children.extend(declarations.iter_mut()),
+            AstNode::FunctionDeclaration { id, params, body, .. }
+            | AstNode::FunctionExpression { id, params, body, .. } => {
+                children.extend(id.as_deref_mut());
+                children.extend(params.iter_mut());
+                children.push(&mut **body);
+            }
+            AstNode::ReturnStatement { argument, .. } => children.extend(argument.as_deref_mut()),
+            AstNode::IfStatement { test, consequent, alternate, .. } => {
+                children.push(&mut **test);
+                children.push(&mut **consequent);
+                children.extend(alternate.as_deref_mut());
+            }
+            AstNode::WhileStatement { test, body, .. } => {
+                children.push(&mut **test);
+                children.push(&mut **body);
+            }
+            AstNode::ForStatement { init, test, update, body, .. } => {
+                children.extend(init.as_deref_mut());
+                children.extend(test.as_deref_mut());
+                children.extend(update.as_deref_mut());
+                children.push(&mut **body);
+            }
+            AstNode::BreakStatement { label, .. } | AstNode::ContinueStatement { label, .. } => {
+                children.extend(label.as_deref_mut());
+            }
+            AstNode::ThrowStatement { argument, .. }
+            | AstNode::UnaryExpression { argument, .. }
+            | AstNode::UpdateExpression { argument, .. }
+            | AstNode::SpreadElement { argument, .. }
+            | AstNode::AwaitExpression { argument, .. } => children.push(&mut **argument),
+            AstNode::TryStatement { block, handler, finalizer, .. } => {
+                children.push(&mut **block);
+                children.extend(handler.as_deref_mut());
+                children.extend(finalizer.as_deref_mut());
+            }
+            AstNode::Identifier { .. } | AstNode::Literal { .. } => {}
+            AstNode::ArrayExpression { elements, .. } => children.extend(elements.iter_mut().flatten()),
+            AstNode::ObjectExpression { properties, .. } => children.extend(properties.iter_mut()),
+            AstNode::ArrowFunctionExpression { params, body, .. } => {
+                children.extend(params.iter_mut());
+                children.push(&mut **body);
+            }
+            AstNode::CallExpression { callee, arguments, .. } => {
+                children.push(&mut **callee);
+                children.extend(arguments.iter_mut());
+            }
+            AstNode::MemberExpression { object, property, .. } => {
+                children.push(&mut **object);
+                children.push(&mut **property);
+            }
+            AstNode::ChainExpression { expression, .. } | AstNode::Decorator { expression, .. } => {
+                children.push(&mut **expression);
+            }
+            AstNode::BinaryExpression { left, right, .. }
+            | AstNode::AssignmentExpression { left, right, .. } => {
+                children.push(&mut **left);
+                children.push(&mut **right);
+            }
+            AstNode::ConditionalExpression { test, consequent, alternate, .. } => {
+                children.push(&mut **test);
+                children.push(&mut **consequent);
+                children.push(&mut **alternate);
+            }
+            AstNode::SequenceExpression { expressions, .. } => children.extend(expressions.iter_mut()),
+            AstNode::TemplateLiteral { quasis, expressions, .. } => {
+                children.extend(quasis.iter_mut());
+                children.extend(expressions.iter_mut());
+            }
+            AstNode::ClassDeclaration { id, superclass, body, decorators, .. } => {
+                children.extend(decorators.iter_mut());
+                children.extend(id.as_deref_mut());
+                children.extend(superclass.as_deref_mut());
+                children.push(&mut **body);
+            }
+            AstNode::ClassBody { body, .. } => children.extend(body.iter_mut()),
+            AstNode::MethodDefinition { key, value, decorators, .. } => {
+                children.extend(decorators.iter_mut());
+                children.push(&mut **key);
+                children.push(&mut **value);
+            }
+            AstNode::PropertyDefinition { key, value, decorators, .. } => {
+                children.extend(decorators.iter_mut());
+                children.push(&mut **key);
+                children.extend(value.as_deref_mut());
+            }
+            AstNode::ImportDeclaration { specifiers, source, attributes, .. } => {
+                children.extend(specifiers.iter_mut());
+                children.push(&mut **source);
+                children.extend(attributes.iter_mut());
+            }
+            AstNode::ExportDeclaration { declaration, specifiers, source, .. } => {
+                children.extend(declaration.as_deref_mut());
+                children.extend(specifiers.iter_mut());
+                children.extend(source.as_deref_mut());
+            }
+            AstNode::VariableDeclarator { id, init, .. } => {
+                children.push(&mut **id);
+                children.extend(init.as_deref_mut());
+            }
+            AstNode::Property { key, value, .. } => {
+                children.push(&mut **key);
+                children.push(&mut **value);
+            }
+            AstNode::CatchClause { param, body, .. } => {
+                children.extend(param.as_deref_mut());
+                children.push(&mut **body);
             }
         }
         
-        count_nodes(&AstNode::Program(self.clone()))
+        children
     }
 }
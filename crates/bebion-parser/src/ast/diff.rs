@@ -0,0 +1,235 @@
+//! Structural diff of two programs
+//!
+//! Nodes are compared by structure, ignoring source locations, so code that
+//! only shifted position is unchanged. Each statement list and child list is
+//! aligned by its longest common subsequence; the nodes left between aligned
+//! ones are paired up by kind, in order, and diffed recursively. A paired node
+//! whose own fields differ is `Updated`, and what stays unpaired is `Removed`
+//! or `Inserted`. Finally a removed node with a structurally equal inserted
+//! node somewhere else becomes one `Moved` change.
+//!
+//! When a node's number of children changes, the change is reported through
+//! its children alone.
+
+use super::visit::placeholder;
+use super::{AstNode, Location, Program, SourceLocation};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstChange {
+    /// A node only in the new program, at its new location
+    Inserted { kind: &'static str, loc: Option<SourceLocation> },
+    /// A node only in the old program, at its old location
+    Removed { kind: &'static str, loc: Option<SourceLocation> },
+    /// A node found unchanged at a different place
+    Moved {
+        kind: &'static str,
+        old: Option<SourceLocation>,
+        new: Option<SourceLocation>,
+    },
+    /// A node whose own fields changed, such as an operator or a literal's
+    /// value; changes to its children are listed separately
+    Updated {
+        kind: &'static str,
+        old: Option<SourceLocation>,
+        new: Option<SourceLocation>,
+    },
+}
+
+impl AstChange {
+    /// The kind of the node that changed, e.g. `"IfStatement"`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AstChange::Inserted { kind, .. }
+            | AstChange::Removed { kind, .. }
+            | AstChange::Moved { kind, .. }
+            | AstChange::Updated { kind, .. } => kind,
+        }
+    }
+}
+
+/// The changes that turn `old` into `new`, in the order both programs are
+/// walked
+pub fn diff(old: &Program, new: &Program) -> Vec<AstChange> {
+    let mut differ = Differ::default();
+    differ.lists(&old.body.iter().collect::<Vec<_>>(), &new.body.iter().collect::<Vec<_>>());
+    differ.finish()
+}
+
+/// A change before removed and inserted nodes are matched into moves
+enum Pending<'a> {
+    Inserted(&'a AstNode),
+    Removed(&'a AstNode),
+    Updated(&'a AstNode, &'a AstNode),
+}
+
+#[derive(Default)]
+struct Differ<'a> {
+    changes: Vec<Pending<'a>>,
+}
+
+impl<'a> Differ<'a> {
+    fn lists(&mut self, old: &[&'a AstNode], new: &[&'a AstNode]) {
+        let old_shapes: Vec<_> = old.iter().map(|node| without_locations(node)).collect();
+        let new_shapes: Vec<_> = new.iter().map(|node| without_locations(node)).collect();
+
+        let (mut i, mut j) = (0, 0);
+        for (matched_old, matched_new) in common_subsequence(&old_shapes, &new_shapes) {
+            self.gap(&old[i..matched_old], &new[j..matched_new]);
+            i = matched_old + 1;
+            j = matched_new + 1;
+        }
+        self.gap(&old[i..], &new[j..]);
+    }
+
+    /// Nodes between two aligned ones: pair them by kind and diff the pairs
+    fn gap(&mut self, old: &[&'a AstNode], new: &[&'a AstNode]) {
+        let mut next_new = 0;
+        for &node in old {
+            let paired = new[next_new..].iter().position(|candidate| candidate.kind() == node.kind());
+            match paired {
+                Some(offset) => {
+                    for &inserted in &new[next_new..next_new + offset] {
+                        self.changes.push(Pending::Inserted(inserted));
+                    }
+                    self.node(node, new[next_new + offset]);
+                    next_new += offset + 1;
+                }
+                None => self.changes.push(Pending::Removed(node)),
+            }
+        }
+        for &inserted in &new[next_new..] {
+            self.changes.push(Pending::Inserted(inserted));
+        }
+    }
+
+    fn node(&mut self, old: &'a AstNode, new: &'a AstNode) {
+        let old_children = old.children();
+        let new_children = new.children();
+        if old_children.len() == new_children.len() && shallow(old) != shallow(new) {
+            self.changes.push(Pending::Updated(old, new));
+        }
+        self.lists(&old_children, &new_children);
+    }
+
+    fn finish(self) -> Vec<AstChange> {
+        let shapes: Vec<_> = self
+            .changes
+            .iter()
+            .map(|change| match change {
+                Pending::Inserted(node) | Pending::Removed(node) => Some(without_locations(node)),
+                Pending::Updated(..) => None,
+            })
+            .collect();
+
+        // Index of the inserted node each removed node moved to
+        let mut moved_to = vec![None; self.changes.len()];
+        let mut taken = vec![false; self.changes.len()];
+        for (removed, change) in self.changes.iter().enumerate() {
+            if !matches!(change, Pending::Removed(_)) {
+                continue;
+            }
+            let target = self.changes.iter().enumerate().position(|(inserted, candidate)| {
+                matches!(candidate, Pending::Inserted(_)) && !taken[inserted] && shapes[inserted] == shapes[removed]
+            });
+            if let Some(inserted) = target {
+                taken[inserted] = true;
+                moved_to[removed] = Some(inserted);
+            }
+        }
+
+        let mut changes = Vec::with_capacity(self.changes.len());
+        for (index, change) in self.changes.iter().enumerate() {
+            if taken[index] {
+                continue;
+            }
+            changes.push(match (change, moved_to[index]) {
+                (Pending::Removed(node), Some(inserted)) => {
+                    let Pending::Inserted(target) = self.changes[inserted] else {
+                        unreachable!("removed nodes only move to inserted nodes");
+                    };
+                    AstChange::Moved {
+                        kind: node.kind(),
+                        old: node.loc().cloned(),
+                        new: target.loc().cloned(),
+                    }
+                }
+                (Pending::Removed(node), None) => AstChange::Removed {
+                    kind: node.kind(),
+                    loc: node.loc().cloned(),
+                },
+                (Pending::Inserted(node), _) => AstChange::Inserted {
+                    kind: node.kind(),
+                    loc: node.loc().cloned(),
+                },
+                (Pending::Updated(old, new), _) => AstChange::Updated {
+                    kind: old.kind(),
+                    old: old.loc().cloned(),
+                    new: new.loc().cloned(),
+                },
+            });
+        }
+        changes
+    }
+}
+
+/// Index pairs of a longest common subsequence of `old` and `new`
+fn common_subsequence(old: &[AstNode], new: &[AstNode]) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the LCS length of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::with_capacity(lengths[0][0]);
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// A copy of `node` with every source location reset
+fn without_locations(node: &AstNode) -> AstNode {
+    let mut node = node.clone();
+    reset_locations(&mut node);
+    node
+}
+
+fn reset_locations(node: &mut AstNode) {
+    reset_location(node);
+    for child in node.children_mut() {
+        reset_locations(child);
+    }
+}
+
+/// A copy of `node` with its location reset and its children replaced,
+/// leaving only the node's own fields to compare
+fn shallow(node: &AstNode) -> AstNode {
+    let mut node = node.clone();
+    reset_location(&mut node);
+    for child in node.children_mut() {
+        *child = placeholder();
+    }
+    node
+}
+
+fn reset_location(node: &mut AstNode) {
+    if let Some(loc) = node.loc_mut() {
+        let origin = Location { line: 0, column: 0, offset: 0 };
+        *loc = SourceLocation { start: origin.clone(), end: origin };
+    }
+}
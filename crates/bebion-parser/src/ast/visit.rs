@@ -0,0 +1,210 @@
+//! AST traversal
+//!
+//! `Visitor` and `VisitorMut` have one `visit_*` method per node kind. Each
+//! defaults to the matching `walk_*` function, which visits the node's
+//! children, so an implementation only overrides the kinds it cares about and
+//! calls `walk_*` itself to keep descending. `Transformer` rebuilds the tree
+//! bottom-up for rewrite passes.
+
+use super::{AstNode, Program};
+
+macro_rules! node_visitors {
+    ($($variant:ident => $visit:ident, $visit_mut:ident, $walk:ident, $walk_mut:ident;)*) => {
+        /// Read-only traversal of the AST
+        pub trait Visitor {
+            fn visit_program(&mut self, program: &Program) {
+                walk_program(self, program)
+            }
+
+            /// Dispatch to the `visit_*` method for the node's kind
+            fn visit_node(&mut self, node: &AstNode) {
+                walk_node(self, node)
+            }
+
+            $(
+                fn $visit(&mut self, node: &AstNode) {
+                    $walk(self, node)
+                }
+            )*
+        }
+
+        /// Traversal of the AST that may modify nodes in place
+        pub trait VisitorMut {
+            fn visit_program_mut(&mut self, program: &mut Program) {
+                walk_program_mut(self, program)
+            }
+
+            /// Dispatch to the `visit_*_mut` method for the node's kind
+            fn visit_node_mut(&mut self, node: &mut AstNode) {
+                walk_node_mut(self, node)
+            }
+
+            $(
+                fn $visit_mut(&mut self, node: &mut AstNode) {
+                    $walk_mut(self, node)
+                }
+            )*
+        }
+
+        impl AstNode {
+            /// The node's variant name, e.g. `"IfStatement"`
+            pub fn kind(&self) -> &'static str {
+                match self {
+                    AstNode::Program(_) => "Program",
+                    $(AstNode::$variant { .. } => stringify!($variant),)*
+                }
+            }
+        }
+
+        pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &AstNode) {
+            match node {
+                AstNode::Program(program) => visitor.visit_program(program),
+                $(AstNode::$variant { .. } => visitor.$visit(node),)*
+            }
+        }
+
+        pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut AstNode) {
+            match node {
+                AstNode::Program(program) => visitor.visit_program_mut(program),
+                $(AstNode::$variant { .. } => visitor.$visit_mut(node),)*
+            }
+        }
+
+        $(
+            pub fn $walk<V: Visitor + ?Sized>(visitor: &mut V, node: &AstNode) {
+                walk_children(visitor, node)
+            }
+
+            pub fn $walk_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut AstNode) {
+                walk_children_mut(visitor, node)
+            }
+        )*
+    };
+}
+
+node_visitors! {
+    ExpressionStatement => visit_expression_statement, visit_expression_statement_mut, walk_expression_statement, walk_expression_statement_mut;
+    BlockStatement => visit_block_statement, visit_block_statement_mut, walk_block_statement, walk_block_statement_mut;
+    VariableDeclaration => visit_variable_declaration, visit_variable_declaration_mut, walk_variable_declaration, walk_variable_declaration_mut;
+    FunctionDeclaration => visit_function_declaration, visit_function_declaration_mut, walk_function_declaration, walk_function_declaration_mut;
+    ReturnStatement => visit_return_statement, visit_return_statement_mut, walk_return_statement, walk_return_statement_mut;
+    IfStatement => visit_if_statement, visit_if_statement_mut, walk_if_statement, walk_if_statement_mut;
+    WhileStatement => visit_while_statement, visit_while_statement_mut, walk_while_statement, walk_while_statement_mut;
+    ForStatement => visit_for_statement, visit_for_statement_mut, walk_for_statement, walk_for_statement_mut;
+    BreakStatement => visit_break_statement, visit_break_statement_mut, walk_break_statement, walk_break_statement_mut;
+    ContinueStatement => visit_continue_statement, visit_continue_statement_mut, walk_continue_statement, walk_continue_statement_mut;
+    ThrowStatement => visit_throw_statement, visit_throw_statement_mut, walk_throw_statement, walk_throw_statement_mut;
+    TryStatement => visit_try_statement, visit_try_statement_mut, walk_try_statement, walk_try_statement_mut;
+    Identifier => visit_identifier, visit_identifier_mut, walk_identifier, walk_identifier_mut;
+    Literal => visit_literal, visit_literal_mut, walk_literal, walk_literal_mut;
+    ArrayExpression => visit_array_expression, visit_array_expression_mut, walk_array_expression, walk_array_expression_mut;
+    ObjectExpression => visit_object_expression, visit_object_expression_mut, walk_object_expression, walk_object_expression_mut;
+    FunctionExpression => visit_function_expression, visit_function_expression_mut, walk_function_expression, walk_function_expression_mut;
+    ArrowFunctionExpression => visit_arrow_function_expression, visit_arrow_function_expression_mut, walk_arrow_function_expression, walk_arrow_function_expression_mut;
+    CallExpression => visit_call_expression, visit_call_expression_mut, walk_call_expression, walk_call_expression_mut;
+    MemberExpression => visit_member_expression, visit_member_expression_mut, walk_member_expression, walk_member_expression_mut;
+    ChainExpression => visit_chain_expression, visit_chain_expression_mut, walk_chain_expression, walk_chain_expression_mut;
+    BinaryExpression => visit_binary_expression, visit_binary_expression_mut, walk_binary_expression, walk_binary_expression_mut;
+    UnaryExpression => visit_unary_expression, visit_unary_expression_mut, walk_unary_expression, walk_unary_expression_mut;
+    AssignmentExpression => visit_assignment_expression, visit_assignment_expression_mut, walk_assignment_expression, walk_assignment_expression_mut;
+    UpdateExpression => visit_update_expression, visit_update_expression_mut, walk_update_expression, walk_update_expression_mut;
+    ConditionalExpression => visit_conditional_expression, visit_conditional_expression_mut, walk_conditional_expression, walk_conditional_expression_mut;
+    SequenceExpression => visit_sequence_expression, visit_sequence_expression_mut, walk_sequence_expression, walk_sequence_expression_mut;
+    TemplateLiteral => visit_template_literal, visit_template_literal_mut, walk_template_literal, walk_template_literal_mut;
+    ClassDeclaration => visit_class_declaration, visit_class_declaration_mut, walk_class_declaration, walk_class_declaration_mut;
+    ClassBody => visit_class_body, visit_class_body_mut, walk_class_body, walk_class_body_mut;
+    MethodDefinition => visit_method_definition, visit_method_definition_mut, walk_method_definition, walk_method_definition_mut;
+    PropertyDefinition => visit_property_definition, visit_property_definition_mut, walk_property_definition, walk_property_definition_mut;
+    Decorator => visit_decorator, visit_decorator_mut, walk_decorator, walk_decorator_mut;
+    ImportDeclaration => visit_import_declaration, visit_import_declaration_mut, walk_import_declaration, walk_import_declaration_mut;
+    ExportDeclaration => visit_export_declaration, visit_export_declaration_mut, walk_export_declaration, walk_export_declaration_mut;
+    SpreadElement => visit_spread_element, visit_spread_element_mut, walk_spread_element, walk_spread_element_mut;
+    AwaitExpression => visit_await_expression, visit_await_expression_mut, walk_await_expression, walk_await_expression_mut;
+    VariableDeclarator => visit_variable_declarator, visit_variable_declarator_mut, walk_variable_declarator, walk_variable_declarator_mut;
+    Property => visit_property, visit_property_mut, walk_property, walk_property_mut;
+    CatchClause => visit_catch_clause, visit_catch_clause_mut, walk_catch_clause, walk_catch_clause_mut;
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for node in &program.body {
+        visitor.visit_node(node);
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for node in &mut program.body {
+        visitor.visit_node_mut(node);
+    }
+}
+
+/// Visit every direct child of `node` in source order
+pub fn walk_children<V: Visitor + ?Sized>(visitor: &mut V, node: &AstNode) {
+    for child in node.children() {
+        visitor.visit_node(child);
+    }
+}
+
+pub fn walk_children_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut AstNode) {
+    for child in node.children_mut() {
+        visitor.visit_node_mut(child);
+    }
+}
+
+/// A rewrite pass that rebuilds the tree bottom-up: children are transformed
+/// before their parent is handed to `transform`
+pub trait Transformer {
+    /// Rewrite a node whose children have already been transformed
+    fn transform(&mut self, node: AstNode) -> AstNode {
+        node
+    }
+
+    /// Decide whether a top-level or block statement is kept, after it has
+    /// been transformed. Passes such as dead code elimination override this.
+    fn keep_statement(&mut self, _statement: &AstNode) -> bool {
+        true
+    }
+
+    fn transform_program(&mut self, program: Program) -> Program {
+        transform_program(self, program)
+    }
+}
+
+pub fn transform_program<T: Transformer + ?Sized>(transformer: &mut T, mut program: Program) -> Program {
+    program.body = transform_statements(transformer, std::mem::take(&mut program.body));
+    program
+}
+
+pub fn transform_node<T: Transformer + ?Sized>(transformer: &mut T, mut node: AstNode) -> AstNode {
+    if let AstNode::BlockStatement { body, .. } = &mut node {
+        *body = transform_statements(transformer, std::mem::take(body));
+    } else if let AstNode::Program(program) = node {
+        return AstNode::Program(transformer.transform_program(program));
+    } else {
+        for child in node.children_mut() {
+            let taken = std::mem::replace(child, placeholder());
+            *child = transform_node(transformer, taken);
+        }
+    }
+
+    transformer.transform(node)
+}
+
+fn transform_statements<T: Transformer + ?Sized>(transformer: &mut T, statements: Vec<AstNode>) -> Vec<AstNode> {
+    let mut kept = Vec::with_capacity(statements.len());
+    for statement in statements {
+        let statement = transform_node(transformer, statement);
+        if transformer.keep_statement(&statement) {
+            kept.push(statement);
+        }
+    }
+    kept
+}
+
+/// Stand-in left in a child slot while that child is being transformed
+pub(super) fn placeholder() -> AstNode {
+    AstNode::Literal {
+        value: super::LiteralValue::Undefined,
+        raw: String::new(),
+        loc: None,
+    }
+}
@@ -0,0 +1,892 @@
+//! JavaScript code generation from the AST
+//!
+//! Prints a `Program` back to source text. Parentheses are inserted from
+//! operator precedence rather than remembered from the input, so the output
+//! re-parses to the same tree but may differ in layout from the original.
+
+use crate::ast::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteStyle {
+    Double,
+    Single,
+    /// Keep the quote character each string literal was written with
+    Preserve,
+}
+
+#[derive(Debug, Clone)]
+pub struct CodegenOptions {
+    /// Text emitted once per nesting level
+    pub indent: String,
+    pub quote_style: QuoteStyle,
+    /// Terminate statements with `;`. When disabled, a `;` is still emitted
+    /// before statements that would otherwise continue the previous line.
+    pub semicolons: bool,
+}
+
+impl Default for CodegenOptions {
+    fn default() -> Self {
+        Self {
+            indent: "    ".to_string(),
+            quote_style: QuoteStyle::Double,
+            semicolons: true,
+        }
+    }
+}
+
+/// Generate source text for `program` with the given formatting
+pub fn generate(program: &Program, options: &CodegenOptions) -> String {
+    let mut codegen = Codegen::new(options.clone());
+    codegen.program(program);
+    codegen.finish()
+}
+
+// Binding power of each expression form, loosest first
+const PREC_SEQUENCE: u8 = 1;
+const PREC_ASSIGNMENT: u8 = 2;
+const PREC_CONDITIONAL: u8 = 3;
+const PREC_UNARY: u8 = 15;
+const PREC_POSTFIX: u8 = 16;
+const PREC_CALL: u8 = 17;
+const PREC_PRIMARY: u8 = 18;
+
+pub struct Codegen {
+    options: CodegenOptions,
+    output: String,
+    level: usize,
+}
+
+impl Codegen {
+    pub fn new(options: CodegenOptions) -> Self {
+        Self {
+            options,
+            output: String::new(),
+            level: 0,
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.output
+    }
+
+    pub fn program(&mut self, program: &Program) {
+        for statement in &program.body {
+            self.statement(statement);
+        }
+    }
+
+    /// Print a single statement on its own line(s)
+    pub fn statement(&mut self, node: &AstNode) {
+        let mut line = Codegen::new(self.options.clone());
+        line.level = self.level;
+        line.statement_body(node);
+
+        // Without semicolons, a line starting with one of these would be
+        // joined to the previous statement
+        if !self.options.semicolons && line.output.starts_with(['(', '[', '`', '+', '-', '/']) {
+            line.output.insert(0, ';');
+        }
+
+        self.write_indent();
+        self.output.push_str(&line.output);
+        self.output.push('\n');
+    }
+
+    /// Print an expression without a trailing newline
+    pub fn expression(&mut self, node: &AstNode) {
+        self.expr(node, PREC_ASSIGNMENT);
+    }
+
+    /// An expression where a sequence needs no parentheses, like a
+    /// statement or a `for` clause
+    fn sequence(&mut self, node: &AstNode) {
+        self.expr(node, PREC_SEQUENCE);
+    }
+
+    fn statement_body(&mut self, node: &AstNode) {
+        match node {
+            AstNode::ExpressionStatement { expression, .. } => {
+                if starts_ambiguously(expression) {
+                    self.write("(");
+                    self.sequence(expression);
+                    self.write(")");
+                } else {
+                    self.sequence(expression);
+                }
+                self.semicolon();
+            }
+            AstNode::BlockStatement { body, .. } => self.block(body),
+            AstNode::VariableDeclaration { .. } => {
+                self.variable_declaration(node);
+                self.semicolon();
+            }
+            AstNode::FunctionDeclaration { id, params, body, is_async, is_generator, .. } => {
+                self.function(id.as_deref(), params, body, *is_async, *is_generator);
+            }
+            AstNode::ReturnStatement { argument, .. } => {
+                self.write("return");
+                if let Some(argument) = argument {
+                    self.write(" ");
+                    self.sequence(argument);
+                }
+                self.semicolon();
+            }
+            AstNode::IfStatement { test, consequent, alternate, .. } => {
+                self.write("if (");
+                self.expression(test);
+                self.write(")");
+                self.nested_statement(consequent);
+                if let Some(alternate) = alternate {
+                    if matches!(**consequent, AstNode::BlockStatement { .. }) {
+                        self.write(" else");
+                    } else {
+                        self.newline();
+                        self.write("else");
+                    }
+                    if matches!(**alternate, AstNode::IfStatement { .. }) {
+                        self.write(" ");
+                        self.statement_body(alternate);
+                    } else {
+                        self.nested_statement(alternate);
+                    }
+                }
+            }
+            AstNode::WhileStatement { test, body, .. } => {
+                self.write("while (");
+                self.expression(test);
+                self.write(")");
+                self.nested_statement(body);
+            }
+            AstNode::ForStatement { init, test, update, body, .. } => {
+                self.write("for (");
+                match init.as_deref() {
+                    Some(init @ AstNode::VariableDeclaration { .. }) => self.variable_declaration(init),
+                    Some(init) => self.sequence(init),
+                    None => {}
+                }
+                self.write(";");
+                if let Some(test) = test {
+                    self.write(" ");
+                    self.sequence(test);
+                }
+                self.write(";");
+                if let Some(update) = update {
+                    self.write(" ");
+                    self.sequence(update);
+                }
+                self.write(")");
+                self.nested_statement(body);
+            }
+            AstNode::BreakStatement { label, .. } | AstNode::ContinueStatement { label, .. } => {
+                self.write(if matches!(node, AstNode::BreakStatement { .. }) { "break" } else { "continue" });
+                if let Some(label) = label {
+                    self.write(" ");
+                    self.expression(label);
+                }
+                self.semicolon();
+            }
+            AstNode::ThrowStatement { argument, .. } => {
+                self.write("throw ");
+                self.expression(argument);
+                self.semicolon();
+            }
+            AstNode::TryStatement { block, handler, finalizer, .. } => {
+                self.write("try");
+                self.nested_statement(block);
+                if let Some(handler) = handler {
+                    self.write(" ");
+                    self.statement_body(handler);
+                }
+                if let Some(finalizer) = finalizer {
+                    self.write(" finally");
+                    self.nested_statement(finalizer);
+                }
+            }
+            AstNode::CatchClause { param, body, .. } => {
+                self.write("catch");
+                if let Some(param) = param {
+                    self.write(" (");
+                    self.expression(param);
+                    self.write(")");
+                }
+                self.nested_statement(body);
+            }
+            AstNode::ClassDeclaration { id, superclass, body, decorators, .. } => {
+                for decorator in decorators {
+                    self.expr(decorator, PREC_PRIMARY);
+                    self.newline();
+                }
+                self.write("class");
+                if let Some(id) = id {
+                    self.write(" ");
+                    self.expression(id);
+                }
+                if let Some(superclass) = superclass {
+                    self.write(" extends ");
+                    self.expr(superclass, PREC_CALL);
+                }
+                self.write(" ");
+                self.statement_body(body);
+            }
+            AstNode::ClassBody { body, .. } => {
+                if body.is_empty() {
+                    self.write("{}");
+                    return;
+                }
+                self.write("{");
+                self.level += 1;
+                for member in body {
+                    self.newline();
+                    self.class_member(member);
+                }
+                self.level -= 1;
+                self.newline();
+                self.write("}");
+            }
+            AstNode::ImportDeclaration { specifiers, source, attributes, .. } => {
+                self.write("import ");
+                match specifiers.as_slice() {
+                    [] => {}
+                    [default @ AstNode::Identifier { .. }] => {
+                        self.expression(default);
+                        self.write(" from ");
+                    }
+                    _ => {
+                        self.write("{ ");
+                        self.comma_separated(specifiers);
+                        self.write(" } from ");
+                    }
+                }
+                self.expression(source);
+                if !attributes.is_empty() {
+                    self.write(" with { ");
+                    self.comma_separated(attributes);
+                    self.write(" }");
+                }
+                self.semicolon();
+            }
+            AstNode::ExportDeclaration { declaration, specifiers, source, .. } => {
+                self.write("export ");
+                if let Some(declaration) = declaration {
+                    if specifiers.is_empty() {
+                        self.statement_body(declaration);
+                    } else {
+                        self.write("default ");
+                        self.expression(declaration);
+                        self.semicolon();
+                    }
+                    return;
+                }
+                self.write("{ ");
+                self.comma_separated(specifiers);
+                self.write(" }");
+                if let Some(source) = source {
+                    self.write(" from ");
+                    self.expression(source);
+                }
+                self.semicolon();
+            }
+            // Anything else in statement position is an expression
+            _ => {
+                self.expression(node);
+                self.semicolon();
+            }
+        }
+    }
+
+    /// The body of `if`/`while`/`for`: blocks stay on the same line,
+    /// single statements are indented on the next
+    fn nested_statement(&mut self, node: &AstNode) {
+        if matches!(node, AstNode::BlockStatement { .. }) {
+            self.write(" ");
+            self.statement_body(node);
+        } else {
+            self.level += 1;
+            self.newline();
+            self.statement_body(node);
+            self.level -= 1;
+        }
+    }
+
+    fn block(&mut self, body: &[AstNode]) {
+        if body.is_empty() {
+            self.write("{}");
+            return;
+        }
+
+        self.write("{\n");
+        self.level += 1;
+        for statement in body {
+            self.statement(statement);
+        }
+        self.level -= 1;
+        self.write_indent();
+        self.write("}");
+    }
+
+    fn variable_declaration(&mut self, node: &AstNode) {
+        if let AstNode::VariableDeclaration { declarations, kind, .. } = node {
+            self.write(match kind {
+                VarKind::Var => "var ",
+                VarKind::Let => "let ",
+                VarKind::Const => "const ",
+            });
+            for (i, declarator) in declarations.iter().enumerate() {
+                if i > 0 {
+                    self.write(", ");
+                }
+                if let AstNode::VariableDeclarator { id, init, .. } = declarator {
+                    self.expression(id);
+                    if let Some(init) = init {
+                        self.write(" = ");
+                        self.expression(init);
+                    }
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, id: Option<&AstNode>, params: &[AstNode], body: &AstNode, is_async: bool, is_generator: bool) {
+        if is_async {
+            self.write("async ");
+        }
+        self.write("function");
+        if is_generator {
+            self.write("*");
+        }
+        if let Some(id) = id {
+            self.write(" ");
+            self.expression(id);
+        }
+        self.params(params);
+        self.write(" ");
+        self.statement_body(body);
+    }
+
+    fn params(&mut self, params: &[AstNode]) {
+        self.write("(");
+        self.comma_separated(params);
+        self.write(")");
+    }
+
+    fn class_member(&mut self, node: &AstNode) {
+        match node {
+            AstNode::MethodDefinition { key, value, kind, is_static, computed, decorators, .. } => {
+                self.decorators_inline(decorators);
+                if *is_static {
+                    self.write("static ");
+                }
+                match kind {
+                    PropertyKind::Get => self.write("get "),
+                    PropertyKind::Set => self.write("set "),
+                    _ => {}
+                }
+                self.method(key, *computed, value);
+            }
+            AstNode::PropertyDefinition { key, value, is_static, computed, decorators, .. } => {
+                self.decorators_inline(decorators);
+                if *is_static {
+                    self.write("static ");
+                }
+                self.property_key(key, *computed);
+                if let Some(value) = value {
+                    self.write(" = ");
+                    self.expression(value);
+                }
+                self.semicolon();
+            }
+            _ => self.statement_body(node),
+        }
+    }
+
+    fn decorators_inline(&mut self, decorators: &[AstNode]) {
+        for decorator in decorators {
+            self.expr(decorator, PREC_PRIMARY);
+            self.write(" ");
+        }
+    }
+
+    /// `key(params) { body }` for methods and accessors
+    fn method(&mut self, key: &AstNode, computed: bool, value: &AstNode) {
+        if let AstNode::FunctionExpression { params, body, is_async, is_generator, .. } = value {
+            if *is_async {
+                self.write("async ");
+            }
+            if *is_generator {
+                self.write("*");
+            }
+            self.property_key(key, computed);
+            self.params(params);
+            self.write(" ");
+            self.statement_body(body);
+        } else {
+            self.property_key(key, computed);
+            self.write(": ");
+            self.expression(value);
+        }
+    }
+
+    fn property_key(&mut self, key: &AstNode, computed: bool) {
+        if computed {
+            self.write("[");
+            self.expression(key);
+            self.write("]");
+        } else {
+            self.expr(key, PREC_PRIMARY);
+        }
+    }
+
+    fn expr(&mut self, node: &AstNode, min_precedence: u8) {
+        let needs_parens = precedence(node) < min_precedence;
+        if needs_parens {
+            self.write("(");
+        }
+        self.expr_inner(node);
+        if needs_parens {
+            self.write(")");
+        }
+    }
+
+    fn expr_inner(&mut self, node: &AstNode) {
+        match node {
+            AstNode::Identifier { name, .. } => self.write(name),
+            AstNode::Literal { value, raw, .. } => self.literal(value, raw),
+            AstNode::ArrayExpression { elements, .. } => {
+                self.write("[");
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    if let Some(element) = element {
+                        self.expression(element);
+                    }
+                }
+                // A trailing hole needs its own comma to survive re-parsing
+                if matches!(elements.last(), Some(None)) {
+                    self.write(",");
+                }
+                self.write("]");
+            }
+            AstNode::ObjectExpression { properties, .. } => {
+                if properties.is_empty() {
+                    self.write("{}");
+                } else {
+                    self.write("{ ");
+                    self.comma_separated(properties);
+                    self.write(" }");
+                }
+            }
+            AstNode::Property { key, value, kind, method, shorthand, computed, .. } => {
+                match kind {
+                    PropertyKind::Get => {
+                        self.write("get ");
+                        self.method(key, *computed, value);
+                    }
+                    PropertyKind::Set => {
+                        self.write("set ");
+                        self.method(key, *computed, value);
+                    }
+                    _ if *method => self.method(key, *computed, value),
+                    _ if *shorthand => self.expression(value),
+                    _ => {
+                        self.property_key(key, *computed);
+                        self.write(": ");
+                        self.expression(value);
+                    }
+                }
+            }
+            AstNode::SpreadElement { argument, .. } => {
+                self.write("...");
+                self.expr(argument, PREC_ASSIGNMENT);
+            }
+            AstNode::FunctionExpression { id, params, body, is_async, is_generator, .. } => {
+                self.function(id.as_deref(), params, body, *is_async, *is_generator);
+            }
+            AstNode::ArrowFunctionExpression { params, body, is_async, .. } => {
+                if *is_async {
+                    self.write("async ");
+                }
+                self.params(params);
+                self.write(" => ");
+                match body.as_ref() {
+                    AstNode::BlockStatement { .. } => self.statement_body(body),
+                    body if starts_ambiguously(body) => {
+                        self.write("(");
+                        self.expression(body);
+                        self.write(")");
+                    }
+                    body => self.expression(body),
+                }
+            }
+            AstNode::CallExpression { callee, arguments, optional, .. } => {
+                self.expr(callee, PREC_CALL);
+                if *optional {
+                    self.write("?.");
+                }
+                self.write("(");
+                self.comma_separated(arguments);
+                self.write(")");
+            }
+            AstNode::MemberExpression { object, property, computed, optional, .. } => {
+                // `1.toString()` would lex as a malformed number
+                if matches!(**object, AstNode::Literal { value: LiteralValue::Number(_), .. }) {
+                    self.write("(");
+                    self.expression(object);
+                    self.write(")");
+                } else {
+                    self.expr(object, PREC_CALL);
+                }
+                match (*computed, *optional) {
+                    (true, true) => self.write("?.["),
+                    (true, false) => self.write("["),
+                    (false, true) => self.write("?."),
+                    (false, false) => self.write("."),
+                }
+                self.expr(property, PREC_PRIMARY);
+                if *computed {
+                    self.write("]");
+                }
+            }
+            AstNode::ChainExpression { expression, .. } => self.expr_inner(expression),
+            AstNode::BinaryExpression { operator, left, right, .. } => {
+                let own = binary_precedence(operator);
+                let (left_min, right_min) = if *operator == BinaryOperator::Pow {
+                    // Right-associative, and a unary operand must be parenthesized
+                    (own + 1, own)
+                } else {
+                    (own, own + 1)
+                };
+
+                self.binary_operand(left, operator, left_min);
+                self.write(" ");
+                self.write(binary_operator_str(operator));
+                self.write(" ");
+                self.binary_operand(right, operator, right_min);
+            }
+            AstNode::UnaryExpression { operator, argument, .. } => {
+                let text = unary_operator_str(operator);
+                self.write(text);
+                let is_word = text.chars().all(|c| c.is_ascii_alphabetic());
+                // Keep `- -x` and `+ +x` from printing as `--x` and `++x`
+                let doubles_sign = matches!(
+                    (operator, argument.as_ref()),
+                    (UnaryOperator::Minus, AstNode::UnaryExpression { operator: UnaryOperator::Minus, .. })
+                        | (UnaryOperator::Plus, AstNode::UnaryExpression { operator: UnaryOperator::Plus, .. })
+                        | (UnaryOperator::Minus, AstNode::UpdateExpression { operator: UpdateOperator::Decrement, prefix: true, .. })
+                        | (UnaryOperator::Plus, AstNode::UpdateExpression { operator: UpdateOperator::Increment, prefix: true, .. })
+                );
+                if is_word || doubles_sign {
+                    self.write(" ");
+                }
+                self.expr(argument, PREC_UNARY);
+            }
+            AstNode::UpdateExpression { operator, argument, prefix, .. } => {
+                let text = match operator {
+                    UpdateOperator::Increment => "++",
+                    UpdateOperator::Decrement => "--",
+                };
+                if *prefix {
+                    self.write(text);
+                    self.expr(argument, PREC_UNARY);
+                } else {
+                    self.expr(argument, PREC_POSTFIX + 1);
+                    self.write(text);
+                }
+            }
+            AstNode::AssignmentExpression { operator, left, right, .. } => {
+                self.expr(left, PREC_POSTFIX);
+                self.write(" ");
+                self.write(assignment_operator_str(operator));
+                self.write(" ");
+                self.expr(right, PREC_ASSIGNMENT);
+            }
+            AstNode::ConditionalExpression { test, consequent, alternate, .. } => {
+                self.expr(test, PREC_CONDITIONAL + 1);
+                self.write(" ? ");
+                self.expr(consequent, PREC_ASSIGNMENT);
+                self.write(" : ");
+                self.expr(alternate, PREC_ASSIGNMENT);
+            }
+            AstNode::SequenceExpression { expressions, .. } => {
+                for (i, expression) in expressions.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.expr(expression, PREC_ASSIGNMENT);
+                }
+            }
+            AstNode::TemplateLiteral { quasis, expressions, .. } => {
+                self.write("`");
+                for (i, quasi) in quasis.iter().enumerate() {
+                    if let AstNode::Literal { value: LiteralValue::String(text), .. } = quasi {
+                        let escaped = text
+                            .replace('\\', "\\\\")
+                            .replace('`', "\\`")
+                            .replace("${", "\\${");
+                        self.write(&escaped);
+                    }
+                    if let Some(expression) = expressions.get(i) {
+                        self.write("${");
+                        self.expression(expression);
+                        self.write("}");
+                    }
+                }
+                self.write("`");
+            }
+            AstNode::AwaitExpression { argument, .. } => {
+                self.write("await ");
+                self.expr(argument, PREC_UNARY);
+            }
+            AstNode::Decorator { expression, .. } => {
+                self.write("@");
+                match expression.as_ref() {
+                    AstNode::Identifier { .. } | AstNode::MemberExpression { .. } | AstNode::CallExpression { .. } => {
+                        self.expr(expression, PREC_CALL);
+                    }
+                    other => {
+                        self.write("(");
+                        self.expression(other);
+                        self.write(")");
+                    }
+                }
+            }
+            AstNode::Program(program) => self.program(program),
+            // Statements never appear in expression position
+            _ => self.statement_body(node),
+        }
+    }
+
+    /// Print a binary operand, parenthesizing where `??` meets `&&`/`||`
+    fn binary_operand(&mut self, operand: &AstNode, parent: &BinaryOperator, min_precedence: u8) {
+        let mixes_nullish = match operand {
+            AstNode::BinaryExpression { operator, .. } => {
+                let is_logical = |op: &BinaryOperator| {
+                    matches!(op, BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr)
+                };
+                (*parent == BinaryOperator::NullishCoalescing && is_logical(operator))
+                    || (is_logical(parent) && *operator == BinaryOperator::NullishCoalescing)
+            }
+            _ => false,
+        };
+
+        let operand_is_unary = matches!(operand, AstNode::UnaryExpression { .. } | AstNode::AwaitExpression { .. });
+        if mixes_nullish || (*parent == BinaryOperator::Pow && operand_is_unary && min_precedence > PREC_UNARY) {
+            self.write("(");
+            self.expression(operand);
+            self.write(")");
+        } else {
+            self.expr(operand, min_precedence);
+        }
+    }
+
+    fn literal(&mut self, value: &LiteralValue, raw: &str) {
+        match value {
+            LiteralValue::String(text) => {
+                let quote = match self.options.quote_style {
+                    QuoteStyle::Double => '"',
+                    QuoteStyle::Single => '\'',
+                    QuoteStyle::Preserve if raw.starts_with('\'') => '\'',
+                    QuoteStyle::Preserve => '"',
+                };
+                let quoted = quote_string(text, quote);
+                self.write(&quoted);
+            }
+            LiteralValue::Number(n) => {
+                if raw.is_empty() {
+                    let text = format_number(*n);
+                    self.write(&text);
+                } else {
+                    self.write(raw);
+                }
+            }
+            LiteralValue::Boolean(b) => self.write(if *b { "true" } else { "false" }),
+            LiteralValue::Null => self.write("null"),
+            LiteralValue::Undefined => self.write("undefined"),
+            LiteralValue::RegExp { pattern, flags } => {
+                let text = format!("/{}/{}", pattern, flags);
+                self.write(&text);
+            }
+        }
+    }
+
+    fn comma_separated(&mut self, nodes: &[AstNode]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.expression(node);
+        }
+    }
+
+    fn semicolon(&mut self) {
+        if self.options.semicolons {
+            self.write(";");
+        }
+    }
+
+    fn newline(&mut self) {
+        self.output.push('\n');
+        self.write_indent();
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.level {
+            self.output.push_str(&self.options.indent);
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+}
+
+fn precedence(node: &AstNode) -> u8 {
+    match node {
+        AstNode::AssignmentExpression { .. }
+        | AstNode::ArrowFunctionExpression { .. } => PREC_ASSIGNMENT,
+        AstNode::SequenceExpression { .. } => PREC_SEQUENCE,
+        AstNode::ConditionalExpression { .. } => PREC_CONDITIONAL,
+        AstNode::BinaryExpression { operator, .. } => binary_precedence(operator),
+        AstNode::UnaryExpression { .. } | AstNode::AwaitExpression { .. } => PREC_UNARY,
+        AstNode::UpdateExpression { prefix: true, .. } => PREC_UNARY,
+        AstNode::UpdateExpression { prefix: false, .. } => PREC_POSTFIX,
+        AstNode::CallExpression { .. }
+        | AstNode::MemberExpression { .. }
+        | AstNode::ChainExpression { .. } => PREC_CALL,
+        AstNode::SpreadElement { .. } => PREC_ASSIGNMENT,
+        _ => PREC_PRIMARY,
+    }
+}
+
+fn binary_precedence(operator: &BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::NullishCoalescing | BinaryOperator::LogicalOr => 4,
+        BinaryOperator::LogicalAnd => 5,
+        BinaryOperator::BitwiseOr => 6,
+        BinaryOperator::BitwiseXor => 7,
+        BinaryOperator::BitwiseAnd => 8,
+        BinaryOperator::Equal | BinaryOperator::NotEqual
+        | BinaryOperator::StrictEqual | BinaryOperator::StrictNotEqual => 9,
+        BinaryOperator::Less | BinaryOperator::Greater
+        | BinaryOperator::LessEqual | BinaryOperator::GreaterEqual
+        | BinaryOperator::In | BinaryOperator::InstanceOf => 10,
+        BinaryOperator::LeftShift | BinaryOperator::RightShift
+        | BinaryOperator::UnsignedRightShift => 11,
+        BinaryOperator::Add | BinaryOperator::Sub => 12,
+        BinaryOperator::Mul | BinaryOperator::Div | BinaryOperator::Mod => 13,
+        BinaryOperator::Pow => 14,
+    }
+}
+
+fn binary_operator_str(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Sub => "-",
+        BinaryOperator::Mul => "*",
+        BinaryOperator::Div => "/",
+        BinaryOperator::Mod => "%",
+        BinaryOperator::Pow => "**",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::StrictEqual => "===",
+        BinaryOperator::StrictNotEqual => "!==",
+        BinaryOperator::Less => "<",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::GreaterEqual => ">=",
+        BinaryOperator::LeftShift => "<<",
+        BinaryOperator::RightShift => ">>",
+        BinaryOperator::UnsignedRightShift => ">>>",
+        BinaryOperator::BitwiseAnd => "&",
+        BinaryOperator::BitwiseOr => "|",
+        BinaryOperator::BitwiseXor => "^",
+        BinaryOperator::LogicalAnd => "&&",
+        BinaryOperator::LogicalOr => "||",
+        BinaryOperator::NullishCoalescing => "??",
+        BinaryOperator::In => "in",
+        BinaryOperator::InstanceOf => "instanceof",
+    }
+}
+
+fn unary_operator_str(operator: &UnaryOperator) -> &'static str {
+    match operator {
+        UnaryOperator::Plus => "+",
+        UnaryOperator::Minus => "-",
+        UnaryOperator::Not => "!",
+        UnaryOperator::BitwiseNot => "~",
+        UnaryOperator::TypeOf => "typeof",
+        UnaryOperator::Void => "void",
+        UnaryOperator::Delete => "delete",
+    }
+}
+
+fn assignment_operator_str(operator: &AssignmentOperator) -> &'static str {
+    match operator {
+        AssignmentOperator::Assign => "=",
+        AssignmentOperator::AddAssign => "+=",
+        AssignmentOperator::SubAssign => "-=",
+        AssignmentOperator::MulAssign => "*=",
+        AssignmentOperator::DivAssign => "/=",
+        AssignmentOperator::ModAssign => "%=",
+        AssignmentOperator::PowAssign => "**=",
+        AssignmentOperator::LeftShiftAssign => "<<=",
+        AssignmentOperator::RightShiftAssign => ">>=",
+        AssignmentOperator::UnsignedRightShiftAssign => ">>>=",
+        AssignmentOperator::BitwiseAndAssign => "&=",
+        AssignmentOperator::BitwiseOrAssign => "|=",
+        AssignmentOperator::BitwiseXorAssign => "^=",
+        AssignmentOperator::LogicalAndAssign => "&&=",
+        AssignmentOperator::LogicalOrAssign => "||=",
+        AssignmentOperator::NullishCoalescingAssign => "??=",
+    }
+}
+
+/// Whether an expression statement would be read as a block, function or
+/// class declaration if printed without parentheses
+fn starts_ambiguously(node: &AstNode) -> bool {
+    match node {
+        AstNode::ObjectExpression { .. } | AstNode::FunctionExpression { .. } => true,
+        AstNode::BinaryExpression { left, .. } | AstNode::AssignmentExpression { left, .. } => starts_ambiguously(left),
+        AstNode::ConditionalExpression { test, .. } => starts_ambiguously(test),
+        AstNode::SequenceExpression { expressions, .. } => expressions.first().is_some_and(starts_ambiguously),
+        AstNode::CallExpression { callee, .. } => starts_ambiguously(callee),
+        AstNode::MemberExpression { object, .. } => starts_ambiguously(object),
+        AstNode::ChainExpression { expression, .. } => starts_ambiguously(expression),
+        AstNode::UpdateExpression { argument, prefix: false, .. } => starts_ambiguously(argument),
+        _ => false,
+    }
+}
+
+fn quote_string(text: &str, quote: char) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push(quote);
+    for ch in text.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            '\u{2028}' => out.push_str("\\u2028"),
+            '\u{2029}' => out.push_str("\\u2029"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push(quote);
+    out
+}
+
+fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        format!("{}", n)
+    }
+}
@@ -0,0 +1,96 @@
+//! Registry of experimental language features
+//!
+//! Proposals that are not part of ES2024 are gated behind these flags so that
+//! default parsing behavior is unaffected.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Feature {
+    /// `@decorator` syntax on classes and class members
+    Decorators,
+    /// The `|>` pipeline operator
+    Pipeline,
+    /// `do { ... }` expressions
+    DoExpressions,
+}
+
+impl Feature {
+    pub const ALL: &'static [Feature] = &[
+        Feature::Decorators,
+        Feature::Pipeline,
+        Feature::DoExpressions,
+    ];
+
+    /// Name used on the command line, e.g. `--experimental decorators`
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Decorators => "decorators",
+            Feature::Pipeline => "pipeline",
+            Feature::DoExpressions => "do-expressions",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Feature> {
+        Self::ALL.iter().copied().find(|feature| feature.name() == name)
+    }
+
+    /// Whether the parser understands the feature yet
+    pub fn is_implemented(self) -> bool {
+        matches!(self, Feature::Decorators)
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The set of experimental features enabled for a parser or engine
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExperimentalFeatures {
+    enabled: HashSet<Feature>,
+}
+
+impl ExperimentalFeatures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build from command-line names, rejecting unknown ones
+    pub fn from_names<I, S>(names: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut features = Self::new();
+        for name in names {
+            let name = name.as_ref();
+            let feature = Feature::from_name(name).ok_or_else(|| {
+                let known: Vec<_> = Feature::ALL.iter().map(|f| f.name()).collect();
+                format!("Unknown experimental feature '{}' (known: {})", name, known.join(", "))
+            })?;
+            features.enable(feature);
+        }
+        Ok(features)
+    }
+
+    pub fn enable(&mut self, feature: Feature) {
+        self.enabled.insert(feature);
+    }
+
+    pub fn disable(&mut self, feature: Feature) {
+        self.enabled.remove(&feature);
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.enabled.contains(&feature)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Feature> + '_ {
+        self.enabled.iter().copied()
+    }
+}
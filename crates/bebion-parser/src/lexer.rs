@@ -27,6 +27,7 @@ pub enum TokenType {
     Equal, NotEqual, StrictEqual, StrictNotEqual,
     Less, Greater, LessEqual, GreaterEqual,
     LogicalAnd, LogicalOr, LogicalNot, NullishCoalescing,
+    LogicalAndAssign, LogicalOrAssign, NullishCoalescingAssign,
     BitwiseAnd, BitwiseOr, BitwiseXor, BitwiseNot,
     LeftShift, RightShift, UnsignedRightShift,
     Increment, Decrement,
@@ -35,11 +36,17 @@ pub enum TokenType {
     LeftParen, RightParen,
     LeftBrace, RightBrace,
     LeftBracket, RightBracket,
-    Semicolon, Comma, Dot, QuestionMark, Colon,
-    Arrow, Spread,
+    Semicolon, Comma, Dot, QuestionMark, OptionalChaining, Colon,
+    Arrow, Spread, At,
     
     // Template literals
-    TemplateHead, TemplateMiddle, TemplateTail, TemplateNoSubstitution,
+    // Each carries its cooked text: `a${` is a head, `}b${` a middle and `}c`` a tail
+    TemplateHead(String), TemplateMiddle(String), TemplateTail(String), TemplateNoSubstitution(String),
+    
+    // Trivia, only emitted when enabled in `LexerConfig`
+    Hashbang(String),
+    LineComment(String),
+    BlockComment(String),
     
     // Special
     EOF,
@@ -63,16 +70,63 @@ impl fmt::Display for Token {
     }
 }
 
+/// How `Token::start` and `Token::end` are measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OffsetKind {
+    /// Offsets count Unicode scalar values
+    Chars,
+    /// Offsets count UTF-8 bytes of the source
+    Bytes,
+}
+
+/// Options for tools that drive the lexer directly
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LexerConfig {
+    /// Emit a leading `#!` line as a `Hashbang` token instead of skipping it
+    pub preserve_shebang: bool,
+    /// Emit `LineComment` and `BlockComment` tokens instead of skipping them
+    pub emit_comments: bool,
+    pub offsets: OffsetKind,
+}
+
+impl Default for LexerConfig {
+    fn default() -> Self {
+        Self {
+            preserve_shebang: false,
+            emit_comments: false,
+            offsets: OffsetKind::Chars,
+        }
+    }
+}
+
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
     line: usize,
     column: usize,
     keywords: std::collections::HashMap<String, TokenType>,
+    regex_allowed: bool,
+    /// Unclosed `{` count inside each open template substitution, innermost last
+    template_braces: Vec<usize>,
+    /// For each unclosed `(`, innermost last, whether it opens the head of
+    /// a statement such as `if (...)`, or a function's parameters
+    open_parens: Vec<bool>,
+    /// For each unclosed `{`, innermost last, whether it opens a block
+    /// rather than an object literal
+    open_braces: Vec<bool>,
+    /// Whether the last token was a `)` closing one of those heads
+    after_head: bool,
+    config: LexerConfig,
+    /// Byte offset of each char index, present when reporting byte offsets
+    byte_offsets: Option<Vec<usize>>,
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
+        Self::with_config(input, LexerConfig::default())
+    }
+
+    pub fn with_config(input: &str, config: LexerConfig) -> Self {
         let mut keywords = std::collections::HashMap::new();
         
         // Populate keywords
@@ -118,39 +172,102 @@ impl Lexer {
         keywords.insert("null".to_string(), TokenType::NullLiteral);
         keywords.insert("undefined".to_string(), TokenType::UndefinedLiteral);
         
+        let byte_offsets = match config.offsets {
+            OffsetKind::Chars => None,
+            OffsetKind::Bytes => Some(
+                input.char_indices()
+                    .map(|(i, _)| i)
+                    .chain(std::iter::once(input.len()))
+                    .collect(),
+            ),
+        };
+        
         Self {
             input: input.chars().collect(),
             position: 0,
             line: 1,
             column: 1,
             keywords,
+            regex_allowed: true,
+            template_braces: Vec::new(),
+            open_parens: Vec::new(),
+            open_braces: Vec::new(),
+            after_head: false,
+            config,
+            byte_offsets,
         }
     }
 
     pub fn tokenize(&mut self) -> ParseResult<Vec<Token>> {
         let mut tokens = Vec::new();
         
+        if let Some(hashbang) = self.hashbang() {
+            if self.config.preserve_shebang {
+                tokens.push(self.with_offsets(hashbang));
+            }
+        }
+        
         while !self.is_at_end() {
             let token = self.next_token()?;
             
-            // Skip whitespace tokens for now
-            if !matches!(token.token_type, TokenType::Whitespace | TokenType::Newline) {
-                tokens.push(token);
+            match token.token_type {
+                // Skip whitespace tokens for now
+                TokenType::Whitespace | TokenType::Newline => {}
+                TokenType::LineComment(_) | TokenType::BlockComment(_) => {
+                    if self.config.emit_comments {
+                        tokens.push(self.with_offsets(token));
+                    }
+                }
+                _ => {
+                    self.regex_allowed = self.regex_allowed_after(&token.token_type, &tokens);
+                    tokens.push(self.with_offsets(token));
+                }
             }
         }
         
-        tokens.push(Token {
+        let eof = Token {
             token_type: TokenType::EOF,
             lexeme: "".to_string(),
             line: self.line,
             column: self.column,
             start: self.position,
             end: self.position,
-        });
+        };
+        tokens.push(self.with_offsets(eof));
         
         Ok(tokens)
     }
 
+    /// Consume a `#!` line at the very start of the input
+    fn hashbang(&mut self) -> Option<Token> {
+        if self.position != 0 || self.peek() != '#' || self.peek_ahead(1) != '!' {
+            return None;
+        }
+        
+        let mut lexeme = String::new();
+        while !self.is_at_end() && !is_line_terminator(self.peek()) {
+            lexeme.push(self.advance());
+        }
+        
+        Some(Token {
+            token_type: TokenType::Hashbang(lexeme[2..].to_string()),
+            lexeme,
+            line: 1,
+            column: 1,
+            start: 0,
+            end: self.position,
+        })
+    }
+
+    /// Convert char offsets to the configured offset kind
+    fn with_offsets(&self, mut token: Token) -> Token {
+        if let Some(byte_offsets) = &self.byte_offsets {
+            token.start = byte_offsets[token.start];
+            token.end = byte_offsets[token.end];
+        }
+        token
+    }
+
     fn next_token(&mut self) -> ParseResult<Token> {
         let start_pos = self.position;
         let start_line = self.line;
@@ -184,11 +301,27 @@ impl Lexer {
             }
             '/' => {
                 if self.peek() == '/' {
-                    self.skip_line_comment();
-                    self.next_token()
+                    let text = self.skip_line_comment();
+                    Ok(Token {
+                        token_type: TokenType::LineComment(text[1..].to_string()),
+                        lexeme: format!("/{}", text),
+                        line: start_line,
+                        column: start_column,
+                        start: start_pos,
+                        end: self.position,
+                    })
                 } else if self.peek() == '*' {
-                    self.skip_block_comment()?;
-                    self.next_token()
+                    let text = self.skip_block_comment()?;
+                    Ok(Token {
+                        token_type: TokenType::BlockComment(text[1..text.len() - 2].to_string()),
+                        lexeme: format!("/{}", text),
+                        line: start_line,
+                        column: start_column,
+                        start: start_pos,
+                        end: self.position,
+                    })
+                } else if self.regex_allowed {
+                    self.regex_literal(start_line, start_column, start_pos)
                 } else if self.peek() == '=' {
                     self.advance();
                     Ok(self.make_token(TokenType::DivideAssign, "/=", start_line, start_column, start_pos))
@@ -293,7 +426,12 @@ impl Lexer {
             '&' => {
                 if self.peek() == '&' {
                     self.advance();
-                    Ok(self.make_token(TokenType::LogicalAnd, "&&", start_line, start_column, start_pos))
+                    if self.peek() == '=' {
+                        self.advance();
+                        Ok(self.make_token(TokenType::LogicalAndAssign, "&&=", start_line, start_column, start_pos))
+                    } else {
+                        Ok(self.make_token(TokenType::LogicalAnd, "&&", start_line, start_column, start_pos))
+                    }
                 } else {
                     Ok(self.make_token(TokenType::BitwiseAnd, "&", start_line, start_column, start_pos))
                 }
@@ -301,7 +439,12 @@ impl Lexer {
             '|' => {
                 if self.peek() == '|' {
                     self.advance();
-                    Ok(self.make_token(TokenType::LogicalOr, "||", start_line, start_column, start_pos))
+                    if self.peek() == '=' {
+                        self.advance();
+                        Ok(self.make_token(TokenType::LogicalOrAssign, "||=", start_line, start_column, start_pos))
+                    } else {
+                        Ok(self.make_token(TokenType::LogicalOr, "||", start_line, start_column, start_pos))
+                    }
                 } else {
                     Ok(self.make_token(TokenType::BitwiseOr, "|", start_line, start_column, start_pos))
                 }
@@ -309,15 +452,41 @@ impl Lexer {
             '?' => {
                 if self.peek() == '?' {
                     self.advance();
-                    Ok(self.make_token(TokenType::NullishCoalescing, "??", start_line, start_column, start_pos))
+                    if self.peek() == '=' {
+                        self.advance();
+                        Ok(self.make_token(TokenType::NullishCoalescingAssign, "??=", start_line, start_column, start_pos))
+                    } else {
+                        Ok(self.make_token(TokenType::NullishCoalescing, "??", start_line, start_column, start_pos))
+                    }
+                } else if self.peek() == '.' && !self.peek_ahead(1).is_ascii_digit() {
+                    // `a?.5:b` is a conditional, not optional chaining
+                    self.advance();
+                    Ok(self.make_token(TokenType::OptionalChaining, "?.", start_line, start_column, start_pos))
                 } else {
                     Ok(self.make_token(TokenType::QuestionMark, "?", start_line, start_column, start_pos))
                 }
             }
             '(' => Ok(self.make_token(TokenType::LeftParen, "(", start_line, start_column, start_pos)),
             ')' => Ok(self.make_token(TokenType::RightParen, ")", start_line, start_column, start_pos)),
-            '{' => Ok(self.make_token(TokenType::LeftBrace, "{", start_line, start_column, start_pos)),
-            '}' => Ok(self.make_token(TokenType::RightBrace, "}", start_line, start_column, start_pos)),
+            '{' => {
+                if let Some(depth) = self.template_braces.last_mut() {
+                    *depth += 1;
+                }
+                Ok(self.make_token(TokenType::LeftBrace, "{", start_line, start_column, start_pos))
+            }
+            '}' => match self.template_braces.last_mut() {
+                // This brace closes a `${`, so the template's text resumes
+                Some(0) => {
+                    self.template_braces.pop();
+                    self.template_literal(false, start_line, start_column, start_pos)
+                }
+                depth => {
+                    if let Some(depth) = depth {
+                        *depth -= 1;
+                    }
+                    Ok(self.make_token(TokenType::RightBrace, "}", start_line, start_column, start_pos))
+                }
+            },
             '[' => Ok(self.make_token(TokenType::LeftBracket, "[", start_line, start_column, start_pos)),
             ']' => Ok(self.make_token(TokenType::RightBracket, "]", start_line, start_column, start_pos)),
             ';' => Ok(self.make_token(TokenType::Semicolon, ";", start_line, start_column, start_pos)),
@@ -342,12 +511,22 @@ impl Lexer {
                     Ok(self.make_token(TokenType::Modulo, "%", start_line, start_column, start_pos))
                 }
             }
+            '@' => Ok(self.make_token(TokenType::At, "@", start_line, start_column, start_pos)),
             '"' | '\'' => self.string_literal(ch, start_line, start_column, start_pos),
-            '`' => self.template_literal(start_line, start_column, start_pos),
+            '`' => self.template_literal(true, start_line, start_column, start_pos),
             _ if ch.is_ascii_digit() => self.numeric_literal(start_line, start_column, start_pos),
-            _ if ch.is_alphabetic() || ch == '_' || ch == '$' => {
+            _ if is_identifier_start(ch) || ch == '\\' => {
                 self.identifier_or_keyword(start_line, start_column, start_pos)
             }
+            '\u{2028}' | '\u{2029}' => {
+                self.line += 1;
+                self.column = 1;
+                Ok(self.make_token(TokenType::Newline, "\n", start_line, start_column, start_pos))
+            }
+            _ if is_unicode_whitespace(ch) => {
+                self.skip_whitespace();
+                Ok(self.make_token(TokenType::Whitespace, " ", start_line, start_column, start_pos))
+            }
             _ => Err(ParseError::LexicalError {
                 message: format!("Unexpected character: '{}'", ch),
                 line: start_line,
@@ -400,25 +579,30 @@ impl Lexer {
     }
 
     fn skip_whitespace(&mut self) {
-        while !self.is_at_end() && matches!(self.peek(), ' ' | '\t' | '\r') {
+        while !self.is_at_end() && (matches!(self.peek(), ' ' | '\t' | '\r') || is_unicode_whitespace(self.peek())) {
             self.advance();
         }
     }
 
-    fn skip_line_comment(&mut self) {
-        while !self.is_at_end() && self.peek() != '\n' {
-            self.advance();
+    /// Skip a `//` comment, returning its text after the first `/`
+    fn skip_line_comment(&mut self) -> String {
+        let mut text = String::new();
+        while !self.is_at_end() && !is_line_terminator(self.peek()) {
+            text.push(self.advance());
         }
+        text
     }
 
-    fn skip_block_comment(&mut self) -> ParseResult<()> {
-        self.advance(); // consume '*'
+    /// Skip a `/* */` comment, returning its text after the first `/`
+    fn skip_block_comment(&mut self) -> ParseResult<String> {
+        let mut text = String::new();
+        text.push(self.advance()); // consume '*'
         
         while !self.is_at_end() {
             if self.peek() == '*' && self.peek_ahead(1) == '/' {
-                self.advance(); // consume '*'
-                self.advance(); // consume '/'
-                return Ok(());
+                text.push(self.advance()); // consume '*'
+                text.push(self.advance()); // consume '/'
+                return Ok(text);
             }
             
             if self.peek() == '\n' {
@@ -426,11 +610,11 @@ impl Lexer {
                 self.column = 0;
             }
             
-            self.advance();
+            text.push(self.advance());
         }
         
-        Err(ParseError::LexicalError {
-            message: "Unterminated block comment".to_string(),
+        Err(ParseError::UnexpectedEof {
+            expected: "*/".to_string(),
             line: self.line,
             column: self.column,
         })
@@ -490,30 +674,57 @@ impl Lexer {
         })
     }
 
-    fn template_literal(&mut self, start_line: usize, start_column: usize, start_pos: usize) -> ParseResult<Token> {
-        let mut value = String::new();
+    fn regex_literal(&mut self, start_line: usize, start_column: usize, start_pos: usize) -> ParseResult<Token> {
+        let mut pattern = String::new();
+        let mut in_class = false;
         
-        while !self.is_at_end() && self.peek() != '`' {
-            if self.peek() == '\n' {
-                self.line += 1;
-                self.column = 0;
+        loop {
+            if self.is_at_end() || self.peek() == '\n' {
+                return Err(ParseError::LexicalError {
+                    message: "Unterminated regular expression literal".to_string(),
+                    line: start_line,
+                    column: start_column,
+                });
+            }
+            
+            let ch = self.advance();
+            match ch {
+                '\\' => {
+                    pattern.push(ch);
+                    if self.is_at_end() || self.peek() == '\n' {
+                        continue;
+                    }
+                    pattern.push(self.advance());
+                }
+                '[' => {
+                    in_class = true;
+                    pattern.push(ch);
+                }
+                ']' => {
+                    in_class = false;
+                    pattern.push(ch);
+                }
+                '/' if !in_class => break,
+                _ => pattern.push(ch),
             }
-            value.push(self.advance());
         }
         
-        if self.is_at_end() {
-            return Err(ParseError::LexicalError {
-                message: "Unterminated template literal".to_string(),
-                line: start_line,
-                column: start_column,
-            });
+        let mut flags = String::new();
+        while !self.is_at_end() && (self.peek().is_alphanumeric() || self.peek() == '_' || self.peek() == '$') {
+            let flag = self.advance();
+            if !matches!(flag, 'd' | 'g' | 'i' | 'm' | 's' | 'u' | 'v' | 'y') || flags.contains(flag) {
+                return Err(ParseError::LexicalError {
+                    message: format!("Invalid regular expression flags: '{}{}'", flags, flag),
+                    line: start_line,
+                    column: start_column,
+                });
+            }
+            flags.push(flag);
         }
         
-        self.advance(); // consume closing '`'
-        
-        let lexeme = format!("`{}`", value);
+        let lexeme = format!("/{}/{}", pattern, flags);
         Ok(Token {
-            token_type: TokenType::TemplateNoSubstitution,
+            token_type: TokenType::RegExpLiteral { pattern, flags },
             lexeme,
             line: start_line,
             column: start_column,
@@ -522,33 +733,218 @@ impl Lexer {
         })
     }
 
+    /// Whether a `/` following `token_type`, which comes after `previous`,
+    /// starts a regular expression literal rather than a division operator.
+    /// After a `)` or `}` that depends on what it closes: a statement can
+    /// start after the head of an `if`, `while`, `for` or `with`, and after
+    /// a block, but not after other parentheses or an object literal.
+    fn regex_allowed_after(&mut self, token_type: &TokenType, previous: &[Token]) -> bool {
+        let mut before = previous
+            .iter()
+            .rev()
+            .map(|token| &token.token_type)
+            .filter(|token_type| !matches!(token_type, TokenType::LineComment(_) | TokenType::BlockComment(_)));
+        let last = before.next();
+        let after_head = std::mem::take(&mut self.after_head);
+        
+        match token_type {
+            TokenType::LeftParen => {
+                let head = match last {
+                    Some(
+                        TokenType::If | TokenType::While | TokenType::For | TokenType::With
+                            | TokenType::Switch | TokenType::Catch | TokenType::Function,
+                    ) => true,
+                    // `function name(` and `function* name(`
+                    Some(TokenType::Identifier(_) | TokenType::Multiply) => {
+                        let mut rest = before.skip_while(|token_type| matches!(token_type, TokenType::Multiply));
+                        matches!(rest.next(), Some(TokenType::Function))
+                    }
+                    _ => false,
+                };
+                self.open_parens.push(head);
+                return true;
+            }
+            TokenType::RightParen => {
+                self.after_head = self.open_parens.pop().unwrap_or(false);
+                return self.after_head;
+            }
+            TokenType::LeftBrace => {
+                let block = match last {
+                    None | Some(TokenType::Semicolon | TokenType::LeftBrace | TokenType::RightBrace) => true,
+                    Some(TokenType::Else | TokenType::Try | TokenType::Finally | TokenType::Do | TokenType::Arrow) => true,
+                    Some(TokenType::RightParen) => after_head,
+                    _ => false,
+                };
+                self.open_braces.push(block);
+                return true;
+            }
+            TokenType::RightBrace => return self.open_braces.pop().unwrap_or(false),
+            _ => {}
+        }
+        
+        !matches!(
+            token_type,
+            TokenType::Identifier(_)
+                | TokenType::StringLiteral(_)
+                | TokenType::NumericLiteral(_)
+                | TokenType::BooleanLiteral(_)
+                | TokenType::NullLiteral
+                | TokenType::UndefinedLiteral
+                | TokenType::RegExpLiteral { .. }
+                | TokenType::This
+                | TokenType::Super
+                | TokenType::RightBracket
+                | TokenType::Increment
+                | TokenType::Decrement
+                | TokenType::TemplateNoSubstitution(_)
+                | TokenType::TemplateTail(_)
+        )
+    }
+
+    /// Scan template text up to the closing '`' or the next `${`. `head` is
+    /// true at the opening '`' and false when resuming after a substitution.
+    fn template_literal(&mut self, head: bool, start_line: usize, start_column: usize, start_pos: usize) -> ParseResult<Token> {
+        let mut value = String::new();
+        
+        let substitution = loop {
+            if self.is_at_end() {
+                return Err(ParseError::UnexpectedEof {
+                    expected: "`".to_string(),
+                    line: start_line,
+                    column: start_column,
+                });
+            }
+            
+            match self.peek() {
+                '`' => {
+                    self.advance();
+                    break false;
+                }
+                '$' if self.peek_ahead(1) == '{' => {
+                    self.advance();
+                    self.advance();
+                    self.template_braces.push(0);
+                    break true;
+                }
+                '\\' => {
+                    self.advance(); // consume '\'
+                    let escaped = self.advance();
+                    match escaped {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        'r' => value.push('\r'),
+                        '0' => value.push('\0'),
+                        '\\' | '`' | '$' | '\'' | '"' => value.push(escaped),
+                        // Line continuation
+                        '\n' => {
+                            self.line += 1;
+                            self.column = 1;
+                        }
+                        _ => {
+                            value.push('\\');
+                            value.push(escaped);
+                        }
+                    }
+                }
+                '\n' => {
+                    self.advance();
+                    self.line += 1;
+                    self.column = 1;
+                    value.push('\n');
+                }
+                _ => value.push(self.advance()),
+            }
+        };
+        
+        let token_type = match (head, substitution) {
+            (true, false) => TokenType::TemplateNoSubstitution(value),
+            (true, true) => TokenType::TemplateHead(value),
+            (false, true) => TokenType::TemplateMiddle(value),
+            (false, false) => TokenType::TemplateTail(value),
+        };
+        Ok(Token {
+            token_type,
+            lexeme: self.input[start_pos..self.position].iter().collect(),
+            line: start_line,
+            column: start_column,
+            start: start_pos,
+            end: self.position,
+        })
+    }
+
     fn numeric_literal(&mut self, start_line: usize, start_column: usize, start_pos: usize) -> ParseResult<Token> {
         self.position -= 1; // Go back to include the first digit
         self.column -= 1;
         
         let mut lexeme = String::new();
-        
-        while !self.is_at_end() && (self.peek().is_ascii_digit() || self.peek() == '.') {
+
+        // Handle 0x, 0o and 0b prefixes
+        let radix = match (self.peek(), self.peek_ahead(1)) {
+            ('0', 'x' | 'X') => Some(16),
+            ('0', 'o' | 'O') => Some(8),
+            ('0', 'b' | 'B') => Some(2),
+            _ => None,
+        };
+
+        let value = if let Some(radix) = radix {
             lexeme.push(self.advance());
-        }
-        
-        // Handle scientific notation
-        if !self.is_at_end() && (self.peek() == 'e' || self.peek() == 'E') {
             lexeme.push(self.advance());
-            if !self.is_at_end() && (self.peek() == '+' || self.peek() == '-') {
+
+            let digits = self.digits_with_separators(&mut lexeme, radix)?;
+            if digits.is_empty() {
+                return Err(ParseError::LexicalError {
+                    message: format!("Expected digits after '{}'", lexeme),
+                    line: start_line,
+                    column: start_column,
+                });
+            }
+
+            digits.chars()
+                .fold(0.0, |acc, d| acc * radix as f64 + d.to_digit(radix).unwrap() as f64)
+        } else {
+            let mut number = self.digits_with_separators(&mut lexeme, 10)?;
+
+            if self.peek() == '.' {
                 lexeme.push(self.advance());
+                number.push('.');
+                number.push_str(&self.digits_with_separators(&mut lexeme, 10)?);
             }
-            while !self.is_at_end() && self.peek().is_ascii_digit() {
+
+            // Handle scientific notation
+            if self.peek() == 'e' || self.peek() == 'E' {
                 lexeme.push(self.advance());
+                number.push('e');
+                if self.peek() == '+' || self.peek() == '-' {
+                    number.push(self.peek());
+                    lexeme.push(self.advance());
+                }
+                let exponent = self.digits_with_separators(&mut lexeme, 10)?;
+                if exponent.is_empty() {
+                    return Err(ParseError::LexicalError {
+                        message: format!("Missing exponent in numeric literal: {}", lexeme),
+                        line: start_line,
+                        column: start_column,
+                    });
+                }
+                number.push_str(&exponent);
             }
+
+            number.parse::<f64>().map_err(|_| ParseError::LexicalError {
+                message: format!("Invalid numeric literal: {}", lexeme),
+                line: start_line,
+                column: start_column,
+            })?
+        };
+
+        let next = self.peek();
+        if next.is_ascii_digit() || is_identifier_start(next) || next == '\\' {
+            return Err(ParseError::LexicalError {
+                message: format!("Invalid character '{}' in numeric literal: {}", next, lexeme),
+                line: self.line,
+                column: self.column,
+            });
         }
-        
-        let value = lexeme.parse::<f64>().map_err(|_| ParseError::LexicalError {
-            message: format!("Invalid numeric literal: {}", lexeme),
-            line: start_line,
-            column: start_column,
-        })?;
-        
+
         Ok(Token {
             token_type: TokenType::NumericLiteral(value),
             lexeme,
@@ -559,19 +955,77 @@ impl Lexer {
         })
     }
 
+    /// Consume digits of the given radix, allowing single `_` separators between
+    /// digits. Returns the digits with separators removed.
+    fn digits_with_separators(&mut self, lexeme: &mut String, radix: u32) -> ParseResult<String> {
+        let mut digits = String::new();
+
+        while !self.is_at_end() {
+            let ch = self.peek();
+            if ch == '_' {
+                if digits.is_empty() || lexeme.ends_with('_') || !self.peek_ahead(1).is_digit(radix) {
+                    return Err(ParseError::LexicalError {
+                        message: "Numeric separators are only allowed between digits".to_string(),
+                        line: self.line,
+                        column: self.column,
+                    });
+                }
+                lexeme.push(self.advance());
+            } else if ch.is_digit(radix) {
+                digits.push(ch);
+                lexeme.push(self.advance());
+            } else {
+                break;
+            }
+        }
+
+        Ok(digits)
+    }
+
     fn identifier_or_keyword(&mut self, start_line: usize, start_column: usize, start_pos: usize) -> ParseResult<Token> {
         self.position -= 1; // Go back to include the first character
         self.column -= 1;
         
         let mut lexeme = String::new();
+        let mut name = String::new();
+        let mut escaped = false;
         
-        while !self.is_at_end() && (self.peek().is_alphanumeric() || self.peek() == '_' || self.peek() == '$') {
-            lexeme.push(self.advance());
+        while !self.is_at_end() {
+            let ch = self.peek();
+            let is_start = name.is_empty();
+            
+            if ch == '\\' {
+                let (decoded, raw) = self.identifier_escape()?;
+                let valid = if is_start { is_identifier_start(decoded) } else { is_identifier_part(decoded) };
+                if !valid {
+                    return Err(ParseError::LexicalError {
+                        message: format!("Invalid identifier escape: {}", raw),
+                        line: start_line,
+                        column: start_column,
+                    });
+                }
+                lexeme.push_str(&raw);
+                name.push(decoded);
+                escaped = true;
+            } else if (is_start && is_identifier_start(ch)) || (!is_start && is_identifier_part(ch)) {
+                lexeme.push(self.advance());
+                name.push(ch);
+            } else {
+                break;
+            }
         }
         
-        let token_type = self.keywords.get(&lexeme)
-            .cloned()
-            .unwrap_or_else(|| TokenType::Identifier(lexeme.clone()));
+        let token_type = match self.keywords.get(&name) {
+            Some(_) if escaped => {
+                return Err(ParseError::LexicalError {
+                    message: format!("Keyword must not contain escaped characters: {}", lexeme),
+                    line: start_line,
+                    column: start_column,
+                });
+            }
+            Some(keyword) => keyword.clone(),
+            None => TokenType::Identifier(name),
+        };
         
         Ok(Token {
             token_type,
@@ -582,4 +1036,76 @@ impl Lexer {
             end: self.position,
         })
     }
+
+    /// Decode a `\uXXXX` or `\u{X...}` escape inside an identifier, returning
+    /// the character and its source text
+    fn identifier_escape(&mut self) -> ParseResult<(char, String)> {
+        let line = self.line;
+        let column = self.column;
+        let mut raw = String::new();
+        raw.push(self.advance()); // consume '\\'
+        
+        let invalid = |raw: &str| ParseError::LexicalError {
+            message: format!("Invalid Unicode escape sequence in identifier: {}", raw),
+            line,
+            column,
+        };
+        
+        if self.peek() != 'u' {
+            return Err(invalid(&raw));
+        }
+        raw.push(self.advance());
+        
+        let mut hex = String::new();
+        if self.peek() == '{' {
+            raw.push(self.advance());
+            while !self.is_at_end() && self.peek().is_ascii_hexdigit() {
+                let digit = self.advance();
+                raw.push(digit);
+                hex.push(digit);
+            }
+            if self.peek() != '}' || hex.is_empty() {
+                return Err(invalid(&raw));
+            }
+            raw.push(self.advance());
+        } else {
+            for _ in 0..4 {
+                if !self.peek().is_ascii_hexdigit() {
+                    return Err(invalid(&raw));
+                }
+                let digit = self.advance();
+                raw.push(digit);
+                hex.push(digit);
+            }
+        }
+        
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .map(|ch| (ch, raw.clone()))
+            .ok_or_else(|| invalid(&raw))
+    }
+}
+
+/// `IdentifierStartChar`: ID_Start, `$` or `_`
+pub fn is_identifier_start(ch: char) -> bool {
+    ch == '$' || ch == '_' || unicode_ident::is_xid_start(ch)
+}
+
+/// `IdentifierPartChar`: ID_Continue, `$`, ZWNJ or ZWJ
+pub fn is_identifier_part(ch: char) -> bool {
+    ch == '$' || ch == '\u{200C}' || ch == '\u{200D}' || unicode_ident::is_xid_continue(ch)
+}
+
+fn is_line_terminator(ch: char) -> bool {
+    matches!(ch, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+/// WhiteSpace code points beyond the ASCII ones matched directly in `next_token`
+fn is_unicode_whitespace(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{000B}' | '\u{000C}' | '\u{00A0}' | '\u{FEFF}' | '\u{1680}'
+            | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}'
+    )
 }
@@ -3,11 +3,17 @@
 //! ECMAScript 2024 compliant parser with full AST generation.
 
 pub mod ast;
+pub mod codegen;
+pub mod features;
 pub mod lexer;
 pub mod parser;
+pub mod scope;
 
 pub use parser::Parser;
 pub use ast::{AstNode, Program};
+pub use codegen::{generate, CodegenOptions, QuoteStyle};
+pub use features::{ExperimentalFeatures, Feature};
+pub use scope::ScopeAnalysis;
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -30,6 +36,13 @@ pub enum ParseError {
         line: usize,
         column: usize,
     },
+    /// The input ended where `expected` should come, as in an unclosed
+    /// block, call or comment; more input might make it parse
+    UnexpectedEof {
+        expected: String,
+        line: usize,
+        column: usize,
+    },
 }
 
 impl fmt::Display for ParseError {
@@ -44,6 +57,9 @@ impl fmt::Display for ParseError {
             ParseError::LexicalError { message, line, column } => {
                 write!(f, "Lexical error at {}:{}: {}", line, column, message)
             }
+            ParseError::UnexpectedEof { expected, line, column } => {
+                write!(f, "Unexpected end of input at {}:{}, expected '{}'", line, column, expected)
+            }
         }
     }
 }
@@ -1,57 +1,308 @@
 //! JavaScript parser implementation
 
 use crate::ast::*;
-use crate::lexer::{Lexer, Token, TokenType};
+use crate::features::{ExperimentalFeatures, Feature};
+use crate::lexer::{Lexer, LexerConfig, OffsetKind, Token, TokenType};
 use crate::{ParseError, ParseResult};
 use tracing::debug;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<ParseError>,
+    features: ExperimentalFeatures,
+    /// Whether `await` is currently an operator, i.e. inside an async function
+    in_async: bool,
+    source: String,
+    /// Byte offset at which each source line begins
+    line_starts: Vec<usize>,
 }
 
 impl Parser {
     pub fn new() -> Self {
+        Self::with_features(ExperimentalFeatures::default())
+    }
+
+    pub fn with_features(features: ExperimentalFeatures) -> Self {
         Self {
             tokens: Vec::new(),
             current: 0,
+            errors: Vec::new(),
+            features,
+            in_async: false,
+            source: String::new(),
+            line_starts: vec![0],
         }
     }
 
+    pub fn features(&self) -> &ExperimentalFeatures {
+        &self.features
+    }
+
+    pub fn features_mut(&mut self) -> &mut ExperimentalFeatures {
+        &mut self.features
+    }
+
+    /// Parse `source`, failing with the first syntax error
     pub fn parse(&mut self, source: &str) -> ParseResult<Program> {
+        let (program, mut errors) = self.parse_with_recovery(source);
+        
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Parse `source`, synchronizing to the next statement after each syntax
+    /// error. Returns the statements that parsed along with every error found.
+    pub fn parse_with_recovery(&mut self, source: &str) -> (Program, Vec<ParseError>) {
         debug!("Parsing source: {} characters", source.len());
         
-        let mut lexer = Lexer::new(source);
-        self.tokens = lexer.tokenize()?;
+        let config = LexerConfig {
+            offsets: OffsetKind::Bytes,
+            ..LexerConfig::default()
+        };
+        let mut lexer = Lexer::with_config(source, config);
+        self.line_starts = line_starts(source);
+        self.source = source.to_string();
+        self.tokens = match lexer.tokenize() {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                let program = Program {
+                    body: Vec::new(),
+                    source_type: SourceType::Script,
+                };
+                return (program, vec![error]);
+            }
+        };
         self.current = 0;
+        self.errors.clear();
+        self.in_async = false;
         
         debug!("Tokenized {} tokens", self.tokens.len());
         
-        self.program()
+        let program = self.program();
+        (program, std::mem::take(&mut self.errors))
     }
 
-    fn program(&mut self) -> ParseResult<Program> {
+    fn program(&mut self) -> Program {
         let mut body = Vec::new();
         
+        let mut source_type = SourceType::Script;
+        
         while !self.is_at_end() {
-            if let Ok(stmt) = self.statement() {
-                body.push(stmt);
+            // `import(` and `import.meta` are expressions
+            let is_import = self.check(&TokenType::Import)
+                && !matches!(self.peek_ahead(1).token_type, TokenType::LeftParen | TokenType::Dot);
+            let statement = if is_import {
+                source_type = SourceType::Module;
+                self.import_declaration()
+            } else if self.check(&TokenType::Export) {
+                source_type = SourceType::Module;
+                self.export_declaration()
             } else {
-                // Skip invalid tokens and continue
-                self.advance();
+                self.statement()
+            };
+            match statement {
+                Ok(stmt) => body.push(stmt),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize();
+                }
             }
         }
         
-        Ok(Program {
+        Program {
             body,
-            source_type: SourceType::Script,
+            source_type,
+        }
+    }
+
+    /// Skip tokens until a likely statement boundary after a syntax error
+    fn synchronize(&mut self) {
+        let start = self.current;
+        
+        while !self.is_at_end() {
+            if self.current > start && self.previous().token_type == TokenType::Semicolon {
+                return;
+            }
+            
+            if self.current > start && matches!(
+                self.peek().token_type,
+                TokenType::Var | TokenType::Let | TokenType::Const | TokenType::Function
+                    | TokenType::Class | TokenType::If | TokenType::While | TokenType::For
+                    | TokenType::Do | TokenType::Return | TokenType::Break | TokenType::Continue
+                    | TokenType::Throw | TokenType::Try | TokenType::Switch
+                    | TokenType::Import | TokenType::Export
+            ) {
+                return;
+            }
+            
+            self.advance();
+        }
+    }
+
+    /// `import name from "source"` or `import "source"`, optionally with
+    /// `with { key: "value", ... }` attributes. Only allowed at the top
+    /// level; named and namespace imports are not supported yet.
+    fn import_declaration(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        self.expect(&TokenType::Import)?;
+        
+        let mut specifiers = Vec::new();
+        if !matches!(self.peek().token_type, TokenType::StringLiteral(_)) {
+            if self.matches(&[TokenType::LeftBrace, TokenType::Multiply]) {
+                return Err(ParseError::SyntaxError {
+                    message: "Named and namespace imports are not supported yet".to_string(),
+                    line: self.peek().line,
+                    column: self.peek().column,
+                });
+            }
+            specifiers.push(self.expect_identifier()?);
+            self.expect_contextual("from")?;
+        }
+        
+        if !matches!(self.peek().token_type, TokenType::StringLiteral(_)) {
+            return Err(self.unexpected("module specifier"));
+        }
+        let source = self.primary()?;
+        
+        let mut attributes = Vec::new();
+        if self.matches(&[TokenType::With]) {
+            self.advance();
+            self.expect(&TokenType::LeftBrace)?;
+            while !self.check(&TokenType::RightBrace) {
+                let attribute_start = self.current;
+                let key = if matches!(self.peek().token_type, TokenType::StringLiteral(_)) {
+                    self.primary()?
+                } else {
+                    self.expect_property_name()?
+                };
+                self.expect(&TokenType::Colon)?;
+                if !matches!(self.peek().token_type, TokenType::StringLiteral(_)) {
+                    return Err(self.unexpected("string attribute value"));
+                }
+                let value = self.primary()?;
+                attributes.push(AstNode::Property {
+                    key: Box::new(key),
+                    value: Box::new(value),
+                    kind: PropertyKind::Init,
+                    method: false,
+                    shorthand: false,
+                    computed: false,
+                    loc: self.loc_from(attribute_start),
+                });
+                if !self.matches(&[TokenType::Comma]) {
+                    break;
+                }
+                self.advance();
+            }
+            self.expect(&TokenType::RightBrace)?;
+        }
+        
+        self.consume_semicolon();
+        
+        Ok(AstNode::ImportDeclaration {
+            specifiers,
+            source: Box::new(source),
+            attributes,
+            loc: self.loc_from(start),
+        })
+    }
+
+    /// `export default expression`, `export` in front of a declaration, or
+    /// `export { a, b }`. Only allowed at the top level; renaming and
+    /// re-exporting from another module are not supported yet.
+    fn export_declaration(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        self.expect(&TokenType::Export)?;
+        
+        if self.check(&TokenType::Default) {
+            let default_start = self.current;
+            self.advance();
+            let default = AstNode::Identifier {
+                name: "default".to_string(),
+                loc: self.loc_from(default_start),
+            };
+            let declaration = self.assignment()?;
+            self.consume_semicolon();
+            return Ok(AstNode::ExportDeclaration {
+                declaration: Some(Box::new(declaration)),
+                specifiers: vec![default],
+                source: None,
+                loc: self.loc_from(start),
+            });
+        }
+        
+        if !self.check(&TokenType::LeftBrace) {
+            let declaration = match self.peek().token_type {
+                TokenType::Var | TokenType::Let | TokenType::Const => self.variable_declaration()?,
+                TokenType::Function => self.function_declaration()?,
+                TokenType::Async if self.peek_ahead(1).token_type == TokenType::Function => {
+                    self.function_declaration()?
+                }
+                TokenType::Class => self.class_declaration(Vec::new())?,
+                _ => return Err(self.unexpected("declaration")),
+            };
+            return Ok(AstNode::ExportDeclaration {
+                declaration: Some(Box::new(declaration)),
+                specifiers: Vec::new(),
+                source: None,
+                loc: self.loc_from(start),
+            });
+        }
+        
+        self.advance();
+        let mut specifiers = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            specifiers.push(self.expect_identifier()?);
+            self.reject_renamed_export()?;
+            if !self.matches(&[TokenType::Comma]) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(&TokenType::RightBrace)?;
+        self.reject_renamed_export()?;
+        self.consume_semicolon();
+        
+        Ok(AstNode::ExportDeclaration {
+            declaration: None,
+            specifiers,
+            source: None,
+            loc: self.loc_from(start),
         })
     }
 
+    /// Fail on the `as` of `export { a as b }` or the `from` of
+    /// `export { a } from "source"`
+    fn reject_renamed_export(&self) -> ParseResult<()> {
+        if matches!(&self.peek().token_type, TokenType::Identifier(word) if word == "as" || word == "from") {
+            return Err(ParseError::SyntaxError {
+                message: "Renamed exports and re-exports are not supported yet".to_string(),
+                line: self.peek().line,
+                column: self.peek().column,
+            });
+        }
+        Ok(())
+    }
+
     fn statement(&mut self) -> ParseResult<AstNode> {
         match self.peek().token_type {
             TokenType::Var | TokenType::Let | TokenType::Const => self.variable_declaration(),
             TokenType::Function => self.function_declaration(),
+            TokenType::Async if self.peek_ahead(1).token_type == TokenType::Function => {
+                self.function_declaration()
+            }
+            TokenType::Class => self.class_declaration(Vec::new()),
+            TokenType::At => {
+                let decorators = self.decorators()?;
+                if !self.check(&TokenType::Class) {
+                    return Err(self.unexpected("class after decorators"));
+                }
+                self.class_declaration(decorators)
+            }
             TokenType::If => self.if_statement(),
             TokenType::While => self.while_statement(),
             TokenType::For => self.for_statement(),
@@ -66,6 +317,7 @@ impl Parser {
     }
 
     fn variable_declaration(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let kind_token = self.advance().clone();
         let kind = match kind_token.token_type {
             TokenType::Var => VarKind::Var,
@@ -77,10 +329,11 @@ impl Parser {
         let mut declarations = Vec::new();
         
         loop {
+            let declarator_start = self.current;
             let id = self.expect_identifier()?;
             let init = if self.matches(&[TokenType::Assign]) {
                 self.advance();
-                Some(Box::new(self.expression()?))
+                Some(Box::new(self.assignment()?))
             } else {
                 None
             };
@@ -88,7 +341,7 @@ impl Parser {
             declarations.push(AstNode::VariableDeclarator {
                 id: Box::new(id),
                 init,
-                loc: None,
+                loc: self.loc_from(declarator_start),
             });
             
             if !self.matches(&[TokenType::Comma]) {
@@ -102,22 +355,21 @@ impl Parser {
         Ok(AstNode::VariableDeclaration {
             declarations,
             kind,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn function_declaration(&mut self) -> ParseResult<AstNode> {
-        self.advance(); // consume 'function'
-        
-        let is_async = false;
-        let is_generator = false;
+        let start = self.current;
+        let is_async = self.function_prefix();
+        let is_generator = self.generator_star();
         let id = Some(Box::new(self.expect_identifier()?));
         
         self.expect(&TokenType::LeftParen)?;
         let params = self.parameter_list()?;
         self.expect(&TokenType::RightParen)?;
         
-        let body = Box::new(self.block_statement()?);
+        let body = Box::new(self.function_body(is_async)?);
         
         Ok(AstNode::FunctionDeclaration {
             id,
@@ -125,11 +377,214 @@ impl Parser {
             body,
             is_async,
             is_generator,
-            loc: None,
+            loc: self.loc_from(start),
+        })
+    }
+
+    fn class_declaration(&mut self, decorators: Vec<AstNode>) -> ParseResult<AstNode> {
+        let start = self.current;
+        self.advance(); // consume 'class'
+        
+        let id = Some(Box::new(self.expect_identifier()?));
+        
+        let superclass = if self.check(&TokenType::Extends) {
+            self.advance();
+            Some(Box::new(self.call()?))
+        } else {
+            None
+        };
+        
+        let body = Box::new(self.class_body()?);
+        
+        Ok(AstNode::ClassDeclaration {
+            id,
+            superclass,
+            body,
+            decorators,
+            loc: self.loc_from(start),
         })
     }
 
+    fn class_body(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        self.expect(&TokenType::LeftBrace)?;
+        
+        let mut body = Vec::new();
+        
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if self.check(&TokenType::Semicolon) {
+                self.advance();
+                continue;
+            }
+            body.push(self.class_member()?);
+        }
+        
+        self.expect(&TokenType::RightBrace)?;
+        
+        Ok(AstNode::ClassBody {
+            body,
+            loc: self.loc_from(start),
+        })
+    }
+
+    fn class_member(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        let decorators = if self.check(&TokenType::At) {
+            self.decorators()?
+        } else {
+            Vec::new()
+        };
+        
+        // `static` is only a modifier when another member name follows it
+        let is_static = self.check(&TokenType::Static)
+            && !matches!(self.peek_ahead(1).token_type, TokenType::LeftParen | TokenType::Assign);
+        if is_static {
+            self.advance();
+        }
+        
+        let is_async = self.check(&TokenType::Async)
+            && !matches!(
+                self.peek_ahead(1).token_type,
+                TokenType::LeftParen | TokenType::Assign | TokenType::Semicolon
+            );
+        if is_async {
+            self.advance();
+        }
+        
+        let mut kind = PropertyKind::Method;
+        if let TokenType::Identifier(name) = &self.peek().token_type {
+            let is_accessor = name == "get" || name == "set";
+            let next = &self.peek_ahead(1).token_type;
+            let followed_by_key = matches!(
+                next,
+                TokenType::Identifier(_) | TokenType::StringLiteral(_)
+                    | TokenType::NumericLiteral(_) | TokenType::LeftBracket
+            );
+            if is_accessor && followed_by_key && !is_async {
+                kind = if name == "get" { PropertyKind::Get } else { PropertyKind::Set };
+                self.advance();
+            }
+        }
+        
+        let (key, computed) = self.class_member_key()?;
+        
+        if self.check(&TokenType::LeftParen) {
+            let value_start = self.current;
+            self.advance();
+            let params = self.parameter_list()?;
+            self.expect(&TokenType::RightParen)?;
+            let body = Box::new(self.function_body(is_async)?);
+            
+            let value = Box::new(AstNode::FunctionExpression {
+                id: None,
+                params,
+                body,
+                is_async,
+                is_generator: false,
+                loc: self.loc_from(value_start),
+            });
+            
+            return Ok(AstNode::MethodDefinition {
+                key: Box::new(key),
+                value,
+                kind,
+                is_static,
+                computed,
+                decorators,
+                loc: self.loc_from(start),
+            });
+        }
+        
+        if kind != PropertyKind::Method || is_async {
+            return Err(self.unexpected("("));
+        }
+        
+        let value = if self.check(&TokenType::Assign) {
+            self.advance();
+            Some(Box::new(self.assignment()?))
+        } else {
+            None
+        };
+        self.consume_semicolon();
+        
+        Ok(AstNode::PropertyDefinition {
+            key: Box::new(key),
+            value,
+            is_static,
+            computed,
+            decorators,
+            loc: self.loc_from(start),
+        })
+    }
+
+    fn class_member_key(&mut self) -> ParseResult<(AstNode, bool)> {
+        let token = self.peek().clone();
+        match token.token_type {
+            TokenType::LeftBracket => {
+                self.advance();
+                let key = self.assignment()?;
+                self.expect(&TokenType::RightBracket)?;
+                Ok((key, true))
+            }
+            TokenType::StringLiteral(_) | TokenType::NumericLiteral(_) => Ok((self.primary()?, false)),
+            _ => Ok((self.expect_property_name()?, false)),
+        }
+    }
+
+    /// Parse one or more `@decorator`s, which require the `decorators` feature
+    fn decorators(&mut self) -> ParseResult<Vec<AstNode>> {
+        if !self.features.is_enabled(Feature::Decorators) {
+            return Err(ParseError::SyntaxError {
+                message: "Decorators are experimental; enable them with --experimental decorators".to_string(),
+                line: self.peek().line,
+                column: self.peek().column,
+            });
+        }
+        
+        let mut decorators = Vec::new();
+        
+        while self.check(&TokenType::At) {
+            let start = self.current;
+            self.advance();
+            
+            let expression_start = self.current;
+            let expression = if self.check(&TokenType::LeftParen) {
+                self.advance();
+                let expression = self.expression()?;
+                self.expect(&TokenType::RightParen)?;
+                expression
+            } else {
+                // DecoratorMemberExpression followed by optional arguments
+                let mut expression = self.expect_identifier()?;
+                while self.check(&TokenType::Dot) {
+                    self.advance();
+                    let property = self.expect_property_name()?;
+                    expression = AstNode::MemberExpression {
+                        object: Box::new(expression),
+                        property: Box::new(property),
+                        computed: false,
+                        optional: false,
+                        loc: self.loc_from(expression_start),
+                    };
+                }
+                if self.check(&TokenType::LeftParen) {
+                    self.advance();
+                    expression = self.finish_call(expression, false, expression_start)?;
+                }
+                expression
+            };
+            
+            decorators.push(AstNode::Decorator {
+                expression: Box::new(expression),
+                loc: self.loc_from(start),
+            });
+        }
+        
+        Ok(decorators)
+    }
+
     fn if_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume 'if'
         
         self.expect(&TokenType::LeftParen)?;
@@ -149,11 +604,12 @@ impl Parser {
             test,
             consequent,
             alternate,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn while_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume 'while'
         
         self.expect(&TokenType::LeftParen)?;
@@ -165,11 +621,12 @@ impl Parser {
         Ok(AstNode::WhileStatement {
             test,
             body,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn for_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume 'for'
         
         self.expect(&TokenType::LeftParen)?;
@@ -213,11 +670,12 @@ impl Parser {
             test,
             update,
             body,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn return_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume 'return'
         
         let argument = if self.matches(&[TokenType::Semicolon, TokenType::EOF]) || self.check(&TokenType::RightBrace) {
@@ -230,11 +688,12 @@ impl Parser {
         
         Ok(AstNode::ReturnStatement {
             argument,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn break_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume 'break'
         
         let label = None;
@@ -243,11 +702,12 @@ impl Parser {
         
         Ok(AstNode::BreakStatement {
             label,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn continue_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume 'continue'
         
         let label = None;
@@ -256,11 +716,12 @@ impl Parser {
         
         Ok(AstNode::ContinueStatement {
             label,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn throw_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume 'throw'
         
         let argument = Box::new(self.expression()?);
@@ -269,16 +730,18 @@ impl Parser {
         
         Ok(AstNode::ThrowStatement {
             argument,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn try_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume 'try'
         
         let block = Box::new(self.block_statement()?);
         
         let handler = if self.matches(&[TokenType::Catch]) {
+            let handler_start = self.current;
             self.advance();
             
             let param = if self.matches(&[TokenType::LeftParen]) {
@@ -295,7 +758,7 @@ impl Parser {
             Some(Box::new(AstNode::CatchClause {
                 param,
                 body,
-                loc: None,
+                loc: self.loc_from(handler_start),
             }))
         } else {
             None
@@ -312,11 +775,12 @@ impl Parser {
             block,
             handler,
             finalizer,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn block_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.expect(&TokenType::LeftBrace)?;
         
         let mut body = Vec::new();
@@ -329,25 +793,44 @@ impl Parser {
         
         Ok(AstNode::BlockStatement {
             body,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn expression_statement(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let expression = Box::new(self.expression()?);
         self.consume_semicolon();
         
         Ok(AstNode::ExpressionStatement {
             expression,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
+    /// An expression, including `a, b` sequences. Places that take a
+    /// single value, like arguments and initializers, parse `assignment`.
     fn expression(&mut self) -> ParseResult<AstNode> {
-        self.assignment()
+        let start = self.current;
+        let first = self.assignment()?;
+        if !self.check(&TokenType::Comma) {
+            return Ok(first);
+        }
+        
+        let mut expressions = vec![first];
+        while self.matches(&[TokenType::Comma]) {
+            self.advance();
+            expressions.push(self.assignment()?);
+        }
+        
+        Ok(AstNode::SequenceExpression {
+            expressions,
+            loc: self.loc_from(start),
+        })
     }
 
     fn assignment(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let expr = self.conditional()?;
         
         if self.matches(&[
@@ -358,8 +841,11 @@ impl Parser {
             TokenType::DivideAssign,
             TokenType::ModuloAssign,
             TokenType::PowerAssign,
+            TokenType::LogicalAndAssign,
+            TokenType::LogicalOrAssign,
+            TokenType::NullishCoalescingAssign,
         ]) {
-            let operator_token = self.previous().clone();
+            let operator_token = self.advance().clone();
             let operator = match operator_token.token_type {
                 TokenType::Assign => AssignmentOperator::Assign,
                 TokenType::PlusAssign => AssignmentOperator::AddAssign,
@@ -368,6 +854,9 @@ impl Parser {
                 TokenType::DivideAssign => AssignmentOperator::DivAssign,
                 TokenType::ModuloAssign => AssignmentOperator::ModAssign,
                 TokenType::PowerAssign => AssignmentOperator::PowAssign,
+                TokenType::LogicalAndAssign => AssignmentOperator::LogicalAndAssign,
+                TokenType::LogicalOrAssign => AssignmentOperator::LogicalOrAssign,
+                TokenType::NullishCoalescingAssign => AssignmentOperator::NullishCoalescingAssign,
                 _ => unreachable!(),
             };
             
@@ -377,7 +866,7 @@ impl Parser {
                 operator,
                 left: Box::new(expr),
                 right,
-                loc: None,
+                loc: self.loc_from(start),
             });
         }
         
@@ -385,11 +874,12 @@ impl Parser {
     }
 
     fn conditional(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let expr = self.logical_or()?;
         
         if self.matches(&[TokenType::QuestionMark]) {
             self.advance();
-            let consequent = Box::new(self.expression()?);
+            let consequent = Box::new(self.assignment()?);
             self.expect(&TokenType::Colon)?;
             let alternate = Box::new(self.conditional()?);
             
@@ -397,7 +887,7 @@ impl Parser {
                 test: Box::new(expr),
                 consequent,
                 alternate,
-                loc: None,
+                loc: self.loc_from(start),
             });
         }
         
@@ -405,23 +895,44 @@ impl Parser {
     }
 
     fn logical_or(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let mut expr = self.logical_and()?;
         
+        // `??` may not share an unparenthesized chain with `&&` or `||`
+        let mut has_nullish = false;
+        let mut has_logical = self.is_bare_logical_and(&expr, start);
+        
         while self.matches(&[TokenType::LogicalOr, TokenType::NullishCoalescing]) {
-            let operator_token = self.previous().clone();
+            let operator_token = self.advance().clone();
             let operator = match operator_token.token_type {
                 TokenType::LogicalOr => BinaryOperator::LogicalOr,
                 TokenType::NullishCoalescing => BinaryOperator::NullishCoalescing,
                 _ => unreachable!(),
             };
             
+            let right_start = self.current;
             let right = Box::new(self.logical_and()?);
             
+            if operator == BinaryOperator::NullishCoalescing {
+                has_nullish = true;
+            } else {
+                has_logical = true;
+            }
+            has_logical |= self.is_bare_logical_and(&right, right_start);
+            
+            if has_nullish && has_logical {
+                return Err(ParseError::SyntaxError {
+                    message: "Cannot mix '??' with '&&' or '||' without parentheses".to_string(),
+                    line: operator_token.line,
+                    column: operator_token.column,
+                });
+            }
+            
             expr = AstNode::BinaryExpression {
                 operator,
                 left: Box::new(expr),
                 right,
-                loc: None,
+                loc: self.loc_from(start),
             };
         }
         
@@ -429,9 +940,11 @@ impl Parser {
     }
 
     fn logical_and(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let mut expr = self.equality()?;
         
         while self.matches(&[TokenType::LogicalAnd]) {
+            self.advance();
             let operator = BinaryOperator::LogicalAnd;
             let right = Box::new(self.equality()?);
             
@@ -439,14 +952,26 @@ impl Parser {
                 operator,
                 left: Box::new(expr),
                 right,
-                loc: None,
+                loc: self.loc_from(start),
             };
         }
         
         Ok(expr)
     }
 
+    /// Whether `node`, parsed from the token at `start`, is an `&&` expression
+    /// that was not wrapped in parentheses
+    fn is_bare_logical_and(&self, node: &AstNode, start: usize) -> bool {
+        match node {
+            AstNode::BinaryExpression { operator: BinaryOperator::LogicalAnd, loc, .. } => {
+                loc.as_ref().is_some_and(|loc| loc.start.offset == self.tokens[start].start)
+            }
+            _ => false,
+        }
+    }
+
     fn equality(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let mut expr = self.comparison()?;
         
         while self.matches(&[
@@ -455,7 +980,7 @@ impl Parser {
             TokenType::StrictEqual,
             TokenType::StrictNotEqual,
         ]) {
-            let operator_token = self.previous().clone();
+            let operator_token = self.advance().clone();
             let operator = match operator_token.token_type {
                 TokenType::Equal => BinaryOperator::Equal,
                 TokenType::NotEqual => BinaryOperator::NotEqual,
@@ -470,7 +995,7 @@ impl Parser {
                 operator,
                 left: Box::new(expr),
                 right,
-                loc: None,
+                loc: self.loc_from(start),
             };
         }
         
@@ -478,6 +1003,7 @@ impl Parser {
     }
 
     fn comparison(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let mut expr = self.term()?;
         
         while self.matches(&[
@@ -488,7 +1014,7 @@ impl Parser {
             TokenType::In,
             TokenType::InstanceOf,
         ]) {
-            let operator_token = self.previous().clone();
+            let operator_token = self.advance().clone();
             let operator = match operator_token.token_type {
                 TokenType::Greater => BinaryOperator::Greater,
                 TokenType::GreaterEqual => BinaryOperator::GreaterEqual,
@@ -505,7 +1031,7 @@ impl Parser {
                 operator,
                 left: Box::new(expr),
                 right,
-                loc: None,
+                loc: self.loc_from(start),
             };
         }
         
@@ -513,10 +1039,11 @@ impl Parser {
     }
 
     fn term(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let mut expr = self.factor()?;
         
         while self.matches(&[TokenType::Minus, TokenType::Plus]) {
-            let operator_token = self.previous().clone();
+            let operator_token = self.advance().clone();
             let operator = match operator_token.token_type {
                 TokenType::Minus => BinaryOperator::Sub,
                 TokenType::Plus => BinaryOperator::Add,
@@ -529,7 +1056,7 @@ impl Parser {
                 operator,
                 left: Box::new(expr),
                 right,
-                loc: None,
+                loc: self.loc_from(start),
             };
         }
         
@@ -537,10 +1064,11 @@ impl Parser {
     }
 
     fn factor(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let mut expr = self.unary()?;
         
         while self.matches(&[TokenType::Divide, TokenType::Multiply, TokenType::Modulo, TokenType::Power]) {
-            let operator_token = self.previous().clone();
+            let operator_token = self.advance().clone();
             let operator = match operator_token.token_type {
                 TokenType::Divide => BinaryOperator::Div,
                 TokenType::Multiply => BinaryOperator::Mul,
@@ -555,7 +1083,7 @@ impl Parser {
                 operator,
                 left: Box::new(expr),
                 right,
-                loc: None,
+                loc: self.loc_from(start),
             };
         }
         
@@ -563,6 +1091,25 @@ impl Parser {
     }
 
     fn unary(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        
+        if self.check(&TokenType::Await) {
+            if !self.in_async {
+                return Err(ParseError::SyntaxError {
+                    message: "await is only valid in async functions".to_string(),
+                    line: self.peek().line,
+                    column: self.peek().column,
+                });
+            }
+            self.advance();
+            let argument = Box::new(self.unary()?);
+            
+            return Ok(AstNode::AwaitExpression {
+                argument,
+                loc: self.loc_from(start),
+            });
+        }
+        
         if self.matches(&[
             TokenType::LogicalNot,
             TokenType::Minus,
@@ -572,7 +1119,7 @@ impl Parser {
             TokenType::Void,
             TokenType::Delete,
         ]) {
-            let operator_token = self.previous().clone();
+            let operator_token = self.advance().clone();
             let operator = match operator_token.token_type {
                 TokenType::LogicalNot => UnaryOperator::Not,
                 TokenType::Minus => UnaryOperator::Minus,
@@ -590,7 +1137,7 @@ impl Parser {
                 operator,
                 argument,
                 prefix: true,
-                loc: None,
+                loc: self.loc_from(start),
             });
         }
         
@@ -598,10 +1145,11 @@ impl Parser {
     }
 
     fn postfix(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let mut expr = self.call()?;
         
         if self.matches(&[TokenType::Increment, TokenType::Decrement]) {
-            let operator_token = self.previous().clone();
+            let operator_token = self.advance().clone();
             let operator = match operator_token.token_type {
                 TokenType::Increment => UpdateOperator::Increment,
                 TokenType::Decrement => UpdateOperator::Decrement,
@@ -612,7 +1160,7 @@ impl Parser {
                 operator,
                 argument: Box::new(expr),
                 prefix: false,
-                loc: None,
+                loc: self.loc_from(start),
             };
         }
         
@@ -620,46 +1168,67 @@ impl Parser {
     }
 
     fn call(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         let mut expr = self.primary()?;
+        let mut in_chain = false;
         
         loop {
-            if self.matches(&[TokenType::LeftParen]) {
-                expr = self.finish_call(expr)?;
-            } else if self.matches(&[TokenType::Dot]) {
-                let property = Box::new(self.expect_identifier()?);
+            // After `?.` the next link may be a call, a computed access or a name
+            let optional = self.check(&TokenType::OptionalChaining);
+            if optional {
+                self.advance();
+                in_chain = true;
+            }
+            
+            if self.check(&TokenType::LeftParen) {
+                self.advance();
+                expr = self.finish_call(expr, optional, start)?;
+            } else if self.check(&TokenType::LeftBracket) {
+                self.advance();
+                let property = Box::new(self.expression()?);
+                self.expect(&TokenType::RightBracket)?;
                 expr = AstNode::MemberExpression {
                     object: Box::new(expr),
                     property,
-                    computed: false,
-                    loc: None,
+                    computed: true,
+                    optional,
+                    loc: self.loc_from(start),
                 };
-            } else if self.matches(&[TokenType::LeftBracket]) {
-                let property = Box::new(self.expression()?);
-                self.expect(&TokenType::RightBracket)?;
+            } else if optional || self.check(&TokenType::Dot) {
+                if !optional {
+                    self.advance();
+                }
+                let property = Box::new(self.expect_property_name()?);
                 expr = AstNode::MemberExpression {
                     object: Box::new(expr),
                     property,
-                    computed: true,
-                    loc: None,
+                    computed: false,
+                    optional,
+                    loc: self.loc_from(start),
                 };
             } else {
                 break;
             }
         }
         
+        if in_chain {
+            expr = AstNode::ChainExpression {
+                expression: Box::new(expr),
+                loc: self.loc_from(start),
+            };
+        }
+        
         Ok(expr)
     }
 
-    fn finish_call(&mut self, callee: AstNode) -> ParseResult<AstNode> {
+    fn finish_call(&mut self, callee: AstNode, optional: bool, start: usize) -> ParseResult<AstNode> {
         let mut arguments = Vec::new();
         
-        if !self.check(&TokenType::RightParen) {
-            loop {
-                arguments.push(self.expression()?);
-                if !self.matches(&[TokenType::Comma]) {
-                    break;
-                }
-                self.advance();
+        // A comma may follow the last argument
+        while !self.check(&TokenType::RightParen) && !self.is_at_end() {
+            arguments.push(self.assignment()?);
+            if !self.check(&TokenType::RightParen) {
+                self.expect(&TokenType::Comma)?;
             }
         }
         
@@ -668,11 +1237,13 @@ impl Parser {
         Ok(AstNode::CallExpression {
             callee: Box::new(callee),
             arguments,
-            loc: None,
+            optional,
+            loc: self.loc_from(start),
         })
     }
 
     fn primary(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         match &self.peek().token_type {
             TokenType::BooleanLiteral(value) => {
                 let value = *value;
@@ -680,7 +1251,7 @@ impl Parser {
                 Ok(AstNode::Literal {
                     value: LiteralValue::Boolean(value),
                     raw: value.to_string(),
-                    loc: None,
+                    loc: self.loc_from(start),
                 })
             }
             TokenType::NullLiteral => {
@@ -688,7 +1259,7 @@ impl Parser {
                 Ok(AstNode::Literal {
                     value: LiteralValue::Null,
                     raw: "null".to_string(),
-                    loc: None,
+                    loc: self.loc_from(start),
                 })
             }
             TokenType::UndefinedLiteral => {
@@ -696,7 +1267,7 @@ impl Parser {
                 Ok(AstNode::Literal {
                     value: LiteralValue::Undefined,
                     raw: "undefined".to_string(),
-                    loc: None,
+                    loc: self.loc_from(start),
                 })
             }
             TokenType::NumericLiteral(value) => {
@@ -705,7 +1276,7 @@ impl Parser {
                 Ok(AstNode::Literal {
                     value: LiteralValue::Number(value),
                     raw,
-                    loc: None,
+                    loc: self.loc_from(start),
                 })
             }
             TokenType::StringLiteral(value) => {
@@ -714,17 +1285,35 @@ impl Parser {
                 Ok(AstNode::Literal {
                     value: LiteralValue::String(value),
                     raw,
-                    loc: None,
+                    loc: self.loc_from(start),
+                })
+            }
+            TokenType::TemplateNoSubstitution(_) | TokenType::TemplateHead(_) => self.template_literal(),
+            TokenType::RegExpLiteral { pattern, flags } => {
+                let value = LiteralValue::RegExp {
+                    pattern: pattern.clone(),
+                    flags: flags.clone(),
+                };
+                let raw = self.advance().lexeme.clone();
+                Ok(AstNode::Literal {
+                    value,
+                    raw,
+                    loc: self.loc_from(start),
                 })
             }
+            TokenType::Identifier(_) if self.peek_ahead(1).token_type == TokenType::Arrow => self.arrow_function(),
             TokenType::Identifier(name) => {
                 let name = name.clone();
                 self.advance();
                 Ok(AstNode::Identifier {
                     name,
-                    loc: None,
+                    loc: self.loc_from(start),
                 })
             }
+            // `(` opens an arrow function's parameters when its matching `)`
+            // is followed by `=>`, and a parenthesized expression or
+            // sequence otherwise
+            TokenType::LeftParen if self.arrow_follows_parens(0) => self.arrow_function(),
             TokenType::LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
@@ -734,23 +1323,41 @@ impl Parser {
             TokenType::LeftBracket => self.array_expression(),
             TokenType::LeftBrace => self.object_expression(),
             TokenType::Function => self.function_expression(),
+            TokenType::Async if self.peek_ahead(1).token_type == TokenType::Function => {
+                self.function_expression()
+            }
+            TokenType::Async if self.is_async_arrow() => self.arrow_function(),
+            // `async` not followed by a function is an ordinary name
+            TokenType::Async => {
+                self.advance();
+                Ok(AstNode::Identifier {
+                    name: "async".to_string(),
+                    loc: self.loc_from(start),
+                })
+            }
+            // `import.meta`, an identifier no script can declare
+            TokenType::Import if self.peek_ahead(1).token_type == TokenType::Dot => {
+                self.advance();
+                self.advance();
+                self.expect_contextual("meta")?;
+                Ok(AstNode::Identifier {
+                    name: "import.meta".to_string(),
+                    loc: self.loc_from(start),
+                })
+            }
             TokenType::This => {
                 self.advance();
                 Ok(AstNode::Identifier {
                     name: "this".to_string(),
-                    loc: None,
+                    loc: self.loc_from(start),
                 })
             }
-            _ => Err(ParseError::UnexpectedToken {
-                expected: "expression".to_string(),
-                found: self.peek().lexeme.clone(),
-                line: self.peek().line,
-                column: self.peek().column,
-            }),
+            _ => Err(self.unexpected("expression")),
         }
     }
 
     fn array_expression(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume '['
         
         let mut elements = Vec::new();
@@ -760,7 +1367,12 @@ impl Parser {
                 elements.push(None); // Hole in sparse array
                 self.advance();
             } else {
-                elements.push(Some(self.expression()?));
+                let element = if self.check(&TokenType::Spread) {
+                    self.spread_element()?
+                } else {
+                    self.assignment()?
+                };
+                elements.push(Some(element));
                 if !self.check(&TokenType::RightBracket) {
                     self.expect(&TokenType::Comma)?;
                 }
@@ -771,11 +1383,12 @@ impl Parser {
         
         Ok(AstNode::ArrayExpression {
             elements,
-            loc: None,
+            loc: self.loc_from(start),
         })
     }
 
     fn object_expression(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
         self.advance(); // consume '{'
         
         let mut properties = Vec::new();
@@ -792,45 +1405,172 @@ impl Parser {
         
         Ok(AstNode::ObjectExpression {
             properties,
-            loc: None,
+            loc: self.loc_from(start),
+        })
+    }
+
+    fn spread_element(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        self.advance(); // consume '...'
+        
+        let argument = Box::new(self.assignment()?);
+        
+        Ok(AstNode::SpreadElement {
+            argument,
+            loc: self.loc_from(start),
         })
     }
 
     fn property(&mut self) -> ParseResult<AstNode> {
-        let key = if self.check_identifier() {
-            Box::new(self.expect_identifier()?)
-        } else if matches!(self.peek().token_type, TokenType::StringLiteral(_) | TokenType::NumericLiteral(_)) {
-            Box::new(self.primary()?)
-        } else if self.matches(&[TokenType::LeftBracket]) {
+        if self.check(&TokenType::Spread) {
+            return self.spread_element();
+        }
+        
+        let start = self.current;
+        
+        // `async`, `get` and `set` are only modifiers when a key follows them;
+        // otherwise they are the key itself (`{get: 1}`, `{async() {}}`)
+        let followed_by_key = |parser: &Self| {
+            !matches!(
+                parser.peek_ahead(1).token_type,
+                TokenType::LeftParen | TokenType::Colon | TokenType::Comma | TokenType::RightBrace | TokenType::Assign
+            )
+        };
+        
+        let is_async = self.check(&TokenType::Async) && followed_by_key(self);
+        if is_async {
             self.advance();
-            let key = Box::new(self.expression()?);
-            self.expect(&TokenType::RightBracket)?;
-            key
-        } else {
-            return Err(ParseError::UnexpectedToken {
-                expected: "property key".to_string(),
-                found: self.peek().lexeme.clone(),
-                line: self.peek().line,
-                column: self.peek().column,
+        }
+        let is_generator = self.generator_star();
+        
+        let mut kind = PropertyKind::Init;
+        if let TokenType::Identifier(name) = &self.peek().token_type {
+            if (name == "get" || name == "set") && !is_async && !is_generator && followed_by_key(self) {
+                kind = if name == "get" { PropertyKind::Get } else { PropertyKind::Set };
+                self.advance();
+            }
+        }
+        
+        let is_identifier = self.check_identifier();
+        let (key, computed) = self.class_member_key()?;
+        let key = Box::new(key);
+        
+        if self.check(&TokenType::LeftParen) {
+            let value_start = self.current;
+            self.advance();
+            let params = self.parameter_list()?;
+            self.expect(&TokenType::RightParen)?;
+            
+            match kind {
+                PropertyKind::Get if !params.is_empty() => {
+                    return Err(ParseError::SyntaxError {
+                        message: "Getter must not have parameters".to_string(),
+                        line: self.tokens[start].line,
+                        column: self.tokens[start].column,
+                    });
+                }
+                PropertyKind::Set if params.len() != 1 => {
+                    return Err(ParseError::SyntaxError {
+                        message: "Setter must have exactly one parameter".to_string(),
+                        line: self.tokens[start].line,
+                        column: self.tokens[start].column,
+                    });
+                }
+                _ => {}
+            }
+            
+            let body = Box::new(self.function_body(is_async)?);
+            let value = Box::new(AstNode::FunctionExpression {
+                id: None,
+                params,
+                body,
+                is_async,
+                is_generator,
+                loc: self.loc_from(value_start),
             });
-        };
+            
+            let method = kind == PropertyKind::Init;
+            return Ok(AstNode::Property {
+                key,
+                value,
+                kind: if method { PropertyKind::Method } else { kind },
+                method,
+                shorthand: false,
+                computed,
+                loc: self.loc_from(start),
+            });
+        }
+        
+        if kind != PropertyKind::Init || is_async || is_generator {
+            return Err(self.unexpected("("));
+        }
         
-        self.expect(&TokenType::Colon)?;
-        let value = Box::new(self.expression()?);
+        if self.check(&TokenType::Colon) {
+            self.advance();
+            let value = Box::new(self.assignment()?);
+            return Ok(AstNode::Property {
+                key,
+                value,
+                kind,
+                method: false,
+                shorthand: false,
+                computed,
+                loc: self.loc_from(start),
+            });
+        }
+        
+        // Shorthand `{x}` reads the binding of the same name
+        if !is_identifier || computed {
+            return Err(self.unexpected(":"));
+        }
         
         Ok(AstNode::Property {
+            value: key.clone(),
             key,
-            value,
-            kind: PropertyKind::Init,
+            kind,
             method: false,
-            shorthand: false,
+            shorthand: true,
             computed: false,
-            loc: None,
+            loc: self.loc_from(start),
+        })
+    }
+
+    fn template_literal(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        let mut quasis = Vec::new();
+        let mut expressions = Vec::new();
+        
+        loop {
+            let quasi_start = self.current;
+            let token = self.peek().clone();
+            let (text, done) = match token.token_type {
+                TokenType::TemplateNoSubstitution(text) | TokenType::TemplateTail(text) => (text, true),
+                TokenType::TemplateHead(text) | TokenType::TemplateMiddle(text) => (text, false),
+                _ => return Err(self.unexpected("}")),
+            };
+            self.advance();
+            quasis.push(AstNode::Literal {
+                value: LiteralValue::String(text),
+                raw: token.lexeme,
+                loc: self.loc_from(quasi_start),
+            });
+            if done {
+                break;
+            }
+            expressions.push(self.expression()?);
+        }
+        
+        Ok(AstNode::TemplateLiteral {
+            quasis,
+            expressions,
+            loc: self.loc_from(start),
         })
     }
 
     fn function_expression(&mut self) -> ParseResult<AstNode> {
-        self.advance(); // consume 'function'
+        let start = self.current;
+        let is_async = self.function_prefix();
+        let is_generator = self.generator_star();
         
         let id = if self.check_identifier() {
             Some(Box::new(self.expect_identifier()?))
@@ -842,28 +1582,116 @@ impl Parser {
         let params = self.parameter_list()?;
         self.expect(&TokenType::RightParen)?;
         
-        let body = Box::new(self.block_statement()?);
+        let body = Box::new(self.function_body(is_async)?);
         
         Ok(AstNode::FunctionExpression {
             id,
             params,
             body,
-            is_async: false,
-            is_generator: false,
-            loc: None,
+            is_async,
+            is_generator,
+            loc: self.loc_from(start),
+        })
+    }
+
+    /// Consume `function` or `async function`, returning whether it was async
+    fn function_prefix(&mut self) -> bool {
+        let is_async = self.check(&TokenType::Async);
+        if is_async {
+            self.advance();
+        }
+        self.advance(); // consume 'function'
+        is_async
+    }
+
+    fn generator_star(&mut self) -> bool {
+        let is_generator = self.check(&TokenType::Multiply);
+        if is_generator {
+            self.advance();
+        }
+        is_generator
+    }
+
+    /// Parse a function's block body with `await` enabled for async functions
+    fn function_body(&mut self, is_async: bool) -> ParseResult<AstNode> {
+        let outer = std::mem::replace(&mut self.in_async, is_async);
+        let body = self.block_statement();
+        self.in_async = outer;
+        body
+    }
+
+    /// `x => ...` or `(a, b) => ...`, either optionally `async`
+    fn arrow_function(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        let is_async = self.check(&TokenType::Async);
+        if is_async {
+            self.advance();
+        }
+        
+        let params = if self.check(&TokenType::LeftParen) {
+            self.advance();
+            let params = self.parameter_list()?;
+            self.expect(&TokenType::RightParen)?;
+            params
+        } else {
+            vec![self.expect_identifier()?]
+        };
+        self.expect(&TokenType::Arrow)?;
+        
+        let outer = std::mem::replace(&mut self.in_async, is_async);
+        let body = if self.check(&TokenType::LeftBrace) {
+            self.block_statement()
+        } else {
+            self.assignment()
+        };
+        self.in_async = outer;
+        
+        Ok(AstNode::ArrowFunctionExpression {
+            params,
+            body: Box::new(body?),
+            is_async,
+            loc: self.loc_from(start),
         })
     }
 
+    /// Whether the tokens at `async` begin an async arrow function
+    fn is_async_arrow(&self) -> bool {
+        match self.peek_ahead(1).token_type {
+            TokenType::Identifier(_) => self.peek_ahead(2).token_type == TokenType::Arrow,
+            TokenType::LeftParen => self.arrow_follows_parens(1),
+            _ => false,
+        }
+    }
+
+    /// Whether the `(` `offset` tokens ahead is closed by a `)` followed by
+    /// `=>`, making the parentheses an arrow function's parameter list
+    fn arrow_follows_parens(&self, offset: usize) -> bool {
+        let mut depth = 0;
+        let mut offset = offset;
+        loop {
+            match self.peek_ahead(offset).token_type {
+                TokenType::LeftParen => depth += 1,
+                TokenType::RightParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.peek_ahead(offset + 1).token_type == TokenType::Arrow;
+                    }
+                }
+                TokenType::EOF => return false,
+                _ => {}
+            }
+            offset += 1;
+        }
+    }
+
     fn parameter_list(&mut self) -> ParseResult<Vec<AstNode>> {
         let mut params = Vec::new();
         
-        if !self.check(&TokenType::RightParen) {
-            loop {
-                params.push(self.expect_identifier()?);
-                if !self.matches(&[TokenType::Comma]) {
-                    break;
-                }
-                self.advance();
+        // A comma may follow the last parameter
+        while !self.check(&TokenType::RightParen) && !self.is_at_end() {
+            params.push(self.expect_identifier()?);
+            if !self.check(&TokenType::RightParen) {
+                self.expect(&TokenType::Comma)?;
             }
         }
         
@@ -871,6 +1699,32 @@ impl Parser {
     }
 
     // Helper methods
+
+    /// Span from the token at index `start` through the last consumed token
+    fn loc_from(&self, start: usize) -> Option<SourceLocation> {
+        let first = self.tokens.get(start)?;
+        let last = &self.tokens[self.current.saturating_sub(1).max(start)];
+        
+        Some(SourceLocation {
+            start: self.location_at(first.start),
+            end: self.location_at(last.end.max(first.start)),
+        })
+    }
+
+    /// Line and column (both 1-based, columns in characters) of a byte offset
+    fn location_at(&self, offset: usize) -> Location {
+        let line = self.line_starts.partition_point(|&line_start| line_start <= offset);
+        let line_start = self.line_starts[line - 1];
+        let column = self.source
+            .get(line_start..offset)
+            .map_or(0, |text| text.chars().count());
+        
+        Location {
+            line,
+            column: column + 1,
+            offset,
+        }
+    }
     
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
@@ -891,6 +1745,11 @@ impl Parser {
         &self.tokens[self.current - 1]
     }
 
+    fn peek_ahead(&self, offset: usize) -> &Token {
+        let index = (self.current + offset).min(self.tokens.len() - 1);
+        &self.tokens[index]
+    }
+
     fn check(&self, token_type: &TokenType) -> bool {
         if self.is_at_end() {
             false
@@ -908,34 +1767,73 @@ impl Parser {
         false
     }
 
+    /// The error for finding the current token where `expected` should be:
+    /// `UnexpectedEof` at the end of the input, which more input could fix
+    fn unexpected(&self, expected: impl Into<String>) -> ParseError {
+        let token = self.peek();
+        if token.token_type == TokenType::EOF {
+            ParseError::UnexpectedEof { expected: expected.into(), line: token.line, column: token.column }
+        } else {
+            ParseError::UnexpectedToken {
+                expected: expected.into(),
+                found: token.lexeme.clone(),
+                line: token.line,
+                column: token.column,
+            }
+        }
+    }
+
     fn expect(&mut self, token_type: &TokenType) -> ParseResult<&Token> {
         if self.check(token_type) {
             Ok(self.advance())
         } else {
-            Err(ParseError::UnexpectedToken {
-                expected: format!("{:?}", token_type),
-                found: self.peek().lexeme.clone(),
-                line: self.peek().line,
-                column: self.peek().column,
-            })
+            Err(self.unexpected(format!("{:?}", token_type)))
         }
     }
 
     fn expect_identifier(&mut self) -> ParseResult<AstNode> {
-        if let TokenType::Identifier(name) = &self.peek().token_type {
-            let name = name.clone();
+        let start = self.current;
+        // `async` is only a keyword in front of a function
+        let name = match &self.peek().token_type {
+            TokenType::Identifier(name) => Some(name.clone()),
+            TokenType::Async => Some("async".to_string()),
+            _ => None,
+        };
+        if let Some(name) = name {
             self.advance();
             Ok(AstNode::Identifier {
                 name,
-                loc: None,
+                loc: self.loc_from(start),
             })
         } else {
-            Err(ParseError::UnexpectedToken {
-                expected: "identifier".to_string(),
-                found: self.peek().lexeme.clone(),
-                line: self.peek().line,
-                column: self.peek().column,
-            })
+            Err(self.unexpected("identifier"))
+        }
+    }
+
+    /// An identifier or a keyword used as a property name, e.g. `a.delete`
+    fn expect_property_name(&mut self) -> ParseResult<AstNode> {
+        let start = self.current;
+        let token = self.peek().clone();
+        let name = match token.token_type {
+            TokenType::Identifier(name) => name,
+            _ if token.lexeme.chars().next().is_some_and(|c| c.is_alphabetic()) => token.lexeme,
+            _ => return Err(self.unexpected("property name")),
+        };
+        
+        self.advance();
+        Ok(AstNode::Identifier {
+            name,
+            loc: self.loc_from(start),
+        })
+    }
+
+    /// An identifier that acts as a keyword in one place, like `from`
+    fn expect_contextual(&mut self, word: &str) -> ParseResult<()> {
+        if matches!(&self.peek().token_type, TokenType::Identifier(name) if name == word) {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.unexpected(word))
         }
     }
 
@@ -949,3 +1847,177 @@ impl Parser {
         }
     }
 }
+
+/// Byte offsets of line starts, treating the same characters as line
+/// terminators as the lexer does
+fn line_starts(source: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            source.char_indices()
+                .filter(|&(_, c)| matches!(c, '\n' | '\u{2028}' | '\u{2029}'))
+                .map(|(i, c)| i + c.len_utf8()),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ParseResult<Program> {
+        Parser::new().parse(source)
+    }
+
+    /// The expression of the single expression statement in `source`
+    fn expression(source: &str) -> AstNode {
+        let program = parse(source).unwrap();
+        match program.body.as_slice() {
+            [AstNode::ExpressionStatement { expression, .. }] => (**expression).clone(),
+            other => panic!("expected one expression statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nullish_cannot_mix_with_logical_operators() {
+        for source in ["a ?? b || c;", "a || b ?? c;", "a ?? b && c;", "a && b ?? c;"] {
+            assert!(
+                matches!(parse(source), Err(ParseError::SyntaxError { .. })),
+                "{} should not parse",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn nullish_mixes_with_logical_operators_in_parentheses() {
+        match expression("(a ?? b) || c;") {
+            AstNode::BinaryExpression { operator: BinaryOperator::LogicalOr, left, .. } => {
+                assert!(matches!(*left, AstNode::BinaryExpression { operator: BinaryOperator::NullishCoalescing, .. }));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match expression("a ?? (b && c);") {
+            AstNode::BinaryExpression { operator: BinaryOperator::NullishCoalescing, right, .. } => {
+                assert!(matches!(*right, AstNode::BinaryExpression { operator: BinaryOperator::LogicalAnd, .. }));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("(a && b) ?? c;").is_ok());
+        assert!(parse("a ?? b ?? c;").is_ok());
+    }
+
+    #[test]
+    fn array_holes_and_trailing_comma() {
+        match expression("[,,1,];") {
+            AstNode::ArrayExpression { elements, .. } => {
+                assert_eq!(elements.len(), 3);
+                assert!(elements[0].is_none() && elements[1].is_none());
+                assert!(matches!(elements[2], Some(AstNode::Literal { .. })));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match expression("[1,,];") {
+            AstNode::ArrayExpression { elements, .. } => assert_eq!(elements.len(), 2),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn trailing_comma_in_call_arguments() {
+        match expression("f(a,);") {
+            AstNode::CallExpression { arguments, .. } => assert_eq!(arguments.len(), 1),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("f(,);").is_err());
+        assert!(parse("f(a,,);").is_err());
+    }
+
+    #[test]
+    fn trailing_comma_in_parameter_lists() {
+        let program = parse("function g(a,){}").unwrap();
+        match program.body.as_slice() {
+            [AstNode::FunctionDeclaration { params, .. }] => assert_eq!(params.len(), 1),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("(function (a, b,) {});").is_ok());
+        assert!(parse("function h(,){}").is_err());
+    }
+
+    #[test]
+    fn trailing_comma_in_object_literals() {
+        match expression("({ a: 1, b: 2, });") {
+            AstNode::ObjectExpression { properties, .. } => assert_eq!(properties.len(), 2),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("({ a: 1,, });").is_err());
+    }
+
+    #[test]
+    fn export_declarations() {
+        let program = parse("export default 1 + 2; export const a = 1; export function f() {} export { a, f };").unwrap();
+        assert_eq!(program.source_type, SourceType::Module);
+        match program.body.as_slice() {
+            [
+                AstNode::ExportDeclaration { declaration: Some(default), specifiers: default_name, .. },
+                AstNode::ExportDeclaration { declaration: Some(constant), specifiers: no_names, .. },
+                AstNode::ExportDeclaration { declaration: Some(function), .. },
+                AstNode::ExportDeclaration { declaration: None, specifiers, .. },
+            ] => {
+                assert!(matches!(default.as_ref(), AstNode::BinaryExpression { .. }));
+                assert!(matches!(default_name.as_slice(), [AstNode::Identifier { name, .. }] if name == "default"));
+                assert!(matches!(constant.as_ref(), AstNode::VariableDeclaration { .. }));
+                assert!(no_names.is_empty());
+                assert!(matches!(function.as_ref(), AstNode::FunctionDeclaration { .. }));
+                assert_eq!(specifiers.len(), 2);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("export { a as b };").is_err());
+        assert!(parse("export { a } from \"./m.js\";").is_err());
+        assert!(parse("export 1;").is_err());
+    }
+
+    #[test]
+    fn import_meta_is_an_expression() {
+        match expression("import.meta.hot;") {
+            AstNode::MemberExpression { object, .. } => {
+                assert!(matches!(object.as_ref(), AstNode::Identifier { name, .. } if name == "import.meta"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("import.other;").is_err());
+    }
+
+    #[test]
+    fn slash_after_a_parenthesis_or_brace_depends_on_what_it_closes() {
+        let regex_statement = |source: &str| {
+            let program = parse(source).unwrap();
+            let last = program.body.last().unwrap().clone();
+            let statement = match last {
+                AstNode::IfStatement { consequent, .. } | AstNode::WhileStatement { body: consequent, .. } => *consequent,
+                other => other,
+            };
+            match statement {
+                AstNode::ExpressionStatement { expression, .. } => match *expression {
+                    AstNode::CallExpression { callee, .. } => match *callee {
+                        AstNode::MemberExpression { object, .. } => {
+                            matches!(*object, AstNode::Literal { value: LiteralValue::RegExp { .. }, .. })
+                        }
+                        _ => false,
+                    },
+                    _ => false,
+                },
+                _ => false,
+            }
+        };
+        assert!(regex_statement("if (a) /x/.test(s);"));
+        assert!(regex_statement("while (a) /x/.exec(s);"));
+        assert!(regex_statement("{ a; } /x/.test(s);"));
+        assert!(regex_statement("if (a) { b; } /x/.test(s);"));
+        assert!(regex_statement("function f() {} /x/.test(s);"));
+
+        for division in ["(a) / 2 / 1;", "f(a) / 2 / 1;", "x = {} / 2 / 1;", "x = { a: (1) } / 2 / 1;"] {
+            assert!(parse(division).is_ok(), "{}", division);
+        }
+    }
+}
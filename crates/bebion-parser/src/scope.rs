@@ -0,0 +1,483 @@
+//! Scope analysis
+//!
+//! Resolves each identifier reference in a program to the declaration it
+//! refers to. `var` declarations belong to the enclosing function; `let`,
+//! `const`, classes and function declarations belong to the enclosing block.
+//! Declarations are hoisted, so a reference resolves to a binding declared
+//! anywhere in a scope on its chain. References that resolve to nothing are
+//! globals. The analysis also reports the declaration errors the parser
+//! does not catch: lexical redeclarations and assignments to constants.
+
+use crate::ast::{AstNode, LiteralValue, Program, SourceLocation, VarKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+pub type ScopeId = usize;
+pub type BindingId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScopeKind {
+    Global,
+    Function,
+    Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub parent: Option<ScopeId>,
+    names: HashMap<String, BindingId>,
+}
+
+impl Scope {
+    /// The binding this scope declares for `name`
+    pub fn lookup(&self, name: &str) -> Option<BindingId> {
+        self.names.get(name).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BindingKind {
+    Var,
+    Let,
+    Const,
+    Function,
+    Class,
+    Parameter,
+    CatchParameter,
+}
+
+impl BindingKind {
+    /// Whether a second declaration of the name in the same scope is an error
+    pub fn is_lexical(self) -> bool {
+        matches!(self, BindingKind::Let | BindingKind::Const | BindingKind::Class)
+    }
+}
+
+impl fmt::Display for BindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keyword = match self {
+            BindingKind::Var => "var",
+            BindingKind::Let => "let",
+            BindingKind::Const => "const",
+            BindingKind::Function => "function",
+            BindingKind::Class => "class",
+            BindingKind::Parameter => "parameter",
+            BindingKind::CatchParameter => "catch parameter",
+        };
+        f.write_str(keyword)
+    }
+}
+
+/// What a binding is known to hold, from its declaration alone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ValueKind {
+    Number,
+    String,
+    Boolean,
+    Null,
+    Undefined,
+    RegExp,
+    Array,
+    Object,
+    Function { params: Vec<String>, is_async: bool },
+    Class,
+    Unknown,
+}
+
+impl ValueKind {
+    fn of_expression(node: &AstNode) -> ValueKind {
+        match node {
+            AstNode::Literal { value, .. } => match value {
+                LiteralValue::Number(_) => ValueKind::Number,
+                LiteralValue::String(_) => ValueKind::String,
+                LiteralValue::Boolean(_) => ValueKind::Boolean,
+                LiteralValue::Null => ValueKind::Null,
+                LiteralValue::Undefined => ValueKind::Undefined,
+                LiteralValue::RegExp { .. } => ValueKind::RegExp,
+            },
+            AstNode::TemplateLiteral { .. } => ValueKind::String,
+            AstNode::ArrayExpression { .. } => ValueKind::Array,
+            AstNode::ObjectExpression { .. } => ValueKind::Object,
+            AstNode::FunctionExpression { params, is_async, .. }
+            | AstNode::ArrowFunctionExpression { params, is_async, .. } => ValueKind::Function {
+                params: param_names(params),
+                is_async: *is_async,
+            },
+            AstNode::ClassDeclaration { .. } => ValueKind::Class,
+            _ => ValueKind::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ValueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueKind::Number => f.write_str("number"),
+            ValueKind::String => f.write_str("string"),
+            ValueKind::Boolean => f.write_str("boolean"),
+            ValueKind::Null => f.write_str("null"),
+            ValueKind::Undefined => f.write_str("undefined"),
+            ValueKind::RegExp => f.write_str("RegExp"),
+            ValueKind::Array => f.write_str("array"),
+            ValueKind::Object => f.write_str("object"),
+            ValueKind::Function { params, is_async } => {
+                let prefix = if *is_async { "async " } else { "" };
+                write!(f, "{}function({})", prefix, params.join(", "))
+            }
+            ValueKind::Class => f.write_str("class"),
+            ValueKind::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub name: String,
+    pub kind: BindingKind,
+    pub value: ValueKind,
+    pub scope: ScopeId,
+    /// The declaring identifier
+    pub loc: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reference {
+    pub name: String,
+    pub scope: ScopeId,
+    /// The declaration it resolves to, `None` for globals
+    pub binding: Option<BindingId>,
+    /// Whether the reference is assigned to
+    pub is_write: bool,
+    pub loc: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SemanticError {
+    pub message: String,
+    pub loc: Option<SourceLocation>,
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.loc {
+            Some(loc) => write!(f, "{} at {}:{}", self.message, loc.start.line, loc.start.column),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScopeAnalysis {
+    scopes: Vec<Scope>,
+    bindings: Vec<Binding>,
+    references: Vec<Reference>,
+    errors: Vec<SemanticError>,
+}
+
+impl ScopeAnalysis {
+    pub fn analyze(program: &Program) -> Self {
+        let mut analyzer = Analyzer {
+            analysis: ScopeAnalysis {
+                scopes: Vec::new(),
+                bindings: Vec::new(),
+                references: Vec::new(),
+                errors: Vec::new(),
+            },
+            current: 0,
+        };
+
+        analyzer.push_scope(ScopeKind::Global);
+        for statement in &program.body {
+            analyzer.node(statement);
+        }
+        analyzer.resolve();
+        analyzer.analysis
+    }
+
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    pub fn binding(&self, id: BindingId) -> &Binding {
+        &self.bindings[id]
+    }
+
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    pub fn errors(&self) -> &[SemanticError] {
+        &self.errors
+    }
+
+    /// References that resolve to the binding `id`
+    pub fn references_to(&self, id: BindingId) -> impl Iterator<Item = &Reference> + '_ {
+        self.references.iter().filter(move |reference| reference.binding == Some(id))
+    }
+
+    /// The binding declared or referred to by the identifier at the byte
+    /// `offset`
+    pub fn binding_at(&self, offset: usize) -> Option<BindingId> {
+        let contains = |loc: &Option<SourceLocation>| loc.as_ref().is_some_and(|loc| loc.contains(offset));
+
+        self.bindings
+            .iter()
+            .position(|binding| contains(&binding.loc))
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|reference| contains(&reference.loc))
+                    .and_then(|reference| reference.binding)
+            })
+    }
+}
+
+struct Analyzer {
+    analysis: ScopeAnalysis,
+    current: ScopeId,
+}
+
+impl Analyzer {
+    fn push_scope(&mut self, kind: ScopeKind) {
+        let parent = self.analysis.scopes.len().checked_sub(1).map(|_| self.current);
+        self.analysis.scopes.push(Scope {
+            kind,
+            parent,
+            names: HashMap::new(),
+        });
+        self.current = self.analysis.scopes.len() - 1;
+    }
+
+    fn pop_scope(&mut self) {
+        self.current = self.analysis.scopes[self.current].parent.unwrap_or(0);
+    }
+
+    /// The scope `var` declarations made here belong to
+    fn var_scope(&self) -> ScopeId {
+        let mut scope = self.current;
+        while self.analysis.scopes[scope].kind == ScopeKind::Block {
+            scope = self.analysis.scopes[scope].parent.unwrap_or(0);
+        }
+        scope
+    }
+
+    fn declare(&mut self, id: &AstNode, kind: BindingKind, value: ValueKind) {
+        let AstNode::Identifier { name, loc } = id else {
+            return;
+        };
+        let scope = match kind {
+            BindingKind::Var => self.var_scope(),
+            _ => self.current,
+        };
+
+        if let Some(&existing) = self.analysis.scopes[scope].names.get(name) {
+            if kind.is_lexical() || self.analysis.bindings[existing].kind.is_lexical() {
+                self.analysis.errors.push(SemanticError {
+                    message: format!("Identifier '{}' has already been declared", name),
+                    loc: loc.clone(),
+                });
+                return;
+            }
+        }
+
+        let binding = self.analysis.bindings.len();
+        self.analysis.bindings.push(Binding {
+            name: name.clone(),
+            kind,
+            value,
+            scope,
+            loc: loc.clone(),
+        });
+        self.analysis.scopes[scope].names.entry(name.clone()).or_insert(binding);
+    }
+
+    fn reference(&mut self, node: &AstNode, is_write: bool) {
+        match node {
+            AstNode::Identifier { name, loc } => self.analysis.references.push(Reference {
+                name: name.clone(),
+                scope: self.current,
+                binding: None,
+                is_write,
+                loc: loc.clone(),
+            }),
+            _ => self.node(node),
+        }
+    }
+
+    fn function(&mut self, id: Option<&AstNode>, params: &[AstNode], body: &AstNode, value: ValueKind) {
+        self.push_scope(ScopeKind::Function);
+        // A function expression's own name is visible only inside it
+        if let Some(id) = id {
+            self.declare(id, BindingKind::Function, value);
+        }
+        for param in params {
+            self.declare(param, BindingKind::Parameter, ValueKind::Unknown);
+        }
+        match body {
+            // The body block shares the function's scope, so a `let` cannot
+            // shadow a parameter
+            AstNode::BlockStatement { body, .. } => {
+                for statement in body {
+                    self.node(statement);
+                }
+            }
+            expression => self.node(expression),
+        }
+        self.pop_scope();
+    }
+
+    fn node(&mut self, node: &AstNode) {
+        match node {
+            AstNode::Identifier { .. } => self.reference(node, false),
+            AstNode::BlockStatement { body, .. } => {
+                self.push_scope(ScopeKind::Block);
+                for statement in body {
+                    self.node(statement);
+                }
+                self.pop_scope();
+            }
+            AstNode::VariableDeclaration { declarations, kind, .. } => {
+                let kind = match kind {
+                    VarKind::Var => BindingKind::Var,
+                    VarKind::Let => BindingKind::Let,
+                    VarKind::Const => BindingKind::Const,
+                };
+                for declaration in declarations {
+                    if let AstNode::VariableDeclarator { id, init, .. } = declaration {
+                        let value = init.as_deref().map_or(ValueKind::Undefined, ValueKind::of_expression);
+                        self.declare(id, kind, value);
+                        if let Some(init) = init {
+                            self.node(init);
+                        }
+                    }
+                }
+            }
+            AstNode::FunctionDeclaration { id, params, body, is_async, .. } => {
+                let value = ValueKind::Function {
+                    params: param_names(params),
+                    is_async: *is_async,
+                };
+                if let Some(id) = id {
+                    self.declare(id, BindingKind::Function, value.clone());
+                }
+                self.function(None, params, body, value);
+            }
+            AstNode::FunctionExpression { id, params, body, .. } => {
+                self.function(id.as_deref(), params, body, ValueKind::of_expression(node));
+            }
+            AstNode::ArrowFunctionExpression { params, body, .. } => {
+                self.function(None, params, body, ValueKind::Unknown);
+            }
+            AstNode::ClassDeclaration { id, superclass, body, decorators, .. } => {
+                if let Some(id) = id {
+                    self.declare(id, BindingKind::Class, ValueKind::Class);
+                }
+                for child in decorators.iter().chain(superclass.as_deref()) {
+                    self.node(child);
+                }
+                self.node(body);
+            }
+            AstNode::MethodDefinition { key, value, computed, decorators, .. } => {
+                for decorator in decorators {
+                    self.node(decorator);
+                }
+                if *computed {
+                    self.node(key);
+                }
+                self.node(value);
+            }
+            AstNode::PropertyDefinition { key, value, computed, decorators, .. } => {
+                for decorator in decorators {
+                    self.node(decorator);
+                }
+                if *computed {
+                    self.node(key);
+                }
+                if let Some(value) = value {
+                    self.node(value);
+                }
+            }
+            AstNode::Property { key, value, computed, .. } => {
+                if *computed {
+                    self.node(key);
+                }
+                self.node(value);
+            }
+            AstNode::MemberExpression { object, property, computed, .. } => {
+                self.node(object);
+                if *computed {
+                    self.node(property);
+                }
+            }
+            AstNode::AssignmentExpression { left, right, .. } => {
+                self.reference(left, true);
+                self.node(right);
+            }
+            AstNode::UpdateExpression { argument, .. } => self.reference(argument, true),
+            AstNode::ForStatement { .. } => {
+                // Declarations in the head are scoped to the loop
+                self.push_scope(ScopeKind::Block);
+                for child in node.children() {
+                    self.node(child);
+                }
+                self.pop_scope();
+            }
+            AstNode::CatchClause { param, body, .. } => {
+                self.push_scope(ScopeKind::Block);
+                if let Some(param) = param {
+                    self.declare(param, BindingKind::CatchParameter, ValueKind::Unknown);
+                }
+                self.node(body);
+                self.pop_scope();
+            }
+            // Labels are not variables
+            AstNode::BreakStatement { .. } | AstNode::ContinueStatement { .. } => {}
+            _ => {
+                for child in node.children() {
+                    self.node(child);
+                }
+            }
+        }
+    }
+
+    /// Resolve every reference once all hoisted declarations are known
+    fn resolve(&mut self) {
+        let ScopeAnalysis { scopes, bindings, references, errors } = &mut self.analysis;
+
+        for reference in references.iter_mut() {
+            let mut scope = Some(reference.scope);
+            while let Some(id) = scope {
+                if let Some(binding) = scopes[id].lookup(&reference.name) {
+                    reference.binding = Some(binding);
+                    break;
+                }
+                scope = scopes[id].parent;
+            }
+
+            if let Some(binding) = reference.binding {
+                if reference.is_write && bindings[binding].kind == BindingKind::Const {
+                    errors.push(SemanticError {
+                        message: format!("Assignment to constant variable '{}'", reference.name),
+                        loc: reference.loc.clone(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn param_names(params: &[AstNode]) -> Vec<String> {
+    params
+        .iter()
+        .map(|param| match param {
+            AstNode::Identifier { name, .. } => name.clone(),
+            _ => "_".to_string(),
+        })
+        .collect()
+}
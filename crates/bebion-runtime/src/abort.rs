@@ -0,0 +1,89 @@
+//! Host side of `AbortSignal`
+//!
+//! Every signal a script creates has an `AbortSignal` handle the host can
+//! hold on to outside the heap. Host operations that take a `signal` option
+//! check it before starting and race their work against `aborted`, which
+//! completes once the script aborts the signal.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// A shareable view of a script's abort signal
+#[derive(Clone, Default)]
+pub struct AbortSignal {
+    state: Arc<Mutex<AbortState>>,
+}
+
+#[derive(Default)]
+struct AbortState {
+    /// The abort reason, described, once the signal is aborted
+    reason: Option<String>,
+    /// Tasks waiting in `aborted`
+    wakers: Vec<Waker>,
+}
+
+impl AbortSignal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.state.lock().unwrap().reason.is_some()
+    }
+
+    /// The abort reason as the script would see it printed
+    pub fn reason(&self) -> Option<String> {
+        self.state.lock().unwrap().reason.clone()
+    }
+
+    /// Mark the signal aborted and wake every task waiting on it. Aborting
+    /// twice keeps the first reason.
+    pub(crate) fn abort(&self, reason: String) {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            if state.reason.is_some() {
+                return;
+            }
+            state.reason = Some(reason);
+            std::mem::take(&mut state.wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Completes with the reason once the signal is aborted
+    pub fn aborted(&self) -> Aborted {
+        Aborted { signal: self.clone() }
+    }
+}
+
+impl fmt::Debug for AbortSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AbortSignal").field("reason", &self.reason()).finish()
+    }
+}
+
+/// Future returned by `AbortSignal::aborted`
+#[derive(Debug)]
+pub struct Aborted {
+    signal: AbortSignal,
+}
+
+impl Future for Aborted {
+    type Output = String;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<String> {
+        let mut state = self.signal.state.lock().unwrap();
+        if let Some(reason) = &state.reason {
+            return Poll::Ready(reason.clone());
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,312 @@
+//! Intrinsic objects and built-in functions
+//!
+//! Built-in functions are function objects without bytecode: calling one
+//! runs the Rust function the VM registered for its handle, with the
+//! receiver and the arguments.
+
+mod abort;
+mod array;
+mod collections;
+mod number;
+mod regexp;
+mod string;
+mod symbol;
+mod typed_array;
+
+pub(crate) use abort::{abort, add_listener, create_signal, error_object, signal_handle, timeout_signal, SignalRecord};
+pub(crate) use typed_array::{array_buffer_from_bytes, byte_window, uint8_array_from_bytes, View};
+
+use crate::vm::{property_key, VirtualMachine};
+use crate::{RuntimeError, RuntimeResult, Symbol, Value};
+use bebion_gc::{GarbageCollector, GcHandle, GcObjectType};
+use std::collections::HashMap;
+
+/// A built-in function: receives the VM, `this` and the arguments
+pub(crate) type Builtin = fn(&mut VirtualMachine, &Value, &[Value]) -> RuntimeResult<Value>;
+
+/// The prototype objects values inherit from
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Intrinsics {
+    pub object_prototype: GcHandle,
+    pub array_prototype: GcHandle,
+    pub function_prototype: GcHandle,
+    pub string_prototype: GcHandle,
+    pub number_prototype: GcHandle,
+    pub regexp_prototype: GcHandle,
+    pub symbol_prototype: GcHandle,
+    pub map_prototype: GcHandle,
+    pub set_prototype: GcHandle,
+    pub weak_map_prototype: GcHandle,
+    pub weak_set_prototype: GcHandle,
+    pub array_buffer_prototype: GcHandle,
+    /// Shared by every kind of typed array
+    pub typed_array_prototype: GcHandle,
+    pub data_view_prototype: GcHandle,
+    pub abort_controller_prototype: GcHandle,
+    pub abort_signal_prototype: GcHandle,
+}
+
+impl Intrinsics {
+    /// Allocate the prototypes, rooted so they outlive every object that
+    /// inherits from them
+    pub fn allocate(gc: &mut GarbageCollector) -> Self {
+        let object_prototype = gc.allocate_object(HashMap::new());
+        let inheriting_object = |gc: &mut GarbageCollector| {
+            let handle = gc.allocate_object(HashMap::new());
+            gc.set_prototype(handle, Some(object_prototype));
+            handle
+        };
+        let intrinsics = Self {
+            object_prototype,
+            array_prototype: inheriting_object(gc),
+            function_prototype: inheriting_object(gc),
+            string_prototype: inheriting_object(gc),
+            number_prototype: inheriting_object(gc),
+            regexp_prototype: inheriting_object(gc),
+            symbol_prototype: inheriting_object(gc),
+            map_prototype: inheriting_object(gc),
+            set_prototype: inheriting_object(gc),
+            weak_map_prototype: inheriting_object(gc),
+            weak_set_prototype: inheriting_object(gc),
+            array_buffer_prototype: inheriting_object(gc),
+            typed_array_prototype: inheriting_object(gc),
+            data_view_prototype: inheriting_object(gc),
+            abort_controller_prototype: inheriting_object(gc),
+            abort_signal_prototype: inheriting_object(gc),
+        };
+
+        for handle in [
+            intrinsics.object_prototype,
+            intrinsics.array_prototype,
+            intrinsics.function_prototype,
+            intrinsics.string_prototype,
+            intrinsics.number_prototype,
+            intrinsics.regexp_prototype,
+            intrinsics.symbol_prototype,
+            intrinsics.map_prototype,
+            intrinsics.set_prototype,
+            intrinsics.weak_map_prototype,
+            intrinsics.weak_set_prototype,
+            intrinsics.array_buffer_prototype,
+            intrinsics.typed_array_prototype,
+            intrinsics.data_view_prototype,
+            intrinsics.abort_controller_prototype,
+            intrinsics.abort_signal_prototype,
+        ] {
+            gc.add_root(handle);
+        }
+        intrinsics
+    }
+}
+
+/// Populate the prototypes and define the `Object`, `Array`, `Function`,
+/// `String`, `Number`, `RegExp`, `Symbol`, `Date` and `Math` globals, the
+/// collections, the binary data types, `AbortController`, `queueMicrotask`
+/// and `structuredClone`
+pub(crate) fn install(vm: &mut VirtualMachine) {
+    let intrinsics = vm.intrinsics();
+
+    vm.define_builtin(intrinsics.object_prototype, "hasOwnProperty", object_has_own_property);
+    vm.define_builtin(intrinsics.object_prototype, "toString", object_to_string);
+    array::install(vm);
+    string::install(vm);
+    number::install(vm);
+    regexp::install(vm);
+    symbol::install(vm);
+    collections::install(vm);
+    typed_array::install(vm);
+    abort::install(vm);
+
+    let globals = [
+        ("Object", intrinsics.object_prototype),
+        ("Array", intrinsics.array_prototype),
+        ("Function", intrinsics.function_prototype),
+        ("String", intrinsics.string_prototype),
+        ("Number", intrinsics.number_prototype),
+        ("RegExp", intrinsics.regexp_prototype),
+    ];
+    for (name, prototype) in globals {
+        let constructor = vm.create_object(HashMap::from([("prototype".to_string(), prototype)]));
+        match name {
+            "Object" => {
+                vm.define_builtin(constructor, "getPrototypeOf", object_get_prototype_of);
+                vm.define_builtin(constructor, "setPrototypeOf", object_set_prototype_of);
+            }
+            "Array" => vm.define_builtin(constructor, "isArray", array::is_array),
+            _ => {}
+        }
+        vm.set_global(name.to_string(), Value::Object(constructor));
+    }
+
+    // Only the members that read the host clock and random source so far
+    let date = vm.create_object(HashMap::new());
+    vm.define_builtin(date, "now", date_now);
+    vm.set_global("Date".to_string(), Value::Object(date));
+    let math = vm.create_object(HashMap::new());
+    vm.define_builtin(math, "random", math_random);
+    vm.set_global("Math".to_string(), Value::Object(math));
+
+    let queue_microtask = vm.create_builtin("queueMicrotask", queue_microtask);
+    vm.set_global("queueMicrotask".to_string(), Value::Object(queue_microtask));
+    let structured_clone = vm.create_builtin("structuredClone", structured_clone);
+    vm.set_global("structuredClone".to_string(), Value::Object(structured_clone));
+}
+
+/// `queueMicrotask(callback)`
+fn queue_microtask(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let callback = argument(args, 0);
+    if !vm.is_callable(&callback) {
+        return Err(RuntimeError::TypeError(
+            "The \"callback\" argument of queueMicrotask must be a function".to_string(),
+        ));
+    }
+    vm.queue_microtask(callback);
+    Ok(Value::Undefined)
+}
+
+/// `structuredClone(value)`
+fn structured_clone(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    match vm.serialize(&argument(args, 0)) {
+        Ok(serialized) => Ok(vm.deserialize(&serialized)),
+        Err(error) => {
+            let error = error_object(vm, "DataCloneError", &error.0);
+            Err(vm.throw(error))
+        }
+    }
+}
+
+fn argument(args: &[Value], index: usize) -> Value {
+    args.get(index).cloned().unwrap_or(Value::Undefined)
+}
+
+/// Set the `Symbol.toStringTag` of `target`, which `toString` reports
+fn define_to_string_tag(vm: &mut VirtualMachine, target: GcHandle, tag: &str) {
+    let mut gc = vm.gc().lock().unwrap();
+    let tag = gc.intern_string(tag);
+    gc.set_property(target, &Symbol::to_string_tag().property_key(), tag);
+}
+
+/// `ToIntegerOrInfinity` of `value`, relative to the end when negative and
+/// clamped to `0..=len`; `default` when `value` is undefined
+fn relative_index(value: &Value, len: usize, default: usize) -> RuntimeResult<usize> {
+    if matches!(value, Value::Undefined) {
+        return Ok(default);
+    }
+    let n = integer(value)?;
+    Ok(if n < 0.0 {
+        (len as f64 + n).max(0.0) as usize
+    } else {
+        n.min(len as f64) as usize
+    })
+}
+
+/// `ToIntegerOrInfinity`
+fn integer(value: &Value) -> RuntimeResult<f64> {
+    let n = value.to_number()?;
+    Ok(if n.is_nan() { 0.0 } else { n.trunc() })
+}
+
+/// `ToUint32`
+fn to_uint32(n: f64) -> u32 {
+    if n.is_finite() {
+        n.trunc().rem_euclid(4294967296.0) as u32
+    } else {
+        0
+    }
+}
+
+/// `Object.getPrototypeOf(value)`
+fn object_get_prototype_of(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    match argument(args, 0) {
+        Value::Null | Value::Undefined => {
+            Err(RuntimeError::TypeError("Cannot convert undefined or null to object".to_string()))
+        }
+        Value::Object(handle) => {
+            let gc = vm.gc().lock().unwrap();
+            Ok(gc.get_prototype(handle).map_or(Value::Null, Value::Object))
+        }
+        Value::String(_) => Ok(Value::Object(vm.intrinsics().string_prototype)),
+        Value::Number(_) => Ok(Value::Object(vm.intrinsics().number_prototype)),
+        Value::Symbol(_) => Ok(Value::Object(vm.intrinsics().symbol_prototype)),
+        Value::NativeFunction(_) => Ok(Value::Object(vm.intrinsics().function_prototype)),
+        Value::Boolean(_) => Ok(Value::Null),
+    }
+}
+
+/// `Object.setPrototypeOf(object, prototype)`
+fn object_set_prototype_of(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let object = argument(args, 0);
+    let prototype = argument(args, 1);
+
+    if matches!(object, Value::Null | Value::Undefined) {
+        return Err(RuntimeError::TypeError("Object.setPrototypeOf called on null or undefined".to_string()));
+    }
+    if !matches!(prototype, Value::Object(_) | Value::Null) {
+        return Err(RuntimeError::TypeError(format!(
+            "Object prototype may only be an Object or null: {}",
+            prototype.to_string()
+        )));
+    }
+
+    if let Value::Object(handle) = object {
+        vm.set_prototype(handle, &prototype)?;
+    }
+    Ok(object)
+}
+
+/// `Object.prototype.hasOwnProperty(key)`
+fn object_has_own_property(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let key = property_key(&argument(args, 0));
+    let has_property = match this {
+        Value::Null | Value::Undefined => {
+            return Err(RuntimeError::TypeError("Cannot convert undefined or null to object".to_string()));
+        }
+        Value::Object(handle) => {
+            let gc = vm.gc().lock().unwrap();
+            match gc.get_object_type(*handle) {
+                Some(GcObjectType::Object(properties)) => properties.contains_key(&key),
+                Some(GcObjectType::Array(elements)) => {
+                    key == "length" || key.parse::<usize>().is_ok_and(|index| index < elements.len())
+                }
+                Some(GcObjectType::Function { .. }) => key == "name",
+                _ => false,
+            }
+        }
+        Value::String(s) => key == "length" || key.parse::<usize>().is_ok_and(|index| index < s.len()),
+        Value::Symbol(_) => key == "description",
+        Value::NativeFunction(_) => key == "name",
+        Value::Number(_) | Value::Boolean(_) => false,
+    };
+    Ok(Value::Boolean(has_property))
+}
+
+/// `Date.now()`: milliseconds since the epoch, fixed while the clock is
+/// frozen
+fn date_now(vm: &mut VirtualMachine, _this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    Ok(Value::Number(vm.clock().now()))
+}
+
+/// `Math.random()`, deterministic once the random source is seeded
+fn math_random(vm: &mut VirtualMachine, _this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    Ok(Value::Number(vm.random().next_f64()))
+}
+
+/// `Object.prototype.toString()`: `[object Tag]`, where the tag is the
+/// receiver's `Symbol.toStringTag` when that is a string
+fn object_to_string(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let builtin_tag = match this {
+        Value::Undefined => return Ok(Value::from("[object Undefined]")),
+        Value::Null => return Ok(Value::from("[object Null]")),
+        Value::String(_) => "String",
+        Value::Number(_) => "Number",
+        Value::Boolean(_) => "Boolean",
+        _ if vm.array_elements(this).is_some() => "Array",
+        _ if vm.is_callable(this) => "Function",
+        _ => "Object",
+    };
+    let tag = match vm.get_property(this, &Value::Symbol(Symbol::to_string_tag()))? {
+        Value::String(tag) => tag.to_rust_string(),
+        _ => builtin_tag.to_string(),
+    };
+    Ok(Value::from(format!("[object {}]", tag)))
+}
@@ -0,0 +1,247 @@
+//! `AbortController` and `AbortSignal`
+//!
+//! A signal is a plain object with `aborted` and `reason` properties; the
+//! VM keeps its listeners and its host `AbortSignal` in a side table, so
+//! host operations given the signal can be cancelled when a script aborts
+//! it. Listeners run synchronously inside `abort()`, and the errors they
+//! throw are reported like those of microtasks rather than thrown from
+//! `abort()`. Only the `abort` event exists, so `addEventListener` ignores
+//! other types.
+//!
+//! `AbortSignal.timeout` only records the signal and its delay; aborting it
+//! when the time is up is left to the host's timers, so without an event
+//! loop such a signal never aborts.
+
+use super::{argument, define_to_string_tag};
+use crate::abort::AbortSignal;
+use crate::vm::VirtualMachine;
+use crate::{RuntimeError, RuntimeResult, Value};
+use bebion_gc::GcHandle;
+use std::collections::HashMap;
+
+/// What the VM keeps for each signal outside the heap
+#[derive(Debug, Default)]
+pub(crate) struct SignalRecord {
+    pub host: AbortSignal,
+    /// `abort` listeners, in the order they were added
+    pub listeners: Vec<Value>,
+}
+
+pub(super) fn install(vm: &mut VirtualMachine) {
+    let intrinsics = vm.intrinsics();
+
+    vm.define_builtin(intrinsics.abort_controller_prototype, "abort", controller_abort);
+    define_to_string_tag(vm, intrinsics.abort_controller_prototype, "AbortController");
+
+    vm.define_builtin(intrinsics.abort_signal_prototype, "throwIfAborted", throw_if_aborted);
+    vm.define_builtin(intrinsics.abort_signal_prototype, "addEventListener", add_event_listener);
+    vm.define_builtin(intrinsics.abort_signal_prototype, "removeEventListener", remove_event_listener);
+    define_to_string_tag(vm, intrinsics.abort_signal_prototype, "AbortSignal");
+
+    let globals = [
+        ("AbortController", intrinsics.abort_controller_prototype, construct_controller as super::Builtin),
+        ("AbortSignal", intrinsics.abort_signal_prototype, construct_signal),
+    ];
+    for (name, prototype, construct) in globals {
+        let statics = vm.create_object(HashMap::from([("prototype".to_string(), prototype)]));
+        if name == "AbortSignal" {
+            vm.define_builtin(statics, "abort", signal_abort);
+            vm.define_builtin(statics, "timeout", signal_timeout);
+        }
+        let constructor = vm.create_builtin(name, construct);
+        let mut gc = vm.gc().lock().unwrap();
+        gc.set_prototype(statics, Some(intrinsics.function_prototype));
+        gc.set_prototype(constructor, Some(statics));
+        gc.add_root(constructor);
+        drop(gc);
+        vm.set_global(name.to_string(), Value::Object(constructor));
+    }
+}
+
+/// A new signal that is not aborted
+pub(crate) fn create_signal(vm: &mut VirtualMachine) -> GcHandle {
+    let prototype = vm.intrinsics().abort_signal_prototype;
+    let properties = {
+        let mut gc = vm.gc().lock().unwrap();
+        HashMap::from([
+            ("aborted".to_string(), gc.allocate_boolean(false)),
+            ("reason".to_string(), gc.allocate_undefined()),
+            ("onabort".to_string(), gc.allocate_null()),
+        ])
+    };
+    let signal = vm.create_object(properties);
+    vm.gc().lock().unwrap().set_prototype(signal, Some(prototype));
+    vm.abort_signals().insert(signal, SignalRecord::default());
+    signal
+}
+
+/// The signal `value` is, if it is one
+pub(crate) fn signal_handle(vm: &mut VirtualMachine, value: &Value) -> Option<GcHandle> {
+    match value {
+        Value::Object(handle) if vm.abort_signals().contains_key(handle) => Some(*handle),
+        _ => None,
+    }
+}
+
+/// Abort `signal` with `reason`, an `AbortError` when undefined, then run
+/// its `onabort` handler and listeners. Aborting an aborted signal does
+/// nothing.
+pub(crate) fn abort(vm: &mut VirtualMachine, signal: GcHandle, reason: Value) -> RuntimeResult<()> {
+    let Some(record) = vm.abort_signals().get_mut(&signal) else {
+        return Err(RuntimeError::TypeError("Cannot abort a value that is not an AbortSignal".to_string()));
+    };
+    if record.host.is_aborted() {
+        return Ok(());
+    }
+    let host = record.host.clone();
+    let listeners = std::mem::take(&mut record.listeners);
+
+    let reason = match reason {
+        Value::Undefined => error_object(vm, "AbortError", "This operation was aborted"),
+        reason => reason,
+    };
+    let target = Value::Object(signal);
+    vm.set_property(&target, &Value::from("aborted"), Value::Boolean(true))?;
+    vm.set_property(&target, &Value::from("reason"), reason.clone())?;
+    host.abort(vm.describe_exception(&reason));
+
+    let event = {
+        let properties = HashMap::from([
+            ("type".to_string(), vm.value_to_handle(Value::from("abort"))),
+            ("target".to_string(), signal),
+        ]);
+        Value::Object(vm.create_object(properties))
+    };
+    let on_abort = vm.get_property(&target, &Value::from("onabort"))?;
+    let handlers = vm.is_callable(&on_abort).then_some(on_abort).into_iter().chain(listeners);
+    for handler in handlers {
+        if let Err(error) = vm.call_function(&handler, &target, std::slice::from_ref(&event)) {
+            vm.report_job_error(error);
+        }
+    }
+    Ok(())
+}
+
+/// An error-like object with `name` and `message`, standing in for a
+/// `DOMException`
+pub(crate) fn error_object(vm: &mut VirtualMachine, name: &str, message: &str) -> Value {
+    let properties = {
+        let mut gc = vm.gc().lock().unwrap();
+        HashMap::from([
+            ("name".to_string(), gc.intern_string(name)),
+            ("message".to_string(), gc.allocate_string(message.to_string())),
+        ])
+    };
+    Value::Object(vm.create_object(properties))
+}
+
+/// The signal a method was called on
+fn receiver(vm: &mut VirtualMachine, this: &Value, method: &str) -> RuntimeResult<GcHandle> {
+    signal_handle(vm, this).ok_or_else(|| {
+        RuntimeError::TypeError(format!(
+            "Method AbortSignal.prototype.{} called on incompatible receiver {}",
+            method,
+            this.to_string()
+        ))
+    })
+}
+
+/// `AbortController()`
+fn construct_controller(vm: &mut VirtualMachine, _this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let signal = create_signal(vm);
+    let controller = vm.create_object(HashMap::from([("signal".to_string(), signal)]));
+    let prototype = vm.intrinsics().abort_controller_prototype;
+    vm.gc().lock().unwrap().set_prototype(controller, Some(prototype));
+    Ok(Value::Object(controller))
+}
+
+/// `AbortSignal()`: signals only come from controllers and the statics
+fn construct_signal(_vm: &mut VirtualMachine, _this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    Err(RuntimeError::TypeError("Illegal constructor".to_string()))
+}
+
+/// `AbortController.prototype.abort(reason)`
+fn controller_abort(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let signal = match this {
+        Value::Object(_) => vm.get_property(this, &Value::from("signal"))?,
+        _ => Value::Undefined,
+    };
+    let Some(signal) = signal_handle(vm, &signal) else {
+        return Err(RuntimeError::TypeError(format!(
+            "Method AbortController.prototype.abort called on incompatible receiver {}",
+            this.to_string()
+        )));
+    };
+    abort(vm, signal, argument(args, 0))?;
+    Ok(Value::Undefined)
+}
+
+/// `AbortSignal.abort(reason)`: a signal that is already aborted
+fn signal_abort(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let signal = create_signal(vm);
+    abort(vm, signal, argument(args, 0))?;
+    Ok(Value::Object(signal))
+}
+
+/// `AbortSignal.timeout(milliseconds)`: a signal the host aborts with a
+/// `TimeoutError` once `milliseconds` have passed
+fn signal_timeout(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let milliseconds = argument(args, 0).to_number()?;
+    if !milliseconds.is_finite() || milliseconds < 0.0 {
+        return Err(RuntimeError::RangeError(format!(
+            "The value of \"milliseconds\" is out of range: {}",
+            milliseconds
+        )));
+    }
+    Ok(Value::Object(timeout_signal(vm, milliseconds.trunc())))
+}
+
+/// A new signal for the host to abort with a `TimeoutError` once
+/// `milliseconds` have passed
+pub(crate) fn timeout_signal(vm: &mut VirtualMachine, milliseconds: f64) -> GcHandle {
+    let signal = create_signal(vm);
+    vm.abort_timeouts().push((signal, milliseconds));
+    signal
+}
+
+/// `AbortSignal.prototype.throwIfAborted()`
+fn throw_if_aborted(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let signal = receiver(vm, this, "throwIfAborted")?;
+    if !vm.abort_signals()[&signal].host.is_aborted() {
+        return Ok(Value::Undefined);
+    }
+    let reason = vm.get_property(this, &Value::from("reason"))?;
+    Err(vm.throw(reason))
+}
+
+/// `AbortSignal.prototype.addEventListener(type, listener)`
+fn add_event_listener(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let signal = receiver(vm, this, "addEventListener")?;
+    let listener = argument(args, 1);
+    if argument(args, 0).to_string() != "abort" || !vm.is_callable(&listener) {
+        return Ok(Value::Undefined);
+    }
+    add_listener(vm, signal, listener);
+    Ok(Value::Undefined)
+}
+
+/// Add an `abort` listener to `signal`
+pub(crate) fn add_listener(vm: &mut VirtualMachine, signal: GcHandle, listener: Value) {
+    let record = vm.abort_signals().get_mut(&signal).expect("signal record");
+    // Adding the same listener twice has no effect, and an aborted signal
+    // never fires again
+    if !record.host.is_aborted() && !record.listeners.contains(&listener) {
+        record.listeners.push(listener);
+    }
+}
+
+/// `AbortSignal.prototype.removeEventListener(type, listener)`
+fn remove_event_listener(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let signal = receiver(vm, this, "removeEventListener")?;
+    let listener = argument(args, 1);
+    if argument(args, 0).to_string() == "abort" {
+        let record = vm.abort_signals().get_mut(&signal).expect("signal record");
+        record.listeners.retain(|added| *added != listener);
+    }
+    Ok(Value::Undefined)
+}
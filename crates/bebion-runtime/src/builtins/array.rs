@@ -0,0 +1,414 @@
+//! `Array.prototype` methods
+//!
+//! Methods work on a copy of the receiver's elements: iteration visits the
+//! elements the array had when it started, and methods that change the
+//! array write their result back in one step.
+
+use super::{argument, integer, relative_index, Builtin};
+use crate::vm::VirtualMachine;
+use crate::{JsString, RuntimeError, RuntimeResult, Symbol, Value};
+use bebion_gc::GcHandle;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+pub(super) fn install(vm: &mut VirtualMachine) {
+    let prototype = vm.intrinsics().array_prototype;
+    let methods: [(&str, Builtin); 22] = [
+        ("push", push),
+        ("pop", pop),
+        ("shift", shift),
+        ("unshift", unshift),
+        ("slice", slice),
+        ("splice", splice),
+        ("concat", concat),
+        ("reverse", reverse),
+        ("indexOf", index_of),
+        ("lastIndexOf", last_index_of),
+        ("includes", includes),
+        ("join", join),
+        ("forEach", for_each),
+        ("map", map),
+        ("filter", filter),
+        ("some", some),
+        ("every", every),
+        ("find", find),
+        ("findIndex", find_index),
+        ("reduce", reduce),
+        ("reduceRight", reduce_right),
+        ("sort", sort),
+    ];
+    for (name, method) in methods {
+        vm.define_builtin(prototype, name, method);
+    }
+    vm.define_symbol_builtin(prototype, &Symbol::iterator(), iterator);
+}
+
+/// `Array.isArray(value)`
+pub(super) fn is_array(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    Ok(Value::Boolean(vm.array_elements(&argument(args, 0)).is_some()))
+}
+
+/// The receiver's elements
+fn elements(vm: &VirtualMachine, this: &Value, method: &str) -> RuntimeResult<Vec<Value>> {
+    vm.array_elements(this)
+        .ok_or_else(|| RuntimeError::TypeError(format!("Array.prototype.{} called on non-array", method)))
+}
+
+/// The callback argument of an iteration method
+fn callback(vm: &VirtualMachine, args: &[Value]) -> RuntimeResult<Value> {
+    let callback = argument(args, 0);
+    if !vm.is_callable(&callback) {
+        return Err(RuntimeError::TypeError(format!("{} is not a function", callback.to_string())));
+    }
+    Ok(callback)
+}
+
+/// Call `callback(element, index, array)` for each element, handing each
+/// result to `visit` until it returns false
+fn iterate(
+    vm: &mut VirtualMachine,
+    this: &Value,
+    args: &[Value],
+    method: &str,
+    mut visit: impl FnMut(usize, &Value, Value) -> bool,
+) -> RuntimeResult<()> {
+    let elements = elements(vm, this, method)?;
+    let callback = callback(vm, args)?;
+    let this_arg = argument(args, 1);
+    for (index, element) in elements.into_iter().enumerate() {
+        let call_args = [element.clone(), Value::Number(index as f64), this.clone()];
+        let result = vm.call_function(&callback, &this_arg, &call_args)?;
+        if !visit(index, &element, result) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// `Array.prototype.push(...items)`
+fn push(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut elements = elements(vm, this, "push")?;
+    elements.extend_from_slice(args);
+    let len = elements.len();
+    vm.set_array_elements(this, elements)?;
+    Ok(Value::Number(len as f64))
+}
+
+/// `Array.prototype.pop()`
+fn pop(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let mut elements = elements(vm, this, "pop")?;
+    let last = elements.pop().unwrap_or(Value::Undefined);
+    vm.set_array_elements(this, elements)?;
+    Ok(last)
+}
+
+/// `Array.prototype.shift()`
+fn shift(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let mut elements = elements(vm, this, "shift")?;
+    if elements.is_empty() {
+        return Ok(Value::Undefined);
+    }
+    let first = elements.remove(0);
+    vm.set_array_elements(this, elements)?;
+    Ok(first)
+}
+
+/// `Array.prototype.unshift(...items)`
+fn unshift(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "unshift")?;
+    let elements: Vec<_> = args.iter().cloned().chain(elements).collect();
+    let len = elements.len();
+    vm.set_array_elements(this, elements)?;
+    Ok(Value::Number(len as f64))
+}
+
+/// `Array.prototype.slice(start, end)`
+fn slice(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "slice")?;
+    let len = elements.len();
+    let start = relative_index(&argument(args, 0), len, 0)?;
+    let end = relative_index(&argument(args, 1), len, len)?.max(start);
+    Ok(vm.array_from_values(elements[start..end].to_vec()))
+}
+
+/// `Array.prototype.splice(start, deleteCount, ...items)`
+fn splice(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut elements = elements(vm, this, "splice")?;
+    let len = elements.len();
+    let start = relative_index(&argument(args, 0), len, 0)?;
+    let delete_count = match args.len() {
+        0 => 0,
+        1 => len - start,
+        _ => integer(&args[1])?.clamp(0.0, (len - start) as f64) as usize,
+    };
+    let items = args.get(2..).unwrap_or_default().iter().cloned();
+    let removed: Vec<_> = elements.splice(start..start + delete_count, items).collect();
+    vm.set_array_elements(this, elements)?;
+    Ok(vm.array_from_values(removed))
+}
+
+/// `Array.prototype.concat(...items)`: arrays are spread, anything else
+/// is appended as is
+fn concat(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut elements = elements(vm, this, "concat")?;
+    for arg in args {
+        match vm.array_elements(arg) {
+            Some(items) => elements.extend(items),
+            None => elements.push(arg.clone()),
+        }
+    }
+    Ok(vm.array_from_values(elements))
+}
+
+/// `Array.prototype.reverse()`
+fn reverse(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let mut elements = elements(vm, this, "reverse")?;
+    elements.reverse();
+    vm.set_array_elements(this, elements)?;
+    Ok(this.clone())
+}
+
+/// `Array.prototype.indexOf(search, fromIndex)`, by strict equality
+fn index_of(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "indexOf")?;
+    let search = argument(args, 0);
+    let from = relative_index(&argument(args, 1), elements.len(), 0)?;
+    let index = elements.iter().skip(from).position(|element| element.strict_equals(&search));
+    Ok(Value::Number(index.map_or(-1.0, |index| (from + index) as f64)))
+}
+
+/// `Array.prototype.lastIndexOf(search, fromIndex)`, by strict equality
+fn last_index_of(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "lastIndexOf")?;
+    let search = argument(args, 0);
+    let len = elements.len() as f64;
+    let from = match args.get(1) {
+        Some(from) => {
+            let n = integer(from)?;
+            if n < 0.0 { len + n } else { n.min(len - 1.0) }
+        }
+        None => len - 1.0,
+    };
+    if from < 0.0 {
+        return Ok(Value::Number(-1.0));
+    }
+    let index = elements[..=from as usize].iter().rposition(|element| element.strict_equals(&search));
+    Ok(Value::Number(index.map_or(-1.0, |index| index as f64)))
+}
+
+/// `Array.prototype.includes(search, fromIndex)`, by SameValueZero so NaN
+/// is found
+fn includes(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "includes")?;
+    let search = argument(args, 0);
+    let from = relative_index(&argument(args, 1), elements.len(), 0)?;
+    let found = elements.iter().skip(from).any(|element| match (element, &search) {
+        (Value::Number(a), Value::Number(b)) if a.is_nan() && b.is_nan() => true,
+        (element, search) => element.strict_equals(search),
+    });
+    Ok(Value::Boolean(found))
+}
+
+/// `Array.prototype[Symbol.iterator]()`: the elements, as a new array
+fn iterator(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "[Symbol.iterator]")?;
+    Ok(vm.array_from_values(elements))
+}
+
+/// `Array.prototype.join(separator)`
+fn join(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "join")?;
+    let separator = match argument(args, 0) {
+        Value::Undefined => ",".to_string(),
+        separator => separator.to_string(),
+    };
+    let mut visiting = HashSet::new();
+    if let Value::Object(handle) = this {
+        visiting.insert(*handle);
+    }
+    Ok(Value::String(JsString::from(join_elements(vm, &elements, &separator, &mut visiting))))
+}
+
+/// Join with `null` and `undefined` as empty strings and nested arrays
+/// joined with commas. An array met again inside itself is empty.
+fn join_elements(vm: &VirtualMachine, elements: &[Value], separator: &str, visiting: &mut HashSet<GcHandle>) -> String {
+    let parts: Vec<_> = elements
+        .iter()
+        .map(|element| match element {
+            Value::Null | Value::Undefined => String::new(),
+            Value::Object(handle) => match vm.array_elements(element) {
+                Some(_) if !visiting.insert(*handle) => String::new(),
+                Some(nested) => {
+                    let joined = join_elements(vm, &nested, ",", visiting);
+                    visiting.remove(handle);
+                    joined
+                }
+                None => element.to_string(),
+            },
+            element => element.to_string(),
+        })
+        .collect();
+    parts.join(separator)
+}
+
+/// `Array.prototype.forEach(callback, thisArg)`
+fn for_each(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    iterate(vm, this, args, "forEach", |_, _, _| true)?;
+    Ok(Value::Undefined)
+}
+
+/// `Array.prototype.map(callback, thisArg)`
+fn map(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut mapped = Vec::new();
+    iterate(vm, this, args, "map", |_, _, result| {
+        mapped.push(result);
+        true
+    })?;
+    Ok(vm.array_from_values(mapped))
+}
+
+/// `Array.prototype.filter(callback, thisArg)`
+fn filter(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut kept = Vec::new();
+    iterate(vm, this, args, "filter", |_, element, result| {
+        if result.to_boolean() {
+            kept.push(element.clone());
+        }
+        true
+    })?;
+    Ok(vm.array_from_values(kept))
+}
+
+/// `Array.prototype.some(callback, thisArg)`
+fn some(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut found = false;
+    iterate(vm, this, args, "some", |_, _, result| {
+        found = result.to_boolean();
+        !found
+    })?;
+    Ok(Value::Boolean(found))
+}
+
+/// `Array.prototype.every(callback, thisArg)`
+fn every(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut all = true;
+    iterate(vm, this, args, "every", |_, _, result| {
+        all = result.to_boolean();
+        all
+    })?;
+    Ok(Value::Boolean(all))
+}
+
+/// `Array.prototype.find(callback, thisArg)`
+fn find(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut found = Value::Undefined;
+    iterate(vm, this, args, "find", |_, element, result| {
+        if result.to_boolean() {
+            found = element.clone();
+            return false;
+        }
+        true
+    })?;
+    Ok(found)
+}
+
+/// `Array.prototype.findIndex(callback, thisArg)`
+fn find_index(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let mut found = -1.0;
+    iterate(vm, this, args, "findIndex", |index, _, result| {
+        if result.to_boolean() {
+            found = index as f64;
+            return false;
+        }
+        true
+    })?;
+    Ok(Value::Number(found))
+}
+
+/// `Array.prototype.reduce(callback, initialValue)`
+fn reduce(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "reduce")?;
+    let indexed: Vec<_> = elements.into_iter().enumerate().collect();
+    fold(vm, this, args, indexed)
+}
+
+/// `Array.prototype.reduceRight(callback, initialValue)`
+fn reduce_right(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let elements = elements(vm, this, "reduceRight")?;
+    let indexed: Vec<_> = elements.into_iter().enumerate().rev().collect();
+    fold(vm, this, args, indexed)
+}
+
+/// Fold `callback(accumulator, element, index, array)` over `elements` in
+/// the order given, starting from the initial value or the first element
+fn fold(vm: &mut VirtualMachine, this: &Value, args: &[Value], elements: Vec<(usize, Value)>) -> RuntimeResult<Value> {
+    let callback = callback(vm, args)?;
+    let mut elements = elements.into_iter();
+    let mut accumulator = match args.get(1) {
+        Some(initial) => initial.clone(),
+        None => match elements.next() {
+            Some((_, first)) => first,
+            None => return Err(RuntimeError::TypeError("Reduce of empty array with no initial value".to_string())),
+        },
+    };
+    for (index, element) in elements {
+        let call_args = [accumulator, element, Value::Number(index as f64), this.clone()];
+        accumulator = vm.call_function(&callback, &Value::Undefined, &call_args)?;
+    }
+    Ok(accumulator)
+}
+
+/// `Array.prototype.sort(compareFn)`: a stable sort, by `compareFn` or by
+/// UTF-16 string order, with undefined elements last
+fn sort(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let comparator = argument(args, 0);
+    if !matches!(comparator, Value::Undefined) && !vm.is_callable(&comparator) {
+        return Err(RuntimeError::TypeError(
+            "The comparison function must be either a function or undefined".to_string(),
+        ));
+    }
+
+    let elements = elements(vm, this, "sort")?;
+    let (defined, undefined): (Vec<_>, Vec<_>) =
+        elements.into_iter().partition(|element| !matches!(element, Value::Undefined));
+
+    let mut compare = |a: &Value, b: &Value| -> RuntimeResult<Ordering> {
+        if matches!(comparator, Value::Undefined) {
+            let (a, b) = (JsString::from(a.to_string()), JsString::from(b.to_string()));
+            return Ok(a.code_units().cmp(b.code_units()));
+        }
+        let result = vm.call_function(&comparator, &Value::Undefined, &[a.clone(), b.clone()])?;
+        Ok(result.to_number()?.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+    };
+    let mut sorted = merge_sort(defined, &mut compare)?;
+    sorted.extend(undefined);
+
+    vm.set_array_elements(this, sorted)?;
+    Ok(this.clone())
+}
+
+/// A stable merge sort. Unlike the standard library's sorts it tolerates
+/// inconsistent comparators, which scripts are free to pass.
+fn merge_sort(
+    mut items: Vec<Value>,
+    compare: &mut dyn FnMut(&Value, &Value) -> RuntimeResult<Ordering>,
+) -> RuntimeResult<Vec<Value>> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Ties keep the left element first
+        let next = if compare(b, a)? == Ordering::Less { right.next() } else { left.next() };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
@@ -0,0 +1,464 @@
+//! `Map`, `Set`, `WeakMap` and `WeakSet`
+//!
+//! The globals are functions that build a collection when called; there is
+//! no `new` yet. `keys`, `values` and `entries` return arrays, which
+//! spread and destructuring take whole, and are also `Symbol.iterator`. Weak collections only take objects as keys and
+//! leave them to the collector, which drops their entries with them.
+
+use super::{argument, define_to_string_tag, Builtin};
+use crate::vm::VirtualMachine;
+use crate::{RuntimeError, RuntimeResult, Symbol, Value};
+use bebion_gc::{GcHandle, GcObjectType, MapKey};
+
+pub(super) fn install(vm: &mut VirtualMachine) {
+    let intrinsics = vm.intrinsics();
+
+    let map_methods: [(&str, Builtin); 9] = [
+        ("get", map_get),
+        ("set", map_set),
+        ("has", has),
+        ("delete", delete),
+        ("clear", clear),
+        ("forEach", for_each),
+        ("keys", keys),
+        ("values", values),
+        ("entries", entries),
+    ];
+    for (name, method) in map_methods {
+        vm.define_builtin(intrinsics.map_prototype, name, method);
+    }
+    vm.define_symbol_builtin(intrinsics.map_prototype, &Symbol::iterator(), entries);
+
+    let set_methods: [(&str, Builtin); 8] = [
+        ("add", set_add),
+        ("has", has),
+        ("delete", delete),
+        ("clear", clear),
+        ("forEach", for_each),
+        ("keys", values),
+        ("values", values),
+        ("entries", entries),
+    ];
+    for (name, method) in set_methods {
+        vm.define_builtin(intrinsics.set_prototype, name, method);
+    }
+    vm.define_symbol_builtin(intrinsics.set_prototype, &Symbol::iterator(), values);
+
+    let weak_map_methods: [(&str, Builtin); 4] = [
+        ("get", weak_map_get),
+        ("set", weak_map_set),
+        ("has", weak_has),
+        ("delete", weak_delete),
+    ];
+    for (name, method) in weak_map_methods {
+        vm.define_builtin(intrinsics.weak_map_prototype, name, method);
+    }
+
+    let weak_set_methods: [(&str, Builtin); 3] = [("add", weak_set_add), ("has", weak_has), ("delete", weak_delete)];
+    for (name, method) in weak_set_methods {
+        vm.define_builtin(intrinsics.weak_set_prototype, name, method);
+    }
+
+    let constructors: [(&str, Builtin); 4] = [
+        ("Map", construct_map),
+        ("Set", construct_set),
+        ("WeakMap", construct_weak_map),
+        ("WeakSet", construct_weak_set),
+    ];
+    let tags = [
+        (intrinsics.map_prototype, "Map"),
+        (intrinsics.set_prototype, "Set"),
+        (intrinsics.weak_map_prototype, "WeakMap"),
+        (intrinsics.weak_set_prototype, "WeakSet"),
+    ];
+    for (prototype, tag) in tags {
+        define_to_string_tag(vm, prototype, tag);
+    }
+
+    for (name, constructor) in constructors {
+        let function = vm.create_builtin(name, constructor);
+        vm.set_global(name.to_string(), Value::Object(function));
+    }
+}
+
+/// Which collection a receiver must be
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Map,
+    Set,
+    WeakMap,
+    WeakSet,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Map => "Map",
+            Kind::Set => "Set",
+            Kind::WeakMap => "WeakMap",
+            Kind::WeakSet => "WeakSet",
+        }
+    }
+}
+
+/// The receiver of a `Map.prototype` or `Set.prototype` method and which
+/// of the two it is
+fn receiver(vm: &VirtualMachine, this: &Value, method: &str) -> RuntimeResult<(GcHandle, Kind)> {
+    if let Value::Object(handle) = this {
+        let gc = vm.gc().lock().unwrap();
+        match gc.get_object_type(*handle) {
+            Some(GcObjectType::Map(_)) => return Ok((*handle, Kind::Map)),
+            Some(GcObjectType::Set(_)) => return Ok((*handle, Kind::Set)),
+            Some(GcObjectType::WeakMap(_)) => return Ok((*handle, Kind::WeakMap)),
+            Some(GcObjectType::WeakSet(_)) => return Ok((*handle, Kind::WeakSet)),
+            _ => {}
+        }
+    }
+    Err(RuntimeError::TypeError(format!(
+        "Method {} called on incompatible receiver {}",
+        method,
+        this.to_string()
+    )))
+}
+
+/// The receiver, which must be of `kind`
+fn expect(vm: &VirtualMachine, this: &Value, kind: Kind, method: &str) -> RuntimeResult<GcHandle> {
+    let method = format!("{}.prototype.{}", kind.name(), method);
+    match receiver(vm, this, &method)? {
+        (handle, found) if found == kind => Ok(handle),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Method {} called on incompatible receiver {}",
+            method,
+            this.to_string()
+        ))),
+    }
+}
+
+/// The receiver, which must be a `Map` or a `Set`
+fn keyed(vm: &VirtualMachine, this: &Value, method: &str) -> RuntimeResult<GcHandle> {
+    match receiver(vm, this, &format!("Map.prototype.{}", method))? {
+        (handle, Kind::Map | Kind::Set) => Ok(handle),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Method Map.prototype.{} called on incompatible receiver {}",
+            method,
+            this.to_string()
+        ))),
+    }
+}
+
+/// `value` as a key, compared by SameValueZero
+fn map_key(vm: &mut VirtualMachine, value: &Value) -> MapKey {
+    match value {
+        Value::Undefined => MapKey::Undefined,
+        Value::Null => MapKey::Null,
+        Value::Boolean(b) => MapKey::Boolean(*b),
+        Value::Number(n) => MapKey::number(*n),
+        Value::String(s) => MapKey::String(s.to_string()),
+        Value::Symbol(symbol) => MapKey::Symbol(symbol.id()),
+        Value::Object(handle) => MapKey::Object(*handle),
+        Value::NativeFunction(_) => MapKey::Object(vm.value_to_handle(value.clone())),
+    }
+}
+
+/// The object a weak collection is keyed by
+fn weak_key(vm: &mut VirtualMachine, value: &Value, kind: Kind) -> RuntimeResult<GcHandle> {
+    match value {
+        Value::Object(handle) => Ok(*handle),
+        Value::NativeFunction(_) => Ok(vm.value_to_handle(value.clone())),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Invalid value used {} {}",
+            if kind == Kind::WeakMap { "as weak map key" } else { "in weak set" },
+            value.to_string()
+        ))),
+    }
+}
+
+/// A new, empty collection inheriting from its prototype
+fn allocate(vm: &mut VirtualMachine, kind: Kind) -> GcHandle {
+    let intrinsics = vm.intrinsics();
+    let mut gc = vm.gc().lock().unwrap();
+    let (handle, prototype) = match kind {
+        Kind::Map => (gc.allocate_map(), intrinsics.map_prototype),
+        Kind::Set => (gc.allocate_set(), intrinsics.set_prototype),
+        Kind::WeakMap => (gc.allocate_weak_map(), intrinsics.weak_map_prototype),
+        Kind::WeakSet => (gc.allocate_weak_set(), intrinsics.weak_set_prototype),
+    };
+    gc.set_prototype(handle, Some(prototype));
+    handle
+}
+
+/// The values to fill a new collection with: the elements of an array, or
+/// the characters of a string
+fn initial_values(vm: &VirtualMachine, iterable: &Value, kind: Kind) -> RuntimeResult<Vec<Value>> {
+    match iterable {
+        Value::Undefined | Value::Null => Ok(Vec::new()),
+        Value::String(s) => Ok(s.to_string().chars().map(|c| Value::from(c.to_string())).collect()),
+        _ => vm.array_elements(iterable).ok_or_else(|| {
+            RuntimeError::TypeError(format!(
+                "{} is not iterable (cannot read {} entries from it)",
+                iterable.to_string(),
+                kind.name()
+            ))
+        }),
+    }
+}
+
+/// The key and value of an entry given to a `Map` or `WeakMap`
+fn entry(vm: &VirtualMachine, value: &Value) -> RuntimeResult<(Value, Value)> {
+    match vm.array_elements(value) {
+        Some(elements) => Ok((argument(&elements, 0), argument(&elements, 1))),
+        None => Err(RuntimeError::TypeError(format!(
+            "Iterator value {} is not an entry object",
+            value.to_string()
+        ))),
+    }
+}
+
+/// `Map(entries)`
+fn construct_map(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let initial = initial_values(vm, &argument(args, 0), Kind::Map)?;
+    let map = Value::Object(allocate(vm, Kind::Map));
+    for value in initial {
+        let (key, value) = entry(vm, &value)?;
+        map_set(vm, &map, &[key, value])?;
+    }
+    Ok(map)
+}
+
+/// `Set(values)`
+fn construct_set(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let initial = initial_values(vm, &argument(args, 0), Kind::Set)?;
+    let set = Value::Object(allocate(vm, Kind::Set));
+    for value in initial {
+        set_add(vm, &set, &[value])?;
+    }
+    Ok(set)
+}
+
+/// `WeakMap(entries)`
+fn construct_weak_map(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let initial = initial_values(vm, &argument(args, 0), Kind::WeakMap)?;
+    let map = Value::Object(allocate(vm, Kind::WeakMap));
+    for value in initial {
+        let (key, value) = entry(vm, &value)?;
+        weak_map_set(vm, &map, &[key, value])?;
+    }
+    Ok(map)
+}
+
+/// `WeakSet(values)`
+fn construct_weak_set(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let initial = initial_values(vm, &argument(args, 0), Kind::WeakSet)?;
+    let set = Value::Object(allocate(vm, Kind::WeakSet));
+    for value in initial {
+        weak_set_add(vm, &set, &[value])?;
+    }
+    Ok(set)
+}
+
+/// `Map.prototype.get(key)`
+fn map_get(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = expect(vm, this, Kind::Map, "get")?;
+    let key = map_key(vm, &argument(args, 0));
+    let value = match vm.gc().lock().unwrap().get_object_type(handle) {
+        Some(GcObjectType::Map(map)) => map.get(&key),
+        _ => None,
+    };
+    Ok(value.map_or(Value::Undefined, |value| vm.handle_to_value(value)))
+}
+
+/// `Map.prototype.set(key, value)`, returning the map
+fn map_set(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = expect(vm, this, Kind::Map, "set")?;
+    let key = argument(args, 0);
+    let map_key = map_key(vm, &key);
+    let key_handle = vm.value_to_handle(key);
+    let value = vm.value_to_handle(argument(args, 1));
+    vm.gc().lock().unwrap().map_insert(handle, map_key, key_handle, value);
+    Ok(this.clone())
+}
+
+/// `Set.prototype.add(value)`, returning the set
+fn set_add(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = expect(vm, this, Kind::Set, "add")?;
+    let value = argument(args, 0);
+    let key = map_key(vm, &value);
+    let value = vm.value_to_handle(value);
+    vm.gc().lock().unwrap().map_insert(handle, key, value, value);
+    Ok(this.clone())
+}
+
+/// `Map.prototype.has(key)` and `Set.prototype.has(value)`
+fn has(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = keyed(vm, this, "has")?;
+    let key = map_key(vm, &argument(args, 0));
+    let gc = vm.gc().lock().unwrap();
+    let found = match gc.get_object_type(handle) {
+        Some(GcObjectType::Map(map) | GcObjectType::Set(map)) => map.contains_key(&key),
+        _ => false,
+    };
+    Ok(Value::Boolean(found))
+}
+
+/// `Map.prototype.delete(key)` and `Set.prototype.delete(value)`
+fn delete(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = keyed(vm, this, "delete")?;
+    let key = map_key(vm, &argument(args, 0));
+    Ok(Value::Boolean(vm.gc().lock().unwrap().map_remove(handle, &key)))
+}
+
+/// `Map.prototype.clear()` and `Set.prototype.clear()`
+fn clear(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let handle = keyed(vm, this, "clear")?;
+    vm.gc().lock().unwrap().collection_clear(handle);
+    Ok(Value::Undefined)
+}
+
+/// The key and value at `position`, `None` past the end, and `Some(None)`
+/// where an entry was removed
+fn entry_at(vm: &VirtualMachine, handle: GcHandle, position: usize) -> Option<Option<(Value, Value)>> {
+    let (key, value) = {
+        let gc = vm.gc().lock().unwrap();
+        let Some(GcObjectType::Map(map) | GcObjectType::Set(map)) = gc.get_object_type(handle) else {
+            return None;
+        };
+        if position >= map.positions() {
+            return None;
+        }
+        match map.entry_at(position) {
+            Some(entry) => entry,
+            None => return Some(None),
+        }
+    };
+    Some(Some((vm.handle_to_value(key), vm.handle_to_value(value))))
+}
+
+/// `forEach(callback, thisArg)`, calling `callback(value, key, collection)`.
+/// Entries added during the walk are visited and removed ones skipped.
+fn for_each(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = keyed(vm, this, "forEach")?;
+    let callback = argument(args, 0);
+    if !vm.is_callable(&callback) {
+        return Err(RuntimeError::TypeError(format!("{} is not a function", callback.to_string())));
+    }
+    let this_arg = argument(args, 1);
+
+    let mut position = 0;
+    while let Some(entry) = entry_at(vm, handle, position) {
+        if let Some((key, value)) = entry {
+            vm.call_function(&callback, &this_arg, &[value, key, this.clone()])?;
+        }
+        position += 1;
+    }
+    Ok(Value::Undefined)
+}
+
+/// The keys and values in insertion order
+fn pairs(vm: &VirtualMachine, handle: GcHandle) -> Vec<(GcHandle, GcHandle)> {
+    match vm.gc().lock().unwrap().get_object_type(handle) {
+        Some(GcObjectType::Map(map) | GcObjectType::Set(map)) => map.iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `Map.prototype.keys()`
+fn keys(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let handle = keyed(vm, this, "keys")?;
+    let keys = pairs(vm, handle).into_iter().map(|(key, _)| vm.handle_to_value(key)).collect();
+    Ok(vm.array_from_values(keys))
+}
+
+/// `Map.prototype.values()` and `Set.prototype.values()`
+fn values(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let handle = keyed(vm, this, "values")?;
+    let values = pairs(vm, handle).into_iter().map(|(_, value)| vm.handle_to_value(value)).collect();
+    Ok(vm.array_from_values(values))
+}
+
+/// `Map.prototype.entries()` and `Set.prototype.entries()`: `[key, value]`
+/// arrays, with a set's values as their own keys
+fn entries(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let handle = keyed(vm, this, "entries")?;
+    let entries = pairs(vm, handle)
+        .into_iter()
+        .map(|(key, value)| {
+            let pair = vec![vm.handle_to_value(key), vm.handle_to_value(value)];
+            vm.array_from_values(pair)
+        })
+        .collect();
+    Ok(vm.array_from_values(entries))
+}
+
+/// The receiver of a `WeakMap.prototype` or `WeakSet.prototype` method
+fn weak(vm: &VirtualMachine, this: &Value, method: &str) -> RuntimeResult<(GcHandle, Kind)> {
+    match receiver(vm, this, &format!("WeakMap.prototype.{}", method))? {
+        (handle, kind @ (Kind::WeakMap | Kind::WeakSet)) => Ok((handle, kind)),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Method WeakMap.prototype.{} called on incompatible receiver {}",
+            method,
+            this.to_string()
+        ))),
+    }
+}
+
+/// The object `value` names if it can be a weak key; anything else is
+/// never in a weak collection
+fn weak_lookup(vm: &mut VirtualMachine, value: &Value) -> Option<GcHandle> {
+    match value {
+        Value::Object(_) | Value::NativeFunction(_) => Some(vm.value_to_handle(value.clone())),
+        _ => None,
+    }
+}
+
+/// `WeakMap.prototype.get(key)`
+fn weak_map_get(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = expect(vm, this, Kind::WeakMap, "get")?;
+    let Some(key) = weak_lookup(vm, &argument(args, 0)) else {
+        return Ok(Value::Undefined);
+    };
+    let value = match vm.gc().lock().unwrap().get_object_type(handle) {
+        Some(GcObjectType::WeakMap(entries)) => entries.get(&key).copied(),
+        _ => None,
+    };
+    Ok(value.map_or(Value::Undefined, |value| vm.handle_to_value(value)))
+}
+
+/// `WeakMap.prototype.set(key, value)`, returning the map
+fn weak_map_set(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = expect(vm, this, Kind::WeakMap, "set")?;
+    let key = weak_key(vm, &argument(args, 0), Kind::WeakMap)?;
+    let value = vm.value_to_handle(argument(args, 1));
+    vm.gc().lock().unwrap().weak_insert(handle, key, value);
+    Ok(this.clone())
+}
+
+/// `WeakSet.prototype.add(value)`, returning the set
+fn weak_set_add(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let handle = expect(vm, this, Kind::WeakSet, "add")?;
+    let key = weak_key(vm, &argument(args, 0), Kind::WeakSet)?;
+    vm.gc().lock().unwrap().weak_insert(handle, key, key);
+    Ok(this.clone())
+}
+
+/// `WeakMap.prototype.has(key)` and `WeakSet.prototype.has(value)`
+fn weak_has(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let (handle, _) = weak(vm, this, "has")?;
+    let Some(key) = weak_lookup(vm, &argument(args, 0)) else {
+        return Ok(Value::Boolean(false));
+    };
+    let found = match vm.gc().lock().unwrap().get_object_type(handle) {
+        Some(GcObjectType::WeakMap(entries)) => entries.contains_key(&key),
+        Some(GcObjectType::WeakSet(members)) => members.contains(&key),
+        _ => false,
+    };
+    Ok(Value::Boolean(found))
+}
+
+/// `WeakMap.prototype.delete(key)` and `WeakSet.prototype.delete(value)`
+fn weak_delete(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let (handle, _) = weak(vm, this, "delete")?;
+    let Some(key) = weak_lookup(vm, &argument(args, 0)) else {
+        return Ok(Value::Boolean(false));
+    };
+    Ok(Value::Boolean(vm.gc().lock().unwrap().weak_remove(handle, key)))
+}
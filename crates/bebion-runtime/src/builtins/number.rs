@@ -0,0 +1,59 @@
+//! `Number.prototype` methods
+
+use super::{argument, integer, Builtin};
+use crate::number::{to_exponential, to_fixed, to_precision};
+use crate::vm::VirtualMachine;
+use crate::{RuntimeError, RuntimeResult, Value};
+
+pub(super) fn install(vm: &mut VirtualMachine) {
+    let prototype = vm.intrinsics().number_prototype;
+    let methods: [(&str, Builtin); 3] = [
+        ("toFixed", number_to_fixed),
+        ("toExponential", number_to_exponential),
+        ("toPrecision", number_to_precision),
+    ];
+    for (name, method) in methods {
+        vm.define_builtin(prototype, name, method);
+    }
+}
+
+/// `Number.prototype.toFixed(fractionDigits)`
+fn number_to_fixed(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let x = this_number(this, "toFixed")?;
+    let digits = integer(&argument(args, 0))? as i32;
+    Ok(Value::from(to_fixed(x, digits)?))
+}
+
+/// `Number.prototype.toExponential(fractionDigits)`
+fn number_to_exponential(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let x = this_number(this, "toExponential")?;
+    let digits = optional_integer(&argument(args, 0))?;
+    Ok(Value::from(to_exponential(x, digits)?))
+}
+
+/// `Number.prototype.toPrecision(precision)`
+fn number_to_precision(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let x = this_number(this, "toPrecision")?;
+    let precision = optional_integer(&argument(args, 0))?;
+    Ok(Value::from(to_precision(x, precision)?))
+}
+
+/// The receiver, which must be a number
+fn this_number(this: &Value, method: &str) -> RuntimeResult<f64> {
+    match this {
+        Value::Number(n) => Ok(*n),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Number.prototype.{} requires that 'this' be a Number",
+            method
+        ))),
+    }
+}
+
+/// `ToIntegerOrInfinity` of an argument that may be left out. Infinities
+/// saturate, so the range checks reject them.
+fn optional_integer(value: &Value) -> RuntimeResult<Option<i32>> {
+    match value {
+        Value::Undefined => Ok(None),
+        value => Ok(Some(integer(value)? as i32)),
+    }
+}
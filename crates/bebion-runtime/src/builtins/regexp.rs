@@ -0,0 +1,76 @@
+//! `RegExp.prototype` methods
+//!
+//! Arrays hold no named properties, so the `index` and `input` of an `exec`
+//! result live on an object between the match array and `Array.prototype`.
+
+use super::{argument, define_to_string_tag, Builtin};
+use crate::vm::VirtualMachine;
+use crate::{RegExp, RuntimeError, RuntimeResult, Value};
+use bebion_gc::GcHandle;
+use std::collections::HashMap;
+
+pub(super) fn install(vm: &mut VirtualMachine) {
+    let prototype = vm.intrinsics().regexp_prototype;
+    let methods: [(&str, Builtin); 3] = [
+        ("exec", exec),
+        ("test", test),
+        ("toString", to_string),
+    ];
+    for (name, method) in methods {
+        vm.define_builtin(prototype, name, method);
+    }
+    define_to_string_tag(vm, prototype, "RegExp");
+}
+
+/// `RegExp.prototype.exec(string)`: the match and its captures, with the
+/// match's `index` and the `input`, or `null`
+fn exec(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let (handle, _) = this_regexp(vm, this, "exec")?;
+    let input = argument(args, 0).to_string();
+    let Some(found) = vm.regexp_exec(handle, &input)? else {
+        return Ok(Value::Null);
+    };
+
+    let captures = found
+        .captures
+        .into_iter()
+        .map(|capture| capture.map_or(Value::Undefined, Value::from))
+        .collect();
+    let result = vm.array_from_values(captures);
+    let index = vm.value_to_handle(Value::Number(found.index as f64));
+    let input = vm.value_to_handle(Value::from(input));
+    let details = vm.create_object(HashMap::from([("index".to_string(), index), ("input".to_string(), input)]));
+
+    let array_prototype = vm.intrinsics().array_prototype;
+    let mut gc = vm.gc().lock().unwrap();
+    gc.set_prototype(details, Some(array_prototype));
+    if let Value::Object(array) = result {
+        gc.set_prototype(array, Some(details));
+    }
+    Ok(result)
+}
+
+/// `RegExp.prototype.test(string)`
+fn test(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let (handle, _) = this_regexp(vm, this, "test")?;
+    let input = argument(args, 0).to_string();
+    Ok(Value::Boolean(vm.regexp_exec(handle, &input)?.is_some()))
+}
+
+/// `RegExp.prototype.toString()`: `/source/flags`
+fn to_string(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let (_, regexp) = this_regexp(vm, this, "toString")?;
+    Ok(Value::from(regexp.to_string()))
+}
+
+/// The receiver, which must be a `RegExp` object, and its compiled form
+fn this_regexp(vm: &mut VirtualMachine, this: &Value, method: &str) -> RuntimeResult<(GcHandle, RegExp)> {
+    match (this, vm.compiled_regexp(this)?) {
+        (Value::Object(handle), Some(regexp)) => Ok((*handle, regexp)),
+        _ => Err(RuntimeError::TypeError(format!(
+            "RegExp.prototype.{} called on incompatible receiver {}",
+            method,
+            this.to_string()
+        ))),
+    }
+}
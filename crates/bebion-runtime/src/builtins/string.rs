@@ -0,0 +1,460 @@
+//! `String.prototype` methods
+//!
+//! Indices and lengths count UTF-16 code units, as in JavaScript, and lone
+//! surrogates pass through untouched. Regular expressions match against the
+//! host form of the string, where a lone surrogate becomes U+FFFD; that is
+//! still one code unit, so match indices line up with the original.
+
+use super::{argument, integer, relative_index, to_uint32, Builtin};
+use crate::vm::VirtualMachine;
+use crate::{JsString, RuntimeError, RuntimeResult, Symbol, Value};
+
+/// Longest string `repeat` and `padStart`/`padEnd` build, in code units
+const MAX_STRING_LENGTH: usize = (1 << 30) - 25;
+
+pub(super) fn install(vm: &mut VirtualMachine) {
+    let prototype = vm.intrinsics().string_prototype;
+    let methods: [(&str, Builtin); 18] = [
+        ("slice", slice),
+        ("substring", substring),
+        ("split", split),
+        ("replace", replace),
+        ("trim", trim),
+        ("trimStart", trim_start),
+        ("trimEnd", trim_end),
+        ("padStart", pad_start),
+        ("padEnd", pad_end),
+        ("startsWith", starts_with),
+        ("endsWith", ends_with),
+        ("toUpperCase", to_upper_case),
+        ("toLowerCase", to_lower_case),
+        ("charAt", char_at),
+        ("charCodeAt", char_code_at),
+        ("codePointAt", code_point_at),
+        ("indexOf", index_of),
+        ("repeat", repeat),
+    ];
+    for (name, method) in methods {
+        vm.define_builtin(prototype, name, method);
+    }
+    vm.define_symbol_builtin(prototype, &Symbol::iterator(), iterator);
+}
+
+/// `String.prototype[Symbol.iterator]()`: the code points, as an array of
+/// strings
+fn iterator(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "[Symbol.iterator]")?;
+    let code_points = char::decode_utf16(s.code_units().iter().copied())
+        .map(|c| Value::from(c.unwrap_or(char::REPLACEMENT_CHARACTER).to_string()))
+        .collect();
+    Ok(vm.array_from_values(code_points))
+}
+
+/// The receiver as a string
+fn this_string(this: &Value, method: &str) -> RuntimeResult<JsString> {
+    match this {
+        Value::Null | Value::Undefined => Err(RuntimeError::TypeError(format!(
+            "String.prototype.{} called on null or undefined",
+            method
+        ))),
+        other => Ok(to_js_string(other)),
+    }
+}
+
+/// `ToString`, keeping a string's code units as they are
+fn to_js_string(value: &Value) -> JsString {
+    match value {
+        Value::String(s) => s.clone(),
+        other => JsString::from(other.to_string()),
+    }
+}
+
+/// `ToIntegerOrInfinity` of `value` clamped to `0..=len`; `default` when
+/// `value` is undefined
+fn clamped_index(value: &Value, len: usize, default: usize) -> RuntimeResult<usize> {
+    if matches!(value, Value::Undefined) {
+        return Ok(default);
+    }
+    Ok(integer(value)?.clamp(0.0, len as f64) as usize)
+}
+
+/// `String.prototype.slice(start, end)`
+fn slice(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "slice")?;
+    let start = relative_index(&argument(args, 0), s.len(), 0)?;
+    let end = relative_index(&argument(args, 1), s.len(), s.len())?;
+    Ok(Value::String(s.substring(start, end.max(start))))
+}
+
+/// `String.prototype.substring(start, end)`, which swaps reversed bounds
+fn substring(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "substring")?;
+    let start = clamped_index(&argument(args, 0), s.len(), 0)?;
+    let end = clamped_index(&argument(args, 1), s.len(), s.len())?;
+    Ok(Value::String(s.substring(start.min(end), start.max(end))))
+}
+
+/// `String.prototype.split(separator, limit)`
+fn split(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "split")?;
+    let separator = argument(args, 0);
+    let limit = match argument(args, 1) {
+        Value::Undefined => u32::MAX as usize,
+        limit => to_uint32(limit.to_number()?) as usize,
+    };
+
+    let mut pieces = Vec::new();
+    if limit == 0 {
+        return Ok(vm.array_from_values(pieces));
+    }
+
+    if let Some(regexp) = vm.compiled_regexp(&separator)? {
+        let input = s.to_rust_string();
+        let (mut last, mut from) = (0, 0);
+        while from < s.len() {
+            let Some(found) = regexp.exec_at(&input, from) else {
+                break;
+            };
+            // An empty match where the previous piece ended splits nothing
+            if found.index >= s.len() || found.end == last {
+                from = found.index.max(from) + 1;
+                continue;
+            }
+            pieces.push(Value::String(s.substring(last, found.index)));
+            for capture in found.captures.into_iter().skip(1) {
+                pieces.push(capture.map_or(Value::Undefined, |text| Value::String(JsString::from(text))));
+            }
+            if pieces.len() >= limit {
+                pieces.truncate(limit);
+                return Ok(vm.array_from_values(pieces));
+            }
+            last = found.end;
+            from = found.end;
+        }
+        pieces.push(Value::String(s.substring(last, s.len())));
+    } else if matches!(separator, Value::Undefined) {
+        pieces.push(Value::String(s));
+    } else {
+        let separator = to_js_string(&separator);
+        if separator.is_empty() {
+            pieces.extend((0..s.len()).map(|i| Value::String(s.char_at(i))));
+        } else {
+            let mut last = 0;
+            while let Some(index) = s.index_of(&separator, last) {
+                pieces.push(Value::String(s.substring(last, index)));
+                last = index + separator.len();
+            }
+            pieces.push(Value::String(s.substring(last, s.len())));
+        }
+    }
+
+    pieces.truncate(limit);
+    Ok(vm.array_from_values(pieces))
+}
+
+/// `String.prototype.replace(pattern, replacement)`. A string pattern
+/// replaces its first occurrence, and a `RegExp` its first match, or every
+/// match when global. The replacement is a function called with the match,
+/// the captures, the index and the whole string, or a string that may use
+/// the `$&`, `` $` ``, `$'`, `$n` and `$$` patterns.
+fn replace(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "replace")?;
+    let pattern = argument(args, 0);
+    let replacement = argument(args, 1);
+    let replace_with_function = vm.is_callable(&replacement);
+    let template = if replace_with_function {
+        JsString::new()
+    } else {
+        to_js_string(&replacement)
+    };
+
+    // Each match as its start, end and captures
+    let mut matches = Vec::new();
+    if let Some(regexp) = vm.compiled_regexp(&pattern)? {
+        let input = s.to_rust_string();
+        let mut from = 0;
+        while let Some(found) = regexp.exec_at(&input, from) {
+            let captures: Vec<_> = found
+                .captures
+                .into_iter()
+                .skip(1)
+                .map(|capture| capture.map_or(Value::Undefined, |text| Value::String(JsString::from(text))))
+                .collect();
+            let (index, end) = (found.index, found.end);
+            matches.push((index, end, captures));
+            if !regexp.is_global() {
+                break;
+            }
+            // Step past an empty match so the search advances
+            from = if end == index { end + 1 } else { end };
+            if from > s.len() {
+                break;
+            }
+        }
+    } else {
+        let search = to_js_string(&pattern);
+        if let Some(index) = s.index_of(&search, 0) {
+            matches.push((index, index + search.len(), Vec::new()));
+        }
+    }
+
+    let mut result = Vec::with_capacity(s.len());
+    let mut last = 0;
+    for (index, end, captures) in matches {
+        result.extend_from_slice(&s.code_units()[last..index]);
+        let matched = s.substring(index, end);
+        if replace_with_function {
+            let mut call_args = Vec::with_capacity(captures.len() + 3);
+            call_args.push(Value::String(matched));
+            call_args.extend(captures);
+            call_args.push(Value::Number(index as f64));
+            call_args.push(Value::String(s.clone()));
+            let replaced = vm.call_function(&replacement, &Value::Undefined, &call_args)?;
+            result.extend_from_slice(to_js_string(&replaced).code_units());
+        } else {
+            expand_replacement(&mut result, &template, &s, index, end, &captures);
+        }
+        last = end;
+    }
+    result.extend_from_slice(&s.code_units()[last..]);
+    Ok(Value::String(JsString::from_code_units(result)))
+}
+
+/// `GetSubstitution`: append `template` to `out` with its `$` patterns
+/// filled in from the match of `s[index..end]`
+fn expand_replacement(out: &mut Vec<u16>, template: &JsString, s: &JsString, index: usize, end: usize, captures: &[Value]) {
+    let units = template.code_units();
+    let digit = |unit: u16| (b'0' as u16..=b'9' as u16).contains(&unit).then(|| (unit - b'0' as u16) as usize);
+    let mut i = 0;
+    while i < units.len() {
+        if units[i] != b'$' as u16 || i + 1 == units.len() {
+            out.push(units[i]);
+            i += 1;
+            continue;
+        }
+
+        let next = units[i + 1];
+        match next {
+            0x24 => out.push(next),
+            0x26 => out.extend_from_slice(&s.code_units()[index..end]),
+            0x60 => out.extend_from_slice(&s.code_units()[..index]),
+            0x27 => out.extend_from_slice(&s.code_units()[end..]),
+            _ => {
+                // `$nn` when that group exists, else `$n`, else literal
+                let first = digit(next);
+                let two = first.zip(units.get(i + 2).and_then(|&unit| digit(unit))).map(|(a, b)| a * 10 + b);
+                let group = match (two, first) {
+                    (Some(n), _) if (1..=captures.len()).contains(&n) => Some((n, 3)),
+                    (_, Some(n)) if (1..=captures.len()).contains(&n) => Some((n, 2)),
+                    _ => None,
+                };
+                match group {
+                    Some((n, width)) => {
+                        if let Value::String(capture) = &captures[n - 1] {
+                            out.extend_from_slice(capture.code_units());
+                        }
+                        i += width;
+                    }
+                    None => {
+                        out.push(units[i]);
+                        i += 1;
+                    }
+                }
+                continue;
+            }
+        }
+        i += 2;
+    }
+}
+
+/// Whether `unit` is JavaScript white space or a line terminator
+fn is_white_space(unit: u16) -> bool {
+    match unit {
+        0xFEFF => true,
+        // U+0085 is Unicode white space but not JavaScript white space
+        0x0085 => false,
+        _ => char::from_u32(unit as u32).is_some_and(char::is_whitespace),
+    }
+}
+
+fn trimmed(s: &JsString, start: bool, end: bool) -> JsString {
+    let units = s.code_units();
+    let mut from = 0;
+    let mut to = units.len();
+    if start {
+        from = units.iter().position(|&unit| !is_white_space(unit)).unwrap_or(to);
+    }
+    if end {
+        to = units.iter().rposition(|&unit| !is_white_space(unit)).map_or(from, |last| last + 1);
+    }
+    s.substring(from, to.max(from))
+}
+
+/// `String.prototype.trim()`
+fn trim(_vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    Ok(Value::String(trimmed(&this_string(this, "trim")?, true, true)))
+}
+
+/// `String.prototype.trimStart()`
+fn trim_start(_vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    Ok(Value::String(trimmed(&this_string(this, "trimStart")?, true, false)))
+}
+
+/// `String.prototype.trimEnd()`
+fn trim_end(_vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    Ok(Value::String(trimmed(&this_string(this, "trimEnd")?, false, true)))
+}
+
+/// The fill `padStart` and `padEnd` add to reach `args[0]` code units, or
+/// `None` when the string is long enough already
+fn padding(s: &JsString, args: &[Value]) -> RuntimeResult<Option<Vec<u16>>> {
+    let target = integer(&argument(args, 0))?;
+    let fill = match argument(args, 1) {
+        Value::Undefined => JsString::from(" "),
+        fill => to_js_string(&fill),
+    };
+    if target <= s.len() as f64 || fill.is_empty() {
+        return Ok(None);
+    }
+    if target > MAX_STRING_LENGTH as f64 {
+        return Err(RuntimeError::RangeError("Invalid string length".to_string()));
+    }
+
+    let needed = target as usize - s.len();
+    Ok(Some(fill.code_units().iter().copied().cycle().take(needed).collect()))
+}
+
+/// `String.prototype.padStart(maxLength, fillString)`
+fn pad_start(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "padStart")?;
+    Ok(Value::String(match padding(&s, args)? {
+        Some(mut padded) => {
+            padded.extend_from_slice(s.code_units());
+            JsString::from_code_units(padded)
+        }
+        None => s,
+    }))
+}
+
+/// `String.prototype.padEnd(maxLength, fillString)`
+fn pad_end(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "padEnd")?;
+    Ok(Value::String(match padding(&s, args)? {
+        Some(fill) => {
+            let mut padded = s.code_units().to_vec();
+            padded.extend(fill);
+            JsString::from_code_units(padded)
+        }
+        None => s,
+    }))
+}
+
+/// The search string of `startsWith` and `endsWith`, which may not be a
+/// regular expression
+fn search_string(vm: &mut VirtualMachine, value: &Value, method: &str) -> RuntimeResult<JsString> {
+    if vm.compiled_regexp(value)?.is_some() {
+        return Err(RuntimeError::TypeError(format!(
+            "First argument to String.prototype.{} must not be a regular expression",
+            method
+        )));
+    }
+    Ok(to_js_string(value))
+}
+
+/// `String.prototype.startsWith(searchString, position)`
+fn starts_with(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "startsWith")?;
+    let search = search_string(vm, &argument(args, 0), "startsWith")?;
+    let start = clamped_index(&argument(args, 1), s.len(), 0)?;
+    Ok(Value::Boolean(s.code_units()[start..].starts_with(search.code_units())))
+}
+
+/// `String.prototype.endsWith(searchString, endPosition)`
+fn ends_with(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "endsWith")?;
+    let search = search_string(vm, &argument(args, 0), "endsWith")?;
+    let end = clamped_index(&argument(args, 1), s.len(), s.len())?;
+    Ok(Value::Boolean(s.code_units()[..end].ends_with(search.code_units())))
+}
+
+/// Map each code point through `convert`, keeping lone surrogates
+fn convert_case<I: Iterator<Item = char>>(s: &JsString, convert: impl Fn(char) -> I) -> JsString {
+    let mut units = Vec::with_capacity(s.len());
+    let mut buf = [0u16; 2];
+    for decoded in char::decode_utf16(s.code_units().iter().copied()) {
+        match decoded {
+            Ok(c) => {
+                for converted in convert(c) {
+                    units.extend_from_slice(converted.encode_utf16(&mut buf));
+                }
+            }
+            Err(lone) => units.push(lone.unpaired_surrogate()),
+        }
+    }
+    JsString::from_code_units(units)
+}
+
+/// `String.prototype.toUpperCase()`
+fn to_upper_case(_vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "toUpperCase")?;
+    Ok(Value::String(convert_case(&s, char::to_uppercase)))
+}
+
+/// `String.prototype.toLowerCase()`
+fn to_lower_case(_vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "toLowerCase")?;
+    Ok(Value::String(convert_case(&s, char::to_lowercase)))
+}
+
+/// The code unit index `args[0]`, `None` when out of range
+fn position(s: &JsString, args: &[Value]) -> RuntimeResult<Option<usize>> {
+    let position = integer(&argument(args, 0))?;
+    Ok((position >= 0.0 && position < s.len() as f64).then_some(position as usize))
+}
+
+/// `String.prototype.charAt(pos)`
+fn char_at(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "charAt")?;
+    Ok(Value::String(match position(&s, args)? {
+        Some(index) => s.char_at(index),
+        None => JsString::new(),
+    }))
+}
+
+/// `String.prototype.charCodeAt(pos)`, `NaN` when out of range
+fn char_code_at(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "charCodeAt")?;
+    let unit = position(&s, args)?.and_then(|index| s.char_code_at(index));
+    Ok(Value::Number(unit.map_or(f64::NAN, f64::from)))
+}
+
+/// `String.prototype.codePointAt(pos)`, `undefined` when out of range
+fn code_point_at(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "codePointAt")?;
+    let code_point = position(&s, args)?.and_then(|index| s.code_point_at(index));
+    Ok(code_point.map_or(Value::Undefined, |code_point| Value::Number(code_point as f64)))
+}
+
+/// `String.prototype.indexOf(searchString, position)`
+fn index_of(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "indexOf")?;
+    let search = to_js_string(&argument(args, 0));
+    let from = clamped_index(&argument(args, 1), s.len(), 0)?;
+    Ok(Value::Number(s.index_of(&search, from).map_or(-1.0, |index| index as f64)))
+}
+
+/// `String.prototype.repeat(count)`
+fn repeat(_vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let s = this_string(this, "repeat")?;
+    let count = integer(&argument(args, 0))?;
+    if count < 0.0 || count.is_infinite() {
+        return Err(RuntimeError::RangeError(format!("Invalid count value: {}", argument(args, 0).to_string())));
+    }
+    if s.is_empty() || count == 0.0 {
+        return Ok(Value::String(JsString::new()));
+    }
+    if count * s.len() as f64 > MAX_STRING_LENGTH as f64 {
+        return Err(RuntimeError::RangeError("Invalid string length".to_string()));
+    }
+    Ok(Value::String(JsString::from_code_units(s.code_units().repeat(count as usize))))
+}
@@ -0,0 +1,90 @@
+//! `Symbol` and the well-known symbols
+//!
+//! Function objects hold no properties of their own, so `Symbol`'s static
+//! members live on an object between it and `Function.prototype`.
+
+use super::{argument, define_to_string_tag};
+use crate::vm::VirtualMachine;
+use crate::{RuntimeError, RuntimeResult, Symbol, Value};
+use std::collections::HashMap;
+
+pub(super) fn install(vm: &mut VirtualMachine) {
+    let intrinsics = vm.intrinsics();
+
+    vm.define_builtin(intrinsics.symbol_prototype, "toString", to_string);
+    define_to_string_tag(vm, intrinsics.symbol_prototype, "Symbol");
+
+    let statics = vm.create_object(HashMap::from([("prototype".to_string(), intrinsics.symbol_prototype)]));
+    vm.define_builtin(statics, "for", symbol_for);
+    vm.define_builtin(statics, "keyFor", key_for);
+    for (name, symbol) in [("iterator", Symbol::iterator()), ("toStringTag", Symbol::to_string_tag())] {
+        let symbol = vm.value_to_handle(Value::Symbol(symbol));
+        vm.gc().lock().unwrap().set_property(statics, name, symbol);
+    }
+
+    let constructor = vm.create_builtin("Symbol", construct);
+    let mut gc = vm.gc().lock().unwrap();
+    gc.set_prototype(statics, Some(intrinsics.function_prototype));
+    gc.set_prototype(constructor, Some(statics));
+    // Globals are not traced, and the statics hang off the constructor
+    gc.add_root(constructor);
+    drop(gc);
+    vm.set_global("Symbol".to_string(), Value::Object(constructor));
+}
+
+/// `Symbol(description)`
+fn construct(_vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let description = match argument(args, 0) {
+        Value::Undefined => None,
+        Value::Symbol(_) => {
+            return Err(RuntimeError::TypeError("Cannot convert a Symbol value to a string".to_string()));
+        }
+        description => Some(description.to_string()),
+    };
+    Ok(Value::Symbol(Symbol::new(description)))
+}
+
+/// `Symbol.for(key)`: the symbol registered under `key`, registering a
+/// new one the first time
+fn symbol_for(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let key = match argument(args, 0) {
+        Value::Symbol(_) => {
+            return Err(RuntimeError::TypeError("Cannot convert a Symbol value to a string".to_string()));
+        }
+        key => key.to_string(),
+    };
+    let symbol = vm
+        .symbol_registry()
+        .entry(key.clone())
+        .or_insert_with(|| Symbol::new(Some(key)))
+        .clone();
+    Ok(Value::Symbol(symbol))
+}
+
+/// `Symbol.keyFor(symbol)`: the key `symbol` is registered under, or
+/// undefined when `Symbol.for` did not create it
+fn key_for(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let Value::Symbol(symbol) = argument(args, 0) else {
+        return Err(RuntimeError::TypeError(format!(
+            "{} is not a symbol",
+            argument(args, 0).to_string()
+        )));
+    };
+    let key = vm
+        .symbol_registry()
+        .iter()
+        .find(|(_, registered)| **registered == symbol)
+        .map(|(key, _)| Value::from(key.as_str()));
+    Ok(key.unwrap_or(Value::Undefined))
+}
+
+/// `Symbol.prototype.toString()`
+fn to_string(_vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    match this {
+        Value::Symbol(symbol) => Ok(Value::from(symbol.to_string())),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Symbol.prototype.toString requires that 'this' be a Symbol, not {}",
+            this.to_string()
+        ))),
+    }
+}
@@ -0,0 +1,649 @@
+//! `ArrayBuffer`, the typed arrays and `DataView`
+//!
+//! Typed arrays and data views are windows onto an `ArrayBuffer`'s bytes,
+//! which the collector stores once and lets every view read and write in
+//! place. Typed arrays use the platform's byte order; `DataView` methods
+//! take the order as an argument and default to big-endian. As with the
+//! collections, the globals are functions that build the object when
+//! called.
+//!
+//! `transfer` moves a buffer's bytes to a new buffer without copying them
+//! and detaches the old one, whose views then read as empty.
+//!
+//! `BigInt64Array`, `BigUint64Array` and `DataView`'s `getBigInt64` family
+//! are missing until there is a BigInt value to read 64-bit elements as;
+//! numbers would lose precision past 2^53.
+
+use super::{argument, define_to_string_tag, integer, relative_index, to_uint32, Builtin};
+use crate::vm::VirtualMachine;
+use crate::{RuntimeError, RuntimeResult, Symbol, Value};
+use bebion_gc::{GarbageCollector, GcHandle, GcObjectType, TypedArrayKind};
+use std::ops::Range;
+
+pub(super) fn install(vm: &mut VirtualMachine) {
+    let intrinsics = vm.intrinsics();
+
+    vm.define_builtin(intrinsics.array_buffer_prototype, "slice", buffer_slice);
+    vm.define_builtin(intrinsics.array_buffer_prototype, "transfer", buffer_transfer);
+    vm.define_builtin(intrinsics.array_buffer_prototype, "transferToFixedLength", buffer_transfer);
+
+    let typed_array_methods: [(&str, Builtin); 4] = [
+        ("set", set),
+        ("subarray", subarray),
+        ("slice", slice),
+        ("fill", fill),
+    ];
+    for (name, method) in typed_array_methods {
+        vm.define_builtin(intrinsics.typed_array_prototype, name, method);
+    }
+    vm.define_symbol_builtin(intrinsics.typed_array_prototype, &Symbol::iterator(), iterator);
+    define_to_string_tag(vm, intrinsics.array_buffer_prototype, "ArrayBuffer");
+    define_to_string_tag(vm, intrinsics.data_view_prototype, "DataView");
+
+    let data_view_methods: [(&str, Builtin); 16] = [
+        ("getInt8", get_int8),
+        ("getUint8", get_uint8),
+        ("getInt16", get_int16),
+        ("getUint16", get_uint16),
+        ("getInt32", get_int32),
+        ("getUint32", get_uint32),
+        ("getFloat32", get_float32),
+        ("getFloat64", get_float64),
+        ("setInt8", set_int8),
+        ("setUint8", set_uint8),
+        ("setInt16", set_int16),
+        ("setUint16", set_uint16),
+        ("setInt32", set_int32),
+        ("setUint32", set_uint32),
+        ("setFloat32", set_float32),
+        ("setFloat64", set_float64),
+    ];
+    for (name, method) in data_view_methods {
+        vm.define_builtin(intrinsics.data_view_prototype, name, method);
+    }
+
+    let constructors: [(&str, Builtin); 11] = [
+        ("ArrayBuffer", construct_array_buffer),
+        ("DataView", construct_data_view),
+        ("Int8Array", construct_int8),
+        ("Uint8Array", construct_uint8),
+        ("Uint8ClampedArray", construct_uint8_clamped),
+        ("Int16Array", construct_int16),
+        ("Uint16Array", construct_uint16),
+        ("Int32Array", construct_int32),
+        ("Uint32Array", construct_uint32),
+        ("Float32Array", construct_float32),
+        ("Float64Array", construct_float64),
+    ];
+    for (name, constructor) in constructors {
+        let function = vm.create_builtin(name, constructor);
+        vm.set_global(name.to_string(), Value::Object(function));
+    }
+}
+
+/// A typed array's place in its buffer
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct View {
+    pub kind: TypedArrayKind,
+    pub buffer: GcHandle,
+    pub byte_offset: usize,
+    pub length: usize,
+}
+
+impl View {
+    /// The view `handle` is, if it is a typed array. Views of a detached
+    /// buffer are empty.
+    pub(crate) fn of(gc: &GarbageCollector, handle: GcHandle) -> Option<View> {
+        match gc.get_object_type(handle)? {
+            GcObjectType::TypedArray { kind, buffer, byte_offset, length } => {
+                let detached = matches!(gc.get_object_type(*buffer), Some(GcObjectType::DetachedArrayBuffer));
+                Some(View {
+                    kind: *kind,
+                    buffer: *buffer,
+                    byte_offset: if detached { 0 } else { *byte_offset },
+                    length: if detached { 0 } else { *length },
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn byte_length(&self) -> usize {
+        self.length * self.kind.element_size()
+    }
+
+    /// Element `index`, or `None` past the end
+    pub(crate) fn get(&self, gc: &GarbageCollector, index: usize) -> Option<f64> {
+        if index >= self.length {
+            return None;
+        }
+        let Some(GcObjectType::ArrayBuffer(bytes)) = gc.get_object_type(self.buffer) else {
+            return None;
+        };
+        let start = self.byte_offset + index * self.kind.element_size();
+        let bytes = bytes.get(start..start + self.kind.element_size())?;
+        Some(decode(self.kind, bytes, NATIVE_LITTLE_ENDIAN))
+    }
+
+    /// Set element `index` to `n`, converted to the element type; writes
+    /// past the end are ignored
+    pub(crate) fn set(&self, gc: &mut GarbageCollector, index: usize, n: f64) {
+        if index >= self.length {
+            return;
+        }
+        let start = self.byte_offset + index * self.kind.element_size();
+        if let Some(bytes) = gc.buffer_bytes_mut(self.buffer) {
+            if let Some(element) = bytes.get_mut(start..start + self.kind.element_size()) {
+                encode(self.kind, n, NATIVE_LITTLE_ENDIAN, element);
+            }
+        }
+    }
+
+    fn elements(&self, gc: &GarbageCollector) -> Vec<f64> {
+        (0..self.length).filter_map(|index| self.get(gc, index)).collect()
+    }
+}
+
+const NATIVE_LITTLE_ENDIAN: bool = cfg!(target_endian = "little");
+
+/// The number `bytes` hold as a `kind` element
+fn decode(kind: TypedArrayKind, bytes: &[u8], little_endian: bool) -> f64 {
+    let mut raw = [0u8; 8];
+    let size = kind.element_size();
+    raw[..size].copy_from_slice(&bytes[..size]);
+    if little_endian != NATIVE_LITTLE_ENDIAN {
+        raw[..size].reverse();
+    }
+    match kind {
+        TypedArrayKind::Int8 => raw[0] as i8 as f64,
+        TypedArrayKind::Uint8 | TypedArrayKind::Uint8Clamped => raw[0] as f64,
+        TypedArrayKind::Int16 => i16::from_ne_bytes([raw[0], raw[1]]) as f64,
+        TypedArrayKind::Uint16 => u16::from_ne_bytes([raw[0], raw[1]]) as f64,
+        TypedArrayKind::Int32 => i32::from_ne_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+        TypedArrayKind::Uint32 => u32::from_ne_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+        TypedArrayKind::Float32 => f32::from_ne_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+        TypedArrayKind::Float64 => f64::from_ne_bytes(raw),
+    }
+}
+
+/// Write `n`, converted to a `kind` element, into `bytes`
+fn encode(kind: TypedArrayKind, n: f64, little_endian: bool, bytes: &mut [u8]) {
+    let mut raw = [0u8; 8];
+    let size = kind.element_size();
+    match kind {
+        TypedArrayKind::Int8 | TypedArrayKind::Uint8 => raw[0] = to_uint32(n) as u8,
+        TypedArrayKind::Uint8Clamped => raw[0] = clamp_to_uint8(n),
+        TypedArrayKind::Int16 | TypedArrayKind::Uint16 => {
+            raw[..2].copy_from_slice(&(to_uint32(n) as u16).to_ne_bytes())
+        }
+        TypedArrayKind::Int32 | TypedArrayKind::Uint32 => raw[..4].copy_from_slice(&to_uint32(n).to_ne_bytes()),
+        TypedArrayKind::Float32 => raw[..4].copy_from_slice(&(n as f32).to_ne_bytes()),
+        TypedArrayKind::Float64 => raw.copy_from_slice(&n.to_ne_bytes()),
+    }
+    if little_endian != NATIVE_LITTLE_ENDIAN {
+        raw[..size].reverse();
+    }
+    bytes[..size].copy_from_slice(&raw[..size]);
+}
+
+/// `ToUint8Clamp`: clamp to 0..=255, rounding halves to even
+fn clamp_to_uint8(n: f64) -> u8 {
+    if n.is_nan() || n <= 0.0 {
+        return 0;
+    }
+    if n >= 255.0 {
+        return 255;
+    }
+    let floor = n.floor();
+    let rounded = match (n - floor).partial_cmp(&0.5) {
+        Some(std::cmp::Ordering::Less) => floor,
+        Some(std::cmp::Ordering::Greater) => floor + 1.0,
+        _ if floor % 2.0 == 0.0 => floor,
+        _ => floor + 1.0,
+    };
+    rounded as u8
+}
+
+/// `ToIndex`: a non-negative integer size or offset
+fn to_index(value: &Value, what: &str) -> RuntimeResult<usize> {
+    if matches!(value, Value::Undefined) {
+        return Ok(0);
+    }
+    let n = integer(value)?;
+    if !(0.0..=9007199254740991.0).contains(&n) {
+        return Err(RuntimeError::RangeError(format!("Invalid {}: {}", what, value.to_string())));
+    }
+    Ok(n as usize)
+}
+
+/// The receiver of a `%TypedArray%.prototype` method
+fn view(vm: &VirtualMachine, this: &Value, method: &str) -> RuntimeResult<View> {
+    let view = match this {
+        Value::Object(handle) => View::of(&vm.gc().lock().unwrap(), *handle),
+        _ => None,
+    };
+    view.ok_or_else(|| RuntimeError::TypeError(format!("%TypedArray%.prototype.{} called on non-typed-array", method)))
+}
+
+/// The length in bytes of an `ArrayBuffer`
+fn buffer_length(vm: &VirtualMachine, value: &Value) -> Option<usize> {
+    let Value::Object(handle) = value else {
+        return None;
+    };
+    match vm.gc().lock().unwrap().get_object_type(*handle)? {
+        GcObjectType::ArrayBuffer(bytes) => Some(bytes.len()),
+        _ => None,
+    }
+}
+
+fn is_detached(vm: &VirtualMachine, value: &Value) -> bool {
+    let Value::Object(handle) = value else {
+        return false;
+    };
+    matches!(vm.gc().lock().unwrap().get_object_type(*handle), Some(GcObjectType::DetachedArrayBuffer))
+}
+
+fn detached_error(operation: &str) -> RuntimeError {
+    RuntimeError::TypeError(format!("Cannot perform {} on a detached ArrayBuffer", operation))
+}
+
+/// The buffer behind `value` and the bytes of it `value` spans: all of an
+/// `ArrayBuffer`, or a typed array's or `DataView`'s window onto its
+/// buffer. `None` for anything else, including detached buffers.
+pub(crate) fn byte_window(gc: &GarbageCollector, value: &Value) -> Option<(GcHandle, Range<usize>)> {
+    let Value::Object(handle) = value else {
+        return None;
+    };
+    let (buffer, window) = match gc.get_object_type(*handle)? {
+        GcObjectType::ArrayBuffer(bytes) => (*handle, 0..bytes.len()),
+        GcObjectType::TypedArray { .. } => {
+            let view = View::of(gc, *handle)?;
+            (view.buffer, view.byte_offset..view.byte_offset + view.byte_length())
+        }
+        GcObjectType::DataView { buffer, byte_offset, byte_length } => {
+            (*buffer, *byte_offset..*byte_offset + *byte_length)
+        }
+        _ => return None,
+    };
+    match gc.get_object_type(buffer)? {
+        GcObjectType::ArrayBuffer(bytes) if window.end <= bytes.len() => Some((buffer, window)),
+        _ => None,
+    }
+}
+
+/// A typed array over all of `buffer`, which nothing references yet, so
+/// it is rooted while the view is allocated
+fn allocate_view_of(gc: &mut GarbageCollector, kind: TypedArrayKind, buffer: GcHandle, length: usize) -> GcHandle {
+    gc.add_root(buffer);
+    let handle = gc.allocate_typed_array(kind, buffer, 0, length);
+    gc.remove_root(buffer);
+    handle
+}
+
+/// A new typed array of `kind` over a fresh buffer holding `elements`
+fn typed_array_from(vm: &mut VirtualMachine, kind: TypedArrayKind, elements: &[f64]) -> Value {
+    let intrinsics = vm.intrinsics();
+    let mut gc = vm.gc().lock().unwrap();
+    let buffer = gc.allocate_array_buffer(elements.len() * kind.element_size());
+    gc.set_prototype(buffer, Some(intrinsics.array_buffer_prototype));
+    let handle = allocate_view_of(&mut gc, kind, buffer, elements.len());
+    gc.set_prototype(handle, Some(intrinsics.typed_array_prototype));
+    let view = View { kind, buffer, byte_offset: 0, length: elements.len() };
+    for (index, n) in elements.iter().enumerate() {
+        view.set(&mut gc, index, *n);
+    }
+    Value::Object(handle)
+}
+
+/// An `ArrayBuffer` that owns `bytes`, without copying them
+pub(crate) fn array_buffer_from_bytes(vm: &mut VirtualMachine, bytes: Vec<u8>) -> Value {
+    let prototype = vm.intrinsics().array_buffer_prototype;
+    let mut gc = vm.gc().lock().unwrap();
+    let handle = gc.allocate(GcObjectType::ArrayBuffer(bytes));
+    gc.set_prototype(handle, Some(prototype));
+    Value::Object(handle)
+}
+
+/// A `Uint8Array` over a new buffer holding `bytes`
+pub(crate) fn uint8_array_from_bytes(vm: &mut VirtualMachine, bytes: Vec<u8>) -> Value {
+    let intrinsics = vm.intrinsics();
+    let length = bytes.len();
+    let mut gc = vm.gc().lock().unwrap();
+    let buffer = gc.allocate(GcObjectType::ArrayBuffer(bytes));
+    gc.set_prototype(buffer, Some(intrinsics.array_buffer_prototype));
+    let handle = allocate_view_of(&mut gc, TypedArrayKind::Uint8, buffer, length);
+    gc.set_prototype(handle, Some(intrinsics.typed_array_prototype));
+    Value::Object(handle)
+}
+
+/// A new typed array of `kind` over `length` elements of `buffer`
+fn typed_array_over(vm: &mut VirtualMachine, kind: TypedArrayKind, buffer: GcHandle, byte_offset: usize, length: usize) -> Value {
+    let prototype = vm.intrinsics().typed_array_prototype;
+    let mut gc = vm.gc().lock().unwrap();
+    let handle = gc.allocate_typed_array(kind, buffer, byte_offset, length);
+    gc.set_prototype(handle, Some(prototype));
+    Value::Object(handle)
+}
+
+/// `ArrayBuffer(byteLength)`
+fn construct_array_buffer(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let byte_length = to_index(&argument(args, 0), "array buffer length")?;
+    let prototype = vm.intrinsics().array_buffer_prototype;
+    let mut gc = vm.gc().lock().unwrap();
+    let handle = gc.allocate_array_buffer(byte_length);
+    gc.set_prototype(handle, Some(prototype));
+    Ok(Value::Object(handle))
+}
+
+/// `ArrayBuffer.prototype.slice(begin, end)`: a copy of the bytes
+fn buffer_slice(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    if is_detached(vm, this) {
+        return Err(detached_error("ArrayBuffer.prototype.slice"));
+    }
+    let len = buffer_length(vm, this).ok_or_else(|| {
+        RuntimeError::TypeError("ArrayBuffer.prototype.slice called on non-ArrayBuffer".to_string())
+    })?;
+    let begin = relative_index(&argument(args, 0), len, 0)?;
+    let end = relative_index(&argument(args, 1), len, len)?.max(begin);
+
+    let Value::Object(source) = this else {
+        unreachable!("buffer_length only accepts objects");
+    };
+    let prototype = vm.intrinsics().array_buffer_prototype;
+    let mut gc = vm.gc().lock().unwrap();
+    let bytes = match gc.get_object_type(*source) {
+        Some(GcObjectType::ArrayBuffer(bytes)) => bytes[begin..end].to_vec(),
+        _ => Vec::new(),
+    };
+    let handle = gc.allocate(GcObjectType::ArrayBuffer(bytes));
+    gc.set_prototype(handle, Some(prototype));
+    Ok(Value::Object(handle))
+}
+
+/// `ArrayBuffer.prototype.transfer(newLength)`: a new buffer that takes
+/// over this one's bytes, truncated or zero-extended to `newLength`,
+/// leaving this one detached
+fn buffer_transfer(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    if is_detached(vm, this) {
+        return Err(detached_error("ArrayBuffer.prototype.transfer"));
+    }
+    let len = buffer_length(vm, this).ok_or_else(|| {
+        RuntimeError::TypeError("ArrayBuffer.prototype.transfer called on non-ArrayBuffer".to_string())
+    })?;
+    let new_length = match argument(args, 0) {
+        Value::Undefined => len,
+        length => to_index(&length, "array buffer length")?,
+    };
+
+    let Value::Object(source) = this else {
+        unreachable!("buffer_length only accepts objects");
+    };
+    let mut bytes = vm.gc().lock().unwrap().detach_buffer(*source).unwrap_or_default();
+    bytes.resize(new_length, 0);
+    Ok(array_buffer_from_bytes(vm, bytes))
+}
+
+/// `new <Kind>Array(length | array | typedArray | buffer, byteOffset, length)`
+fn construct(vm: &mut VirtualMachine, kind: TypedArrayKind, args: &[Value]) -> RuntimeResult<Value> {
+    let source = argument(args, 0);
+    if is_detached(vm, &source) {
+        return Err(detached_error(&format!("new {}", kind.name())));
+    }
+    let element_size = kind.element_size();
+
+    if let Some(buffer_len) = buffer_length(vm, &source) {
+        let Value::Object(buffer) = source else {
+            unreachable!("buffer_length only accepts objects");
+        };
+        let byte_offset = to_index(&argument(args, 1), "typed array offset")?;
+        if byte_offset % element_size != 0 {
+            return Err(RuntimeError::RangeError(format!(
+                "start offset of {} should be a multiple of {}",
+                kind.name(),
+                element_size
+            )));
+        }
+        let length = match argument(args, 2) {
+            Value::Undefined => {
+                if buffer_len % element_size != 0 {
+                    return Err(RuntimeError::RangeError(format!(
+                        "byte length of {} should be a multiple of {}",
+                        kind.name(),
+                        element_size
+                    )));
+                }
+                buffer_len.checked_sub(byte_offset).map(|bytes| bytes / element_size)
+            }
+            length => {
+                let length = to_index(&length, "typed array length")?;
+                (byte_offset + length * element_size <= buffer_len).then_some(length)
+            }
+        };
+        let length = length.ok_or_else(|| {
+            RuntimeError::RangeError(format!("Invalid typed array length: {}", argument(args, 2).to_string()))
+        })?;
+        return Ok(typed_array_over(vm, kind, buffer, byte_offset, length));
+    }
+
+    let elements = match &source {
+        Value::Object(handle) => {
+            let view = View::of(&vm.gc().lock().unwrap(), *handle);
+            match view {
+                Some(view) => view.elements(&vm.gc().lock().unwrap()),
+                None => vm
+                    .array_elements(&source)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(Value::to_number)
+                    .collect::<RuntimeResult<_>>()?,
+            }
+        }
+        _ => vec![0.0; to_index(&source, "typed array length")?],
+    };
+    Ok(typed_array_from(vm, kind, &elements))
+}
+
+macro_rules! typed_array_constructors {
+    ($($name:ident => $kind:ident,)*) => {
+        $(
+            fn $name(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+                construct(vm, TypedArrayKind::$kind, args)
+            }
+        )*
+    };
+}
+
+typed_array_constructors! {
+    construct_int8 => Int8,
+    construct_uint8 => Uint8,
+    construct_uint8_clamped => Uint8Clamped,
+    construct_int16 => Int16,
+    construct_uint16 => Uint16,
+    construct_int32 => Int32,
+    construct_uint32 => Uint32,
+    construct_float32 => Float32,
+    construct_float64 => Float64,
+}
+
+/// `%TypedArray%.prototype.set(source, offset)`: copy an array or typed
+/// array's elements in, starting at `offset`
+fn set(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let target = view(vm, this, "set")?;
+    let source = argument(args, 0);
+    let offset = to_index(&argument(args, 1), "offset")?;
+
+    let source_view = match &source {
+        Value::Object(handle) => View::of(&vm.gc().lock().unwrap(), *handle),
+        _ => None,
+    };
+    let elements = match source_view {
+        Some(view) => view.elements(&vm.gc().lock().unwrap()),
+        None => vm
+            .array_elements(&source)
+            .ok_or_else(|| RuntimeError::TypeError(format!("{} is not an array", source.to_string())))?
+            .iter()
+            .map(Value::to_number)
+            .collect::<RuntimeResult<_>>()?,
+    };
+    if offset + elements.len() > target.length {
+        return Err(RuntimeError::RangeError("offset is out of bounds".to_string()));
+    }
+
+    let mut gc = vm.gc().lock().unwrap();
+    for (index, n) in elements.into_iter().enumerate() {
+        target.set(&mut gc, offset + index, n);
+    }
+    Ok(Value::Undefined)
+}
+
+/// `%TypedArray%.prototype.subarray(begin, end)`: a view of the same
+/// buffer
+fn subarray(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let view = view(vm, this, "subarray")?;
+    let begin = relative_index(&argument(args, 0), view.length, 0)?;
+    let end = relative_index(&argument(args, 1), view.length, view.length)?.max(begin);
+    let byte_offset = view.byte_offset + begin * view.kind.element_size();
+    Ok(typed_array_over(vm, view.kind, view.buffer, byte_offset, end - begin))
+}
+
+/// `%TypedArray%.prototype.slice(begin, end)`: a copy in a new buffer
+fn slice(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let view = view(vm, this, "slice")?;
+    let begin = relative_index(&argument(args, 0), view.length, 0)?;
+    let end = relative_index(&argument(args, 1), view.length, view.length)?.max(begin);
+    let elements = view.elements(&vm.gc().lock().unwrap());
+    Ok(typed_array_from(vm, view.kind, &elements[begin..end]))
+}
+
+/// `%TypedArray%.prototype[Symbol.iterator]()`: the elements, as an array
+fn iterator(vm: &mut VirtualMachine, this: &Value, _args: &[Value]) -> RuntimeResult<Value> {
+    let view = view(vm, this, "[Symbol.iterator]")?;
+    let elements = view.elements(&vm.gc().lock().unwrap()).into_iter().map(Value::Number).collect();
+    Ok(vm.array_from_values(elements))
+}
+
+/// `%TypedArray%.prototype.fill(value, begin, end)`
+fn fill(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let view = view(vm, this, "fill")?;
+    let n = argument(args, 0).to_number()?;
+    let begin = relative_index(&argument(args, 1), view.length, 0)?;
+    let end = relative_index(&argument(args, 2), view.length, view.length)?;
+    let mut gc = vm.gc().lock().unwrap();
+    for index in begin..end {
+        view.set(&mut gc, index, n);
+    }
+    Ok(this.clone())
+}
+
+/// `new DataView(buffer, byteOffset, byteLength)`
+fn construct_data_view(vm: &mut VirtualMachine, _this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    let buffer = argument(args, 0);
+    if is_detached(vm, &buffer) {
+        return Err(detached_error("new DataView"));
+    }
+    let buffer_len = buffer_length(vm, &buffer)
+        .ok_or_else(|| RuntimeError::TypeError("First argument to DataView constructor must be an ArrayBuffer".to_string()))?;
+    let Value::Object(buffer) = buffer else {
+        unreachable!("buffer_length only accepts objects");
+    };
+
+    let byte_offset = to_index(&argument(args, 1), "DataView offset")?;
+    if byte_offset > buffer_len {
+        return Err(RuntimeError::RangeError(format!(
+            "Start offset {} is outside the bounds of the buffer",
+            byte_offset
+        )));
+    }
+    let byte_length = match argument(args, 2) {
+        Value::Undefined => buffer_len - byte_offset,
+        length => to_index(&length, "DataView length")?,
+    };
+    if byte_offset + byte_length > buffer_len {
+        return Err(RuntimeError::RangeError(format!("Invalid DataView length {}", byte_length)));
+    }
+
+    let prototype = vm.intrinsics().data_view_prototype;
+    let mut gc = vm.gc().lock().unwrap();
+    let handle = gc.allocate_data_view(buffer, byte_offset, byte_length);
+    gc.set_prototype(handle, Some(prototype));
+    Ok(Value::Object(handle))
+}
+
+/// The buffer and the absolute offset of the `kind` element at
+/// `byteOffset` (the first argument) of the receiving `DataView`
+fn data_view_slot(
+    vm: &VirtualMachine,
+    this: &Value,
+    args: &[Value],
+    kind: TypedArrayKind,
+    method: &str,
+) -> RuntimeResult<(GcHandle, usize)> {
+    let view = match this {
+        Value::Object(handle) => match vm.gc().lock().unwrap().get_object_type(*handle) {
+            Some(GcObjectType::DataView { buffer, byte_offset, byte_length }) => {
+                Some((*buffer, *byte_offset, *byte_length))
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    let (buffer, view_offset, view_length) = view
+        .ok_or_else(|| RuntimeError::TypeError(format!("DataView.prototype.{} called on non-DataView", method)))?;
+    if is_detached(vm, &Value::Object(buffer)) {
+        return Err(detached_error(&format!("DataView.prototype.{}", method)));
+    }
+
+    let offset = to_index(&argument(args, 0), "DataView offset")?;
+    if offset + kind.element_size() > view_length {
+        return Err(RuntimeError::RangeError("Offset is outside the bounds of the DataView".to_string()));
+    }
+    Ok((buffer, view_offset + offset))
+}
+
+/// `DataView.prototype.get<Kind>(byteOffset, littleEndian)`
+fn data_view_get(vm: &mut VirtualMachine, this: &Value, args: &[Value], kind: TypedArrayKind, method: &str) -> RuntimeResult<Value> {
+    let (buffer, offset) = data_view_slot(vm, this, args, kind, method)?;
+    let little_endian = argument(args, 1).to_boolean();
+    let gc = vm.gc().lock().unwrap();
+    let Some(GcObjectType::ArrayBuffer(bytes)) = gc.get_object_type(buffer) else {
+        return Err(RuntimeError::TypeError("DataView buffer is gone".to_string()));
+    };
+    Ok(Value::Number(decode(kind, &bytes[offset..], little_endian)))
+}
+
+/// `DataView.prototype.set<Kind>(byteOffset, value, littleEndian)`
+fn data_view_set(vm: &mut VirtualMachine, this: &Value, args: &[Value], kind: TypedArrayKind, method: &str) -> RuntimeResult<Value> {
+    let (buffer, offset) = data_view_slot(vm, this, args, kind, method)?;
+    let n = argument(args, 1).to_number()?;
+    let little_endian = argument(args, 2).to_boolean();
+    let mut gc = vm.gc().lock().unwrap();
+    if let Some(bytes) = gc.buffer_bytes_mut(buffer) {
+        encode(kind, n, little_endian, &mut bytes[offset..]);
+    }
+    Ok(Value::Undefined)
+}
+
+macro_rules! data_view_accessors {
+    ($($get:ident, $set:ident => $kind:ident,)*) => {
+        $(
+            fn $get(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+                data_view_get(vm, this, args, TypedArrayKind::$kind, concat!("get", stringify!($kind)))
+            }
+
+            fn $set(vm: &mut VirtualMachine, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+                data_view_set(vm, this, args, TypedArrayKind::$kind, concat!("set", stringify!($kind)))
+            }
+        )*
+    };
+}
+
+data_view_accessors! {
+    get_int8, set_int8 => Int8,
+    get_uint8, set_uint8 => Uint8,
+    get_int16, set_int16 => Int16,
+    get_uint16, set_uint16 => Uint16,
+    get_int32, set_int32 => Int32,
+    get_uint32, set_uint32 => Uint32,
+    get_float32, set_float32 => Float32,
+    get_float64, set_float64 => Float64,
+}
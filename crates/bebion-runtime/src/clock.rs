@@ -0,0 +1,143 @@
+//! Host wall clock shared by `Date` and the event loop
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Process-wide time for the engine.
+///
+/// Reads the system clock unless frozen, for reproducible runs, at a fixed
+/// instant. A frozen clock only moves when advanced, which the event loop
+/// does in virtual time mode as it jumps to each timer.
+pub struct HostClock {
+    /// Milliseconds since the epoch the clock is frozen at
+    frozen: Mutex<Option<f64>>,
+}
+
+impl HostClock {
+    pub fn new() -> Self {
+        Self { frozen: Mutex::new(None) }
+    }
+
+    /// Milliseconds since the epoch, as `Date.now` reports them
+    pub fn now(&self) -> f64 {
+        if let Some(frozen) = *self.frozen.lock().unwrap() {
+            return frozen;
+        }
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |elapsed| elapsed.as_millis() as f64)
+    }
+
+    /// Stop the clock at `epoch_ms` milliseconds since the epoch
+    pub fn freeze(&self, epoch_ms: f64) {
+        *self.frozen.lock().unwrap() = Some(epoch_ms);
+    }
+
+    /// Go back to the system clock
+    pub fn unfreeze(&self) {
+        *self.frozen.lock().unwrap() = None;
+    }
+
+    /// The instant the clock is frozen at, if it is
+    pub fn frozen_at(&self) -> Option<f64> {
+        *self.frozen.lock().unwrap()
+    }
+
+    /// Move a frozen clock forward by `elapsed`; a running clock is left
+    /// alone
+    pub fn advance(&self, elapsed: Duration) {
+        if let Some(frozen) = self.frozen.lock().unwrap().as_mut() {
+            *frozen += elapsed.as_millis() as f64;
+        }
+    }
+}
+
+impl Default for HostClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Milliseconds since the epoch for an ISO 8601 timestamp: a date
+/// (`2024-01-31`), optionally followed by `T`, a time with optional
+/// fraction and a `Z` or `±HH:MM` offset. Times without an offset are UTC.
+pub fn parse_iso_timestamp(timestamp: &str) -> Option<f64> {
+    let (date, time) = match timestamp.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (timestamp, None),
+    };
+
+    let mut fields = date.splitn(3, '-');
+    let year: i64 = fields.next()?.parse().ok()?;
+    let month: u32 = fields.next()?.parse().ok()?;
+    let day: u32 = fields.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    let mut ms = days_from_civil(year, month, day) as f64 * 86_400_000.0;
+
+    if let Some(time) = time {
+        let (time, offset_ms) = if let Some(time) = time.strip_suffix('Z') {
+            (time, 0.0)
+        } else if let Some(sign_at) = time.rfind(['+', '-']) {
+            let (time, offset) = time.split_at(sign_at);
+            let sign = if offset.starts_with('-') { -1.0 } else { 1.0 };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let hours: u32 = hours.parse().ok()?;
+            let minutes: u32 = minutes.parse().ok()?;
+            (time, sign * f64::from(hours * 60 + minutes) * 60_000.0)
+        } else {
+            (time, 0.0)
+        };
+
+        let mut fields = time.splitn(3, ':');
+        let hours: u32 = fields.next()?.parse().ok()?;
+        let minutes: u32 = fields.next()?.parse().ok()?;
+        let seconds: f64 = fields.next().map_or(Some(0.0), |seconds| seconds.parse().ok())?;
+        if hours > 23 || minutes > 59 || !(0.0..60.0).contains(&seconds) {
+            return None;
+        }
+        ms += f64::from(hours * 3600 + minutes * 60) * 1000.0 + (seconds * 1000.0).floor() - offset_ms;
+    }
+    Some(ms)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to the given date in the proleptic Gregorian
+/// calendar
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year, month and day `days` after 1970-01-01, the inverse of
+/// `days_from_civil`
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = (if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 }) as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
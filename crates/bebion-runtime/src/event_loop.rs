@@ -1,6 +1,6 @@
 //! Event loop implementation for async/await and Promises
 
-use crate::{RuntimeError, RuntimeResult};
+use crate::{HostClock, RuntimeError, RuntimeResult};
 use futures::future::{BoxFuture, Future};
 use std::collections::{HashMap, VecDeque};
 use std::pin::Pin;
@@ -9,6 +9,7 @@ use std::task::{Context, Poll, Waker};
 use std::thread;
 use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
 use tracing::{debug, trace};
 
 pub struct EventLoop {
@@ -18,28 +19,39 @@ pub struct EventLoop {
     next_timer_id: u64,
     running: bool,
     handle: Option<Handle>,
+    /// Tasks handed to tokio that may still be doing I/O
+    in_flight: Vec<JoinHandle<()>>,
+    virtual_time: Option<VirtualTime>,
+}
+
+/// Timers measured against a frozen `HostClock` rather than real time
+struct VirtualTime {
+    clock: Arc<HostClock>,
+    /// The real instant that stands for the clock's reading `origin_ms`
+    origin: Instant,
+    origin_ms: f64,
 }
 
-#[derive(Debug)]
 struct Task {
     id: u64,
     future: BoxFuture<'static, ()>,
     created_at: Instant,
 }
 
-#[derive(Debug)]
 struct Microtask {
     id: u64,
     callback: Box<dyn FnOnce() + Send>,
     created_at: Instant,
 }
 
-#[derive(Debug)]
 struct Timer {
     id: u64,
-    callback: Box<dyn FnOnce() + Send>,
+    /// Run each time the timer fires; a timeout's runs only once
+    callback: Box<dyn FnMut() + Send>,
     fire_at: Instant,
     interval: Option<Duration>,
+    /// Whether the timer keeps the loop from being idle
+    referenced: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -48,7 +60,6 @@ pub struct Promise {
     state: Arc<Mutex<PromiseState>>,
 }
 
-#[derive(Debug)]
 enum PromiseState {
     Pending {
         then_callbacks: Vec<Box<dyn FnOnce(PromiseResult) + Send>>,
@@ -57,6 +68,18 @@ enum PromiseState {
     Rejected(PromiseValue),
 }
 
+impl std::fmt::Debug for PromiseState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromiseState::Pending { then_callbacks } => {
+                write!(f, "Pending({} callbacks)", then_callbacks.len())
+            }
+            PromiseState::Fulfilled(value) => f.debug_tuple("Fulfilled").field(value).finish(),
+            PromiseState::Rejected(value) => f.debug_tuple("Rejected").field(value).finish(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum PromiseValue {
     Number(f64),
@@ -81,6 +104,33 @@ impl EventLoop {
             next_timer_id: 1,
             running: false,
             handle: None,
+            in_flight: Vec::new(),
+            virtual_time: None,
+        }
+    }
+
+    /// Run timers in virtual time against `clock`: instead of waiting for
+    /// the next timer, the loop advances the clock to it, so timers fire
+    /// at once, in order, and `Date.now` reads the time each was due. The
+    /// clock should be frozen; a running one leaves timers on real time.
+    pub fn use_virtual_time(&mut self, clock: Arc<HostClock>) {
+        debug!("Event loop switched to virtual time");
+        let origin_ms = clock.now();
+        self.virtual_time = Some(VirtualTime {
+            clock,
+            origin: Instant::now(),
+            origin_ms,
+        });
+    }
+
+    /// The current instant, on the virtual clock in virtual time mode
+    fn now(&self) -> Instant {
+        match &self.virtual_time {
+            Some(virtual_time) => {
+                let elapsed_ms = (virtual_time.clock.now() - virtual_time.origin_ms).max(0.0);
+                virtual_time.origin + Duration::from_secs_f64(elapsed_ms / 1000.0)
+            }
+            None => Instant::now(),
         }
     }
 
@@ -109,6 +159,15 @@ impl EventLoop {
     }
 
     pub fn process_pending(&mut self) {
+        self.process_pending_with(|| {});
+    }
+
+    /// Process pending work as `process_pending` does, running
+    /// `checkpoint` after the microtasks and again after every timer
+    /// callback and task, as HTML and Node drain microtasks between
+    /// macrotasks. Hosts with their own job queue, such as the VM's
+    /// promise reactions, drain it in `checkpoint`.
+    pub fn process_pending_with(&mut self, mut checkpoint: impl FnMut()) {
         if !self.running {
             return;
         }
@@ -116,31 +175,39 @@ impl EventLoop {
         trace!("Processing pending tasks and microtasks");
 
         // Process all pending microtasks first
-        while let Some(microtask) = self.microtasks.pop_front() {
-            trace!("Executing microtask {}", microtask.id);
-            (microtask.callback)();
-        }
-
-        // Process timers
-        let now = Instant::now();
-        let mut expired_timers = Vec::new();
-        
-        for (&timer_id, timer) in &self.timers {
-            if now >= timer.fire_at {
-                expired_timers.push(timer_id);
+        self.run_microtasks();
+        checkpoint();
+
+        // Process timers in the order they fire; in virtual time, skip
+        // ahead to the next one once there is nothing else to do
+        let mut now = self.now();
+        if let Some(virtual_time) = &self.virtual_time {
+            let next = self.timers.values().map(|timer| timer.fire_at).min();
+            if let Some(next) = next.filter(|&next| next > now && self.tasks.is_empty()) {
+                virtual_time.clock.advance(next - now);
+                now = next;
             }
         }
-
-        for timer_id in expired_timers {
-            if let Some(timer) = self.timers.remove(&timer_id) {
+        let mut expired_timers: Vec<(Instant, u64)> = self.timers.values()
+            .filter(|timer| now >= timer.fire_at)
+            .map(|timer| (timer.fire_at, timer.id))
+            .collect();
+        expired_timers.sort();
+
+        for (_, timer_id) in expired_timers {
+            if let Some(mut timer) = self.timers.remove(&timer_id) {
                 trace!("Executing timer {}", timer.id);
                 (timer.callback)();
                 
-                // Reschedule if it's an interval
+                // An interval is rescheduled under the same id, so clearing
+                // it still works
                 if let Some(interval) = timer.interval {
-                    self.set_timer(interval, timer.callback);
+                    timer.fire_at += interval;
+                    self.timers.insert(timer_id, timer);
                 }
             }
+            self.run_microtasks();
+            checkpoint();
         }
 
         // Process one task from the task queue
@@ -148,11 +215,47 @@ impl EventLoop {
             trace!("Processing task {}", task.id);
             
             if let Some(handle) = &self.handle {
-                handle.spawn(task.future);
+                self.in_flight.push(handle.spawn(task.future));
             } else {
                 // If no tokio runtime available, we can't execute async tasks
                 debug!("No tokio runtime available for task execution");
             }
+            self.run_microtasks();
+            checkpoint();
+        }
+    }
+
+    /// Whether nothing is left to do: no tasks, microtasks or referenced
+    /// timers are queued and every task handed to tokio has finished
+    pub fn is_idle(&mut self) -> bool {
+        self.in_flight.retain(|task| !task.is_finished());
+        self.tasks.is_empty()
+            && self.microtasks.is_empty()
+            && self.timers.values().all(|timer| !timer.referenced)
+            && self.in_flight.is_empty()
+    }
+
+    /// How long until there is something to process: zero when tasks or
+    /// microtasks are queued, the wait for the next timer otherwise, and
+    /// `None` when only in-flight tasks remain. Virtual time skips ahead to
+    /// timers, so they never need waiting for.
+    pub fn time_until_ready(&self) -> Option<Duration> {
+        if !self.tasks.is_empty() || !self.microtasks.is_empty() {
+            return Some(Duration::ZERO);
+        }
+        let now = self.now();
+        let next_timer = self.timers.values().map(|timer| timer.fire_at.saturating_duration_since(now)).min();
+        match &self.virtual_time {
+            Some(_) => next_timer.map(|_| Duration::ZERO),
+            None => next_timer,
+        }
+    }
+
+    /// Run queued microtasks, including any they queue, until none are left
+    fn run_microtasks(&mut self) {
+        while let Some(microtask) = self.microtasks.pop_front() {
+            trace!("Executing microtask {}", microtask.id);
+            (microtask.callback)();
         }
     }
 
@@ -167,9 +270,8 @@ impl EventLoop {
         };
         
         self.next_timer_id += 1;
-        self.microtasks.push_back(microtask);
-        
         trace!("Queued microtask {}", microtask.id);
+        self.microtasks.push_back(microtask);
     }
 
     pub fn set_timeout<F>(&mut self, callback: F, delay: Duration) -> u64
@@ -179,11 +281,17 @@ impl EventLoop {
         let timer_id = self.next_timer_id;
         self.next_timer_id += 1;
         
+        let mut callback = Some(callback);
         let timer = Timer {
             id: timer_id,
-            callback: Box::new(callback),
-            fire_at: Instant::now() + delay,
+            callback: Box::new(move || {
+                if let Some(callback) = callback.take() {
+                    callback();
+                }
+            }),
+            fire_at: self.now() + delay,
             interval: None,
+            referenced: true,
         };
         
         self.timers.insert(timer_id, timer);
@@ -194,7 +302,7 @@ impl EventLoop {
 
     pub fn set_interval<F>(&mut self, callback: F, interval: Duration) -> u64
     where
-        F: FnOnce() + Send + 'static,
+        F: FnMut() + Send + 'static,
     {
         let timer_id = self.next_timer_id;
         self.next_timer_id += 1;
@@ -202,8 +310,9 @@ impl EventLoop {
         let timer = Timer {
             id: timer_id,
             callback: Box::new(callback),
-            fire_at: Instant::now() + interval,
+            fire_at: self.now() + interval,
             interval: Some(interval),
+            referenced: true,
         };
         
         self.timers.insert(timer_id, timer);
@@ -232,6 +341,19 @@ impl EventLoop {
         self.clear_timeout(timer_id)
     }
 
+    /// Let the loop go idle while the timer is pending, as for a timeout
+    /// that only matters while other work is running. It still fires if
+    /// the loop is turned until then.
+    pub fn unref_timer(&mut self, timer_id: u64) -> bool {
+        match self.timers.get_mut(&timer_id) {
+            Some(timer) => {
+                timer.referenced = false;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn spawn_task<F>(&mut self, future: F) -> u64
     where
         F: Future<Output = ()> + Send + 'static,
@@ -276,23 +398,25 @@ impl EventLoop {
         let state_reject = Arc::clone(&state);
         
         let resolve: Box<dyn FnOnce(T) + Send> = Box::new(move |value| {
+            let value: PromiseValue = value.into();
             let mut state = state_resolve.lock().unwrap();
             if let PromiseState::Pending { then_callbacks } = 
-                std::mem::replace(&mut *state, PromiseState::Fulfilled(value.into()))
+                std::mem::replace(&mut *state, PromiseState::Fulfilled(value.clone()))
             {
                 for callback in then_callbacks {
-                    callback(PromiseResult::Ok(value.into()));
+                    callback(PromiseResult::Ok(value.clone()));
                 }
             }
         });
         
         let reject: Box<dyn FnOnce(T) + Send> = Box::new(move |value| {
+            let value: PromiseValue = value.into();
             let mut state = state_reject.lock().unwrap();
             if let PromiseState::Pending { then_callbacks } = 
-                std::mem::replace(&mut *state, PromiseState::Rejected(value.into()))
+                std::mem::replace(&mut *state, PromiseState::Rejected(value.clone()))
             {
                 for callback in then_callbacks {
-                    callback(PromiseResult::Err(value.into()));
+                    callback(PromiseResult::Err(value.clone()));
                 }
             }
         });
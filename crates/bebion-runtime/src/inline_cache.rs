@@ -0,0 +1,213 @@
+//! Inline caches
+//!
+//! Every property access and call site remembers what it found the last few
+//! times it ran, keyed by its code and pc like the hotness counters. A
+//! property site keeps the shape of each object it saw and the slot the
+//! property sat in, so the next object of that shape is read or written
+//! with a shape comparison instead of a name lookup up the prototype chain.
+//! A call site keeps the function objects it called and what runs them.
+//!
+//! A site with one entry is monomorphic, and one with up to
+//! `MAX_POLYMORPHIC_ENTRIES` polymorphic. A site that sees more goes
+//! megamorphic: it drops its entries and takes the slow path from then on.
+//! Entries are checked against the shape an object has now, and adding or
+//! deleting a property moves an object to another shape, so a shape
+//! transition invalidates the entries made for the old layout without the
+//! caches being told.
+
+use crate::tier::CodeId;
+use bebion_gc::{GarbageCollector, GcHandle, ShapeId};
+use std::collections::HashMap;
+
+/// Entries a site holds before it gives up caching
+pub const MAX_POLYMORPHIC_ENTRIES: usize = 4;
+
+/// A cache site: the code holding the instruction and its pc
+pub(crate) type Site = (CodeId, usize);
+
+/// Hit counts for one kind of site, and how many sites of that kind are in
+/// each state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IcCounters {
+    pub hits: u64,
+    pub misses: u64,
+    pub monomorphic: usize,
+    pub polymorphic: usize,
+    pub megamorphic: usize,
+}
+
+impl IcCounters {
+    pub fn lookups(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    /// Fraction of lookups answered from the cache, 0 before any
+    pub fn hit_rate(&self) -> f64 {
+        match self.lookups() {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// Inline cache counters reported by `ic_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IcStats {
+    pub loads: IcCounters,
+    pub stores: IcCounters,
+    pub calls: IcCounters,
+}
+
+/// Where property `key` was found on objects of shape `shape`
+#[derive(Debug, Clone)]
+pub(crate) struct PropertyEntry {
+    key: String,
+    shape: ShapeId,
+    slot: usize,
+    /// The receiver's prototype and its shape, for an inherited property.
+    /// Objects of `shape` lack the property themselves.
+    holder: Option<(GcHandle, ShapeId)>,
+}
+
+impl PropertyEntry {
+    /// An entry for `key` of `handle`, found on the object itself or on
+    /// its prototype. `None` if it lives anywhere a shape cannot describe.
+    pub fn load(gc: &GarbageCollector, handle: GcHandle, key: &str) -> Option<Self> {
+        let shape = gc.shape_id(handle)?;
+        if let Some((_, slot)) = gc.property_slot(handle, key) {
+            return Some(Self { key: key.to_string(), shape, slot, holder: None });
+        }
+        let prototype = gc.get_prototype(handle)?;
+        let (holder_shape, slot) = gc.property_slot(prototype, key)?;
+        Some(Self { key: key.to_string(), shape, slot, holder: Some((prototype, holder_shape)) })
+    }
+
+    /// An entry for `key` of `handle` if the object has it itself, which is
+    /// the only place a store can go without changing its shape
+    pub fn store(gc: &GarbageCollector, handle: GcHandle, key: &str) -> Option<Self> {
+        let (shape, slot) = gc.property_slot(handle, key)?;
+        Some(Self { key: key.to_string(), shape, slot, holder: None })
+    }
+}
+
+/// An entry a site can hold
+pub(crate) trait CacheEntry {
+    /// Whether this entry answers the same lookups as `other`, which it
+    /// replaces when recorded after `other` stopped applying
+    fn replaces(&self, other: &Self) -> bool;
+}
+
+impl CacheEntry for PropertyEntry {
+    fn replaces(&self, other: &Self) -> bool {
+        self.shape == other.shape && self.key == other.key
+    }
+}
+
+impl<T> CacheEntry for (GcHandle, T) {
+    fn replaces(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+struct SiteEntries<T> {
+    entries: Vec<T>,
+    megamorphic: bool,
+}
+
+/// The sites of one kind and their hit counts
+pub(crate) struct SiteCache<T> {
+    sites: HashMap<Site, SiteEntries<T>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<T: CacheEntry> SiteCache<T> {
+    pub fn new() -> Self {
+        Self { sites: HashMap::new(), hits: 0, misses: 0 }
+    }
+
+    /// The entries `site` holds, none once it is megamorphic
+    pub fn entries(&self, site: Site) -> &[T] {
+        self.sites.get(&site).map_or(&[], |site| &site.entries)
+    }
+
+    pub fn record_hit(&mut self) {
+        self.hits += 1;
+    }
+
+    /// Count a miss at `site` and remember `entry`, if there is one, for
+    /// next time
+    pub fn record_miss(&mut self, site: Site, entry: Option<T>) {
+        self.misses += 1;
+        let Some(entry) = entry else {
+            return;
+        };
+
+        let site = self.sites.entry(site).or_insert_with(|| SiteEntries { entries: Vec::new(), megamorphic: false });
+        if site.megamorphic {
+            return;
+        }
+        site.entries.retain(|old| !entry.replaces(old));
+        if site.entries.len() == MAX_POLYMORPHIC_ENTRIES {
+            site.entries.clear();
+            site.megamorphic = true;
+        } else {
+            site.entries.push(entry);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.sites.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    pub fn counters(&self) -> IcCounters {
+        let mut counters = IcCounters { hits: self.hits, misses: self.misses, ..IcCounters::default() };
+        for site in self.sites.values() {
+            match site.entries.len() {
+                _ if site.megamorphic => counters.megamorphic += 1,
+                1 => counters.monomorphic += 1,
+                _ => counters.polymorphic += 1,
+            }
+        }
+        counters
+    }
+}
+
+impl SiteCache<PropertyEntry> {
+    /// The value `site` has cached for `key` of `handle`, if it has seen
+    /// the object's shape and the property is still where it was
+    pub fn load(&self, gc: &GarbageCollector, site: Site, handle: GcHandle, key: &str) -> Option<GcHandle> {
+        let shape = gc.shape_id(handle)?;
+        let entry = self.entries(site).iter().find(|entry| entry.shape == shape && entry.key == key)?;
+        match entry.holder {
+            None => gc.get_property_slot(handle, shape, entry.slot),
+            Some((holder, holder_shape)) if gc.get_prototype(handle) == Some(holder) => {
+                gc.get_property_slot(holder, holder_shape, entry.slot)
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// The shape and slot `site` has cached for storing to `key` of
+    /// `handle`
+    pub fn store(&self, gc: &GarbageCollector, site: Site, handle: GcHandle, key: &str) -> Option<(ShapeId, usize)> {
+        let shape = gc.shape_id(handle)?;
+        let entry = self.entries(site).iter().find(|entry| entry.shape == shape && entry.key == key)?;
+        Some((entry.shape, entry.slot))
+    }
+}
+
+impl<T: Clone> SiteCache<(GcHandle, T)> {
+    /// What `site` has cached for calling `function`
+    pub fn call_target(&self, site: Site, function: GcHandle) -> Option<T> {
+        self.entries(site).iter().find(|(handle, _)| *handle == function).map(|(_, target)| target.clone())
+    }
+}
+
+impl<T: CacheEntry> Default for SiteCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
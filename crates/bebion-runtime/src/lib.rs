@@ -2,47 +2,130 @@
 //! 
 //! Executes bytecode with async/await support and event loop integration.
 
+pub mod abort;
+mod builtins;
+pub mod clock;
+#[cfg(feature = "event-loop")]
 pub mod event_loop;
+pub mod inline_cache;
+pub mod number;
+pub mod random;
+pub mod regexp;
 pub mod runtime;
+pub mod string;
+pub mod structured_clone;
+pub mod symbol;
+pub mod tier;
 pub mod vm;
 pub mod value;
 
+#[cfg(feature = "event-loop")]
 pub use event_loop::EventLoop;
+pub use abort::{AbortSignal, Aborted};
+pub use clock::HostClock;
+pub use inline_cache::{IcCounters, IcStats};
+pub use random::HostRandom;
+pub use regexp::RegExp;
 pub use runtime::Runtime;
-pub use vm::VirtualMachine;
-pub use value::Value;
+pub use string::JsString;
+pub use structured_clone::{DataCloneError, SerializedValue};
+pub use symbol::Symbol;
+pub use tier::{CompiledCode, HotFunction, HotLoop, NativeOutcome, Tier, TierThresholds, VmStats};
+pub use vm::{FrameSnapshot, PromiseInspection, RootSource, StackFrameInfo, VirtualMachine};
+pub use value::{NativeFn, NativeFunction, Value};
 
 use std::fmt;
 
 #[derive(Debug, Clone)]
 pub enum RuntimeError {
+    /// A plain `Error`, such as a failed host operation
+    Error(String),
     TypeError(String),
     ReferenceError(String),
     SyntaxError(String),
     RangeError(String),
-    StackOverflow,
+    /// The call stack or operand stack outgrew its limit, with the stack at
+    /// that point
+    StackOverflow { stack: Vec<StackFrameInfo> },
     OutOfMemory,
     InvalidBytecode(String),
     InvalidOperation(String),
     AsyncError(String),
+    /// A JS exception no handler caught, with the stack where it was thrown
+    Thrown { message: String, stack: Vec<StackFrameInfo> },
 }
 
 impl fmt::Display for RuntimeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            RuntimeError::Error(msg) => write!(f, "Error: {}", msg),
             RuntimeError::TypeError(msg) => write!(f, "TypeError: {}", msg),
             RuntimeError::ReferenceError(msg) => write!(f, "ReferenceError: {}", msg),
             RuntimeError::SyntaxError(msg) => write!(f, "SyntaxError: {}", msg),
             RuntimeError::RangeError(msg) => write!(f, "RangeError: {}", msg),
-            RuntimeError::StackOverflow => write!(f, "RangeError: Maximum call stack size exceeded"),
+            RuntimeError::StackOverflow { stack } => {
+                write!(f, "RangeError: Maximum call stack size exceeded")?;
+                write_overflowed_stack(f, stack)
+            }
             RuntimeError::OutOfMemory => write!(f, "RangeError: Out of memory"),
             RuntimeError::InvalidBytecode(msg) => write!(f, "Internal Error: Invalid bytecode - {}", msg),
             RuntimeError::InvalidOperation(msg) => write!(f, "Internal Error: Invalid operation - {}", msg),
             RuntimeError::AsyncError(msg) => write!(f, "Async Error: {}", msg),
+            RuntimeError::Thrown { message, stack } => {
+                write!(f, "Uncaught {}", message)?;
+                for frame in stack {
+                    write!(f, "\n    {}", frame)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
+/// Innermost frames shown for a stack overflow
+const OVERFLOW_TOP_FRAMES: usize = 10;
+/// Outermost frames shown for a stack overflow
+const OVERFLOW_BOTTOM_FRAMES: usize = 5;
+
+/// The innermost and outermost frames of an overflowed stack, with the
+/// frames between them summarized by function, e.g. `… 983 frames of fib()
+/// omitted`
+fn write_overflowed_stack(f: &mut fmt::Formatter<'_>, stack: &[StackFrameInfo]) -> fmt::Result {
+    if stack.len() <= OVERFLOW_TOP_FRAMES + OVERFLOW_BOTTOM_FRAMES {
+        for frame in stack {
+            write!(f, "\n    {}", frame)?;
+        }
+        return Ok(());
+    }
+
+    let omitted = &stack[OVERFLOW_TOP_FRAMES..stack.len() - OVERFLOW_BOTTOM_FRAMES];
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for frame in omitted {
+        let name = frame.function.as_deref().unwrap_or("<anonymous>");
+        match counts.iter_mut().find(|(counted, _)| *counted == name) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((name, 1)),
+        }
+    }
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+
+    for frame in &stack[..OVERFLOW_TOP_FRAMES] {
+        write!(f, "\n    {}", frame)?;
+    }
+    match counts.as_slice() {
+        [(name, count)] => write!(f, "\n    … {} frames of {}() omitted", count, name)?,
+        _ => {
+            let summary: Vec<_> = counts.iter().take(3).map(|(name, count)| format!("{} of {}()", count, name)).collect();
+            let rest = if counts.len() > 3 { ", …" } else { "" };
+            write!(f, "\n    … {} frames omitted ({}{})", omitted.len(), summary.join(", "), rest)?;
+        }
+    }
+    for frame in &stack[stack.len() - OVERFLOW_BOTTOM_FRAMES..] {
+        write!(f, "\n    {}", frame)?;
+    }
+    Ok(())
+}
+
 impl std::error::Error for RuntimeError {}
 
 pub type RuntimeResult<T> = Result<T, RuntimeError>;
\ No newline at end of file
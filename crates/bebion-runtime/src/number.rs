@@ -0,0 +1,334 @@
+//! Number conversions following ECMA-262: `Number::toString`, the
+//! `toFixed` / `toPrecision` / `toExponential` methods and `StringToNumber`
+
+use crate::{RuntimeError, RuntimeResult};
+
+/// `Number::toString(x)` for radix 10
+pub fn number_to_string(x: f64) -> String {
+    if x.is_nan() {
+        return "NaN".to_string();
+    }
+    if x == 0.0 {
+        // Covers -0 as well
+        return "0".to_string();
+    }
+    if x < 0.0 {
+        return format!("-{}", number_to_string(-x));
+    }
+    if x.is_infinite() {
+        return "Infinity".to_string();
+    }
+
+    let (digits, n) = shortest_digits(x);
+    let k = digits.len() as i32;
+    let s = String::from_utf8(digits).unwrap();
+
+    if k <= n && n <= 21 {
+        format!("{}{}", s, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        let (int_part, frac_part) = s.split_at(n as usize);
+        format!("{}.{}", int_part, frac_part)
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat((-n) as usize), s)
+    } else {
+        let exponent = format_exponent(n - 1);
+        if k == 1 {
+            format!("{}{}", s, exponent)
+        } else {
+            format!("{}.{}{}", &s[..1], &s[1..], exponent)
+        }
+    }
+}
+
+/// `Number.prototype.toFixed`
+pub fn to_fixed(x: f64, fraction_digits: i32) -> RuntimeResult<String> {
+    if !(0..=100).contains(&fraction_digits) {
+        return Err(RuntimeError::RangeError(
+            "toFixed() digits argument must be between 0 and 100".to_string(),
+        ));
+    }
+    if !x.is_finite() {
+        return Ok(number_to_string(x));
+    }
+    if x.abs() >= 1e21 {
+        return Ok(number_to_string(x));
+    }
+
+    let sign = if x < 0.0 { "-" } else { "" };
+    let (digits, point) = exact_digits(x.abs());
+    let (digits, point) = round_digits(&digits, point, point + fraction_digits);
+
+    let int_part = if point <= 0 {
+        "0".to_string()
+    } else {
+        (0..point).map(|i| digit_at(&digits, i)).collect()
+    };
+    let frac_part: String = (point..point + fraction_digits)
+        .map(|i| digit_at(&digits, i))
+        .collect();
+
+    if frac_part.is_empty() {
+        Ok(format!("{}{}", sign, int_part))
+    } else {
+        Ok(format!("{}{}.{}", sign, int_part, frac_part))
+    }
+}
+
+/// `Number.prototype.toExponential`; `None` uses as many digits as needed
+pub fn to_exponential(x: f64, fraction_digits: Option<i32>) -> RuntimeResult<String> {
+    if !x.is_finite() {
+        return Ok(number_to_string(x));
+    }
+    if let Some(f) = fraction_digits {
+        if !(0..=100).contains(&f) {
+            return Err(RuntimeError::RangeError(
+                "toExponential() argument must be between 0 and 100".to_string(),
+            ));
+        }
+    }
+
+    let sign = if x < 0.0 { "-" } else { "" };
+    let x = x.abs();
+
+    let (digits, exponent) = if x == 0.0 {
+        (vec![b'0'; fraction_digits.unwrap_or(0) as usize + 1], 0)
+    } else {
+        match fraction_digits {
+            Some(f) => significant_digits(x, f as usize + 1),
+            None => {
+                let (digits, point) = shortest_digits(x);
+                (digits, point - 1)
+            }
+        }
+    };
+
+    let s = String::from_utf8(digits).unwrap();
+    let mantissa = if s.len() == 1 {
+        s
+    } else {
+        format!("{}.{}", &s[..1], &s[1..])
+    };
+
+    Ok(format!("{}{}{}", sign, mantissa, format_exponent(exponent)))
+}
+
+/// `Number.prototype.toPrecision`; `None` behaves like `ToString`
+pub fn to_precision(x: f64, precision: Option<i32>) -> RuntimeResult<String> {
+    let p = match precision {
+        Some(p) => p,
+        None => return Ok(number_to_string(x)),
+    };
+    if !x.is_finite() {
+        return Ok(number_to_string(x));
+    }
+    if !(1..=100).contains(&p) {
+        return Err(RuntimeError::RangeError(
+            "toPrecision() argument must be between 1 and 100".to_string(),
+        ));
+    }
+
+    let sign = if x < 0.0 { "-" } else { "" };
+    let x = x.abs();
+
+    let (digits, e) = if x == 0.0 {
+        (vec![b'0'; p as usize], 0)
+    } else {
+        significant_digits(x, p as usize)
+    };
+    let m = String::from_utf8(digits).unwrap();
+
+    let body = if e < -6 || e >= p {
+        let mantissa = if p == 1 {
+            m
+        } else {
+            format!("{}.{}", &m[..1], &m[1..])
+        };
+        format!("{}{}", mantissa, format_exponent(e))
+    } else if e == p - 1 {
+        m
+    } else if e >= 0 {
+        let (int_part, frac_part) = m.split_at(e as usize + 1);
+        format!("{}.{}", int_part, frac_part)
+    } else {
+        format!("0.{}{}", "0".repeat((-(e + 1)) as usize), m)
+    };
+
+    Ok(format!("{}{}", sign, body))
+}
+
+fn format_exponent(e: i32) -> String {
+    if e < 0 {
+        format!("e-{}", -e)
+    } else {
+        format!("e+{}", e)
+    }
+}
+
+/// Shortest digit string that round-trips to `x` (positive, finite, non-zero),
+/// together with the decimal point position `n` such that `x = 0.d1d2... × 10^n`
+fn shortest_digits(x: f64) -> (Vec<u8>, i32) {
+    let formatted = format!("{:e}", x);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let digits = mantissa.bytes().filter(|b| b.is_ascii_digit()).collect();
+    (digits, exponent + 1)
+}
+
+/// Exact decimal expansion of a positive finite `x`: significant digits without
+/// leading or trailing zeros, and the decimal point position as in `shortest_digits`
+fn exact_digits(x: f64) -> (Vec<u8>, i32) {
+    // Every f64 has a terminating decimal expansion of at most 1074 fractional digits
+    let formatted = format!("{:.1100}", x);
+    let (int_part, frac_part) = formatted.split_once('.').unwrap_or((&formatted, ""));
+
+    let mut digits: Vec<u8> = int_part.bytes().chain(frac_part.bytes()).collect();
+    let mut point = int_part.len() as i32;
+
+    let leading = digits.iter().take_while(|&&d| d == b'0').count();
+    digits.drain(..leading);
+    point -= leading as i32;
+
+    while digits.last() == Some(&b'0') {
+        digits.pop();
+    }
+
+    (digits, point)
+}
+
+/// Round to `count` significant digits, returning the digits and the exponent
+/// of the first digit
+fn significant_digits(x: f64, count: usize) -> (Vec<u8>, i32) {
+    let (digits, point) = exact_digits(x);
+    let (mut digits, point) = round_digits(&digits, point, count as i32);
+    digits.truncate(count);
+    (digits, point - 1)
+}
+
+/// Keep the first `keep` digits, rounding half away from zero on the exact
+/// expansion (the spec picks the larger candidate on ties). A carry out of the
+/// leading digit prepends a `1` and advances the decimal point.
+fn round_digits(digits: &[u8], point: i32, keep: i32) -> (Vec<u8>, i32) {
+    if keep < 0 {
+        return (Vec::new(), point);
+    }
+
+    let keep = keep as usize;
+    if digits.len() <= keep {
+        let mut padded = digits.to_vec();
+        padded.resize(keep, b'0');
+        return (padded, point);
+    }
+
+    let mut rounded = digits[..keep].to_vec();
+    if digits[keep] >= b'5' {
+        let mut i = rounded.len();
+        loop {
+            if i == 0 {
+                rounded.insert(0, b'1');
+                return (rounded, point + 1);
+            }
+            i -= 1;
+            if rounded[i] == b'9' {
+                rounded[i] = b'0';
+            } else {
+                rounded[i] += 1;
+                break;
+            }
+        }
+    }
+
+    (rounded, point)
+}
+
+fn digit_at(digits: &[u8], index: i32) -> char {
+    if index < 0 {
+        return '0';
+    }
+    digits.get(index as usize).map(|&d| d as char).unwrap_or('0')
+}
+
+/// `StringToNumber`: the string-to-number conversion used by `ToNumber`,
+/// `Number()` and loose equality. Returns NaN for anything that is not a
+/// `StringNumericLiteral`.
+pub fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(is_js_whitespace);
+    if s.is_empty() {
+        return 0.0;
+    }
+
+    // Prefixed integer literals take no sign
+    if s.len() > 2 && s.as_bytes()[0] == b'0' {
+        let radix = match s.as_bytes()[1] {
+            b'x' | b'X' => Some(16),
+            b'o' | b'O' => Some(8),
+            b'b' | b'B' => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            return s[2..].chars().try_fold(0.0, |acc, ch| {
+                ch.to_digit(radix).map(|d| acc * radix as f64 + d as f64)
+            }).unwrap_or(f64::NAN);
+        }
+    }
+
+    let (sign, unsigned) = match s.as_bytes()[0] {
+        b'+' => (1.0, &s[1..]),
+        b'-' => (-1.0, &s[1..]),
+        _ => (1.0, s),
+    };
+
+    if unsigned == "Infinity" {
+        return sign * f64::INFINITY;
+    }
+
+    if !is_str_unsigned_decimal_literal(unsigned) {
+        return f64::NAN;
+    }
+
+    unsigned.parse::<f64>().map(|n| sign * n).unwrap_or(f64::NAN)
+}
+
+/// WhiteSpace and LineTerminator code points trimmed by `StringToNumber`
+pub fn is_js_whitespace(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{0009}' | '\u{000B}' | '\u{000C}' | '\u{0020}' | '\u{00A0}' | '\u{FEFF}'
+            | '\u{000A}' | '\u{000D}' | '\u{2028}' | '\u{2029}'
+            | '\u{1680}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}'
+    )
+}
+
+/// `StrUnsignedDecimalLiteral` without the `Infinity` alternative. Rust's
+/// float parser is more lenient (`inf`, `nan`), so the shape is checked first.
+fn is_str_unsigned_decimal_literal(s: &str) -> bool {
+    let bytes = s.as_bytes();
+    let mut i = 0;
+
+    let int_digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+    i += int_digits;
+
+    let mut frac_digits = 0;
+    if bytes.get(i) == Some(&b'.') {
+        i += 1;
+        frac_digits = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        i += frac_digits;
+    }
+
+    if int_digits == 0 && frac_digits == 0 {
+        return false;
+    }
+
+    if matches!(bytes.get(i), Some(b'e' | b'E')) {
+        i += 1;
+        if matches!(bytes.get(i), Some(b'+' | b'-')) {
+            i += 1;
+        }
+        let exp_digits = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if exp_digits == 0 {
+            return false;
+        }
+        i += exp_digits;
+    }
+
+    i == bytes.len()
+}
@@ -0,0 +1,89 @@
+//! Host random number source shared by `Math.random` and the crypto module
+
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::sync::Mutex;
+
+enum RandomSource {
+    Os,
+    Seeded(Box<StdRng>),
+}
+
+impl RandomSource {
+    fn next_f64(&mut self) -> f64 {
+        match self {
+            RandomSource::Os => rand::thread_rng().gen::<f64>(),
+            RandomSource::Seeded(rng) => rng.gen::<f64>(),
+        }
+    }
+
+    fn fill_bytes(&mut self, buf: &mut [u8]) {
+        match self {
+            RandomSource::Os => rand::thread_rng().fill_bytes(buf),
+            RandomSource::Seeded(rng) => rng.fill_bytes(buf),
+        }
+    }
+}
+
+/// Process-wide randomness for the engine.
+///
+/// `Math.random` draws from a stream that can be seeded for reproducible runs,
+/// while crypto APIs keep using OS entropy unless explicitly overridden.
+pub struct HostRandom {
+    math: Mutex<RandomSource>,
+    crypto: Mutex<RandomSource>,
+}
+
+impl HostRandom {
+    pub fn new() -> Self {
+        Self {
+            math: Mutex::new(RandomSource::Os),
+            crypto: Mutex::new(RandomSource::Os),
+        }
+    }
+
+    /// Create a source whose `Math.random` stream is deterministic
+    pub fn seeded(seed: u64) -> Self {
+        let random = Self::new();
+        random.reseed(seed);
+        random
+    }
+
+    /// Make the `Math.random` stream deterministic from `seed`
+    pub fn reseed(&self, seed: u64) {
+        *self.math.lock().unwrap() = RandomSource::Seeded(Box::new(StdRng::seed_from_u64(seed)));
+    }
+
+    /// Override the crypto stream with a seeded generator, or restore OS entropy with `None`
+    pub fn override_crypto_seed(&self, seed: Option<u64>) {
+        *self.crypto.lock().unwrap() = match seed {
+            Some(seed) => RandomSource::Seeded(Box::new(StdRng::seed_from_u64(seed))),
+            None => RandomSource::Os,
+        };
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        matches!(*self.math.lock().unwrap(), RandomSource::Seeded(_))
+    }
+
+    /// Next value in `[0, 1)` for `Math.random`
+    pub fn next_f64(&self) -> f64 {
+        self.math.lock().unwrap().next_f64()
+    }
+
+    /// Fill `buf` from the crypto stream
+    pub fn fill_crypto_bytes(&self, buf: &mut [u8]) {
+        self.crypto.lock().unwrap().fill_bytes(buf)
+    }
+
+    /// Next value in `[0, 1)` from the crypto stream
+    pub fn crypto_f64(&self) -> f64 {
+        self.crypto.lock().unwrap().next_f64()
+    }
+}
+
+impl Default for HostRandom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
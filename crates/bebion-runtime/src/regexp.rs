@@ -0,0 +1,172 @@
+//! Regular expression support backing JavaScript `RegExp` objects
+
+use crate::{RuntimeError, RuntimeResult};
+use bebion_gc::{GarbageCollector, GcHandle, GcObjectType};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+/// A compiled regular expression together with its JavaScript source and flags
+#[derive(Debug, Clone)]
+pub struct RegExp {
+    regex: Regex,
+    source: String,
+    flags: String,
+}
+
+/// Result of a successful `exec`
+#[derive(Debug, Clone)]
+pub struct RegExpMatch {
+    /// UTF-16 code unit index where the match starts
+    pub index: usize,
+    /// UTF-16 code unit index just past the end of the match
+    pub end: usize,
+    /// Full match followed by each capture group (`None` if the group did not participate)
+    pub captures: Vec<Option<String>>,
+}
+
+impl RegExp {
+    pub fn new(pattern: &str, flags: &str) -> RuntimeResult<Self> {
+        for flag in flags.chars() {
+            if !matches!(flag, 'd' | 'g' | 'i' | 'm' | 's' | 'u' | 'v' | 'y') {
+                return Err(RuntimeError::SyntaxError(format!(
+                    "Invalid regular expression flags '{}'",
+                    flags
+                )));
+            }
+        }
+
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(flags.contains('i'))
+            .multi_line(flags.contains('m'))
+            .dot_matches_new_line(flags.contains('s'))
+            .build()
+            .map_err(|e| {
+                RuntimeError::SyntaxError(format!("Invalid regular expression: /{}/: {}", pattern, e))
+            })?;
+
+        Ok(Self {
+            regex,
+            source: pattern.to_string(),
+            flags: flags.to_string(),
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn flags(&self) -> &str {
+        &self.flags
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.flags.contains('g')
+    }
+
+    pub fn is_sticky(&self) -> bool {
+        self.flags.contains('y')
+    }
+
+    /// Match against `input` starting at the given UTF-16 code unit index
+    pub fn exec_at(&self, input: &str, last_index: usize) -> Option<RegExpMatch> {
+        let start_byte = code_unit_to_byte_offset(input, last_index)?;
+        let captures = self.regex.captures_at(input, start_byte)?;
+        let whole = captures.get(0)?;
+
+        if self.is_sticky() && whole.start() != start_byte {
+            return None;
+        }
+
+        let index = input[..whole.start()].encode_utf16().count();
+        let end = index + whole.as_str().encode_utf16().count();
+
+        let captures = captures
+            .iter()
+            .map(|group| group.map(|m| m.as_str().to_string()))
+            .collect();
+
+        Some(RegExpMatch { index, end, captures })
+    }
+}
+
+impl std::fmt::Display for RegExp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/{}/{}", self.source, self.flags)
+    }
+}
+
+fn code_unit_to_byte_offset(input: &str, code_unit_index: usize) -> Option<usize> {
+    let mut units = 0;
+    for (byte_index, ch) in input.char_indices() {
+        if units >= code_unit_index {
+            return Some(byte_index);
+        }
+        units += ch.len_utf16();
+    }
+    (units >= code_unit_index).then_some(input.len())
+}
+
+/// Compiled forms of the `RegExp` objects matched so far, so a pattern is
+/// compiled once per object rather than on every match
+#[derive(Debug, Default)]
+pub(crate) struct RegExpCache {
+    compiled: HashMap<GcHandle, RegExp>,
+}
+
+impl RegExpCache {
+    /// Remember `regexp` as the compiled form of `handle`
+    pub(crate) fn insert(&mut self, handle: GcHandle, regexp: RegExp) {
+        self.compiled.insert(handle, regexp);
+    }
+
+    /// The compiled form of `handle` and its `lastIndex`, or `None` if it
+    /// is not a `RegExp` object
+    pub(crate) fn load(&mut self, gc: &GarbageCollector, handle: GcHandle) -> RuntimeResult<Option<(RegExp, usize)>> {
+        let Some(GcObjectType::RegExp { pattern, flags, last_index }) = gc.get_object_type(handle) else {
+            return Ok(None);
+        };
+        // The handle of a collected expression may name another one now
+        let cached = self.compiled.get(&handle).filter(|regexp| regexp.source == *pattern && regexp.flags == *flags);
+        let regexp = match cached {
+            Some(regexp) => regexp.clone(),
+            None => {
+                let regexp = RegExp::new(pattern, flags)?;
+                self.compiled.insert(handle, regexp.clone());
+                regexp
+            }
+        };
+        Ok(Some((regexp, *last_index)))
+    }
+}
+
+fn store_last_index(gc: &mut GarbageCollector, handle: GcHandle, regexp: &RegExp, last_index: usize) {
+    gc.update_object(handle, GcObjectType::RegExp {
+        pattern: regexp.source().to_string(),
+        flags: regexp.flags().to_string(),
+        last_index,
+    });
+}
+
+/// Run `RegExp.prototype.exec` semantics, updating `lastIndex` for global and
+/// sticky expressions
+pub(crate) fn regexp_exec_match(
+    gc: &mut GarbageCollector,
+    cache: &mut RegExpCache,
+    handle: GcHandle,
+    input: &str,
+) -> RuntimeResult<Option<RegExpMatch>> {
+    let (regexp, last_index) = cache
+        .load(gc, handle)?
+        .ok_or_else(|| RuntimeError::TypeError("Receiver is not a RegExp".to_string()))?;
+    let uses_last_index = regexp.is_global() || regexp.is_sticky();
+    let start = if uses_last_index { last_index } else { 0 };
+
+    let result = regexp.exec_at(input, start);
+
+    if uses_last_index {
+        let next_index = result.as_ref().map(|m| m.end).unwrap_or(0);
+        store_last_index(gc, handle, &regexp, next_index);
+    }
+
+    Ok(result)
+}
@@ -1,21 +1,32 @@
 //! High-level runtime interface
 
-use crate::{RuntimeError, RuntimeResult, Value, VirtualMachine};
+use crate::builtins;
+use crate::vm::{FrameSnapshot, PromiseInspection, StackFrameInfo};
+use crate::{AbortSignal, DataCloneError, HostClock, HostRandom, IcStats, NativeFunction, RuntimeError, RuntimeResult, SerializedValue, Tier, TierThresholds, Value, VirtualMachine, VmStats};
 use bebion_compiler::bytecode::Bytecode;
 use bebion_gc::{GarbageCollector, GcHandle};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use tracing::{debug, info};
+use tracing::debug;
 
+/// The VM and everything it owns. Transparent over the VM so a native
+/// function called from inside it can be handed the runtime.
+#[repr(transparent)]
 pub struct Runtime {
     vm: VirtualMachine,
-    gc: Arc<Mutex<GarbageCollector>>,
 }
 
 impl Runtime {
     pub fn new(gc: Arc<Mutex<GarbageCollector>>) -> Self {
-        let vm = VirtualMachine::new(Arc::clone(&gc));
-        
-        Self { vm, gc }
+        Self {
+            vm: VirtualMachine::new(gc),
+        }
+    }
+
+    /// The runtime around `vm`
+    pub(crate) fn from_vm_mut(vm: &mut VirtualMachine) -> &mut Runtime {
+        // SAFETY: `Runtime` is `repr(transparent)` over its only field
+        unsafe { &mut *(vm as *mut VirtualMachine as *mut Runtime) }
     }
 
     pub fn execute(&mut self, bytecode: &Bytecode) -> RuntimeResult<GcHandle> {
@@ -29,6 +40,11 @@ impl Runtime {
         Ok(handle)
     }
 
+    /// Read back a value returned by `execute`
+    pub fn handle_to_value(&self, handle: GcHandle) -> Value {
+        self.vm.handle_to_value(handle)
+    }
+
     pub fn set_global(&mut self, name: &str, value: Value) {
         self.vm.set_global(name.to_string(), value);
     }
@@ -37,39 +53,384 @@ impl Runtime {
         self.vm.get_global(name)
     }
 
-    fn value_to_gc_handle(&mut self, value: Value) -> RuntimeResult<GcHandle> {
-        match value {
-            Value::Object(handle) => Ok(handle),
-            Value::Number(n) => {
-                let mut gc = self.gc.lock().unwrap();
-                Ok(gc.allocate_number(n))
-            }
-            Value::String(s) => {
-                let mut gc = self.gc.lock().unwrap();
-                Ok(gc.allocate_string(s))
-            }
-            Value::Boolean(b) => {
-                let mut gc = self.gc.lock().unwrap();
-                Ok(gc.allocate_boolean(b))
-            }
-            Value::Null => {
-                let mut gc = self.gc.lock().unwrap();
-                Ok(gc.allocate_null())
-            }
-            Value::Undefined => {
-                let mut gc = self.gc.lock().unwrap();
-                Ok(gc.allocate_undefined())
-            }
-        }
+    /// Define a global function running `function`
+    pub fn set_global_function<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&mut Runtime, &[Value]) -> RuntimeResult<Value> + Send + Sync + 'static,
+    {
+        self.set_global(name, Value::NativeFunction(NativeFunction::new(name, function)));
+    }
+
+    /// A plain object with the given properties
+    pub fn create_object(&mut self, properties: HashMap<String, Value>) -> Value {
+        let properties = properties
+            .into_iter()
+            .map(|(key, value)| (key, self.vm.value_to_handle(value)))
+            .collect();
+        Value::Object(self.vm.create_object(properties))
+    }
+
+    /// `object[key]`, following the prototype chain and calling getters
+    pub fn get_property(&mut self, object: &Value, key: &str) -> RuntimeResult<Value> {
+        self.vm.get_property(object, &Value::from(key))
+    }
+
+    /// `object[key] = value`, calling setters
+    pub fn set_property(&mut self, object: &Value, key: &str, value: Value) -> RuntimeResult<()> {
+        self.vm.set_property(object, &Value::from(key), value)
+    }
+
+    /// An array of the given elements
+    pub fn create_array(&mut self, elements: Vec<Value>) -> Value {
+        self.vm.array_from_values(elements)
+    }
+
+    /// The elements of `array`, or `None` if it is not an array
+    pub fn array_elements(&self, array: &Value) -> Option<Vec<Value>> {
+        self.vm.array_elements(array)
+    }
+
+    /// A `Uint8Array` over a new buffer holding `bytes`
+    pub fn create_uint8_array(&mut self, bytes: Vec<u8>) -> Value {
+        builtins::uint8_array_from_bytes(&mut self.vm, bytes)
+    }
+
+    /// An `ArrayBuffer` that takes ownership of `bytes` without copying
+    pub fn create_array_buffer(&mut self, bytes: Vec<u8>) -> Value {
+        builtins::array_buffer_from_bytes(&mut self.vm, bytes)
+    }
+
+    /// Take an `ArrayBuffer`'s bytes without copying them, detaching it
+    /// as `transfer` does. `None` if `buffer` is not an attached buffer.
+    pub fn detach_array_buffer(&mut self, buffer: &Value) -> Option<Vec<u8>> {
+        let Value::Object(handle) = buffer else {
+            return None;
+        };
+        self.vm.gc().lock().unwrap().detach_buffer(*handle)
+    }
+
+    /// Run `f` on the bytes an `ArrayBuffer`, typed array or `DataView`
+    /// spans, in place in the heap, so hosts can fill a script's buffer
+    /// straight from a file or socket. `None` if `view` is none of those
+    /// or its buffer is detached. The heap is locked while `f` runs.
+    pub fn with_buffer_bytes_mut<R>(&mut self, view: &Value, f: impl FnOnce(&mut [u8]) -> R) -> Option<R> {
+        let mut gc = self.vm.gc().lock().unwrap();
+        let (buffer, window) = builtins::byte_window(&gc, view)?;
+        let bytes = gc.buffer_bytes_mut(buffer)?;
+        Some(f(&mut bytes[window]))
+    }
+
+    /// Load what `import` statements name: `hook` gets the specifier and
+    /// the `type` import attribute, empty for a JavaScript module, and
+    /// returns the value to bind. An `Err` fails the import.
+    pub fn set_import_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Runtime, &str, &str) -> RuntimeResult<Value> + Send + Sync + 'static,
+    {
+        self.vm.set_import_hook(NativeFunction::new("import", move |runtime, args| {
+            let specifier = args.first().map(Value::to_string).unwrap_or_default();
+            let import_type = args.get(1).map(Value::to_string).unwrap_or_default();
+            hook(runtime, &specifier, &import_type)
+        }));
+    }
+
+    /// Record what a module's `export`s name: `hook` gets the export's
+    /// name, `default` for `export default`, and its value. An `Err` fails
+    /// the export.
+    pub fn set_export_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Runtime, &str, Value) -> RuntimeResult<()> + Send + Sync + 'static,
+    {
+        self.vm.set_export_hook(NativeFunction::new("export", move |runtime, args| {
+            let name = args.first().map(Value::to_string).unwrap_or_default();
+            let value = args.get(1).cloned().unwrap_or(Value::Undefined);
+            hook(runtime, &name, value)?;
+            Ok(Value::Undefined)
+        }));
+    }
+
+    /// Make each module's `import.meta`: `hook` gets the module's URL and
+    /// returns the object, which it should hand out again on later reads
+    pub fn set_import_meta_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Runtime, &str) -> RuntimeResult<Value> + Send + Sync + 'static,
+    {
+        self.vm.set_import_meta_hook(NativeFunction::new("meta", move |runtime, args| {
+            let url = args.first().map(Value::to_string).unwrap_or_default();
+            hook(runtime, &url)
+        }));
+    }
+
+    /// Whether `value` is a function scripts can call
+    pub fn is_callable(&self, value: &Value) -> bool {
+        self.vm.is_callable(value)
+    }
+
+    /// Call a function value with `args` and an undefined `this`, running
+    /// it to completion
+    pub fn call_function(&mut self, function: &Value, args: &[Value]) -> RuntimeResult<Value> {
+        self.vm.call_function(function, &Value::Undefined, args)
+    }
+
+    /// A new `AbortSignal` that is not aborted, for host APIs that hand
+    /// scripts a signal they abort themselves
+    pub fn create_abort_signal(&mut self) -> Value {
+        Value::Object(builtins::create_signal(&mut self.vm))
+    }
+
+    /// The host handle of `signal`, or `None` if it is not an
+    /// `AbortSignal`. Host operations taking a `signal` option use it to
+    /// notice the script aborting them.
+    pub fn abort_signal(&mut self, signal: &Value) -> Option<AbortSignal> {
+        let handle = builtins::signal_handle(&mut self.vm, signal)?;
+        Some(self.vm.abort_signals()[&handle].host.clone())
+    }
+
+    /// Abort `signal` as `AbortController.prototype.abort` does, running
+    /// its listeners; an undefined `reason` means an `AbortError`
+    pub fn abort(&mut self, signal: &Value, reason: Value) -> RuntimeResult<()> {
+        let Some(handle) = builtins::signal_handle(&mut self.vm, signal) else {
+            return Err(RuntimeError::TypeError("Cannot abort a value that is not an AbortSignal".to_string()));
+        };
+        builtins::abort(&mut self.vm, handle, reason)
+    }
+
+    /// A new signal that aborts with a `TimeoutError` once `milliseconds`
+    /// have passed, as `AbortSignal.timeout` makes
+    pub fn create_timeout_signal(&mut self, milliseconds: f64) -> Value {
+        Value::Object(builtins::timeout_signal(&mut self.vm, milliseconds))
+    }
+
+    /// Call `listener` with the `abort` event when `signal` aborts, as
+    /// `signal.addEventListener("abort", listener)` does
+    pub fn add_abort_listener(&mut self, signal: &Value, listener: Value) -> RuntimeResult<()> {
+        let Some(handle) = builtins::signal_handle(&mut self.vm, signal) else {
+            return Err(RuntimeError::TypeError("Cannot listen to a value that is not an AbortSignal".to_string()));
+        };
+        builtins::add_listener(&mut self.vm, handle, listener);
+        Ok(())
+    }
+
+    /// Signals `AbortSignal.timeout` created since the last call, with their
+    /// delays in milliseconds. The host aborts each with a `TimeoutError`
+    /// when its delay has passed.
+    pub fn take_abort_timeouts(&mut self) -> Vec<(Value, f64)> {
+        std::mem::take(self.vm.abort_timeouts())
+            .into_iter()
+            .map(|(signal, milliseconds)| (Value::Object(signal), milliseconds))
+            .collect()
+    }
+
+    /// The reason of an aborted `signal`, as scripts see it
+    pub fn abort_reason(&mut self, signal: &Value) -> Value {
+        self.get_property(signal, "reason").unwrap_or(Value::Undefined)
+    }
+
+    /// An error-like object with `name` and `message`, for the errors host
+    /// APIs reject with, such as a `TimeoutError`
+    pub fn create_error(&mut self, name: &str, message: &str) -> Value {
+        builtins::error_object(&mut self.vm, name, message)
+    }
+
+    /// Shared random source for `Math.random` and crypto APIs
+    pub fn random(&self) -> &Arc<HostRandom> {
+        self.vm.random()
+    }
+
+    /// `Math.random`
+    pub fn math_random(&self) -> f64 {
+        self.random().next_f64()
+    }
+
+    /// Shared wall clock for `Date` and the event loop's virtual time
+    pub fn clock(&self) -> &Arc<HostClock> {
+        self.vm.clock()
+    }
+
+    /// Run `bytecode` as an async function body, returning its promise
+    pub fn execute_async(&mut self, bytecode: &Bytecode) -> RuntimeResult<GcHandle> {
+        self.vm.execute_async(bytecode)
+    }
+
+    /// Resume async functions whose awaited promises have settled
+    pub fn run_jobs(&mut self) -> usize {
+        self.vm.run_jobs()
     }
 
+    /// Errors thrown by `queueMicrotask` callbacks since the last call
+    pub fn take_job_errors(&mut self) -> Vec<RuntimeError> {
+        self.vm.take_job_errors().into_iter().map(|(error, _)| error).collect()
+    }
+
+    /// `take_job_errors` with the values the errors threw, for hosts that
+    /// hand them to scripts. `None` for errors scripts cannot catch.
+    pub fn take_job_exceptions(&mut self) -> Vec<(RuntimeError, Option<Value>)> {
+        self.vm.take_job_errors()
+    }
+
+    /// Report an error a host callback threw where no script can catch it,
+    /// alongside those of microtasks
+    pub fn report_job_error(&mut self, error: RuntimeError) {
+        self.vm.report_job_error(error);
+    }
+
+    /// Reasons of promises rejected with no handler since the last call,
+    /// described as uncaught exceptions are
+    pub fn take_unhandled_rejections(&mut self) -> Vec<String> {
+        self.take_rejected_promises()
+            .into_iter()
+            .map(|(_, reason)| self.describe_exception(&reason))
+            .collect()
+    }
+
+    /// Promises rejected with no handler since the last call, with their
+    /// reasons, for hosts that hand them to scripts
+    pub fn take_rejected_promises(&mut self) -> Vec<(GcHandle, Value)> {
+        self.vm.take_unhandled_rejections()
+    }
+
+    /// The value an uncaught `error` threw, for hosts that hand it to
+    /// scripts: the exception itself, or the error object a runtime error
+    /// stands for. `None` for errors scripts cannot catch, such as running
+    /// out of memory. Call it once, before running more code.
+    pub fn exception_value(&mut self, error: &RuntimeError) -> Option<Value> {
+        self.vm.error_value(error)
+    }
+
+    pub fn has_pending_jobs(&self) -> bool {
+        self.vm.has_pending_jobs()
+    }
+
+    pub fn create_promise(&mut self) -> GcHandle {
+        self.vm.create_promise()
+    }
+
+    pub fn resolve_promise(&mut self, promise: GcHandle, value: Value) {
+        self.vm.resolve_promise(promise, value);
+    }
+
+    pub fn reject_promise(&mut self, promise: GcHandle, reason: Value) {
+        self.vm.reject_promise(promise, reason);
+    }
+
+    /// Call `on_fulfilled` with `value`'s value or `on_rejected` with its
+    /// reason once it settles, as a microtask; what `then` does for scripts
+    pub fn when_settled(&mut self, value: Value, on_fulfilled: Value, on_rejected: Value) {
+        self.vm.when_settled(value, on_fulfilled, on_rejected);
+    }
+
+    /// The state of `value` if it is a promise
+    pub fn promise_state(&self, value: &Value) -> Option<PromiseInspection> {
+        self.vm.promise_state(value)
+    }
+
+    /// Count the host as handling `promise`'s rejection, now or when it
+    /// comes, so it is not reported as unhandled
+    pub fn handle_rejection(&mut self, promise: GcHandle) {
+        self.vm.handle_rejection(promise);
+    }
+
+    /// Copy `value` out of the heap with the structured clone algorithm, to
+    /// recreate in this runtime or another with `deserialize`
+    pub fn serialize(&self, value: &Value) -> Result<SerializedValue, DataCloneError> {
+        self.vm.serialize(value)
+    }
+
+    pub fn deserialize(&mut self, serialized: &SerializedValue) -> Value {
+        self.vm.deserialize(serialized)
+    }
+
+    /// `name: message` for an error-like thrown value, its string form
+    /// otherwise, as uncaught exceptions are reported
+    pub fn describe_exception(&self, exception: &Value) -> String {
+        self.vm.describe_exception(exception)
+    }
+
+    pub fn vm_stats(&self) -> VmStats {
+        self.vm.vm_stats()
+    }
+
+    pub fn ic_stats(&self) -> IcStats {
+        self.vm.ic_stats()
+    }
+
+    pub fn add_tier(&mut self, tier: Box<dyn Tier>) {
+        self.vm.add_tier(tier);
+    }
+
+    pub fn set_tier_thresholds(&mut self, thresholds: TierThresholds) {
+        self.vm.set_tier_thresholds(thresholds);
+    }
+
+    pub fn stack_trace(&self) -> Vec<StackFrameInfo> {
+        self.vm.stack_trace()
+    }
+
+    /// Set up `bytecode` to run one instruction per `step`
+    pub fn begin_stepping(&mut self, bytecode: &Bytecode) {
+        self.vm.begin_stepping(bytecode);
+    }
+
+    /// Run the stepped code's next instruction; its result once finished
+    pub fn step(&mut self) -> RuntimeResult<Option<Value>> {
+        self.vm.step()
+    }
+
+    pub fn end_stepping(&mut self) {
+        self.vm.end_stepping();
+    }
+
+    pub fn current_frame(&self) -> Option<FrameSnapshot> {
+        self.vm.current_frame()
+    }
+
+    /// A handle for `value`, as `execute` returns its result
+    pub fn value_to_gc_handle(&mut self, value: Value) -> RuntimeResult<GcHandle> {
+        Ok(self.vm.value_to_handle(value))
+    }
+
+    /// Collect garbage, keeping alive what scripts can still reach
     pub fn gc_collect(&mut self) -> usize {
-        let mut gc = self.gc.lock().unwrap();
-        gc.collect()
+        self.vm.collect_garbage()
+    }
+
+    /// Snapshot the heap, with everything scripts can reach as roots
+    pub fn heap_snapshot(&self) -> bebion_gc::HeapSnapshot {
+        self.vm.heap_snapshot()
+    }
+
+    /// Run `finalizer` once `object` is collected, to release a native
+    /// resource it wraps. Returns false if `object` is not a heap object.
+    pub fn register_finalizer<F>(&mut self, object: &Value, finalizer: F) -> bool
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let Some(handle) = object.as_handle() else {
+            return false;
+        };
+        self.vm.gc().lock().unwrap().register_finalizer(handle, Box::new(finalizer))
+    }
+
+    /// Count `bytes` of native memory `object` keeps alive towards the
+    /// collection thresholds, replacing what was counted before
+    pub fn set_external_bytes(&mut self, object: &Value, bytes: usize) -> bool {
+        let Some(handle) = object.as_handle() else {
+            return false;
+        };
+        self.vm.gc().lock().unwrap().set_external_bytes(handle, bytes)
+    }
+
+    /// Trace the objects `source` pushes as roots at every collection, for
+    /// host tables holding script values
+    pub fn add_root_source<F>(&mut self, source: F)
+    where
+        F: Fn(&mut Vec<GcHandle>) + Send + Sync + 'static,
+    {
+        self.vm.add_root_source(Box::new(source));
     }
 
     pub fn gc_stats(&self) -> bebion_gc::GcStats {
-        let gc = self.gc.lock().unwrap();
+        let gc = self.vm.gc().lock().unwrap();
         gc.stats()
     }
 }
\ No newline at end of file
@@ -0,0 +1,164 @@
+//! JavaScript string representation
+//!
+//! JS strings are sequences of UTF-16 code units that may contain unpaired
+//! surrogates (WTF-16). Lengths and indices are measured in code units;
+//! conversion to Rust `String` happens only at host boundaries.
+
+use std::fmt;
+
+#[derive(Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JsString {
+    units: Vec<u16>,
+}
+
+impl JsString {
+    pub fn new() -> Self {
+        Self { units: Vec::new() }
+    }
+
+    pub fn from_code_units(units: Vec<u16>) -> Self {
+        Self { units }
+    }
+
+    pub fn from_code_point(code_point: u32) -> Option<Self> {
+        let ch = char::from_u32(code_point)?;
+        let mut buf = [0u16; 2];
+        Some(Self::from_code_units(ch.encode_utf16(&mut buf).to_vec()))
+    }
+
+    pub fn code_units(&self) -> &[u16] {
+        &self.units
+    }
+
+    /// Length in UTF-16 code units, as reported by `.length`
+    pub fn len(&self) -> usize {
+        self.units.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+
+    /// `String.prototype.charCodeAt`
+    pub fn char_code_at(&self, index: usize) -> Option<u16> {
+        self.units.get(index).copied()
+    }
+
+    /// `String.prototype.codePointAt`, combining a surrogate pair starting at `index`
+    pub fn code_point_at(&self, index: usize) -> Option<u32> {
+        let first = *self.units.get(index)?;
+        if is_lead_surrogate(first) {
+            if let Some(&second) = self.units.get(index + 1) {
+                if is_trail_surrogate(second) {
+                    return Some(combine_surrogates(first, second));
+                }
+            }
+        }
+        Some(first as u32)
+    }
+
+    /// `String.prototype.charAt`; empty when out of range
+    pub fn char_at(&self, index: usize) -> JsString {
+        self.substring(index, index + 1)
+    }
+
+    /// Code units in `start..end`, with both bounds clamped to the length
+    pub fn substring(&self, start: usize, end: usize) -> JsString {
+        let end = end.min(self.len());
+        let start = start.min(end);
+        Self::from_code_units(self.units[start..end].to_vec())
+    }
+
+    pub fn concat(&self, other: &JsString) -> JsString {
+        let mut units = Vec::with_capacity(self.len() + other.len());
+        units.extend_from_slice(&self.units);
+        units.extend_from_slice(&other.units);
+        Self::from_code_units(units)
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.units.extend(s.encode_utf16());
+    }
+
+    /// Index of the first occurrence of `search` at or after `from`
+    pub fn index_of(&self, search: &JsString, from: usize) -> Option<usize> {
+        if search.is_empty() {
+            return Some(from.min(self.len()));
+        }
+        if search.len() > self.len() {
+            return None;
+        }
+        (from..=self.len() - search.len())
+            .find(|&i| self.units[i..i + search.len()] == search.units[..])
+    }
+
+    pub fn contains(&self, search: &str) -> bool {
+        self.index_of(&JsString::from(search), 0).is_some()
+    }
+
+    /// Whether the string contains no unpaired surrogates
+    pub fn is_well_formed(&self) -> bool {
+        char::decode_utf16(self.units.iter().copied()).all(|c| c.is_ok())
+    }
+
+    /// Convert for the host, replacing unpaired surrogates with U+FFFD
+    pub fn to_rust_string(&self) -> String {
+        char::decode_utf16(self.units.iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+fn is_lead_surrogate(unit: u16) -> bool {
+    (0xD800..=0xDBFF).contains(&unit)
+}
+
+fn is_trail_surrogate(unit: u16) -> bool {
+    (0xDC00..=0xDFFF).contains(&unit)
+}
+
+fn combine_surrogates(lead: u16, trail: u16) -> u32 {
+    (((lead as u32) - 0xD800) << 10) + ((trail as u32) - 0xDC00) + 0x10000
+}
+
+impl From<&str> for JsString {
+    fn from(s: &str) -> Self {
+        Self::from_code_units(s.encode_utf16().collect())
+    }
+}
+
+impl From<String> for JsString {
+    fn from(s: String) -> Self {
+        Self::from(s.as_str())
+    }
+}
+
+impl From<&String> for JsString {
+    fn from(s: &String) -> Self {
+        Self::from(s.as_str())
+    }
+}
+
+impl PartialEq<str> for JsString {
+    fn eq(&self, other: &str) -> bool {
+        self.units.iter().copied().eq(other.encode_utf16())
+    }
+}
+
+impl PartialEq<&str> for JsString {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Display for JsString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_rust_string())
+    }
+}
+
+impl fmt::Debug for JsString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.to_rust_string())
+    }
+}
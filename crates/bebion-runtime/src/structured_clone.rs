@@ -0,0 +1,304 @@
+//! The structured clone algorithm
+//!
+//! `VirtualMachine::serialize` copies the object graph below a value out of
+//! the heap into a `SerializedValue`, which owns its data and can move to
+//! another thread, be kept as a cache or snapshot, and be deserialized into
+//! the same engine or another one any number of times. Objects reached
+//! twice are serialized once, so shared references and cycles come back as
+//! they were, including typed arrays sharing a buffer.
+//!
+//! Plain objects, arrays, `Map`s, `Set`s, `ArrayBuffer`s, typed arrays,
+//! `DataView`s and `RegExp`s can be cloned. Objects come back inheriting
+//! from the target's `Object.prototype` with their own string-keyed
+//! properties only; a `RegExp`'s `lastIndex` starts over. Functions,
+//! symbols, promises, weak collections and detached buffers cannot be
+//! cloned. There are no `Date` objects yet, so a `Date.now()` timestamp
+//! clones as the number it is.
+
+use crate::symbol::Symbol;
+use crate::vm::VirtualMachine;
+use crate::Value;
+use bebion_gc::{GarbageCollector, GcHandle, GcObjectType, MapKey, OrderedMap, TypedArrayKind};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A value copied out of a heap by `VirtualMachine::serialize`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerializedValue {
+    root: Entry,
+    /// Every object in the graph, referenced from entries by position
+    objects: Vec<SerializedObject>,
+}
+
+/// A primitive, or a reference to one of the serialized objects
+#[derive(Debug, Clone, PartialEq)]
+enum Entry {
+    Undefined,
+    Null,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    Object(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SerializedObject {
+    Object(Vec<(String, Entry)>),
+    Array(Vec<Entry>),
+    Map(Vec<(Entry, Entry)>),
+    Set(Vec<Entry>),
+    RegExp { pattern: String, flags: String },
+    ArrayBuffer(Vec<u8>),
+    TypedArray { kind: TypedArrayKind, buffer: usize, byte_offset: usize, length: usize },
+    DataView { buffer: usize, byte_offset: usize, byte_length: usize },
+}
+
+/// A value the structured clone algorithm cannot copy
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataCloneError(pub String);
+
+impl fmt::Display for DataCloneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DataCloneError: {}", self.0)
+    }
+}
+
+impl std::error::Error for DataCloneError {}
+
+impl VirtualMachine {
+    /// Copy `value` and everything it references out of the heap
+    pub fn serialize(&self, value: &Value) -> Result<SerializedValue, DataCloneError> {
+        let gc = self.gc().lock().unwrap();
+        let mut serializer = Serializer { gc: &gc, memo: HashMap::new(), objects: Vec::new(), pending: Vec::new() };
+        let root = match value {
+            Value::Undefined => Entry::Undefined,
+            Value::Null => Entry::Null,
+            Value::Boolean(b) => Entry::Boolean(*b),
+            Value::Number(n) => Entry::Number(*n),
+            Value::String(s) => Entry::String(s.to_string()),
+            Value::Symbol(symbol) => return Err(not_cloneable(&symbol.to_string())),
+            Value::NativeFunction(function) => return Err(not_cloneable(&format!("[Function: {}]", function.name()))),
+            Value::Object(handle) => serializer.entry(*handle)?,
+        };
+        // Objects are filled in after they are first reached, so deep
+        // graphs do not recurse
+        while let Some((handle, position)) = serializer.pending.pop() {
+            serializer.objects[position] = Some(serializer.object(handle)?);
+        }
+        Ok(SerializedValue {
+            root,
+            objects: serializer.objects.into_iter().map(|object| object.expect("serialized object")).collect(),
+        })
+    }
+
+    /// Recreate a serialized value in this heap
+    pub fn deserialize(&mut self, serialized: &SerializedValue) -> Value {
+        let intrinsics = self.intrinsics();
+        let gc = self.gc().clone();
+        let mut gc = gc.lock().unwrap();
+
+        // Collections and buffers first, empty, then the views over the
+        // buffers; everything stays rooted until the graph is linked up
+        let mut handles = vec![None; serialized.objects.len()];
+        for (position, object) in serialized.objects.iter().enumerate() {
+            let (handle, prototype) = match object {
+                SerializedObject::Object(_) => (gc.allocate_object(HashMap::new()), Some(intrinsics.object_prototype)),
+                SerializedObject::Array(_) => (gc.allocate_array(Vec::new()), Some(intrinsics.array_prototype)),
+                SerializedObject::Map(_) => (gc.allocate_map(), Some(intrinsics.map_prototype)),
+                SerializedObject::Set(_) => (gc.allocate_set(), Some(intrinsics.set_prototype)),
+                SerializedObject::RegExp { pattern, flags } => {
+                    (gc.allocate_regexp(pattern.clone(), flags.clone()), Some(intrinsics.regexp_prototype))
+                }
+                SerializedObject::ArrayBuffer(bytes) => {
+                    (gc.allocate(GcObjectType::ArrayBuffer(bytes.clone())), Some(intrinsics.array_buffer_prototype))
+                }
+                SerializedObject::TypedArray { .. } | SerializedObject::DataView { .. } => continue,
+            };
+            gc.set_prototype(handle, prototype);
+            gc.add_root(handle);
+            handles[position] = Some(handle);
+        }
+        for (position, object) in serialized.objects.iter().enumerate() {
+            let (handle, prototype) = match *object {
+                SerializedObject::TypedArray { kind, buffer, byte_offset, length } => {
+                    let buffer = handles[buffer].expect("buffer deserialized");
+                    (gc.allocate_typed_array(kind, buffer, byte_offset, length), intrinsics.typed_array_prototype)
+                }
+                SerializedObject::DataView { buffer, byte_offset, byte_length } => {
+                    let buffer = handles[buffer].expect("buffer deserialized");
+                    (gc.allocate_data_view(buffer, byte_offset, byte_length), intrinsics.data_view_prototype)
+                }
+                _ => continue,
+            };
+            gc.set_prototype(handle, Some(prototype));
+            gc.add_root(handle);
+            handles[position] = Some(handle);
+        }
+        let handles: Vec<GcHandle> = handles.into_iter().map(|handle| handle.expect("object deserialized")).collect();
+
+        let mut primitives = Vec::new();
+        let mut handle_of = |gc: &mut GarbageCollector, entry: &Entry| -> GcHandle {
+            let handle = match entry {
+                Entry::Object(position) => return handles[*position],
+                Entry::Undefined => gc.allocate_undefined(),
+                Entry::Null => gc.allocate_null(),
+                Entry::Boolean(b) => gc.allocate_boolean(*b),
+                Entry::Number(n) => gc.allocate_number(*n),
+                Entry::String(s) => gc.intern_string(s),
+            };
+            gc.add_root(handle);
+            primitives.push(handle);
+            handle
+        };
+        for (&handle, object) in handles.iter().zip(&serialized.objects) {
+            let object_type = match object {
+                SerializedObject::Object(properties) => GcObjectType::Object(
+                    properties.iter().map(|(key, entry)| (key.clone(), handle_of(&mut gc, entry))).collect(),
+                ),
+                SerializedObject::Array(elements) => {
+                    GcObjectType::Array(elements.iter().map(|entry| handle_of(&mut gc, entry)).collect())
+                }
+                SerializedObject::Map(entries) => {
+                    let mut map = OrderedMap::new();
+                    for (key, value) in entries {
+                        let key_handle = handle_of(&mut gc, key);
+                        let value = handle_of(&mut gc, value);
+                        map.insert(map_key(key, key_handle), key_handle, value);
+                    }
+                    GcObjectType::Map(map)
+                }
+                SerializedObject::Set(members) => {
+                    let mut set = OrderedMap::new();
+                    for member in members {
+                        let handle = handle_of(&mut gc, member);
+                        set.insert(map_key(member, handle), handle, handle);
+                    }
+                    GcObjectType::Set(set)
+                }
+                _ => continue,
+            };
+            gc.update_object(handle, object_type);
+        }
+
+        for handle in handles.iter().chain(&primitives) {
+            gc.remove_root(*handle);
+        }
+        drop(gc);
+        match &serialized.root {
+            Entry::Undefined => Value::Undefined,
+            Entry::Null => Value::Null,
+            Entry::Boolean(b) => Value::Boolean(*b),
+            Entry::Number(n) => Value::Number(*n),
+            Entry::String(s) => Value::from(s.as_str()),
+            Entry::Object(position) => Value::Object(handles[*position]),
+        }
+    }
+}
+
+/// Walks a heap for `VirtualMachine::serialize`
+struct Serializer<'a> {
+    gc: &'a GarbageCollector,
+    /// Position of each object reached so far
+    memo: HashMap<GcHandle, usize>,
+    objects: Vec<Option<SerializedObject>>,
+    /// Objects reached but not serialized yet, with their positions
+    pending: Vec<(GcHandle, usize)>,
+}
+
+impl Serializer<'_> {
+    /// The entry for the value `handle` holds, reserving a position for it
+    /// if it is an object reached for the first time
+    fn entry(&mut self, handle: GcHandle) -> Result<Entry, DataCloneError> {
+        if let Some(&position) = self.memo.get(&handle) {
+            return Ok(Entry::Object(position));
+        }
+        let Some(object_type) = self.gc.get_object_type(handle) else {
+            return Ok(Entry::Undefined);
+        };
+        match object_type {
+            GcObjectType::Undefined => return Ok(Entry::Undefined),
+            GcObjectType::Null => return Ok(Entry::Null),
+            GcObjectType::Boolean(b) => return Ok(Entry::Boolean(*b)),
+            GcObjectType::Number(n) => return Ok(Entry::Number(*n)),
+            GcObjectType::String(s) => return Ok(Entry::String(s.to_string())),
+            GcObjectType::Symbol { id, description } => {
+                return Err(not_cloneable(&Symbol::from_parts(*id, description.as_deref()).to_string()));
+            }
+            GcObjectType::Function { name, .. } => {
+                let description = match name.as_deref() {
+                    Some(name) if !name.is_empty() => format!("[Function: {}]", name),
+                    _ => "[Function (anonymous)]".to_string(),
+                };
+                return Err(not_cloneable(&description));
+            }
+            GcObjectType::Promise { .. } => return Err(not_cloneable("#<Promise>")),
+            GcObjectType::WeakMap(_) => return Err(not_cloneable("#<WeakMap>")),
+            GcObjectType::WeakSet(_) => return Err(not_cloneable("#<WeakSet>")),
+            GcObjectType::DetachedArrayBuffer => return Err(not_cloneable("A detached ArrayBuffer")),
+            _ => {}
+        }
+        let position = self.objects.len();
+        self.objects.push(None);
+        self.memo.insert(handle, position);
+        self.pending.push((handle, position));
+        Ok(Entry::Object(position))
+    }
+
+    /// The position of a buffer a view is over
+    fn buffer(&mut self, buffer: GcHandle) -> Result<usize, DataCloneError> {
+        match self.entry(buffer)? {
+            Entry::Object(position) => Ok(position),
+            _ => Err(not_cloneable("A view over a collected buffer")),
+        }
+    }
+
+    fn object(&mut self, handle: GcHandle) -> Result<SerializedObject, DataCloneError> {
+        let gc = self.gc;
+        Ok(match gc.get_object_type(handle).expect("reachable object") {
+            GcObjectType::Object(properties) => {
+                // In property order, so the copy gets the same shape
+                let mut entries = Vec::with_capacity(properties.len());
+                for (key, &value) in properties.iter().filter(|(key, _)| !Symbol::is_property_key(key)) {
+                    entries.push((key.to_string(), self.entry(value)?));
+                }
+                SerializedObject::Object(entries)
+            }
+            GcObjectType::Array(elements) => {
+                SerializedObject::Array(elements.iter().map(|&element| self.entry(element)).collect::<Result<_, _>>()?)
+            }
+            GcObjectType::Map(map) => SerializedObject::Map(
+                map.iter().map(|(key, value)| Ok((self.entry(key)?, self.entry(value)?))).collect::<Result<_, _>>()?,
+            ),
+            GcObjectType::Set(set) => {
+                SerializedObject::Set(set.iter().map(|(member, _)| self.entry(member)).collect::<Result<_, _>>()?)
+            }
+            GcObjectType::RegExp { pattern, flags, .. } => {
+                SerializedObject::RegExp { pattern: pattern.clone(), flags: flags.clone() }
+            }
+            GcObjectType::ArrayBuffer(bytes) => SerializedObject::ArrayBuffer(bytes.clone()),
+            &GcObjectType::TypedArray { kind, buffer, byte_offset, length } => {
+                SerializedObject::TypedArray { kind, buffer: self.buffer(buffer)?, byte_offset, length }
+            }
+            &GcObjectType::DataView { buffer, byte_offset, byte_length } => {
+                SerializedObject::DataView { buffer: self.buffer(buffer)?, byte_offset, byte_length }
+            }
+            _ => unreachable!("entry only reserves cloneable objects"),
+        })
+    }
+}
+
+/// The key a deserialized `Map` entry or `Set` member is found by
+fn map_key(entry: &Entry, handle: GcHandle) -> MapKey {
+    match entry {
+        Entry::Undefined => MapKey::Undefined,
+        Entry::Null => MapKey::Null,
+        Entry::Boolean(b) => MapKey::Boolean(*b),
+        Entry::Number(n) => MapKey::number(*n),
+        Entry::String(s) => MapKey::String(s.clone()),
+        Entry::Object(_) => MapKey::Object(handle),
+    }
+}
+
+fn not_cloneable(description: &str) -> DataCloneError {
+    DataCloneError(format!("{} could not be cloned.", description))
+}
@@ -0,0 +1,105 @@
+//! JavaScript symbol representation
+//!
+//! A symbol is a unique value with an optional description. Object
+//! properties are keyed by strings, so a symbol-keyed property is stored
+//! under a key no script can spell: a NUL, the symbol's id and, after a
+//! second NUL, its description.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Ids below this belong to the well-known symbols
+const FIRST_UNIQUE_ID: u64 = 16;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(FIRST_UNIQUE_ID);
+
+/// Marks a property key as a symbol's
+const KEY_PREFIX: char = '\0';
+
+#[derive(Clone)]
+pub struct Symbol {
+    id: u64,
+    description: Option<Arc<str>>,
+}
+
+impl Symbol {
+    /// A new symbol, different from every other
+    pub fn new(description: Option<String>) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            description: description.map(Arc::from),
+        }
+    }
+
+    /// The symbol `id` names, as read back from the heap
+    pub fn from_parts(id: u64, description: Option<&str>) -> Self {
+        Self {
+            id,
+            description: description.map(Arc::from),
+        }
+    }
+
+    /// `Symbol.iterator`
+    pub fn iterator() -> Self {
+        Self::from_parts(1, Some("Symbol.iterator"))
+    }
+
+    /// `Symbol.toStringTag`
+    pub fn to_string_tag() -> Self {
+        Self::from_parts(2, Some("Symbol.toStringTag"))
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The key properties keyed by this symbol are stored under
+    pub fn property_key(&self) -> String {
+        match &self.description {
+            Some(description) => format!("{}{}{}{}", KEY_PREFIX, self.id, KEY_PREFIX, description),
+            None => format!("{}{}", KEY_PREFIX, self.id),
+        }
+    }
+
+    /// The symbol a property key belongs to, if it is a symbol's
+    pub fn from_property_key(key: &str) -> Option<Self> {
+        let key = key.strip_prefix(KEY_PREFIX)?;
+        let (id, description) = match key.split_once(KEY_PREFIX) {
+            Some((id, description)) => (id, Some(description)),
+            None => (key, None),
+        };
+        Some(Self::from_parts(id.parse().ok()?, description))
+    }
+
+    /// Whether `key` is a symbol's rather than a string property name, so
+    /// listings of string keys can skip it
+    pub fn is_property_key(key: &str) -> bool {
+        key.starts_with(KEY_PREFIX)
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Symbol {}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// `Symbol(description)`, as `String(symbol)` gives
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Symbol({})", self.description().unwrap_or(""))
+    }
+}
@@ -0,0 +1,269 @@
+//! Hotness tracking and tiering hooks
+//!
+//! The VM counts how often each function is entered and each loop back-edge
+//! is taken. When a counter reaches its threshold every registered `Tier` is
+//! told once, so a JIT or optimizer can pick up the code, and the counts are
+//! reported through `vm_stats` for people tuning performance. A tier that
+//! compiles code hands it back through `compiled_code`, and the VM runs it in
+//! place of the interpreter when entering that code. Compiled code calls back
+//! into the VM through `NativeHost` for what it does not do natively.
+
+use crate::{RuntimeError, RuntimeResult, Value};
+use bebion_compiler::bytecode::Bytecode;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Identity of a piece of bytecode: a script or a function body
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CodeId(usize);
+
+impl CodeId {
+    pub fn of(bytecode: &Arc<Bytecode>) -> Self {
+        Self(Arc::as_ptr(bytecode) as usize)
+    }
+}
+
+/// A loop whose back-edge count reached the threshold
+#[derive(Debug, Clone)]
+pub struct HotLoop {
+    pub code: CodeId,
+    pub bytecode: Arc<Bytecode>,
+    /// First instruction of the loop, where an on-stack replacement would enter
+    pub header_pc: usize,
+    /// The backwards jump that closes the loop
+    pub back_edge_pc: usize,
+    pub iterations: u64,
+}
+
+/// A function whose entry count reached the threshold
+#[derive(Debug, Clone)]
+pub struct HotFunction {
+    pub code: CodeId,
+    pub bytecode: Arc<Bytecode>,
+    pub name: Option<String>,
+    /// Locals the frame held on its first entry, i.e. its arguments
+    pub entry_locals: usize,
+    pub calls: u64,
+}
+
+/// A higher execution tier, notified when code becomes hot
+pub trait Tier {
+    fn name(&self) -> &str;
+
+    fn on_hot_loop(&mut self, _hot_loop: &HotLoop) {}
+
+    fn on_hot_function(&mut self, _hot_function: &HotFunction) {}
+
+    /// Code this tier compiled for `code`, if any
+    fn compiled_code(&self, _code: CodeId) -> Option<Arc<dyn CompiledCode>> {
+        None
+    }
+}
+
+/// Code produced by a tier that can stand in for the interpreter
+pub trait CompiledCode {
+    /// Run a frame from its first instruction with the given locals.
+    /// `None` declines, e.g. when the arguments do not match what the code
+    /// was specialized for, and leaves the frame to the interpreter.
+    fn invoke(&self, host: &mut dyn NativeHost, locals: &mut Vec<Value>) -> Option<NativeOutcome>;
+}
+
+/// The VM as seen by compiled code running one of its frames. Indexes refer
+/// to the frame's bytecode and `pc` to the instruction doing the access,
+/// whose inline cache the VM uses.
+pub trait NativeHost {
+    fn load_constant(&mut self, index: usize) -> RuntimeResult<Value>;
+
+    fn load_global(&mut self, name_index: usize) -> RuntimeResult<Value>;
+
+    fn get_property(&mut self, pc: usize, object: &Value, key: &Value) -> RuntimeResult<Value>;
+
+    fn set_property(&mut self, pc: usize, object: &Value, key: &Value, value: Value) -> RuntimeResult<()>;
+}
+
+#[derive(Debug, Clone)]
+pub enum NativeOutcome {
+    Returned(Value),
+    /// The interpreter continues the frame at `pc` with `stack` as its
+    /// operand stack; the locals were updated in place
+    SideExit { pc: usize, stack: Vec<Value> },
+    /// A call back into the VM failed at `pc`. The frame is left as for a
+    /// side exit there and the error is thrown from that instruction.
+    Threw { pc: usize, stack: Vec<Value>, error: RuntimeError },
+}
+
+/// Counts at which code is reported to the registered tiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TierThresholds {
+    pub loop_iterations: u64,
+    pub function_calls: u64,
+}
+
+impl Default for TierThresholds {
+    fn default() -> Self {
+        Self {
+            loop_iterations: 1000,
+            function_calls: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopHotness {
+    pub code: CodeId,
+    pub header_pc: usize,
+    /// Source position of the loop header, when the bytecode has one
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub iterations: u64,
+    pub hot: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionHotness {
+    pub code: CodeId,
+    /// `None` for scripts and anonymous functions
+    pub name: Option<String>,
+    pub calls: u64,
+    pub hot: bool,
+}
+
+/// Execution counters reported by `vm_stats`, hottest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VmStats {
+    pub loops: Vec<LoopHotness>,
+    pub functions: Vec<FunctionHotness>,
+}
+
+struct LoopCounter {
+    bytecode: Arc<Bytecode>,
+    back_edge_pc: usize,
+    iterations: u64,
+}
+
+struct FunctionCounter {
+    bytecode: Arc<Bytecode>,
+    name: Option<String>,
+    entry_locals: usize,
+    calls: u64,
+}
+
+/// Per-loop and per-function counters plus the tiers listening to them
+#[derive(Default)]
+pub struct Hotness {
+    thresholds: TierThresholds,
+    loops: HashMap<(CodeId, usize), LoopCounter>,
+    functions: HashMap<CodeId, FunctionCounter>,
+    tiers: Vec<Box<dyn Tier>>,
+}
+
+impl Hotness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn thresholds(&self) -> TierThresholds {
+        self.thresholds
+    }
+
+    pub fn set_thresholds(&mut self, thresholds: TierThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    pub fn add_tier(&mut self, tier: Box<dyn Tier>) {
+        self.tiers.push(tier);
+    }
+
+    /// Count a backwards jump from `back_edge_pc` to `header_pc`
+    pub fn record_back_edge(&mut self, bytecode: &Arc<Bytecode>, header_pc: usize, back_edge_pc: usize) {
+        let code = CodeId::of(bytecode);
+        let counter = self.loops.entry((code, header_pc)).or_insert_with(|| LoopCounter {
+            bytecode: Arc::clone(bytecode),
+            back_edge_pc,
+            iterations: 0,
+        });
+        counter.iterations += 1;
+
+        if counter.iterations == self.thresholds.loop_iterations {
+            let hot_loop = HotLoop {
+                code,
+                bytecode: Arc::clone(&counter.bytecode),
+                header_pc,
+                back_edge_pc: counter.back_edge_pc,
+                iterations: counter.iterations,
+            };
+            for tier in &mut self.tiers {
+                tier.on_hot_loop(&hot_loop);
+            }
+        }
+    }
+
+    /// Count an entry into `bytecode`
+    pub fn record_function_entry(&mut self, bytecode: &Arc<Bytecode>, name: Option<&str>, entry_locals: usize) {
+        let code = CodeId::of(bytecode);
+        let counter = self.functions.entry(code).or_insert_with(|| FunctionCounter {
+            bytecode: Arc::clone(bytecode),
+            name: name.map(str::to_string),
+            entry_locals,
+            calls: 0,
+        });
+        counter.calls += 1;
+
+        if counter.calls == self.thresholds.function_calls {
+            let hot_function = HotFunction {
+                code,
+                bytecode: Arc::clone(&counter.bytecode),
+                name: counter.name.clone(),
+                entry_locals: counter.entry_locals,
+                calls: counter.calls,
+            };
+            for tier in &mut self.tiers {
+                tier.on_hot_function(&hot_function);
+            }
+        }
+    }
+
+    /// Compiled code for `code` from the first tier that has some
+    pub fn compiled_code(&self, code: CodeId) -> Option<Arc<dyn CompiledCode>> {
+        self.tiers.iter().find_map(|tier| tier.compiled_code(code))
+    }
+
+    /// Forget every count, releasing the bytecode the counters keep alive
+    pub fn reset(&mut self) {
+        self.loops.clear();
+        self.functions.clear();
+    }
+
+    pub fn stats(&self) -> VmStats {
+        let mut loops: Vec<_> = self
+            .loops
+            .iter()
+            .map(|(&(code, header_pc), counter)| {
+                let position = counter.bytecode.source_position(header_pc);
+                LoopHotness {
+                    code,
+                    header_pc,
+                    line: position.map(|(line, _)| line),
+                    column: position.map(|(_, column)| column),
+                    iterations: counter.iterations,
+                    hot: counter.iterations >= self.thresholds.loop_iterations,
+                }
+            })
+            .collect();
+        loops.sort_by(|a, b| b.iterations.cmp(&a.iterations).then(a.header_pc.cmp(&b.header_pc)));
+
+        let mut functions: Vec<_> = self
+            .functions
+            .iter()
+            .map(|(&code, counter)| FunctionHotness {
+                code,
+                name: counter.name.clone(),
+                calls: counter.calls,
+                hot: counter.calls >= self.thresholds.function_calls,
+            })
+            .collect();
+        functions.sort_by(|a, b| b.calls.cmp(&a.calls).then(a.code.cmp(&b.code)));
+
+        VmStats { loops, functions }
+    }
+}
@@ -1,27 +1,78 @@
 //! JavaScript value representation
 
+use crate::number::string_to_number;
+use crate::{JsString, Runtime, RuntimeResult, Symbol};
 use bebion_gc::{GcHandle, GcObjectType};
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Number(f64),
-    String(String),
+    String(JsString),
     Boolean(bool),
     Null,
     Undefined,
+    Symbol(Symbol),
     Object(GcHandle),
+    /// A host function implemented in Rust
+    NativeFunction(NativeFunction),
+}
+
+/// The Rust side of a native function: receives the runtime and the arguments
+pub type NativeFn = dyn Fn(&mut Runtime, &[Value]) -> RuntimeResult<Value> + Send + Sync;
+
+/// A host function scripts can call like any other function. Clones share
+/// the same function, and compare equal only to each other.
+#[derive(Clone)]
+pub struct NativeFunction {
+    name: String,
+    function: Arc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: impl Into<String>, function: F) -> Self
+    where
+        F: Fn(&mut Runtime, &[Value]) -> RuntimeResult<Value> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            function: Arc::new(function),
+        }
+    }
+
+    /// The function's `name` property
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn call(&self, runtime: &mut Runtime, args: &[Value]) -> RuntimeResult<Value> {
+        (self.function)(runtime, args)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.function, &other.function)
+    }
 }
 
 impl Value {
     pub fn from_gc_object_type(obj_type: &GcObjectType, handle: GcHandle) -> Self {
         match obj_type {
             GcObjectType::Number(n) => Value::Number(*n),
-            GcObjectType::String(s) => Value::String(s.clone()),
+            GcObjectType::String(s) => Value::String(JsString::from(&**s)),
             GcObjectType::Boolean(b) => Value::Boolean(*b),
             GcObjectType::Null => Value::Null,
             GcObjectType::Undefined => Value::Undefined,
+            GcObjectType::Symbol { id, description } => Value::Symbol(Symbol::from_parts(*id, description.as_deref())),
             _ => Value::Object(handle),
         }
     }
@@ -32,7 +83,7 @@ impl Value {
             Value::Number(n) => *n != 0.0 && !n.is_nan(),
             Value::String(s) => !s.is_empty(),
             Value::Null | Value::Undefined => false,
-            Value::Object(_) => true,
+            Value::Symbol(_) | Value::Object(_) | Value::NativeFunction(_) => true,
         }
     }
 
@@ -41,34 +92,30 @@ impl Value {
             Value::Number(n) => Ok(*n),
             Value::Boolean(true) => Ok(1.0),
             Value::Boolean(false) => Ok(0.0),
-            Value::String(s) => {
-                s.parse::<f64>().map_err(|_| {
-                    crate::RuntimeError::TypeError(format!("Cannot convert string '{}' to number", s))
-                })
-            }
+            Value::String(s) => Ok(string_to_number(&s.to_rust_string())),
             Value::Null => Ok(0.0),
             Value::Undefined => Ok(f64::NAN),
+            Value::Symbol(_) => Err(crate::RuntimeError::TypeError(
+                "Cannot convert a Symbol value to a number".to_string()
+            )),
             Value::Object(_) => Err(crate::RuntimeError::TypeError(
                 "Cannot convert object to number".to_string()
             )),
+            Value::NativeFunction(_) => Ok(f64::NAN),
         }
     }
 
     pub fn to_string(&self) -> String {
         match self {
-            Value::Number(n) => {
-                if n.fract() == 0.0 && n.is_finite() {
-                    format!("{}", *n as i64)
-                } else {
-                    n.to_string()
-                }
-            }
-            Value::String(s) => s.clone(),
+            Value::Number(n) => crate::number::number_to_string(*n),
+            Value::String(s) => s.to_rust_string(),
             Value::Boolean(true) => "true".to_string(),
             Value::Boolean(false) => "false".to_string(),
             Value::Null => "null".to_string(),
             Value::Undefined => "undefined".to_string(),
+            Value::Symbol(symbol) => symbol.to_string(),
             Value::Object(_) => "[object Object]".to_string(),
+            Value::NativeFunction(function) => format!("function {}() {{ [native code] }}", function.name()),
         }
     }
 
@@ -79,12 +126,22 @@ impl Value {
             Value::Boolean(_) => "boolean",
             Value::Null => "object", // JavaScript quirk
             Value::Undefined => "undefined",
+            Value::Symbol(_) => "symbol",
             Value::Object(_) => "object",
+            Value::NativeFunction(_) => "function",
+        }
+    }
+
+    /// The heap object behind the value, if it is one
+    pub fn as_handle(&self) -> Option<GcHandle> {
+        match self {
+            Value::Object(handle) => Some(*handle),
+            _ => None,
         }
     }
 
     pub fn is_primitive(&self) -> bool {
-        !matches!(self, Value::Object(_))
+        !matches!(self, Value::Object(_) | Value::NativeFunction(_))
     }
 
     pub fn strict_equals(&self, other: &Value) -> bool {
@@ -100,7 +157,9 @@ impl Value {
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Null, Value::Null) => true,
             (Value::Undefined, Value::Undefined) => true,
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
             (Value::Object(a), Value::Object(b)) => a == b,
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => a == b,
             _ => false,
         }
     }
@@ -114,18 +173,16 @@ impl Value {
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Null, Value::Null) => true,
             (Value::Undefined, Value::Undefined) => true,
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
             (Value::Object(a), Value::Object(b)) => a == b,
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => a == b,
             
             // null == undefined
             (Value::Null, Value::Undefined) | (Value::Undefined, Value::Null) => true,
             
             // Number and string conversion
             (Value::Number(n), Value::String(s)) | (Value::String(s), Value::Number(n)) => {
-                if let Ok(s_num) = s.parse::<f64>() {
-                    n == &s_num
-                } else {
-                    false
-                }
+                *n == string_to_number(&s.to_rust_string())
             }
             
             // Boolean conversion
@@ -165,13 +222,25 @@ impl From<bool> for Value {
 
 impl From<String> for Value {
     fn from(s: String) -> Self {
-        Value::String(s)
+        Value::String(JsString::from(s))
     }
 }
 
 impl From<&str> for Value {
     fn from(s: &str) -> Self {
-        Value::String(s.to_string())
+        Value::String(JsString::from(s))
+    }
+}
+
+impl From<Symbol> for Value {
+    fn from(symbol: Symbol) -> Self {
+        Value::Symbol(symbol)
+    }
+}
+
+impl From<JsString> for Value {
+    fn from(s: JsString) -> Self {
+        Value::String(s)
     }
 }
 
@@ -179,9 +248,12 @@ impl From<&str> for Value {
 pub fn add_values(left: &Value, right: &Value) -> Result<Value, crate::RuntimeError> {
     match (left, right) {
         (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
-        (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
-        (Value::String(a), b) => Ok(Value::String(format!("{}{}", a, b.to_string()))),
-        (a, Value::String(b)) => Ok(Value::String(format!("{}{}", a.to_string(), b))),
+        (Value::String(a), Value::String(b)) => Ok(Value::String(a.concat(b))),
+        (Value::String(_), Value::Symbol(_)) | (Value::Symbol(_), Value::String(_)) => Err(
+            crate::RuntimeError::TypeError("Cannot convert a Symbol value to a string".to_string())
+        ),
+        (Value::String(a), b) => Ok(Value::String(a.concat(&JsString::from(b.to_string())))),
+        (a, Value::String(b)) => Ok(Value::String(JsString::from(a.to_string()).concat(b))),
         (a, b) => {
             let a_num = a.to_number()?;
             let b_num = b.to_number()?;
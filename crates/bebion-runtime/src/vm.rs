@@ -1,9 +1,14 @@
 //! Virtual machine for executing bytecode
 
-use crate::{RuntimeError, RuntimeResult, Value};
+use crate::builtins::{self, Builtin, Intrinsics, SignalRecord, View};
+use crate::inline_cache::{IcStats, PropertyEntry, Site, SiteCache};
+use crate::regexp::{self, RegExp, RegExpCache, RegExpMatch};
+use crate::tier::{CodeId, Hotness, NativeHost, NativeOutcome, Tier, TierThresholds, VmStats};
+use crate::{HostClock, HostRandom, NativeFunction, Runtime, RuntimeError, RuntimeResult, Symbol, Value};
 use bebion_compiler::bytecode::{Bytecode, Constant, Instruction};
-use bebion_gc::{GarbageCollector, GcHandle, GcObjectType};
-use std::collections::HashMap;
+use bebion_gc::{GarbageCollector, GcHandle, GcObjectType, HeapSnapshot, PromiseState};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use tracing::{debug, trace};
 
@@ -12,8 +17,131 @@ pub struct VirtualMachine {
     stack: Vec<Value>,
     call_stack: Vec<CallFrame>,
     globals: HashMap<String, Value>,
+    /// Script-level `let`s and `const`s in their temporal dead zone
+    uninitialized_globals: HashSet<String>,
     max_stack_size: usize,
     max_call_depth: usize,
+    /// Async frames suspended at an `await`, keyed by coroutine id
+    coroutines: HashMap<u64, Coroutine>,
+    next_coroutine_id: u64,
+    /// What is waiting on each pending promise
+    promise_waiters: HashMap<GcHandle, Vec<Waiter>>,
+    /// Promise jobs ready to run, in FIFO order
+    jobs: VecDeque<PromiseJob>,
+    /// Promises rejected with nothing waiting on them, until something
+    /// awaits them or the host takes them as unhandled
+    unhandled_rejections: Vec<GcHandle>,
+    /// Errors that escaped `queueMicrotask` callbacks, for the host to
+    /// report, with the values they threw
+    job_errors: Vec<(RuntimeError, Option<Value>)>,
+    hotness: Hotness,
+    /// Inline caches of the property reads, property writes and calls run
+    /// so far
+    load_cache: SiteCache<PropertyEntry>,
+    store_cache: SiteCache<PropertyEntry>,
+    call_cache: SiteCache<(GcHandle, CallTarget)>,
+    /// Code of each function literal evaluated so far, keyed by the enclosing
+    /// bytecode and constant index so every closure of a literal shares one
+    /// `Arc` (and one `CodeId`). The enclosing bytecode is held to keep its
+    /// address from being reused by other code.
+    function_code: HashMap<(CodeId, usize), (Arc<Bytecode>, Arc<FunctionCode>)>,
+    /// Code behind each function object
+    functions: HashMap<GcHandle, Arc<FunctionCode>>,
+    /// Rust implementation behind each built-in function object
+    builtins: HashMap<GcHandle, Builtin>,
+    /// Host function behind each function object a native function was
+    /// stored as
+    native_functions: HashMap<GcHandle, NativeFunction>,
+    intrinsics: Intrinsics,
+    random: Arc<HostRandom>,
+    clock: Arc<HostClock>,
+    /// The exception behind the last `RuntimeError::Thrown`, so a run that
+    /// called back into JS (e.g. from a built-in) can rethrow it to its own
+    /// handlers
+    escaped_exception: Option<Value>,
+    /// Call depth of the run `step` advances, if one was begun
+    stepping: Option<usize>,
+    /// Loads what an `import` names, given the specifier and the import
+    /// type; without one, imports fail
+    import_hook: Option<NativeFunction>,
+    /// Takes what a module's `export`s name, given the name and the value;
+    /// without one, exports fail
+    export_hook: Option<NativeFunction>,
+    /// Makes a module's `import.meta`, given the module's URL; without one,
+    /// reading it fails
+    import_meta_hook: Option<NativeFunction>,
+    /// Symbols `Symbol.for` has handed out, by key
+    symbol_registry: HashMap<String, Symbol>,
+    /// Compiled form of each `RegExp` object matched so far
+    regexps: RegExpCache,
+    /// Listeners and host handle of each `AbortSignal`
+    abort_signals: HashMap<GcHandle, SignalRecord>,
+    /// Signals from `AbortSignal.timeout` and their delays in milliseconds,
+    /// for the host's timers to abort
+    abort_timeouts: Vec<(GcHandle, f64)>,
+    /// Raised by the collector when an allocation wants a collection, which
+    /// waits for the next instruction outside any host call
+    collection_requested: Arc<AtomicBool>,
+    /// Built-ins and native functions running, whose Rust locals may hold
+    /// objects the collector cannot see
+    host_calls: usize,
+    /// Host tables traced as roots along with the VM's own
+    root_sources: Vec<Box<RootSource>>,
+    /// Raised by compiled code at the instruction the frame resumes at,
+    /// thrown when the interpreter next steps
+    pending_throw: Option<RuntimeError>,
+}
+
+/// The VM serving calls from the compiled code of the frame running
+/// `bytecode`
+struct FrameHost<'a> {
+    vm: &'a mut VirtualMachine,
+    bytecode: Arc<Bytecode>,
+}
+
+impl NativeHost for FrameHost<'_> {
+    fn load_constant(&mut self, index: usize) -> RuntimeResult<Value> {
+        match self.bytecode.constants.get(index) {
+            Some(Constant::Function { .. }) => self.vm.function_value(&self.bytecode, index),
+            Some(constant) => self.vm.constant_to_value(constant),
+            None => Err(RuntimeError::InvalidBytecode(format!("Invalid constant index: {}", index))),
+        }
+    }
+
+    fn load_global(&mut self, name_index: usize) -> RuntimeResult<Value> {
+        let name = self.bytecode.names.get(name_index)
+            .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", name_index)))?;
+        self.vm.check_global_initialized(name)?;
+        Ok(self.vm.globals.get(name).cloned().unwrap_or(Value::Undefined))
+    }
+
+    fn get_property(&mut self, pc: usize, object: &Value, key: &Value) -> RuntimeResult<Value> {
+        self.vm.get_property_at_site((CodeId::of(&self.bytecode), pc), object, key)
+    }
+
+    fn set_property(&mut self, pc: usize, object: &Value, key: &Value, value: Value) -> RuntimeResult<()> {
+        self.vm.set_property_at_site((CodeId::of(&self.bytecode), pc), object, key, value)
+    }
+}
+
+/// Pushes the objects a host table holds onto the list of roots
+pub type RootSource = dyn Fn(&mut Vec<GcHandle>) + Send + Sync;
+
+/// What a call site runs for a function object it has called before
+#[derive(Clone)]
+enum CallTarget {
+    Builtin(Builtin),
+    Function(Arc<FunctionCode>),
+}
+
+/// What calling a function object runs
+#[derive(Debug)]
+struct FunctionCode {
+    name: Option<String>,
+    param_count: usize,
+    bytecode: Arc<Bytecode>,
+    is_async: bool,
+    is_generator: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -22,392 +150,2407 @@ struct CallFrame {
     pc: usize, // Program counter
     locals: Vec<Value>,
     base_stack_offset: usize,
+    /// Value of the last top-level expression statement
+    completion: Value,
+    /// Promise returned to the caller of an async function
+    async_promise: Option<GcHandle>,
+    /// Name of the function the frame runs, for stack traces
+    function_name: Option<String>,
+    /// Receiver the function was called on, undefined for a plain call
+    this: Value,
+    /// Line and column of the last `DebugInfo` marker the frame ran
+    position: Option<(usize, usize)>,
+    /// `let` and `const` slots in their temporal dead zone
+    uninitialized: HashSet<usize>,
+}
+
+impl CallFrame {
+    /// Where the frame is in its source: the statement the source map puts
+    /// `pc` in, or else the last `DebugInfo` marker the frame ran
+    fn source_position(&self) -> Option<(usize, usize)> {
+        self.bytecode.source_position(self.pc).or(self.position)
+    }
+}
+
+/// An async function frame parked at an `await`, with its operand stack
+#[derive(Debug)]
+struct Coroutine {
+    frame: CallFrame,
+    stack: Vec<Value>,
+}
+
+#[derive(Debug, Clone)]
+enum Waiter {
+    /// Resume the coroutine with the promise's outcome
+    Coroutine(u64),
+    /// Settle another promise the same way (promise adoption)
+    Promise(GcHandle),
+    /// The host waits on the promise itself, so its rejection is handled
+    Host,
+    /// Call the host's function for the outcome with the value or reason
+    Reaction { on_fulfilled: Value, on_rejected: Value },
+}
+
+/// A microtask: an `await` continuation, a `queueMicrotask` callback or a
+/// host's reaction to a promise
+#[derive(Debug)]
+enum PromiseJob {
+    Resume { coroutine: u64, outcome: Result<Value, Value> },
+    Callback { function: Value },
+    /// A `Waiter::Reaction` function with the outcome it reacts to
+    Reaction { function: Value, argument: Value },
+}
+
+/// The state of an awaited value
+enum Awaited {
+    Pending(GcHandle),
+    Settled(Result<Value, Value>),
+}
+
+/// The state of the innermost frame of a stepped run, for debuggers
+#[derive(Debug, Clone)]
+pub struct FrameSnapshot {
+    pub function: Option<String>,
+    /// Frames below this one
+    pub depth: usize,
+    pub pc: usize,
+    /// The instruction `step` runs next; `None` at the end of the code
+    pub instruction: Option<Instruction>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub locals: Vec<Value>,
+    /// The frame's operand stack, bottom first
+    pub stack: Vec<Value>,
+    pub constants: Vec<Constant>,
+}
+
+/// The state of a promise, as debuggers and `util.inspect` show it
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromiseInspection {
+    Pending,
+    Fulfilled(Value),
+    Rejected(Value),
+}
+
+/// A snapshot of one active call frame, innermost first in `stack_trace`
+#[derive(Debug, Clone)]
+pub struct StackFrameInfo {
+    pub function: Option<String>,
+    pub pc: usize,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl std::fmt::Display for StackFrameInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.function, self.line, self.column) {
+            (Some(function), Some(line), Some(column)) => write!(f, "at {} (<anonymous>:{}:{})", function, line, column),
+            (Some(function), _, _) => write!(f, "at {} (pc {})", function, self.pc),
+            (None, Some(line), Some(column)) => write!(f, "at <anonymous>:{}:{}", line, column),
+            (None, _, _) => write!(f, "at <anonymous> (pc {})", self.pc),
+        }
+    }
 }
 
 impl VirtualMachine {
     pub fn new(gc: Arc<Mutex<GarbageCollector>>) -> Self {
-        Self {
+        let intrinsics = Intrinsics::allocate(&mut gc.lock().unwrap());
+        let collection_requested = gc.lock().unwrap().defer_collections();
+        let mut vm = Self {
             gc,
             stack: Vec::with_capacity(1024),
             call_stack: Vec::with_capacity(256),
             globals: HashMap::new(),
+            uninitialized_globals: HashSet::new(),
             max_stack_size: 10000,
             max_call_depth: 1000,
-        }
+            coroutines: HashMap::new(),
+            next_coroutine_id: 0,
+            promise_waiters: HashMap::new(),
+            unhandled_rejections: Vec::new(),
+            job_errors: Vec::new(),
+            jobs: VecDeque::new(),
+            hotness: Hotness::new(),
+            load_cache: SiteCache::new(),
+            store_cache: SiteCache::new(),
+            call_cache: SiteCache::new(),
+            function_code: HashMap::new(),
+            functions: HashMap::new(),
+            builtins: HashMap::new(),
+            native_functions: HashMap::new(),
+            intrinsics,
+            random: Arc::new(HostRandom::new()),
+            clock: Arc::new(HostClock::new()),
+            escaped_exception: None,
+            stepping: None,
+            import_hook: None,
+            export_hook: None,
+            import_meta_hook: None,
+            symbol_registry: HashMap::new(),
+            regexps: RegExpCache::default(),
+            abort_signals: HashMap::new(),
+            abort_timeouts: Vec::new(),
+            collection_requested,
+            host_calls: 0,
+            root_sources: Vec::new(),
+            pending_throw: None,
+        };
+        builtins::install(&mut vm);
+        vm
     }
 
     pub fn execute(&mut self, bytecode: &Bytecode) -> RuntimeResult<Value> {
         debug!("Executing bytecode with {} instructions", bytecode.len());
         
-        let frame = CallFrame {
+        let depth = self.call_stack.len();
+        let mut frame = CallFrame {
             bytecode: Arc::new(bytecode.clone()),
             pc: 0,
             locals: Vec::new(),
             base_stack_offset: self.stack.len(),
+            completion: Value::Undefined,
+            async_promise: None,
+            function_name: None,
+            this: Value::Undefined,
+            position: None,
+            uninitialized: HashSet::new(),
         };
         
+        self.hotness.record_function_entry(&frame.bytecode, None, frame.locals.len());
+        self.presize_frame(&mut frame);
         self.call_stack.push(frame);
         
-        let result = self.run_interpreter_loop();
+        let result = match self.run_compiled_code() {
+            Some(value) => Ok(value),
+            None => self.run_interpreter_loop(),
+        };
         
         // Clean up call stack
-        self.call_stack.pop();
+        self.call_stack.truncate(depth);
         
         result
     }
 
+    /// Run `bytecode` as the body of an async function. Returns the promise
+    /// that settles with its return value once every `await` has resumed.
+    pub fn execute_async(&mut self, bytecode: &Bytecode) -> RuntimeResult<GcHandle> {
+        let promise = self.create_promise();
+        let depth = self.call_stack.len();
+        let mut frame = CallFrame {
+            bytecode: Arc::new(bytecode.clone()),
+            pc: 0,
+            locals: Vec::new(),
+            base_stack_offset: self.stack.len(),
+            completion: Value::Undefined,
+            async_promise: Some(promise),
+            function_name: None,
+            this: Value::Undefined,
+            position: None,
+            uninitialized: HashSet::new(),
+        };
+        
+        // Async frames stay in the interpreter, which settles their promise
+        self.hotness.record_function_entry(&frame.bytecode, None, frame.locals.len());
+        self.presize_frame(&mut frame);
+        self.call_stack.push(frame);
+        let result = self.run_interpreter_loop();
+        self.unwind_async(depth, result);
+        
+        Ok(promise)
+    }
+
+    /// Set up `bytecode` to run one instruction per `step`, abandoning any
+    /// run stepped before
+    pub fn begin_stepping(&mut self, bytecode: &Bytecode) {
+        self.end_stepping();
+        let mut frame = CallFrame {
+            bytecode: Arc::new(bytecode.clone()),
+            pc: 0,
+            locals: Vec::new(),
+            base_stack_offset: self.stack.len(),
+            completion: Value::Undefined,
+            async_promise: None,
+            function_name: None,
+            this: Value::Undefined,
+            position: None,
+            uninitialized: HashSet::new(),
+        };
+        self.presize_frame(&mut frame);
+        self.stepping = Some(self.call_stack.len());
+        self.call_stack.push(frame);
+    }
+
+    /// Run the stepped run's next instruction. A call into a built-in runs
+    /// to completion in one step, including any JS it calls back. Returns
+    /// the run's result once it has finished.
+    pub fn step(&mut self) -> RuntimeResult<Option<Value>> {
+        let depth = self.stepping
+            .ok_or_else(|| RuntimeError::InvalidOperation("No run is being stepped".to_string()))?;
+        let base_depth = depth + 1;
+
+        let result = match self.step_instruction(base_depth) {
+            Ok(None) => return Ok(None),
+            Ok(Some(value)) => Ok(value),
+            Err(error) => match self.error_value(&error) {
+                Some(exception) => match self.throw_value(exception, base_depth) {
+                    Ok(None) => return Ok(None),
+                    Ok(Some(value)) => Ok(value),
+                    Err(_) => Err(error),
+                },
+                None => Err(error),
+            },
+        };
+
+        self.end_stepping();
+        result.map(Some)
+    }
+
+    /// Whether a run begun with `begin_stepping` is still going
+    pub fn is_stepping(&self) -> bool {
+        self.stepping.is_some()
+    }
+
+    /// Abandon the stepped run, dropping its frames
+    pub fn end_stepping(&mut self) {
+        if let Some(depth) = self.stepping.take() {
+            if let Some(frame) = self.call_stack.get(depth) {
+                self.stack.truncate(frame.base_stack_offset);
+            }
+            self.call_stack.truncate(depth);
+        }
+    }
+
+    /// The innermost frame of the stepped run
+    pub fn current_frame(&self) -> Option<FrameSnapshot> {
+        let depth = self.stepping?;
+        let frame = self.call_stack.last()?;
+        let position = frame.source_position();
+        Some(FrameSnapshot {
+            function: frame.function_name.clone(),
+            depth: self.call_stack.len() - 1 - depth,
+            pc: frame.pc,
+            instruction: frame.bytecode.instructions.get(frame.pc).cloned(),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            locals: frame.locals.clone(),
+            stack: self.stack[frame.base_stack_offset.min(self.stack.len())..].to_vec(),
+            constants: frame.bytecode.constants.clone(),
+        })
+    }
+
+    /// Run the frames from the current one up until the frame below it is
+    /// reached again. Errors raised by instructions are thrown as error
+    /// objects that JS handlers can catch, and exceptions escaping a nested
+    /// run are thrown on.
     fn run_interpreter_loop(&mut self) -> RuntimeResult<Value> {
+        let base_depth = self.call_stack.len();
         loop {
-            let frame = self.call_stack.last_mut()
-                .ok_or_else(|| RuntimeError::InvalidOperation("No call frame".to_string()))?;
-            
-            if frame.pc >= frame.bytecode.instructions.len() {
-                // End of bytecode reached
-                return Ok(self.stack.pop().unwrap_or(Value::Undefined));
+            let error = match self.run_frames(base_depth) {
+                Err(error) => error,
+                result => return result,
+            };
+            let Some(exception) = self.error_value(&error) else {
+                return Err(error);
+            };
+            // Uncaught errors come back as `RuntimeError::Thrown`, with the
+            // stack at the failing instruction
+            if let Some(value) = self.throw_value(exception, base_depth)? {
+                return Ok(value);
             }
-            
-            let instruction = &frame.bytecode.instructions[frame.pc];
-            trace!("PC: {}, Instruction: {:?}", frame.pc, instruction);
-            
-            match instruction {
-                Instruction::LoadConstant(idx) => {
-                    let constant = frame.bytecode.constants.get(*idx)
-                        .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid constant index: {}", idx)))?;
-                    
-                    let value = self.constant_to_value(constant)?;
-                    self.push_stack(value)?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::LoadGlobal(idx) => {
-                    let name = frame.bytecode.names.get(*idx)
-                        .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", idx)))?;
-                    
-                    let value = self.globals.get(name).cloned().unwrap_or(Value::Undefined);
-                    self.push_stack(value)?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::StoreGlobal(idx) => {
-                    let name = frame.bytecode.names.get(*idx)
-                        .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", idx)))?;
-                    
-                    let value = self.pop_stack()?;
-                    self.globals.insert(name.clone(), value);
-                    frame.pc += 1;
-                }
-                
-                Instruction::LoadLocal(idx) => {
-                    let value = frame.locals.get(*idx).cloned().unwrap_or(Value::Undefined);
-                    self.push_stack(value)?;
-                    frame.pc += 1;
-                }
+        }
+    }
+
+    fn run_frames(&mut self, base_depth: usize) -> RuntimeResult<Value> {
+        loop {
+            if let Some(value) = self.step_instruction(base_depth)? {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Execute the current frame's next instruction. Returns the run's
+    /// result once the frame at `base_depth` has finished.
+    fn step_instruction(&mut self, base_depth: usize) -> RuntimeResult<Option<Value>> {
+        // Between instructions every live value is on the stack, in a frame
+        // or in a table `roots` walks. Scripts cannot catch running out of
+        // heap, since handling it would need memory too.
+        if self.host_calls == 0 && self.collection_requested.load(Ordering::Relaxed) {
+            self.collect_garbage();
+            if self.gc.lock().unwrap().heap_limit_exceeded() {
+                return Err(RuntimeError::OutOfMemory);
+            }
+        }
+        if let Some(error) = self.pending_throw.take() {
+            return Err(error);
+        }
+        
+        // Instructions push, pop and call through `self`, so the frame is
+        // looked up again by index after each of those rather than borrowed
+        // across them
+        let frame_index = self.call_stack.len().checked_sub(1)
+            .ok_or_else(|| RuntimeError::InvalidOperation("No call frame".to_string()))?;
+        let bytecode = Arc::clone(&self.call_stack[frame_index].bytecode);
+        let pc = self.call_stack[frame_index].pc;
+        
+        if pc >= bytecode.instructions.len() {
+            // End of bytecode reached
+            return Ok(Some(self.stack.pop().unwrap_or(Value::Undefined)));
+        }
+        
+        let instruction = &bytecode.instructions[pc];
+        trace!("PC: {}, Instruction: {:?}", pc, instruction);
+        
+        match instruction {
+            Instruction::LoadConstant(idx) => {
+                let constant = bytecode.constants.get(*idx)
+                    .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid constant index: {}", idx)))?;
                 
-                Instruction::StoreLocal(idx) => {
-                    let value = self.pop_stack()?;
-                    
-                    // Extend locals vector if necessary
-                    while frame.locals.len() <= *idx {
-                        frame.locals.push(Value::Undefined);
+                let value = match constant {
+                    Constant::Function { .. } => {
+                                                self.function_value(&bytecode, *idx)?
                     }
-                    
-                    frame.locals[*idx] = value;
-                    frame.pc += 1;
-                }
-                
-                // Arithmetic operations
-                Instruction::Add => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = crate::value::add_values(&left, &right)?;
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::Subtract => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = crate::value::subtract_values(&left, &right)?;
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::Multiply => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = crate::value::multiply_values(&left, &right)?;
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::Divide => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = crate::value::divide_values(&left, &right)?;
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::Modulo => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = crate::value::modulo_values(&left, &right)?;
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::Power => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = crate::value::power_values(&left, &right)?;
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
+                    constant => self.constant_to_value(constant)?,
+                };
+                self.push_stack(value)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::LoadGlobal(idx) => {
+                let name = bytecode.names.get(*idx)
+                    .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", idx)))?;
+                self.check_global_initialized(name)?;
                 
-                // Comparison operations
-                Instruction::Equal => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = Value::Boolean(left.loose_equals(&right));
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
+                let value = self.globals.get(name).cloned().unwrap_or(Value::Undefined);
+                self.push_stack(value)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::StoreGlobal(idx) => {
+                let name = bytecode.names.get(*idx)
+                    .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", idx)))?;
+                self.check_global_initialized(name)?;
                 
-                Instruction::StrictEqual => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = Value::Boolean(left.strict_equals(&right));
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
+                let value = self.pop_stack()?;
+                self.globals.insert(name.clone(), value);
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::DeclareGlobal(idx) => {
+                let name = bytecode.names.get(*idx)
+                    .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", idx)))?;
                 
-                Instruction::Less => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let left_num = left.to_number()?;
-                    let right_num = right.to_number()?;
-                    let result = Value::Boolean(left_num < right_num);
-                    self.push_stack(result)?;
-                    frame.pc += 1;
-                }
+                let value = self.pop_stack()?;
+                self.uninitialized_globals.remove(name);
+                self.globals.insert(name.clone(), value);
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::MarkGlobalUninitialized(idx) => {
+                let name = bytecode.names.get(*idx)
+                    .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", idx)))?;
+                self.uninitialized_globals.insert(name.clone());
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::LoadLocal(idx) => {
+                let value = self.call_stack[frame_index].locals.get(*idx).cloned().unwrap_or(Value::Undefined);
+                self.push_stack(value)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::StoreLocal(idx)
+            | Instruction::DeclareVar(idx)
+            | Instruction::DeclareLet(idx)
+            | Instruction::DeclareConst(idx) => {
+                let value = self.pop_stack()?;
                 
-                Instruction::Greater => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let left_num = left.to_number()?;
-                    let right_num = right.to_number()?;
-                    let result = Value::Boolean(left_num > right_num);
-                    self.push_stack(result)?;
-                    frame.pc += 1;
+                // Frames are presized, but bytecode from before frame sizes
+                // were recorded still grows its locals here
+                let frame = &mut self.call_stack[frame_index];
+                while frame.locals.len() <= *idx {
+                    frame.locals.push(Value::Undefined);
                 }
                 
-                Instruction::LessEqual => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let left_num = left.to_number()?;
-                    let right_num = right.to_number()?;
-                    let result = Value::Boolean(left_num <= right_num);
-                    self.push_stack(result)?;
-                    frame.pc += 1;
+                frame.locals[*idx] = value;
+                if matches!(instruction, Instruction::DeclareLet(_) | Instruction::DeclareConst(_)) {
+                    frame.uninitialized.remove(idx);
                 }
-                
-                Instruction::GreaterEqual => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let left_num = left.to_number()?;
-                    let right_num = right.to_number()?;
-                    let result = Value::Boolean(left_num >= right_num);
-                    self.push_stack(result)?;
-                    frame.pc += 1;
+                frame.pc += 1;
+            }
+            
+            Instruction::MarkUninitialized(idx) => {
+                let frame = &mut self.call_stack[frame_index];
+                frame.uninitialized.insert(*idx);
+                frame.pc += 1;
+            }
+            
+            Instruction::CheckInitialized(idx, name_idx) => {
+                if self.call_stack[frame_index].uninitialized.contains(idx) {
+                    let name = bytecode.names.get(*name_idx)
+                        .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", name_idx)))?;
+                    return Err(RuntimeError::ReferenceError(format!("Cannot access '{}' before initialization", name)));
                 }
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            // Arithmetic operations
+            Instruction::Add => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = crate::value::add_values(&left, &right)?;
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Subtract => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = crate::value::subtract_values(&left, &right)?;
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Multiply => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = crate::value::multiply_values(&left, &right)?;
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Divide => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = crate::value::divide_values(&left, &right)?;
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Modulo => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = crate::value::modulo_values(&left, &right)?;
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Power => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = crate::value::power_values(&left, &right)?;
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            // Comparison operations
+            Instruction::Equal => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = Value::Boolean(left.loose_equals(&right));
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::StrictEqual => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = Value::Boolean(left.strict_equals(&right));
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Less => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let left_num = left.to_number()?;
+                let right_num = right.to_number()?;
+                let result = Value::Boolean(left_num < right_num);
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Greater => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let left_num = left.to_number()?;
+                let right_num = right.to_number()?;
+                let result = Value::Boolean(left_num > right_num);
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::LessEqual => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let left_num = left.to_number()?;
+                let right_num = right.to_number()?;
+                let result = Value::Boolean(left_num <= right_num);
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::GreaterEqual => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let left_num = left.to_number()?;
+                let right_num = right.to_number()?;
+                let result = Value::Boolean(left_num >= right_num);
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            // Logical operations
+            Instruction::LogicalAnd => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = if left.to_boolean() { right } else { left };
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::LogicalOr => {
+                let right = self.pop_stack()?;
+                let left = self.pop_stack()?;
+                let result = if left.to_boolean() { left } else { right };
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::LogicalNot => {
+                let value = self.pop_stack()?;
+                let result = Value::Boolean(!value.to_boolean());
+                self.push_stack(result)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            // Control flow
+            Instruction::Jump(offset) => {
+                let target = ((pc as isize) + offset + 1) as usize;
+                self.call_stack[frame_index].pc = target;
                 
-                // Logical operations
-                Instruction::LogicalAnd => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = if left.to_boolean() { right } else { left };
-                    self.push_stack(result)?;
-                    frame.pc += 1;
+                // Loops close with a backwards jump to their header
+                if *offset < 0 {
+                    self.hotness.record_back_edge(&bytecode, target, pc);
                 }
-                
-                Instruction::LogicalOr => {
-                    let right = self.pop_stack()?;
-                    let left = self.pop_stack()?;
-                    let result = if left.to_boolean() { left } else { right };
-                    self.push_stack(result)?;
-                    frame.pc += 1;
+            }
+            
+            Instruction::JumpIfFalse(offset) => {
+                let condition = self.pop_stack()?;
+                if !condition.to_boolean() {
+                    self.call_stack[frame_index].pc = ((pc as isize) + offset + 1) as usize;
+                } else {
+                    self.call_stack[frame_index].pc += 1;
                 }
-                
-                Instruction::LogicalNot => {
-                    let value = self.pop_stack()?;
-                    let result = Value::Boolean(!value.to_boolean());
-                    self.push_stack(result)?;
-                    frame.pc += 1;
+            }
+            
+            Instruction::JumpIfTrue(offset) => {
+                let condition = self.pop_stack()?;
+                if condition.to_boolean() {
+                    self.call_stack[frame_index].pc = ((pc as isize) + offset + 1) as usize;
+                } else {
+                    self.call_stack[frame_index].pc += 1;
                 }
-                
-                // Control flow
-                Instruction::Jump(offset) => {
-                    frame.pc = ((frame.pc as isize) + offset + 1) as usize;
+            }
+
+            Instruction::JumpIfNullish(offset) => {
+                let value = self.pop_stack()?;
+                if matches!(value, Value::Null | Value::Undefined) {
+                    self.call_stack[frame_index].pc = ((pc as isize) + offset + 1) as usize;
+                } else {
+                    self.call_stack[frame_index].pc += 1;
                 }
+            }
+
+            Instruction::Call(arg_count) => {
+                let site = (CodeId::of(&bytecode), pc);
+                let args = self.pop_arguments(*arg_count)?;
+                let function = self.pop_stack()?;
+                self.call_at_site(site, function, Value::Undefined, args)?;
+                // PC will be managed by the new call frame
+            }
+            
+            Instruction::CallWithReceiver(arg_count) => {
+                let site = (CodeId::of(&bytecode), pc);
+                let args = self.pop_arguments(*arg_count)?;
+                let function = self.pop_stack()?;
+                let receiver = self.pop_stack()?;
+                self.call_at_site(site, function, receiver, args)?;
+            }
+            
+            Instruction::CallMethod(idx, arg_count) => {
+                let site = (CodeId::of(&bytecode), pc);
+                let name = bytecode.names.get(*idx)
+                    .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", idx)))?;
+                let key = Value::from(name.as_str());
                 
-                Instruction::JumpIfFalse(offset) => {
-                    let condition = self.pop_stack()?;
-                    if !condition.to_boolean() {
-                        frame.pc = ((frame.pc as isize) + offset + 1) as usize;
-                    } else {
-                        frame.pc += 1;
-                    }
-                }
+                let args = self.pop_arguments(*arg_count)?;
+                let receiver = self.pop_stack()?;
+                let function = self.get_property_at_site(site, &receiver, &key)?;
+                self.call_at_site(site, function, receiver, args)?;
+            }
+            
+            Instruction::LoadThis => {
+                let this = self.call_stack[frame_index].this.clone();
+                self.push_stack(this)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Return => {
+                let mut return_value = self.pop_stack().unwrap_or(Value::Undefined);
                 
-                Instruction::JumpIfTrue(offset) => {
-                    let condition = self.pop_stack()?;
-                    if condition.to_boolean() {
-                        frame.pc = ((frame.pc as isize) + offset + 1) as usize;
-                    } else {
-                        frame.pc += 1;
-                    }
-                }
+                // Clean up the current frame's stack space
+                let frame = self.call_stack.pop().unwrap();
+                self.stack.truncate(frame.base_stack_offset);
                 
-                Instruction::Call(arg_count) => {
-                    self.handle_function_call(*arg_count)?;
-                    // PC will be managed by the new call frame
+                // An async function hands its caller the promise instead
+                if let Some(promise) = frame.async_promise {
+                    self.settle_promise(promise, Ok(return_value));
+                    return_value = Value::Object(promise);
                 }
                 
-                Instruction::Return => {
-                    let return_value = self.pop_stack().unwrap_or(Value::Undefined);
-                    
-                    // Clean up the current frame's stack space
-                    let frame = self.call_stack.pop().unwrap();
-                    self.stack.truncate(frame.base_stack_offset);
-                    
-                    // Push return value
-                    if !self.call_stack.is_empty() {
-                        self.push_stack(return_value)?;
-                        // Continue execution in the calling frame
-                        if let Some(caller_frame) = self.call_stack.last_mut() {
-                            caller_frame.pc += 1;
-                        }
-                    } else {
-                        // Main function returned
-                        return Ok(return_value);
+                // Push return value
+                if self.call_stack.len() >= base_depth {
+                    self.push_stack(return_value)?;
+                    // Continue execution in the calling frame
+                    if let Some(caller_frame) = self.call_stack.last_mut() {
+                        caller_frame.pc += 1;
                     }
+                } else {
+                    // Main function returned
+                    return Ok(Some(return_value));
                 }
+            }
+            
+            Instruction::Await => {
+                let awaited = self.pop_stack()?;
+                self.call_stack[frame_index].pc += 1;
+                let promise = self.suspend_frame(awaited)?;
                 
-                Instruction::NewObject => {
-                    let handle = {
-                        let mut gc = self.gc.lock().unwrap();
-                        gc.allocate_object(HashMap::new())
-                    };
-                    self.push_stack(Value::Object(handle))?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::NewArray(size) => {
-                    let mut elements = Vec::with_capacity(*size);
-                    for _ in 0..*size {
-                        if let Value::Object(handle) = self.pop_stack()? {
-                            elements.push(handle);
-                        } else {
-                            return Err(RuntimeError::TypeError("Array elements must be objects".to_string()));
-                        }
+                // The caller continues with the pending promise, as if the
+                // async function had returned it
+                if self.call_stack.len() >= base_depth {
+                    self.push_stack(Value::Object(promise))?;
+                    if let Some(caller_frame) = self.call_stack.last_mut() {
+                        caller_frame.pc += 1;
                     }
-                    elements.reverse(); // Stack is LIFO
-                    
-                    let handle = {
-                        let mut gc = self.gc.lock().unwrap();
-                        gc.allocate_array(elements)
-                    };
-                    self.push_stack(Value::Object(handle))?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::Pop => {
-                    self.pop_stack()?;
-                    frame.pc += 1;
-                }
-                
-                Instruction::Duplicate => {
-                    let value = self.peek_stack(0)?;
-                    self.push_stack(value)?;
-                    frame.pc += 1;
+                } else {
+                    return Ok(Some(Value::Object(promise)));
                 }
-                
-                Instruction::Halt => {
-                    return Ok(self.stack.pop().unwrap_or(Value::Undefined));
+            }
+            
+            Instruction::NewObject => {
+                let handle = self.create_object(HashMap::new());
+                self.push_stack(Value::Object(handle))?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::NewArray(size) => {
+                let mut elements = Vec::with_capacity(*size);
+                for _ in 0..*size {
+                    let value = self.pop_stack()?;
+                    elements.push(self.value_to_handle(value));
                 }
+                elements.reverse(); // Stack is LIFO
                 
-                _ => {
-                    return Err(RuntimeError::InvalidBytecode(
-                        format!("Unimplemented instruction: {:?}", instruction)
-                    ));
-                }
+                let handle = self.create_array(elements);
+                self.push_stack(Value::Object(handle))?;
+                self.call_stack[frame_index].pc += 1;
             }
             
-            // Check for stack overflow
-            if self.stack.len() > self.max_stack_size {
-                return Err(RuntimeError::StackOverflow);
+            Instruction::ArrayAppend => {
+                let value = self.pop_stack()?;
+                let handle = self.value_to_handle(value);
+                let array = self.peek_stack(0)?;
+                self.append_to_array(&array, vec![handle])?;
+                self.call_stack[frame_index].pc += 1;
             }
             
-            // Check for call depth overflow
-            if self.call_stack.len() > self.max_call_depth {
-                return Err(RuntimeError::StackOverflow);
+            Instruction::ArraySpread => {
+                let iterable = self.pop_stack()?;
+                let values = self.iterate_to_handles(&iterable)?;
+                let array = self.peek_stack(0)?;
+                self.append_to_array(&array, values)?;
+                self.call_stack[frame_index].pc += 1;
             }
-        }
-    }
-
-    fn constant_to_value(&mut self, constant: &Constant) -> RuntimeResult<Value> {
-        match constant {
-            Constant::Number(n) => Ok(Value::Number(*n)),
-            Constant::String(s) => Ok(Value::String(s.clone())),
-            Constant::Boolean(b) => Ok(Value::Boolean(*b)),
-            Constant::Null => Ok(Value::Null),
-            Constant::Undefined => Ok(Value::Undefined),
-            Constant::Function { name, bytecode, .. } => {
-                let handle = {
-                    let mut gc = self.gc.lock().unwrap();
-                    gc.allocate_function(
-                        name.clone(),
-                        vec![],
-                        HashMap::new(),
-                    )
-                };
-                Ok(Value::Object(handle))
+            
+            Instruction::GetProperty | Instruction::GetElement => {
+                let site = (CodeId::of(&bytecode), pc);
+                let key = self.pop_stack()?;
+                let object = self.pop_stack()?;
+                let value = self.get_property_at_site(site, &object, &key)?;
+                self.push_stack(value)?;
+                self.call_stack[frame_index].pc += 1;
             }
-        }
-    }
-
-    fn handle_function_call(&mut self, arg_count: usize) -> RuntimeResult<()> {
-        // Pop arguments from stack
-        let mut args = Vec::with_capacity(arg_count);
-        for _ in 0..arg_count {
-            args.push(self.pop_stack()?);
-        }
-        args.reverse(); // Stack is LIFO
-        
-        // Pop function from stack
-        let function = self.pop_stack()?;
-        
-        match function {
-            Value::Object(handle) => {
-                // Check if it's a function object
-                let gc = self.gc.lock().unwrap();
-                if let Some(GcObjectType::Function { bytecode, .. }) = gc.get_object_type(handle) {
-                    return Err(RuntimeError::InvalidOperation("Function calls not fully implemented".to_string()));
-                } else {
-                    return Err(RuntimeError::TypeError("Not a function".to_string()));
-                }
+            
+            Instruction::SetProperty | Instruction::SetElement => {
+                let site = (CodeId::of(&bytecode), pc);
+                let value = self.pop_stack()?;
+                let key = self.pop_stack()?;
+                let object = self.pop_stack()?;
+                self.set_property_at_site(site, &object, &key, value.clone())?;
+                self.push_stack(value)?;
+                self.call_stack[frame_index].pc += 1;
             }
-            _ => {
-                return Err(RuntimeError::TypeError("Not a function".to_string()));
+            
+            Instruction::DeleteProperty => {
+                let key = self.pop_stack()?;
+                let object = self.pop_stack()?;
+                let deleted = self.delete_property(&object, &key)?;
+                self.push_stack(Value::Boolean(deleted))?;
+                self.call_stack[frame_index].pc += 1;
             }
-        }
-    }
-
-    fn push_stack(&mut self, value: Value) -> RuntimeResult<()> {
-        if self.stack.len() >= self.max_stack_size {
-            Err(RuntimeError::StackOverflow)
-        } else {
-            self.stack.push(value);
+            
+            Instruction::HasProperty => {
+                let object = self.pop_stack()?;
+                let key = self.pop_stack()?;
+                let found = self.has_property(&object, &key)?;
+                self.push_stack(Value::Boolean(found))?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::InstanceOf => {
+                let constructor = self.pop_stack()?;
+                let value = self.pop_stack()?;
+                let is_instance = self.instance_of(&value, &constructor)?;
+                self.push_stack(Value::Boolean(is_instance))?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::DefineGetter | Instruction::DefineSetter => {
+                let function = self.pop_stack()?;
+                let key = self.pop_stack()?;
+                let object = self.pop_stack()?;
+                let is_getter = matches!(bytecode.instructions[pc], Instruction::DefineGetter);
+                self.define_accessor(&object, &key, function, is_getter)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::CopyDataProperties => {
+                let source = self.pop_stack()?;
+                let target = self.pop_stack()?;
+                self.copy_data_properties(&target, &source)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Pop => {
+                self.pop_stack()?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Duplicate => {
+                let value = self.peek_stack(0)?;
+                self.push_stack(value)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Swap => {
+                self.rotate_stack(2)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Rot(count) => {
+                self.rotate_stack(*count)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Pick(depth) => {
+                let value = self.peek_stack(*depth)?;
+                self.push_stack(value)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::StoreCompletion => {
+                self.call_stack[frame_index].completion = self.pop_stack()?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::LoadCompletion => {
+                let completion = self.call_stack[frame_index].completion.clone();
+                self.push_stack(completion)?;
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Throw => {
+                let exception = self.pop_stack()?;
+                if let Some(value) = self.throw_value(exception, base_depth)? {
+                    return Ok(Some(value));
+                }
+            }
+            
+            Instruction::Import(idx) => {
+                let specifier = match bytecode.constants.get(*idx) {
+                    Some(Constant::String(specifier)) => specifier.clone(),
+                    _ => return Err(RuntimeError::InvalidBytecode(format!("Invalid module specifier index: {}", idx))),
+                };
+                self.call_stack[frame_index].pc += 1;
+                let import_type = self.pop_stack()?;
+                let value = self.import(&specifier, import_type)?;
+                self.push_stack(value)?;
+            }
+            
+            Instruction::ImportMeta(idx) => {
+                let url = match bytecode.constants.get(*idx) {
+                    Some(Constant::String(url)) => url.clone(),
+                    _ => return Err(RuntimeError::InvalidBytecode(format!("Invalid module URL index: {}", idx))),
+                };
+                self.call_stack[frame_index].pc += 1;
+                let meta = self.import_meta(&url)?;
+                self.push_stack(meta)?;
+            }
+            
+            Instruction::Export(idx) => {
+                let name = bytecode.names.get(*idx)
+                    .ok_or_else(|| RuntimeError::InvalidBytecode(format!("Invalid name index: {}", idx)))?
+                    .clone();
+                self.call_stack[frame_index].pc += 1;
+                let value = self.pop_stack()?;
+                self.export(&name, value)?;
+            }
+            
+            Instruction::DebugInfo(line, column) => {
+                self.call_stack[frame_index].position = Some((*line, *column));
+                self.call_stack[frame_index].pc += 1;
+            }
+            
+            Instruction::Halt => {
+                return Ok(Some(self.stack.pop().unwrap_or(Value::Undefined)));
+            }
+            
+            _ => {
+                return Err(RuntimeError::InvalidBytecode(
+                    format!("Unimplemented instruction: {:?}", instruction)
+                ));
+            }
+        }
+        
+        // Check for stack overflow
+        if self.stack.len() > self.max_stack_size {
+            return Err(self.stack_overflow());
+        }
+        
+        // Check for call depth overflow
+        if self.call_stack.len() > self.max_call_depth {
+            return Err(self.stack_overflow());
+        }
+        
+        Ok(None)
+    }
+
+    /// Throw if the global `name` is a `let` or `const` whose declaration
+    /// has not run yet
+    fn check_global_initialized(&self, name: &str) -> RuntimeResult<()> {
+        if self.uninitialized_globals.contains(name) {
+            return Err(RuntimeError::ReferenceError(format!("Cannot access '{}' before initialization", name)));
+        }
+        Ok(())
+    }
+
+    /// Have the host load the module `specifier` names
+    fn import(&mut self, specifier: &str, import_type: Value) -> RuntimeResult<Value> {
+        let hook = self.import_hook.clone().ok_or_else(|| {
+            RuntimeError::Error(format!("Cannot import '{}': this host does not load modules", specifier))
+        })?;
+        hook.call(Runtime::from_vm_mut(self), &[Value::from(specifier), import_type])
+    }
+
+    pub(crate) fn set_import_hook(&mut self, hook: NativeFunction) {
+        self.import_hook = Some(hook);
+    }
+
+    /// Have the host record the module's export `name`
+    fn export(&mut self, name: &str, value: Value) -> RuntimeResult<()> {
+        let hook = self.export_hook.clone().ok_or_else(|| {
+            RuntimeError::Error(format!("Cannot export '{}': this host does not load modules", name))
+        })?;
+        hook.call(Runtime::from_vm_mut(self), &[Value::from(name), value])?;
+        Ok(())
+    }
+
+    pub(crate) fn set_export_hook(&mut self, hook: NativeFunction) {
+        self.export_hook = Some(hook);
+    }
+
+    /// Have the host make `import.meta` for the module at `url`
+    fn import_meta(&mut self, url: &str) -> RuntimeResult<Value> {
+        let hook = self.import_meta_hook.clone().ok_or_else(|| {
+            RuntimeError::Error("Cannot read import.meta: this host does not load modules".to_string())
+        })?;
+        hook.call(Runtime::from_vm_mut(self), &[Value::from(url)])
+    }
+
+    pub(crate) fn set_import_meta_hook(&mut self, hook: NativeFunction) {
+        self.import_meta_hook = Some(hook);
+    }
+
+    fn constant_to_value(&mut self, constant: &Constant) -> RuntimeResult<Value> {
+        match constant {
+            Constant::Number(n) => Ok(Value::Number(*n)),
+            Constant::String(s) => Ok(Value::String(crate::JsString::from(&**s))),
+            Constant::Boolean(b) => Ok(Value::Boolean(*b)),
+            Constant::Null => Ok(Value::Null),
+            Constant::Undefined => Ok(Value::Undefined),
+            Constant::RegExp { pattern, flags } => {
+                // Compile eagerly so malformed patterns surface where the literal is evaluated
+                let regexp = RegExp::new(pattern, flags)?;
+                let handle = {
+                    let mut gc = self.gc.lock().unwrap();
+                    let handle = gc.allocate_regexp(pattern.clone(), flags.clone());
+                    gc.set_prototype(handle, Some(self.intrinsics.regexp_prototype));
+                    handle
+                };
+                self.regexps.insert(handle, regexp);
+                Ok(Value::Object(handle))
+            }
+            Constant::Function { .. } => Err(RuntimeError::InvalidBytecode(
+                "Function constants are loaded through their enclosing bytecode".to_string(),
+            )),
+        }
+    }
+
+    /// Create a function object for the function literal at constant `idx`
+    /// of `enclosing`
+    fn function_value(&mut self, enclosing: &Arc<Bytecode>, idx: usize) -> RuntimeResult<Value> {
+        let key = (CodeId::of(enclosing), idx);
+        let code = match self.function_code.get(&key) {
+            Some((_, code)) => Arc::clone(code),
+            None => {
+                let Some(Constant::Function { name, param_count, bytecode, is_async, is_generator }) =
+                    enclosing.constants.get(idx)
+                else {
+                    return Err(RuntimeError::InvalidBytecode(format!("Constant {} is not a function", idx)));
+                };
+                let code = Arc::new(FunctionCode {
+                    name: name.clone(),
+                    param_count: *param_count,
+                    bytecode: Arc::new(bytecode.clone()),
+                    is_async: *is_async,
+                    is_generator: *is_generator,
+                });
+                self.function_code.insert(key, (Arc::clone(enclosing), Arc::clone(&code)));
+                code
+            }
+        };
+        
+        let handle = {
+            let mut gc = self.gc.lock().unwrap();
+            let handle = gc.allocate_function(code.name.clone(), vec![], HashMap::new());
+            gc.set_prototype(handle, Some(self.intrinsics.function_prototype));
+            handle
+        };
+        self.functions.insert(handle, code);
+        Ok(Value::Object(handle))
+    }
+
+    /// A plain object inheriting from `Object.prototype`
+    pub(crate) fn create_object(&mut self, properties: HashMap<String, GcHandle>) -> GcHandle {
+        let mut gc = self.gc.lock().unwrap();
+        let handle = gc.allocate_object(properties);
+        gc.set_prototype(handle, Some(self.intrinsics.object_prototype));
+        handle
+    }
+
+    /// An array inheriting from `Array.prototype`
+    pub(crate) fn create_array(&mut self, elements: Vec<GcHandle>) -> GcHandle {
+        let mut gc = self.gc.lock().unwrap();
+        let handle = gc.allocate_array(elements);
+        gc.set_prototype(handle, Some(self.intrinsics.array_prototype));
+        handle
+    }
+
+    /// Define `name` on `target` as a built-in function running `builtin`
+    pub(crate) fn define_builtin(&mut self, target: GcHandle, name: &str, builtin: Builtin) {
+        let function = self.create_builtin(name, builtin);
+        self.gc.lock().unwrap().set_property(target, name, function);
+    }
+
+    /// Define the `symbol`-keyed method of `target` as a built-in function
+    /// running `builtin`, named after the symbol like `[Symbol.iterator]`
+    pub(crate) fn define_symbol_builtin(&mut self, target: GcHandle, symbol: &Symbol, builtin: Builtin) {
+        let name = format!("[{}]", symbol.description().unwrap_or_default());
+        let function = self.create_builtin(&name, builtin);
+        self.gc.lock().unwrap().set_property(target, &symbol.property_key(), function);
+    }
+
+    /// A function object running `builtin`
+    pub(crate) fn create_builtin(&mut self, name: &str, builtin: Builtin) -> GcHandle {
+        let mut gc = self.gc.lock().unwrap();
+        let function = gc.allocate_function(Some(name.to_string()), vec![], HashMap::new());
+        gc.set_prototype(function, Some(self.intrinsics.function_prototype));
+        drop(gc);
+        self.builtins.insert(function, builtin);
+        function
+    }
+
+    pub(crate) fn gc(&self) -> &Arc<Mutex<GarbageCollector>> {
+        &self.gc
+    }
+
+    pub(crate) fn intrinsics(&self) -> Intrinsics {
+        self.intrinsics
+    }
+
+    pub(crate) fn random(&self) -> &Arc<HostRandom> {
+        &self.random
+    }
+
+    pub(crate) fn clock(&self) -> &Arc<HostClock> {
+        &self.clock
+    }
+
+    pub(crate) fn symbol_registry(&mut self) -> &mut HashMap<String, Symbol> {
+        &mut self.symbol_registry
+    }
+
+    /// The compiled form of `value` if it is a `RegExp` object
+    pub(crate) fn compiled_regexp(&mut self, value: &Value) -> RuntimeResult<Option<RegExp>> {
+        let Value::Object(handle) = value else {
+            return Ok(None);
+        };
+        let gc = self.gc.lock().unwrap();
+        Ok(self.regexps.load(&gc, *handle)?.map(|(regexp, _)| regexp))
+    }
+
+    /// Match the `RegExp` object `handle` against `input` as `exec` does,
+    /// moving its `lastIndex` if it is global or sticky
+    pub(crate) fn regexp_exec(&mut self, handle: GcHandle, input: &str) -> RuntimeResult<Option<RegExpMatch>> {
+        let mut gc = self.gc.lock().unwrap();
+        regexp::regexp_exec_match(&mut gc, &mut self.regexps, handle, input)
+    }
+
+    pub(crate) fn abort_signals(&mut self) -> &mut HashMap<GcHandle, SignalRecord> {
+        &mut self.abort_signals
+    }
+
+    pub(crate) fn abort_timeouts(&mut self) -> &mut Vec<(GcHandle, f64)> {
+        &mut self.abort_timeouts
+    }
+
+    /// Native functions are stored as function objects that remember the
+    /// host function, one per function, so reading one back gives an equal
+    /// value
+    pub(crate) fn value_to_handle(&mut self, value: Value) -> GcHandle {
+        let mut gc = self.gc.lock().unwrap();
+        match value {
+            Value::Object(handle) => handle,
+            Value::Number(n) => gc.allocate_number(n),
+            Value::String(s) => gc.intern_string(&s.to_rust_string()),
+            Value::Boolean(b) => gc.allocate_boolean(b),
+            Value::Null => gc.allocate_null(),
+            Value::Undefined => gc.allocate_undefined(),
+            Value::Symbol(symbol) => gc.allocate(GcObjectType::Symbol {
+                id: symbol.id(),
+                description: symbol.description().map(String::from),
+            }),
+            Value::NativeFunction(function) => {
+                let stored = self.native_functions.iter().find(|(_, stored)| **stored == function);
+                if let Some((&handle, _)) = stored {
+                    return handle;
+                }
+                let handle = gc.allocate_function(Some(function.name().to_string()), vec![], HashMap::new());
+                gc.set_prototype(handle, Some(self.intrinsics.function_prototype));
+                self.native_functions.insert(handle, function);
+                handle
+            }
+        }
+    }
+
+    /// The value a heap handle holds
+    pub(crate) fn handle_to_value(&self, handle: GcHandle) -> Value {
+        self.value_in(&self.gc.lock().unwrap(), handle)
+    }
+
+    /// `handle_to_value` with the heap already locked
+    fn value_in(&self, gc: &GarbageCollector, handle: GcHandle) -> Value {
+        if let Some(function) = self.native_functions.get(&handle) {
+            return Value::NativeFunction(function.clone());
+        }
+        gc.get_object_type(handle)
+            .map_or(Value::Undefined, |object| Value::from_gc_object_type(object, handle))
+    }
+
+    /// The elements of `array`, or `None` if it is not an array
+    pub(crate) fn array_elements(&self, array: &Value) -> Option<Vec<Value>> {
+        let Value::Object(handle) = array else {
+            return None;
+        };
+        let gc = self.gc.lock().unwrap();
+        match gc.get_object_type(*handle) {
+            Some(GcObjectType::Array(elements)) => {
+                Some(elements.iter().map(|element| self.value_in(&gc, *element)).collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// An array of `values` inheriting from `Array.prototype`
+    pub(crate) fn array_from_values(&mut self, values: Vec<Value>) -> Value {
+        let elements = values.into_iter().map(|value| self.value_to_handle(value)).collect();
+        Value::Object(self.create_array(elements))
+    }
+
+    /// Replace the elements of `array`
+    pub(crate) fn set_array_elements(&mut self, array: &Value, values: Vec<Value>) -> RuntimeResult<()> {
+        let handle = match array {
+            Value::Object(handle) => *handle,
+            _ => return Err(RuntimeError::InvalidOperation("Target is not an array".to_string())),
+        };
+        
+        let elements = values.into_iter().map(|value| self.value_to_handle(value)).collect();
+        let mut gc = self.gc.lock().unwrap();
+        if !matches!(gc.get_object_type(handle), Some(GcObjectType::Array(_))) {
+            return Err(RuntimeError::InvalidOperation("Target is not an array".to_string()));
+        }
+        gc.update_object(handle, GcObjectType::Array(elements));
+        Ok(())
+    }
+
+    pub(crate) fn append_to_array(&mut self, array: &Value, values: Vec<GcHandle>) -> RuntimeResult<()> {
+        let handle = match array {
+            Value::Object(handle) => *handle,
+            _ => return Err(RuntimeError::InvalidOperation("Append target is not an array".to_string())),
+        };
+        
+        let mut gc = self.gc.lock().unwrap();
+        let mut elements = match gc.get_object_type(handle) {
+            Some(GcObjectType::Array(elements)) => elements.clone(),
+            _ => return Err(RuntimeError::InvalidOperation("Append target is not an array".to_string())),
+        };
+        elements.extend(values);
+        gc.update_object(handle, GcObjectType::Array(elements));
+        Ok(())
+    }
+
+    /// Values produced by iterating `iterable`, for spread and destructuring.
+    /// Arrays yield their elements and strings their code points; other
+    /// objects are iterated through their `Symbol.iterator` method.
+    fn iterate_to_handles(&mut self, iterable: &Value) -> RuntimeResult<Vec<GcHandle>> {
+        match iterable {
+            Value::String(s) => {
+                let mut gc = self.gc.lock().unwrap();
+                Ok(char::decode_utf16(s.code_units().iter().copied())
+                    .map(|c| {
+                        let ch = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+                        gc.intern_string(ch.encode_utf8(&mut [0; 4]))
+                    })
+                    .collect())
+            }
+            Value::Object(handle) => {
+                if let Some(GcObjectType::Array(elements)) = self.gc.lock().unwrap().get_object_type(*handle) {
+                    return Ok(elements.clone());
+                }
+                let method = self.get_property(iterable, &Value::Symbol(Symbol::iterator()))?;
+                if !self.is_callable(&method) {
+                    return Err(RuntimeError::TypeError("object is not iterable".to_string()));
+                }
+                let iterator = self.call_function(&method, iterable, &[])?;
+                self.drain_iterator(&iterator)
+            }
+            other => Err(RuntimeError::TypeError(format!("{} is not iterable", other.to_string()))),
+        }
+    }
+
+    /// Call `iterator.next()` until it reports `done`. The built-in
+    /// iterator methods return arrays, which are taken whole.
+    fn drain_iterator(&mut self, iterator: &Value) -> RuntimeResult<Vec<GcHandle>> {
+        if let Some(elements) = self.array_elements(iterator) {
+            return Ok(elements.into_iter().map(|element| self.value_to_handle(element)).collect());
+        }
+        let next = self.get_property(iterator, &Value::from("next"))?;
+        if !self.is_callable(&next) {
+            return Err(RuntimeError::TypeError(format!("{} is not an iterator", iterator.to_string())));
+        }
+        
+        let mut values = Vec::new();
+        loop {
+            let result = self.call_function(&next, iterator, &[])?;
+            if !matches!(result, Value::Object(_)) {
+                return Err(RuntimeError::TypeError(format!(
+                    "Iterator result {} is not an object",
+                    result.to_string()
+                )));
+            }
+            if self.get_property(&result, &Value::from("done"))?.to_boolean() {
+                return Ok(values);
+            }
+            let value = self.get_property(&result, &Value::from("value"))?;
+            values.push(self.value_to_handle(value));
+        }
+    }
+
+    /// `CopyDataProperties`: copy the own enumerable properties of `source` onto `target`.
+    /// `null` and `undefined` sources copy nothing.
+    fn copy_data_properties(&mut self, target: &Value, source: &Value) -> RuntimeResult<()> {
+        let target = match target {
+            Value::Object(handle) => *handle,
+            _ => return Err(RuntimeError::InvalidOperation("Spread target is not an object".to_string())),
+        };
+        
+        let entries: Vec<(String, GcHandle)> = match source {
+            Value::Null
+            | Value::Undefined
+            | Value::Number(_)
+            | Value::Boolean(_)
+            | Value::Symbol(_)
+            | Value::NativeFunction(_) => {
+                return Ok(())
+            }
+            Value::String(s) => {
+                let mut gc = self.gc.lock().unwrap();
+                (0..s.len())
+                    .map(|i| (i.to_string(), gc.intern_string(&s.char_at(i).to_rust_string())))
+                    .collect()
+            }
+            Value::Object(handle) => {
+                let gc = self.gc.lock().unwrap();
+                match gc.get_object_type(*handle) {
+                    Some(GcObjectType::Object(properties)) => properties
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), *value))
+                        .collect(),
+                    Some(GcObjectType::Array(elements)) => elements
+                        .iter()
+                        .enumerate()
+                        .map(|(i, value)| (i.to_string(), *value))
+                        .collect(),
+                    _ => Vec::new(),
+                }
+            }
+        };
+        
+        // Accessor properties are copied as the values their getters return
+        let mut copied = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let is_accessor = self.gc.lock().unwrap().accessor(value).is_some();
+            let value = if is_accessor {
+                let resolved = self.resolve_accessor(source, Value::Object(value))?;
+                self.value_to_handle(resolved)
+            } else {
+                value
+            };
+            copied.push((key, value));
+        }
+        let entries = copied;
+        
+        let mut gc = self.gc.lock().unwrap();
+        let mut properties = match gc.get_object_type(target) {
+            Some(GcObjectType::Object(properties)) => properties.clone(),
+            _ => return Err(RuntimeError::InvalidOperation("Spread target is not an object".to_string())),
+        };
+        properties.extend(entries);
+        gc.update_object(target, GcObjectType::Object(properties));
+        Ok(())
+    }
+
+    /// `object[key]`, looking through the prototype chain. Strings expose
+    /// their length and code units and inherit from `String.prototype`,
+    /// numbers inherit from `Number.prototype`, and symbols expose their
+    /// description and inherit from `Symbol.prototype`; other primitives
+    /// have no properties. An accessor property gives what its getter
+    /// returns when called on `object`.
+    pub(crate) fn get_property(&mut self, object: &Value, key: &Value) -> RuntimeResult<Value> {
+        let value = self.lookup_property(object, key)?;
+        self.resolve_accessor(object, value)
+    }
+
+    /// `value` as the value of a property read from `this`: itself, or the
+    /// result of its getter if it is an accessor
+    fn resolve_accessor(&mut self, this: &Value, value: Value) -> RuntimeResult<Value> {
+        let Value::Object(handle) = value else {
+            return Ok(value);
+        };
+        let getter = {
+            let gc = self.gc.lock().unwrap();
+            match gc.accessor(handle) {
+                None => return Ok(value),
+                Some((getter, _)) => getter.map(|getter| self.value_in(&gc, getter)),
+            }
+        };
+        match getter {
+            Some(getter) => self.call_function(&getter, this, &[]),
+            None => Ok(Value::Undefined),
+        }
+    }
+
+    /// The setter of the accessor property `name` that `handle` has or
+    /// inherits, if the property is an accessor. An accessor without a
+    /// setter gives `Some(None)`, and writing to it does nothing.
+    fn inherited_setter(&self, handle: GcHandle, name: &str) -> Option<Option<Value>> {
+        let gc = self.gc.lock().unwrap();
+        let mut current = Some(handle);
+        while let Some(handle) = current {
+            if let Some(GcObjectType::Object(properties)) = gc.get_object_type(handle) {
+                if let Some(&found) = properties.get(name) {
+                    let (_, setter) = gc.accessor(found)?;
+                    return Some(setter.map(|setter| self.value_in(&gc, setter)));
+                }
+            }
+            current = gc.get_prototype(handle);
+        }
+        None
+    }
+
+    /// Define the getter or setter of the accessor property `key` of
+    /// `object`, keeping the other half if the property already is one
+    fn define_accessor(&mut self, object: &Value, key: &Value, function: Value, is_getter: bool) -> RuntimeResult<()> {
+        let Value::Object(handle) = *object else {
+            return Err(RuntimeError::TypeError("Accessors can only be defined on objects".to_string()));
+        };
+        let name = property_key(key);
+        let function = self.value_to_handle(function);
+        
+        let mut gc = self.gc.lock().unwrap();
+        let existing = match gc.get_object_type(handle) {
+            Some(GcObjectType::Object(properties)) => properties.get(&name).copied(),
+            _ => return Err(RuntimeError::TypeError("Accessors can only be defined on objects".to_string())),
+        };
+        let (mut getter, mut setter) = existing.and_then(|existing| gc.accessor(existing)).unwrap_or_default();
+        if is_getter {
+            getter = Some(function);
+        } else {
+            setter = Some(function);
+        }
+        let accessor = gc.allocate(GcObjectType::Accessor { getter, setter });
+        gc.set_property(handle, &name, accessor);
+        Ok(())
+    }
+
+    /// `object[key]` without calling getters: an accessor property gives
+    /// the object holding its getter and setter
+    fn lookup_property(&self, object: &Value, key: &Value) -> RuntimeResult<Value> {
+        // Convert a string key once, for both its name and its index
+        let name = property_key(key);
+        let index = array_index(key, &name);
+        
+        let gc = self.gc.lock().unwrap();
+        let handle = match object {
+            Value::Object(handle) => *handle,
+            Value::String(s) => {
+                match index {
+                    Some(index) if index < s.len() => return Ok(Value::String(s.char_at(index))),
+                    Some(_) => return Ok(Value::Undefined),
+                    None if name == "length" => return Ok(Value::Number(s.len() as f64)),
+                    None => {}
+                }
+                self.intrinsics.string_prototype
+            }
+            Value::Number(_) => self.intrinsics.number_prototype,
+            Value::Symbol(symbol) => {
+                if name == "description" {
+                    return Ok(symbol.description().map_or(Value::Undefined, Value::from));
+                }
+                self.intrinsics.symbol_prototype
+            }
+            Value::NativeFunction(function) => {
+                if name == "name" {
+                    return Ok(Value::from(function.name()));
+                }
+                self.intrinsics.function_prototype
+            }
+            Value::Null | Value::Undefined => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Cannot read properties of {} (reading '{}')",
+                    object.to_string(),
+                    key.to_string()
+                )));
+            }
+            _ => return Ok(Value::Undefined),
+        };
+        
+        if name == "__proto__" {
+            return Ok(match object {
+                Value::Object(_) => gc.get_prototype(handle).map_or(Value::Null, Value::Object),
+                _ => Value::Object(handle),
+            });
+        }
+        
+        let mut current = Some(handle);
+        while let Some(handle) = current {
+            let found = match gc.get_object_type(handle) {
+                Some(GcObjectType::Object(properties)) => properties.get(&name).copied(),
+                Some(GcObjectType::Array(elements)) => match index {
+                    Some(index) => elements.get(index).copied(),
+                    None if name == "length" => return Ok(Value::Number(elements.len() as f64)),
+                    None => None,
+                },
+                Some(GcObjectType::Function { name: function_name, .. }) if name == "name" => {
+                    return Ok(Value::from(function_name.clone().unwrap_or_default()));
+                }
+                Some(GcObjectType::Map(entries) | GcObjectType::Set(entries)) if name == "size" => {
+                    return Ok(Value::Number(entries.len() as f64));
+                }
+                Some(GcObjectType::TypedArray { .. }) => {
+                    let view = View::of(&gc, handle).expect("typed array has a view");
+                    match (index, name.as_str()) {
+                        (Some(index), _) => return Ok(view.get(&gc, index).map_or(Value::Undefined, Value::Number)),
+                        (None, "length") => return Ok(Value::Number(view.length as f64)),
+                        (None, "byteLength") => return Ok(Value::Number(view.byte_length() as f64)),
+                        (None, "byteOffset") => return Ok(Value::Number(view.byte_offset as f64)),
+                        (None, "buffer") => return Ok(Value::Object(view.buffer)),
+                        (None, "BYTES_PER_ELEMENT") => return Ok(Value::Number(view.kind.element_size() as f64)),
+                        (None, _) => None,
+                    }
+                }
+                Some(GcObjectType::ArrayBuffer(bytes)) => match name.as_str() {
+                    "byteLength" => return Ok(Value::Number(bytes.len() as f64)),
+                    "detached" => return Ok(Value::Boolean(false)),
+                    _ => None,
+                },
+                Some(GcObjectType::DetachedArrayBuffer) => match name.as_str() {
+                    "byteLength" => return Ok(Value::Number(0.0)),
+                    "detached" => return Ok(Value::Boolean(true)),
+                    _ => None,
+                },
+                Some(GcObjectType::DataView { buffer, byte_offset, byte_length }) => match name.as_str() {
+                    "byteLength" => return Ok(Value::Number(*byte_length as f64)),
+                    "byteOffset" => return Ok(Value::Number(*byte_offset as f64)),
+                    "buffer" => return Ok(Value::Object(*buffer)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            
+            if let Some(found) = found {
+                return Ok(self.value_in(&gc, found));
+            }
+            current = gc.get_prototype(handle);
+        }
+        
+        Ok(Value::Undefined)
+    }
+
+    /// `object[key] = value`. Writes to primitives are dropped, and arrays
+    /// keep only their elements and `length`. `__proto__` sets the prototype
+    /// to an object or null and ignores anything else.
+    pub(crate) fn set_property(&mut self, object: &Value, key: &Value, value: Value) -> RuntimeResult<()> {
+        let name = property_key(key);
+        let index = array_index(key, &name);
+        
+        let handle = match object {
+            Value::Object(handle) => *handle,
+            Value::Null | Value::Undefined => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Cannot set properties of {} (setting '{}')",
+                    object.to_string(),
+                    key.to_string()
+                )));
+            }
+            _ => return Ok(()),
+        };
+        
+        if name == "__proto__" {
+            return self.set_prototype(handle, &value);
+        }
+        
+        let is_array = {
+            let gc = self.gc.lock().unwrap();
+            match gc.get_object_type(handle) {
+                Some(GcObjectType::Object(_)) => false,
+                Some(GcObjectType::Array(_)) => true,
+                Some(GcObjectType::TypedArray { .. }) => {
+                    let view = View::of(&gc, handle).expect("typed array has a view");
+                    drop(gc);
+                    // Elements are the only properties a typed array holds
+                    if let Some(index) = index {
+                        let n = value.to_number()?;
+                        view.set(&mut self.gc.lock().unwrap(), index, n);
+                    }
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            }
+        };
+        
+        if !is_array {
+            if let Some(setter) = self.inherited_setter(handle, &name) {
+                if let Some(setter) = setter {
+                    self.call_function(&setter, object, &[value])?;
+                }
+                return Ok(());
+            }
+            let value = self.value_to_handle(value);
+            self.gc.lock().unwrap().set_property(handle, &name, value);
+        } else if let Some(index) = index {
+            let value = self.value_to_handle(value);
+            let fill = self.value_to_handle(Value::Undefined);
+            self.gc.lock().unwrap().set_element(handle, index, value, fill);
+        } else if name == "length" {
+            let length = value.to_number()?;
+            if length.fract() != 0.0 || !(0.0..4_294_967_296.0).contains(&length) {
+                return Err(RuntimeError::RangeError("Invalid array length".to_string()));
+            }
+            let fill = self.value_to_handle(Value::Undefined);
+            let mut gc = self.gc.lock().unwrap();
+            if let Some(GcObjectType::Array(elements)) = gc.get_object_type(handle) {
+                let mut elements = elements.clone();
+                elements.resize(length as usize, fill);
+                gc.update_object(handle, GcObjectType::Array(elements));
+            }
+        }
+        
+        Ok(())
+    }
+
+    /// `delete object[key]`: remove an own property, returning whether it
+    /// is gone. Deleted array elements become undefined, as arrays have no
+    /// holes, and built-in properties like `length` cannot be deleted.
+    pub(crate) fn delete_property(&mut self, object: &Value, key: &Value) -> RuntimeResult<bool> {
+        let name = property_key(key);
+        let index = array_index(key, &name);
+        
+        let handle = match object {
+            Value::Object(handle) => *handle,
+            Value::String(s) => return Ok(name != "length" && !matches!(index, Some(index) if index < s.len())),
+            Value::Null | Value::Undefined => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Cannot convert {} to object (deleting '{}')",
+                    object.to_string(),
+                    key.to_string()
+                )));
+            }
+            _ => return Ok(true),
+        };
+        
+        let fill = self.value_to_handle(Value::Undefined);
+        let mut gc = self.gc.lock().unwrap();
+        let deleted = match gc.get_object_type(handle) {
+            Some(GcObjectType::Object(_)) => gc.remove_property(handle, &name),
+            Some(GcObjectType::Array(elements)) => match index {
+                Some(index) if index < elements.len() => gc.set_element(handle, index, fill, fill),
+                Some(_) => true,
+                None => name != "length",
+            },
+            Some(GcObjectType::TypedArray { .. }) => {
+                let view = View::of(&gc, handle).expect("typed array has a view");
+                !matches!(index, Some(index) if index < view.length)
+            }
+            Some(GcObjectType::Function { .. }) => name != "name",
+            _ => true,
+        };
+        Ok(deleted)
+    }
+
+    /// `key in object`: whether `object` or its prototype chain has the
+    /// property `key`, for the same properties `get_property` finds
+    pub(crate) fn has_property(&self, object: &Value, key: &Value) -> RuntimeResult<bool> {
+        let name = property_key(key);
+        let index = array_index(key, &name);
+        
+        let handle = match object {
+            Value::Object(handle) => *handle,
+            Value::NativeFunction(_) if name == "name" => return Ok(true),
+            Value::NativeFunction(_) => self.intrinsics.function_prototype,
+            _ => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Cannot use 'in' operator to search for '{}' in {}",
+                    key.to_string(),
+                    object.to_string()
+                )));
+            }
+        };
+        
+        let gc = self.gc.lock().unwrap();
+        let mut current = Some(handle);
+        while let Some(handle) = current {
+            let found = match gc.get_object_type(handle) {
+                Some(GcObjectType::Object(properties)) => properties.contains_key(&name),
+                Some(GcObjectType::Array(elements)) => {
+                    name == "length" || matches!(index, Some(index) if index < elements.len())
+                }
+                Some(GcObjectType::Function { .. }) => name == "name",
+                Some(GcObjectType::Map(_) | GcObjectType::Set(_)) => name == "size",
+                Some(GcObjectType::TypedArray { .. }) => {
+                    let view = View::of(&gc, handle).expect("typed array has a view");
+                    match index {
+                        Some(index) => index < view.length,
+                        None => matches!(
+                            name.as_str(),
+                            "length" | "byteLength" | "byteOffset" | "buffer" | "BYTES_PER_ELEMENT"
+                        ),
+                    }
+                }
+                Some(GcObjectType::ArrayBuffer(_) | GcObjectType::DetachedArrayBuffer) => {
+                    matches!(name.as_str(), "byteLength" | "detached")
+                }
+                Some(GcObjectType::DataView { .. }) => matches!(name.as_str(), "byteLength" | "byteOffset" | "buffer"),
+                _ => false,
+            };
+            
+            if found {
+                return Ok(true);
+            }
+            current = gc.get_prototype(handle);
+        }
+        
+        Ok(false)
+    }
+
+    /// `value instanceof constructor`: whether `constructor.prototype` is on
+    /// the prototype chain of `value`. Built-in constructors are objects
+    /// holding their prototype, so any object with one can be checked against.
+    pub(crate) fn instance_of(&mut self, value: &Value, constructor: &Value) -> RuntimeResult<bool> {
+        if !matches!(constructor, Value::Object(_) | Value::NativeFunction(_)) {
+            return Err(RuntimeError::TypeError(format!(
+                "Right-hand side of 'instanceof' is not an object: {}",
+                constructor.to_string()
+            )));
+        }
+        let prototype = match self.get_property(constructor, &Value::from("prototype"))? {
+            Value::Object(prototype) => prototype,
+            other => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Function has non-object prototype '{}' in instanceof check",
+                    other.to_string()
+                )));
+            }
+        };
+        
+        // Primitives are instances of nothing
+        let Value::Object(handle) = value else {
+            return Ok(false);
+        };
+        let gc = self.gc.lock().unwrap();
+        let mut current = gc.get_prototype(*handle);
+        while let Some(handle) = current {
+            if handle == prototype {
+                return Ok(true);
+            }
+            current = gc.get_prototype(handle);
+        }
+        Ok(false)
+    }
+
+    /// `get_property` at the property site `site`, answered from the site's
+    /// inline cache when it has seen the object's shape
+    fn get_property_at_site(&mut self, site: Site, object: &Value, key: &Value) -> RuntimeResult<Value> {
+        let Value::Object(handle) = *object else {
+            return self.get_property(object, key);
+        };
+        let name = property_key(key);
+        
+        {
+            let gc = self.gc.lock().unwrap();
+            if let Some(found) = self.load_cache.load(&gc, site, handle, &name) {
+                let value = self.value_in(&gc, found);
+                drop(gc);
+                self.load_cache.record_hit();
+                return self.resolve_accessor(object, value);
+            }
+        }
+        
+        let value = self.get_property(object, key)?;
+        let entry = PropertyEntry::load(&self.gc.lock().unwrap(), handle, &name);
+        self.load_cache.record_miss(site, entry);
+        Ok(value)
+    }
+
+    /// `set_property` at the property site `site`, writing straight to the
+    /// slot when the site has stored to an object of the same shape. Only
+    /// existing properties are cached, as adding one changes the shape.
+    fn set_property_at_site(&mut self, site: Site, object: &Value, key: &Value, value: Value) -> RuntimeResult<()> {
+        let Value::Object(handle) = *object else {
+            return self.set_property(object, key, value);
+        };
+        let name = property_key(key);
+        
+        let cached = self.store_cache.store(&self.gc.lock().unwrap(), site, handle, &name);
+        if let Some((shape, slot)) = cached {
+            let value = self.value_to_handle(value.clone());
+            if self.gc.lock().unwrap().set_property_slot(handle, shape, slot, value) {
+                self.store_cache.record_hit();
+                return Ok(());
+            }
+        }
+        
+        self.set_property(object, key, value)?;
+        let entry = PropertyEntry::store(&self.gc.lock().unwrap(), handle, &name);
+        self.store_cache.record_miss(site, entry);
+        Ok(())
+    }
+
+    /// Make `object` inherit from `prototype` if it is an object or null,
+    /// refusing to close a cycle
+    pub(crate) fn set_prototype(&mut self, object: GcHandle, prototype: &Value) -> RuntimeResult<()> {
+        let prototype = match prototype {
+            Value::Object(prototype) => Some(*prototype),
+            Value::Null => None,
+            _ => return Ok(()),
+        };
+        
+        let mut gc = self.gc.lock().unwrap();
+        let mut current = prototype;
+        while let Some(handle) = current {
+            if handle == object {
+                return Err(RuntimeError::TypeError("Cyclic __proto__ value".to_string()));
+            }
+            current = gc.get_prototype(handle);
+        }
+        gc.set_prototype(object, prototype);
+        Ok(())
+    }
+
+    /// Run the newly entered frame's compiled code when a tier has some.
+    /// Returns the frame's result if native code ran it to completion; after
+    /// a side exit the interpreter picks the frame up where it left off.
+    fn run_compiled_code(&mut self) -> Option<Value> {
+        let frame = self.call_stack.last_mut()?;
+        let bytecode = Arc::clone(&frame.bytecode);
+        let code = self.hotness.compiled_code(CodeId::of(&bytecode))?;
+        
+        // The locals are out of the frame while native code runs, so
+        // collection waits as it does for a host call
+        let mut locals = std::mem::take(&mut frame.locals);
+        self.host_calls += 1;
+        let outcome = code.invoke(&mut FrameHost { vm: self, bytecode }, &mut locals);
+        self.host_calls -= 1;
+        let frame = self.call_stack.last_mut()?;
+        frame.locals = locals;
+        
+        match outcome? {
+            NativeOutcome::Returned(value) => Some(value),
+            NativeOutcome::SideExit { pc, stack } => {
+                frame.pc = pc;
+                self.stack.extend(stack);
+                None
+            }
+            NativeOutcome::Threw { pc, stack, error } => {
+                frame.pc = pc;
+                self.stack.extend(stack);
+                self.pending_throw = Some(error);
+                None
+            }
+        }
+    }
+
+    /// Park the current async frame at an `await` and arrange for it to be
+    /// resumed once `awaited` settles. Returns the frame's promise.
+    fn suspend_frame(&mut self, awaited: Value) -> RuntimeResult<GcHandle> {
+        let frame = self.call_stack.pop()
+            .ok_or_else(|| RuntimeError::InvalidOperation("No call frame".to_string()))?;
+        let promise = frame.async_promise.ok_or_else(|| {
+            RuntimeError::InvalidOperation("await is only valid in async functions".to_string())
+        })?;
+        
+        let stack = self.stack.split_off(frame.base_stack_offset);
+        let coroutine = self.next_coroutine_id;
+        self.next_coroutine_id += 1;
+        self.coroutines.insert(coroutine, Coroutine { frame, stack });
+        
+        if let Value::Object(handle) = &awaited {
+            self.mark_rejection_handled(*handle);
+        }
+        match self.inspect_awaited(awaited) {
+            Awaited::Settled(outcome) => self.jobs.push_back(PromiseJob::Resume { coroutine, outcome }),
+            Awaited::Pending(handle) => self.promise_waiters
+                .entry(handle)
+                .or_default()
+                .push(Waiter::Coroutine(coroutine)),
+        }
+        
+        Ok(promise)
+    }
+
+    /// Non-promise values are treated as already fulfilled
+    fn inspect_awaited(&self, value: Value) -> Awaited {
+        let handle = match value {
+            Value::Object(handle) => handle,
+            other => return Awaited::Settled(Ok(other)),
+        };
+        
+        let gc = self.gc.lock().unwrap();
+        match gc.get_object_type(handle) {
+            Some(GcObjectType::Promise { state, value: result, .. }) => {
+                let result = result
+                    .and_then(|h| gc.get_object_type(h).map(|object| Value::from_gc_object_type(object, h)))
+                    .unwrap_or(Value::Undefined);
+                match state {
+                    PromiseState::Pending => Awaited::Pending(handle),
+                    PromiseState::Fulfilled => Awaited::Settled(Ok(result)),
+                    PromiseState::Rejected => Awaited::Settled(Err(result)),
+                }
+            }
+            _ => Awaited::Settled(Ok(Value::Object(handle))),
+        }
+    }
+
+    /// Continue a suspended async frame with the outcome of its `await`
+    fn resume_coroutine(&mut self, coroutine: u64, outcome: Result<Value, Value>) {
+        let Some(Coroutine { mut frame, stack }) = self.coroutines.remove(&coroutine) else {
+            return;
+        };
+        
+        let depth = self.call_stack.len();
+        frame.base_stack_offset = self.stack.len();
+        self.stack.extend(stack);
+        self.call_stack.push(frame);
+        
+        let result = match outcome {
+            Ok(value) => {
+                self.stack.push(value);
+                self.run_interpreter_loop()
+            }
+            // A rejected await throws at the `await`
+            Err(reason) => match self.throw_value(reason, depth + 1) {
+                Ok(None) => self.run_interpreter_loop(),
+                Ok(Some(value)) => Ok(value),
+                Err(error) => Err(error),
+            },
+        };
+        self.unwind_async(depth, result);
+    }
+
+    /// Unwind to the innermost handler for `exception`, popping the frames
+    /// that have none. An async frame stops the unwinding by rejecting its
+    /// promise, which its caller gets as the call's result. Returns the
+    /// run's result when that was the base frame, or `RuntimeError::Thrown`
+    /// with the stack at the throw when nothing caught the exception.
+    fn throw_value(&mut self, exception: Value, base_depth: usize) -> RuntimeResult<Option<Value>> {
+        let stack = self.stack_trace();
+        
+        while self.call_stack.len() >= base_depth {
+            let Some(frame) = self.call_stack.last_mut() else {
+                break;
+            };
+            
+            let pc = frame.pc;
+            if let Some(handler) = frame.bytecode.handlers.iter().find(|handler| handler.covers(pc)) {
+                frame.pc = handler.handler;
+                self.stack.truncate(frame.base_stack_offset + handler.stack_depth);
+                self.push_stack(exception)?;
+                return Ok(None);
+            }
+            
+            let frame = self.call_stack.pop().unwrap();
+            self.stack.truncate(frame.base_stack_offset);
+            
+            if let Some(promise) = frame.async_promise {
+                self.settle_promise(promise, Err(exception));
+                if self.call_stack.len() < base_depth {
+                    return Ok(Some(Value::Object(promise)));
+                }
+                self.push_stack(Value::Object(promise))?;
+                if let Some(caller_frame) = self.call_stack.last_mut() {
+                    caller_frame.pc += 1;
+                }
+                return Ok(None);
+            }
+        }
+        
+        let message = self.describe_exception(&exception);
+        self.escaped_exception = Some(exception);
+        Err(RuntimeError::Thrown { message, stack })
+    }
+
+    /// The error object a catchable runtime error throws, or the exception
+    /// that escaped a nested run
+    pub(crate) fn error_value(&mut self, error: &RuntimeError) -> Option<Value> {
+        let (name, message) = match error {
+            RuntimeError::Thrown { .. } => return self.escaped_exception.take(),
+            RuntimeError::Error(message) => ("Error", message),
+            RuntimeError::TypeError(message) => ("TypeError", message),
+            RuntimeError::ReferenceError(message) => ("ReferenceError", message),
+            RuntimeError::RangeError(message) => ("RangeError", message),
+            RuntimeError::SyntaxError(message) => ("SyntaxError", message),
+            _ => return None,
+        };
+        
+        let properties = {
+            let mut gc = self.gc.lock().unwrap();
+            HashMap::from([
+                ("name".to_string(), gc.intern_string(name)),
+                ("message".to_string(), gc.allocate_string(message.clone())),
+            ])
+        };
+        Some(Value::Object(self.create_object(properties)))
+    }
+
+    /// The error a built-in returns to throw `exception` as is, for the
+    /// caller's handlers to catch
+    pub(crate) fn throw(&mut self, exception: Value) -> RuntimeError {
+        let message = self.describe_exception(&exception);
+        self.escaped_exception = Some(exception);
+        RuntimeError::Thrown { message, stack: self.stack_trace() }
+    }
+
+    /// `name: message` for error-like objects, the string form otherwise
+    pub(crate) fn describe_exception(&self, exception: &Value) -> String {
+        if let Value::Object(handle) = exception {
+            let gc = self.gc.lock().unwrap();
+            if let Some(GcObjectType::Object(properties)) = gc.get_object_type(*handle) {
+                if let Some(message) = properties.get("message") {
+                    let text = |handle: GcHandle| {
+                        gc.get_object_type(handle)
+                            .map(|object| Value::from_gc_object_type(object, handle).to_string())
+                    };
+                    let name = properties.get("name").and_then(|name| text(*name));
+                    return format!("{}: {}", name.as_deref().unwrap_or("Error"), text(*message).unwrap_or_default());
+                }
+            }
+        }
+        exception.to_string()
+    }
+
+    /// Drop the frames an async run left above `depth`. A runtime error
+    /// rejects the promise of the async frame it escaped from.
+    fn unwind_async(&mut self, depth: usize, result: RuntimeResult<Value>) {
+        if let Err(error) = result {
+            if let Some(frame) = self.call_stack.get(depth) {
+                let (promise, base) = (frame.async_promise, frame.base_stack_offset);
+                self.stack.truncate(base);
+                if let Some(promise) = promise {
+                    let reason = Value::String(crate::JsString::from(error.to_string()));
+                    self.settle_promise(promise, Err(reason));
+                }
+            }
+        }
+        self.call_stack.truncate(depth);
+    }
+
+    pub fn create_promise(&mut self) -> GcHandle {
+        let mut gc = self.gc.lock().unwrap();
+        gc.allocate_promise()
+    }
+
+    pub fn resolve_promise(&mut self, promise: GcHandle, value: Value) {
+        self.settle_promise(promise, Ok(value));
+    }
+
+    pub fn reject_promise(&mut self, promise: GcHandle, reason: Value) {
+        self.settle_promise(promise, Err(reason));
+    }
+
+    /// The state of `value` if it is a promise
+    pub fn promise_state(&self, value: &Value) -> Option<PromiseInspection> {
+        let Value::Object(handle) = value else {
+            return None;
+        };
+        if !matches!(self.gc.lock().unwrap().get_object_type(*handle), Some(GcObjectType::Promise { .. })) {
+            return None;
+        }
+        Some(match self.inspect_awaited(value.clone()) {
+            Awaited::Pending(_) => PromiseInspection::Pending,
+            Awaited::Settled(Ok(value)) => PromiseInspection::Fulfilled(value),
+            Awaited::Settled(Err(reason)) => PromiseInspection::Rejected(reason),
+        })
+    }
+
+    /// Count the host as handling `promise`'s rejection, whether it has
+    /// rejected already or does later, as when the host waits on it
+    pub fn handle_rejection(&mut self, promise: GcHandle) {
+        self.mark_rejection_handled(promise);
+        if let Awaited::Pending(promise) = self.inspect_awaited(Value::Object(promise)) {
+            self.promise_waiters.entry(promise).or_default().push(Waiter::Host);
+        }
+    }
+
+    /// Settle a pending promise and queue a job for everything awaiting it.
+    /// Resolving with another promise adopts that promise's eventual state.
+    fn settle_promise(&mut self, promise: GcHandle, outcome: Result<Value, Value>) {
+        if let Ok(Value::Object(handle)) = &outcome {
+            if *handle != promise {
+                self.mark_rejection_handled(*handle);
+                match self.inspect_awaited(Value::Object(*handle)) {
+                    Awaited::Pending(inner) => {
+                        self.promise_waiters.entry(inner).or_default().push(Waiter::Promise(promise));
+                        return;
+                    }
+                    // A plain object settles as itself
+                    Awaited::Settled(Ok(Value::Object(value))) if value == *handle => {}
+                    Awaited::Settled(inner) => return self.settle_promise(promise, inner),
+                }
+            }
+        }
+        
+        let (state, value) = match &outcome {
+            Ok(value) => (PromiseState::Fulfilled, value.clone()),
+            Err(reason) => (PromiseState::Rejected, reason.clone()),
+        };
+        let value = self.value_to_handle(value);
+        
+        {
+            let mut gc = self.gc.lock().unwrap();
+            match gc.get_object_type(promise) {
+                Some(GcObjectType::Promise { state: PromiseState::Pending, callbacks, .. }) => {
+                    let callbacks = callbacks.clone();
+                    gc.update_object(promise, GcObjectType::Promise { state, value: Some(value), callbacks });
+                }
+                // Already settled, or not a promise at all
+                _ => return,
+            }
+        }
+        
+        let waiters = self.promise_waiters.remove(&promise).unwrap_or_default();
+        if outcome.is_err() && waiters.is_empty() {
+            self.unhandled_rejections.push(promise);
+        }
+        for waiter in waiters {
+            match waiter {
+                Waiter::Coroutine(coroutine) => {
+                    self.jobs.push_back(PromiseJob::Resume { coroutine, outcome: outcome.clone() });
+                }
+                Waiter::Promise(adopter) => self.settle_promise(adopter, outcome.clone()),
+                Waiter::Host => {}
+                Waiter::Reaction { on_fulfilled, on_rejected } => self.queue_reaction(on_fulfilled, on_rejected, &outcome),
+            }
+        }
+    }
+
+    /// Run queued promise jobs until the queue drains, including jobs queued
+    /// while running. Returns how many ran.
+    pub fn run_jobs(&mut self) -> usize {
+        let mut ran = 0;
+        while let Some(job) = self.jobs.pop_front() {
+            match job {
+                PromiseJob::Resume { coroutine, outcome } => self.resume_coroutine(coroutine, outcome),
+                PromiseJob::Callback { function } => {
+                    if let Err(error) = self.call_function(&function, &Value::Undefined, &[]) {
+                        self.report_job_error(error);
+                    }
+                }
+                PromiseJob::Reaction { function, argument } => {
+                    if let Err(error) = self.call_function(&function, &Value::Undefined, &[argument]) {
+                        self.report_job_error(error);
+                    }
+                }
+            }
+            ran += 1;
+        }
+        ran
+    }
+
+    pub fn has_pending_jobs(&self) -> bool {
+        !self.jobs.is_empty()
+    }
+
+    /// Queue `callback` to run with the other microtasks, after the current
+    /// script and before any timer
+    pub(crate) fn queue_microtask(&mut self, callback: Value) {
+        self.jobs.push_back(PromiseJob::Callback { function: callback });
+    }
+
+    /// Call `on_fulfilled` with `value`'s value once it fulfills, or
+    /// `on_rejected` with its reason once it rejects, as a microtask. A
+    /// value that is not a promise fulfills with itself. Reacting handles
+    /// the promise's rejection.
+    pub fn when_settled(&mut self, value: Value, on_fulfilled: Value, on_rejected: Value) {
+        if let Value::Object(handle) = value {
+            self.mark_rejection_handled(handle);
+        }
+        match self.inspect_awaited(value) {
+            Awaited::Pending(promise) => {
+                let waiter = Waiter::Reaction { on_fulfilled, on_rejected };
+                self.promise_waiters.entry(promise).or_default().push(waiter);
+            }
+            Awaited::Settled(outcome) => self.queue_reaction(on_fulfilled, on_rejected, &outcome),
+        }
+    }
+
+    fn queue_reaction(&mut self, on_fulfilled: Value, on_rejected: Value, outcome: &Result<Value, Value>) {
+        let (function, argument) = match outcome {
+            Ok(value) => (on_fulfilled, value.clone()),
+            Err(reason) => (on_rejected, reason.clone()),
+        };
+        self.jobs.push_back(PromiseJob::Reaction { function, argument });
+    }
+
+    /// Report an error a callback threw where no script can catch it, as
+    /// an `abort` listener's, alongside those of microtasks
+    pub(crate) fn report_job_error(&mut self, error: RuntimeError) {
+        // Taken now, before other code throws over it
+        let exception = self.error_value(&error);
+        self.job_errors.push((error, exception));
+    }
+
+    /// Errors thrown by microtask callbacks and event listeners since the
+    /// last call, with the values they threw where scripts could catch them
+    pub fn take_job_errors(&mut self) -> Vec<(RuntimeError, Option<Value>)> {
+        std::mem::take(&mut self.job_errors)
+    }
+
+    /// Something now handles `promise`'s rejection
+    fn mark_rejection_handled(&mut self, promise: GcHandle) {
+        self.unhandled_rejections.retain(|&rejected| rejected != promise);
+    }
+
+    /// The promises rejected with no handler since the last call, with
+    /// their reasons. Hosts call this at the end of a tick, once jobs have
+    /// had their chance to handle them.
+    pub fn take_unhandled_rejections(&mut self) -> Vec<(GcHandle, Value)> {
+        let rejected = std::mem::take(&mut self.unhandled_rejections);
+        rejected
+            .into_iter()
+            .filter_map(|promise| match self.inspect_awaited(Value::Object(promise)) {
+                Awaited::Settled(Err(reason)) => Some((promise, reason)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// `Call`: pop the callee and its arguments and enter the callee's frame.
+    /// Missing arguments are `undefined` and extra ones are dropped. The
+    /// caller's pc moves past the call when the callee returns.
+    /// Pop the top `arg_count` values, first argument first
+    fn pop_arguments(&mut self, arg_count: usize) -> RuntimeResult<Vec<Value>> {
+        let start = self.stack.len().checked_sub(arg_count).ok_or_else(|| {
+            RuntimeError::InvalidOperation("Stack underflow".to_string())
+        })?;
+        Ok(self.stack.split_off(start))
+    }
+
+    fn handle_function_call(&mut self, function: Value, this: Value, args: Vec<Value>) -> RuntimeResult<()> {
+        let host_call = self.call_host_function(&function, &this, &args);
+        if let Some(value) = host_call {
+            return self.finish_host_call(value?);
+        }
+        
+        let code = self.function_code_of(&function)?;
+        self.enter_function(code, this, args)
+    }
+
+    /// `handle_function_call` at the call site `site`, taking what to run
+    /// from the site's inline cache when it has called `function` before
+    fn call_at_site(&mut self, site: Site, function: Value, this: Value, args: Vec<Value>) -> RuntimeResult<()> {
+        let Value::Object(handle) = function else {
+            return self.handle_function_call(function, this, args);
+        };
+        
+        let target = match self.call_cache.call_target(site, handle) {
+            Some(target) => {
+                self.call_cache.record_hit();
+                target
+            }
+            None => {
+                let target = match self.builtin_of(&function) {
+                    Some(builtin) => CallTarget::Builtin(builtin),
+                    None => CallTarget::Function(self.function_code_of(&function)?),
+                };
+                self.call_cache.record_miss(site, Some((handle, target.clone())));
+                target
+            }
+        };
+        
+        match target {
+            CallTarget::Builtin(builtin) => {
+                self.host_calls += 1;
+                let result = builtin(self, &this, &args);
+                self.host_calls -= 1;
+                self.finish_host_call(result?)
+            }
+            CallTarget::Function(code) => self.enter_function(code, this, args),
+        }
+    }
+
+    /// Push what a built-in or native function returned and move the caller
+    /// past its call
+    fn finish_host_call(&mut self, value: Value) -> RuntimeResult<()> {
+        self.push_stack(value)?;
+        if let Some(caller_frame) = self.call_stack.last_mut() {
+            caller_frame.pc += 1;
+        }
+        Ok(())
+    }
+
+    /// Enter a frame running `code`, or run it to completion if a tier has
+    /// compiled it
+    fn enter_function(&mut self, code: Arc<FunctionCode>, this: Value, args: Vec<Value>) -> RuntimeResult<()> {
+        let is_async = code.is_async;
+        self.push_call_frame(code, this, args);
+        
+        // Async frames stay in the interpreter, which settles their promise
+        if !is_async {
+            if let Some(value) = self.run_compiled_code() {
+                self.call_stack.pop();
+                self.push_stack(value)?;
+                if let Some(caller_frame) = self.call_stack.last_mut() {
+                    caller_frame.pc += 1;
+                }
+            }
+        }
+        
+        Ok(())
+    }
+
+    /// Call `function` from Rust and run it to completion, e.g. for a
+    /// callback passed to a built-in. An exception the callee does not catch
+    /// comes back as the error.
+    pub(crate) fn call_function(&mut self, function: &Value, this: &Value, args: &[Value]) -> RuntimeResult<Value> {
+        if let Some(value) = self.call_host_function(function, this, args) {
+            return value;
+        }
+        
+        let code = self.function_code_of(function)?;
+        let is_async = code.is_async;
+        let (depth, stack_len) = (self.call_stack.len(), self.stack.len());
+        self.push_call_frame(code, this.clone(), args.to_vec());
+        
+        let compiled = if is_async { None } else { self.run_compiled_code() };
+        let result = match compiled {
+            Some(value) => Ok(value),
+            None => self.run_interpreter_loop(),
+        };
+        
+        // Frames an uncaught exception escaped from are left behind
+        self.call_stack.truncate(depth);
+        self.stack.truncate(stack_len);
+        result
+    }
+
+    /// Run `function` if it is implemented in Rust
+    fn call_host_function(&mut self, function: &Value, this: &Value, args: &[Value]) -> Option<RuntimeResult<Value>> {
+        self.host_calls += 1;
+        let result = match function {
+            Value::NativeFunction(native) => Some(native.call(Runtime::from_vm_mut(self), args)),
+            _ => self.builtin_of(function).map(|builtin| builtin(self, this, args)),
+        };
+        self.host_calls -= 1;
+        result
+    }
+
+    /// Collect garbage, keeping alive what the VM and its root sources hold,
+    /// then run the finalizers of what was collected
+    pub fn collect_garbage(&mut self) -> usize {
+        let roots = self.roots();
+        let (collected, finalizers) = {
+            let mut gc = self.gc.lock().unwrap();
+            let collected = gc.collect_with_roots(roots);
+            (collected, gc.take_finalizers())
+        };
+        for finalizer in finalizers {
+            finalizer();
+        }
+        collected
+    }
+
+    /// Snapshot the heap, with everything scripts can reach as roots
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        let roots = self.roots();
+        self.gc.lock().unwrap().heap_snapshot_with_roots(roots)
+    }
+
+    /// Trace the objects `source` pushes as roots at every collection
+    pub fn add_root_source(&mut self, source: Box<RootSource>) {
+        self.root_sources.push(source);
+    }
+
+    /// The objects held outside the heap: on the stack, in frames and
+    /// parked coroutines, in globals, in promise bookkeeping and queued
+    /// jobs, and in the root sources
+    fn roots(&self) -> Vec<GcHandle> {
+        let frames = self.call_stack.iter().chain(self.coroutines.values().map(|coroutine| &coroutine.frame));
+        let mut values: Vec<&Value> = self.stack.iter().chain(self.globals.values()).collect();
+        for frame in frames {
+            values.extend(&frame.locals);
+            values.push(&frame.completion);
+            values.push(&frame.this);
+        }
+        for coroutine in self.coroutines.values() {
+            values.extend(&coroutine.stack);
+        }
+        for job in &self.jobs {
+            match job {
+                PromiseJob::Resume { outcome: Ok(value) | Err(value), .. } => values.push(value),
+                PromiseJob::Callback { function } => values.push(function),
+                PromiseJob::Reaction { function, argument } => values.extend([function, argument]),
+            }
+        }
+        for waiters in self.promise_waiters.values() {
+            for waiter in waiters {
+                if let Waiter::Reaction { on_fulfilled, on_rejected } = waiter {
+                    values.extend([on_fulfilled, on_rejected]);
+                }
+            }
+        }
+        for record in self.abort_signals.values() {
+            values.extend(&record.listeners);
+        }
+        values.extend(&self.escaped_exception);
+        values.extend(self.job_errors.iter().filter_map(|(_, exception)| exception.as_ref()));
+
+        let mut roots: Vec<GcHandle> = values.into_iter().filter_map(Value::as_handle).collect();
+        let frames = self.call_stack.iter().chain(self.coroutines.values().map(|coroutine| &coroutine.frame));
+        roots.extend(frames.filter_map(|frame| frame.async_promise));
+        for (&promise, waiters) in &self.promise_waiters {
+            roots.push(promise);
+            roots.extend(waiters.iter().filter_map(|waiter| match waiter {
+                Waiter::Promise(promise) => Some(*promise),
+                _ => None,
+            }));
+        }
+        roots.extend(&self.unhandled_rejections);
+        roots.extend(self.abort_signals.keys());
+        roots.extend(self.abort_timeouts.iter().map(|&(signal, _)| signal));
+        for source in &self.root_sources {
+            source(&mut roots);
+        }
+        roots
+    }
+
+    /// The bytecode behind a function object
+    fn function_code_of(&self, function: &Value) -> RuntimeResult<Arc<FunctionCode>> {
+        let code = match function {
+            Value::Object(handle) => self.functions.get(handle).cloned(),
+            _ => None,
+        };
+        let code = code.ok_or_else(|| RuntimeError::TypeError(format!("{} is not a function", function.to_string())))?;
+        
+        if code.is_generator {
+            return Err(RuntimeError::InvalidOperation("Generator functions are not supported yet".to_string()));
+        }
+        Ok(code)
+    }
+
+    /// Enter a frame running `code` on `this` with `args` as its parameters
+    fn push_call_frame(&mut self, code: Arc<FunctionCode>, this: Value, mut args: Vec<Value>) {
+        args.resize(code.param_count, Value::Undefined);
+        let async_promise = code.is_async.then(|| self.create_promise());
+        let mut frame = CallFrame {
+            bytecode: Arc::clone(&code.bytecode),
+            pc: 0,
+            locals: args,
+            base_stack_offset: self.stack.len(),
+            completion: Value::Undefined,
+            async_promise,
+            function_name: code.name.clone(),
+            this,
+            position: None,
+            uninitialized: HashSet::new(),
+        };
+        
+        self.hotness.record_function_entry(&frame.bytecode, code.name.as_deref(), code.param_count);
+        self.presize_frame(&mut frame);
+        self.call_stack.push(frame);
+    }
+
+    /// Give `frame` every local slot its code uses and reserve room for its
+    /// deepest operand stack, as the compiler measured them
+    fn presize_frame(&mut self, frame: &mut CallFrame) {
+        let bytecode = &frame.bytecode;
+        if frame.locals.len() < bytecode.local_count {
+            frame.locals.resize(bytecode.local_count, Value::Undefined);
+        }
+        self.stack.reserve(bytecode.max_stack_depth);
+    }
+
+    /// Whether calling `value` runs a function
+    pub(crate) fn is_callable(&self, value: &Value) -> bool {
+        match value {
+            Value::NativeFunction(_) => true,
+            Value::Object(handle) => self.functions.contains_key(handle) || self.builtins.contains_key(handle),
+            _ => false,
+        }
+    }
+
+    fn builtin_of(&self, function: &Value) -> Option<Builtin> {
+        match function {
+            Value::Object(handle) => self.builtins.get(handle).copied(),
+            _ => None,
+        }
+    }
+
+    fn push_stack(&mut self, value: Value) -> RuntimeResult<()> {
+        if self.stack.len() >= self.max_stack_size {
+            Err(self.stack_overflow())
+        } else {
+            self.stack.push(value);
             Ok(())
         }
     }
@@ -419,10 +2562,19 @@ impl VirtualMachine {
     }
 
     fn peek_stack(&self, offset: usize) -> RuntimeResult<Value> {
-        let index = self.stack.len().saturating_sub(offset + 1);
-        self.stack.get(index).cloned().ok_or_else(|| {
+        let index = self.stack.len().checked_sub(offset + 1).ok_or_else(|| {
             RuntimeError::InvalidOperation("Stack underflow".to_string())
-        })
+        })?;
+        Ok(self.stack[index].clone())
+    }
+
+    /// Move the top of the stack below the `count - 1` values under it
+    fn rotate_stack(&mut self, count: usize) -> RuntimeResult<()> {
+        let start = self.stack.len().checked_sub(count).ok_or_else(|| {
+            RuntimeError::InvalidOperation("Stack underflow".to_string())
+        })?;
+        self.stack[start..].rotate_right(1);
+        Ok(())
     }
 
     pub fn get_global(&self, name: &str) -> Option<&Value> {
@@ -433,6 +2585,40 @@ impl VirtualMachine {
         self.globals.insert(name, value);
     }
 
+    /// Loop and function execution counts, hottest first
+    pub fn vm_stats(&self) -> VmStats {
+        self.hotness.stats()
+    }
+
+    /// Hit rates and site states of the inline caches
+    pub fn ic_stats(&self) -> IcStats {
+        IcStats {
+            loads: self.load_cache.counters(),
+            stores: self.store_cache.counters(),
+            calls: self.call_cache.counters(),
+        }
+    }
+
+    /// Empty the inline caches and their counts
+    pub fn reset_inline_caches(&mut self) {
+        self.load_cache.clear();
+        self.store_cache.clear();
+        self.call_cache.clear();
+    }
+
+    /// Register a tier to be told when loops or functions become hot
+    pub fn add_tier(&mut self, tier: Box<dyn Tier>) {
+        self.hotness.add_tier(tier);
+    }
+
+    pub fn set_tier_thresholds(&mut self, thresholds: TierThresholds) {
+        self.hotness.set_thresholds(thresholds);
+    }
+
+    pub fn reset_hotness(&mut self) {
+        self.hotness.reset();
+    }
+
     pub fn stack_size(&self) -> usize {
         self.stack.len()
     }
@@ -440,4 +2626,50 @@ impl VirtualMachine {
     pub fn call_depth(&self) -> usize {
         self.call_stack.len()
     }
+
+    /// `RuntimeError::StackOverflow` with the frames at the overflow
+    fn stack_overflow(&self) -> RuntimeError {
+        RuntimeError::StackOverflow { stack: self.stack_trace() }
+    }
+
+    /// Capture the active call frames, innermost first
+    pub fn stack_trace(&self) -> Vec<StackFrameInfo> {
+        self.call_stack
+            .iter()
+            .rev()
+            .map(|frame| {
+                let position = frame.source_position();
+                StackFrameInfo {
+                    function: frame.function_name.clone(),
+                    pc: frame.pc,
+                    line: position.map(|(line, _)| line),
+                    column: position.map(|(_, column)| column),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The string `key` names a property by: a symbol's key for symbols, and
+/// the key converted to a string otherwise
+pub(crate) fn property_key(key: &Value) -> String {
+    match key {
+        Value::Symbol(symbol) => symbol.property_key(),
+        _ => key.to_string(),
+    }
+}
+
+/// The array index `key` names: an integer in `0..2^32 - 1`, as a number or
+/// in its canonical string form. `name` is the key's `property_key`, so
+/// string keys are not converted again, and names that cannot be indices
+/// are not parsed.
+fn array_index(key: &Value, name: &str) -> Option<usize> {
+    match key {
+        Value::Number(n) if n.fract() == 0.0 && (0.0..4_294_967_295.0).contains(n) => Some(*n as usize),
+        Value::String(_) if name.starts_with(|c: char| c.is_ascii_digit()) => {
+            let index: u32 = name.parse().ok()?;
+            (index != u32::MAX && index.to_string() == name).then_some(index as usize)
+        }
+        _ => None,
+    }
 }
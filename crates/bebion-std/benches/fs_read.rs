@@ -0,0 +1,52 @@
+//! Large-file read throughput of the `fs` module: `readFileSync` decoding
+//! a string, `readBytesSync` handing its read buffer to a `Uint8Array`
+//! whole, and `readIntoSync` refilling one preallocated buffer in place.
+//!
+//! Run with `cargo bench -p bebion-std --bench fs_read`.
+
+use bebion_gc::GarbageCollector;
+use bebion_runtime::{Runtime, Value};
+use bebion_std::fs::FileSystemModule;
+use bebion_std::Module;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+const FILE_SIZE: usize = 64 * 1024 * 1024;
+const ITERATIONS: u32 = 10;
+
+fn main() {
+    let path = std::env::temp_dir().join(format!("bebion-fs-read-{}.bin", std::process::id()));
+    let contents: Vec<u8> = (0..FILE_SIZE).map(|i| b'a' + (i % 26) as u8).collect();
+    std::fs::write(&path, contents).expect("write benchmark file");
+    let path_value = Value::from(path.display().to_string());
+
+    let mut runtime = Runtime::new(Arc::new(Mutex::new(GarbageCollector::new())));
+    let exports = FileSystemModule::new().get_exports();
+    let function = |name: &str| match &exports[name] {
+        Value::NativeFunction(function) => function.clone(),
+        _ => panic!("fs.{} is not a function", name),
+    };
+
+    for name in ["readFileSync", "readBytesSync", "readIntoSync"] {
+        let read = function(name);
+        // Made just before use, as host-held values are not GC roots
+        let args = match name {
+            "readIntoSync" => vec![path_value.clone(), runtime.create_array_buffer(vec![0; FILE_SIZE])],
+            _ => vec![path_value.clone()],
+        };
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            read.call(&mut runtime, &args).expect("read benchmark file");
+        }
+        let elapsed = start.elapsed();
+        let megabytes = (FILE_SIZE as f64 * ITERATIONS as f64) / (1024.0 * 1024.0);
+        println!(
+            "{:<14} {:>8.1} MiB/s ({:?} per read)",
+            name,
+            megabytes / elapsed.as_secs_f64(),
+            elapsed / ITERATIONS
+        );
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,1181 @@
+//! Archive module
+//!
+//! Reads and writes .zip and .tar.gz archives. Both readers stream: a tar
+//! archive is decompressed and read one entry at a time, and a zip entry is
+//! inflated as its data is read, so neither has to fit in memory. Zip
+//! archives are written with data descriptors after each entry, so the
+//! writer only needs a `Write` and can feed a socket or a gzip stream.
+//!
+//! Extraction refuses entries whose names are absolute or climb out of the
+//! target directory with `..`, and link entries, whose targets could point
+//! anywhere. Symbolic links are likewise left out when packing a directory.
+
+use crate::glob::{self, WalkOptions};
+use crate::{Module, Value};
+use bebion_runtime::clock::{civil_from_days, days_from_civil};
+use bebion_runtime::{NativeFunction, Runtime, RuntimeError, RuntimeResult};
+use flate2::read::{DeflateDecoder, GzDecoder};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BLOCK_SIZE: u64 = 512;
+
+/// Longest GNU long name or PAX header a tar reader loads into memory
+const MAX_METADATA_SIZE: u64 = 1 << 20;
+
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const ZIP_DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP_END_OF_DIRECTORY: u32 = 0x0605_4b50;
+
+/// Zip "version made by": Unix, format 2.0, so readers use the mode bits
+const ZIP_MADE_BY_UNIX: u16 = 3 << 8 | 20;
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFREG: u32 = 0o100_000;
+const S_IFLNK: u32 = 0o120_000;
+
+/// The archive formats the module reads and writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// The format called `name`: "zip", or "tar.gz" or "tgz"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "zip" => Some(Self::Zip),
+            "tar.gz" | "tgz" => Some(Self::TarGz),
+            _ => None,
+        }
+    }
+
+    /// The format a file name's extension stands for
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else {
+            None
+        }
+    }
+
+    /// The format of an archive starting with `magic`
+    pub fn detect(magic: &[u8]) -> Option<Self> {
+        match magic {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => Some(Self::Zip),
+            [0x1f, 0x8b, ..] => Some(Self::TarGz),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// A symbolic or hard link, which is listed but never extracted
+    Link,
+}
+
+impl EntryKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryKind::File => "file",
+            EntryKind::Directory => "directory",
+            EntryKind::Link => "link",
+        }
+    }
+}
+
+/// What an archive records about an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    /// The `/`-separated path in the archive, without a trailing slash
+    pub name: String,
+    pub kind: EntryKind,
+    /// Bytes of data, 0 for directories
+    pub size: u64,
+    /// Unix permission bits
+    pub mode: u32,
+    /// Modification time in seconds since the epoch
+    pub mtime: u64,
+}
+
+impl EntryInfo {
+    /// A file of `size` bytes, readable by everyone, modified now
+    pub fn file(name: &str, size: u64) -> Self {
+        Self { name: name.to_string(), kind: EntryKind::File, size, mode: 0o644, mtime: now() }
+    }
+
+    pub fn directory(name: &str) -> Self {
+        Self { name: name.to_string(), kind: EntryKind::Directory, size: 0, mode: 0o755, mtime: now() }
+    }
+
+    /// The name with a trailing slash for a directory, as archives store it
+    fn stored_name(&self) -> String {
+        match self.kind {
+            EntryKind::Directory => format!("{}/", self.name),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+/// An entry held in memory, for `pack` and `unpack`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryEntry {
+    pub info: EntryInfo,
+    pub data: Vec<u8>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn unsupported(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message.into())
+}
+
+/// Read exactly `size` bytes of entry data from `data` into `out`
+fn copy_entry_data(data: &mut dyn Read, size: u64, out: &mut dyn Write) -> io::Result<()> {
+    let copied = io::copy(&mut data.take(size), out)?;
+    if copied != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("entry data ended after {} of {} bytes", copied, size),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads a tar stream one entry at a time. `next_entry` moves to the next
+/// entry, and reading from the reader reads the current entry's data.
+pub struct TarReader<R: Read> {
+    inner: R,
+    /// Data left to read in the current entry, and the padding after it
+    remaining: u64,
+    padding: u64,
+    finished: bool,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, remaining: 0, padding: 0, finished: false }
+    }
+
+    /// Move to the next entry, skipping what is left of the current one.
+    /// `None` at the end of the archive. GNU long names and PAX paths are
+    /// applied to the entry they precede, and device and FIFO entries are
+    /// skipped.
+    pub fn next_entry(&mut self) -> io::Result<Option<EntryInfo>> {
+        if self.finished {
+            return Ok(None);
+        }
+        self.skip(self.remaining + self.padding)?;
+        self.remaining = 0;
+        self.padding = 0;
+
+        let mut long_name = None;
+        loop {
+            let mut header = [0u8; BLOCK_SIZE as usize];
+            if !self.read_header(&mut header)? || header.iter().all(|&byte| byte == 0) {
+                self.finished = true;
+                return Ok(None);
+            }
+            if tar_checksum(&header) != parse_octal(&header[148..156])? {
+                return Err(invalid_data("tar header checksum mismatch"));
+            }
+
+            let size = parse_octal(&header[124..136])?;
+            let padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+            let kind = match header[156] {
+                b'L' | b'x' => {
+                    let data = self.read_metadata(size, padding)?;
+                    if header[156] == b'L' {
+                        long_name = Some(String::from_utf8_lossy(&data).trim_end_matches('\0').to_string());
+                    } else if let Some(path) = pax_path(&data) {
+                        long_name = Some(path);
+                    }
+                    continue;
+                }
+                b'0' | b'\0' | b'7' => EntryKind::File,
+                b'5' => EntryKind::Directory,
+                b'1' | b'2' => EntryKind::Link,
+                _ => {
+                    self.skip(size + padding)?;
+                    continue;
+                }
+            };
+
+            let name = match long_name {
+                Some(name) => name,
+                None => {
+                    let name = field_str(&header[0..100]);
+                    let prefix = if &header[257..262] == b"ustar" { field_str(&header[345..500]) } else { String::new() };
+                    if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) }
+                }
+            };
+            // Only files expose their data; whatever a link carries is
+            // skipped with the padding
+            let (size, skipped) = if kind == EntryKind::File { (size, 0) } else { (0, size) };
+            self.remaining = size;
+            self.padding = skipped + padding;
+            return Ok(Some(EntryInfo {
+                name: name.trim_end_matches('/').to_string(),
+                kind,
+                size,
+                mode: parse_octal(&header[100..108])? as u32 & 0o7777,
+                mtime: parse_octal(&header[136..148])?,
+            }));
+        }
+    }
+
+    /// Fill `header` with the next block, or return false if the stream
+    /// ends cleanly before it
+    fn read_header(&mut self, header: &mut [u8]) -> io::Result<bool> {
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.inner.read(&mut header[filled..])? {
+                0 if filled == 0 => return Ok(false),
+                0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "tar archive ends inside a header")),
+                read => filled += read,
+            }
+        }
+        Ok(true)
+    }
+
+    /// The data of a long name or PAX header entry
+    fn read_metadata(&mut self, size: u64, padding: u64) -> io::Result<Vec<u8>> {
+        if size > MAX_METADATA_SIZE {
+            return Err(invalid_data("tar extended header is too large"));
+        }
+        let mut data = vec![0; size as usize];
+        self.inner.read_exact(&mut data)?;
+        self.skip(padding)?;
+        Ok(data)
+    }
+
+    fn skip(&mut self, bytes: u64) -> io::Result<()> {
+        if io::copy(&mut (&mut self.inner).take(bytes), &mut io::sink())? != bytes {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "tar archive ends inside an entry"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for TarReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let limit = buffer.len().min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        if limit == 0 {
+            return Ok(0);
+        }
+        let read = self.inner.read(&mut buffer[..limit])?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "tar archive ends inside an entry"));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+/// A NUL-terminated header field as text
+fn field_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&byte| byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// A numeric header field: octal digits, or big-endian base 256 when the
+/// first byte's high bit is set
+fn parse_octal(field: &[u8]) -> io::Result<u64> {
+    if field.first().is_some_and(|&byte| byte & 0x80 != 0) {
+        return Ok(field[1..].iter().fold(u64::from(field[0] & 0x7f), |value, &byte| value << 8 | u64::from(byte)));
+    }
+    let digits = field_str(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid_data(format!("invalid tar header number '{}'", digits)))
+}
+
+/// The sum of a header's bytes with its checksum field read as spaces
+fn tar_checksum(header: &[u8]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(index, &byte)| if (148..156).contains(&index) { u64::from(b' ') } else { u64::from(byte) })
+        .sum()
+}
+
+/// The `path` record of a PAX extended header
+fn pax_path(data: &[u8]) -> Option<String> {
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&byte| byte == b' ')?;
+        let length: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..length)?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            return Some(String::from_utf8_lossy(path.strip_suffix(b"\n").unwrap_or(path)).into_owned());
+        }
+        rest = &rest[length..];
+    }
+    None
+}
+
+/// Writes a tar stream in ustar format, with GNU long name entries for
+/// names ustar cannot hold
+pub struct TarWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Add an entry, copying `info.size` bytes of data from `data` for a
+    /// file
+    pub fn append(&mut self, info: &EntryInfo, data: &mut dyn Read) -> io::Result<()> {
+        let typeflag = match info.kind {
+            EntryKind::File => b'0',
+            EntryKind::Directory => b'5',
+            EntryKind::Link => return Err(unsupported("links cannot be added to an archive")),
+        };
+        let name = info.stored_name();
+        let size = if info.kind == EntryKind::File { info.size } else { 0 };
+
+        let header = match split_ustar_name(name.as_bytes()) {
+            Some((prefix, short)) => tar_header(short, prefix, typeflag, info.mode, size, info.mtime),
+            None => {
+                let mut long_name = name.clone().into_bytes();
+                long_name.push(0);
+                let header = tar_header(b"././@LongLink", b"", b'L', 0o644, long_name.len() as u64, 0);
+                self.inner.write_all(&header)?;
+                self.write_padded(&long_name)?;
+                tar_header(&name.as_bytes()[..100], b"", typeflag, info.mode, size, info.mtime)
+            }
+        };
+        self.inner.write_all(&header)?;
+        copy_entry_data(data, size, &mut self.inner)?;
+        self.pad(size)
+    }
+
+    /// End the archive, returning the writer it went to
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&[0; 2 * BLOCK_SIZE as usize])?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_padded(&mut self, data: &[u8]) -> io::Result<()> {
+        self.inner.write_all(data)?;
+        self.pad(data.len() as u64)
+    }
+
+    fn pad(&mut self, size: u64) -> io::Result<()> {
+        let padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+        self.inner.write_all(&[0; BLOCK_SIZE as usize][..padding as usize])
+    }
+}
+
+/// Split `name` into a ustar prefix and name, if it fits the two fields
+fn split_ustar_name(name: &[u8]) -> Option<(&[u8], &[u8])> {
+    if name.len() <= 100 {
+        return Some((b"", name));
+    }
+    name.iter()
+        .enumerate()
+        .filter(|&(index, &byte)| byte == b'/' && index <= 155)
+        .map(|(index, _)| (&name[..index], &name[index + 1..]))
+        .find(|(_, rest)| !rest.is_empty() && rest.len() <= 100)
+}
+
+fn tar_header(name: &[u8], prefix: &[u8], typeflag: u8, mode: u32, size: u64, mtime: u64) -> [u8; BLOCK_SIZE as usize] {
+    let mut header = [0u8; BLOCK_SIZE as usize];
+    header[..name.len()].copy_from_slice(name);
+    write_octal(&mut header[100..108], u64::from(mode & 0o7777));
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], mtime);
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix);
+
+    let checksum = format!("{:06o}\0 ", tar_checksum(&header));
+    header[148..156].copy_from_slice(checksum.as_bytes());
+    header
+}
+
+/// Fill a numeric field with zero-padded octal and a NUL, or base 256 if
+/// the value has too many digits
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if value < 1 << (3 * digits) {
+        let octal = format!("{:0width$o}\0", value, width = digits);
+        field.copy_from_slice(octal.as_bytes());
+    } else {
+        field.fill(0);
+        for (index, byte) in value.to_be_bytes().iter().rev().enumerate().take(field.len() - 1) {
+            field[field.len() - 1 - index] = *byte;
+        }
+        field[0] |= 0x80;
+    }
+}
+
+struct ZipEntry {
+    info: EntryInfo,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    header_offset: u64,
+}
+
+/// Reads a zip archive's central directory up front, then the data of any
+/// entry on demand
+pub struct ZipReader<R: Read + Seek> {
+    inner: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl<R: Read + Seek> ZipReader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        // The end of central directory record is in the last 22 bytes
+        // plus a comment of up to 64K
+        let length = inner.seek(SeekFrom::End(0))?;
+        let tail_length = length.min(22 + 0xffff);
+        inner.seek(SeekFrom::Start(length - tail_length))?;
+        let mut tail = vec![0; tail_length as usize];
+        inner.read_exact(&mut tail)?;
+        let end = (0..=tail.len().saturating_sub(22))
+            .rev()
+            .find(|&at| u32_at(&tail, at) == ZIP_END_OF_DIRECTORY)
+            .ok_or_else(|| invalid_data("not a zip archive"))?;
+        let end = &tail[end..];
+
+        let count = u16_at(end, 10);
+        let directory_size = u32_at(end, 12);
+        let directory_offset = u32_at(end, 16);
+        if count == 0xffff || directory_offset == 0xffff_ffff {
+            return Err(unsupported("zip64 archives are not supported"));
+        }
+
+        inner.seek(SeekFrom::Start(u64::from(directory_offset)))?;
+        let mut directory = vec![0; directory_size as usize];
+        inner.read_exact(&mut directory)?;
+
+        let mut entries = Vec::with_capacity(usize::from(count));
+        let mut at = 0;
+        for _ in 0..count {
+            if directory.len() < at + 46 || u32_at(&directory, at) != ZIP_CENTRAL_HEADER {
+                return Err(invalid_data("corrupt zip central directory"));
+            }
+            let record = &directory[at..];
+            let name_length = usize::from(u16_at(record, 28));
+            let extra_length = usize::from(u16_at(record, 30));
+            let comment_length = usize::from(u16_at(record, 32));
+            let name = record
+                .get(46..46 + name_length)
+                .ok_or_else(|| invalid_data("corrupt zip central directory"))?;
+            let name = String::from_utf8_lossy(name).into_owned();
+
+            let external = u32_at(record, 38);
+            let unix_mode = (u16_at(record, 4) >> 8 == 3).then_some(external >> 16).filter(|&mode| mode != 0);
+            let kind = match unix_mode.map(|mode| mode & S_IFMT) {
+                Some(S_IFLNK) => EntryKind::Link,
+                Some(S_IFDIR) => EntryKind::Directory,
+                _ if name.ends_with('/') || external & 0x10 != 0 => EntryKind::Directory,
+                _ => EntryKind::File,
+            };
+            let default_mode = if kind == EntryKind::Directory { 0o755 } else { 0o644 };
+            let size = if kind == EntryKind::File { u64::from(u32_at(record, 24)) } else { 0 };
+
+            entries.push(ZipEntry {
+                info: EntryInfo {
+                    name: name.trim_end_matches('/').to_string(),
+                    kind,
+                    size,
+                    mode: unix_mode.map_or(default_mode, |mode| mode & 0o7777),
+                    mtime: unix_time(u16_at(record, 12), u16_at(record, 14)),
+                },
+                method: u16_at(record, 10),
+                crc: u32_at(record, 16),
+                compressed_size: u64::from(u32_at(record, 20)),
+                header_offset: u64::from(u32_at(record, 42)),
+            });
+            at += 46 + name_length + extra_length + comment_length;
+        }
+
+        Ok(Self { inner, entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entries in the order the central directory lists them
+    pub fn entries(&self) -> impl Iterator<Item = &EntryInfo> {
+        self.entries.iter().map(|entry| &entry.info)
+    }
+
+    /// A reader of the data of entry `index`, inflated and checked against
+    /// its CRC as it is read
+    pub fn open(&mut self, index: usize) -> io::Result<Box<dyn Read + '_>> {
+        let entry = self.entries.get(index).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such zip entry"))?;
+        if entry.info.kind != EntryKind::File {
+            return Ok(Box::new(io::empty()));
+        }
+
+        self.inner.seek(SeekFrom::Start(entry.header_offset))?;
+        let mut header = [0u8; 30];
+        self.inner.read_exact(&mut header)?;
+        if u32_at(&header, 0) != ZIP_LOCAL_HEADER {
+            return Err(invalid_data("corrupt zip local header"));
+        }
+        let skip = i64::from(u16_at(&header, 26)) + i64::from(u16_at(&header, 28));
+        self.inner.seek(SeekFrom::Current(skip))?;
+
+        let compressed = (&mut self.inner).take(entry.compressed_size);
+        let data: Box<dyn Read + '_> = match entry.method {
+            0 => Box::new(compressed),
+            8 => Box::new(DeflateDecoder::new(compressed)),
+            method => return Err(unsupported(format!("zip compression method {} is not supported", method))),
+        };
+        Ok(Box::new(CrcReader {
+            inner: data,
+            hasher: crc32fast::Hasher::new(),
+            expected_crc: entry.crc,
+            expected_size: entry.info.size,
+            size: 0,
+        }))
+    }
+}
+
+/// Checks the data read through it against a CRC-32 and size once it ends
+struct CrcReader<R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+    expected_crc: u32,
+    expected_size: u64,
+    size: u64,
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buffer)?;
+        self.hasher.update(&buffer[..read]);
+        self.size += read as u64;
+        if read == 0
+            && !buffer.is_empty()
+            && (self.size != self.expected_size || self.hasher.clone().finalize() != self.expected_crc)
+        {
+            return Err(invalid_data("zip entry is corrupt: its CRC or size does not match"));
+        }
+        Ok(read)
+    }
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// Counts the bytes written through it, for the offsets a zip records
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buffer)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes a zip archive, deflating files. Sizes and CRCs follow each entry
+/// in a data descriptor, so nothing is written twice.
+pub struct ZipWriter<W: Write> {
+    inner: CountingWriter<W>,
+    central_directory: Vec<u8>,
+    count: u16,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner: CountingWriter { inner, written: 0 }, central_directory: Vec::new(), count: 0 }
+    }
+
+    /// Add an entry, copying `info.size` bytes of data from `data` for a
+    /// file
+    pub fn append(&mut self, info: &EntryInfo, data: &mut dyn Read) -> io::Result<()> {
+        let file_type = match info.kind {
+            EntryKind::File => S_IFREG,
+            EntryKind::Directory => S_IFDIR,
+            EntryKind::Link => return Err(unsupported("links cannot be added to an archive")),
+        };
+        if self.count == 0xffff {
+            return Err(unsupported("zip archives hold at most 65535 entries without zip64"));
+        }
+        let offset = u32::try_from(self.inner.written).map_err(|_| unsupported("zip64 archives are not supported"))?;
+        let name = info.stored_name();
+        let name_length = u16::try_from(name.len()).map_err(|_| invalid_data("entry name is too long"))?;
+        let method: u16 = if info.kind == EntryKind::File { 8 } else { 0 };
+        // Sizes in a data descriptor, UTF-8 names
+        let flags: u16 = 1 << 3 | 1 << 11;
+        let (time, date) = dos_date_time(info.mtime);
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&ZIP_LOCAL_HEADER.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes());
+        header.extend_from_slice(&flags.to_le_bytes());
+        header.extend_from_slice(&method.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&name_length.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.inner.write_all(&header)?;
+
+        let start = self.inner.written;
+        let mut hasher = crc32fast::Hasher::new();
+        if info.kind == EntryKind::File {
+            let mut encoder = DeflateEncoder::new(&mut self.inner, Compression::default());
+            copy_entry_data(data, info.size, &mut HashingWriter { inner: &mut encoder, hasher: &mut hasher })?;
+            encoder.finish()?;
+        }
+        let crc = hasher.finalize();
+        let too_large = || unsupported("zip64 archives are not supported");
+        let compressed_size = u32::try_from(self.inner.written - start).map_err(|_| too_large())?;
+        let size = u32::try_from(if info.kind == EntryKind::File { info.size } else { 0 }).map_err(|_| too_large())?;
+
+        let mut descriptor = Vec::with_capacity(16);
+        descriptor.extend_from_slice(&ZIP_DATA_DESCRIPTOR.to_le_bytes());
+        descriptor.extend_from_slice(&crc.to_le_bytes());
+        descriptor.extend_from_slice(&compressed_size.to_le_bytes());
+        descriptor.extend_from_slice(&size.to_le_bytes());
+        self.inner.write_all(&descriptor)?;
+
+        // Unix type and mode in the high half, the MS-DOS directory bit in
+        // the low one
+        let dos_attributes = if info.kind == EntryKind::Directory { 0x10 } else { 0 };
+        let external = (file_type | (info.mode & 0o7777)) << 16 | dos_attributes;
+        let record = &mut self.central_directory;
+        record.extend_from_slice(&ZIP_CENTRAL_HEADER.to_le_bytes());
+        record.extend_from_slice(&ZIP_MADE_BY_UNIX.to_le_bytes());
+        record.extend_from_slice(&20u16.to_le_bytes());
+        record.extend_from_slice(&flags.to_le_bytes());
+        record.extend_from_slice(&method.to_le_bytes());
+        record.extend_from_slice(&time.to_le_bytes());
+        record.extend_from_slice(&date.to_le_bytes());
+        record.extend_from_slice(&crc.to_le_bytes());
+        record.extend_from_slice(&compressed_size.to_le_bytes());
+        record.extend_from_slice(&size.to_le_bytes());
+        record.extend_from_slice(&name_length.to_le_bytes());
+        // Extra field and comment lengths, disk number, internal attributes
+        record.extend_from_slice(&[0; 8]);
+        record.extend_from_slice(&external.to_le_bytes());
+        record.extend_from_slice(&offset.to_le_bytes());
+        record.extend_from_slice(name.as_bytes());
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Write the central directory, returning the writer the archive went
+    /// to
+    pub fn finish(mut self) -> io::Result<W> {
+        let too_large = || unsupported("zip64 archives are not supported");
+        let directory_offset = u32::try_from(self.inner.written).map_err(|_| too_large())?;
+        let directory_size = u32::try_from(self.central_directory.len()).map_err(|_| too_large())?;
+        self.inner.write_all(&self.central_directory)?;
+
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&ZIP_END_OF_DIRECTORY.to_le_bytes());
+        end.extend_from_slice(&[0; 4]);
+        end.extend_from_slice(&self.count.to_le_bytes());
+        end.extend_from_slice(&self.count.to_le_bytes());
+        end.extend_from_slice(&directory_size.to_le_bytes());
+        end.extend_from_slice(&directory_offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.inner.write_all(&end)?;
+        self.inner.flush()?;
+        Ok(self.inner.inner)
+    }
+}
+
+/// Feeds what is written through it to a CRC-32
+struct HashingWriter<'a, W> {
+    inner: &'a mut W,
+    hasher: &'a mut crc32fast::Hasher,
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buffer)?;
+        self.hasher.update(&buffer[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// MS-DOS time and date fields for `mtime`, clamped to the 1980-2107 range
+/// they can hold
+fn dos_date_time(mtime: u64) -> (u16, u16) {
+    let (year, month, day) = civil_from_days((mtime / 86_400) as i64);
+    let seconds = mtime % 86_400;
+    match year {
+        ..=1979 => (0, 1 << 5 | 1),
+        2108.. => (23 << 11 | 59 << 5 | 29, 127 << 9 | 12 << 5 | 31),
+        _ => (
+            ((seconds / 3600) << 11 | (seconds % 3600 / 60) << 5 | (seconds % 60 / 2)) as u16,
+            ((year - 1980) << 9 | i64::from(month) << 5 | i64::from(day)) as u16,
+        ),
+    }
+}
+
+/// Seconds since the epoch for MS-DOS time and date fields
+fn unix_time(time: u16, date: u16) -> u64 {
+    let year = 1980 + i64::from(date >> 9);
+    let month = u32::from(date >> 5 & 0xf).clamp(1, 12);
+    let day = u32::from(date & 0x1f).max(1);
+    let seconds = u64::from(time >> 11) * 3600 + u64::from(time >> 5 & 0x3f) * 60 + u64::from(time & 0x1f) * 2;
+    days_from_civil(year, month, day) as u64 * 86_400 + seconds
+}
+
+/// Call `visit` with each entry of the archive `source` and a reader of its
+/// data, in archive order, until it returns false. The format is detected
+/// from the archive's first bytes.
+pub fn for_each_entry<R: Read + Seek>(
+    mut source: R,
+    mut visit: impl FnMut(&EntryInfo, &mut dyn Read) -> io::Result<bool>,
+) -> io::Result<()> {
+    let mut magic = [0u8; 4];
+    let read = source.read(&mut magic)?;
+    source.seek(SeekFrom::Start(0))?;
+
+    match ArchiveFormat::detect(&magic[..read]) {
+        Some(ArchiveFormat::Zip) => {
+            let mut zip = ZipReader::new(source)?;
+            for index in 0..zip.len() {
+                let info = zip.entries[index].info.clone();
+                if !visit(&info, &mut zip.open(index)?)? {
+                    break;
+                }
+            }
+        }
+        Some(ArchiveFormat::TarGz) => {
+            let mut tar = TarReader::new(GzDecoder::new(source));
+            while let Some(info) = tar.next_entry()? {
+                if !visit(&info, &mut tar)? {
+                    break;
+                }
+            }
+        }
+        None => return Err(invalid_data("not a zip or gzipped tar archive")),
+    }
+    Ok(())
+}
+
+/// Where entry `name` goes under `dest`. Fails for names that are absolute
+/// or contain `..`, which could reach outside `dest`.
+pub fn entry_path(dest: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut path = dest.to_path_buf();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(invalid_data(format!("refusing to extract '{}' outside the target directory", name)));
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// The entries of the archive at `path`
+pub fn list(path: &Path) -> io::Result<Vec<EntryInfo>> {
+    let mut entries = Vec::new();
+    for_each_entry(BufReader::new(File::open(path)?), |info, _| {
+        entries.push(info.clone());
+        Ok(true)
+    })?;
+    Ok(entries)
+}
+
+/// Extract the archive at `path` into `dest`, creating it if needed, and
+/// return the names extracted. Stops at the first entry that is a link or
+/// would land outside `dest`; the entries before it stay extracted.
+pub fn extract(path: &Path, dest: &Path) -> io::Result<Vec<String>> {
+    fs::create_dir_all(dest)?;
+    let mut extracted = Vec::new();
+    for_each_entry(BufReader::new(File::open(path)?), |info, data| {
+        if info.kind == EntryKind::Link {
+            return Err(invalid_data(format!("refusing to extract link '{}'", info.name)));
+        }
+        let target = entry_path(dest, &info.name)?;
+        if info.kind == EntryKind::Directory {
+            fs::create_dir_all(&target)?;
+        } else {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = BufWriter::new(File::create(&target)?);
+            io::copy(data, &mut file)?;
+            file.flush()?;
+            set_mode(&target, info.mode)?;
+        }
+        extracted.push(info.name.clone());
+        Ok(true)
+    })?;
+    Ok(extracted)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    // Never extract setuid, setgid or sticky files
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// The data of entry `name` of the archive at `path`, or `None` if it has
+/// no such file. A tar archive is read only as far as the entry.
+pub fn read_entry(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    let mut found = None;
+    for_each_entry(BufReader::new(File::open(path)?), |info, data| {
+        if info.kind != EntryKind::File || info.name != name {
+            return Ok(true);
+        }
+        let mut bytes = Vec::with_capacity(info.size.min(1 << 24) as usize);
+        data.read_to_end(&mut bytes)?;
+        found = Some(bytes);
+        Ok(false)
+    })?;
+    Ok(found)
+}
+
+/// Writes either format, so directory and memory packing share one path
+enum ArchiveWriter<W: Write> {
+    Zip(ZipWriter<W>),
+    TarGz(TarWriter<GzEncoder<W>>),
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    fn new(inner: W, format: ArchiveFormat) -> Self {
+        match format {
+            ArchiveFormat::Zip => Self::Zip(ZipWriter::new(inner)),
+            ArchiveFormat::TarGz => Self::TarGz(TarWriter::new(GzEncoder::new(inner, Compression::default()))),
+        }
+    }
+
+    fn append(&mut self, info: &EntryInfo, data: &mut dyn Read) -> io::Result<()> {
+        match self {
+            Self::Zip(zip) => zip.append(info, data),
+            Self::TarGz(tar) => tar.append(info, data),
+        }
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Zip(zip) => zip.finish(),
+            Self::TarGz(tar) => tar.finish()?.finish(),
+        }
+    }
+}
+
+/// Archive everything below `root` into a new archive at `path`, returning
+/// how many entries it holds. Names are relative to `root`; symbolic links
+/// are left out.
+pub fn create(path: &Path, root: &Path, format: ArchiveFormat) -> io::Result<usize> {
+    let options = WalkOptions { dot: true, directories: true, ..WalkOptions::default() };
+    let mut writer = ArchiveWriter::new(BufWriter::new(File::create(path)?), format);
+    let mut count = 0;
+    for entry in glob::walk(root, &options)? {
+        let metadata = fs::symlink_metadata(&entry.path)?;
+        if metadata.file_type().is_symlink() {
+            continue;
+        }
+        let mtime = metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        let mut info = if entry.is_dir {
+            EntryInfo::directory(&entry.relative)
+        } else {
+            EntryInfo::file(&entry.relative, metadata.len())
+        };
+        info.mtime = mtime;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            info.mode = metadata.permissions().mode() & 0o7777;
+        }
+
+        if entry.is_dir {
+            writer.append(&info, &mut io::empty())?;
+        } else {
+            writer.append(&info, &mut BufReader::new(File::open(&entry.path)?))?;
+        }
+        count += 1;
+    }
+    writer.finish()?.flush()?;
+    Ok(count)
+}
+
+/// An archive in `format` holding `entries`
+pub fn pack(entries: &[MemoryEntry], format: ArchiveFormat) -> io::Result<Vec<u8>> {
+    let mut writer = ArchiveWriter::new(Vec::new(), format);
+    for entry in entries {
+        let info = EntryInfo { size: entry.data.len() as u64, ..entry.info.clone() };
+        writer.append(&info, &mut entry.data.as_slice())?;
+    }
+    writer.finish()
+}
+
+/// Every entry of the archive `bytes`, with its data
+pub fn unpack(bytes: &[u8]) -> io::Result<Vec<MemoryEntry>> {
+    let mut entries = Vec::new();
+    for_each_entry(Cursor::new(bytes), |info, data| {
+        let mut bytes = Vec::with_capacity(info.size.min(1 << 24) as usize);
+        data.read_to_end(&mut bytes)?;
+        entries.push(MemoryEntry { info: info.clone(), data: bytes });
+        Ok(true)
+    })?;
+    Ok(entries)
+}
+
+type ArchiveBinding = fn(&mut Runtime, &[Value]) -> RuntimeResult<Value>;
+
+pub struct ArchiveModule {
+    exports: HashMap<String, Value>,
+}
+
+impl ArchiveModule {
+    pub fn new() -> Self {
+        let bindings: [(&str, ArchiveBinding); 6] = [
+            // `list(path)`: `{ name, type, size, mode, mtimeMs }` for each
+            // entry
+            ("list", |runtime, args| {
+                let entries = list(Path::new(&string_argument(args, 0, "path")?)).map_err(archive_error)?;
+                let entries = entries.iter().map(|info| entry_value(runtime, info, None)).collect();
+                Ok(runtime.create_array(entries))
+            }),
+            // `extract(path, dest)`: the names extracted
+            ("extract", |runtime, args| {
+                let path = string_argument(args, 0, "path")?;
+                let dest = string_argument(args, 1, "dest")?;
+                let names = extract(Path::new(&path), Path::new(&dest)).map_err(archive_error)?;
+                Ok(runtime.create_array(names.into_iter().map(Value::from).collect()))
+            }),
+            // `create(path, dir, { format })`: archive a directory, in the
+            // format the path's extension names unless one is given
+            ("create", |runtime, args| {
+                let path = PathBuf::from(string_argument(args, 0, "path")?);
+                let root = PathBuf::from(string_argument(args, 1, "dir")?);
+                let format = match format_option(runtime, args.get(2))? {
+                    Some(format) => format,
+                    None => ArchiveFormat::from_path(&path).ok_or_else(|| {
+                        RuntimeError::TypeError(
+                            "Cannot tell the archive format from the path; pass { format: \"zip\" | \"tar.gz\" }".to_string(),
+                        )
+                    })?,
+                };
+                let count = create(&path, &root, format).map_err(archive_error)?;
+                Ok(Value::Number(count as f64))
+            }),
+            // `readEntry(path, name)`: the entry's data as a Uint8Array, or
+            // undefined
+            ("readEntry", |runtime, args| {
+                let path = string_argument(args, 0, "path")?;
+                let name = string_argument(args, 1, "name")?;
+                match read_entry(Path::new(&path), &name).map_err(archive_error)? {
+                    Some(bytes) => Ok(runtime.create_uint8_array(bytes)),
+                    None => Ok(Value::Undefined),
+                }
+            }),
+            // `pack(entries, { format })`: an archive, zip by default, as a
+            // Uint8Array. Entries are `{ name, data, directory, mode }`,
+            // with data a string or buffer.
+            ("pack", |runtime, args| {
+                let list = args.first().cloned().unwrap_or(Value::Undefined);
+                let values = runtime.array_elements(&list).ok_or_else(|| {
+                    RuntimeError::TypeError("The \"entries\" argument must be an array".to_string())
+                })?;
+                let mtime = (runtime.clock().now() / 1000.0) as u64;
+                let entries = values
+                    .iter()
+                    .map(|value| memory_entry(runtime, value, mtime))
+                    .collect::<RuntimeResult<Vec<_>>>()?;
+                let format = format_option(runtime, args.get(1))?.unwrap_or(ArchiveFormat::Zip);
+                let bytes = pack(&entries, format).map_err(archive_error)?;
+                Ok(runtime.create_uint8_array(bytes))
+            }),
+            // `unpack(buffer)`: each entry as from `list`, plus its `data`
+            // as a Uint8Array
+            ("unpack", |runtime, args| {
+                let buffer = args.first().cloned().unwrap_or(Value::Undefined);
+                let bytes = runtime.with_buffer_bytes_mut(&buffer, |bytes| bytes.to_vec()).ok_or_else(|| {
+                    RuntimeError::TypeError(
+                        "The \"buffer\" argument must be an ArrayBuffer, typed array or DataView".to_string(),
+                    )
+                })?;
+                let entries = unpack(&bytes).map_err(archive_error)?;
+                let entries = entries
+                    .into_iter()
+                    .map(|entry| entry_value(runtime, &entry.info, Some(entry.data)))
+                    .collect();
+                Ok(runtime.create_array(entries))
+            }),
+        ];
+
+        // Like `fs`, each operation is exported as `nameSync`, returning its
+        // result, and as `name`, returning a promise settled with it
+        let mut exports = HashMap::new();
+        for (name, binding) in bindings {
+            let sync_name = format!("{}Sync", name);
+            let sync = NativeFunction::new(sync_name.as_str(), binding);
+            exports.insert(sync_name, Value::NativeFunction(sync));
+
+            let promise = NativeFunction::new(name, move |runtime, args| {
+                let outcome = binding(runtime, args);
+                let promise = runtime.create_promise();
+                match outcome {
+                    Ok(value) => runtime.resolve_promise(promise, value),
+                    Err(error) => runtime.reject_promise(promise, Value::from(error.to_string())),
+                }
+                Ok(Value::Object(promise))
+            });
+            exports.insert(name.to_string(), Value::NativeFunction(promise));
+        }
+
+        Self { exports }
+    }
+}
+
+/// An entry as scripts see it, with its data if it was read
+fn entry_value(runtime: &mut Runtime, info: &EntryInfo, data: Option<Vec<u8>>) -> Value {
+    let mut properties = HashMap::from([
+        ("name".to_string(), Value::from(info.name.as_str())),
+        ("type".to_string(), Value::from(info.kind.as_str())),
+        ("size".to_string(), Value::Number(info.size as f64)),
+        ("mode".to_string(), Value::Number(f64::from(info.mode))),
+        ("mtimeMs".to_string(), Value::Number(info.mtime as f64 * 1000.0)),
+    ]);
+    if let Some(data) = data {
+        properties.insert("data".to_string(), runtime.create_uint8_array(data));
+    }
+    runtime.create_object(properties)
+}
+
+/// A `pack` entry object as a `MemoryEntry`, modified at `mtime` unless it
+/// says otherwise
+fn memory_entry(runtime: &mut Runtime, value: &Value, mtime: u64) -> RuntimeResult<MemoryEntry> {
+    if !matches!(value, Value::Object(_)) {
+        return Err(RuntimeError::TypeError("Each archive entry must be an object".to_string()));
+    }
+    let name = match runtime.get_property(value, "name")? {
+        Value::String(name) => name.to_rust_string(),
+        _ => return Err(RuntimeError::TypeError("An archive entry's \"name\" must be a string".to_string())),
+    };
+    let name = name.trim_end_matches('/');
+    let mut info = if runtime.get_property(value, "directory")?.to_boolean() {
+        EntryInfo::directory(name)
+    } else {
+        EntryInfo::file(name, 0)
+    };
+    info.mtime = match runtime.get_property(value, "mtimeMs")? {
+        Value::Undefined => mtime,
+        mtime_ms => (mtime_ms.to_number()?.max(0.0) / 1000.0) as u64,
+    };
+    if let mode @ Value::Number(_) = runtime.get_property(value, "mode")? {
+        info.mode = mode.to_number()? as u32 & 0o7777;
+    }
+
+    let data = match runtime.get_property(value, "data")? {
+        _ if info.kind == EntryKind::Directory => Vec::new(),
+        Value::Undefined => Vec::new(),
+        Value::String(text) => text.to_rust_string().into_bytes(),
+        buffer => runtime.with_buffer_bytes_mut(&buffer, |bytes| bytes.to_vec()).ok_or_else(|| {
+            RuntimeError::TypeError(format!("The data of archive entry '{}' must be a string or buffer", name))
+        })?,
+    };
+    Ok(MemoryEntry { info, data })
+}
+
+/// The `format` of an options object
+fn format_option(runtime: &mut Runtime, options: Option<&Value>) -> RuntimeResult<Option<ArchiveFormat>> {
+    let format = match options {
+        Some(options @ Value::Object(_)) => runtime.get_property(options, "format")?,
+        _ => return Ok(None),
+    };
+    match format {
+        Value::Undefined => Ok(None),
+        format => ArchiveFormat::from_name(&format.to_string()).map(Some).ok_or_else(|| {
+            RuntimeError::TypeError(format!(
+                "Unknown archive format '{}'; expected \"zip\" or \"tar.gz\"",
+                format.to_string()
+            ))
+        }),
+    }
+}
+
+fn string_argument(args: &[Value], index: usize, name: &str) -> RuntimeResult<String> {
+    match args.get(index) {
+        Some(Value::String(value)) => Ok(value.to_rust_string()),
+        other => Err(RuntimeError::TypeError(format!(
+            "The \"{}\" argument must be of type string. Received {}",
+            name,
+            other.map_or("undefined".to_string(), |value| value.to_string())
+        ))),
+    }
+}
+
+fn archive_error(error: io::Error) -> RuntimeError {
+    RuntimeError::Error(error.to_string())
+}
+
+impl Module for ArchiveModule {
+    fn name(&self) -> &str {
+        "archive"
+    }
+
+    fn initialize(&mut self, _runtime: &mut Runtime) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn get_exports(&self) -> HashMap<String, Value> {
+        self.exports.clone()
+    }
+}
@@ -0,0 +1,388 @@
+//! Interactive prompts and progress bars for command-line scripts
+//!
+//! Prompts are written to stderr so a script's stdout stays clean for
+//! piping. On a Unix terminal `select` and `password` put the terminal in
+//! raw mode to read single keys: arrows move the selection and typed
+//! characters are not echoed. Without a terminal, or on other platforms,
+//! every prompt falls back to reading a line from stdin, and `select`
+//! takes the number of a choice.
+
+use crate::tty::{self, Style, Stream};
+use crate::{Module, Value};
+use bebion_runtime::{NativeFunction, Runtime, RuntimeError, RuntimeResult};
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// Width of a progress bar unless the script picks another
+pub const DEFAULT_PROGRESS_WIDTH: usize = 30;
+
+const CTRL_C: u8 = 0x03;
+const BACKSPACE: u8 = 0x7f;
+const ESCAPE: u8 = 0x1b;
+
+type CliBinding = fn(&CliModule, &mut Runtime, &[Value]) -> RuntimeResult<Value>;
+
+pub struct CliModule {
+    exports: HashMap<String, Value>,
+}
+
+impl CliModule {
+    pub fn new() -> Self {
+        let bindings: [(&str, CliBinding); 5] = [
+            // `prompt(message, default)`: the line typed, or `default` if it
+            // is empty
+            ("prompt", |cli, _, args| {
+                let default = optional_string(args.get(1));
+                Ok(Value::from(cli.prompt(&message_argument(args), default.as_deref()).map_err(cli_error)?))
+            }),
+            // `confirm(message, default = false)`
+            ("confirm", |cli, _, args| {
+                let default = args.get(1).is_some_and(Value::to_boolean);
+                Ok(Value::Boolean(cli.confirm(&message_argument(args), default).map_err(cli_error)?))
+            }),
+            // `select(message, choices)`: the chosen element of `choices`
+            ("select", |cli, runtime, args| {
+                let choices = args
+                    .get(1)
+                    .and_then(|choices| runtime.array_elements(choices))
+                    .filter(|choices| !choices.is_empty())
+                    .ok_or_else(|| {
+                        RuntimeError::TypeError("The \"choices\" argument must be a non-empty array".to_string())
+                    })?;
+                let labels: Vec<String> = choices.iter().map(Value::to_string).collect();
+                let index = cli.select(&message_argument(args), &labels).map_err(cli_error)?;
+                Ok(choices[index].clone())
+            }),
+            ("password", |cli, _, args| {
+                Ok(Value::from(cli.password(&message_argument(args)).map_err(cli_error)?))
+            }),
+            // `progress(total, width)`: an object whose `update(value)`,
+            // `tick(amount = 1)` and `done()` redraw the bar
+            ("progress", |_, runtime, args| {
+                let total = args.first().map(Value::to_number).transpose()?.unwrap_or(100.0);
+                let width = match args.get(1) {
+                    None | Some(Value::Undefined) => DEFAULT_PROGRESS_WIDTH,
+                    Some(width) => width.to_number()?.max(1.0) as usize,
+                };
+                Ok(ProgressBar::new(total, width).to_value(runtime))
+            }),
+        ];
+
+        let cli = Arc::new(Self { exports: HashMap::new() });
+        let exports = bindings
+            .into_iter()
+            .map(|(name, binding)| {
+                let cli = Arc::clone(&cli);
+                let function = NativeFunction::new(name, move |runtime, args| binding(&cli, runtime, args));
+                (name.to_string(), Value::NativeFunction(function))
+            })
+            .collect();
+
+        Self { exports }
+    }
+
+    /// Ask for a line of text, answering `default` when it is left empty
+    pub fn prompt(&self, message: &str, default: Option<&str>) -> io::Result<String> {
+        match default {
+            Some(default) => ask(&format!("{} ({}) ", message, default))?,
+            None => ask(&format!("{} ", message))?,
+        };
+        let answer = read_line()?;
+        Ok(match default {
+            Some(default) if answer.is_empty() => default.to_string(),
+            _ => answer,
+        })
+    }
+
+    /// Ask a yes/no question until it gets `y`, `yes`, `n`, `no` or an
+    /// empty answer, which means `default`
+    pub fn confirm(&self, message: &str, default: bool) -> io::Result<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            ask(&format!("{} ({}) ", message, hint))?;
+            match read_line()?.to_ascii_lowercase().as_str() {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+
+    /// Let the user pick one of `choices`, returning its index
+    pub fn select(&self, message: &str, choices: &[String]) -> io::Result<usize> {
+        if choices.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "select needs at least one choice"));
+        }
+        match RawMode::enable()? {
+            Some(raw) => select_with_keys(&raw, message, choices),
+            None => select_by_number(message, choices),
+        }
+    }
+
+    /// Ask for a line of text without echoing it
+    pub fn password(&self, message: &str) -> io::Result<String> {
+        ask(&format!("{} ", message))?;
+        let Some(raw) = RawMode::enable()? else {
+            return read_line();
+        };
+
+        let mut password = Vec::new();
+        loop {
+            match raw.read_byte()? {
+                b'\r' | b'\n' => break,
+                CTRL_C => return Err(cancelled()),
+                BACKSPACE | 0x08 => {
+                    // Drop a whole character, not just its last byte
+                    while let Some(byte) = password.pop() {
+                        if byte & 0xc0 != 0x80 {
+                            break;
+                        }
+                    }
+                }
+                byte => password.push(byte),
+            }
+        }
+        drop(raw);
+        eprintln!();
+        String::from_utf8(password).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+/// Write a prompt to stderr without ending the line
+fn ask(prompt: &str) -> io::Result<()> {
+    let mut stderr = io::stderr();
+    write!(stderr, "{}", prompt)?;
+    stderr.flush()
+}
+
+/// A line from stdin without its line ending. End of input is an error, so
+/// a script cannot loop forever asking a closed stdin.
+fn read_line() -> io::Result<String> {
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stdin closed before an answer was given"));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn cancelled() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "Prompt cancelled")
+}
+
+/// `select` on a terminal: the choices listed under the message with the
+/// current one highlighted, moved with the arrow keys or `j`/`k`
+fn select_with_keys(raw: &RawMode, message: &str, choices: &[String]) -> io::Result<usize> {
+    let mut stderr = io::stderr();
+    let colors = tty::colors_enabled(Stream::Stderr);
+    let mut selected = 0;
+    eprintln!("{}", message);
+    loop {
+        for (index, choice) in choices.iter().enumerate() {
+            if index == selected {
+                writeln!(stderr, "{}{}", tty::CLEAR_LINE, tty::paint(&format!("> {}", choice), &[Style::Cyan], colors))?;
+            } else {
+                writeln!(stderr, "{}  {}", tty::CLEAR_LINE, choice)?;
+            }
+        }
+        stderr.flush()?;
+
+        match raw.read_byte()? {
+            b'\r' | b'\n' => break,
+            CTRL_C => return Err(cancelled()),
+            b'k' => selected = selected.checked_sub(1).unwrap_or(choices.len() - 1),
+            b'j' => selected = (selected + 1) % choices.len(),
+            ESCAPE => {
+                if raw.read_byte()? == b'[' {
+                    match raw.read_byte()? {
+                        b'A' => selected = selected.checked_sub(1).unwrap_or(choices.len() - 1),
+                        b'B' => selected = (selected + 1) % choices.len(),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        // Back to the first choice to redraw the list
+        write!(stderr, "{}", tty::move_cursor(0, -(choices.len() as i32)))?;
+    }
+    Ok(selected)
+}
+
+/// `select` without a terminal: a numbered list, asked until it gets a
+/// valid number
+fn select_by_number(message: &str, choices: &[String]) -> io::Result<usize> {
+    eprintln!("{}", message);
+    for (index, choice) in choices.iter().enumerate() {
+        eprintln!("  {}) {}", index + 1, choice);
+    }
+    loop {
+        ask(&format!("Enter a number (1-{}): ", choices.len()))?;
+        if let Ok(number) = read_line()?.trim().parse::<usize>() {
+            if (1..=choices.len()).contains(&number) {
+                return Ok(number - 1);
+            }
+        }
+    }
+}
+
+/// The terminal in raw mode until dropped, when its settings are restored
+struct RawMode {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// Raw mode on stdin, or `None` if stdin is not a terminal or raw mode
+    /// is unsupported here
+    #[cfg(unix)]
+    fn enable() -> io::Result<Option<Self>> {
+        if !io::stdin().is_terminal() {
+            return Ok(None);
+        }
+        // SAFETY: `termios` is plain data, filled in by `tcgetattr` before
+        // it is read
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        // Keys arrive one at a time, unechoed, and Ctrl-C as a byte
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(Self { original }))
+    }
+
+    #[cfg(not(unix))]
+    fn enable() -> io::Result<Option<Self>> {
+        Ok(None)
+    }
+
+    fn read_byte(&self) -> io::Result<u8> {
+        let mut byte = [0u8; 1];
+        io::stdin().lock().read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// A progress bar drawn on one line of stderr
+#[derive(Debug)]
+pub struct ProgressBar {
+    total: f64,
+    width: usize,
+    current: f64,
+}
+
+impl ProgressBar {
+    pub fn new(total: f64, width: usize) -> Self {
+        Self { total: total.max(0.0), width, current: 0.0 }
+    }
+
+    /// Set the progress to `value`, clamped to the total, and redraw
+    pub fn update(&mut self, value: f64) {
+        self.current = value.clamp(0.0, self.total);
+        self.draw();
+    }
+
+    pub fn tick(&mut self, amount: f64) {
+        self.update(self.current + amount);
+    }
+
+    /// Fill the bar and end its line
+    pub fn done(&mut self) {
+        self.update(self.total);
+        eprintln!();
+    }
+
+    /// The bar as drawn, e.g. `[=========>          ] 45% 45/100`
+    pub fn render(&self) -> String {
+        let fraction = if self.total > 0.0 { self.current / self.total } else { 1.0 };
+        let filled = (fraction * self.width as f64).round() as usize;
+        let bar = if filled >= self.width {
+            "=".repeat(self.width)
+        } else {
+            format!("{}>{}", "=".repeat(filled), " ".repeat(self.width - filled - 1))
+        };
+        format!("[{}] {:>3}% {}/{}", bar, (fraction * 100.0).round(), self.current, self.total)
+    }
+
+    /// Redraw the bar over its line, erasing what was there on a terminal
+    fn draw(&self) {
+        let mut stderr = io::stderr();
+        let clear = if Stream::Stderr.is_terminal() { tty::CLEAR_LINE } else { "" };
+        let _ = write!(stderr, "\r{}{}", clear, self.render());
+        let _ = stderr.flush();
+    }
+
+    /// An object whose methods drive this bar
+    fn to_value(self, runtime: &mut Runtime) -> Value {
+        let bar = Arc::new(Mutex::new(self));
+        let method = |name: &str, action: fn(&mut ProgressBar, &[Value]) -> RuntimeResult<()>| {
+            let bar = Arc::clone(&bar);
+            let function = NativeFunction::new(name, move |_, args| {
+                action(&mut bar.lock().unwrap(), args)?;
+                Ok(Value::Undefined)
+            });
+            (name.to_string(), Value::NativeFunction(function))
+        };
+        let properties = HashMap::from([
+            method("update", |bar, args| {
+                bar.update(args.first().map(Value::to_number).transpose()?.unwrap_or(0.0));
+                Ok(())
+            }),
+            method("tick", |bar, args| {
+                bar.tick(args.first().map(Value::to_number).transpose()?.unwrap_or(1.0));
+                Ok(())
+            }),
+            method("done", |bar, _| {
+                bar.done();
+                Ok(())
+            }),
+        ]);
+        runtime.create_object(properties)
+    }
+}
+
+/// The message a prompt function was called with
+fn message_argument(args: &[Value]) -> String {
+    args.first().map(Value::to_string).unwrap_or_default()
+}
+
+fn optional_string(value: Option<&Value>) -> Option<String> {
+    match value {
+        None | Some(Value::Undefined) | Some(Value::Null) => None,
+        Some(value) => Some(value.to_string()),
+    }
+}
+
+fn cli_error(error: io::Error) -> RuntimeError {
+    RuntimeError::Error(error.to_string())
+}
+
+impl Module for CliModule {
+    fn name(&self) -> &str {
+        "cli"
+    }
+
+    fn initialize(&mut self, _runtime: &mut Runtime) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn get_exports(&self) -> HashMap<String, Value> {
+        self.exports.clone()
+    }
+}
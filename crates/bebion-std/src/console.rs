@@ -1,86 +1,280 @@
 //! Console module for logging and debugging
 
+use crate::tty::{self, Stream};
+use crate::util::{InspectOptions, UtilModule};
 use crate::{Module, Value};
-use bebion_runtime::Runtime;
+use bebion_runtime::{NativeFunction, Runtime};
+use serde_json::json;
 use std::collections::HashMap;
+use std::fs::File;
 use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Receives console messages as inspector protocol notifications
+pub trait InspectorChannel: Send + Sync {
+    fn send(&self, message: serde_json::Value);
+}
+
+/// Writes inspector notifications to a file of their own, one JSON message
+/// per line, so they never mix with the script's output
+pub struct FileInspector {
+    file: Mutex<File>,
+}
+
+impl FileInspector {
+    /// Create or truncate the file at `path`
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+}
+
+impl InspectorChannel for FileInspector {
+    fn send(&self, message: serde_json::Value) {
+        let mut file = self.file.lock().unwrap();
+        let _ = writeln!(file, "{}", message).and_then(|_| file.flush());
+    }
+}
+
+static INSPECTOR: RwLock<Option<Arc<dyn InspectorChannel>>> = RwLock::new(None);
+
+/// Mirror all console output to the given inspector channel
+pub fn attach_inspector(channel: Arc<dyn InspectorChannel>) {
+    *INSPECTOR.write().unwrap() = Some(channel);
+}
+
+pub fn detach_inspector() {
+    *INSPECTOR.write().unwrap() = None;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleLevel {
+    Log,
+    Info,
+    Warn,
+    Error,
+    Debug,
+    Trace,
+    Assert,
+    Dir,
+}
+
+impl ConsoleLevel {
+    /// The `type` reported in `Runtime.consoleAPICalled`
+    fn protocol_type(self) -> &'static str {
+        match self {
+            ConsoleLevel::Log => "log",
+            ConsoleLevel::Info => "info",
+            ConsoleLevel::Warn => "warning",
+            ConsoleLevel::Error => "error",
+            ConsoleLevel::Debug => "debug",
+            ConsoleLevel::Trace => "trace",
+            ConsoleLevel::Assert => "assert",
+            ConsoleLevel::Dir => "dir",
+        }
+    }
+
+    fn is_stderr(self) -> bool {
+        matches!(
+            self,
+            ConsoleLevel::Warn | ConsoleLevel::Error | ConsoleLevel::Trace | ConsoleLevel::Assert
+        )
+    }
+}
+
+type ConsoleMethod = fn(&ConsoleModule, &mut Runtime, &[Value]);
 
 pub struct ConsoleModule {
     exports: HashMap<String, Value>,
+    util: UtilModule,
+    /// Start of each running `console.time` timer, by label
+    timers: Mutex<HashMap<String, Instant>>,
 }
 
 impl ConsoleModule {
     pub fn new() -> Self {
-        let mut exports = HashMap::new();
-        
-        // Add console functions
-        exports.insert("log".to_string(), Value::Undefined); // Placeholder
-        exports.insert("error".to_string(), Value::Undefined);
-        exports.insert("warn".to_string(), Value::Undefined);
-        exports.insert("info".to_string(), Value::Undefined);
-        exports.insert("debug".to_string(), Value::Undefined);
-        exports.insert("trace".to_string(), Value::Undefined);
-        exports.insert("clear".to_string(), Value::Undefined);
-        exports.insert("time".to_string(), Value::Undefined);
-        exports.insert("timeEnd".to_string(), Value::Undefined);
-        
-        Self { exports }
-    }
-    
+        // The exported functions share one console of their own
+        let console = Arc::new(Self::without_exports());
+        let methods: [(&str, ConsoleMethod); 11] = [
+            ("log", |console, _, args| console.log(args.to_vec())),
+            ("error", |console, _, args| console.error(args.to_vec())),
+            ("warn", |console, _, args| console.warn(args.to_vec())),
+            ("info", |console, _, args| console.info(args.to_vec())),
+            ("debug", |console, _, args| console.debug(args.to_vec())),
+            ("trace", |console, runtime, args| console.trace(runtime, args.to_vec())),
+            ("assert", |console, _, args| {
+                let condition = args.first().cloned().unwrap_or(Value::Undefined);
+                console.assert(&condition, args.iter().skip(1).cloned().collect())
+            }),
+            ("dir", |console, _, args| console.dir(args.first().unwrap_or(&Value::Undefined), None)),
+            ("clear", |console, _, _| console.clear()),
+            ("time", |console, _, args| console.time(&label(args))),
+            ("timeEnd", |console, _, args| console.time_end(&label(args))),
+        ];
+
+        let exports = methods
+            .into_iter()
+            .map(|(name, method)| {
+                let console = Arc::clone(&console);
+                let function = NativeFunction::new(name, move |runtime, args| {
+                    method(&console, runtime, args);
+                    Ok(Value::Undefined)
+                });
+                (name.to_string(), Value::NativeFunction(function))
+            })
+            .collect();
+
+        Self {
+            exports,
+            ..Self::without_exports()
+        }
+    }
+
+    fn without_exports() -> Self {
+        Self {
+            exports: HashMap::new(),
+            util: UtilModule::new(),
+            timers: Mutex::new(HashMap::new()),
+        }
+    }
+
     pub fn log(&self, args: Vec<Value>) {
-        let message = args.iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<_>>()
-            .join(" ");
-        
-        println!("{}", message);
-        io::stdout().flush().unwrap_or(());
-    }
-    
+        let message = self.format_args(&args);
+        self.emit(ConsoleLevel::Log, &message, &message);
+    }
+
     pub fn error(&self, args: Vec<Value>) {
-        let message = args.iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<_>>()
-            .join(" ");
-        
-        eprintln!("{}", message);
-        io::stderr().flush().unwrap_or(());
-    }
-    
+        let message = self.format_args(&args);
+        self.emit(ConsoleLevel::Error, &message, &message);
+    }
+
     pub fn warn(&self, args: Vec<Value>) {
-        let message = args.iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<_>>()
-            .join(" ");
-        
-        eprintln!("Warning: {}", message);
-        io::stderr().flush().unwrap_or(());
-    }
-    
+        let message = self.format_args(&args);
+        self.emit(ConsoleLevel::Warn, &format!("Warning: {}", message), &message);
+    }
+
     pub fn info(&self, args: Vec<Value>) {
-        let message = args.iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<_>>()
-            .join(" ");
-        
-        println!("Info: {}", message);
-        io::stdout().flush().unwrap_or(());
-    }
-    
+        let message = self.format_args(&args);
+        self.emit(ConsoleLevel::Info, &format!("Info: {}", message), &message);
+    }
+
     pub fn debug(&self, args: Vec<Value>) {
-        let message = args.iter()
-            .map(|v| v.to_string())
-            .collect::<Vec<_>>()
-            .join(" ");
-        
-        println!("Debug: {}", message);
-        io::stdout().flush().unwrap_or(());
-    }
-    
+        let message = self.format_args(&args);
+        self.emit(ConsoleLevel::Debug, &format!("Debug: {}", message), &message);
+    }
+
+    /// Print the message followed by the current JavaScript call stack
+    pub fn trace(&self, runtime: &Runtime, args: Vec<Value>) {
+        let message = self.format_args(&args);
+        let mut output = if message.is_empty() {
+            "Trace".to_string()
+        } else {
+            format!("Trace: {}", message)
+        };
+
+        for frame in runtime.stack_trace() {
+            output.push_str(&format!("\n    {}", frame));
+        }
+
+        self.emit(ConsoleLevel::Trace, &output, &message);
+    }
+
+    /// Report `Assertion failed` with the formatted message when `condition` is falsy
+    pub fn assert(&self, condition: &Value, args: Vec<Value>) {
+        if condition.to_boolean() {
+            return;
+        }
+
+        let message = if args.is_empty() {
+            "Assertion failed".to_string()
+        } else {
+            format!("Assertion failed: {}", self.format_args(&args))
+        };
+
+        self.emit(ConsoleLevel::Assert, &message, &message);
+    }
+
+    /// Print `value` inspected, in colors when stdout shows them unless
+    /// `options` say otherwise
+    pub fn dir(&self, value: &Value, options: Option<InspectOptions>) {
+        let options = options.unwrap_or_else(|| InspectOptions {
+            colors: tty::colors_enabled(Stream::Stdout),
+            ..InspectOptions::default()
+        });
+        let output = self.util.inspect(value, Some(options));
+        self.emit(ConsoleLevel::Dir, &output, &tty::strip_ansi(&output));
+    }
+
+    /// Clear the terminal, doing nothing when stdout is not one
     pub fn clear(&self) {
-        // Clear the console
-        print!("\x1B[2J\x1B[1;1H");
-        io::stdout().flush().unwrap_or(());
+        tty::control(tty::CLEAR_SCREEN);
+    }
+
+    /// Start the timer `label`, unless it is already running
+    pub fn time(&self, label: &str) {
+        let mut timers = self.timers.lock().unwrap();
+        if timers.contains_key(label) {
+            let message = format!("Label '{}' already exists for console.time()", label);
+            self.emit(ConsoleLevel::Warn, &format!("Warning: {}", message), &message);
+            return;
+        }
+        timers.insert(label.to_string(), Instant::now());
+    }
+
+    /// Stop the timer `label` and print how long it ran
+    pub fn time_end(&self, label: &str) {
+        let Some(start) = self.timers.lock().unwrap().remove(label) else {
+            let message = format!("No such label '{}' for console.timeEnd()", label);
+            self.emit(ConsoleLevel::Warn, &format!("Warning: {}", message), &message);
+            return;
+        };
+        let message = format!("{}: {:.3}ms", label, start.elapsed().as_secs_f64() * 1000.0);
+        self.emit(ConsoleLevel::Log, &message, &message);
+    }
+
+    /// Join arguments with spaces, applying printf-style substitutions when the
+    /// first argument is a string
+    fn format_args(&self, args: &[Value]) -> String {
+        match args.split_first() {
+            Some((Value::String(template), rest)) if template.contains("%") => {
+                self.util.format(&template.to_rust_string(), rest)
+            }
+            _ => args.iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// Write `output` to the terminal and mirror `message` to an attached inspector
+    fn emit(&self, level: ConsoleLevel, output: &str, message: &str) {
+        if level.is_stderr() {
+            eprintln!("{}", output);
+            io::stderr().flush().unwrap_or(());
+        } else {
+            println!("{}", output);
+            io::stdout().flush().unwrap_or(());
+        }
+
+        if let Some(inspector) = INSPECTOR.read().unwrap().as_ref() {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64() * 1000.0)
+                .unwrap_or(0.0);
+
+            inspector.send(json!({
+                "method": "Runtime.consoleAPICalled",
+                "params": {
+                    "type": level.protocol_type(),
+                    "args": [{ "type": "string", "value": message }],
+                    "executionContextId": 1,
+                    "timestamp": timestamp,
+                }
+            }));
+        }
     }
 }
 
@@ -88,17 +282,23 @@ impl Module for ConsoleModule {
     fn name(&self) -> &str {
         "console"
     }
-    
+
     fn initialize(&mut self, runtime: &mut Runtime) -> Result<(), Box<dyn std::error::Error>> {
-        // Set global console object
-        runtime.set_global("console", Value::Object(
-            bebion_gc::GcHandle::new(0)
-        ));
-        
+        let console = runtime.create_object(self.exports.clone());
+        runtime.set_global("console", console);
+
         Ok(())
     }
-    
+
     fn get_exports(&self) -> HashMap<String, Value> {
         self.exports.clone()
     }
 }
+
+/// The label argument of `console.time` and `console.timeEnd`
+fn label(args: &[Value]) -> String {
+    match args.first() {
+        None | Some(Value::Undefined) => "default".to_string(),
+        Some(label) => label.to_string(),
+    }
+}
@@ -1,14 +1,15 @@
 //! Cryptographic functions module
 
 use crate::{Module, Value};
-use bebion_runtime::Runtime;
+use bebion_runtime::{HostRandom, Runtime};
 use base64;
-use rand::{thread_rng, Rng};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 pub struct CryptoModule {
     exports: HashMap<String, Value>,
+    random: Arc<HostRandom>,
 }
 
 impl CryptoModule {
@@ -22,20 +23,22 @@ impl CryptoModule {
         exports.insert("base64Encode".to_string(), Value::Undefined);
         exports.insert("base64Decode".to_string(), Value::Undefined);
         
-        Self { exports }
+        Self {
+            exports,
+            random: Arc::new(HostRandom::new()),
+        }
     }
     
     pub fn random_bytes(&self, size: usize) -> Vec<u8> {
-        let mut rng = thread_rng();
-        (0..size).map(|_| rng.gen::<u8>()).collect()
+        let mut bytes = vec![0u8; size];
+        self.random.fill_crypto_bytes(&mut bytes);
+        bytes
     }
     
     pub fn random_uuid(&self) -> String {
-        let mut rng = thread_rng();
-        
         // Generate 16 random bytes
         let mut bytes = [0u8; 16];
-        rng.fill(&mut bytes);
+        self.random.fill_crypto_bytes(&mut bytes);
         
         // Set version (4) and variant bits
         bytes[6] = (bytes[6] & 0x0f) | 0x40; // Version 4
@@ -74,14 +77,28 @@ impl CryptoModule {
         Ok(base64::decode(data)?)
     }
     
-    pub fn random_int(&self, min: i32, max: i32) -> i32 {
-        let mut rng = thread_rng();
-        rng.gen_range(min..=max)
+    /// A uniformly distributed integer in `min..=max`
+    pub fn random_int(&self, min: i32, max: i32) -> Result<i32, Box<dyn std::error::Error>> {
+        if min > max {
+            return Err(format!("Invalid range: min {} is greater than max {}", min, max).into());
+        }
+        
+        // Reject draws past the last whole multiple of the span so that
+        // every value is equally likely; the span is at most 2^32
+        let span = (max as i64 - min as i64 + 1) as u64;
+        let limit = (1u64 << 32) - (1u64 << 32) % span;
+        loop {
+            let mut bytes = [0u8; 4];
+            self.random.fill_crypto_bytes(&mut bytes);
+            let draw = u32::from_le_bytes(bytes) as u64;
+            if draw < limit {
+                return Ok((min as i64 + (draw % span) as i64) as i32);
+            }
+        }
     }
     
     pub fn random_float(&self) -> f64 {
-        let mut rng = thread_rng();
-        rng.gen::<f64>()
+        self.random.crypto_f64()
     }
 }
 
@@ -90,7 +107,9 @@ impl Module for CryptoModule {
         "crypto"
     }
     
-    fn initialize(&mut self, _runtime: &mut Runtime) -> Result<(), Box<dyn std::error::Error>> {
+    fn initialize(&mut self, runtime: &mut Runtime) -> Result<(), Box<dyn std::error::Error>> {
+        // Share the engine's random source so crypto overrides apply here
+        self.random = Arc::clone(runtime.random());
         Ok(())
     }
     
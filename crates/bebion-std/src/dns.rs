@@ -0,0 +1,169 @@
+//! Host name resolution and connection racing for net and http
+//!
+//! Lookups go through a `DnsCache` shared by the whole process, so scripts
+//! opening many connections to the same host resolve it once. The system
+//! resolver does not report record TTLs, so entries live for the cache's
+//! `ttl`. When refreshing an expired entry fails, the old addresses keep
+//! being served for `stale_ttl` longer, riding out a flaky resolver.
+//!
+//! `connect` races the resolved addresses as Happy Eyeballs (RFC 8305)
+//! does: addresses alternate between IPv6 and IPv4, and each attempt gets a
+//! head start of `CONNECTION_ATTEMPT_DELAY` before the next one begins, so
+//! a broken address family costs a fraction of a second instead of a full
+//! connect timeout.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+
+/// How long lookups are cached unless the host picks another TTL
+pub const DEFAULT_DNS_TTL: Duration = Duration::from_secs(30);
+/// How long past its TTL an entry is served when refreshing it fails
+pub const DEFAULT_DNS_STALE_TTL: Duration = Duration::from_secs(300);
+/// Head start of each connection attempt before the next one begins
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+static SHARED: OnceLock<DnsCache> = OnceLock::new();
+
+/// Resolved addresses of host names
+#[derive(Debug)]
+pub struct DnsCache {
+    entries: Mutex<HashMap<String, DnsEntry>>,
+    ttl: Mutex<Duration>,
+    stale_ttl: Mutex<Duration>,
+}
+
+#[derive(Debug, Clone)]
+struct DnsEntry {
+    addresses: Vec<IpAddr>,
+    resolved_at: Instant,
+}
+
+impl DnsCache {
+    pub fn new(ttl: Duration, stale_ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl: Mutex::new(ttl),
+            stale_ttl: Mutex::new(stale_ttl),
+        }
+    }
+
+    /// The cache net and http resolve through
+    pub fn shared() -> &'static DnsCache {
+        SHARED.get_or_init(|| DnsCache::new(DEFAULT_DNS_TTL, DEFAULT_DNS_STALE_TTL))
+    }
+
+    /// Cache lookups for `ttl`, and serve them `stale_ttl` longer when a
+    /// refresh fails. A zero `ttl` turns caching off.
+    pub fn set_ttl(&self, ttl: Duration, stale_ttl: Duration) {
+        *self.ttl.lock().unwrap() = ttl;
+        *self.stale_ttl.lock().unwrap() = stale_ttl;
+    }
+
+    /// Forget every cached lookup
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// The addresses of `host`, IPv6 and IPv4 alternating, from the cache
+    /// while its entry is fresh
+    pub async fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(address) = host.parse::<IpAddr>() {
+            return Ok(vec![address]);
+        }
+
+        let key = host.to_ascii_lowercase();
+        let ttl = *self.ttl.lock().unwrap();
+        let stale_ttl = *self.stale_ttl.lock().unwrap();
+        let cached = self.entries.lock().unwrap().get(&key).cloned();
+        if let Some(entry) = &cached {
+            if entry.resolved_at.elapsed() < ttl {
+                return Ok(entry.addresses.clone());
+            }
+        }
+
+        let resolved = tokio::net::lookup_host((key.as_str(), 0))
+            .await
+            .map(|resolved| resolved.map(|address| address.ip()).collect::<Vec<IpAddr>>());
+        match resolved {
+            Ok(resolved) => {
+                let mut addresses: Vec<IpAddr> = Vec::new();
+                for address in resolved {
+                    if !addresses.contains(&address) {
+                        addresses.push(address);
+                    }
+                }
+                let addresses = interleave(addresses);
+                if !ttl.is_zero() {
+                    let entry = DnsEntry { addresses: addresses.clone(), resolved_at: Instant::now() };
+                    self.entries.lock().unwrap().insert(key, entry);
+                }
+                Ok(addresses)
+            }
+            Err(error) => match cached {
+                Some(entry) if entry.resolved_at.elapsed() < ttl + stale_ttl => Ok(entry.addresses),
+                _ => Err(error),
+            },
+        }
+    }
+}
+
+/// Order addresses IPv6 and IPv4 alternating, starting with the family of
+/// the first one, keeping each family's order
+fn interleave(addresses: Vec<IpAddr>) -> Vec<IpAddr> {
+    let first_is_v6 = addresses.first().is_some_and(IpAddr::is_ipv6);
+    let (mut preferred, mut other): (VecDeque<IpAddr>, VecDeque<IpAddr>) =
+        addresses.into_iter().partition(|address| address.is_ipv6() == first_is_v6);
+
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    while !preferred.is_empty() || !other.is_empty() {
+        ordered.extend(preferred.pop_front());
+        ordered.extend(other.pop_front());
+    }
+    ordered
+}
+
+/// Connect to `host:port` through the shared cache, racing its addresses
+pub async fn connect_host(host: &str, port: u16) -> io::Result<TcpStream> {
+    let addresses = DnsCache::shared().lookup(host).await?;
+    let addresses: Vec<SocketAddr> = addresses.into_iter().map(|ip| SocketAddr::new(ip, port)).collect();
+    connect(&addresses).await
+}
+
+/// Connect to whichever of `addresses` answers first. Attempts start in
+/// order, each once the one before has failed or had
+/// `CONNECTION_ATTEMPT_DELAY` to itself; the first to connect wins and the
+/// others are dropped.
+pub async fn connect(addresses: &[SocketAddr]) -> io::Result<TcpStream> {
+    let mut pending: VecDeque<SocketAddr> = addresses.iter().copied().collect();
+    let mut attempts = JoinSet::new();
+    let mut last_error = None;
+
+    loop {
+        match pending.pop_front() {
+            Some(address) => {
+                attempts.spawn(TcpStream::connect(address));
+            }
+            None if attempts.is_empty() => {
+                return Err(last_error.unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to")
+                }));
+            }
+            None => {}
+        }
+
+        tokio::select! {
+            Some(attempt) = attempts.join_next() => match attempt {
+                Ok(Ok(stream)) => return Ok(stream),
+                Ok(Err(error)) => last_error = Some(error),
+                Err(error) => last_error = Some(io::Error::other(error)),
+            },
+            _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if !pending.is_empty() => {}
+        }
+    }
+}
@@ -1,34 +1,122 @@
 //! File system module
 
+use crate::glob::{self, WalkOptions};
 use crate::{Module, Value};
-use bebion_runtime::Runtime;
+use bebion_runtime::{AbortSignal, NativeFunction, Runtime, RuntimeError, RuntimeResult};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs as async_fs;
 
+type FsBinding = fn(&FileSystemModule, &mut Runtime, &[Value]) -> RuntimeResult<Value>;
+
 pub struct FileSystemModule {
     exports: HashMap<String, Value>,
 }
 
 impl FileSystemModule {
     pub fn new() -> Self {
-        let mut exports = HashMap::new();
+        let bindings: [(&str, FsBinding); 11] = [
+            ("readFile", |fs, _, args| {
+                let content = fs.read_file_sync(&path_argument(args)?).map_err(fs_error)?;
+                Ok(Value::from(content))
+            }),
+            // The file's bytes become the array's buffer as read, uncopied
+            ("readBytes", |fs, runtime, args| {
+                let bytes = fs.read_bytes_sync(&path_argument(args)?).map_err(fs_error)?;
+                Ok(runtime.create_uint8_array(bytes))
+            }),
+            // `readInto(path, buffer, position)`: fill an ArrayBuffer or view
+            // in place from `position`, returning the bytes read
+            ("readInto", |fs, runtime, args| {
+                let path = path_argument(args)?;
+                let target = args.get(1).cloned().unwrap_or(Value::Undefined);
+                let position = match args.get(2) {
+                    None | Some(Value::Undefined) => 0,
+                    Some(position) => position.to_number()?.max(0.0) as u64,
+                };
+                let read = runtime
+                    .with_buffer_bytes_mut(&target, |buffer| fs.read_into_sync(&path, position, buffer))
+                    .ok_or_else(|| {
+                        RuntimeError::TypeError(
+                            "The \"buffer\" argument must be an ArrayBuffer, typed array or DataView".to_string(),
+                        )
+                    })?
+                    .map_err(fs_error)?;
+                Ok(Value::Number(read as f64))
+            }),
+            ("writeFile", |fs, _, args| {
+                let content = args.get(1).map(|content| content.to_string()).unwrap_or_default();
+                fs.write_file_sync(&path_argument(args)?, &content).map_err(fs_error)?;
+                Ok(Value::Undefined)
+            }),
+            ("exists", |fs, _, args| Ok(Value::Boolean(fs.exists_sync(&path_argument(args)?)))),
+            ("mkdir", |fs, _, args| {
+                fs.mkdir_sync(&path_argument(args)?).map_err(fs_error)?;
+                Ok(Value::Undefined)
+            }),
+            ("readdir", |fs, runtime, args| {
+                let entries = fs.readdir_sync(&path_argument(args)?).map_err(fs_error)?;
+                Ok(runtime.create_array(entries.into_iter().map(Value::from).collect()))
+            }),
+            ("stat", |fs, runtime, args| {
+                let stats = fs.stat_sync(&path_argument(args)?).map_err(fs_error)?;
+                Ok(stats.to_value(runtime))
+            }),
+            ("unlink", |fs, _, args| {
+                fs.unlink_sync(&path_argument(args)?).map_err(fs_error)?;
+                Ok(Value::Undefined)
+            }),
+            // `glob(pattern, { cwd, ignore, gitignore, dot, directories })`:
+            // the matching paths, sorted
+            ("glob", |fs, runtime, args| {
+                let pattern = match args.first() {
+                    Some(Value::String(pattern)) => pattern.to_rust_string(),
+                    _ => return Err(RuntimeError::TypeError("The \"pattern\" argument must be of type string".to_string())),
+                };
+                let options = args.get(1).cloned().unwrap_or(Value::Undefined);
+                let cwd = match option(runtime, &options, "cwd")? {
+                    Value::Undefined => PathBuf::from("."),
+                    cwd => PathBuf::from(cwd.to_string()),
+                };
+                let walk_options = walk_options(runtime, &options)?;
+                let paths = fs.glob_sync(&pattern, &cwd, &walk_options).map_err(fs_error)?;
+                Ok(runtime.create_array(paths.into_iter().map(Value::from).collect()))
+            }),
+            // `walk(dir, { ignore, gitignore, dot, directories, maxDepth })`:
+            // the paths below `dir`, sorted
+            ("walk", |fs, runtime, args| {
+                let root = path_argument(args)?;
+                let options = walk_options(runtime, args.get(1).unwrap_or(&Value::Undefined))?;
+                let paths = fs.walk_sync(&root, &options).map_err(fs_error)?;
+                Ok(runtime.create_array(paths.into_iter().map(Value::from).collect()))
+            }),
+        ];
         
-        exports.insert("readFile".to_string(), Value::Undefined);
-        exports.insert("writeFile".to_string(), Value::Undefined);
-        exports.insert("readFileSync".to_string(), Value::Undefined);
-        exports.insert("writeFileSync".to_string(), Value::Undefined);
-        exports.insert("exists".to_string(), Value::Undefined);
-        exports.insert("existsSync".to_string(), Value::Undefined);
-        exports.insert("mkdir".to_string(), Value::Undefined);
-        exports.insert("mkdirSync".to_string(), Value::Undefined);
-        exports.insert("readdir".to_string(), Value::Undefined);
-        exports.insert("readdirSync".to_string(), Value::Undefined);
-        exports.insert("stat".to_string(), Value::Undefined);
-        exports.insert("statSync".to_string(), Value::Undefined);
-        exports.insert("unlink".to_string(), Value::Undefined);
-        exports.insert("unlinkSync".to_string(), Value::Undefined);
+        // Each operation is exported as `nameSync`, returning its result, and
+        // as `name`, returning a promise settled with it
+        let fs = Arc::new(Self { exports: HashMap::new() });
+        let mut exports = HashMap::new();
+        for (name, binding) in bindings {
+            let sync_name = format!("{}Sync", name);
+            let sync_fs = Arc::clone(&fs);
+            let sync = NativeFunction::new(sync_name.as_str(), move |runtime, args| binding(&sync_fs, runtime, args));
+            exports.insert(sync_name, Value::NativeFunction(sync));
+            
+            let promise_fs = Arc::clone(&fs);
+            let promise = NativeFunction::new(name, move |runtime, args| {
+                let outcome = binding(&promise_fs, runtime, args);
+                let promise = runtime.create_promise();
+                match outcome {
+                    Ok(value) => runtime.resolve_promise(promise, value),
+                    Err(error) => runtime.reject_promise(promise, Value::from(error.to_string())),
+                }
+                Ok(Value::Object(promise))
+            });
+            exports.insert(name.to_string(), Value::NativeFunction(promise));
+        }
         
         Self { exports }
     }
@@ -38,6 +126,25 @@ impl FileSystemModule {
         Ok(content)
     }
     
+    pub fn read_bytes_sync(&self, path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(fs::read(path)?)
+    }
+    
+    /// Read from `position` into `buffer` until it is full or the file
+    /// ends, returning how many bytes were read
+    pub fn read_into_sync(&self, path: &str, position: u64, buffer: &mut [u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut file = fs::File::open(path)?;
+        file.seek(SeekFrom::Start(position))?;
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match file.read(&mut buffer[filled..])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        Ok(filled)
+    }
+    
     pub fn write_file_sync(&self, path: &str, content: &str) -> Result<(), Box<dyn std::error::Error>> {
         fs::write(path, content)?;
         Ok(())
@@ -82,14 +189,27 @@ impl FileSystemModule {
         Ok(())
     }
     
-    pub async fn read_file(&self, path: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        let content = async_fs::read_to_string(path).await?;
+    /// The paths under `cwd` matching `pattern`, sorted
+    pub fn glob_sync(&self, pattern: &str, cwd: &Path, options: &WalkOptions) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(glob::glob(pattern, cwd, options)?)
+    }
+    
+    /// The paths below `root`, each starting with `root`, sorted
+    pub fn walk_sync(&self, root: &str, options: &WalkOptions) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let entries = glob::walk(Path::new(root), options)?;
+        Ok(entries.into_iter().map(|entry| entry.path.to_string_lossy().into_owned()).collect())
+    }
+    
+    /// Read a file as text, giving up as soon as `signal` is aborted
+    pub async fn read_file(&self, path: &str, signal: Option<&AbortSignal>) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let content = crate::abortable(signal, async { Ok(async_fs::read_to_string(path).await?) }).await?;
         Ok(content)
     }
     
-    pub async fn write_file(&self, path: &str, content: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        async_fs::write(path, content).await?;
-        Ok(())
+    /// Write a file, giving up as soon as `signal` is aborted; the file may
+    /// then be partly written
+    pub async fn write_file(&self, path: &str, content: &str, signal: Option<&AbortSignal>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        crate::abortable(signal, async { Ok(async_fs::write(path, content).await?) }).await
     }
     
     pub async fn mkdir(&self, path: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -137,6 +257,79 @@ pub struct FileStats {
     pub modified_time: u64,
 }
 
+impl FileStats {
+    /// A `Stats`-like object with `size`, `mtimeMs`, `isFile()` and
+    /// `isDirectory()`
+    fn to_value(&self, runtime: &mut Runtime) -> Value {
+        let (is_file, is_directory) = (self.is_file, self.is_directory);
+        let properties = HashMap::from([
+            ("size".to_string(), Value::Number(self.size as f64)),
+            ("mtimeMs".to_string(), Value::Number(self.modified_time as f64 * 1000.0)),
+            (
+                "isFile".to_string(),
+                Value::NativeFunction(NativeFunction::new("isFile", move |_, _| Ok(Value::Boolean(is_file)))),
+            ),
+            (
+                "isDirectory".to_string(),
+                Value::NativeFunction(NativeFunction::new("isDirectory", move |_, _| {
+                    Ok(Value::Boolean(is_directory))
+                })),
+            ),
+        ]);
+        runtime.create_object(properties)
+    }
+}
+
+/// `options[key]`, or undefined when there are no options
+fn option(runtime: &mut Runtime, options: &Value, key: &str) -> RuntimeResult<Value> {
+    match options {
+        Value::Object(_) => runtime.get_property(options, key),
+        _ => Ok(Value::Undefined),
+    }
+}
+
+/// The `WalkOptions` of a `glob` or `walk` options object
+fn walk_options(runtime: &mut Runtime, options: &Value) -> RuntimeResult<WalkOptions> {
+    let ignore = match option(runtime, options, "ignore")? {
+        Value::Undefined => Vec::new(),
+        Value::String(pattern) => vec![pattern.to_rust_string()],
+        patterns => runtime
+            .array_elements(&patterns)
+            .ok_or_else(|| {
+                RuntimeError::TypeError("The \"ignore\" option must be a string or an array of strings".to_string())
+            })?
+            .iter()
+            .map(Value::to_string)
+            .collect(),
+    };
+    let max_depth = match option(runtime, options, "maxDepth")? {
+        Value::Undefined => None,
+        depth => Some(depth.to_number()?.max(0.0) as usize),
+    };
+    Ok(WalkOptions {
+        ignore,
+        gitignore: option(runtime, options, "gitignore")?.to_boolean(),
+        dot: option(runtime, options, "dot")?.to_boolean(),
+        directories: option(runtime, options, "directories")?.to_boolean(),
+        max_depth,
+    })
+}
+
+/// The path a file system function was called with
+fn path_argument(args: &[Value]) -> RuntimeResult<String> {
+    match args.first() {
+        Some(Value::String(path)) => Ok(path.to_rust_string()),
+        other => Err(RuntimeError::TypeError(format!(
+            "The \"path\" argument must be of type string. Received {}",
+            other.map_or("undefined".to_string(), |value| value.to_string())
+        ))),
+    }
+}
+
+fn fs_error(error: Box<dyn std::error::Error>) -> RuntimeError {
+    RuntimeError::Error(error.to_string())
+}
+
 impl Module for FileSystemModule {
     fn name(&self) -> &str {
         "fs"
@@ -0,0 +1,462 @@
+//! Glob patterns, gitignore rules and directory walking for fs
+//!
+//! Patterns use `/` as the separator on every platform. In a component,
+//! `*` matches any run of characters, `?` any one character and `[a-z]`
+//! or `[!a-z]` one character in or out of a set; `{a,b}` matches either
+//! alternative and `**` as a whole component matches any number of
+//! directories. A name starting with a dot is only matched by a component
+//! that spells out the dot, unless `dot` is set.
+//!
+//! Ignore rules follow `.gitignore`: a rule without a `/` matches a name at
+//! any depth, one with a `/` matches from the directory it was given for, a
+//! trailing `/` only matches directories, `!` re-includes what an earlier
+//! rule excluded, and the last matching rule wins. Walks skip an ignored
+//! directory without reading it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A compiled glob pattern
+#[derive(Debug, Clone)]
+pub struct Glob {
+    /// One segment list per brace alternative
+    alternatives: Vec<Vec<Segment>>,
+    dot: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    /// `**`
+    AnyDirectories,
+    Component(Vec<Token>),
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(char),
+    /// `*`
+    Any,
+    /// `?`
+    One,
+    /// `[...]`
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(literal) => *literal == c,
+            Token::Any | Token::One => true,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated
+            }
+        }
+    }
+}
+
+impl Glob {
+    /// Compile `pattern`, matching names that start with a dot with
+    /// wildcards only if `dot` is set. Unclosed `[` and `{` are literal.
+    pub fn new(pattern: &str, dot: bool) -> Self {
+        let alternatives = expand_braces(pattern)
+            .iter()
+            .map(|pattern| {
+                pattern
+                    .split('/')
+                    .filter(|component| !component.is_empty())
+                    .map(|component| match component {
+                        "**" => Segment::AnyDirectories,
+                        component => Segment::Component(tokenize(component)),
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { alternatives, dot }
+    }
+
+    /// Whether the `/`-separated relative `path` matches
+    pub fn matches(&self, path: &str) -> bool {
+        let components: Vec<&str> = path.split('/').filter(|component| !component.is_empty()).collect();
+        self.alternatives
+            .iter()
+            .any(|segments| match_segments(segments, &components, self.dot))
+    }
+
+    /// Whether anything below the directory `path` could match, so a walk
+    /// can skip directories that cannot
+    pub fn could_match_below(&self, path: &str) -> bool {
+        let components: Vec<&str> = path.split('/').filter(|component| !component.is_empty()).collect();
+        self.alternatives
+            .iter()
+            .any(|segments| match_prefix(segments, &components, self.dot))
+    }
+}
+
+/// Whether `pattern` has any wildcard, class or brace in it
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// `pattern` with each `{a,b}` group expanded, outermost first
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut depth = 0;
+    let mut open = None;
+    let mut commas = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' => index += 1,
+            '{' => {
+                if depth == 0 {
+                    open = Some(index);
+                    commas.clear();
+                }
+                depth += 1;
+            }
+            ',' if depth == 1 => commas.push(index),
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 && !commas.is_empty() {
+                    let open = open.unwrap_or(0);
+                    let prefix: String = chars[..open].iter().collect();
+                    let suffix: String = chars[index + 1..].iter().collect();
+                    let mut bounds = vec![open];
+                    bounds.extend(&commas);
+                    bounds.push(index);
+                    return bounds
+                        .windows(2)
+                        .flat_map(|bound| {
+                            let alternative: String = chars[bound[0] + 1..bound[1]].iter().collect();
+                            expand_braces(&format!("{}{}{}", prefix, alternative, suffix))
+                        })
+                        .collect();
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    vec![pattern.to_string()]
+}
+
+fn tokenize(component: &str) -> Vec<Token> {
+    let chars: Vec<char> = component.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' if index + 1 < chars.len() => {
+                index += 1;
+                tokens.push(Token::Literal(chars[index]));
+            }
+            // Runs of `*` match the same as one
+            '*' if matches!(tokens.last(), Some(Token::Any)) => {}
+            '*' => tokens.push(Token::Any),
+            '?' => tokens.push(Token::One),
+            '[' => match parse_class(&chars[index + 1..]) {
+                Some((class, length)) => {
+                    tokens.push(class);
+                    index += length;
+                }
+                None => tokens.push(Token::Literal('[')),
+            },
+            c => tokens.push(Token::Literal(c)),
+        }
+        index += 1;
+    }
+    tokens
+}
+
+/// The class after a `[` and how many characters it took, or `None` if
+/// it is never closed
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    let mut index = usize::from(negated);
+    let mut ranges = Vec::new();
+    // A `]` right after the opening bracket is part of the set
+    let mut first = true;
+    while index < chars.len() {
+        let c = chars[index];
+        if c == ']' && !first {
+            return Some((Token::Class { negated, ranges }, index + 1));
+        }
+        first = false;
+        if chars.get(index + 1) == Some(&'-') && chars.get(index + 2).is_some_and(|&high| high != ']') {
+            ranges.push((c, chars[index + 2]));
+            index += 3;
+        } else {
+            ranges.push((c, c));
+            index += 1;
+        }
+    }
+    None
+}
+
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+fn match_segments(segments: &[Segment], components: &[&str], dot: bool) -> bool {
+    match segments.split_first() {
+        None => components.is_empty(),
+        Some((Segment::AnyDirectories, rest)) => {
+            match_segments(rest, components, dot)
+                || components.split_first().is_some_and(|(component, deeper)| {
+                    (dot || !is_hidden(component)) && match_segments(segments, deeper, dot)
+                })
+        }
+        Some((Segment::Component(tokens), rest)) => components
+            .split_first()
+            .is_some_and(|(component, deeper)| {
+                match_component(tokens, component, dot) && match_segments(rest, deeper, dot)
+            }),
+    }
+}
+
+/// Like `match_segments`, but true if `components` could be the start of
+/// a matching path with more components after it
+fn match_prefix(segments: &[Segment], components: &[&str], dot: bool) -> bool {
+    let Some((component, deeper)) = components.split_first() else {
+        return !segments.is_empty();
+    };
+    match segments.split_first() {
+        None => false,
+        Some((Segment::AnyDirectories, rest)) => {
+            match_prefix(rest, components, dot) || ((dot || !is_hidden(component)) && match_prefix(segments, deeper, dot))
+        }
+        Some((Segment::Component(tokens), rest)) => {
+            match_component(tokens, component, dot) && match_prefix(rest, deeper, dot)
+        }
+    }
+}
+
+fn match_component(tokens: &[Token], name: &str, dot: bool) -> bool {
+    if is_hidden(name) && !dot && !matches!(tokens.first(), Some(Token::Literal('.'))) {
+        return false;
+    }
+    let name: Vec<char> = name.chars().collect();
+    match_tokens(tokens, &name)
+}
+
+fn match_tokens(tokens: &[Token], name: &[char]) -> bool {
+    match tokens.split_first() {
+        None => name.is_empty(),
+        Some((Token::Any, rest)) => (0..=name.len()).any(|skip| match_tokens(rest, &name[skip..])),
+        Some((token, rest)) => name
+            .split_first()
+            .is_some_and(|(&c, name)| token.matches(c) && match_tokens(rest, name)),
+    }
+}
+
+/// Gitignore-style rules deciding which paths a walk skips
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    /// Directory the rule was given for, relative to the walk's root
+    base: String,
+    glob: Glob,
+    negated: bool,
+    directories_only: bool,
+}
+
+impl IgnoreRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the rules in `lines`, as written in a `.gitignore` in the
+    /// directory `base`, relative to the walk's root. Blank lines and
+    /// `#` comments are skipped.
+    pub fn add<'a>(&mut self, base: &str, lines: impl IntoIterator<Item = &'a str>) {
+        for line in lines {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(line) => (true, line),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (directories_only, line) = match line.strip_suffix('/') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let pattern = if line.contains('/') {
+                line.trim_start_matches('/').to_string()
+            } else {
+                format!("**/{}", line)
+            };
+            self.rules.push(IgnoreRule {
+                base: base.trim_matches('/').to_string(),
+                glob: Glob::new(&pattern, true),
+                negated,
+                directories_only,
+            });
+        }
+    }
+
+    /// Add the rules of the `.gitignore` in `directory`, if it has one
+    pub fn add_gitignore(&mut self, directory: &Path, base: &str) -> io::Result<()> {
+        match fs::read_to_string(directory.join(".gitignore")) {
+            Ok(contents) => {
+                self.add(base, contents.lines());
+                Ok(())
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Whether the relative `path` is ignored
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.directories_only && !is_dir {
+                continue;
+            }
+            let relative = if rule.base.is_empty() {
+                Some(path)
+            } else {
+                path.strip_prefix(rule.base.as_str()).and_then(|rest| rest.strip_prefix('/'))
+            };
+            if relative.is_some_and(|relative| rule.glob.matches(relative)) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+/// What a walk visits and returns
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Gitignore-style rules for paths to skip, relative to the root
+    pub ignore: Vec<String>,
+    /// Also skip what the `.gitignore` files met on the way exclude, and
+    /// `.git` itself
+    pub gitignore: bool,
+    /// Visit names starting with a dot
+    pub dot: bool,
+    /// Return directories as well as files
+    pub directories: bool,
+    /// How many directories deep to go below the root, unlimited if `None`
+    pub max_depth: Option<usize>,
+}
+
+/// One path a walk found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalkEntry {
+    /// The path relative to the root, `/`-separated
+    pub relative: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// The files below `root`, and its directories if `options.directories` is
+/// set, sorted by path. Symbolic links are returned but not followed.
+pub fn walk(root: &Path, options: &WalkOptions) -> io::Result<Vec<WalkEntry>> {
+    let mut entries = Vec::new();
+    let mut walker = Walker::new(options);
+    let visible = |relative: &str| options.dot || !relative.split('/').any(is_hidden);
+    walker.visit(
+        root,
+        "",
+        0,
+        &mut |entry| {
+            if visible(&entry.relative) {
+                entries.push(entry);
+            }
+        },
+        &visible,
+    )?;
+    Ok(entries)
+}
+
+/// The paths under `cwd` matching `pattern`, sorted. A relative pattern
+/// gives paths relative to `cwd`, an absolute one absolute paths.
+pub fn glob(pattern: &str, cwd: &Path, options: &WalkOptions) -> io::Result<Vec<String>> {
+    let (root, relative_pattern, prefix) = match pattern.strip_prefix('/') {
+        Some(rest) => (PathBuf::from("/"), rest, "/"),
+        None => (cwd.to_path_buf(), pattern, ""),
+    };
+    let glob = Glob::new(relative_pattern, options.dot);
+
+    let mut matches = Vec::new();
+    let mut walker = Walker::new(options);
+    walker.visit(
+        &root,
+        "",
+        0,
+        &mut |entry| {
+            if glob.matches(&entry.relative) {
+                matches.push(format!("{}{}", prefix, entry.relative));
+            }
+        },
+        &|relative| glob.could_match_below(relative),
+    )?;
+    Ok(matches)
+}
+
+struct Walker<'a> {
+    options: &'a WalkOptions,
+    rules: IgnoreRules,
+}
+
+impl<'a> Walker<'a> {
+    fn new(options: &'a WalkOptions) -> Self {
+        let mut rules = IgnoreRules::new();
+        if options.gitignore {
+            rules.add("", [".git/"]);
+        }
+        rules.add("", options.ignore.iter().map(String::as_str));
+        Self { options, rules }
+    }
+
+    /// Report the entries of `directory`, the root's `relative` path, in
+    /// name order, descending into the directories `descend` accepts
+    fn visit(
+        &mut self,
+        directory: &Path,
+        relative: &str,
+        depth: usize,
+        found: &mut dyn FnMut(WalkEntry),
+        descend: &dyn Fn(&str) -> bool,
+    ) -> io::Result<()> {
+        let rule_count = self.rules.rules.len();
+        if self.options.gitignore {
+            self.rules.add_gitignore(directory, relative)?;
+        }
+
+        let mut children: Vec<(String, PathBuf, bool)> = Vec::new();
+        for entry in fs::read_dir(directory)? {
+            let entry = entry?;
+            let is_dir = entry.file_type()?.is_dir();
+            children.push((entry.file_name().to_string_lossy().into_owned(), entry.path(), is_dir));
+        }
+        children.sort();
+
+        for (name, path, is_dir) in children {
+            let child = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+            if self.rules.is_ignored(&child, is_dir) {
+                continue;
+            }
+            if !is_dir || self.options.directories {
+                found(WalkEntry { relative: child.clone(), path: path.clone(), is_dir });
+            }
+            let within_depth = self.options.max_depth.is_none_or(|max_depth| depth < max_depth);
+            if is_dir && within_depth && descend(&child) {
+                self.visit(&path, &child, depth + 1, found, descend)?;
+            }
+        }
+
+        // A directory's .gitignore only applies inside it
+        self.rules.rules.truncate(rule_count);
+        Ok(())
+    }
+}
@@ -1,15 +1,29 @@
 //! HTTP client and server module
 
+use crate::dns::DnsCache;
 use crate::{Module, Value};
-use bebion_runtime::Runtime;
+use bebion_runtime::{AbortSignal, Runtime};
+use hyper::client::connect::dns::Name;
 use reqwest;
+use reqwest::dns::{Addrs, Resolve, Resolving};
 use serde_json;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
 
 pub struct HttpModule {
     exports: HashMap<String, Value>,
+    /// Shared by every request, so connections are pooled and host names
+    /// resolve through the DNS cache
+    client: reqwest::Client,
+    server_limits: ServerLimits,
+    server_metrics: Arc<ServerMetrics>,
 }
 
 impl HttpModule {
@@ -23,12 +37,21 @@ impl HttpModule {
         exports.insert("request".to_string(), Value::Undefined);
         exports.insert("createServer".to_string(), Value::Undefined);
         
-        Self { exports }
+        let client = reqwest::Client::builder()
+            .dns_resolver(Arc::new(CachedResolver))
+            .build()
+            .unwrap_or_default();
+        
+        Self {
+            exports,
+            client,
+            server_limits: ServerLimits::default(),
+            server_metrics: Arc::default(),
+        }
     }
     
-    pub async fn get(&self, url: &str, headers: Option<HashMap<String, String>>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
-        let client = reqwest::Client::new();
-        let mut request = client.get(url);
+    pub async fn get(&self, url: &str, headers: Option<HashMap<String, String>>, signal: Option<&AbortSignal>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        let mut request = self.client.get(url);
         
         if let Some(headers) = headers {
             for (key, value) in headers {
@@ -36,26 +59,11 @@ impl HttpModule {
             }
         }
         
-        let response = request.send().await?;
-        
-        let status = response.status().as_u16();
-        let headers: HashMap<String, String> = response.headers()
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-            .collect();
-        
-        let body = response.text().await?;
-        
-        Ok(HttpResponse {
-            status,
-            headers,
-            body,
-        })
+        Self::send(request, signal).await
     }
     
-    pub async fn post(&self, url: &str, data: Option<String>, headers: Option<HashMap<String, String>>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
-        let client = reqwest::Client::new();
-        let mut request = client.post(url);
+    pub async fn post(&self, url: &str, data: Option<String>, headers: Option<HashMap<String, String>>, signal: Option<&AbortSignal>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        let mut request = self.client.post(url);
         
         if let Some(data) = data {
             request = request.body(data);
@@ -67,26 +75,11 @@ impl HttpModule {
             }
         }
         
-        let response = request.send().await?;
-        
-        let status = response.status().as_u16();
-        let headers: HashMap<String, String> = response.headers()
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-            .collect();
-        
-        let body = response.text().await?;
-        
-        Ok(HttpResponse {
-            status,
-            headers,
-            body,
-        })
+        Self::send(request, signal).await
     }
     
-    pub async fn put(&self, url: &str, data: Option<String>, headers: Option<HashMap<String, String>>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
-        let client = reqwest::Client::new();
-        let mut request = client.put(url);
+    pub async fn put(&self, url: &str, data: Option<String>, headers: Option<HashMap<String, String>>, signal: Option<&AbortSignal>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        let mut request = self.client.put(url);
         
         if let Some(data) = data {
             request = request.body(data);
@@ -98,26 +91,11 @@ impl HttpModule {
             }
         }
         
-        let response = request.send().await?;
-        
-        let status = response.status().as_u16();
-        let headers: HashMap<String, String> = response.headers()
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-            .collect();
-        
-        let body = response.text().await?;
-        
-        Ok(HttpResponse {
-            status,
-            headers,
-            body,
-        })
+        Self::send(request, signal).await
     }
     
-    pub async fn delete(&self, url: &str, headers: Option<HashMap<String, String>>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
-        let client = reqwest::Client::new();
-        let mut request = client.delete(url);
+    pub async fn delete(&self, url: &str, headers: Option<HashMap<String, String>>, signal: Option<&AbortSignal>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        let mut request = self.client.delete(url);
         
         if let Some(headers) = headers {
             for (key, value) in headers {
@@ -125,21 +103,44 @@ impl HttpModule {
             }
         }
         
-        let response = request.send().await?;
-        
-        let status = response.status().as_u16();
-        let headers: HashMap<String, String> = response.headers()
-            .iter()
-            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-            .collect();
-        
-        let body = response.text().await?;
-        
-        Ok(HttpResponse {
-            status,
-            headers,
-            body,
+        Self::send(request, signal).await
+    }
+    
+    /// Send `request` and read the whole response, giving up with an error
+    /// as soon as `signal` is aborted
+    async fn send(request: reqwest::RequestBuilder, signal: Option<&AbortSignal>) -> Result<HttpResponse, Box<dyn std::error::Error + Send + Sync>> {
+        crate::abortable(signal, async {
+            let response = request.send().await?;
+            
+            let status = response.status().as_u16();
+            let headers: HashMap<String, String> = response.headers()
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
+                .collect();
+            
+            let body = response.text().await?;
+            
+            Ok(HttpResponse {
+                status,
+                headers,
+                body,
+            })
         })
+        .await
+    }
+    
+    /// Limits applied to connections of servers started after the call
+    pub fn set_server_limits(&mut self, limits: ServerLimits) {
+        self.server_limits = limits;
+    }
+    
+    pub fn server_limits(&self) -> &ServerLimits {
+        &self.server_limits
+    }
+    
+    /// Counters of the servers started by this module
+    pub fn server_stats(&self) -> ServerStats {
+        self.server_metrics.snapshot()
     }
     
     pub async fn create_server<F>(&self, port: u16, handler: F) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
@@ -149,14 +150,33 @@ impl HttpModule {
         let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
         println!("HTTP server listening on port {}", port);
         
+        let handler = Arc::new(handler);
+        let limits = Arc::new(self.server_limits.clone());
+        let connections = Arc::new(Semaphore::new(limits.max_connections));
+        
         loop {
-            let (stream, _) = listener.accept().await?;
-            let handler = &handler;
+            let (mut stream, _) = listener.accept().await?;
+            let metrics = Arc::clone(&self.server_metrics);
+            
+            let Ok(permit) = Arc::clone(&connections).try_acquire_owned() else {
+                metrics.rejected_connections.fetch_add(1, Ordering::Relaxed);
+                tokio::spawn(async move {
+                    let _ = Self::write_response(&mut stream, &HttpResponse::status(503)).await;
+                });
+                continue;
+            };
+            
+            metrics.connections.fetch_add(1, Ordering::Relaxed);
+            metrics.active_connections.fetch_add(1, Ordering::Relaxed);
+            let handler = Arc::clone(&handler);
+            let limits = Arc::clone(&limits);
             
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_connection(stream, handler).await {
+                if let Err(e) = Self::handle_connection(stream, &*handler, &limits, &metrics).await {
                     eprintln!("Error handling connection: {}", e);
                 }
+                metrics.active_connections.fetch_sub(1, Ordering::Relaxed);
+                drop(permit);
             });
         }
     }
@@ -164,24 +184,104 @@ impl HttpModule {
     async fn handle_connection<F>(
         mut stream: TcpStream,
         handler: &F,
+        limits: &ServerLimits,
+        metrics: &ServerMetrics,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
     where
         F: Fn(HttpRequest) -> HttpResponse,
     {
-        let mut buffer = [0; 1024];
-        let n = stream.read(&mut buffer).await?;
-        
-        let request_str = String::from_utf8_lossy(&buffer[..n]);
-        let request = Self::parse_request(&request_str)?;
+        let request = match Self::read_request(&mut stream, limits).await {
+            Ok(request) => request,
+            Err(RequestError::Io(e)) => return Err(e.into()),
+            Err(RequestError::Rejected(status)) => {
+                match status {
+                    408 => &metrics.timeouts,
+                    413 => &metrics.payload_too_large,
+                    431 => &metrics.headers_too_large,
+                    _ => &metrics.bad_requests,
+                }
+                .fetch_add(1, Ordering::Relaxed);
+                return Self::write_response(&mut stream, &HttpResponse::status(status)).await;
+            }
+        };
         
+        metrics.requests.fetch_add(1, Ordering::Relaxed);
         let response = handler(request);
+        Self::write_response(&mut stream, &response).await
+    }
+    
+    /// Read one request, giving up once it outgrows `limits` or the client
+    /// takes longer than the timeouts to send it. The timeouts bound the
+    /// whole head and the whole body rather than each read, so a client
+    /// trickling a byte at a time is cut off all the same.
+    async fn read_request(stream: &mut TcpStream, limits: &ServerLimits) -> Result<HttpRequest, RequestError> {
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 1024];
+        
+        let head_end = timeout(limits.header_timeout, async {
+            loop {
+                if let Some(end) = find_head_end(&buffer) {
+                    return Ok(end);
+                }
+                if buffer.len() > limits.max_header_bytes {
+                    return Err(RequestError::Rejected(431));
+                }
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    return Err(RequestError::Rejected(400));
+                }
+                buffer.extend_from_slice(&chunk[..n]);
+            }
+        })
+        .await
+        .map_err(|_| RequestError::Rejected(408))??;
         
-        let response_str = format!(
-            "HTTP/1.1 {} OK\r\nContent-Length: {}\r\n\r\n{}",
-            response.status,
+        if head_end > limits.max_header_bytes {
+            return Err(RequestError::Rejected(431));
+        }
+        
+        let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+        let mut request = Self::parse_request(&head).map_err(|_| RequestError::Rejected(400))?;
+        
+        let content_length = match request.header("content-length") {
+            Some(value) => value.parse::<usize>().map_err(|_| RequestError::Rejected(400))?,
+            None => 0,
+        };
+        if content_length > limits.max_body_bytes {
+            return Err(RequestError::Rejected(413));
+        }
+        
+        let mut body = buffer.split_off(head_end + 4);
+        body.truncate(content_length);
+        
+        timeout(limits.body_timeout, async {
+            while body.len() < content_length {
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    return Err(RequestError::Rejected(400));
+                }
+                let wanted = (content_length - body.len()).min(n);
+                body.extend_from_slice(&chunk[..wanted]);
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|_| RequestError::Rejected(408))??;
+        
+        request.body = String::from_utf8_lossy(&body).into_owned();
+        Ok(request)
+    }
+    
+    async fn write_response(stream: &mut TcpStream, response: &HttpResponse) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut response_str = format!("HTTP/1.1 {} {}\r\n", response.status, reason_phrase(response.status));
+        for (key, value) in &response.headers {
+            response_str.push_str(&format!("{}: {}\r\n", key, value));
+        }
+        response_str.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
             response.body.len(),
             response.body
-        );
+        ));
         
         stream.write_all(response_str.as_bytes()).await?;
         stream.flush().await?;
@@ -189,6 +289,7 @@ impl HttpModule {
         Ok(())
     }
     
+    /// Parse the request line and headers of a request head
     fn parse_request(request_str: &str) -> Result<HttpRequest, Box<dyn std::error::Error + Send + Sync>> {
         let lines: Vec<&str> = request_str.split("\r\n").collect();
         
@@ -205,33 +306,148 @@ impl HttpModule {
         let path = request_line[1].to_string();
         
         let mut headers = HashMap::new();
-        let mut i = 1;
         
-        while i < lines.len() && !lines[i].is_empty() {
-            if let Some(colon_pos) = lines[i].find(':') {
-                let key = lines[i][..colon_pos].trim().to_string();
-                let value = lines[i][colon_pos + 1..].trim().to_string();
+        for line in &lines[1..] {
+            if let Some(colon_pos) = line.find(':') {
+                let key = line[..colon_pos].trim().to_string();
+                let value = line[colon_pos + 1..].trim().to_string();
                 headers.insert(key, value);
             }
-            i += 1;
         }
         
-        // Body would be after empty line
-        let body = if i + 1 < lines.len() {
-            lines[i + 1..].join("\r\n")
-        } else {
-            String::new()
-        };
-        
         Ok(HttpRequest {
             method,
             path,
             headers,
-            body,
+            body: String::new(),
         })
     }
 }
 
+/// Where the blank line ending a request head starts
+fn find_head_end(buffer: &[u8]) -> Option<usize> {
+    buffer.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// Resolves the client's host names through the shared `DnsCache`. The
+/// connector races the addresses it returns, IPv6 and IPv4 alternating.
+struct CachedResolver;
+
+impl Resolve for CachedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addresses = DnsCache::shared().lookup(&host).await?;
+            let addresses: Addrs = Box::new(addresses.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addresses)
+        })
+    }
+}
+
+/// Why a request could not be read
+enum RequestError {
+    Io(std::io::Error),
+    /// The client gets a response with this status and the connection closes
+    Rejected(u16),
+}
+
+impl From<std::io::Error> for RequestError {
+    fn from(error: std::io::Error) -> Self {
+        RequestError::Io(error)
+    }
+}
+
+/// Bounds on what the server accepts from a client. A request whose head
+/// outgrows `max_header_bytes` gets a 431, one declaring a body over
+/// `max_body_bytes` a 413, and a client too slow to send either a 408.
+/// Connections over `max_connections` get a 503 and are closed.
+#[derive(Debug, Clone)]
+pub struct ServerLimits {
+    pub max_header_bytes: usize,
+    pub max_body_bytes: usize,
+    pub header_timeout: Duration,
+    pub body_timeout: Duration,
+    pub max_connections: usize,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        Self {
+            max_header_bytes: 16 * 1024,
+            max_body_bytes: 1024 * 1024,
+            header_timeout: Duration::from_secs(10),
+            body_timeout: Duration::from_secs(30),
+            max_connections: 1024,
+        }
+    }
+}
+
+/// Live counters of a server
+#[derive(Debug, Default)]
+struct ServerMetrics {
+    connections: AtomicU64,
+    active_connections: AtomicU64,
+    rejected_connections: AtomicU64,
+    requests: AtomicU64,
+    bad_requests: AtomicU64,
+    headers_too_large: AtomicU64,
+    payload_too_large: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl ServerMetrics {
+    fn snapshot(&self) -> ServerStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        ServerStats {
+            connections: load(&self.connections),
+            active_connections: load(&self.active_connections),
+            rejected_connections: load(&self.rejected_connections),
+            requests: load(&self.requests),
+            bad_requests: load(&self.bad_requests),
+            headers_too_large: load(&self.headers_too_large),
+            payload_too_large: load(&self.payload_too_large),
+            timeouts: load(&self.timeouts),
+        }
+    }
+}
+
+/// Server counters: connections accepted, open and turned away over
+/// `max_connections`, requests handed to the handler, and requests
+/// answered with 400, 431, 413 and 408
+#[derive(Debug, Clone, Default)]
+pub struct ServerStats {
+    pub connections: u64,
+    pub active_connections: u64,
+    pub rejected_connections: u64,
+    pub requests: u64,
+    pub bad_requests: u64,
+    pub headers_too_large: u64,
+    pub payload_too_large: u64,
+    pub timeouts: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct HttpRequest {
     pub method: String,
@@ -240,6 +456,16 @@ pub struct HttpRequest {
     pub body: String,
 }
 
+impl HttpRequest {
+    /// The value of header `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HttpResponse {
     pub status: u16,
@@ -247,6 +473,17 @@ pub struct HttpResponse {
     pub body: String,
 }
 
+impl HttpResponse {
+    /// An empty response with `status`
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: HashMap::new(),
+            body: String::new(),
+        }
+    }
+}
+
 impl Module for HttpModule {
     fn name(&self) -> &str {
         "http"
@@ -2,18 +2,30 @@
 //! 
 //! Built-in modules providing filesystem, networking, crypto, and other APIs.
 
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod cli;
 pub mod console;
+#[cfg(feature = "crypto")]
 pub mod crypto;
+#[cfg(any(feature = "http", feature = "net"))]
+pub mod dns;
 pub mod fs;
+pub mod glob;
+#[cfg(feature = "http")]
 pub mod http;
+#[cfg(feature = "net")]
 pub mod net;
 pub mod process;
+pub mod tasks;
 pub mod timers;
+pub mod tty;
 pub mod url;
 pub mod util;
 
-use bebion_runtime::{Runtime, Value};
+use bebion_runtime::{AbortSignal, Runtime, RuntimeError, Value};
 use std::collections::HashMap;
+use std::future::Future;
 
 pub struct StandardLibrary {
     modules: HashMap<String, Box<dyn Module>>,
@@ -32,13 +44,21 @@ impl StandardLibrary {
         };
         
         // Register built-in modules
+        #[cfg(feature = "archive")]
+        stdlib.register_module(Box::new(archive::ArchiveModule::new()));
+        stdlib.register_module(Box::new(cli::CliModule::new()));
         stdlib.register_module(Box::new(console::ConsoleModule::new()));
+        #[cfg(feature = "crypto")]
         stdlib.register_module(Box::new(crypto::CryptoModule::new()));
         stdlib.register_module(Box::new(fs::FileSystemModule::new()));
+        #[cfg(feature = "http")]
         stdlib.register_module(Box::new(http::HttpModule::new()));
+        #[cfg(feature = "net")]
         stdlib.register_module(Box::new(net::NetworkModule::new()));
         stdlib.register_module(Box::new(process::ProcessModule::new()));
+        stdlib.register_module(Box::new(tasks::AsyncModule::new()));
         stdlib.register_module(Box::new(timers::TimersModule::new()));
+        stdlib.register_module(Box::new(tty::TtyModule::new()));
         stdlib.register_module(Box::new(url::UrlModule::new()));
         stdlib.register_module(Box::new(util::UtilModule::new()));
         
@@ -72,6 +92,39 @@ impl StandardLibrary {
     }
 }
 
+/// The `signal` of an options object passed to a module function, for
+/// operations scripts can cancel with an `AbortController`
+pub fn signal_option(runtime: &mut Runtime, options: &Value) -> Result<Option<AbortSignal>, RuntimeError> {
+    if !matches!(options, Value::Object(_)) {
+        return Ok(None);
+    }
+    match runtime.get_property(options, "signal")? {
+        Value::Undefined => Ok(None),
+        signal => runtime
+            .abort_signal(&signal)
+            .map(Some)
+            .ok_or_else(|| RuntimeError::TypeError("The \"signal\" option must be an AbortSignal".to_string())),
+    }
+}
+
+/// Run `operation` unless `signal` is aborted first, failing with the
+/// abort reason if it is
+pub(crate) async fn abortable<T>(
+    signal: Option<&AbortSignal>,
+    operation: impl Future<Output = Result<T, Box<dyn std::error::Error + Send + Sync>>>,
+) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(signal) = signal else {
+        return operation.await;
+    };
+    if let Some(reason) = signal.reason() {
+        return Err(format!("The operation was aborted: {}", reason).into());
+    }
+    tokio::select! {
+        result = operation => result,
+        reason = signal.aborted() => Err(format!("The operation was aborted: {}", reason).into()),
+    }
+}
+
 impl Default for StandardLibrary {
     fn default() -> Self {
         Self::new()
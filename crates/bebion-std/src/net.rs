@@ -1,9 +1,15 @@
-//! Network module for TCP and UDP
+//! Network module for TCP, UDP and local sockets
+//!
+//! Besides TCP, stream connections and servers can use a filesystem path:
+//! a Unix domain socket, or on Windows a named pipe such as
+//! `\\.\pipe\docker_engine`. That is how local daemons such as Docker,
+//! systemd and databases are usually reached.
 
+use crate::dns;
 use crate::{Module, Value};
 use bebion_runtime::Runtime;
 use std::collections::HashMap;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream, UdpSocket};
 
 pub struct NetworkModule {
@@ -17,6 +23,8 @@ impl NetworkModule {
         exports.insert("createTcpServer".to_string(), Value::Undefined);
         exports.insert("connectTcp".to_string(), Value::Undefined);
         exports.insert("createUdpSocket".to_string(), Value::Undefined);
+        exports.insert("connect".to_string(), Value::Undefined);
+        exports.insert("createServer".to_string(), Value::Undefined);
         
         Self { exports }
     }
@@ -39,30 +47,144 @@ impl NetworkModule {
         }
     }
     
+    /// Connect to `host:port`, resolving the host through the shared DNS
+    /// cache and racing its IPv6 and IPv4 addresses
     pub async fn connect_tcp(&self, address: &str) -> Result<TcpConnection, Box<dyn std::error::Error + Send + Sync>> {
-        let stream = TcpStream::connect(address).await?;
+        let (host, port) = address
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| format!("Invalid address '{}': expected host:port", address))?;
+        let stream = dns::connect_host(host, port).await?;
         Ok(TcpConnection::new(stream, address.to_string()))
     }
     
+    /// Listen on the Unix domain socket at `path`, or on Windows the named
+    /// pipe `path`, calling `handler` with each connection
+    #[cfg(unix)]
+    pub async fn create_path_server<F>(&self, path: &str, handler: F) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: Fn(Connection) + Send + Sync + Clone + 'static,
+    {
+        let listener = tokio::net::UnixListener::bind(path)?;
+        println!("Unix socket server listening on {}", path);
+        
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let handler = handler.clone();
+            let path = path.to_string();
+            
+            tokio::spawn(async move {
+                handler(Connection::from_stream(stream, path));
+            });
+        }
+    }
+    
+    #[cfg(windows)]
+    pub async fn create_path_server<F>(&self, path: &str, handler: F) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+    where
+        F: Fn(Connection) + Send + Sync + Clone + 'static,
+    {
+        use tokio::net::windows::named_pipe::ServerOptions;
+        
+        let mut server = ServerOptions::new().first_pipe_instance(true).create(path)?;
+        println!("Named pipe server listening on {}", path);
+        
+        loop {
+            server.connect().await?;
+            // Open the next instance before handing this one off, so
+            // clients never find the pipe missing
+            let connected = std::mem::replace(&mut server, ServerOptions::new().create(path)?);
+            let handler = handler.clone();
+            let path = path.to_string();
+            
+            tokio::spawn(async move {
+                handler(Connection::from_stream(connected, path));
+            });
+        }
+    }
+    
+    /// Connect to the Unix domain socket at `path`, or on Windows the named
+    /// pipe `path`
+    #[cfg(unix)]
+    pub async fn connect_path(&self, path: &str) -> Result<Connection, Box<dyn std::error::Error + Send + Sync>> {
+        let stream = tokio::net::UnixStream::connect(path).await?;
+        Ok(Connection::from_stream(stream, path.to_string()))
+    }
+    
+    #[cfg(windows)]
+    pub async fn connect_path(&self, path: &str) -> Result<Connection, Box<dyn std::error::Error + Send + Sync>> {
+        use tokio::net::windows::named_pipe::ClientOptions;
+        
+        // ERROR_PIPE_BUSY: every instance is taken, so wait for the server
+        // to open another
+        const ERROR_PIPE_BUSY: i32 = 231;
+        let client = loop {
+            match ClientOptions::new().open(path) {
+                Ok(client) => break client,
+                Err(error) if error.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                }
+                Err(error) => return Err(error.into()),
+            }
+        };
+        Ok(Connection::from_stream(client, path.to_string()))
+    }
+    
+    /// Connect as `net.connect` does: to `options.path` if given, otherwise
+    /// to `options.host` (default `localhost`) on `options.port`
+    pub async fn connect(&self, options: &ConnectOptions) -> Result<Connection, Box<dyn std::error::Error + Send + Sync>> {
+        match (&options.path, options.port) {
+            (Some(path), _) => self.connect_path(path).await,
+            (None, Some(port)) => {
+                let host = options.host.as_deref().unwrap_or("localhost");
+                let stream = dns::connect_host(host, port).await?;
+                Ok(Connection::new(stream, format!("{}:{}", host, port)))
+            }
+            (None, None) => Err("net.connect needs a path or a port".into()),
+        }
+    }
+    
     pub async fn create_udp_socket(&self, address: &str) -> Result<UdpConnection, Box<dyn std::error::Error + Send + Sync>> {
         let socket = UdpSocket::bind(address).await?;
         Ok(UdpConnection::new(socket))
     }
 }
 
-pub struct TcpConnection {
-    stream: TcpStream,
+/// Where `NetworkModule::connect` connects
+#[derive(Debug, Clone, Default)]
+pub struct ConnectOptions {
+    /// Unix domain socket or named pipe, taking precedence over host and port
+    pub path: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+}
+
+/// A byte stream a `Connection` can run over
+trait NetStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> NetStream for T {}
+
+/// A stream connection over TCP, a Unix domain socket or a named pipe
+pub struct Connection {
+    stream: Box<dyn NetStream>,
     remote_address: String,
 }
 
-impl TcpConnection {
+pub type TcpConnection = Connection;
+
+impl Connection {
     pub fn new(stream: TcpStream, remote_address: String) -> Self {
+        Self::from_stream(stream, remote_address)
+    }
+    
+    fn from_stream<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(stream: S, remote_address: String) -> Self {
         Self {
-            stream,
+            stream: Box::new(stream),
             remote_address,
         }
     }
     
+    /// `host:port` of a TCP peer, or the path of a local socket
     pub fn remote_address(&self) -> &str {
         &self.remote_address
     }
@@ -8,7 +8,7 @@ use std::process;
 
 pub struct ProcessModule {
     exports: HashMap<String, Value>,
-    exit_handlers: Vec<Box<dyn FnOnce() + Send>>,
+    exit_handlers: Vec<Box<dyn FnOnce() + Send + Sync>>,
 }
 
 impl ProcessModule {
@@ -110,7 +110,7 @@ impl ProcessModule {
     
     pub fn on_exit<F>(&mut self, handler: F)
     where
-        F: FnOnce() + Send + 'static,
+        F: FnOnce() + Send + Sync + 'static,
     {
         self.exit_handlers.push(Box::new(handler));
     }
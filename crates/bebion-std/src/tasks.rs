@@ -0,0 +1,810 @@
+//! The `async` module: bounded pools, task groups, retries and timeouts
+//!
+//! Everything here is built on promises and `AbortSignal`s. A task is a
+//! function the helpers call, usually an async one, and a task that throws
+//! counts as one that rejects. Tasks get an `AbortSignal` as their last
+//! argument, aborted once the work they belong to has failed or been
+//! cancelled so they can stop early; the helpers stop starting tasks and
+//! settle straight away. Each helper takes a `signal` option to cancel it
+//! from outside.
+//!
+//! - `mapLimit(items, limit, task, { signal })` calls
+//!   `task(item, index, signal)` for each item of an array, at most `limit`
+//!   at a time, and fulfills with the results in order or rejects with the
+//!   first failure
+//! - `pool(tasks, limit, { signal })` does the same for an array of
+//!   functions, calling each as `task(signal)`
+//! - `TaskGroup({ signal })` makes a group to `spawn(task)` tasks in.
+//!   `wait()` settles once every task has: with their results, or with the
+//!   first failure, which cancels the rest. `cancel(reason)` cancels them.
+//! - `retry(task, { retries, minDelay, maxDelay, factor, jitter, signal })`
+//!   calls `task(attempt, signal)` until it fulfills, waiting `backoff`
+//!   between attempts, and rejects with the last failure
+//! - `backoff(attempt, options)` is the delay `retry` waits after a failed
+//!   attempt
+//! - `timeout(task, ms, { signal })` settles like a promise or a task's
+//!   result, or rejects with a `TimeoutError` and aborts the task's signal
+//!   after `ms`
+//! - `sleep(ms, { signal })` fulfills after `ms`
+//!
+//! Delays go through the host's `scheduler.wait`, so `sleep`, `retry` and
+//! `timeout` need an engine with an event loop.
+
+use crate::{Module, Value};
+use bebion_gc::GcHandle;
+use bebion_runtime::{NativeFunction, Runtime, RuntimeError, RuntimeResult};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+type SharedPins = Arc<Mutex<Pins>>;
+
+/// What `mapLimit` and the other helpers export as: called with the
+/// values held for work in flight
+type AsyncBinding = fn(&mut Runtime, &SharedPins, &[Value]) -> RuntimeResult<Value>;
+
+pub struct AsyncModule {
+    exports: HashMap<String, Value>,
+    pins: SharedPins,
+}
+
+/// Values the helpers hold on to while their work is in flight, which
+/// scripts may no longer reference
+#[derive(Default)]
+struct Pins {
+    last_id: u64,
+    values: HashMap<u64, Vec<Value>>,
+}
+
+impl Pins {
+    fn open(&mut self, values: Vec<Value>) -> u64 {
+        self.last_id += 1;
+        self.values.insert(self.last_id, values);
+        self.last_id
+    }
+
+    fn add(&mut self, id: u64, value: Value) {
+        if let Some(values) = self.values.get_mut(&id) {
+            values.push(value);
+        }
+    }
+
+    fn close(&mut self, id: u64) {
+        self.values.remove(&id);
+    }
+
+    fn trace(&self, roots: &mut Vec<GcHandle>) {
+        roots.extend(self.values.values().flatten().filter_map(Value::as_handle));
+    }
+}
+
+/// How long `retry` waits after a failed attempt: `min_delay` times
+/// `factor` to the power of the attempts before it, up to `max_delay`.
+/// With `jitter`, a random delay between zero and that, which spreads out
+/// clients that failed together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    pub min_delay: f64,
+    pub max_delay: f64,
+    pub factor: f64,
+    pub jitter: bool,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            min_delay: 100.0,
+            max_delay: f64::INFINITY,
+            factor: 2.0,
+            jitter: false,
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay in milliseconds after failed attempt `attempt`, counting
+    /// from 0, with `random` in `[0, 1)` for the jitter
+    pub fn delay(&self, attempt: u32, random: f64) -> f64 {
+        let delay = (self.min_delay * self.factor.powi(attempt.min(1024) as i32)).min(self.max_delay);
+        if self.jitter {
+            delay * random
+        } else {
+            delay
+        }
+    }
+
+    fn from_options(runtime: &mut Runtime, options: &Value) -> RuntimeResult<Self> {
+        let defaults = Self::default();
+        Ok(Self {
+            min_delay: number_option(runtime, options, "minDelay", defaults.min_delay)?,
+            max_delay: number_option(runtime, options, "maxDelay", defaults.max_delay)?,
+            factor: number_option(runtime, options, "factor", defaults.factor)?,
+            jitter: option(runtime, options, "jitter")?.is_some_and(|jitter| jitter.to_boolean()),
+        })
+    }
+}
+
+impl AsyncModule {
+    pub fn new() -> Self {
+        let bindings: [(&str, AsyncBinding); 7] = [
+            ("mapLimit", |runtime, pins, args| {
+                let items = array_argument(runtime, args, 0, "items")?;
+                let limit = limit_argument(args, 1)?;
+                let task = function_argument(runtime, args, 2, "task")?;
+                let parent = signal_option(runtime, &argument(args, 3))?;
+                map_limit(runtime, pins, items, limit, Some(task), parent)
+            }),
+            ("pool", |runtime, pins, args| {
+                let tasks = array_argument(runtime, args, 0, "tasks")?;
+                if tasks.iter().any(|task| !runtime.is_callable(task)) {
+                    return Err(RuntimeError::TypeError("Every task in a pool must be a function".to_string()));
+                }
+                let limit = limit_argument(args, 1)?;
+                let parent = signal_option(runtime, &argument(args, 2))?;
+                map_limit(runtime, pins, tasks, limit, None, parent)
+            }),
+            ("TaskGroup", |runtime, pins, args| {
+                let parent = signal_option(runtime, &argument(args, 0))?;
+                task_group(runtime, pins, parent)
+            }),
+            ("retry", |runtime, pins, args| {
+                let task = function_argument(runtime, args, 0, "task")?;
+                let options = argument(args, 1);
+                let retries = number_option(runtime, &options, "retries", 3.0)?;
+                if retries.is_nan() || retries < 0.0 {
+                    return Err(RuntimeError::RangeError(format!(
+                        "The \"retries\" option must be a non-negative number, received {}",
+                        retries
+                    )));
+                }
+                let backoff = Backoff::from_options(runtime, &options)?;
+                let signal = signal_option(runtime, &options)?;
+                retry(runtime, pins, task, retries.min(u32::MAX as f64) as u32, backoff, signal)
+            }),
+            ("backoff", |runtime, _, args| {
+                let attempt = argument(args, 0).to_number()?.max(0.0);
+                let backoff = Backoff::from_options(runtime, &argument(args, 1))?;
+                Ok(Value::Number(backoff.delay(attempt.min(u32::MAX as f64) as u32, runtime.math_random())))
+            }),
+            ("timeout", |runtime, pins, args| {
+                let milliseconds = argument(args, 1).to_number()?;
+                let parent = signal_option(runtime, &argument(args, 2))?;
+                timeout(runtime, pins, argument(args, 0), milliseconds, parent)
+            }),
+            ("sleep", |runtime, _, args| {
+                let milliseconds = argument(args, 0).to_number()?;
+                let signal = signal_option(runtime, &argument(args, 1))?;
+                delay(runtime, milliseconds, signal.as_ref())
+            }),
+        ];
+
+        let pins = SharedPins::default();
+        let exports = bindings
+            .into_iter()
+            .map(|(name, binding)| {
+                let pins = Arc::clone(&pins);
+                let function = NativeFunction::new(name, move |runtime, args| binding(runtime, &pins, args));
+                (name.to_string(), Value::NativeFunction(function))
+            })
+            .collect();
+
+        Self { exports, pins }
+    }
+}
+
+/// A `mapLimit` or `pool` in flight
+struct MapLimit {
+    pins: SharedPins,
+    pin: u64,
+    items: Vec<Value>,
+    /// The function to call with each item, or `None` when the items are
+    /// the tasks
+    mapper: Option<Value>,
+    results: Vec<Value>,
+    /// Index of the next item to start
+    next: usize,
+    settled: usize,
+    done: bool,
+    promise: GcHandle,
+    signal: Value,
+}
+
+fn map_limit(
+    runtime: &mut Runtime,
+    pins: &SharedPins,
+    items: Vec<Value>,
+    limit: usize,
+    mapper: Option<Value>,
+    parent: Option<Value>,
+) -> RuntimeResult<Value> {
+    let promise = runtime.create_promise();
+    let signal = child_signal(runtime, parent.as_ref())?;
+    if is_aborted(runtime, &signal) {
+        let reason = runtime.abort_reason(&signal);
+        runtime.reject_promise(promise, reason);
+        return Ok(Value::Object(promise));
+    }
+    if items.is_empty() {
+        let results = runtime.create_array(Vec::new());
+        runtime.resolve_promise(promise, results);
+        return Ok(Value::Object(promise));
+    }
+
+    let pinned = items.iter().chain(&mapper).cloned().chain([Value::Object(promise), signal.clone()]).collect();
+    let pin = pins.lock().unwrap().open(pinned);
+    let count = items.len();
+    let state = Arc::new(Mutex::new(MapLimit {
+        pins: Arc::clone(pins),
+        pin,
+        items,
+        mapper,
+        results: vec![Value::Undefined; count],
+        next: 0,
+        settled: 0,
+        done: false,
+        promise,
+        signal: signal.clone(),
+    }));
+
+    // A failure aborts the signal too, so this is where every map that
+    // does not complete ends
+    let cancelled = Arc::clone(&state);
+    on_abort(runtime, &signal, move |runtime, reason| {
+        MapLimit::finish(&cancelled, runtime, Err(reason));
+        Ok(())
+    })?;
+    for _ in 0..limit.min(count) {
+        MapLimit::start_next(&state, runtime)?;
+    }
+    Ok(Value::Object(promise))
+}
+
+impl MapLimit {
+    fn start_next(state: &Arc<Mutex<Self>>, runtime: &mut Runtime) -> RuntimeResult<()> {
+        let (index, item, mapper, signal) = {
+            let mut map = state.lock().unwrap();
+            if map.done || map.next == map.items.len() {
+                return Ok(());
+            }
+            map.next += 1;
+            let index = map.next - 1;
+            (index, map.items[index].clone(), map.mapper.clone(), map.signal.clone())
+        };
+        let result = match &mapper {
+            Some(mapper) => call_task(runtime, mapper, &[item, Value::Number(index as f64), signal.clone()])?,
+            None => call_task(runtime, &item, std::slice::from_ref(&signal))?,
+        };
+
+        let fulfilled = Arc::clone(state);
+        react(
+            runtime,
+            result,
+            move |runtime, value| {
+                let results = {
+                    let mut map = fulfilled.lock().unwrap();
+                    if map.done {
+                        return Ok(());
+                    }
+                    map.pins.lock().unwrap().add(map.pin, value.clone());
+                    map.results[index] = value;
+                    map.settled += 1;
+                    (map.settled == map.items.len()).then(|| map.results.clone())
+                };
+                match results {
+                    Some(results) => {
+                        let results = runtime.create_array(results);
+                        Self::finish(&fulfilled, runtime, Ok(results));
+                        Ok(())
+                    }
+                    None => Self::start_next(&fulfilled, runtime),
+                }
+            },
+            move |runtime, reason| runtime.abort(&signal, reason),
+        );
+        Ok(())
+    }
+
+    fn finish(state: &Arc<Mutex<Self>>, runtime: &mut Runtime, outcome: Result<Value, Value>) {
+        let (promise, pins, pin) = {
+            let mut map = state.lock().unwrap();
+            if map.done {
+                return;
+            }
+            map.done = true;
+            map.items.clear();
+            map.results.clear();
+            (map.promise, Arc::clone(&map.pins), map.pin)
+        };
+        settle(runtime, promise, outcome);
+        pins.lock().unwrap().close(pin);
+    }
+}
+
+/// A `TaskGroup` and the tasks running in it
+struct TaskGroup {
+    pins: SharedPins,
+    /// Pin of the signal, the results and the pending `wait()` promises,
+    /// closed once the group object is collected
+    pin: u64,
+    /// Pin of the group object while tasks run or `wait()` promises are
+    /// pending
+    busy: Option<u64>,
+    object: Value,
+    signal: Value,
+    results: Vec<Value>,
+    running: usize,
+    /// The first failure, or the reason the group was cancelled with
+    failure: Option<Value>,
+    waiters: Vec<GcHandle>,
+}
+
+impl TaskGroup {
+    /// Keep the group object alive while there is work it must see through
+    fn update_busy(&mut self) {
+        let busy = self.running > 0 || !self.waiters.is_empty();
+        match (busy, self.busy) {
+            (true, None) => self.busy = Some(self.pins.lock().unwrap().open(vec![self.object.clone()])),
+            (false, Some(pin)) => {
+                self.pins.lock().unwrap().close(pin);
+                self.busy = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Settle the `wait()` promises if no task is running
+    fn settle_waiters(state: &Arc<Mutex<Self>>, runtime: &mut Runtime) {
+        let (waiters, outcome) = {
+            let mut group = state.lock().unwrap();
+            if group.running > 0 {
+                return;
+            }
+            let waiters = std::mem::take(&mut group.waiters);
+            group.update_busy();
+            (waiters, group.failure.clone().ok_or_else(|| group.results.clone()))
+        };
+        // Fulfilled with the results, or rejected with the failure
+        let outcome = match outcome {
+            Ok(failure) => Err(failure),
+            Err(results) => Ok(runtime.create_array(results)),
+        };
+        for promise in waiters {
+            settle(runtime, promise, outcome.clone());
+        }
+    }
+}
+
+fn task_group(runtime: &mut Runtime, pins: &SharedPins, parent: Option<Value>) -> RuntimeResult<Value> {
+    let signal = child_signal(runtime, parent.as_ref())?;
+    let failure = is_aborted(runtime, &signal).then(|| runtime.abort_reason(&signal));
+    let pin = pins.lock().unwrap().open(vec![signal.clone()]);
+    let state = Arc::new(Mutex::new(TaskGroup {
+        pins: Arc::clone(pins),
+        pin,
+        busy: None,
+        object: Value::Undefined,
+        signal: signal.clone(),
+        results: Vec::new(),
+        running: 0,
+        failure,
+        waiters: Vec::new(),
+    }));
+
+    let cancelled = Arc::clone(&state);
+    on_abort(runtime, &signal, move |_, reason| {
+        cancelled.lock().unwrap().failure.get_or_insert(reason);
+        Ok(())
+    })?;
+
+    // `spawn(task)`: start `task(signal)` unless the group was cancelled,
+    // returning whether it started
+    let spawner = Arc::clone(&state);
+    let spawn = NativeFunction::new("spawn", move |runtime, args| {
+        let task = function_argument(runtime, args, 0, "task")?;
+        let (index, signal) = {
+            let mut group = spawner.lock().unwrap();
+            if group.failure.is_some() {
+                return Ok(Value::Boolean(false));
+            }
+            group.results.push(Value::Undefined);
+            group.running += 1;
+            group.update_busy();
+            (group.results.len() - 1, group.signal.clone())
+        };
+        let result = call_task(runtime, &task, std::slice::from_ref(&signal))?;
+        let (fulfilled, rejected) = (Arc::clone(&spawner), Arc::clone(&spawner));
+        react(
+            runtime,
+            result,
+            move |runtime, value| {
+                {
+                    let mut group = fulfilled.lock().unwrap();
+                    group.pins.lock().unwrap().add(group.pin, value.clone());
+                    group.results[index] = value;
+                    group.running -= 1;
+                }
+                TaskGroup::settle_waiters(&fulfilled, runtime);
+                Ok(())
+            },
+            move |runtime, reason| {
+                // The first failure cancels the tasks still running
+                runtime.abort(&signal, reason)?;
+                rejected.lock().unwrap().running -= 1;
+                TaskGroup::settle_waiters(&rejected, runtime);
+                Ok(())
+            },
+        );
+        Ok(Value::Boolean(true))
+    });
+
+    // `wait()`: a promise of the results once every task has settled
+    let waiter = Arc::clone(&state);
+    let wait = NativeFunction::new("wait", move |runtime, _| {
+        let promise = runtime.create_promise();
+        {
+            let mut group = waiter.lock().unwrap();
+            group.pins.lock().unwrap().add(group.pin, Value::Object(promise));
+            group.waiters.push(promise);
+            group.update_busy();
+        }
+        TaskGroup::settle_waiters(&waiter, runtime);
+        Ok(Value::Object(promise))
+    });
+
+    // `cancel(reason)`: abort the group's signal
+    let cancel = {
+        let signal = signal.clone();
+        NativeFunction::new("cancel", move |runtime, args| {
+            runtime.abort(&signal, argument(args, 0))?;
+            Ok(Value::Undefined)
+        })
+    };
+
+    let object = runtime.create_object(HashMap::from([
+        ("signal".to_string(), signal),
+        ("spawn".to_string(), Value::NativeFunction(spawn)),
+        ("wait".to_string(), Value::NativeFunction(wait)),
+        ("cancel".to_string(), Value::NativeFunction(cancel)),
+    ]));
+    state.lock().unwrap().object = object.clone();
+    let pins = Arc::clone(pins);
+    runtime.register_finalizer(&object, move || pins.lock().unwrap().close(pin));
+    Ok(object)
+}
+
+/// A `retry` in flight
+struct Retry {
+    pins: SharedPins,
+    pin: u64,
+    task: Value,
+    promise: GcHandle,
+    signal: Option<Value>,
+    attempt: u32,
+    retries: u32,
+    backoff: Backoff,
+    done: bool,
+}
+
+fn retry(
+    runtime: &mut Runtime,
+    pins: &SharedPins,
+    task: Value,
+    retries: u32,
+    backoff: Backoff,
+    signal: Option<Value>,
+) -> RuntimeResult<Value> {
+    let promise = runtime.create_promise();
+    if let Some(signal) = signal.as_ref().filter(|signal| is_aborted(runtime, signal)) {
+        let reason = runtime.abort_reason(signal);
+        runtime.reject_promise(promise, reason);
+        return Ok(Value::Object(promise));
+    }
+
+    let pinned = [task.clone(), Value::Object(promise)].into_iter().chain(signal.clone()).collect();
+    let pin = pins.lock().unwrap().open(pinned);
+    let state = Arc::new(Mutex::new(Retry {
+        pins: Arc::clone(pins),
+        pin,
+        task,
+        promise,
+        signal: signal.clone(),
+        attempt: 0,
+        retries,
+        backoff,
+        done: false,
+    }));
+    if let Some(signal) = &signal {
+        let cancelled = Arc::clone(&state);
+        on_abort(runtime, signal, move |runtime, reason| {
+            Retry::finish(&cancelled, runtime, Err(reason));
+            Ok(())
+        })?;
+    }
+    Retry::attempt(&state, runtime)?;
+    Ok(Value::Object(promise))
+}
+
+impl Retry {
+    fn attempt(state: &Arc<Mutex<Self>>, runtime: &mut Runtime) -> RuntimeResult<()> {
+        let (task, attempt, signal) = {
+            let retry = state.lock().unwrap();
+            if retry.done {
+                return Ok(());
+            }
+            (retry.task.clone(), retry.attempt, retry.signal.clone())
+        };
+        let args = [Value::Number(attempt as f64), signal.unwrap_or(Value::Undefined)];
+        let result = call_task(runtime, &task, &args)?;
+
+        let (fulfilled, rejected) = (Arc::clone(state), Arc::clone(state));
+        react(
+            runtime,
+            result,
+            move |runtime, value| {
+                Self::finish(&fulfilled, runtime, Ok(value));
+                Ok(())
+            },
+            move |runtime, reason| {
+                let wait = {
+                    let mut retry = rejected.lock().unwrap();
+                    let waits = !retry.done && retry.attempt < retry.retries;
+                    let delay = retry.backoff.delay(retry.attempt, runtime.math_random());
+                    retry.attempt += 1;
+                    waits.then(|| (delay, retry.signal.clone()))
+                };
+                let Some((milliseconds, signal)) = wait else {
+                    Self::finish(&rejected, runtime, Err(reason));
+                    return Ok(());
+                };
+                let timer = delay(runtime, milliseconds, signal.as_ref())?;
+                let (elapsed, aborted) = (Arc::clone(&rejected), Arc::clone(&rejected));
+                react(
+                    runtime,
+                    timer,
+                    move |runtime, _| Self::attempt(&elapsed, runtime),
+                    move |runtime, reason| {
+                        Self::finish(&aborted, runtime, Err(reason));
+                        Ok(())
+                    },
+                );
+                Ok(())
+            },
+        );
+        Ok(())
+    }
+
+    fn finish(state: &Arc<Mutex<Self>>, runtime: &mut Runtime, outcome: Result<Value, Value>) {
+        let (promise, pins, pin) = {
+            let mut retry = state.lock().unwrap();
+            if retry.done {
+                return;
+            }
+            retry.done = true;
+            (retry.promise, Arc::clone(&retry.pins), retry.pin)
+        };
+        settle(runtime, promise, outcome);
+        pins.lock().unwrap().close(pin);
+    }
+}
+
+fn timeout(
+    runtime: &mut Runtime,
+    pins: &SharedPins,
+    task: Value,
+    milliseconds: f64,
+    parent: Option<Value>,
+) -> RuntimeResult<Value> {
+    let promise = runtime.create_promise();
+    let signal = child_signal(runtime, parent.as_ref())?;
+    if is_aborted(runtime, &signal) {
+        let reason = runtime.abort_reason(&signal);
+        runtime.reject_promise(promise, reason);
+        return Ok(Value::Object(promise));
+    }
+    let pin = pins.lock().unwrap().open(vec![Value::Object(promise), signal.clone(), task.clone()]);
+
+    // Timing out and cancelling both abort the signal, which settles the
+    // promise unless the task already has
+    let cancelled = Arc::clone(pins);
+    on_abort(runtime, &signal, move |runtime, reason| {
+        runtime.reject_promise(promise, reason);
+        cancelled.lock().unwrap().close(pin);
+        Ok(())
+    })?;
+
+    let result = if runtime.is_callable(&task) {
+        call_task(runtime, &task, std::slice::from_ref(&signal))?
+    } else {
+        task
+    };
+    let timer = delay(runtime, milliseconds, Some(&signal))?;
+    let expired = signal.clone();
+    react(
+        runtime,
+        timer,
+        move |runtime, _| {
+            let message = format!("The operation timed out after {}ms", milliseconds);
+            let error = runtime.create_error("TimeoutError", &message);
+            runtime.abort(&expired, error)
+        },
+        // Cleared because the task settled or the work was cancelled
+        |_, _| Ok(()),
+    );
+    let (fulfilled, rejected) = (signal.clone(), signal);
+    react(
+        runtime,
+        result,
+        move |runtime, value| {
+            runtime.resolve_promise(promise, value);
+            runtime.abort(&fulfilled, Value::Undefined)
+        },
+        move |runtime, reason| {
+            runtime.reject_promise(promise, reason);
+            runtime.abort(&rejected, Value::Undefined)
+        },
+    );
+    Ok(Value::Object(promise))
+}
+
+/// Argument `index`, undefined if it was left out
+fn argument(args: &[Value], index: usize) -> Value {
+    args.get(index).cloned().unwrap_or(Value::Undefined)
+}
+
+fn function_argument(runtime: &Runtime, args: &[Value], index: usize, name: &str) -> RuntimeResult<Value> {
+    let value = argument(args, index);
+    if runtime.is_callable(&value) {
+        Ok(value)
+    } else {
+        Err(RuntimeError::TypeError(format!("The \"{}\" argument must be a function", name)))
+    }
+}
+
+fn array_argument(runtime: &Runtime, args: &[Value], index: usize, name: &str) -> RuntimeResult<Vec<Value>> {
+    runtime
+        .array_elements(&argument(args, index))
+        .ok_or_else(|| RuntimeError::TypeError(format!("The \"{}\" argument must be an array", name)))
+}
+
+/// How many tasks may run at once: a positive number, with `Infinity`
+/// for no limit
+fn limit_argument(args: &[Value], index: usize) -> RuntimeResult<usize> {
+    let limit = argument(args, index).to_number()?;
+    if limit.is_nan() || limit < 1.0 {
+        return Err(RuntimeError::RangeError(format!(
+            "The \"limit\" argument must be a positive number, received {}",
+            limit
+        )));
+    }
+    Ok(if limit.is_finite() { limit as usize } else { usize::MAX })
+}
+
+/// `options[name]`, if `options` is an object that sets it
+fn option(runtime: &mut Runtime, options: &Value, name: &str) -> RuntimeResult<Option<Value>> {
+    if !matches!(options, Value::Object(_)) {
+        return Ok(None);
+    }
+    match runtime.get_property(options, name)? {
+        Value::Undefined => Ok(None),
+        value => Ok(Some(value)),
+    }
+}
+
+fn number_option(runtime: &mut Runtime, options: &Value, name: &str, default: f64) -> RuntimeResult<f64> {
+    option(runtime, options, name)?.map_or(Ok(default), |value| value.to_number())
+}
+
+/// The `signal` option, checked to be an `AbortSignal`
+fn signal_option(runtime: &mut Runtime, options: &Value) -> RuntimeResult<Option<Value>> {
+    if crate::signal_option(runtime, options)?.is_none() {
+        return Ok(None);
+    }
+    option(runtime, options, "signal")
+}
+
+fn is_aborted(runtime: &mut Runtime, signal: &Value) -> bool {
+    runtime.abort_signal(signal).is_some_and(|signal| signal.is_aborted())
+}
+
+/// A new signal that aborts when `parent` does, with the same reason
+fn child_signal(runtime: &mut Runtime, parent: Option<&Value>) -> RuntimeResult<Value> {
+    let signal = runtime.create_abort_signal();
+    let Some(parent) = parent else {
+        return Ok(signal);
+    };
+    if is_aborted(runtime, parent) {
+        let reason = runtime.abort_reason(parent);
+        runtime.abort(&signal, reason)?;
+    } else {
+        let child = signal.clone();
+        on_abort(runtime, parent, move |runtime, reason| runtime.abort(&child, reason))?;
+    }
+    Ok(signal)
+}
+
+/// Call `listener` with `signal`'s reason when it aborts
+fn on_abort<F>(runtime: &mut Runtime, signal: &Value, listener: F) -> RuntimeResult<()>
+where
+    F: Fn(&mut Runtime, Value) -> RuntimeResult<()> + Send + Sync + 'static,
+{
+    let target = signal.clone();
+    let listener = NativeFunction::new("onabort", move |runtime, _| {
+        let reason = runtime.abort_reason(&target);
+        listener(runtime, reason)?;
+        Ok(Value::Undefined)
+    });
+    runtime.add_abort_listener(signal, Value::NativeFunction(listener))
+}
+
+/// Call `task` with `args`. A task that throws gives a promise rejected
+/// with what it threw, so callers handle both failures the same way.
+fn call_task(runtime: &mut Runtime, task: &Value, args: &[Value]) -> RuntimeResult<Value> {
+    match runtime.call_function(task, args) {
+        Ok(value) => Ok(value),
+        Err(error) => {
+            let Some(reason) = runtime.exception_value(&error) else {
+                return Err(error);
+            };
+            let promise = runtime.create_promise();
+            runtime.reject_promise(promise, reason);
+            Ok(Value::Object(promise))
+        }
+    }
+}
+
+/// Call `on_fulfilled` with `value`'s value or `on_rejected` with its
+/// reason once it settles
+fn react<F, R>(runtime: &mut Runtime, value: Value, on_fulfilled: F, on_rejected: R)
+where
+    F: Fn(&mut Runtime, Value) -> RuntimeResult<()> + Send + Sync + 'static,
+    R: Fn(&mut Runtime, Value) -> RuntimeResult<()> + Send + Sync + 'static,
+{
+    let fulfilled = NativeFunction::new("onFulfilled", move |runtime, args| {
+        on_fulfilled(runtime, argument(args, 0))?;
+        Ok(Value::Undefined)
+    });
+    let rejected = NativeFunction::new("onRejected", move |runtime, args| {
+        on_rejected(runtime, argument(args, 0))?;
+        Ok(Value::Undefined)
+    });
+    runtime.when_settled(value, Value::NativeFunction(fulfilled), Value::NativeFunction(rejected));
+}
+
+fn settle(runtime: &mut Runtime, promise: GcHandle, outcome: Result<Value, Value>) {
+    match outcome {
+        Ok(value) => runtime.resolve_promise(promise, value),
+        Err(reason) => runtime.reject_promise(promise, reason),
+    }
+}
+
+/// A promise fulfilled after `milliseconds`, from the host's
+/// `scheduler.wait`. Aborting `signal` clears the timer and rejects the
+/// promise with the signal's reason.
+fn delay(runtime: &mut Runtime, milliseconds: f64, signal: Option<&Value>) -> RuntimeResult<Value> {
+    let wait = match runtime.get_global("scheduler").cloned() {
+        Some(scheduler @ Value::Object(_)) => runtime.get_property(&scheduler, "wait")?,
+        _ => Value::Undefined,
+    };
+    if !runtime.is_callable(&wait) {
+        return Err(RuntimeError::Error(
+            "Delays need an event loop, and scheduler.wait is not available".to_string(),
+        ));
+    }
+    let options = runtime.create_object(signal.map(|signal| ("signal".to_string(), signal.clone())).into_iter().collect());
+    runtime.call_function(&wait, &[Value::Number(milliseconds), options])
+}
+
+impl Module for AsyncModule {
+    fn name(&self) -> &str {
+        "async"
+    }
+
+    fn initialize(&mut self, runtime: &mut Runtime) -> Result<(), Box<dyn std::error::Error>> {
+        let pins = Arc::clone(&self.pins);
+        runtime.add_root_source(move |roots| pins.lock().unwrap().trace(roots));
+        Ok(())
+    }
+
+    fn get_exports(&self) -> HashMap<String, Value> {
+        self.exports.clone()
+    }
+}
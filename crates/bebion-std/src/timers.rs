@@ -1,7 +1,7 @@
 //! Timers module for setTimeout, setInterval, etc.
 
 use crate::{Module, Value};
-use bebion_runtime::Runtime;
+use bebion_runtime::{NativeFunction, Runtime, RuntimeError};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
@@ -10,6 +10,8 @@ use tokio::time::{sleep, interval};
 pub struct TimersModule {
     exports: HashMap<String, Value>,
     timers: Arc<Mutex<HashMap<u64, TimerHandle>>>,
+    /// Timers scheduled from JavaScript, which share ids with `timers`
+    script_timers: Arc<Mutex<HashMap<u64, ScriptTimer>>>,
     next_id: Arc<Mutex<u64>>,
 }
 
@@ -18,34 +20,96 @@ struct TimerHandle {
     cancel_tx: tokio::sync::oneshot::Sender<()>,
 }
 
+/// A JavaScript callback scheduled with `setTimeout`, `setInterval` or
+/// `setImmediate`. Calling it is up to the event loop that owns the runtime.
+#[derive(Debug, Clone)]
+pub struct ScriptTimer {
+    pub callback: Value,
+    /// Extra arguments to pass to the callback
+    pub args: Vec<Value>,
+    pub delay: Duration,
+    pub repeat: bool,
+}
+
 impl TimersModule {
     pub fn new() -> Self {
-        let mut exports = HashMap::new();
-        
-        exports.insert("setTimeout".to_string(), Value::Undefined);
-        exports.insert("clearTimeout".to_string(), Value::Undefined);
-        exports.insert("setInterval".to_string(), Value::Undefined);
-        exports.insert("clearInterval".to_string(), Value::Undefined);
-        exports.insert("setImmediate".to_string(), Value::Undefined);
-        exports.insert("clearImmediate".to_string(), Value::Undefined);
-        
-        Self {
-            exports,
+        let mut module = Self {
+            exports: HashMap::new(),
             timers: Arc::new(Mutex::new(HashMap::new())),
+            script_timers: Arc::new(Mutex::new(HashMap::new())),
             next_id: Arc::new(Mutex::new(1)),
+        };
+        
+        module.export_scheduler("setTimeout", false, true);
+        module.export_scheduler("setInterval", true, true);
+        module.export_scheduler("setImmediate", false, false);
+        for name in ["clearTimeout", "clearInterval", "clearImmediate"] {
+            module.export_clear(name);
         }
+        
+        module
+    }
+    
+    /// Export `name(callback, [delay,] ...args)`, which schedules
+    /// `callback` and returns the timer's id
+    fn export_scheduler(&mut self, name: &str, repeat: bool, takes_delay: bool) {
+        let script_timers = Arc::clone(&self.script_timers);
+        let next_id = Arc::clone(&self.next_id);
+        let function_name = name.to_string();
+        
+        let function = NativeFunction::new(name, move |runtime, args| {
+            let callback = args.first().cloned().unwrap_or(Value::Undefined);
+            if !runtime.is_callable(&callback) {
+                return Err(RuntimeError::TypeError(format!(
+                    "The \"callback\" argument of {} must be a function",
+                    function_name
+                )));
+            }
+            
+            let (delay, extra) = if takes_delay {
+                let delay = args.get(1).map_or(Ok(0.0), |delay| delay.to_number())?;
+                (delay, args.get(2..).unwrap_or_default())
+            } else {
+                (0.0, args.get(1..).unwrap_or_default())
+            };
+            // Like Node, delays that are not positive finite numbers mean 1ms
+            let delay = if delay.is_finite() && delay >= 1.0 { delay as u64 } else { u64::from(takes_delay) };
+            
+            let id = next_timer_id(&next_id);
+            script_timers.lock().unwrap().insert(id, ScriptTimer {
+                callback,
+                args: extra.to_vec(),
+                delay: Duration::from_millis(delay),
+                repeat,
+            });
+            Ok(Value::Number(id as f64))
+        });
+        self.exports.insert(name.to_string(), Value::NativeFunction(function));
+    }
+    
+    /// Export `name(id)`, which cancels a timer scheduled from JavaScript
+    fn export_clear(&mut self, name: &str) {
+        let script_timers = Arc::clone(&self.script_timers);
+        
+        let function = NativeFunction::new(name, move |_, args| {
+            if let Some(Value::Number(id)) = args.first() {
+                script_timers.lock().unwrap().remove(&(*id as u64));
+            }
+            Ok(Value::Undefined)
+        });
+        self.exports.insert(name.to_string(), Value::NativeFunction(function));
+    }
+    
+    /// The JavaScript timer with id `id`, if it has not been cleared
+    pub fn script_timer(&self, id: u64) -> Option<ScriptTimer> {
+        self.script_timers.lock().unwrap().get(&id).cloned()
     }
     
     pub fn set_timeout<F>(&self, callback: F, delay: u64) -> u64
     where
         F: FnOnce() + Send + 'static,
     {
-        let id = {
-            let mut next_id = self.next_id.lock().unwrap();
-            let current_id = *next_id;
-            *next_id += 1;
-            current_id
-        };
+        let id = next_timer_id(&self.next_id);
         
         let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
         
@@ -97,12 +161,7 @@ impl TimersModule {
     where
         F: Fn() + Send + Sync + 'static,
     {
-        let id = {
-            let mut next_id = self.next_id.lock().unwrap();
-            let current_id = *next_id;
-            *next_id += 1;
-            current_id
-        };
+        let id = next_timer_id(&self.next_id);
         
         let (cancel_tx, mut cancel_rx) = tokio::sync::oneshot::channel();
         
@@ -160,10 +219,17 @@ impl TimersModule {
     
     pub fn active_timers(&self) -> usize {
         let timers = self.timers.lock().unwrap();
-        timers.len()
+        timers.len() + self.script_timers.lock().unwrap().len()
     }
 }
 
+fn next_timer_id(next_id: &Mutex<u64>) -> u64 {
+    let mut next_id = next_id.lock().unwrap();
+    let current_id = *next_id;
+    *next_id += 1;
+    current_id
+}
+
 impl Module for TimersModule {
     fn name(&self) -> &str {
         "timers"
@@ -171,12 +237,9 @@ impl Module for TimersModule {
     
     fn initialize(&mut self, runtime: &mut Runtime) -> Result<(), Box<dyn std::error::Error>> {
         // Set global timer functions
-        runtime.set_global("setTimeout", Value::Undefined);
-        runtime.set_global("clearTimeout", Value::Undefined);
-        runtime.set_global("setInterval", Value::Undefined);
-        runtime.set_global("clearInterval", Value::Undefined);
-        runtime.set_global("setImmediate", Value::Undefined);
-        runtime.set_global("clearImmediate", Value::Undefined);
+        for (name, function) in &self.exports {
+            runtime.set_global(name, function.clone());
+        }
         
         Ok(())
     }
@@ -0,0 +1,464 @@
+//! Terminal detection, size, cursor control and colors
+//!
+//! Everything that writes escape sequences asks this module first, so a
+//! script piped into a file or another program gets plain text. Whether a
+//! stream gets colors follows the usual conventions, in this order:
+//!
+//! - `FORCE_COLOR` forces them even off a terminal: `0` or `false` turns
+//!   them off, `2` asks for 256 colors, `3` for 24-bit color and anything
+//!   else, including an empty value, for the basic 16
+//! - a non-empty `NO_COLOR` turns them off
+//! - otherwise the stream must be a terminal whose `TERM` is not `dumb`,
+//!   and `COLORTERM` and `TERM` tell how many colors it has
+//!
+//! Cursor and screen helpers write to stdout only when it is a terminal.
+
+use crate::{Module, Value};
+use bebion_runtime::{NativeFunction, Runtime, RuntimeError, RuntimeResult};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+
+/// Erase the whole line the cursor is on
+pub const CLEAR_LINE: &str = "\x1b[2K";
+/// Erase from the cursor to the end of the line
+pub const CLEAR_LINE_RIGHT: &str = "\x1b[0K";
+/// Erase from the start of the line to the cursor
+pub const CLEAR_LINE_LEFT: &str = "\x1b[1K";
+/// Erase the screen and move the cursor to its top left corner
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[1;1H";
+pub const HIDE_CURSOR: &str = "\x1b[?25l";
+pub const SHOW_CURSOR: &str = "\x1b[?25h";
+
+/// Size assumed for a terminal that does not report one
+pub const DEFAULT_WINDOW_SIZE: WindowSize = WindowSize { columns: 80, rows: 24 };
+
+/// One of the standard streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn from_fd(fd: i32) -> Option<Self> {
+        match fd {
+            0 => Some(Stream::Stdin),
+            1 => Some(Stream::Stdout),
+            2 => Some(Stream::Stderr),
+            _ => None,
+        }
+    }
+
+    pub fn fd(self) -> i32 {
+        match self {
+            Stream::Stdin => 0,
+            Stream::Stdout => 1,
+            Stream::Stderr => 2,
+        }
+    }
+
+    pub fn is_terminal(self) -> bool {
+        match self {
+            Stream::Stdin => io::stdin().is_terminal(),
+            Stream::Stdout => io::stdout().is_terminal(),
+            Stream::Stderr => io::stderr().is_terminal(),
+        }
+    }
+}
+
+/// Whether the file descriptor `fd` refers to a terminal
+pub fn isatty(fd: i32) -> bool {
+    if let Some(stream) = Stream::from_fd(fd) {
+        return stream.is_terminal();
+    }
+    #[cfg(unix)]
+    {
+        // SAFETY: `isatty` only inspects the descriptor, and fails cleanly
+        // on one that is not open
+        fd >= 0 && unsafe { libc::isatty(fd) } == 1
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// A terminal's size in character cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSize {
+    pub columns: u16,
+    pub rows: u16,
+}
+
+/// The size of the terminal `stream` is attached to, or the one `COLUMNS`
+/// and `LINES` give when it does not say
+pub fn window_size(stream: Stream) -> Option<WindowSize> {
+    terminal_size(stream).or_else(|| {
+        let columns = env::var("COLUMNS").ok()?.trim().parse().ok()?;
+        let rows = env::var("LINES").ok()?.trim().parse().ok()?;
+        Some(WindowSize { columns, rows })
+    })
+}
+
+#[cfg(unix)]
+fn terminal_size(stream: Stream) -> Option<WindowSize> {
+    if !stream.is_terminal() {
+        return None;
+    }
+    // SAFETY: `winsize` is plain data, filled in by the ioctl before it is
+    // read
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(stream.fd(), libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+        return None;
+    }
+    Some(WindowSize { columns: size.ws_col, rows: size.ws_row })
+}
+
+#[cfg(not(unix))]
+fn terminal_size(_stream: Stream) -> Option<WindowSize> {
+    None
+}
+
+/// How many colors a stream can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorLevel {
+    None,
+    Basic,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorLevel {
+    /// Bits per color, as `getColorDepth` reports it
+    pub fn depth(self) -> u8 {
+        match self {
+            ColorLevel::None => 1,
+            ColorLevel::Basic => 4,
+            ColorLevel::Ansi256 => 8,
+            ColorLevel::TrueColor => 24,
+        }
+    }
+}
+
+/// The colors output to `stream` should use
+pub fn color_level(stream: Stream) -> ColorLevel {
+    if let Ok(force) = env::var("FORCE_COLOR") {
+        return match force.trim() {
+            "0" | "false" => ColorLevel::None,
+            "2" => ColorLevel::Ansi256,
+            "3" => ColorLevel::TrueColor,
+            _ => ColorLevel::Basic,
+        };
+    }
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) || !stream.is_terminal() {
+        return ColorLevel::None;
+    }
+
+    let term = env::var("TERM").unwrap_or_default();
+    if term == "dumb" {
+        return ColorLevel::None;
+    }
+    match env::var("COLORTERM").as_deref() {
+        Ok("truecolor" | "24bit") => ColorLevel::TrueColor,
+        _ if term.contains("256") => ColorLevel::Ansi256,
+        _ => ColorLevel::Basic,
+    }
+}
+
+/// Whether output to `stream` should be colored at all
+pub fn colors_enabled(stream: Stream) -> bool {
+    color_level(stream) > ColorLevel::None
+}
+
+/// A text style or color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Bold,
+    Dim,
+    Italic,
+    Underline,
+    Inverse,
+    Strikethrough,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Gray,
+    BgBlack,
+    BgRed,
+    BgGreen,
+    BgYellow,
+    BgBlue,
+    BgMagenta,
+    BgCyan,
+    BgWhite,
+}
+
+impl Style {
+    const ALL: [Style; 23] = [
+        Style::Bold,
+        Style::Dim,
+        Style::Italic,
+        Style::Underline,
+        Style::Inverse,
+        Style::Strikethrough,
+        Style::Black,
+        Style::Red,
+        Style::Green,
+        Style::Yellow,
+        Style::Blue,
+        Style::Magenta,
+        Style::Cyan,
+        Style::White,
+        Style::Gray,
+        Style::BgBlack,
+        Style::BgRed,
+        Style::BgGreen,
+        Style::BgYellow,
+        Style::BgBlue,
+        Style::BgMagenta,
+        Style::BgCyan,
+        Style::BgWhite,
+    ];
+
+    /// The style named `name`, e.g. `red`, `bgBlue` or `underline`
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|style| style.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Bold => "bold",
+            Style::Dim => "dim",
+            Style::Italic => "italic",
+            Style::Underline => "underline",
+            Style::Inverse => "inverse",
+            Style::Strikethrough => "strikethrough",
+            Style::Black => "black",
+            Style::Red => "red",
+            Style::Green => "green",
+            Style::Yellow => "yellow",
+            Style::Blue => "blue",
+            Style::Magenta => "magenta",
+            Style::Cyan => "cyan",
+            Style::White => "white",
+            Style::Gray => "gray",
+            Style::BgBlack => "bgBlack",
+            Style::BgRed => "bgRed",
+            Style::BgGreen => "bgGreen",
+            Style::BgYellow => "bgYellow",
+            Style::BgBlue => "bgBlue",
+            Style::BgMagenta => "bgMagenta",
+            Style::BgCyan => "bgCyan",
+            Style::BgWhite => "bgWhite",
+        }
+    }
+
+    /// The SGR codes that turn this style on and off
+    fn codes(self) -> (u8, u8) {
+        match self {
+            Style::Bold => (1, 22),
+            Style::Dim => (2, 22),
+            Style::Italic => (3, 23),
+            Style::Underline => (4, 24),
+            Style::Inverse => (7, 27),
+            Style::Strikethrough => (9, 29),
+            Style::Gray => (90, 39),
+            Style::Black | Style::Red | Style::Green | Style::Yellow | Style::Blue | Style::Magenta | Style::Cyan
+            | Style::White => (30 + self as u8 - Style::Black as u8, 39),
+            _ => (40 + self as u8 - Style::BgBlack as u8, 49),
+        }
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// `text` wrapped in `styles`, or unchanged when `colors` is false
+pub fn paint(text: &str, styles: &[Style], colors: bool) -> String {
+    if !colors || styles.is_empty() {
+        return text.to_string();
+    }
+    let mut painted = String::new();
+    for style in styles {
+        painted.push_str(&format!("\x1b[{}m", style.codes().0));
+    }
+    painted.push_str(text);
+    for style in styles.iter().rev() {
+        painted.push_str(&format!("\x1b[{}m", style.codes().1));
+    }
+    painted
+}
+
+/// `text` without the escape sequences `paint` and the cursor helpers
+/// write
+pub fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' || chars.peek() != Some(&'[') {
+            stripped.push(ch);
+            continue;
+        }
+        chars.next();
+        // Parameters and intermediates, up to the final byte
+        for ch in chars.by_ref() {
+            if ('@'..='~').contains(&ch) {
+                break;
+            }
+        }
+    }
+    stripped
+}
+
+/// The sequence moving the cursor to column `x` (from 0), and row `y` if
+/// given
+pub fn cursor_to(x: u16, y: Option<u16>) -> String {
+    match y {
+        Some(y) => format!("\x1b[{};{}H", y + 1, x + 1),
+        None => format!("\x1b[{}G", x + 1),
+    }
+}
+
+/// The sequence moving the cursor `dx` columns right and `dy` rows down,
+/// negative values going left and up
+pub fn move_cursor(dx: i32, dy: i32) -> String {
+    let mut sequence = String::new();
+    match dx {
+        0 => {}
+        dx if dx < 0 => sequence.push_str(&format!("\x1b[{}D", -dx)),
+        dx => sequence.push_str(&format!("\x1b[{}C", dx)),
+    }
+    match dy {
+        0 => {}
+        dy if dy < 0 => sequence.push_str(&format!("\x1b[{}A", -dy)),
+        dy => sequence.push_str(&format!("\x1b[{}B", dy)),
+    }
+    sequence
+}
+
+/// Write `sequence` to stdout if it is a terminal, returning whether it was
+/// written
+pub fn control(sequence: &str) -> bool {
+    if !Stream::Stdout.is_terminal() {
+        return false;
+    }
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", sequence).and_then(|_| stdout.flush()).is_ok()
+}
+
+type TtyBinding = fn(&mut Runtime, &[Value]) -> RuntimeResult<Value>;
+
+pub struct TtyModule {
+    exports: HashMap<String, Value>,
+}
+
+impl TtyModule {
+    pub fn new() -> Self {
+        let bindings: [(&str, TtyBinding); 12] = [
+            ("isatty", |_, args| Ok(Value::Boolean(isatty(integer_argument(args, 0)?.unwrap_or(-1))))),
+            // `getWindowSize(fd = 1)`: `{ columns, rows }`
+            ("getWindowSize", |runtime, args| {
+                let size = stream_argument(args, 0)?.and_then(window_size).unwrap_or(DEFAULT_WINDOW_SIZE);
+                let properties = HashMap::from([
+                    ("columns".to_string(), Value::Number(size.columns as f64)),
+                    ("rows".to_string(), Value::Number(size.rows as f64)),
+                ]);
+                Ok(runtime.create_object(properties))
+            }),
+            // `getColorDepth(fd = 1)`: 1, 4, 8 or 24 bits
+            ("getColorDepth", |_, args| {
+                let level = stream_argument(args, 0)?.map_or(ColorLevel::None, color_level);
+                Ok(Value::Number(level.depth() as f64))
+            }),
+            ("hasColors", |_, args| Ok(Value::Boolean(stream_argument(args, 0)?.is_some_and(colors_enabled)))),
+            // `style(text, ...styles)`: `text` in the named styles when
+            // stdout shows colors, e.g. `style("done", "bold", "green")`
+            ("style", |_, args| {
+                let text = args.first().map(Value::to_string).unwrap_or_default();
+                let styles = args
+                    .iter()
+                    .skip(1)
+                    .map(|name| {
+                        let name = name.to_string();
+                        Style::from_name(&name).ok_or_else(|| RuntimeError::TypeError(format!("Unknown style '{}'", name)))
+                    })
+                    .collect::<RuntimeResult<Vec<_>>>()?;
+                Ok(Value::from(paint(&text, &styles, colors_enabled(Stream::Stdout))))
+            }),
+            ("stripAnsi", |_, args| Ok(Value::from(strip_ansi(&args.first().map(Value::to_string).unwrap_or_default())))),
+            // `cursorTo(x, y)`, with `y` optional
+            ("cursorTo", |_, args| {
+                let x = integer_argument(args, 0)?.unwrap_or(0).max(0) as u16;
+                let y = integer_argument(args, 1)?.map(|y| y.max(0) as u16);
+                Ok(Value::Boolean(control(&cursor_to(x, y))))
+            }),
+            ("moveCursor", |_, args| {
+                let dx = integer_argument(args, 0)?.unwrap_or(0);
+                let dy = integer_argument(args, 1)?.unwrap_or(0);
+                Ok(Value::Boolean(control(&move_cursor(dx, dy))))
+            }),
+            // `clearLine(dir = 0)`: -1 clears left of the cursor, 1 right
+            // of it and 0 the whole line
+            ("clearLine", |_, args| {
+                let sequence = match integer_argument(args, 0)?.unwrap_or(0) {
+                    dir if dir < 0 => CLEAR_LINE_LEFT,
+                    0 => CLEAR_LINE,
+                    _ => CLEAR_LINE_RIGHT,
+                };
+                Ok(Value::Boolean(control(sequence)))
+            }),
+            ("clearScreen", |_, _| Ok(Value::Boolean(control(CLEAR_SCREEN)))),
+            ("hideCursor", |_, _| Ok(Value::Boolean(control(HIDE_CURSOR)))),
+            ("showCursor", |_, _| Ok(Value::Boolean(control(SHOW_CURSOR)))),
+        ];
+
+        let exports = bindings
+            .into_iter()
+            .map(|(name, binding)| (name.to_string(), Value::NativeFunction(NativeFunction::new(name, binding))))
+            .collect();
+
+        Self { exports }
+    }
+}
+
+/// Argument `index` as an integer, `None` if it was left out
+fn integer_argument(args: &[Value], index: usize) -> RuntimeResult<Option<i32>> {
+    match args.get(index) {
+        None | Some(Value::Undefined) => Ok(None),
+        Some(value) => Ok(Some(value.to_number()? as i32)),
+    }
+}
+
+/// The stream whose file descriptor is argument `index`, stdout if it was
+/// left out and `None` for a descriptor that is not a standard stream
+fn stream_argument(args: &[Value], index: usize) -> RuntimeResult<Option<Stream>> {
+    Ok(match integer_argument(args, index)? {
+        None => Some(Stream::Stdout),
+        Some(fd) => Stream::from_fd(fd),
+    })
+}
+
+impl Module for TtyModule {
+    fn name(&self) -> &str {
+        "tty"
+    }
+
+    fn initialize(&mut self, _runtime: &mut Runtime) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn get_exports(&self) -> HashMap<String, Value> {
+        self.exports.clone()
+    }
+}
@@ -1,7 +1,9 @@
 //! Utility functions module
 
+use crate::tty::{paint, Style};
 use crate::{Module, Value};
-use bebion_runtime::Runtime;
+use bebion_runtime::number::number_to_string;
+use bebion_runtime::{PromiseInspection, Runtime};
 use std::collections::HashMap;
 
 pub struct UtilModule {
@@ -28,56 +30,56 @@ impl UtilModule {
     
     pub fn inspect(&self, value: &Value, options: Option<InspectOptions>) -> String {
         let opts = options.unwrap_or_default();
-        self.inspect_value(value, 0, &opts)
+        self.inspect_value(None, value, 0, &opts)
     }
     
-    fn inspect_value(&self, value: &Value, depth: usize, options: &InspectOptions) -> String {
+    /// `inspect` with `runtime` to look into heap values, so a promise
+    /// shows its state as `Promise { <pending> }`
+    pub fn inspect_with(&self, runtime: &Runtime, value: &Value, options: Option<InspectOptions>) -> String {
+        let opts = options.unwrap_or_default();
+        self.inspect_value(Some(runtime), value, 0, &opts)
+    }
+    
+    fn inspect_value(&self, runtime: Option<&Runtime>, value: &Value, depth: usize, options: &InspectOptions) -> String {
         if depth > options.depth {
             return "[object]".to_string();
         }
         
         match value {
             Value::Number(n) => {
-                if options.colors {
-                    format!("\x1b[33m{}\x1b[39m", n)
-                } else {
-                    n.to_string()
-                }
+                let n = number_to_string(*n);
+                paint(&n, &[Style::Yellow], options.colors)
             }
             Value::String(s) => {
-                if options.colors {
-                    format!("\x1b[32m'{}'\x1b[39m", s)
-                } else {
-                    format!("'{}'", s)
-                }
+                paint(&format!("'{}'", s), &[Style::Green], options.colors)
             }
             Value::Boolean(b) => {
-                if options.colors {
-                    format!("\x1b[33m{}\x1b[39m", b)
-                } else {
-                    b.to_string()
-                }
+                paint(&b.to_string(), &[Style::Yellow], options.colors)
             }
             Value::Null => {
-                if options.colors {
-                    "\x1b[1mnull\x1b[22m".to_string()
-                } else {
-                    "null".to_string()
-                }
+                paint("null", &[Style::Bold], options.colors)
             }
             Value::Undefined => {
-                if options.colors {
-                    "\x1b[90mundefined\x1b[39m".to_string()
-                } else {
-                    "undefined".to_string()
-                }
+                paint("undefined", &[Style::Gray], options.colors)
+            }
+            Value::Symbol(symbol) => {
+                paint(&symbol.to_string(), &[Style::Green], options.colors)
             }
             Value::Object(_) => {
-                if options.colors {
-                    "\x1b[36m[Object]\x1b[39m".to_string()
-                } else {
-                    "[Object]".to_string()
+                if let Some(state) = runtime.and_then(|runtime| runtime.promise_state(value)) {
+                    let state = match state {
+                        PromiseInspection::Pending => "<pending>".to_string(),
+                        PromiseInspection::Fulfilled(value) => self.inspect_value(runtime, &value, depth + 1, options),
+                        PromiseInspection::Rejected(reason) => {
+                            format!("<rejected> {}", self.inspect_value(runtime, &reason, depth + 1, options))
+                        }
+                    };
+                    return format!("Promise {{ {} }}", state);
                 }
+                paint("[Object]", &[Style::Cyan], options.colors)
+            }
+            Value::NativeFunction(function) => {
+                paint(&format!("[Function: {}]", function.name()), &[Style::Cyan], options.colors)
             }
         }
     }
@@ -118,7 +120,7 @@ impl UtilModule {
                         // Float
                         if arg_index < args.len() {
                             if let Ok(n) = args[arg_index].to_number() {
-                                result.push_str(&n.to_string());
+                                result.push_str(&number_to_string(n));
                             } else {
                                 result.push_str("NaN");
                             }
@@ -197,7 +199,7 @@ impl UtilModule {
     }
     
     pub fn is_function(&self, value: &Value) -> bool {
-        matches!(value, Value::Object(_))
+        matches!(value, Value::Object(_) | Value::NativeFunction(_))
     }
 }
 
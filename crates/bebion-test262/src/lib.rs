@@ -0,0 +1,315 @@
+//! Test262 conformance harness
+//!
+//! Runs tests from a checkout of https://github.com/tc39/test262 against a
+//! fresh engine each. A test is one script: the harness files (`assert.js`,
+//! `sta.js` and its `includes`) followed by the test source. Negative tests
+//! pass when they fail in the phase their metadata names, and async tests
+//! when they call `$DONE` without an error once the event loop has drained.
+//! Module tests are skipped until the parser handles `import`/`export`.
+//!
+//! A baseline file lists the tests that passed in an earlier run, so a run
+//! can report the tests that regressed since.
+
+pub mod metadata;
+
+use bebion_core::{BebionEngine, BebionError};
+use metadata::{Metadata, Phase};
+use std::collections::BTreeSet;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Stands in for `doneprintHandle.js`, which needs a host `print`: records
+/// the outcome in globals the harness reads back after the run
+const ASYNC_PRELUDE: &str = r#"
+function $DONE(error) {
+    $test262AsyncDone = true;
+    if (error) {
+        $test262AsyncError = error.name ? error.name + ": " + error.message : "" + error;
+    }
+}
+"#;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    /// Path relative to the test262 checkout, with `/` separators
+    pub path: String,
+    pub outcome: Outcome,
+}
+
+/// A test262 checkout
+pub struct Harness {
+    root: PathBuf,
+}
+
+impl Harness {
+    /// The checkout containing `path`, which may be the checkout itself or
+    /// any directory or test inside it
+    pub fn locate(path: &Path) -> Result<Self, String> {
+        let path = path
+            .canonicalize()
+            .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        path.ancestors()
+            .find(|dir| dir.join("harness").join("assert.js").is_file())
+            .map(|root| Self { root: root.to_path_buf() })
+            .ok_or_else(|| format!("{} is not inside a test262 checkout", path.display()))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Test files under `path`, sorted, keeping those whose relative path
+    /// contains `filter`. The checkout root itself means its `test` directory.
+    pub fn collect_tests(&self, path: &Path, filter: Option<&str>) -> Result<Vec<PathBuf>, String> {
+        let path = path
+            .canonicalize()
+            .map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        let path = if path == self.root { self.root.join("test") } else { path };
+
+        let mut tests = Vec::new();
+        collect_js_files(&path, &mut tests).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        tests.retain(|test| {
+            let relative = self.relative_path(test);
+            !relative.contains("_FIXTURE") && filter.is_none_or(|filter| relative.contains(filter))
+        });
+        tests.sort();
+        Ok(tests)
+    }
+
+    /// Run `tests` one after another, handing each result to `on_result`
+    /// as it completes
+    pub fn run(&self, tests: &[PathBuf], mut on_result: impl FnMut(&TestResult)) -> Report {
+        let results = tests
+            .iter()
+            .map(|test| {
+                let result = TestResult {
+                    path: self.relative_path(test),
+                    outcome: self.run_test(test),
+                };
+                on_result(&result);
+                result
+            })
+            .collect();
+        Report { results }
+    }
+
+    pub fn run_test(&self, test: &Path) -> Outcome {
+        let source = match fs::read_to_string(test) {
+            Ok(source) => source,
+            Err(e) => return Outcome::Fail(format!("Cannot read test: {}", e)),
+        };
+        let metadata = match Metadata::parse(&source) {
+            Ok(metadata) => metadata,
+            Err(e) => return Outcome::Fail(format!("Bad frontmatter: {}", e)),
+        };
+
+        if metadata.has_flag("module") {
+            return Outcome::Skip("modules are not supported".to_string());
+        }
+        if metadata.negative.as_ref().is_some_and(|negative| negative.phase == Phase::Resolution) {
+            return Outcome::Skip("module resolution is not supported".to_string());
+        }
+
+        let script = match self.script(&source, &metadata) {
+            Ok(script) => script,
+            Err(e) => return Outcome::Fail(e),
+        };
+        debug!("Running {} ({} bytes)", test.display(), script.len());
+
+        // A panic in the engine fails the test rather than the whole run
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut engine = BebionEngine::new()?;
+            let result = engine.execute_script(&script).map(|_| ());
+            Ok::<_, BebionError>((result, engine))
+        }));
+        let (result, engine) = match result {
+            Ok(Ok(run)) => run,
+            Ok(Err(e)) => return Outcome::Fail(format!("Engine failed to start: {}", e)),
+            Err(_) => return Outcome::Fail("Engine panicked".to_string()),
+        };
+
+        match (&metadata.negative, result) {
+            (Some(negative), Ok(())) => Outcome::Fail(format!(
+                "Expected a {} {} error, but the test completed",
+                phase_name(negative.phase),
+                negative.error_type
+            )),
+            (Some(negative), Err(error)) => {
+                // Every parse error is a SyntaxError, so only runtime errors
+                // have a type to check
+                let matches = match negative.phase {
+                    Phase::Parse => matches!(error, BebionError::ParseError(_) | BebionError::CompileError(_)),
+                    Phase::Runtime => {
+                        matches!(error, BebionError::RuntimeError(_)) && error.to_string().contains(&negative.error_type)
+                    }
+                    Phase::Resolution => false,
+                };
+                if matches {
+                    Outcome::Pass
+                } else {
+                    Outcome::Fail(format!(
+                        "Expected a {} {} error, got {}",
+                        phase_name(negative.phase),
+                        negative.error_type,
+                        error
+                    ))
+                }
+            }
+            (None, Err(error)) => Outcome::Fail(error.to_string()),
+            (None, Ok(())) if metadata.has_flag("async") => async_outcome(&engine),
+            (None, Ok(())) => Outcome::Pass,
+        }
+    }
+
+    /// The harness files and the test as one script
+    fn script(&self, source: &str, metadata: &Metadata) -> Result<String, String> {
+        if metadata.has_flag("raw") {
+            return Ok(source.to_string());
+        }
+
+        let mut script = String::new();
+        if metadata.has_flag("onlyStrict") {
+            script.push_str("\"use strict\";\n");
+        }
+        for include in ["assert.js", "sta.js"]
+            .into_iter()
+            .chain(metadata.includes.iter().map(String::as_str))
+            .filter(|include| *include != "doneprintHandle.js")
+        {
+            let path = self.root.join("harness").join(include);
+            let harness = fs::read_to_string(&path).map_err(|e| format!("Cannot read harness file {}: {}", include, e))?;
+            script.push_str(&harness);
+            script.push('\n');
+        }
+        if metadata.has_flag("async") {
+            script.push_str(ASYNC_PRELUDE);
+        }
+        script.push_str(source);
+        Ok(script)
+    }
+
+    fn relative_path(&self, test: &Path) -> String {
+        let relative = test.strip_prefix(&self.root).unwrap_or(test);
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+fn collect_js_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_file() {
+        if path.extension().is_some_and(|extension| extension == "js") {
+            files.push(path.to_path_buf());
+        }
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)? {
+        collect_js_files(&entry?.path(), files)?;
+    }
+    Ok(())
+}
+
+fn async_outcome(engine: &BebionEngine) -> Outcome {
+    if engine.get_global_json("$test262AsyncDone") != Some(serde_json::Value::Bool(true)) {
+        return Outcome::Fail("$DONE was never called".to_string());
+    }
+    match engine.get_global_json("$test262AsyncError") {
+        Some(serde_json::Value::String(error)) => Outcome::Fail(format!("$DONE called with {}", error)),
+        Some(error) => Outcome::Fail(format!("$DONE called with {}", error)),
+        None => Outcome::Pass,
+    }
+}
+
+fn phase_name(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Parse => "parse",
+        Phase::Resolution => "resolution",
+        Phase::Runtime => "runtime",
+    }
+}
+
+/// The results of a run
+pub struct Report {
+    pub results: Vec<TestResult>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Pass))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Fail(_)))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Skip(_)))
+    }
+
+    /// Tests the baseline lists as passing that failed this time. Tests
+    /// outside this run are not regressions.
+    pub fn regressions<'a>(&'a self, baseline: &'a Baseline) -> impl Iterator<Item = &'a TestResult> + 'a {
+        self.results
+            .iter()
+            .filter(|result| matches!(result.outcome, Outcome::Fail(_)) && baseline.passing.contains(&result.path))
+    }
+
+    /// Tests that pass now but not in the baseline
+    pub fn newly_passing<'a>(&'a self, baseline: &'a Baseline) -> impl Iterator<Item = &'a TestResult> + 'a {
+        self.results
+            .iter()
+            .filter(|result| result.outcome == Outcome::Pass && !baseline.passing.contains(&result.path))
+    }
+
+    /// Record this run into `baseline`: tests that ran are listed as passing
+    /// exactly when they passed, the rest keep their earlier entry
+    pub fn update_baseline(&self, baseline: &mut Baseline) {
+        for result in &self.results {
+            if result.outcome == Outcome::Pass {
+                baseline.passing.insert(result.path.clone());
+            } else {
+                baseline.passing.remove(&result.path);
+            }
+        }
+    }
+
+    fn count(&self, predicate: impl Fn(&Outcome) -> bool) -> usize {
+        self.results.iter().filter(|result| predicate(&result.outcome)).count()
+    }
+}
+
+/// The tests that passed in an earlier run, stored as a sorted JSON array
+/// of relative paths so it diffs well under version control
+#[derive(Debug, Default)]
+pub struct Baseline {
+    pub passing: BTreeSet<String>,
+}
+
+impl Baseline {
+    /// Load `path`, or start empty if it does not exist yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let passing =
+            serde_json::from_str(&contents).map_err(|e| format!("Invalid baseline {}: {}", path.display(), e))?;
+        Ok(Self { passing })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(&self.passing).map_err(|e| e.to_string())?;
+        fs::write(path, contents + "\n").map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,114 @@
+//! Test metadata from the `/*--- ... ---*/` frontmatter
+//!
+//! Frontmatter is YAML, but test262 only uses a small, regular subset of it:
+//! scalar keys, lists written `[a, b]` or as `- item` lines, and the nested
+//! `negative` mapping. That subset is parsed by hand.
+
+/// The phase a negative test expects its error in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Parse,
+    Resolution,
+    Runtime,
+}
+
+/// A test expected to fail with an error of type `error_type`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negative {
+    pub phase: Phase,
+    pub error_type: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// Harness files to load before the test
+    pub includes: Vec<String>,
+    pub flags: Vec<String>,
+    pub features: Vec<String>,
+    pub negative: Option<Negative>,
+}
+
+impl Metadata {
+    /// Metadata of `source`, empty when it has no frontmatter
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let Some(start) = source.find("/*---") else {
+            return Ok(Self::default());
+        };
+        let body = &source[start + 5..];
+        let end = body.find("---*/").ok_or("Unterminated frontmatter")?;
+
+        let mut metadata = Self::default();
+        let mut key = "";
+        let mut negative_phase = None;
+        let mut negative_type = None;
+
+        for line in body[..end].lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let indented = line.starts_with([' ', '\t']);
+
+            if let Some(item) = trimmed.strip_prefix("- ") {
+                if let Some(list) = metadata.list_mut(key) {
+                    list.push(unquote(item));
+                }
+            } else if indented && key == "negative" {
+                if let Some((field, value)) = trimmed.split_once(':') {
+                    match field.trim() {
+                        "phase" => negative_phase = Some(parse_phase(value.trim())?),
+                        "type" => negative_type = Some(value.trim().to_string()),
+                        _ => {}
+                    }
+                }
+            } else if !indented {
+                // Multi-line scalars such as `description: |` leave their
+                // continuation lines indented under the key
+                let Some((field, value)) = trimmed.split_once(':') else {
+                    continue;
+                };
+                key = field.trim();
+                let value = value.trim();
+                if let Some(items) = value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+                    if let Some(list) = metadata.list_mut(key) {
+                        list.extend(items.split(',').map(str::trim).filter(|item| !item.is_empty()).map(unquote));
+                    }
+                }
+            }
+        }
+
+        metadata.negative = match (negative_phase, negative_type) {
+            (Some(phase), Some(error_type)) => Some(Negative { phase, error_type }),
+            (None, None) => None,
+            _ => return Err("negative needs both a phase and a type".to_string()),
+        };
+        Ok(metadata)
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+
+    fn list_mut(&mut self, key: &str) -> Option<&mut Vec<String>> {
+        match key {
+            "includes" => Some(&mut self.includes),
+            "flags" => Some(&mut self.flags),
+            "features" => Some(&mut self.features),
+            _ => None,
+        }
+    }
+}
+
+fn parse_phase(phase: &str) -> Result<Phase, String> {
+    match phase {
+        // Early errors are reported when the script is parsed
+        "parse" | "early" => Ok(Phase::Parse),
+        "resolution" => Ok(Phase::Resolution),
+        "runtime" => Ok(Phase::Runtime),
+        other => Err(format!("Unknown negative phase '{}'", other)),
+    }
+}
+
+fn unquote(item: &str) -> String {
+    item.trim().trim_matches(|c| c == '\'' || c == '"').to_string()
+}
@@ -0,0 +1,83 @@
+//! Bebion WebAssembly Bindings
+//! 
+//! Exposes the parser, compiler and VM to JavaScript hosts through
+//! wasm-bindgen, for running bebion in browsers and serverless WASM hosts.
+//! Build with `wasm-pack build crates/bebion-wasm` or
+//! `cargo build -p bebion-wasm --target wasm32-unknown-unknown`.
+//! 
+//! The engine is built without the host event loop, so timers and async
+//! host tasks are unavailable; promises and `await` still settle.
+
+use bebion_compiler::Compiler;
+use bebion_core::BebionEngine;
+use bebion_parser::Parser;
+use wasm_bindgen::prelude::*;
+
+/// Parse `source` and return the AST as JSON
+#[wasm_bindgen]
+pub fn parse(source: &str) -> Result<String, JsError> {
+    let program = Parser::new().parse(source).map_err(|e| JsError::new(&e.to_string()))?;
+    serde_json::to_string(&program).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Compile `source` and return the bytecode as JSON. `opt_level` is 0 to 2,
+/// as with `bebion -O`.
+#[wasm_bindgen]
+pub fn compile(source: &str, opt_level: Option<u8>) -> Result<String, JsError> {
+    let program = Parser::new().parse(source).map_err(|e| JsError::new(&e.to_string()))?;
+    
+    let mut compiler = Compiler::new();
+    if let Some(level) = opt_level {
+        let level = bebion_compiler::OptLevel::from_level(level)
+            .ok_or_else(|| JsError::new(&format!("Invalid optimization level: {}", level)))?;
+        compiler.set_opt_level(level);
+    }
+    
+    let bytecode = compiler.compile(&program).map_err(|e| JsError::new(&e.to_string()))?;
+    serde_json::to_string(&bytecode).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// An engine whose globals persist across `execute` calls
+#[wasm_bindgen]
+pub struct Engine {
+    inner: BebionEngine,
+}
+
+#[wasm_bindgen]
+impl Engine {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Engine, JsError> {
+        let inner = BebionEngine::new().map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Engine { inner })
+    }
+
+    /// Run a script and return its completion value as a string
+    pub fn execute(&mut self, source: &str) -> Result<String, JsError> {
+        let result = self.inner.execute_script(source).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(self.inner.value_of(result).to_string())
+    }
+
+    /// Seed `Math.random` so runs are reproducible
+    #[wasm_bindgen(js_name = setRandomSeed)]
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.inner.set_random_seed(seed);
+    }
+
+    /// Freeze `Date.now` at `epochMs` and seed `Math.random` from it
+    #[wasm_bindgen(js_name = freezeTime)]
+    pub fn freeze_time(&mut self, epoch_ms: f64) {
+        self.inner.freeze_time(epoch_ms);
+    }
+
+    /// Run a garbage collection, returning the number of objects freed
+    #[wasm_bindgen(js_name = collectGarbage)]
+    pub fn collect_garbage(&mut self) -> usize {
+        self.inner.gc_collect()
+    }
+}
+
+/// Run a script in a fresh engine and return its completion value
+#[wasm_bindgen]
+pub fn execute(source: &str) -> Result<String, JsError> {
+    Engine::new()?.execute(source)
+}
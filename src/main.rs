@@ -3,7 +3,7 @@
 //! A high-performance JavaScript runtime built with Rust and C.
 //! Provides ECMAScript 2024 compliance with advanced features.
 
-use bebion_cli::Cli;
+use bebion_cli::{standalone, Cli};
 use bebion_core::BebionEngine;
 use tracing::{info, Level};
 use tracing_subscriber;
@@ -16,11 +16,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Starting Bebion JavaScript Runtime v{}", env!("CARGO_PKG_VERSION"));
 
-    // Initialize the core engine
-    let mut engine = BebionEngine::new()?;
+    // A standalone executable runs its embedded script instead of the CLI
+    if let Some(package) = standalone::embedded_package()? {
+        let mut engine = BebionEngine::new()?;
+        return standalone::run_package(&mut engine, package);
+    }
     
-    // Start the CLI
+    // Start the CLI, with an engine configured as its flags say
     let cli = Cli::new();
+    let mut engine = BebionEngine::builder().gc_config(cli.gc_config()).build()?;
     cli.run(&mut engine)?;
 
     Ok(())